@@ -0,0 +1,127 @@
+//! Buffer and broadcast hot paths: `cargo bench -p flywatch-core [filter]`.
+//!
+//! Each benchmark runs for a fixed number of samples and prints the median
+//! and best time per iteration, so runs before and after a change can be
+//! compared directly.
+
+use chrono::Utc;
+use flywatch_core::loadgen::LogGenerator;
+use flywatch_core::{LogBuffer, LogBufferConfig, TimestampedLog};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::runtime::Runtime;
+use tokio::sync::broadcast;
+
+const SAMPLES: usize = 20;
+const CAPACITY: usize = 10_000;
+
+struct Bench {
+    filter: Option<String>,
+    runtime: Runtime,
+}
+
+impl Bench {
+    /// Time `iterations` runs of `f` per sample
+    fn run(&self, name: &str, iterations: u32, mut f: impl FnMut()) {
+        if self
+            .filter
+            .as_deref()
+            .is_some_and(|filter| !name.contains(filter))
+        {
+            return;
+        }
+        f();
+        let mut samples: Vec<Duration> = (0..SAMPLES)
+            .map(|_| {
+                let started = Instant::now();
+                for _ in 0..iterations {
+                    f();
+                }
+                started.elapsed() / iterations
+            })
+            .collect();
+        samples.sort();
+        println!(
+            "{:<28} median {:>10?}  best {:>10?}  ({} x {})",
+            name,
+            samples[SAMPLES / 2],
+            samples[0],
+            SAMPLES,
+            iterations
+        );
+    }
+}
+
+fn lines(count: usize) -> Vec<String> {
+    let mut generator = LogGenerator::new(42, 8);
+    (0..count).map(|_| generator.next_line()).collect()
+}
+
+/// A buffer already at capacity, so pushes also prune
+fn full_buffer(runtime: &Runtime) -> Arc<LogBuffer> {
+    let buffer = LogBuffer::new(
+        LogBufferConfig {
+            max_entries: CAPACITY,
+            max_age_minutes: 30,
+        },
+        None,
+    );
+    runtime.block_on(async {
+        for line in lines(CAPACITY) {
+            buffer.push(line).await;
+        }
+    });
+    buffer
+}
+
+fn main() {
+    let bench = Bench {
+        filter: std::env::args().skip(1).find(|arg| !arg.starts_with("--")),
+        runtime: tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("tokio runtime"),
+    };
+    let runtime = &bench.runtime;
+    let input = lines(1_000);
+
+    let mut next = input.iter().cycle();
+    bench.run("parse", 1_000, || {
+        std::hint::black_box(TimestampedLog::new(next.next().unwrap().clone(), 1));
+    });
+
+    let buffer = full_buffer(runtime);
+    let mut next = input.iter().cycle();
+    bench.run("push_full_buffer", 1_000, || {
+        runtime.block_on(buffer.push(next.next().unwrap().clone()));
+    });
+
+    // The path every ingested line takes: buffer, then fan out to streams
+    let (tx, _) = broadcast::channel::<Arc<TimestampedLog>>(CAPACITY);
+    let mut subscribers: Vec<_> = (0..4).map(|_| tx.subscribe()).collect();
+    let mut next = input.iter().cycle();
+    bench.run("push_and_broadcast_4", 1_000, || {
+        let log = runtime.block_on(buffer.push(next.next().unwrap().clone()));
+        let _ = tx.send(Arc::new(log));
+        for rx in subscribers.iter_mut() {
+            while rx.try_recv().is_ok() {}
+        }
+    });
+
+    bench.run("page_100", 100, || {
+        std::hint::black_box(runtime.block_on(buffer.page_before(Utc::now(), None, 100, |_| true)));
+    });
+
+    bench.run("page_100_errors_only", 100, || {
+        std::hint::black_box(runtime.block_on(buffer.page_before(
+            Utc::now(),
+            None,
+            100,
+            TimestampedLog::is_error,
+        )));
+    });
+
+    bench.run("summary", 20, || {
+        std::hint::black_box(runtime.block_on(buffer.get_summary()));
+    });
+}
@@ -0,0 +1,22 @@
+//! flywatch-core - the log buffer, line parser and source/sink abstractions
+//! behind flywatch, for services that want to embed them without running
+//! the HTTP server.
+//!
+//! ```ignore
+//! let buffer = LogBuffer::new(LogBufferConfig::default(), None);
+//! let log = buffer.push(raw_line).await;
+//! println!("{:?} {:?}", log.level, log.message);
+//! ```
+
+/// Synthetic Fly log lines for load tests and benchmarks
+pub mod loadgen;
+/// Time-ordered ring of parsed lines with optional persistence
+pub mod log_buffer;
+/// Exporter trait and its error types
+pub mod sink;
+/// Source trait, supervisor and line helpers
+pub mod source;
+
+pub use log_buffer::{LogBuffer, LogBufferConfig, LogMessage, TimestampedLog};
+pub use sink::{Sink, SinkError};
+pub use source::{Ingest, LogSource, SourceContext, SourceError, SourceRegistry};
@@ -0,0 +1,157 @@
+//! Synthetic Fly log lines for load generation and benchmarks: a mix of
+//! access logs, structured app lines carrying trace ids, warnings and the
+//! occasional error, spread over a few instances and regions.
+
+use chrono::Utc;
+use serde_json::json;
+
+const APP: &str = "loadgen";
+const REGIONS: [&str; 4] = ["iad", "ord", "lhr", "syd"];
+const PATHS: [&str; 6] = [
+    "/",
+    "/api/users",
+    "/api/orders",
+    "/api/orders/checkout",
+    "/healthz",
+    "/static/app.js",
+];
+const WARNINGS: [&str; 3] = [
+    "slow query took 812ms",
+    "retrying upstream request (attempt 2)",
+    "connection pool at 90% capacity",
+];
+const ERRORS: [&str; 3] = [
+    "panicked at 'called `Option::unwrap()` on a `None` value', src/orders.rs:88:14",
+    "database error: connection reset by peer",
+    "payment provider returned 502 Bad Gateway",
+];
+
+/// Deterministic source of realistic Fly log JSON lines
+pub struct LogGenerator {
+    state: u64,
+    instances: Vec<String>,
+    produced: u64,
+}
+
+impl LogGenerator {
+    /// The same seed always yields the same sequence of lines (timestamps aside)
+    pub fn new(seed: u64, instances: usize) -> Self {
+        let mut generator = Self {
+            state: seed | 1,
+            instances: Vec::new(),
+            produced: 0,
+        };
+        generator.instances = (0..instances.max(1))
+            .map(|_| format!("{:014x}", generator.next_u64() >> 8))
+            .collect();
+        generator
+    }
+
+    /// Lines produced so far
+    pub fn produced(&self) -> u64 {
+        self.produced
+    }
+
+    /// xorshift64*: fast and good enough to vary the traffic
+    fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    fn pick<'a>(&mut self, items: &'a [&'a str]) -> &'a str {
+        items[(self.next_u64() % items.len() as u64) as usize]
+    }
+
+    /// Next line in the Fly NATS log shape
+    pub fn next_line(&mut self) -> String {
+        self.produced += 1;
+        let index = (self.next_u64() % self.instances.len() as u64) as usize;
+        let instance = self.instances[index].clone();
+        let region = self.pick(&REGIONS);
+        let trace_id = format!("{:016x}", self.next_u64());
+
+        // Roughly 70% access logs, 20% app lines, 7% warnings, 3% errors
+        let roll = self.next_u64() % 100;
+        let (level, message) = if roll < 70 {
+            let path = self.pick(&PATHS);
+            let status = match self.next_u64() % 50 {
+                0 => 500,
+                1..=3 => 404,
+                _ => 200,
+            };
+            let millis = 1 + self.next_u64() % 250;
+            let level = if status >= 500 { "error" } else { "info" };
+            (
+                level,
+                format!(
+                    "GET {} {} {}ms request_id={}",
+                    path,
+                    status,
+                    millis,
+                    &trace_id[..8]
+                ),
+            )
+        } else if roll < 90 {
+            let order = self.next_u64() % 100_000;
+            (
+                "info",
+                json!({
+                    "level": "info",
+                    "msg": "order processed",
+                    "order_id": order,
+                    "trace_id": trace_id,
+                })
+                .to_string(),
+            )
+        } else if roll < 97 {
+            ("warn", self.pick(&WARNINGS).to_string())
+        } else {
+            ("error", self.pick(&ERRORS).to_string())
+        };
+
+        json!({
+            "timestamp": Utc::now().to_rfc3339(),
+            "message": message,
+            "log": { "level": level },
+            "fly": {
+                "app": { "name": APP, "instance": instance },
+                "region": region,
+            },
+        })
+        .to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::log_buffer::TimestampedLog;
+
+    #[test]
+    fn test_generated_lines_parse_like_fly_logs() {
+        let mut generator = LogGenerator::new(7, 3);
+        let logs: Vec<TimestampedLog> = (0..500)
+            .map(|seq| TimestampedLog::new(generator.next_line(), seq))
+            .collect();
+
+        assert_eq!(generator.produced(), 500);
+        assert!(logs.iter().all(|l| l.app.as_deref() == Some(APP)));
+        assert!(logs
+            .iter()
+            .all(|l| l.instance.is_some() && l.region.is_some()));
+        assert!(logs.iter().any(|l| l.is_error()));
+        assert!(logs.iter().any(|l| l.is_warning()));
+        assert!(logs.iter().any(|l| l.trace_id.is_some()));
+
+        let instances: std::collections::HashSet<_> =
+            logs.iter().filter_map(|l| l.instance.clone()).collect();
+        assert_eq!(instances.len(), 3);
+
+        // Same seed, same traffic
+        let mut again = LogGenerator::new(7, 3);
+        let first = TimestampedLog::new(again.next_line(), 0);
+        assert_eq!(first.message, logs[0].message);
+    }
+}
@@ -0,0 +1,1019 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::hash_map::RandomState;
+use std::collections::{BTreeMap, VecDeque};
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use stoar::Store;
+use tokio::sync::RwLock;
+use tracing::{error, info};
+
+const LOGS_COLLECTION: &str = "logs";
+const HEALTH_COLLECTION: &str = "health";
+/// Event times further ahead of the receive time than this are not trusted
+const MAX_CLOCK_SKEW_MINUTES: i64 = 5;
+/// Error reported for writes failed by `set_write_failure_rate`
+const INJECTED_FAILURE: &str = "injected store write failure";
+
+/// Keys correlation ids go by in common loggers and tracers; dotted keys
+/// also match nested objects (ECS writes `{"trace": {"id": ...}}`)
+const TRACE_ID_KEYS: [&str; 5] = ["trace_id", "traceId", "trace.id", "traceID", "otelTraceID"];
+const SPAN_ID_KEYS: [&str; 5] = ["span_id", "spanId", "span.id", "spanID", "otelSpanID"];
+const REQUEST_ID_KEYS: [&str; 5] = [
+    "request_id",
+    "requestId",
+    "req_id",
+    "http.request.id",
+    "x_request_id",
+];
+
+/// A parsed log entry as broadcast to streaming clients
+pub type LogMessage = Arc<TimestampedLog>;
+
+/// A timestamped log entry with parsed metadata
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct TimestampedLog {
+    /// Monotonically increasing position in the ingest stream
+    #[serde(default)]
+    pub seq: u64,
+    /// When the event happened, from the payload's timestamp if it has one;
+    /// time-range queries use this
+    pub timestamp: DateTime<Utc>,
+    /// When flywatch received it; retention uses this
+    #[serde(default)]
+    pub received_at: DateTime<Utc>,
+    pub raw: String,
+    pub level: Option<String>,
+    pub instance: Option<String>,
+    pub region: Option<String>,
+    pub message: Option<String>,
+    #[serde(default)]
+    pub app: Option<String>,
+    /// Set for logs flywatch produced itself ("self")
+    #[serde(default)]
+    pub source: Option<String>,
+    /// Correlation ids from the payload or a JSON message
+    #[serde(default)]
+    pub trace_id: Option<String>,
+    #[serde(default)]
+    pub span_id: Option<String>,
+    #[serde(default)]
+    pub request_id: Option<String>,
+    /// The app's own keys: the top level of a JSON message (or of a JSON line
+    /// outside the Fly envelope), else `key=value` pairs in the text
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    #[cfg_attr(feature = "openapi", schema(value_type = Object))]
+    pub fields: BTreeMap<String, Value>,
+}
+
+/// Metadata pulled out of a Fly.io log line
+#[derive(Default)]
+struct ParsedLog {
+    level: Option<String>,
+    instance: Option<String>,
+    region: Option<String>,
+    message: Option<String>,
+    app: Option<String>,
+    source: Option<String>,
+    timestamp: Option<DateTime<Utc>>,
+    trace_id: Option<String>,
+    span_id: Option<String>,
+    request_id: Option<String>,
+    fields: BTreeMap<String, Value>,
+}
+
+impl TimestampedLog {
+    pub fn new(raw: String, seq: u64) -> Self {
+        let parsed = Self::parse_log(&raw);
+        let received_at = Utc::now();
+        let skew = Duration::minutes(MAX_CLOCK_SKEW_MINUTES);
+        Self {
+            seq,
+            timestamp: parsed
+                .timestamp
+                .filter(|t| *t <= received_at + skew)
+                .unwrap_or(received_at),
+            received_at,
+            raw,
+            level: parsed.level,
+            instance: parsed.instance,
+            region: parsed.region,
+            message: parsed.message,
+            app: parsed.app,
+            source: parsed.source,
+            trace_id: parsed.trace_id,
+            span_id: parsed.span_id,
+            request_id: parsed.request_id,
+            fields: parsed.fields,
+        }
+    }
+
+    /// Whether this line belongs to the trace or request `id`
+    pub fn correlates_with(&self, id: &str) -> bool {
+        self.trace_id.as_deref() == Some(id) || self.request_id.as_deref() == Some(id)
+    }
+
+    /// Parse Fly.io log JSON to extract useful fields
+    fn parse_log(raw: &str) -> ParsedLog {
+        #[derive(Deserialize)]
+        struct FlyLog {
+            log: Option<LogLevel>,
+            fly: Option<FlyMeta>,
+            message: Option<String>,
+            source: Option<String>,
+            // Fly's field first, then common spellings from other shippers
+            timestamp: Option<Value>,
+            #[serde(rename = "@timestamp")]
+            at_timestamp: Option<Value>,
+            time: Option<Value>,
+            ts: Option<Value>,
+        }
+
+        #[derive(Deserialize)]
+        struct LogLevel {
+            level: Option<String>,
+        }
+
+        #[derive(Deserialize)]
+        struct FlyMeta {
+            app: Option<AppMeta>,
+            region: Option<String>,
+        }
+
+        #[derive(Deserialize)]
+        struct AppMeta {
+            instance: Option<String>,
+            name: Option<String>,
+        }
+
+        let Ok(value) = serde_json::from_str::<Value>(raw) else {
+            return ParsedLog {
+                fields: logfmt_fields(raw),
+                ..Default::default()
+            };
+        };
+        match FlyLog::deserialize(&value) {
+            Ok(parsed) => {
+                let fly = parsed.fly.unwrap_or(FlyMeta {
+                    app: None,
+                    region: None,
+                });
+                let (instance, app) = fly.app.map_or((None, None), |a| (a.instance, a.name));
+                // Apps on Fly usually log JSON to stdout, which arrives as the message
+                let message_json = parsed
+                    .message
+                    .as_deref()
+                    .filter(|m| m.trim_start().starts_with('{'))
+                    .and_then(|m| serde_json::from_str::<Value>(m).ok());
+                let correlation = |keys: &[&str]| {
+                    find_id(&value, keys)
+                        .or_else(|| message_json.as_ref().and_then(|m| find_id(m, keys)))
+                };
+                let fields = match (&message_json, &value) {
+                    (Some(Value::Object(message)), _) => message.clone().into_iter().collect(),
+                    (_, Value::Object(line)) if !line.contains_key("fly") => {
+                        line.clone().into_iter().collect()
+                    }
+                    _ => parsed
+                        .message
+                        .as_deref()
+                        .map(logfmt_fields)
+                        .unwrap_or_default(),
+                };
+                ParsedLog {
+                    fields,
+                    trace_id: correlation(&TRACE_ID_KEYS),
+                    span_id: correlation(&SPAN_ID_KEYS),
+                    request_id: correlation(&REQUEST_ID_KEYS),
+                    level: parsed.log.and_then(|l| l.level),
+                    instance,
+                    region: fly.region,
+                    message: parsed.message,
+                    app,
+                    source: parsed.source,
+                    timestamp: [
+                        parsed.timestamp,
+                        parsed.at_timestamp,
+                        parsed.time,
+                        parsed.ts,
+                    ]
+                    .iter()
+                    .flatten()
+                    .find_map(parse_event_time),
+                }
+            }
+            Err(_) => ParsedLog::default(),
+        }
+    }
+
+    /// A structured field; dotted keys reach into nested objects
+    pub fn field(&self, key: &str) -> Option<&Value> {
+        self.fields.get(key).or_else(|| {
+            let (head, rest) = key.split_once('.')?;
+            self.fields
+                .get(head)?
+                .pointer(&format!("/{}", rest.replace('.', "/")))
+        })
+    }
+
+    /// Check if this is an error log
+    pub fn is_error(&self) -> bool {
+        self.level
+            .as_ref()
+            .map(|l| l.eq_ignore_ascii_case("error") || l.eq_ignore_ascii_case("err"))
+            .unwrap_or(false)
+    }
+
+    /// Check if this is a warning log
+    pub fn is_warning(&self) -> bool {
+        self.level
+            .as_ref()
+            .map(|l| l.eq_ignore_ascii_case("warn") || l.eq_ignore_ascii_case("warning"))
+            .unwrap_or(false)
+    }
+}
+
+/// `key=value` pairs in plain text (logfmt), typed as numbers or booleans
+/// where they parse as one; values may be double-quoted
+fn logfmt_fields(text: &str) -> BTreeMap<String, Value> {
+    let mut fields = BTreeMap::new();
+    let mut rest = text;
+    while let Some(eq) = rest.find('=') {
+        let key = rest[..eq].rsplit(char::is_whitespace).next().unwrap_or("");
+        let after = &rest[eq + 1..];
+        let (value, tail) = match after.strip_prefix('"') {
+            Some(quoted) => match quoted.find('"') {
+                Some(end) => (&quoted[..end], &quoted[end + 1..]),
+                None => (quoted, ""),
+            },
+            None => {
+                let end = after.find(char::is_whitespace).unwrap_or(after.len());
+                (&after[..end], &after[end..])
+            }
+        };
+        let valid_key = key
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'));
+        if valid_key && !value.is_empty() {
+            fields.insert(key.to_string(), typed(value));
+        }
+        rest = tail;
+    }
+    fields
+}
+
+fn typed(value: &str) -> Value {
+    if let Ok(n) = value.parse::<i64>() {
+        return Value::from(n);
+    }
+    if let Some(n) = value.parse::<f64>().ok().filter(|n| n.is_finite()) {
+        return Value::from(n);
+    }
+    match value {
+        "true" => Value::Bool(true),
+        "false" => Value::Bool(false),
+        _ => Value::String(value.to_string()),
+    }
+}
+
+/// The first of `keys` set to a non-empty string or a number
+pub fn find_id(value: &Value, keys: &[&str]) -> Option<String> {
+    keys.iter().find_map(|key| {
+        let found = value
+            .get(key)
+            .or_else(|| value.pointer(&format!("/{}", key.replace('.', "/"))))?;
+        match found {
+            Value::String(s) if !s.is_empty() => Some(s.clone()),
+            Value::Number(n) => Some(n.to_string()),
+            _ => None,
+        }
+    })
+}
+
+/// An event time from a log payload: RFC3339 or "YYYY-MM-DD HH:MM:SS[.f]"
+/// (UTC) strings, or Unix times in seconds, milliseconds, microseconds or
+/// nanoseconds
+fn parse_event_time(value: &Value) -> Option<DateTime<Utc>> {
+    match value {
+        Value::String(s) => DateTime::parse_from_rfc3339(s)
+            .map(|t| t.with_timezone(&Utc))
+            .ok()
+            .or_else(|| {
+                NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S%.f")
+                    .ok()
+                    .map(|t| t.and_utc())
+            })
+            .or_else(|| s.parse().ok().and_then(from_epoch)),
+        Value::Number(n) => n.as_f64().and_then(from_epoch),
+        _ => None,
+    }
+}
+
+/// Guess the unit from the magnitude (anything after 2001 is unambiguous)
+fn from_epoch(value: f64) -> Option<DateTime<Utc>> {
+    let nanos = match value {
+        v if v >= 1e17 => v,
+        v if v >= 1e14 => v * 1e3,
+        v if v >= 1e11 => v * 1e6,
+        v if v > 0.0 => v * 1e9,
+        _ => return None,
+    };
+    (nanos < i64::MAX as f64).then(|| DateTime::from_timestamp_nanos(nanos as i64))
+}
+
+/// Configuration for the log buffer
+#[derive(Debug, Clone, Copy)]
+pub struct LogBufferConfig {
+    pub max_entries: usize,
+    pub max_age_minutes: i64,
+}
+
+impl Default for LogBufferConfig {
+    fn default() -> Self {
+        Self {
+            max_entries: 10_000,
+            max_age_minutes: 30,
+        }
+    }
+}
+
+/// Summary of buffered logs for initial context
+#[derive(Debug, Clone, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct LogSummary {
+    pub total_count: usize,
+    pub oldest_timestamp: Option<DateTime<Utc>>,
+    pub newest_timestamp: Option<DateTime<Utc>>,
+    pub error_count: usize,
+    pub warn_count: usize,
+    pub recent_errors: Vec<String>,
+    pub active_instances: Vec<String>,
+}
+
+/// Buffer statistics
+#[derive(Debug, Clone, Serialize)]
+pub struct LogBufferStats {
+    pub count: usize,
+    pub oldest_timestamp: Option<DateTime<Utc>>,
+    pub newest_timestamp: Option<DateTime<Utc>>,
+    pub max_entries: usize,
+    pub max_age_minutes: i64,
+}
+
+/// Opaque position for paging backwards through the buffer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogCursor {
+    /// Timestamp of the oldest entry already returned
+    pub timestamp: DateTime<Utc>,
+    /// Sequence of the oldest entry already returned
+    pub seq: u64,
+    /// Upper bound fixed by the first page
+    pub snapshot: DateTime<Utc>,
+}
+
+impl LogCursor {
+    pub fn encode(&self) -> String {
+        let raw = format!(
+            "{}:{}:{}",
+            self.timestamp.timestamp_nanos_opt().unwrap_or(0),
+            self.seq,
+            self.snapshot.timestamp_nanos_opt().unwrap_or(0)
+        );
+        URL_SAFE_NO_PAD.encode(raw)
+    }
+
+    pub fn decode(cursor: &str) -> Option<Self> {
+        let raw = String::from_utf8(URL_SAFE_NO_PAD.decode(cursor).ok()?).ok()?;
+        let mut parts = raw.split(':');
+        let timestamp = DateTime::from_timestamp_nanos(parts.next()?.parse().ok()?);
+        let seq = parts.next()?.parse().ok()?;
+        let snapshot = DateTime::from_timestamp_nanos(parts.next()?.parse().ok()?);
+        if parts.next().is_some() {
+            return None;
+        }
+        Some(Self {
+            timestamp,
+            seq,
+            snapshot,
+        })
+    }
+}
+
+/// One page of historical logs
+#[derive(Debug, Clone)]
+pub struct LogPage {
+    pub logs: Vec<TimestampedLog>,
+    pub next_cursor: Option<LogCursor>,
+    /// Entries visible to this paging session (stable across pages)
+    pub total_estimate: usize,
+}
+
+/// Receive times are unique per entry; event times can repeat
+fn store_key(log: &TimestampedLog) -> String {
+    log.received_at
+        .timestamp_nanos_opt()
+        .unwrap_or(0)
+        .to_string()
+}
+
+/// Thread-safe rolling log buffer with optional persistence
+pub struct LogBuffer {
+    config: std::sync::RwLock<LogBufferConfig>,
+    logs: RwLock<VecDeque<TimestampedLog>>,
+    next_seq: AtomicU64,
+    store: Option<Store>,
+    /// Share of store writes to fail on purpose, as f64 bits
+    write_failure_rate: AtomicU64,
+}
+
+impl LogBuffer {
+    pub fn new(config: LogBufferConfig, store_path: Option<&str>) -> Arc<Self> {
+        let capacity = config.max_entries;
+
+        // Initialize store if path provided
+        let store = store_path.and_then(|path| {
+            match Store::open(path) {
+                Ok(s) => {
+                    info!(path = %path, "Log persistence enabled");
+                    Some(s)
+                }
+                Err(e) => {
+                    error!(error = %e, path = %path, "Failed to open log store, running without persistence");
+                    None
+                }
+            }
+        });
+
+        // Load existing logs from store
+        let mut initial_logs = VecDeque::with_capacity(capacity);
+        if let Some(ref s) = store {
+            match s.all::<TimestampedLog>(LOGS_COLLECTION) {
+                Ok(mut persisted) => {
+                    // Entries persisted before receive times existed were
+                    // stamped on receipt
+                    for log in persisted.iter_mut() {
+                        if log.received_at == DateTime::<Utc>::default() {
+                            log.received_at = log.timestamp;
+                        }
+                    }
+                    // Sort by arrival
+                    persisted.sort_by_key(|log| log.received_at);
+
+                    // Only keep logs within max_age
+                    let cutoff = Utc::now() - Duration::minutes(config.max_age_minutes);
+                    for log in persisted {
+                        if log.received_at >= cutoff {
+                            initial_logs.push_back(log);
+                        }
+                    }
+
+                    // Trim to max_entries
+                    while initial_logs.len() > config.max_entries {
+                        initial_logs.pop_front();
+                    }
+
+                    // Entries persisted before sequences existed get fresh ones
+                    let mut next = 1;
+                    for log in initial_logs.iter_mut() {
+                        if log.seq < next {
+                            log.seq = next;
+                        }
+                        next = log.seq + 1;
+                    }
+
+                    info!(count = initial_logs.len(), "Loaded persisted logs");
+                }
+                Err(e) => {
+                    error!(error = %e, "Failed to load persisted logs");
+                }
+            }
+        }
+
+        let next_seq = initial_logs.back().map_or(1, |l| l.seq + 1);
+
+        Arc::new(Self {
+            config: std::sync::RwLock::new(config),
+            logs: RwLock::new(initial_logs),
+            next_seq: AtomicU64::new(next_seq),
+            store,
+            write_failure_rate: AtomicU64::new(0),
+        })
+    }
+
+    /// Push a new log entry, pruning old entries if necessary.
+    /// Returns the stored entry with its assigned sequence number.
+    pub async fn push(&self, raw: String) -> TimestampedLog {
+        // Assign the sequence under the write lock so buffer order matches it
+        let mut logs = self.logs.write().await;
+        let seq = self.next_seq.fetch_add(1, Ordering::SeqCst);
+        let entry = TimestampedLog::new(raw, seq);
+        self.insert(&mut logs, entry)
+    }
+
+    /// Push an entry another buffer already numbered, keeping its sequence so
+    /// a seq names the same line in both. Entries may arrive out of order.
+    pub async fn push_sequenced(&self, entry: TimestampedLog) -> TimestampedLog {
+        let mut logs = self.logs.write().await;
+        self.next_seq.fetch_max(entry.seq + 1, Ordering::SeqCst);
+        self.insert(&mut logs, entry)
+    }
+
+    /// Store an entry in sequence order, then prune
+    fn insert(&self, logs: &mut VecDeque<TimestampedLog>, entry: TimestampedLog) -> TimestampedLog {
+        let log_id = store_key(&entry);
+
+        // Persist to store
+        if let Some(ref store) = self.store {
+            let result = match self.injected_write_failure() {
+                true => Err(INJECTED_FAILURE.to_string()),
+                false => store
+                    .put(LOGS_COLLECTION, &log_id, &entry)
+                    .map_err(|e| e.to_string()),
+            };
+            if let Err(e) = result {
+                error!(error = %e, "Failed to persist log entry");
+            }
+        }
+
+        let index = logs.partition_point(|l| l.seq < entry.seq);
+        logs.insert(index, entry.clone());
+
+        // Prune by count
+        let config = self.limits();
+        while logs.len() > config.max_entries {
+            if let Some(old) = logs.pop_front() {
+                // Remove from store
+                if let Some(ref store) = self.store {
+                    let _ = store.delete(LOGS_COLLECTION, &store_key(&old));
+                }
+            }
+        }
+
+        // Prune by age
+        let cutoff = Utc::now() - Duration::minutes(config.max_age_minutes);
+        while let Some(front) = logs.front() {
+            if front.received_at < cutoff {
+                if let Some(old) = logs.pop_front() {
+                    // Remove from store
+                    if let Some(ref store) = self.store {
+                        let _ = store.delete(LOGS_COLLECTION, &store_key(&old));
+                    }
+                }
+            } else {
+                break;
+            }
+        }
+
+        entry
+    }
+
+    /// Every buffered line of a trace or request, in event order
+    pub async fn get_trace(&self, id: &str) -> Vec<TimestampedLog> {
+        let logs = self.logs.read().await;
+        let mut lines: Vec<TimestampedLog> = logs
+            .iter()
+            .filter(|log| log.correlates_with(id))
+            .cloned()
+            .collect();
+        lines.sort_by_key(|log| (log.timestamp, log.seq));
+        lines
+    }
+
+    /// Get the last N log entries
+    pub async fn get_last_n(&self, n: usize) -> Vec<TimestampedLog> {
+        let logs = self.logs.read().await;
+        logs.iter()
+            .rev()
+            .take(n)
+            .cloned()
+            .collect::<Vec<_>>()
+            .into_iter()
+            .rev()
+            .collect()
+    }
+
+    /// Visit every entry with an event time at or after `since`, without
+    /// copying the buffer
+    pub async fn for_each_since(&self, since: DateTime<Utc>, mut f: impl FnMut(&TimestampedLog)) {
+        let logs = self.logs.read().await;
+        for log in logs.iter().filter(|log| log.timestamp >= since) {
+            f(log);
+        }
+    }
+
+    /// Get logs from the last X minutes
+    pub async fn get_last_minutes(&self, minutes: i64) -> Vec<TimestampedLog> {
+        let cutoff = Utc::now() - Duration::minutes(minutes);
+        let logs = self.logs.read().await;
+        logs.iter()
+            .filter(|log| log.timestamp >= cutoff)
+            .cloned()
+            .collect()
+    }
+
+    /// Get logs within a specific time range, in event-time order
+    pub async fn get_time_range(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Vec<TimestampedLog> {
+        let logs = self.logs.read().await;
+        let mut range: Vec<TimestampedLog> = logs
+            .iter()
+            .filter(|log| log.timestamp >= start && log.timestamp <= end)
+            .cloned()
+            .collect();
+        range.sort_by_key(|log| (log.timestamp, log.seq));
+        range
+    }
+
+    /// Page backwards through history, newest first, resuming from `cursor`.
+    /// Returns the page in chronological order.
+    /// Only entries `keep` accepts are paged.
+    pub async fn page_before(
+        &self,
+        before: DateTime<Utc>,
+        cursor: Option<&LogCursor>,
+        limit: usize,
+        keep: impl Fn(&TimestampedLog) -> bool,
+    ) -> LogPage {
+        let logs = self.logs.read().await;
+
+        // The snapshot pins the newest entry the first page could see, so
+        // later pages ignore logs that arrived while paging
+        let snapshot = cursor.map_or(before, |c| c.snapshot);
+        let total_estimate = logs
+            .iter()
+            .filter(|l| l.timestamp < snapshot && keep(l))
+            .count();
+
+        let mut newest_first: Vec<&TimestampedLog> = logs
+            .iter()
+            .filter(|l| l.timestamp < snapshot && keep(l))
+            .filter(|l| cursor.is_none_or(|c| (l.timestamp, l.seq) < (c.timestamp, c.seq)))
+            .collect();
+        newest_first.sort_by_key(|l| std::cmp::Reverse((l.timestamp, l.seq)));
+
+        let has_more = newest_first.len() > limit;
+        let mut page: Vec<TimestampedLog> = newest_first.into_iter().take(limit).cloned().collect();
+
+        let next_cursor = match page.last() {
+            Some(last) if has_more => Some(LogCursor {
+                timestamp: last.timestamp,
+                seq: last.seq,
+                snapshot,
+            }),
+            _ => None,
+        };
+
+        page.reverse();
+
+        LogPage {
+            logs: page,
+            next_cursor,
+            total_estimate,
+        }
+    }
+
+    /// Get up to `limit` logs with a sequence greater than `after`, oldest first.
+    /// Also returns the oldest sequence still buffered so callers can tell
+    /// whether part of the requested range has already been evicted.
+    pub async fn get_since(&self, after: u64, limit: usize) -> (Vec<TimestampedLog>, Option<u64>) {
+        let logs = self.logs.read().await;
+        let oldest_seq = logs.front().map(|l| l.seq);
+        let result = logs
+            .iter()
+            .filter(|l| l.seq > after)
+            .take(limit)
+            .cloned()
+            .collect();
+        (result, oldest_seq)
+    }
+
+    /// Get total count of logs in buffer
+    pub async fn total_count(&self) -> usize {
+        let logs = self.logs.read().await;
+        logs.len()
+    }
+
+    /// Get a summary of the buffer for initial AI context
+    pub async fn get_summary(&self) -> LogSummary {
+        let logs = self.logs.read().await;
+
+        let total_count = logs.len();
+        // Backlogged lines arrive late, so the ends of the buffer aren't
+        // necessarily the oldest and newest events
+        let oldest_timestamp = logs.iter().map(|l| l.timestamp).min();
+        let newest_timestamp = logs.iter().map(|l| l.timestamp).max();
+
+        let mut error_count = 0;
+        let mut warn_count = 0;
+        let mut recent_errors: Vec<String> = Vec::new();
+        // Sorted, so the same instances always summarize the same way
+        let mut instances = std::collections::BTreeSet::new();
+
+        for log in logs.iter() {
+            if log.is_error() {
+                error_count += 1;
+                if recent_errors.len() < 5 {
+                    if let Some(msg) = &log.message {
+                        recent_errors.push(format!(
+                            "[{}] {}",
+                            log.timestamp.format("%H:%M:%S"),
+                            msg
+                        ));
+                    }
+                }
+            } else if log.is_warning() {
+                warn_count += 1;
+            }
+
+            if let Some(instance) = &log.instance {
+                instances.insert(instance.clone());
+            }
+        }
+
+        // Get most recent errors (reverse to show newest first)
+        recent_errors.reverse();
+        recent_errors.truncate(5);
+
+        LogSummary {
+            total_count,
+            oldest_timestamp,
+            newest_timestamp,
+            error_count,
+            warn_count,
+            recent_errors,
+            active_instances: instances.into_iter().collect(),
+        }
+    }
+
+    /// Get buffer statistics
+    pub async fn stats(&self) -> LogBufferStats {
+        let logs = self.logs.read().await;
+        let config = self.limits();
+        LogBufferStats {
+            count: logs.len(),
+            oldest_timestamp: logs.front().map(|l| l.timestamp),
+            newest_timestamp: logs.back().map(|l| l.timestamp),
+            max_entries: config.max_entries,
+            max_age_minutes: config.max_age_minutes,
+        }
+    }
+
+    /// Write and delete a probe row to prove the store is writable;
+    /// None when running without persistence
+    pub fn probe_store(&self) -> Option<Result<(), String>> {
+        let store = self.store.as_ref()?;
+        if self.injected_write_failure() {
+            return Some(Err(INJECTED_FAILURE.to_string()));
+        }
+        let result = store
+            .put(HEALTH_COLLECTION, "ready_probe", &Utc::now())
+            .and_then(|_| store.delete(HEALTH_COLLECTION, "ready_probe"))
+            .map_err(|e| e.to_string());
+        Some(result)
+    }
+
+    pub fn limits(&self) -> LogBufferConfig {
+        *self.config.read().unwrap()
+    }
+
+    /// Change retention limits; the next push prunes down to them
+    pub fn set_limits(&self, config: LogBufferConfig) {
+        *self.config.write().unwrap() = config;
+    }
+
+    /// Fail this share (0 to 1) of store writes on purpose, to exercise the
+    /// error paths in chaos testing
+    pub fn set_write_failure_rate(&self, rate: f64) {
+        self.write_failure_rate
+            .store(rate.clamp(0.0, 1.0).to_bits(), Ordering::Relaxed);
+    }
+
+    fn injected_write_failure(&self) -> bool {
+        let rate = f64::from_bits(self.write_failure_rate.load(Ordering::Relaxed));
+        // Each RandomState is freshly keyed, which is random enough here
+        let roll = RandomState::new().build_hasher().finish() >> 11;
+        rate > 0.0 && (roll as f64) < rate * (1u64 << 53) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::fly_envelope;
+
+    fn log_at(ts: DateTime<Utc>, seq: u64, raw: &str) -> TimestampedLog {
+        TimestampedLog {
+            seq,
+            timestamp: ts,
+            received_at: ts,
+            raw: raw.to_string(),
+            level: None,
+            instance: None,
+            region: None,
+            message: None,
+            app: None,
+            source: None,
+            trace_id: None,
+            span_id: None,
+            request_id: None,
+            fields: BTreeMap::new(),
+        }
+    }
+
+    fn buffer_with(logs: Vec<TimestampedLog>) -> Arc<LogBuffer> {
+        let buffer = LogBuffer::new(LogBufferConfig::default(), None);
+        *buffer.logs.try_write().unwrap() = logs.into();
+        buffer
+    }
+
+    #[test]
+    fn test_cursor_round_trip() {
+        let cursor = LogCursor {
+            timestamp: Utc::now(),
+            seq: 42,
+            snapshot: Utc::now(),
+        };
+        assert_eq!(LogCursor::decode(&cursor.encode()), Some(cursor));
+        assert_eq!(LogCursor::decode("not-a-cursor"), None);
+    }
+
+    #[tokio::test]
+    async fn test_page_before_walks_ties_without_gaps() {
+        let t0 = Utc::now() - Duration::seconds(10);
+        let t1 = t0 + Duration::seconds(1);
+        // Three entries share t1, so a page boundary lands inside the tie
+        let buffer = buffer_with(vec![
+            log_at(t0, 1, "a"),
+            log_at(t1, 2, "b"),
+            log_at(t1, 3, "c"),
+            log_at(t1, 4, "d"),
+        ]);
+
+        let mut seen = Vec::new();
+        let mut cursor = None;
+        loop {
+            let page = buffer
+                .page_before(Utc::now(), cursor.as_ref(), 2, |_| true)
+                .await;
+            assert_eq!(page.total_estimate, 4);
+            seen.splice(0..0, page.logs.iter().map(|l| l.raw.clone()));
+            match page.next_cursor {
+                Some(c) => cursor = Some(c),
+                None => break,
+            }
+        }
+        assert_eq!(seen, vec!["a", "b", "c", "d"]);
+    }
+
+    #[test]
+    fn test_injected_write_failures() {
+        let path = std::env::temp_dir().join(format!("flywatch-chaos-{}.db", std::process::id()));
+        let buffer = LogBuffer::new(LogBufferConfig::default(), path.to_str());
+        assert_eq!(buffer.probe_store(), Some(Ok(())));
+
+        buffer.set_write_failure_rate(1.0);
+        assert_eq!(
+            buffer.probe_store(),
+            Some(Err(INJECTED_FAILURE.to_string()))
+        );
+        buffer.set_write_failure_rate(0.0);
+        assert_eq!(buffer.probe_store(), Some(Ok(())));
+
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", path.display(), suffix));
+        }
+    }
+
+    #[test]
+    fn test_parse_fly_log_fields() {
+        let raw = r#"{"message":"boot","log":{"level":"info"},"fly":{"app":{"name":"web","instance":"abc"},"region":"ord"}}"#;
+        let log = TimestampedLog::new(raw.to_string(), 1);
+        assert_eq!(log.app.as_deref(), Some("web"));
+        assert_eq!(log.instance.as_deref(), Some("abc"));
+        assert_eq!(log.region.as_deref(), Some("ord"));
+        assert_eq!(log.level.as_deref(), Some("info"));
+        assert_eq!(log.message.as_deref(), Some("boot"));
+        assert_eq!(log.trace_id, None);
+    }
+
+    #[test]
+    fn test_parse_correlation_ids() {
+        let log = TimestampedLog::new(
+            r#"{"trace_id":"4bf92f35","span":{"id":"00f067aa"},"message":"hi"}"#.to_string(),
+            1,
+        );
+        assert_eq!(log.trace_id.as_deref(), Some("4bf92f35"));
+        assert_eq!(log.span_id.as_deref(), Some("00f067aa"));
+
+        // A JSON line the app wrote to stdout
+        let message = r#"{"msg":"charge failed","traceId":"abc","requestId":42}"#;
+        let log = TimestampedLog::new(fly_envelope(message, None, Some("web-1"), None), 2);
+        assert_eq!(log.trace_id.as_deref(), Some("abc"));
+        assert_eq!(log.request_id.as_deref(), Some("42"));
+        assert!(log.correlates_with("42"));
+        assert!(!log.correlates_with("abd"));
+    }
+
+    #[test]
+    fn test_parse_structured_fields() {
+        let message = r#"{"msg":"charge failed","status":502,"user":{"id":"u1"}}"#;
+        let log = TimestampedLog::new(fly_envelope(message, None, None, None), 1);
+        assert_eq!(log.field("status"), Some(&Value::from(502)));
+        assert_eq!(log.field("user.id"), Some(&Value::from("u1")));
+        assert_eq!(log.field("fly"), None);
+
+        // Plain-text messages yield their key=value pairs
+        let text = r#"GET /cart 200 took=12.5 cached=true user="ann lee" 5=x =y"#;
+        let log = TimestampedLog::new(fly_envelope(text, None, None, None), 2);
+        assert_eq!(log.fields.len(), 3);
+        assert_eq!(log.field("took"), Some(&Value::from(12.5)));
+        assert_eq!(log.field("cached"), Some(&Value::Bool(true)));
+        assert_eq!(log.field("user"), Some(&Value::from("ann lee")));
+
+        // JSON lines from other shippers, and plain lines
+        let log = TimestampedLog::new(r#"{"level":"warn","queue":"emails"}"#.to_string(), 3);
+        assert_eq!(log.field("queue"), Some(&Value::from("emails")));
+        let log = TimestampedLog::new("worker=3 retry=2".to_string(), 4);
+        assert_eq!(log.field("retry"), Some(&Value::from(2)));
+    }
+
+    #[tokio::test]
+    async fn test_push_assigns_increasing_seq() {
+        let buffer = LogBuffer::new(LogBufferConfig::default(), None);
+        let first = buffer.push("one".to_string()).await.seq;
+        let second = buffer.push("two".to_string()).await.seq;
+        assert_eq!(second, first + 1);
+
+        let (since, oldest) = buffer.get_since(first, 10).await;
+        assert_eq!(oldest, Some(first));
+        assert_eq!(since.len(), 1);
+        assert_eq!(since[0].raw, "two");
+    }
+
+    #[tokio::test]
+    async fn test_sequenced_push_keeps_the_seq() {
+        let buffer = LogBuffer::new(LogBufferConfig::default(), None);
+        buffer
+            .push_sequenced(TimestampedLog::new("seven".to_string(), 7))
+            .await;
+        buffer
+            .push_sequenced(TimestampedLog::new("five".to_string(), 5))
+            .await;
+
+        let (since, oldest) = buffer.get_since(5, 10).await;
+        assert_eq!(oldest, Some(5));
+        assert_eq!(since.len(), 1);
+        assert_eq!(since[0].raw, "seven");
+        assert_eq!(buffer.push("next".to_string()).await.seq, 8);
+    }
+
+    #[tokio::test]
+    async fn test_backlogged_lines_keep_their_event_time() {
+        let event = DateTime::parse_from_rfc3339("2025-03-01T10:00:00.5Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let log = TimestampedLog::new(
+            r#"{"timestamp":"2025-03-01T10:00:00.5Z","message":"late"}"#.to_string(),
+            1,
+        );
+        assert_eq!(log.timestamp, event);
+        assert!(log.received_at > event);
+
+        for raw in [
+            r#"{"ts":1740823200.5}"#,
+            r#"{"time":1740823200500}"#,
+            r#"{"@timestamp":"2025-03-01 10:00:00.500"}"#,
+        ] {
+            let log = TimestampedLog::new(raw.to_string(), 1);
+            assert_eq!(log.timestamp, event, "{}", raw);
+        }
+        // Implausibly future times fall back to receipt
+        let future = r#"{"timestamp":"2999-01-01T00:00:00Z"}"#;
+        let future = TimestampedLog::new(future.to_string(), 1);
+        assert_eq!(future.timestamp, future.received_at);
+
+        // Time ranges follow the event time even though the line arrived now
+        let buffer = LogBuffer::new(LogBufferConfig::default(), None);
+        buffer
+            .push(r#"{"timestamp":"2025-03-01T10:00:00.5Z"}"#.to_string())
+            .await;
+        buffer.push("now".to_string()).await;
+        let range = buffer
+            .get_time_range(event - Duration::minutes(1), event + Duration::minutes(1))
+            .await;
+        assert_eq!(range.len(), 1);
+        assert_eq!(buffer.total_count().await, 2);
+    }
+}
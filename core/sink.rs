@@ -0,0 +1,61 @@
+//! The exporter abstraction: a `Sink` receives batches of buffered lines
+//! and reports which of them the destination refused.
+
+use async_trait::async_trait;
+use std::fmt;
+use std::time::Duration;
+
+use crate::log_buffer::LogMessage;
+
+// ==================== Sink Trait ====================
+
+/// A destination every buffered line is exported to.
+///
+/// `send` is only ever called by the sink's own worker, one batch at a time;
+/// the worker owns queueing, retries and dead letters.
+#[async_trait]
+pub trait Sink: Send + Sync {
+    /// Sink kind, e.g. "clickhouse"; also its name in health and metrics
+    fn kind(&self) -> &'static str;
+
+    /// Queue and batch sizes that suit the destination
+    fn defaults(&self) -> SinkDefaults;
+
+    /// Deliver a batch, returning the lines the destination refused
+    async fn send(&self, batch: &[LogMessage]) -> Result<Vec<Rejection>, SinkError>;
+}
+
+/// A line in a batch the destination didn't accept
+#[derive(Debug)]
+pub struct Rejection {
+    /// Position in the batch passed to `send`
+    pub index: usize,
+    /// Whether sending it again may succeed (e.g. the destination was busy)
+    pub retryable: bool,
+    pub reason: String,
+}
+
+#[derive(Debug)]
+pub enum SinkError {
+    /// The destination couldn't be reached or was overloaded; retried
+    Unavailable(String),
+    /// The destination refused the batch; retrying won't help
+    Rejected(String),
+}
+
+impl fmt::Display for SinkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SinkError::Unavailable(msg) => write!(f, "unavailable: {}", msg),
+            SinkError::Rejected(msg) => write!(f, "rejected: {}", msg),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SinkDefaults {
+    pub queue_capacity: usize,
+    pub batch_size: usize,
+    pub flush_interval: Duration,
+    pub max_attempts: u32,
+}
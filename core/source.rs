@@ -0,0 +1,441 @@
+//! Log sources behind one abstraction: each runs under a supervisor that
+//! restarts it with backoff and tracks its health, and hands its lines to
+//! an `Ingest` implementation.
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures::FutureExt;
+use serde::Serialize;
+use std::any::Any;
+use std::fmt;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tracing::{error, info, warn};
+
+const RESTART_BACKOFF_INITIAL: Duration = Duration::from_secs(1);
+const RESTART_BACKOFF_MAX: Duration = Duration::from_secs(30);
+
+// ==================== Source Trait ====================
+
+/// A producer of raw log lines feeding an `Ingest`.
+///
+/// `run` should only return when the source can no longer make progress;
+/// the supervisor restarts it with backoff, including after a panic.
+#[async_trait]
+pub trait LogSource: Send + Sync {
+    /// Unique name of this source instance (used in health and metrics)
+    fn name(&self) -> &str;
+
+    /// Source kind, e.g. "nats" or "syslog"
+    fn kind(&self) -> &'static str;
+
+    /// Run the source until it fails or its input ends
+    async fn run(&self, ctx: &SourceContext) -> Result<(), SourceError>;
+}
+
+#[derive(Debug)]
+pub enum SourceError {
+    Connect(String),
+    Io(String),
+    Config(String),
+}
+
+impl fmt::Display for SourceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SourceError::Connect(msg) => write!(f, "connect error: {}", msg),
+            SourceError::Io(msg) => write!(f, "io error: {}", msg),
+            SourceError::Config(msg) => write!(f, "config error: {}", msg),
+        }
+    }
+}
+
+// ==================== Ingest ====================
+
+/// Where sources deliver their lines; the flywatch binary's `Pipeline`
+/// redacts, buffers and broadcasts them, an embedder can do anything
+#[async_trait]
+pub trait Ingest: Send + Sync {
+    /// Ingest a line, with the NATS subject it arrived on if any
+    async fn ingest_on(&self, subject: Option<&str>, raw: String);
+
+    /// Ingest a line another replica published
+    async fn ingest_replica(&self, raw: String);
+}
+
+// ==================== Source Context ====================
+
+/// Handle passed to a running source for emitting logs and reporting state
+pub struct SourceContext {
+    ingest: Arc<dyn Ingest>,
+    health: Arc<SourceHealth>,
+}
+
+impl SourceContext {
+    /// Feed one raw log line into the ingest path
+    pub async fn emit(&self, raw: String) {
+        self.health.record_message().await;
+        self.ingest.ingest_on(None, raw).await;
+    }
+
+    /// Feed one raw line received on a NATS subject
+    pub async fn emit_on(&self, subject: &str, raw: String) {
+        self.health.record_message().await;
+        self.ingest.ingest_on(Some(subject), raw).await;
+    }
+
+    /// Feed one line received from another replica
+    pub async fn emit_replica(&self, raw: String) {
+        self.health.record_message().await;
+        self.ingest.ingest_replica(raw).await;
+    }
+
+    /// Mark the source as running (e.g. once connected or bound)
+    pub async fn running(&self) {
+        self.health.set_status(SourceStatus::Running).await;
+    }
+
+    /// Record a non-fatal error that did not stop the source
+    pub async fn report_error(&self, err: SourceError) {
+        warn!(source = %self.health.name, error = %err, "Source error");
+        self.health.record_error(&err).await;
+    }
+}
+
+// ==================== Health ====================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum SourceStatus {
+    Starting,
+    Running,
+    Backoff,
+}
+
+/// Per-source health and counters
+#[derive(Debug)]
+pub struct SourceHealth {
+    name: String,
+    kind: &'static str,
+    messages_received: AtomicU64,
+    errors: AtomicU64,
+    restarts: AtomicU64,
+    panics: AtomicU64,
+    /// Exits since the source last stayed up long enough to count as healthy
+    consecutive_failures: AtomicU64,
+    state: RwLock<SourceState>,
+}
+
+#[derive(Debug, Clone)]
+struct SourceState {
+    status: SourceStatus,
+    last_message_at: Option<DateTime<Utc>>,
+    last_error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct SourceHealthSnapshot {
+    pub name: String,
+    pub kind: &'static str,
+    pub status: SourceStatus,
+    pub messages_received: u64,
+    pub errors: u64,
+    pub restarts: u64,
+    pub panics: u64,
+    pub consecutive_failures: u64,
+    pub last_message_at: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+}
+
+impl SourceHealth {
+    fn new(name: String, kind: &'static str) -> Arc<Self> {
+        Arc::new(Self {
+            name,
+            kind,
+            messages_received: AtomicU64::new(0),
+            errors: AtomicU64::new(0),
+            restarts: AtomicU64::new(0),
+            panics: AtomicU64::new(0),
+            consecutive_failures: AtomicU64::new(0),
+            state: RwLock::new(SourceState {
+                status: SourceStatus::Starting,
+                last_message_at: None,
+                last_error: None,
+            }),
+        })
+    }
+
+    async fn set_status(&self, status: SourceStatus) {
+        self.state.write().await.status = status;
+    }
+
+    async fn record_message(&self) {
+        self.messages_received.fetch_add(1, Ordering::SeqCst);
+        self.state.write().await.last_message_at = Some(Utc::now());
+    }
+
+    async fn record_error(&self, err: &SourceError) {
+        self.errors.fetch_add(1, Ordering::SeqCst);
+        self.state.write().await.last_error = Some(err.to_string());
+    }
+
+    async fn record_panic(&self, message: &str) {
+        self.panics.fetch_add(1, Ordering::SeqCst);
+        self.state.write().await.last_error = Some(format!("panicked: {}", message));
+    }
+
+    pub async fn snapshot(&self) -> SourceHealthSnapshot {
+        let state = self.state.read().await.clone();
+        SourceHealthSnapshot {
+            name: self.name.clone(),
+            kind: self.kind,
+            status: state.status,
+            messages_received: self.messages_received.load(Ordering::SeqCst),
+            errors: self.errors.load(Ordering::SeqCst),
+            restarts: self.restarts.load(Ordering::SeqCst),
+            panics: self.panics.load(Ordering::SeqCst),
+            consecutive_failures: self.consecutive_failures.load(Ordering::SeqCst),
+            last_message_at: state.last_message_at,
+            last_error: state.last_error,
+        }
+    }
+}
+
+// ==================== Helpers ====================
+
+/// Wrap a non-Fly log line in the Fly log JSON shape so `TimestampedLog`
+/// extracts level/instance/region the same way for every source
+pub fn fly_envelope(
+    message: &str,
+    level: Option<&str>,
+    instance: Option<&str>,
+    region: Option<&str>,
+) -> String {
+    fly_app_envelope(message, level, None, instance, region)
+}
+
+/// `fly_envelope` for sources that know which app a line belongs to
+pub fn fly_app_envelope(
+    message: &str,
+    level: Option<&str>,
+    app: Option<&str>,
+    instance: Option<&str>,
+    region: Option<&str>,
+) -> String {
+    let mut envelope = serde_json::json!({
+        "message": message,
+        "log": { "level": level },
+        "fly": { "app": { "instance": instance }, "region": region },
+    });
+    if let Some(app) = app {
+        envelope["fly"]["app"]["name"] = app.into();
+    }
+    envelope.to_string()
+}
+
+/// Use the level of a JSON-formatted application line if it has one
+pub fn json_line_level(line: &str) -> Option<&'static str> {
+    let value: serde_json::Value = serde_json::from_str(line).ok()?;
+    let level = value.get("level")?.as_str()?.to_ascii_lowercase();
+    match level.as_str() {
+        "error" | "err" | "fatal" | "critical" => Some("error"),
+        "warn" | "warning" => Some("warn"),
+        "info" => Some("info"),
+        "debug" | "trace" => Some("debug"),
+        _ => None,
+    }
+}
+
+/// Split complete lines off the front of `buf`, leaving any trailing partial line
+pub fn drain_lines(buf: &mut Vec<u8>) -> Vec<String> {
+    let Some(last_newline) = buf.iter().rposition(|&b| b == b'\n') else {
+        return Vec::new();
+    };
+
+    let complete: Vec<u8> = buf.drain(..=last_newline).collect();
+    String::from_utf8_lossy(&complete)
+        .lines()
+        .map(|l| l.trim_end_matches('\r'))
+        .filter(|l| !l.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+// ==================== Registry & Supervisor ====================
+
+/// Registry of supervised sources, shared with the HTTP layer
+#[derive(Default)]
+pub struct SourceRegistry {
+    sources: RwLock<Vec<Arc<SourceHealth>>>,
+}
+
+impl SourceRegistry {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    pub async fn snapshot(&self) -> Vec<SourceHealthSnapshot> {
+        let sources = self.sources.read().await;
+        let mut result = Vec::with_capacity(sources.len());
+        for health in sources.iter() {
+            result.push(health.snapshot().await);
+        }
+        result
+    }
+
+    /// Register a source and spawn a supervisor task that restarts it on exit
+    pub async fn spawn(&self, source: Box<dyn LogSource>, ingest: Arc<dyn Ingest>) {
+        let health = SourceHealth::new(source.name().to_string(), source.kind());
+        self.sources.write().await.push(health.clone());
+
+        info!(source = %source.name(), kind = source.kind(), "Starting log source");
+
+        tokio::spawn(async move {
+            let ctx = SourceContext { ingest, health };
+
+            loop {
+                ctx.health.set_status(SourceStatus::Starting).await;
+                let started = std::time::Instant::now();
+
+                // A panic must not take the supervisor down with the source
+                match AssertUnwindSafe(source.run(&ctx)).catch_unwind().await {
+                    Ok(Ok(())) => warn!(source = %source.name(), "Log source ended"),
+                    Ok(Err(e)) => {
+                        error!(source = %source.name(), error = %e, "Log source failed");
+                        ctx.health.record_error(&e).await;
+                    }
+                    Err(payload) => {
+                        let message = panic_message(payload.as_ref());
+                        error!(source = %source.name(), panic = %message, "Log source panicked");
+                        ctx.health.record_panic(&message).await;
+                    }
+                }
+
+                // A source that stayed up for a while starts counting afresh
+                if started.elapsed() > RESTART_BACKOFF_MAX {
+                    ctx.health.consecutive_failures.store(0, Ordering::SeqCst);
+                }
+                let failures = ctx
+                    .health
+                    .consecutive_failures
+                    .fetch_add(1, Ordering::SeqCst)
+                    + 1;
+                let backoff = restart_backoff(failures);
+
+                ctx.health.set_status(SourceStatus::Backoff).await;
+                ctx.health.restarts.fetch_add(1, Ordering::SeqCst);
+                warn!(
+                    source = %source.name(),
+                    consecutive_failures = failures,
+                    backoff_secs = backoff.as_secs(),
+                    "Restarting log source after backoff"
+                );
+                tokio::time::sleep(backoff).await;
+            }
+        });
+    }
+}
+
+/// Delay before the restart following the `failures`-th consecutive exit
+fn restart_backoff(failures: u64) -> Duration {
+    let exponent = failures.saturating_sub(1).min(16) as u32;
+    std::cmp::min(
+        RESTART_BACKOFF_INITIAL * 2u32.pow(exponent),
+        RESTART_BACKOFF_MAX,
+    )
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_drain_lines_keeps_partial() {
+        let mut buf = b"first\r\nsecond\n\nthi".to_vec();
+        assert_eq!(drain_lines(&mut buf), vec!["first", "second"]);
+        assert_eq!(buf, b"thi");
+
+        buf.extend_from_slice(b"rd\n");
+        assert_eq!(drain_lines(&mut buf), vec!["third"]);
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_fly_envelope_round_trips_through_parser() {
+        let raw = fly_envelope("boom", Some("error"), Some("web-1"), Some("iad"));
+        let log = crate::log_buffer::TimestampedLog::new(raw, 1);
+        assert_eq!(log.message.as_deref(), Some("boom"));
+        assert_eq!(log.level.as_deref(), Some("error"));
+        assert_eq!(log.instance.as_deref(), Some("web-1"));
+        assert_eq!(log.region.as_deref(), Some("iad"));
+        assert_eq!(log.app, None);
+
+        let raw = fly_app_envelope("boom", None, Some("payments"), Some("api-7f9"), None);
+        let log = crate::log_buffer::TimestampedLog::new(raw, 2);
+        assert_eq!(log.app.as_deref(), Some("payments"));
+        assert_eq!(log.instance.as_deref(), Some("api-7f9"));
+    }
+
+    struct PanickingSource;
+
+    #[async_trait]
+    impl LogSource for PanickingSource {
+        fn name(&self) -> &str {
+            "broken"
+        }
+
+        fn kind(&self) -> &'static str {
+            "test"
+        }
+
+        async fn run(&self, _ctx: &SourceContext) -> Result<(), SourceError> {
+            panic!("subscriber blew up");
+        }
+    }
+
+    struct Discard;
+
+    #[async_trait]
+    impl Ingest for Discard {
+        async fn ingest_on(&self, _subject: Option<&str>, _raw: String) {}
+
+        async fn ingest_replica(&self, _raw: String) {}
+    }
+
+    #[tokio::test]
+    async fn test_supervisor_survives_panic() {
+        let registry = SourceRegistry::new();
+        registry
+            .spawn(Box::new(PanickingSource), Arc::new(Discard))
+            .await;
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let health = &registry.snapshot().await[0];
+        assert_eq!(health.status, SourceStatus::Backoff);
+        assert_eq!(health.panics, 1);
+        assert_eq!(health.consecutive_failures, 1);
+        assert_eq!(
+            health.last_error.as_deref(),
+            Some("panicked: subscriber blew up")
+        );
+
+        assert_eq!(restart_backoff(1), RESTART_BACKOFF_INITIAL);
+        assert_eq!(restart_backoff(3), RESTART_BACKOFF_INITIAL * 4);
+        assert_eq!(restart_backoff(100), RESTART_BACKOFF_MAX);
+    }
+}
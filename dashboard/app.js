@@ -0,0 +1,306 @@
+// flywatch dashboard: live logs, metrics, buffer stats, and chat.
+"use strict";
+
+const MAX_LOG_LINES = 2000;
+const CHART_POINTS = 60;
+
+const $ = (id) => document.getElementById(id);
+
+const state = {
+  // The session cookie is HttpOnly; only its CSRF token is visible here
+  csrf: null,
+  paused: false,
+  filter: "",
+  lastForwarded: null,
+  series: { cpu: [], mem: [], rate: [] },
+  stream: null,
+};
+
+function csrfHeaders() {
+  return state.csrf ? { "X-CSRF-Token": state.csrf } : {};
+}
+
+// ==================== Session ====================
+
+function showLogin(message) {
+  state.csrf = null;
+  if (state.stream) state.stream.close();
+  $("login-form").hidden = false;
+  $("logout").hidden = true;
+  if (message) setStatus(message, false);
+}
+
+function startSession(session) {
+  state.csrf = session.csrf_token;
+  $("login-form").hidden = true;
+  $("logout").hidden = !session.auth_required;
+  startLogStream();
+}
+
+async function resumeSession() {
+  const res = await fetch("/auth/session");
+  if (res.ok) startSession(await res.json());
+  else showLogin("Log in to stream logs");
+}
+
+async function login(username, secret) {
+  const body = username ? { username, password: secret } : { token: secret };
+  const res = await fetch("/auth/login", {
+    method: "POST",
+    headers: { "Content-Type": "application/json" },
+    body: JSON.stringify(body),
+  });
+  const session = await res.json().catch(() => ({}));
+  if (!res.ok) throw new Error(session.error || `HTTP ${res.status}`);
+  startSession(session);
+}
+
+// ==================== Logs ====================
+
+function renderLog(log) {
+  const line = document.createElement("div");
+  const level = (log.level || "").toLowerCase();
+  line.className = `log ${level.startsWith("err") ? "error" : level.startsWith("warn") ? "warn" : ""}`;
+
+  const ts = document.createElement("span");
+  ts.className = "ts";
+  ts.textContent = new Date(log.timestamp).toLocaleTimeString() + " ";
+
+  const meta = document.createElement("span");
+  meta.className = "meta";
+  meta.textContent = [log.region, log.instance, log.level].filter(Boolean).join(" ") + " ";
+
+  const msg = document.createElement("span");
+  msg.className = "msg";
+  msg.textContent = log.message ?? log.raw;
+
+  line.append(ts, meta, msg);
+  line.dataset.text = line.textContent.toLowerCase();
+  line.hidden = state.filter !== "" && !line.dataset.text.includes(state.filter);
+  return line;
+}
+
+function appendLogs(logs) {
+  if (state.paused) return;
+  const container = $("logs");
+  const stick = container.scrollTop + container.clientHeight >= container.scrollHeight - 20;
+
+  const fragment = document.createDocumentFragment();
+  for (const log of logs) fragment.append(renderLog(log));
+  container.append(fragment);
+
+  while (container.childElementCount > MAX_LOG_LINES) container.firstElementChild.remove();
+  if (stick) container.scrollTop = container.scrollHeight;
+}
+
+function handleFrame(text) {
+  let data;
+  try {
+    data = JSON.parse(text);
+  } catch {
+    return;
+  }
+  if (Array.isArray(data)) appendLogs(data);
+  else if (data.type === "error" || data.type === "close") setStatus(data.message, false);
+}
+
+// The session cookie rides along on the upgrade
+function startLogStream() {
+  if (state.stream) state.stream.close();
+  const proto = location.protocol === "https:" ? "wss" : "ws";
+  const ws = new WebSocket(`${proto}://${location.host}/logs/ws?format=json&batch_ms=250`);
+  ws.onmessage = (e) => handleFrame(e.data);
+  ws.onclose = (e) => {
+    // 1008: session expired or token rotated, 4001: closed by an operator; see the README
+    if (e.code === 1008) showLogin("Session expired");
+    else if (e.code !== 4001) setTimeout(startLogStream, e.code === 1013 ? 5000 : 2000);
+  };
+  state.stream = { close: () => { ws.onclose = null; ws.close(); } };
+}
+
+// ==================== Metrics ====================
+
+function setStatus(text, ok) {
+  const el = $("status");
+  el.textContent = text;
+  el.className = `pill ${ok ? "ok" : "bad"}`;
+}
+
+function drawChart(canvas, points, max) {
+  const ctx = canvas.getContext("2d");
+  const { width, height } = canvas;
+  ctx.clearRect(0, 0, width, height);
+  if (points.length < 2) return;
+
+  const top = Math.max(max ?? Math.max(...points), 1);
+  ctx.strokeStyle = getComputedStyle(document.body).getPropertyValue("--accent");
+  ctx.lineWidth = 2;
+  ctx.beginPath();
+  points.forEach((p, i) => {
+    const x = (i / (CHART_POINTS - 1)) * width;
+    const y = height - (p / top) * (height - 4) - 2;
+    i === 0 ? ctx.moveTo(x, y) : ctx.lineTo(x, y);
+  });
+  ctx.stroke();
+}
+
+function push(series, value) {
+  series.push(value);
+  if (series.length > CHART_POINTS) series.shift();
+}
+
+function renderStats(el, entries) {
+  el.replaceChildren(
+    ...entries.flatMap(([k, v]) => {
+      const dt = document.createElement("dt");
+      dt.textContent = k;
+      const dd = document.createElement("dd");
+      dd.textContent = v;
+      return [dt, dd];
+    }),
+  );
+}
+
+async function pollMetrics() {
+  try {
+    const m = await (await fetch("/metrics")).json();
+    setStatus(m.nats_connected ? "NATS connected" : "NATS disconnected", m.nats_connected);
+    $("uptime").textContent = `up ${formatDuration(m.uptime_seconds)}`;
+
+    const rate = state.lastForwarded === null ? 0 : m.messages_forwarded - state.lastForwarded;
+    state.lastForwarded = m.messages_forwarded;
+    push(state.series.rate, Math.max(rate, 0));
+
+    if (m.system) {
+      push(state.series.cpu, m.system.cpu_usage_percent);
+      push(state.series.mem, m.system.memory_usage_percent);
+      $("cpu-now").textContent = m.system.cpu_usage_percent.toFixed(1);
+      $("mem-now").textContent = m.system.memory_usage_percent.toFixed(1);
+    }
+    $("rate-now").textContent = rate;
+
+    drawChart($("chart-cpu"), state.series.cpu, 100);
+    drawChart($("chart-mem"), state.series.mem, 100);
+    drawChart($("chart-rate"), state.series.rate);
+
+    renderStats($("connections"), [
+      ["Forwarded", m.messages_forwarded.toLocaleString()],
+      ["SSE clients", m.active_sse_connections],
+      ["WS clients", m.active_ws_connections],
+      ["Subscription errors", m.subscription_errors],
+    ]);
+  } catch {
+    setStatus("flywatch unreachable", false);
+  }
+}
+
+async function pollBuffer() {
+  try {
+    const b = await (await fetch("/logs/buffer/stats")).json();
+    renderStats($("buffer"), [
+      ["Entries", b.total_count.toLocaleString()],
+      ["Errors", b.error_count],
+      ["Warnings", b.warn_count],
+      ["Oldest", b.oldest_timestamp ? new Date(b.oldest_timestamp).toLocaleTimeString() : "–"],
+      ["Instances", b.active_instances.join(", ") || "–"],
+    ]);
+    $("recent-errors").replaceChildren(
+      ...b.recent_errors.slice(0, 5).map((e) => {
+        const li = document.createElement("li");
+        li.textContent = e;
+        return li;
+      }),
+    );
+  } catch {
+    // Status pill already reports connectivity problems
+  }
+}
+
+function formatDuration(seconds) {
+  const h = Math.floor(seconds / 3600);
+  const m = Math.floor((seconds % 3600) / 60);
+  return h > 0 ? `${h}h ${m}m` : `${m}m ${seconds % 60}s`;
+}
+
+// ==================== Chat ====================
+
+function addChatMessage(role, text, meta) {
+  const el = document.createElement("div");
+  el.className = `msg ${role}`;
+  el.textContent = text;
+  if (meta) {
+    const m = document.createElement("div");
+    m.className = "meta";
+    m.textContent = meta;
+    el.append(m);
+  }
+  $("chat").append(el);
+  $("chat").scrollTop = $("chat").scrollHeight;
+  return el;
+}
+
+async function sendChat(message) {
+  addChatMessage("user", message);
+  const pending = addChatMessage("assistant", "Thinking…");
+  try {
+    const res = await fetch("/chat", {
+      method: "POST",
+      headers: { "Content-Type": "application/json", ...csrfHeaders() },
+      body: JSON.stringify({ message }),
+    });
+    const body = await res.json().catch(() => ({}));
+    if (!res.ok) throw new Error(body.error || body.message || `HTTP ${res.status}`);
+
+    pending.remove();
+    const tools = body.tools_called.length ? ` · tools: ${body.tools_called.join(", ")}` : "";
+    addChatMessage("assistant", body.response, `${body.model} · ${body.processing_time_ms}ms${tools}`);
+  } catch (e) {
+    pending.textContent = `Error: ${e.message}`;
+  }
+}
+
+// ==================== Wiring ====================
+
+$("login-form").addEventListener("submit", async (e) => {
+  e.preventDefault();
+  try {
+    await login($("login-user").value.trim(), $("login-secret").value);
+    $("login-secret").value = "";
+  } catch (err) {
+    setStatus(`login: ${err.message}`, false);
+  }
+});
+
+$("logout").addEventListener("click", async () => {
+  await fetch("/auth/logout", { method: "POST", headers: csrfHeaders() });
+  showLogin("Logged out");
+});
+
+$("log-filter").addEventListener("input", (e) => {
+  state.filter = e.target.value.toLowerCase();
+  for (const line of $("logs").children) {
+    line.hidden = state.filter !== "" && !line.dataset.text.includes(state.filter);
+  }
+});
+
+$("pause").addEventListener("click", (e) => {
+  state.paused = !state.paused;
+  e.target.textContent = state.paused ? "Resume" : "Pause";
+});
+
+$("clear").addEventListener("click", () => $("logs").replaceChildren());
+
+$("chat-form").addEventListener("submit", (e) => {
+  e.preventDefault();
+  const input = $("chat-input");
+  const message = input.value.trim();
+  if (!message) return;
+  input.value = "";
+  sendChat(message);
+});
+
+resumeSession();
+pollMetrics();
+pollBuffer();
+setInterval(pollMetrics, 1000);
+setInterval(pollBuffer, 5000);
@@ -0,0 +1,1311 @@
+//! Alert state and delivery. Evaluators (SLO burn rates, ...) report
+//! conditions with `fire` and `resolve`; each transition is delivered to
+//! every configured notifier in the background. Firing alerts can be
+//! acknowledged and matching alerts silenced through the HTTP API; both
+//! survive restarts when a store is configured. With ALERT_AI_ENRICHMENT,
+//! new alerts are explained by the model before they go out.
+
+use async_trait::async_trait;
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{HashMap, VecDeque};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use stoar::Store;
+use tracing::{debug, error, info, warn};
+use utoipa::ToSchema;
+
+use crate::analysis::ResponseFormat;
+use crate::chat::{self, ChatRequest};
+use crate::config::Config;
+use crate::digest::escape_html;
+use crate::error::{ApiError, ErrorBody};
+use crate::http::{check_auth, AppState};
+use crate::log_buffer::LogBuffer;
+use crate::maintenance::Maintenance;
+use crate::smtp::{self, Email, SmtpSettings};
+use crate::tickets::{self, FiledTickets, TicketNotifier};
+
+const NOTIFY_TIMEOUT: Duration = Duration::from_secs(10);
+/// Longest an alert waits for its analysis before going out without one
+const ENRICH_TIMEOUT: Duration = Duration::from_secs(30);
+const ENRICH_MAX_TOKENS: u32 = 300;
+const PAGERDUTY_EVENTS_URL: &str = "https://events.pagerduty.com/v2/enqueue";
+/// Transitions kept for digests
+const HISTORY_CAPACITY: usize = 1000;
+const ACTIVE_COLLECTION: &str = "alerts_active";
+const SILENCE_COLLECTION: &str = "alert_silences";
+/// Longest silence accepted
+const MAX_SILENCE_MINUTES: i64 = 7 * 24 * 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Warning,
+    Critical,
+}
+
+impl FromStr for Severity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "warning" => Ok(Self::Warning),
+            "critical" => Ok(Self::Critical),
+            other => Err(format!(
+                "Invalid severity: {}. Use warning or critical.",
+                other
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertStatus {
+    Firing,
+    Resolved,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Alert {
+    /// Identifies this firing, for POST /alerts/{id}/ack
+    pub id: String,
+    /// The rule that raised it, e.g. "slo:checkout"
+    pub rule: String,
+    /// What within the rule is alerting; one rule can fire several alerts
+    pub signature: String,
+    pub severity: Severity,
+    pub status: AlertStatus,
+    pub summary: String,
+    pub started_at: DateTime<Utc>,
+    pub resolved_at: Option<DateTime<Utc>>,
+    pub acknowledged_at: Option<DateTime<Utc>>,
+    pub acknowledged_by: Option<String>,
+    /// A silence currently mutes it (set when listed)
+    #[serde(default)]
+    pub silenced: bool,
+    /// Whether notifiers have been told it is firing
+    #[serde(default)]
+    pub notified: bool,
+    /// The model's explanation, with ALERT_AI_ENRICHMENT
+    #[serde(default)]
+    pub analysis: Option<String>,
+}
+
+impl Alert {
+    pub fn new(rule: &str, signature: &str, severity: Severity, summary: String) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            rule: rule.to_string(),
+            signature: signature.to_string(),
+            severity,
+            status: AlertStatus::Firing,
+            summary,
+            started_at: Utc::now(),
+            resolved_at: None,
+            acknowledged_at: None,
+            acknowledged_by: None,
+            silenced: false,
+            notified: false,
+            analysis: None,
+        }
+    }
+
+    /// Stable identity of the alert across fire/resolve
+    pub fn key(&self) -> String {
+        format!("{}:{}", self.rule, self.signature)
+    }
+}
+
+/// Matches alert rules exactly, or by prefix with a trailing `*`
+/// (`slo:*`). Exact patterns also match a full `rule:signature` key.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RulePattern {
+    rule: String,
+    prefix: bool,
+}
+
+impl FromStr for RulePattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err("empty rule pattern".to_string());
+        }
+        Ok(match s.strip_suffix('*') {
+            Some(prefix) => Self {
+                rule: prefix.to_string(),
+                prefix: true,
+            },
+            None => Self {
+                rule: s.to_string(),
+                prefix: false,
+            },
+        })
+    }
+}
+
+impl RulePattern {
+    pub fn matches(&self, alert: &Alert) -> bool {
+        self.matches_key(&alert.rule, &alert.key())
+    }
+
+    /// Match an alert that may not exist yet by its rule and key
+    pub fn matches_key(&self, rule: &str, key: &str) -> bool {
+        if self.prefix {
+            key.starts_with(&self.rule)
+        } else {
+            rule == self.rule || key == self.rule
+        }
+    }
+}
+
+/// Names accepted in `ALERT_ROUTES`
+pub const NOTIFIER_NAMES: &[&str] = &[
+    "webhook",
+    "pagerduty",
+    "discord",
+    "telegram",
+    "email",
+    "nats",
+    "github",
+    "linear",
+    "jira",
+];
+
+/// Sends matching alerts to a subset of the notifiers, e.g.
+/// `slo:checkout=discord|telegram` or `slo:*=pagerduty`
+#[derive(Debug, Clone)]
+pub struct AlertRoute {
+    text: String,
+    pattern: RulePattern,
+    pub notifiers: Vec<String>,
+}
+
+impl PartialEq for AlertRoute {
+    fn eq(&self, other: &Self) -> bool {
+        self.text == other.text
+    }
+}
+
+impl FromStr for AlertRoute {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (rule, notifiers) = s
+            .trim()
+            .split_once('=')
+            .ok_or("expected <rule>=<notifier>|<notifier>")?;
+        let notifiers: Vec<String> = notifiers
+            .split('|')
+            .map(|n| n.trim().to_lowercase())
+            .filter(|n| !n.is_empty())
+            .collect();
+        if notifiers.is_empty() {
+            return Err("no notifiers listed".to_string());
+        }
+        if let Some(unknown) = notifiers
+            .iter()
+            .find(|n| !NOTIFIER_NAMES.contains(&n.as_str()))
+        {
+            return Err(format!(
+                "unknown notifier '{}' (use {})",
+                unknown,
+                NOTIFIER_NAMES.join(", ")
+            ));
+        }
+        Ok(Self {
+            text: s.to_string(),
+            pattern: rule.parse()?,
+            notifiers,
+        })
+    }
+}
+
+pub(crate) fn notify_client() -> Client {
+    Client::builder()
+        .timeout(NOTIFY_TIMEOUT)
+        .build()
+        .unwrap_or_default()
+}
+
+/// One-message rendering for chat channels
+fn alert_text(alert: &Alert) -> String {
+    let severity = match alert.severity {
+        Severity::Warning => "WARNING",
+        Severity::Critical => "CRITICAL",
+    };
+    match (alert.status, &alert.acknowledged_by) {
+        (AlertStatus::Resolved, _) => format!("[RESOLVED] {}: {}", alert.rule, alert.summary),
+        (AlertStatus::Firing, Some(by)) => {
+            format!("[ACK by {}] {}: {}", by, alert.rule, alert.summary)
+        }
+        (AlertStatus::Firing, None) => {
+            let mut text = format!("[{}] {}: {}", severity, alert.rule, alert.summary);
+            if let Some(ref analysis) = alert.analysis {
+                text.push_str("\n\n");
+                text.push_str(analysis);
+            }
+            text
+        }
+    }
+}
+
+/// A delivery channel for alert transitions
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    async fn notify(&self, alert: &Alert) -> Result<(), String>;
+}
+
+/// POSTs the alert as JSON to a URL
+pub struct WebhookNotifier {
+    url: String,
+    client: Client,
+}
+
+impl WebhookNotifier {
+    pub fn new(url: String) -> Self {
+        Self {
+            url,
+            client: notify_client(),
+        }
+    }
+}
+
+#[async_trait]
+impl Notifier for WebhookNotifier {
+    fn name(&self) -> &'static str {
+        "webhook"
+    }
+
+    async fn notify(&self, alert: &Alert) -> Result<(), String> {
+        let response = self
+            .client
+            .post(&self.url)
+            .json(alert)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if !response.status().is_success() {
+            return Err(format!("webhook returned {}", response.status()));
+        }
+        Ok(())
+    }
+}
+
+/// Triggers, acknowledges and resolves PagerDuty incidents through the
+/// Events API v2.
+/// Alerts below `min_severity` are not paged; resolves are always sent,
+/// since PagerDuty ignores those for dedup keys it never saw.
+pub struct PagerDutyNotifier {
+    routing_key: String,
+    min_severity: Severity,
+    source: String,
+    client: Client,
+}
+
+impl PagerDutyNotifier {
+    pub fn new(routing_key: String, min_severity: Severity, source: String) -> Self {
+        Self {
+            routing_key,
+            min_severity,
+            source,
+            client: notify_client(),
+        }
+    }
+
+    fn event(&self, alert: &Alert) -> Option<serde_json::Value> {
+        let dedup_key = alert.key();
+        match alert.status {
+            AlertStatus::Firing if alert.severity < self.min_severity => None,
+            AlertStatus::Firing if alert.acknowledged_at.is_some() => Some(json!({
+                "routing_key": self.routing_key,
+                "event_action": "acknowledge",
+                "dedup_key": dedup_key,
+            })),
+            AlertStatus::Firing => Some(json!({
+                "routing_key": self.routing_key,
+                "event_action": "trigger",
+                "dedup_key": dedup_key,
+                "payload": {
+                    "summary": alert.summary,
+                    "source": self.source,
+                    "severity": alert.severity,
+                    "timestamp": alert.started_at,
+                    "component": alert.rule,
+                    "custom_details": { "analysis": alert.analysis },
+                },
+            })),
+            AlertStatus::Resolved => Some(json!({
+                "routing_key": self.routing_key,
+                "event_action": "resolve",
+                "dedup_key": dedup_key,
+            })),
+        }
+    }
+}
+
+#[async_trait]
+impl Notifier for PagerDutyNotifier {
+    fn name(&self) -> &'static str {
+        "pagerduty"
+    }
+
+    async fn notify(&self, alert: &Alert) -> Result<(), String> {
+        let Some(event) = self.event(alert) else {
+            return Ok(());
+        };
+        let response = self
+            .client
+            .post(PAGERDUTY_EVENTS_URL)
+            .json(&event)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(format!("PagerDuty returned {}: {}", status, body));
+        }
+        Ok(())
+    }
+}
+
+/// Posts to a Discord channel webhook
+pub struct DiscordNotifier {
+    url: String,
+    client: Client,
+}
+
+impl DiscordNotifier {
+    /// Discord rejects messages over 2000 characters
+    const MAX_CONTENT: usize = 2000;
+
+    pub fn new(url: String) -> Self {
+        Self {
+            url,
+            client: notify_client(),
+        }
+    }
+}
+
+#[async_trait]
+impl Notifier for DiscordNotifier {
+    fn name(&self) -> &'static str {
+        "discord"
+    }
+
+    async fn notify(&self, alert: &Alert) -> Result<(), String> {
+        let content: String = alert_text(alert).chars().take(Self::MAX_CONTENT).collect();
+        let response = self
+            .client
+            .post(&self.url)
+            .json(&json!({ "content": content }))
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if !response.status().is_success() {
+            return Err(format!("Discord returned {}", response.status()));
+        }
+        Ok(())
+    }
+}
+
+/// Sends through a Telegram bot to one chat
+pub struct TelegramNotifier {
+    url: String,
+    chat_id: String,
+    client: Client,
+}
+
+impl TelegramNotifier {
+    pub fn new(bot_token: &str, chat_id: String) -> Self {
+        Self {
+            url: format!("https://api.telegram.org/bot{}/sendMessage", bot_token),
+            chat_id,
+            client: notify_client(),
+        }
+    }
+}
+
+#[async_trait]
+impl Notifier for TelegramNotifier {
+    fn name(&self) -> &'static str {
+        "telegram"
+    }
+
+    async fn notify(&self, alert: &Alert) -> Result<(), String> {
+        let response = self
+            .client
+            .post(&self.url)
+            .json(&json!({ "chat_id": self.chat_id, "text": alert_text(alert) }))
+            .send()
+            .await
+            // The error would include the URL, and with it the bot token
+            .map_err(|e| e.without_url().to_string())?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(format!("Telegram returned {}: {}", status, body));
+        }
+        Ok(())
+    }
+}
+
+/// Emails each transition to the SMTP recipients
+pub struct EmailNotifier {
+    settings: SmtpSettings,
+}
+
+impl EmailNotifier {
+    pub fn new(settings: SmtpSettings) -> Self {
+        Self { settings }
+    }
+}
+
+#[async_trait]
+impl Notifier for EmailNotifier {
+    fn name(&self) -> &'static str {
+        "email"
+    }
+
+    async fn notify(&self, alert: &Alert) -> Result<(), String> {
+        let text = alert_text(alert);
+        let mut html = format!(
+            "<p><strong>{}</strong></p><p>{}</p><p>Started {}</p>",
+            escape_html(&alert.rule),
+            escape_html(&alert.summary),
+            alert.started_at.to_rfc3339()
+        );
+        if let Some(ref analysis) = alert.analysis {
+            html.push_str(&format!("<p><em>{}</em></p>", escape_html(analysis)));
+        }
+        let email = Email {
+            subject: text.clone(),
+            text,
+            html,
+        };
+        smtp::send(&self.settings, &email)
+            .await
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// Explains a new alert before it is delivered (see ALERT_AI_ENRICHMENT)
+#[async_trait]
+pub trait Enricher: Send + Sync {
+    async fn analyze(&self, alert: &Alert) -> Option<String>;
+}
+
+/// Asks the chat pipeline, with its log tools, to explain the alert
+pub struct ChatEnricher {
+    state: AppState,
+}
+
+impl ChatEnricher {
+    pub fn new(state: AppState) -> Self {
+        Self { state }
+    }
+}
+
+#[async_trait]
+impl Enricher for ChatEnricher {
+    async fn analyze(&self, alert: &Alert) -> Option<String> {
+        let request = ChatRequest {
+            message: format!(
+                "Alert {} is firing ({:?}): {}. Explain this spike for the on-call \
+                 engineer in 2-3 sentences: the likely cause according to the recent \
+                 logs, and where to look first. Plain text, no lists.",
+                alert.key(),
+                alert.severity,
+                alert.summary
+            ),
+            model: None,
+            conversation_id: Some(format!("alert-{}", alert.id)),
+            max_tokens: Some(ENRICH_MAX_TOKENS),
+            temperature: None,
+            response_format: ResponseFormat::Text,
+        };
+        match chat::run_chat(&self.state, request, None).await {
+            Ok(response) => Some(response.response.trim().to_string()).filter(|a| !a.is_empty()),
+            Err(e) => {
+                warn!(alert = %alert.key(), error = ?e, "Alert analysis failed");
+                None
+            }
+        }
+    }
+}
+
+fn build_notifiers(
+    config: &Config,
+    log_buffer: Option<&Arc<LogBuffer>>,
+    filed: &Arc<FiledTickets>,
+) -> Vec<Arc<dyn Notifier>> {
+    let mut notifiers: Vec<Arc<dyn Notifier>> = Vec::new();
+    if let Some(ref url) = config.alert_webhook_url {
+        notifiers.push(Arc::new(WebhookNotifier::new(url.clone())));
+    }
+    if let Some(ref routing_key) = config.pagerduty_routing_key {
+        notifiers.push(Arc::new(PagerDutyNotifier::new(
+            routing_key.clone(),
+            config.pagerduty_min_severity,
+            format!("flywatch/{}", config.fly_prod_app_name),
+        )));
+    }
+    if let Some(ref url) = config.discord_webhook_url {
+        notifiers.push(Arc::new(DiscordNotifier::new(url.clone())));
+    }
+    if let (Some(ref token), Some(ref chat_id)) =
+        (&config.telegram_bot_token, &config.telegram_chat_id)
+    {
+        notifiers.push(Arc::new(TelegramNotifier::new(token, chat_id.clone())));
+    }
+    // Alert emails are opt-in; SMTP on its own only sends digests
+    if config.smtp_alerts {
+        if let Some(settings) = SmtpSettings::from_config(config) {
+            notifiers.push(Arc::new(EmailNotifier::new(settings)));
+        }
+    }
+    // Likewise issues: trackers on their own only take confirmed findings
+    if config.ticket_alerts {
+        for tracker in tickets::trackers(config) {
+            notifiers.push(Arc::new(TicketNotifier::new(
+                tracker,
+                config,
+                log_buffer.cloned(),
+                filed.clone(),
+            )));
+        }
+    }
+    notifiers
+}
+
+/// Configured notifiers and the routes that choose between them
+struct Delivery {
+    notifiers: Vec<Arc<dyn Notifier>>,
+    routes: Vec<AlertRoute>,
+    enrich: bool,
+}
+
+impl Delivery {
+    /// `events` is the NATS event publisher, which outlives config reloads;
+    /// `log_buffer` is quoted in alert issues and `filed` remembers them
+    fn new(
+        config: &Config,
+        events: Option<Arc<dyn Notifier>>,
+        log_buffer: Option<&Arc<LogBuffer>>,
+        filed: &Arc<FiledTickets>,
+    ) -> Self {
+        let mut notifiers = build_notifiers(config, log_buffer, filed);
+        notifiers.extend(events);
+        Self {
+            notifiers,
+            routes: config.alert_routes.clone(),
+            enrich: config.alert_ai_enrichment,
+        }
+    }
+
+    /// The first matching route's notifiers, or all of them
+    fn for_alert(&self, alert: &Alert) -> Vec<Arc<dyn Notifier>> {
+        match self
+            .routes
+            .iter()
+            .find(|route| route.pattern.matches(alert))
+        {
+            Some(route) => self
+                .notifiers
+                .iter()
+                .filter(|n| route.notifiers.iter().any(|name| name == n.name()))
+                .cloned()
+                .collect(),
+            None => self.notifiers.clone(),
+        }
+    }
+}
+
+/// Mutes notifications for matching alerts until `ends_at`
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Silence {
+    pub id: String,
+    /// Rule pattern, e.g. "slo:checkout" or "slo:*"
+    pub matcher: String,
+    pub created_at: DateTime<Utc>,
+    pub ends_at: DateTime<Utc>,
+    pub created_by: Option<String>,
+    pub comment: Option<String>,
+}
+
+impl Silence {
+    fn mutes(&self, alert: &Alert, now: DateTime<Utc>) -> bool {
+        self.ends_at > now
+            && self
+                .matcher
+                .parse::<RulePattern>()
+                .is_ok_and(|pattern| pattern.matches(alert))
+    }
+}
+
+/// Active alerts and the notifiers they are delivered to
+pub struct Alerts {
+    active: Arc<Mutex<HashMap<String, Alert>>>,
+    silences: Mutex<Vec<Silence>>,
+    /// Recent transitions, oldest first
+    history: Mutex<VecDeque<Alert>>,
+    delivery: RwLock<Delivery>,
+    /// Publishes transitions on NATS (EVENTS_SUBJECT)
+    events: RwLock<Option<Arc<dyn Notifier>>>,
+    maintenance: Maintenance,
+    enricher: RwLock<Option<Arc<dyn Enricher>>>,
+    /// Lines quoted in alert issues (TICKET_ALERTS)
+    log_buffer: RwLock<Option<Arc<LogBuffer>>>,
+    /// Firings with an issue open, kept across notifier rebuilds
+    filed_tickets: Arc<FiledTickets>,
+    store: Option<Arc<Store>>,
+}
+
+impl Alerts {
+    pub fn new(config: &Config) -> Arc<Self> {
+        let store = config
+            .store_path
+            .as_deref()
+            .and_then(|path| match Store::open(path) {
+                Ok(s) => Some(Arc::new(s)),
+                Err(e) => {
+                    error!(error = %e, path = %path, "Failed to open alert store, keeping alert state in memory");
+                    None
+                }
+            });
+
+        let (mut active, mut silences) = (HashMap::new(), Vec::new());
+        if let Some(ref store) = store {
+            let alerts: Vec<Alert> = store.all(ACTIVE_COLLECTION).unwrap_or_else(|e| {
+                error!(error = %e, "Failed to load active alerts");
+                Vec::new()
+            });
+            active.extend(alerts.into_iter().map(|alert| (alert.key(), alert)));
+            let now = Utc::now();
+            let stored: Vec<Silence> = store.all(SILENCE_COLLECTION).unwrap_or_else(|e| {
+                error!(error = %e, "Failed to load silences");
+                Vec::new()
+            });
+            for silence in stored {
+                if silence.ends_at > now {
+                    silences.push(silence);
+                } else {
+                    let _ = store.delete(SILENCE_COLLECTION, &silence.id);
+                }
+            }
+            if !active.is_empty() || !silences.is_empty() {
+                info!(
+                    alerts = active.len(),
+                    silences = silences.len(),
+                    "Restored alert state"
+                );
+            }
+        }
+
+        let filed_tickets = Arc::new(FiledTickets::default());
+        Arc::new(Self {
+            active: Arc::new(Mutex::new(active)),
+            silences: Mutex::new(silences),
+            history: Mutex::new(VecDeque::new()),
+            delivery: RwLock::new(Delivery::new(config, None, None, &filed_tickets)),
+            events: RwLock::new(None),
+            maintenance: Maintenance::new(config.store_path.as_deref()),
+            enricher: RwLock::new(None),
+            log_buffer: RwLock::new(None),
+            filed_tickets,
+            store,
+        })
+    }
+
+    pub fn maintenance(&self) -> &Maintenance {
+        &self.maintenance
+    }
+
+    /// Used for new alerts while ALERT_AI_ENRICHMENT is on
+    pub fn set_enricher(&self, enricher: Arc<dyn Enricher>) {
+        *self.enricher.write().unwrap() = Some(enricher);
+    }
+
+    /// Deliver to NATS as well, once the event publisher is connected
+    pub fn set_events(&self, events: Arc<dyn Notifier>) {
+        *self.events.write().unwrap() = Some(events.clone());
+        self.delivery.write().unwrap().notifiers.push(events);
+    }
+
+    /// Quote lines from this buffer in alert issues
+    pub fn set_log_buffer(&self, config: &Config, log_buffer: Arc<LogBuffer>) {
+        *self.log_buffer.write().unwrap() = Some(log_buffer);
+        self.configure(config);
+    }
+
+    /// Rebuild the notifiers and routes after a config reload
+    pub fn configure(&self, config: &Config) {
+        let events = self.events.read().unwrap().clone();
+        let log_buffer = self.log_buffer.read().unwrap().clone();
+        *self.delivery.write().unwrap() =
+            Delivery::new(config, events, log_buffer.as_ref(), &self.filed_tickets);
+    }
+
+    fn persist(&self, alert: &Alert) {
+        persist_alert(self.store.as_deref(), alert);
+    }
+
+    /// Raise an alert, or update it if already firing. Notifies when the
+    /// alert is new or its severity changed, unless it was acknowledged.
+    /// Nothing is raised while a maintenance window covers the rule.
+    pub fn fire(&self, rule: &str, signature: &str, severity: Severity, summary: String) {
+        let key = format!("{}:{}", rule, signature);
+        if let Some(window) = self.maintenance.covering(rule, &key, Utc::now()) {
+            debug!(alert = %key, window = %window.name, "Alert suppressed by maintenance window");
+            return;
+        }
+        let (alert, notify) = {
+            let mut active = self.active.lock().unwrap();
+            match active.get_mut(&key) {
+                Some(alert) if alert.severity == severity => {
+                    alert.summary = summary;
+                    return;
+                }
+                Some(alert) => {
+                    alert.severity = severity;
+                    alert.summary = summary;
+                    (alert.clone(), alert.acknowledged_at.is_none())
+                }
+                None => {
+                    let alert = Alert::new(rule, signature, severity, summary);
+                    active.insert(key, alert.clone());
+                    (alert, true)
+                }
+            }
+        };
+        warn!(alert = %alert.key(), severity = ?alert.severity, summary = %alert.summary, "Alert firing");
+        self.record(alert.clone());
+        if notify {
+            self.notify(alert);
+        } else {
+            self.persist(&alert);
+        }
+    }
+
+    /// Clear an alert if it is firing
+    pub fn resolve(&self, rule: &str, signature: &str) {
+        let key = format!("{}:{}", rule, signature);
+        let Some(mut alert) = self.active.lock().unwrap().remove(&key) else {
+            return;
+        };
+        if let Some(ref store) = self.store {
+            let _ = store.delete(ACTIVE_COLLECTION, &key);
+        }
+        alert.status = AlertStatus::Resolved;
+        alert.resolved_at = Some(Utc::now());
+        info!(alert = %key, "Alert resolved");
+        // Routes may have changed since it fired, so not every tracker hears
+        self.filed_tickets.forget(&alert.id);
+        self.record(alert.clone());
+        // Nobody heard about it firing, so there is nothing to resolve
+        if alert.notified {
+            self.dispatch(alert);
+        }
+    }
+
+    /// Acknowledge a firing alert by id: later escalations stay quiet and
+    /// notifiers that track incidents (PagerDuty) are told
+    pub fn acknowledge(&self, id: &str, by: String) -> Option<Alert> {
+        let alert = {
+            let mut active = self.active.lock().unwrap();
+            let alert = active.values_mut().find(|alert| alert.id == id)?;
+            if alert.acknowledged_at.is_none() {
+                alert.acknowledged_at = Some(Utc::now());
+                alert.acknowledged_by = Some(by);
+            }
+            alert.clone()
+        };
+        info!(alert = %alert.key(), by = ?alert.acknowledged_by, "Alert acknowledged");
+        self.persist(&alert);
+        if alert.notified {
+            self.dispatch(alert.clone());
+        }
+        Some(alert)
+    }
+
+    /// Firing alerts, most severe first
+    pub fn active(&self) -> Vec<Alert> {
+        let now = Utc::now();
+        let silences = self.silences();
+        let mut alerts: Vec<Alert> = self.active.lock().unwrap().values().cloned().collect();
+        for alert in &mut alerts {
+            alert.silenced = silences.iter().any(|s| s.mutes(alert, now));
+        }
+        alerts.sort_by(|a, b| {
+            b.severity
+                .cmp(&a.severity)
+                .then(a.started_at.cmp(&b.started_at))
+        });
+        alerts
+    }
+
+    /// Transitions since `since`, oldest first
+    pub fn history_since(&self, since: DateTime<Utc>) -> Vec<Alert> {
+        self.history
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|alert| alert.resolved_at.unwrap_or(alert.started_at) >= since)
+            .cloned()
+            .collect()
+    }
+
+    /// Silences that have not ended
+    pub fn silences(&self) -> Vec<Silence> {
+        let now = Utc::now();
+        let mut silences = self.silences.lock().unwrap();
+        silences.retain(|s| s.ends_at > now);
+        silences.clone()
+    }
+
+    pub fn add_silence(
+        &self,
+        matcher: String,
+        minutes: i64,
+        created_by: Option<String>,
+        comment: Option<String>,
+    ) -> Result<Silence, String> {
+        matcher.parse::<RulePattern>()?;
+        if !(1..=MAX_SILENCE_MINUTES).contains(&minutes) {
+            return Err(format!(
+                "duration_minutes must be between 1 and {}",
+                MAX_SILENCE_MINUTES
+            ));
+        }
+        let now = Utc::now();
+        let silence = Silence {
+            id: uuid::Uuid::new_v4().to_string(),
+            matcher,
+            created_at: now,
+            ends_at: now + chrono::Duration::minutes(minutes),
+            created_by,
+            comment,
+        };
+        if let Some(ref store) = self.store {
+            if let Err(e) = store.put(SILENCE_COLLECTION, &silence.id, &silence) {
+                error!(error = %e, "Failed to persist silence");
+            }
+        }
+        info!(matcher = %silence.matcher, ends_at = %silence.ends_at, "Silence added");
+        self.silences.lock().unwrap().push(silence.clone());
+        Ok(silence)
+    }
+
+    /// End a silence early
+    pub fn expire_silence(&self, id: &str) -> bool {
+        let mut silences = self.silences.lock().unwrap();
+        let before = silences.len();
+        silences.retain(|s| s.id != id);
+        if let Some(ref store) = self.store {
+            let _ = store.delete(SILENCE_COLLECTION, id);
+        }
+        silences.len() != before
+    }
+
+    fn is_silenced(&self, alert: &Alert) -> bool {
+        let now = Utc::now();
+        self.silences
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|s| s.mutes(alert, now))
+    }
+
+    fn record(&self, alert: Alert) {
+        let mut history = self.history.lock().unwrap();
+        if history.len() == HISTORY_CAPACITY {
+            history.pop_front();
+        }
+        history.push_back(alert);
+    }
+
+    /// Announce a firing alert unless a silence mutes it
+    fn notify(&self, mut alert: Alert) {
+        let silenced = self.is_silenced(&alert);
+        if !silenced {
+            alert.notified = true;
+            if let Some(stored) = self.active.lock().unwrap().get_mut(&alert.key()) {
+                stored.notified = true;
+            }
+        }
+        self.persist(&alert);
+        if silenced {
+            info!(alert = %alert.key(), "Alert silenced");
+        } else {
+            self.dispatch(alert);
+        }
+    }
+
+    /// The enricher, if this alert should be explained before delivery
+    fn enricher_for(&self, alert: &Alert) -> Option<Arc<dyn Enricher>> {
+        if !self.delivery.read().unwrap().enrich
+            || alert.status != AlertStatus::Firing
+            || alert.acknowledged_at.is_some()
+            || alert.analysis.is_some()
+        {
+            return None;
+        }
+        self.enricher.read().unwrap().clone()
+    }
+
+    fn dispatch(&self, mut alert: Alert) {
+        let notifiers = self.delivery.read().unwrap().for_alert(&alert);
+        let enricher = self.enricher_for(&alert);
+        if notifiers.is_empty() && enricher.is_none() {
+            return;
+        }
+        let (active, store) = (self.active.clone(), self.store.clone());
+        tokio::spawn(async move {
+            if let Some(enricher) = enricher {
+                match tokio::time::timeout(ENRICH_TIMEOUT, enricher.analyze(&alert)).await {
+                    Ok(analysis) => alert.analysis = analysis,
+                    Err(_) => warn!(alert = %alert.key(), "Alert analysis timed out"),
+                }
+                // The alert may have resolved or refired while the model ran
+                if let Some(stored) = active
+                    .lock()
+                    .unwrap()
+                    .get_mut(&alert.key())
+                    .filter(|a| a.id == alert.id)
+                {
+                    stored.analysis = alert.analysis.clone();
+                    persist_alert(store.as_deref(), stored);
+                }
+            }
+            for notifier in notifiers {
+                if let Err(e) = notifier.notify(&alert).await {
+                    warn!(notifier = notifier.name(), alert = %alert.key(), error = %e, "Alert delivery failed");
+                }
+            }
+        });
+    }
+}
+
+fn persist_alert(store: Option<&Store>, alert: &Alert) {
+    if let Some(store) = store {
+        if let Err(e) = store.put(ACTIVE_COLLECTION, &alert.key(), alert) {
+            error!(error = %e, "Failed to persist alert");
+        }
+    }
+}
+
+// ==================== HTTP API ====================
+
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct AckRequest {
+    /// Who is handling it (default "api")
+    pub by: Option<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SilenceRequest {
+    /// Rule pattern: exact ("slo:checkout"), a full key
+    /// ("slo:checkout:burn_rate") or a prefix ("slo:*")
+    pub matcher: String,
+    /// How long to mute, up to 7 days
+    pub duration_minutes: i64,
+    pub created_by: Option<String>,
+    pub comment: Option<String>,
+}
+
+/// GET /alerts/active - firing alerts, most severe first
+#[utoipa::path(
+    get, path = "/alerts/active", tag = "alerts",
+    responses(
+        (status = 200, description = "Firing alerts, most severe first", body = Vec<Alert>),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+pub async fn active_alerts_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<Alert>>, ApiError> {
+    check_auth(&state, &headers)?;
+    Ok(Json(state.alerts.active()))
+}
+
+/// POST /alerts/{id}/ack - acknowledge a firing alert
+#[utoipa::path(
+    post, path = "/alerts/{id}/ack", tag = "alerts",
+    params(("id" = String, Path, description = "Alert id from GET /alerts/active")),
+    request_body(content = AckRequest, description = "Optional; who is handling it"),
+    responses(
+        (status = 200, description = "The acknowledged alert", body = Alert),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+        (status = 404, description = "No firing alert with this id", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+pub async fn ack_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    body: Option<Json<AckRequest>>,
+) -> Result<Json<Alert>, ApiError> {
+    check_auth(&state, &headers)?;
+    let by = body
+        .and_then(|Json(request)| request.by)
+        .unwrap_or_else(|| "api".to_string());
+    state
+        .alerts
+        .acknowledge(&id, by)
+        .map(Json)
+        .ok_or_else(|| ApiError::NotFound("Alert not found or no longer firing".to_string()))
+}
+
+/// GET /silences - silences that have not ended
+#[utoipa::path(
+    get, path = "/silences", tag = "alerts",
+    responses(
+        (status = 200, description = "Current silences", body = Vec<Silence>),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+pub async fn silences_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<Silence>>, ApiError> {
+    check_auth(&state, &headers)?;
+    Ok(Json(state.alerts.silences()))
+}
+
+/// POST /silences - mute matching alerts for a while
+#[utoipa::path(
+    post, path = "/silences", tag = "alerts",
+    request_body = SilenceRequest,
+    responses(
+        (status = 201, description = "Silence created", body = Silence),
+        (status = 400, description = "Invalid matcher or duration", body = ErrorBody),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+pub async fn create_silence_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<SilenceRequest>,
+) -> Result<(StatusCode, Json<Silence>), ApiError> {
+    check_auth(&state, &headers)?;
+    let silence = state
+        .alerts
+        .add_silence(
+            request.matcher,
+            request.duration_minutes,
+            request.created_by,
+            request.comment,
+        )
+        .map_err(ApiError::InvalidRequest)?;
+    Ok((StatusCode::CREATED, Json(silence)))
+}
+
+/// DELETE /silences/{id} - end a silence early
+#[utoipa::path(
+    delete, path = "/silences/{id}", tag = "alerts",
+    params(("id" = String, Path, description = "Silence id")),
+    responses(
+        (status = 204, description = "Silence ended"),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+        (status = 404, description = "No such silence", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+pub async fn delete_silence_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    check_auth(&state, &headers)?;
+    if state.alerts.expire_silence(&id) {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(ApiError::NotFound("Silence not found".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_fire_updates_and_resolves() {
+        let alerts = Alerts::new(&Config::for_tests(""));
+        alerts.fire("slo:a", "burn", Severity::Warning, "slow burn".to_string());
+        alerts.fire("slo:b", "burn", Severity::Critical, "fast burn".to_string());
+        alerts.fire(
+            "slo:a",
+            "burn",
+            Severity::Warning,
+            "still burning".to_string(),
+        );
+
+        let active = alerts.active();
+        assert_eq!(active.len(), 2);
+        assert_eq!(active[0].rule, "slo:b");
+        assert_eq!(active[1].summary, "still burning");
+
+        alerts.resolve("slo:b", "burn");
+        alerts.resolve("slo:c", "burn");
+        assert_eq!(alerts.active().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_ack_and_silence_persist() {
+        let dir = std::env::temp_dir().join(format!("flywatch-alerts-{}", uuid::Uuid::new_v4()));
+        let config = Config::for_tests(&format!("store_path = {:?}", dir.join("store.db")));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let alerts = Alerts::new(&config);
+        alerts.fire("slo:a", "burn", Severity::Critical, "fast burn".to_string());
+        let id = alerts.active()[0].id.clone();
+        assert!(alerts.acknowledge("missing", "bob".to_string()).is_none());
+        let acked = alerts.acknowledge(&id, "alice".to_string()).unwrap();
+        assert_eq!(acked.acknowledged_by.as_deref(), Some("alice"));
+
+        assert!(alerts
+            .add_silence("slo:*".to_string(), 0, None, None)
+            .is_err());
+        let silence = alerts
+            .add_silence("slo:b".to_string(), 60, None, None)
+            .unwrap();
+        alerts.fire("slo:b", "burn", Severity::Warning, "slow burn".to_string());
+        let b = alerts
+            .active()
+            .into_iter()
+            .find(|a| a.rule == "slo:b")
+            .unwrap();
+        assert!(b.silenced && !b.notified);
+
+        // A restart keeps the acknowledgement and the silence
+        let restored = Alerts::new(&config);
+        let a = restored
+            .active()
+            .into_iter()
+            .find(|a| a.rule == "slo:a")
+            .unwrap();
+        assert_eq!(a.acknowledged_by.as_deref(), Some("alice"));
+        assert_eq!(restored.silences()[0].id, silence.id);
+        assert!(restored.expire_silence(&silence.id));
+        assert!(restored.silences().is_empty());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_routes_pick_notifiers_per_rule() {
+        let config = Config::for_tests(
+            r#"
+            alert_webhook_url = "http://hooks.example.com/alerts"
+            discord_webhook_url = "https://discord.example.com/api/webhooks/1"
+            telegram_bot_token = "123:abc"
+            telegram_chat_id = "-100"
+            alert_routes = ["slo:checkout=discord|telegram", "slo:*=webhook"]
+            "#,
+        );
+        let delivery = Delivery::new(&config, None, None, &Default::default());
+        let names = |rule: &str| -> Vec<&'static str> {
+            let alert = Alert::new(rule, "burn", Severity::Warning, String::new());
+            delivery
+                .for_alert(&alert)
+                .iter()
+                .map(|n| n.name())
+                .collect()
+        };
+        assert_eq!(names("slo:checkout"), vec!["discord", "telegram"]);
+        assert_eq!(names("slo:api"), vec!["webhook"]);
+        assert_eq!(names("heartbeat:nats").len(), 3);
+
+        assert!("slo:*=sms".parse::<AlertRoute>().is_err());
+    }
+
+    #[test]
+    fn test_pagerduty_pages_critical_and_always_resolves() {
+        let pagerduty = PagerDutyNotifier::new(
+            "key".to_string(),
+            Severity::Critical,
+            "flywatch/app".to_string(),
+        );
+        let mut alert = Alert::new(
+            "slo:api",
+            "burn_rate",
+            Severity::Warning,
+            "burning".to_string(),
+        );
+        assert!(pagerduty.event(&alert).is_none());
+
+        alert.severity = Severity::Critical;
+        let trigger = pagerduty.event(&alert).unwrap();
+        assert_eq!(trigger["event_action"], "trigger");
+        assert_eq!(trigger["dedup_key"], "slo:api:burn_rate");
+        assert_eq!(trigger["payload"]["severity"], "critical");
+
+        alert.acknowledged_at = Some(Utc::now());
+        assert_eq!(
+            pagerduty.event(&alert).unwrap()["event_action"],
+            "acknowledge"
+        );
+
+        alert.status = AlertStatus::Resolved;
+        let resolve = pagerduty.event(&alert).unwrap();
+        assert_eq!(resolve["event_action"], "resolve");
+        assert_eq!(resolve["dedup_key"], "slo:api:burn_rate");
+    }
+
+    struct FixedEnricher;
+
+    #[async_trait]
+    impl Enricher for FixedEnricher {
+        async fn analyze(&self, alert: &Alert) -> Option<String> {
+            Some(format!("{} is failing after the deploy", alert.rule))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_enrichment_attaches_analysis() {
+        let alerts = Alerts::new(&Config::for_tests(
+            r#"
+            openrouter_api_key = "sk-test"
+            alert_ai_enrichment = true
+            "#,
+        ));
+        alerts.set_enricher(Arc::new(FixedEnricher));
+        alerts.fire(
+            "slo:api",
+            "burn",
+            Severity::Critical,
+            "fast burn".to_string(),
+        );
+
+        let mut analysis = None;
+        for _ in 0..50 {
+            analysis = alerts.active()[0].analysis.clone();
+            if analysis.is_some() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let analysis = analysis.expect("analysis attached");
+        assert_eq!(analysis, "slo:api is failing after the deploy");
+        assert!(alert_text(&alerts.active()[0]).ends_with(&analysis));
+    }
+}
@@ -0,0 +1,175 @@
+//! Structured analysis for `/chat` requests with `"response_format":
+//! "structured"`. The agent is told to answer with JSON findings instead of
+//! prose, and the answer is checked before it is returned: every finding
+//! needs a known severity and a summary, and the log lines it cites as
+//! evidence (by the `#seq` each line is shown with) must be in the buffer.
+//! An answer that fails the check is sent back to the model once with the
+//! problem; a second bad answer fails the request.
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::log_buffer::{LogBuffer, TimestampedLog};
+
+/// Appended to the system prompt in structured mode
+pub const STRUCTURED_INSTRUCTIONS: &str = r#"
+
+## Response Format
+Reply with only a JSON object, no prose and no code fence:
+{"findings": [{"severity": "critical|high|medium|low|info", "summary": "...", "evidence_log_seqs": [1234], "suggested_action": "..."}]}
+- One finding per distinct problem, most severe first; an empty list if nothing is wrong
+- evidence_log_seqs: the #seq numbers of the log lines that show it
+- suggested_action: the next step for whoever acts on it, or null"#;
+
+/// Bad answers sent back to the model before the request fails
+pub const MAX_REPAIRS: usize = 1;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ResponseFormat {
+    /// A prose answer
+    #[default]
+    Text,
+    /// JSON findings in `findings`, validated
+    Structured,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Critical,
+    High,
+    Medium,
+    Low,
+    Info,
+}
+
+/// One problem the agent found
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Finding {
+    pub severity: Severity,
+    pub summary: String,
+    /// Sequence numbers of the log lines that show it
+    #[serde(default)]
+    pub evidence_log_seqs: Vec<u64>,
+    #[serde(default)]
+    pub suggested_action: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Findings {
+    findings: Vec<Finding>,
+}
+
+/// The findings in an answer, tolerating a code fence around the JSON
+fn parse(answer: &str) -> Result<Vec<Finding>, String> {
+    let json = answer.trim();
+    let json = json
+        .strip_prefix("```json")
+        .or_else(|| json.strip_prefix("```"))
+        .and_then(|rest| rest.strip_suffix("```"))
+        .unwrap_or(json);
+    let findings = serde_json::from_str::<Findings>(json.trim())
+        .map_err(|e| format!("not a findings object: {}", e))?
+        .findings;
+    if let Some(i) = findings.iter().position(|f| f.summary.trim().is_empty()) {
+        return Err(format!("finding {} has an empty summary", i + 1));
+    }
+    Ok(findings)
+}
+
+/// The buffered lines among `seqs`, oldest first; evicted ones are skipped
+pub async fn evidence(buffer: &LogBuffer, seqs: &[u64]) -> Vec<TimestampedLog> {
+    let mut seqs = seqs.to_vec();
+    seqs.sort_unstable();
+    seqs.dedup();
+    let mut lines = Vec::new();
+    for seq in seqs {
+        let (next, _) = buffer.get_since(seq.saturating_sub(1), 1).await;
+        lines.extend(next.into_iter().filter(|log| log.seq == seq));
+    }
+    lines
+}
+
+/// Check an answer in structured mode, returning its findings or what is
+/// wrong with it
+pub async fn validate(buffer: &LogBuffer, answer: &str) -> Result<Vec<Finding>, String> {
+    let findings = parse(answer)?;
+    let cited: Vec<u64> = findings
+        .iter()
+        .flat_map(|f| f.evidence_log_seqs.iter().copied())
+        .collect();
+    let found = evidence(buffer, &cited).await;
+    let mut missing: Vec<String> = Vec::new();
+    for seq in cited {
+        let name = format!("#{}", seq);
+        if !found.iter().any(|log| log.seq == seq) && !missing.contains(&name) {
+            missing.push(name);
+        }
+    }
+    if !missing.is_empty() {
+        return Err(format!(
+            "evidence_log_seqs cite lines that aren't in the buffer: {}",
+            missing.join(", ")
+        ));
+    }
+    Ok(findings)
+}
+
+/// What the model is told after a bad answer
+pub fn repair_prompt(problem: &str) -> String {
+    format!(
+        "That answer can't be used: {}. Reply again with only the JSON findings object.",
+        problem
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::log_buffer::LogBufferConfig;
+
+    #[tokio::test]
+    async fn test_validate_checks_shape_and_evidence() {
+        let buffer = LogBuffer::new(
+            LogBufferConfig {
+                max_entries: 10,
+                max_age_minutes: 60,
+            },
+            None,
+        );
+        let first = buffer
+            .push("upstream payments-api returned 502".to_string())
+            .await;
+        let second = buffer
+            .push("upstream payments-api returned 502".to_string())
+            .await;
+
+        let answer = format!(
+            "```json\n{{\"findings\": [{{\"severity\": \"high\", \"summary\": \"Checkout gets 502s from payments-api\", \"evidence_log_seqs\": [{}, {}], \"suggested_action\": \"Check payments-api health\"}}]}}\n```",
+            first.seq, second.seq
+        );
+        let findings = validate(&buffer, &answer).await.unwrap();
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].severity, Severity::High);
+        assert_eq!(findings[0].evidence_log_seqs, vec![first.seq, second.seq]);
+        assert!(validate(&buffer, r#"{"findings": []}"#)
+            .await
+            .unwrap()
+            .is_empty());
+
+        let unknown =
+            r#"{"findings": [{"severity": "high", "summary": "x", "evidence_log_seqs": [999]}]}"#;
+        assert!(validate(&buffer, unknown)
+            .await
+            .unwrap_err()
+            .contains("#999"));
+        let severity = r#"{"findings": [{"severity": "urgent", "summary": "x"}]}"#;
+        assert!(validate(&buffer, severity).await.is_err());
+        let empty = r#"{"findings": [{"severity": "low", "summary": " "}]}"#;
+        assert!(validate(&buffer, empty).await.is_err());
+        assert!(validate(&buffer, "Checkouts fail because of payments-api.")
+            .await
+            .is_err());
+    }
+}
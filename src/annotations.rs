@@ -0,0 +1,482 @@
+//! Human markers on the log timeline ("deploy v1.42 started here"). An
+//! annotation is pinned to a moment or time range, or to a range of
+//! sequence numbers, and is returned alongside the lines it overlaps in
+//! `/logs/history` and `/logs/since` and shown to the chat agent. A note
+//! may name the Fly app it is about; tenants only see notes about their own
+//! apps. Persisted when a store is configured.
+
+use axum::{
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, RwLock};
+use stoar::Store;
+use tracing::error;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::error::{ApiError, ErrorBody};
+use crate::http::{check_auth, parse_timestamp, AppState};
+use crate::log_buffer::TimestampedLog;
+
+const ANNOTATION_COLLECTION: &str = "annotations";
+const MAX_TEXT_LEN: usize = 4096;
+/// Annotations shown to the chat agent at most
+const MAX_CONTEXT_ANNOTATIONS: usize = 20;
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Annotation {
+    pub id: String,
+    pub author: String,
+    pub text: String,
+    pub tags: Vec<String>,
+    /// Moment (or start of the range) the note refers to
+    pub at: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    /// First sequence the note refers to, for notes pinned to lines
+    pub from_seq: Option<u64>,
+    pub to_seq: Option<u64>,
+    /// Fly app the note is about; notes without one are fleet-wide
+    #[serde(default)]
+    pub app: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Annotation {
+    /// Whether the note refers to any of `seqs` or to a time in `times`
+    fn overlaps(&self, seqs: (u64, u64), times: (DateTime<Utc>, DateTime<Utc>)) -> bool {
+        match (self.from_seq, self.at) {
+            (Some(from), _) => from <= seqs.1 && self.to_seq.unwrap_or(from) >= seqs.0,
+            (None, Some(at)) => at <= times.1 && self.until.unwrap_or(at) >= times.0,
+            (None, None) => false,
+        }
+    }
+
+    /// Whether the note is about one of `apps`, or anything when unscoped
+    fn in_scope(&self, apps: Option<&[String]>) -> bool {
+        apps.is_none_or(|apps| self.app.as_ref().is_some_and(|app| apps.contains(app)))
+    }
+
+    /// Sort key: its moment, or when it was written for line ranges
+    fn time(&self) -> DateTime<Utc> {
+        self.at.unwrap_or(self.created_at)
+    }
+
+    /// One line for the chat agent
+    fn compact(&self) -> String {
+        let anchor = match (self.from_seq, self.at) {
+            (Some(from), _) => match self.to_seq.filter(|to| *to != from) {
+                Some(to) => format!("seq {}-{}", from, to),
+                None => format!("seq {}", from),
+            },
+            (None, Some(at)) => match self.until {
+                Some(until) => format!("{} - {}", at.format("%H:%M:%S"), until.format("%H:%M:%S")),
+                None => at.format("%H:%M:%S").to_string(),
+            },
+            (None, None) => String::new(),
+        };
+        let tags = if self.tags.is_empty() {
+            String::new()
+        } else {
+            format!(" [{}]", self.tags.join(", "))
+        };
+        format!("[{}] {}: {}{}", anchor, self.author, self.text, tags)
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct AnnotationRequest {
+    pub author: String,
+    pub text: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Moment the note refers to; now when neither `at` nor `from_seq` is set
+    pub at: Option<DateTime<Utc>>,
+    /// End of a time range starting at `at`
+    pub until: Option<DateTime<Utc>>,
+    /// Pin the note to lines instead of a time
+    pub from_seq: Option<u64>,
+    pub to_seq: Option<u64>,
+    /// Fly app the note is about; tenants only see notes about their apps
+    pub app: Option<String>,
+}
+
+impl AnnotationRequest {
+    fn into_annotation(self, id: String, now: DateTime<Utc>) -> Result<Annotation, String> {
+        let author = self.author.trim().to_string();
+        let text = self.text.trim().to_string();
+        if author.is_empty() || text.is_empty() {
+            return Err("author and text are required".to_string());
+        }
+        if text.len() > MAX_TEXT_LEN {
+            return Err(format!("text is longer than {} bytes", MAX_TEXT_LEN));
+        }
+        if self.from_seq.is_some() && (self.at.is_some() || self.until.is_some()) {
+            return Err(
+                "pin a note to a time (at/until) or to lines (from_seq/to_seq), not both"
+                    .to_string(),
+            );
+        }
+        if self.to_seq.is_some() && self.from_seq.is_none() {
+            return Err("to_seq needs from_seq".to_string());
+        }
+        if self
+            .from_seq
+            .zip(self.to_seq)
+            .is_some_and(|(from, to)| to < from)
+        {
+            return Err("to_seq must not be before from_seq".to_string());
+        }
+        let at = match (self.at, self.from_seq) {
+            (None, None) => Some(now),
+            (at, _) => at,
+        };
+        if self
+            .until
+            .is_some_and(|until| at.is_none_or(|at| until < at))
+        {
+            return Err("until must not be before at".to_string());
+        }
+        Ok(Annotation {
+            id,
+            author,
+            text,
+            tags: self
+                .tags
+                .into_iter()
+                .filter(|t| !t.trim().is_empty())
+                .collect(),
+            at,
+            until: self.until,
+            from_seq: self.from_seq,
+            to_seq: self.to_seq,
+            app: self.app.filter(|app| !app.trim().is_empty()),
+            created_at: now,
+        })
+    }
+}
+
+/// Notes on the log timeline
+pub struct Annotations {
+    annotations: RwLock<Vec<Annotation>>,
+    store: Option<Store>,
+}
+
+impl Annotations {
+    pub fn new(store_path: Option<&str>) -> Arc<Self> {
+        let store = store_path.and_then(|path| match Store::open(path) {
+            Ok(s) => Some(s),
+            Err(e) => {
+                error!(error = %e, path = %path, "Failed to open annotation store, keeping annotations in memory");
+                None
+            }
+        });
+        let mut annotations: Vec<Annotation> = store
+            .as_ref()
+            .map(|store| {
+                store.all(ANNOTATION_COLLECTION).unwrap_or_else(|e| {
+                    error!(error = %e, "Failed to load annotations");
+                    Vec::new()
+                })
+            })
+            .unwrap_or_default();
+        annotations.sort_by_key(Annotation::time);
+        Arc::new(Self {
+            annotations: RwLock::new(annotations),
+            store,
+        })
+    }
+
+    pub fn create(&self, request: AnnotationRequest) -> Result<Annotation, String> {
+        let annotation = request.into_annotation(uuid::Uuid::new_v4().to_string(), Utc::now())?;
+        if let Some(ref store) = self.store {
+            if let Err(e) = store.put(ANNOTATION_COLLECTION, &annotation.id, &annotation) {
+                error!(error = %e, "Failed to persist annotation");
+            }
+        }
+        let mut annotations = self.annotations.write().unwrap();
+        annotations.push(annotation.clone());
+        annotations.sort_by_key(Annotation::time);
+        Ok(annotation)
+    }
+
+    pub fn delete(&self, id: &str) -> bool {
+        let mut annotations = self.annotations.write().unwrap();
+        let before = annotations.len();
+        annotations.retain(|a| a.id != id);
+        if let Some(ref store) = self.store {
+            let _ = store.delete(ANNOTATION_COLLECTION, id);
+        }
+        annotations.len() != before
+    }
+
+    /// Notes in `from..=to` (by their moment), optionally with a tag, about
+    /// `apps` when given
+    pub fn list(
+        &self,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+        tag: Option<&str>,
+        apps: Option<&[String]>,
+    ) -> Vec<Annotation> {
+        self.annotations
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|a| a.in_scope(apps))
+            .filter(|a| from.is_none_or(|from| a.until.unwrap_or(a.time()) >= from))
+            .filter(|a| to.is_none_or(|to| a.time() <= to))
+            .filter(|a| tag.is_none_or(|tag| a.tags.iter().any(|t| t == tag)))
+            .cloned()
+            .collect()
+    }
+
+    /// Notes that refer to any of `logs`, by sequence or time, about `apps`
+    /// when given
+    pub fn overlapping(&self, logs: &[TimestampedLog], apps: Option<&[String]>) -> Vec<Annotation> {
+        let Some(seqs) = logs
+            .iter()
+            .map(|l| l.seq)
+            .fold(None, |range: Option<(u64, u64)>, seq| {
+                Some(range.map_or((seq, seq), |(lo, hi)| (lo.min(seq), hi.max(seq))))
+            })
+        else {
+            return Vec::new();
+        };
+        let times = logs.iter().fold(
+            (DateTime::<Utc>::MAX_UTC, DateTime::<Utc>::MIN_UTC),
+            |(lo, hi), l| (lo.min(l.timestamp), hi.max(l.timestamp)),
+        );
+        self.annotations
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|a| a.in_scope(apps) && a.overlaps(seqs, times))
+            .cloned()
+            .collect()
+    }
+}
+
+/// Notes for the chat agent, newest last
+pub fn format_annotations(annotations: &[Annotation]) -> String {
+    let skip = annotations.len().saturating_sub(MAX_CONTEXT_ANNOTATIONS);
+    annotations[skip..]
+        .iter()
+        .map(Annotation::compact)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct AnnotationQuery {
+    /// RFC3339 start
+    pub from: Option<String>,
+    /// RFC3339 end
+    pub to: Option<String>,
+    /// Only notes with this tag
+    pub tag: Option<String>,
+    /// Only notes about this app
+    pub app: Option<String>,
+}
+
+/// GET /annotations - notes on the timeline, oldest first
+#[utoipa::path(
+    get, path = "/annotations", tag = "logs",
+    params(AnnotationQuery),
+    responses(
+        (status = 200, description = "Annotations, oldest first", body = Vec<Annotation>),
+        (status = 400, description = "Invalid parameters", body = ErrorBody),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+pub async fn list_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<AnnotationQuery>,
+) -> Result<Json<Vec<Annotation>>, ApiError> {
+    check_auth(&state, &headers)?;
+    let from = query
+        .from
+        .map(|ts| parse_timestamp("from", &ts))
+        .transpose()?;
+    let to = query.to.map(|ts| parse_timestamp("to", &ts)).transpose()?;
+    let apps = query.app.map(|app| vec![app]);
+    Ok(Json(state.annotations.list(
+        from,
+        to,
+        query.tag.as_deref(),
+        apps.as_deref(),
+    )))
+}
+
+/// POST /annotations - attach a note to a time or to lines
+#[utoipa::path(
+    post, path = "/annotations", tag = "logs",
+    request_body = AnnotationRequest,
+    responses(
+        (status = 201, description = "Annotation added", body = Annotation),
+        (status = 400, description = "Invalid annotation", body = ErrorBody),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+pub async fn create_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<AnnotationRequest>,
+) -> Result<(StatusCode, Json<Annotation>), ApiError> {
+    check_auth(&state, &headers)?;
+    let annotation = state
+        .annotations
+        .create(request)
+        .map_err(ApiError::InvalidRequest)?;
+    Ok((StatusCode::CREATED, Json(annotation)))
+}
+
+/// DELETE /annotations/{id} - remove a note
+#[utoipa::path(
+    delete, path = "/annotations/{id}", tag = "logs",
+    params(("id" = String, Path, description = "Annotation id")),
+    responses(
+        (status = 204, description = "Annotation removed"),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+        (status = 404, description = "No such annotation", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+pub async fn delete_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    check_auth(&state, &headers)?;
+    if state.annotations.delete(&id) {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(ApiError::NotFound("Annotation not found".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn request(text: &str) -> AnnotationRequest {
+        AnnotationRequest {
+            author: "alice".to_string(),
+            text: text.to_string(),
+            tags: vec!["deploy".to_string()],
+            at: None,
+            until: None,
+            from_seq: None,
+            to_seq: None,
+            app: None,
+        }
+    }
+
+    fn log_at(timestamp: DateTime<Utc>, seq: u64) -> TimestampedLog {
+        let mut log = TimestampedLog::new("{}".to_string(), seq);
+        log.timestamp = timestamp;
+        log
+    }
+
+    #[test]
+    fn test_annotations_overlap_logs() {
+        let annotations = Annotations::new(None);
+        let now = Utc::now();
+        let deploy = annotations
+            .create(request("deploy v1.42 started here"))
+            .unwrap();
+        assert!(deploy.at.is_some());
+        annotations
+            .create(AnnotationRequest {
+                from_seq: Some(10),
+                to_seq: Some(12),
+                ..request("retry storm")
+            })
+            .unwrap();
+        annotations
+            .create(AnnotationRequest {
+                at: Some(now - Duration::hours(2)),
+                ..request("old incident")
+            })
+            .unwrap();
+        assert!(annotations
+            .create(AnnotationRequest {
+                at: Some(now),
+                from_seq: Some(1),
+                ..request("both")
+            })
+            .is_err());
+
+        let page = [
+            log_at(now - Duration::minutes(1), 11),
+            log_at(now + Duration::minutes(1), 13),
+        ];
+        let found: Vec<String> = annotations
+            .overlapping(&page, None)
+            .into_iter()
+            .map(|a| a.text)
+            .collect();
+        assert_eq!(found.len(), 2);
+        assert!(found.contains(&"deploy v1.42 started here".to_string()));
+        assert!(found.contains(&"retry storm".to_string()));
+        assert!(annotations.overlapping(&[], None).is_empty());
+
+        assert_eq!(
+            annotations
+                .list(
+                    Some(now - Duration::hours(3)),
+                    Some(now - Duration::hours(1)),
+                    None,
+                    None
+                )
+                .len(),
+            1
+        );
+        assert!(
+            format_annotations(&annotations.list(None, None, Some("deploy"), None))
+                .contains("[seq 10-12] alice: retry storm [deploy]")
+        );
+        assert!(annotations.delete(&deploy.id));
+    }
+
+    #[test]
+    fn test_app_scope_hides_other_apps_notes() {
+        let annotations = Annotations::new(None);
+        let notes = [
+            ("acme", Some("acme-web")),
+            ("globex", Some("globex")),
+            ("all", None),
+        ];
+        for (text, app) in notes {
+            annotations
+                .create(AnnotationRequest {
+                    app: app.map(str::to_string),
+                    ..request(text)
+                })
+                .unwrap();
+        }
+        let texts =
+            |notes: Vec<Annotation>| -> Vec<String> { notes.into_iter().map(|a| a.text).collect() };
+
+        let acme = ["acme-web".to_string()];
+        assert_eq!(
+            texts(annotations.list(None, None, None, Some(&acme))),
+            vec!["acme"]
+        );
+        assert_eq!(annotations.list(None, None, None, None).len(), 3);
+        let page = [log_at(
+            annotations.list(None, None, None, None)[0].at.unwrap(),
+            1,
+        )];
+        assert_eq!(
+            texts(annotations.overlapping(&page, Some(&acme))),
+            vec!["acme"]
+        );
+    }
+}
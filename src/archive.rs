@@ -0,0 +1,377 @@
+//! Long-term log archive: a sink that writes every buffered line to a SQLite
+//! database at ARCHIVE_PATH, in batches and in WAL mode, and keeps them for
+//! ARCHIVE_RETENTION_DAYS. `POST /logs/sql` runs read-only queries against it
+//! for investigations that reach past the in-memory buffer.
+
+use async_trait::async_trait;
+use axum::{extract::State, http::HeaderMap, Json};
+use chrono::{SecondsFormat, Utc};
+use rusqlite::types::ValueRef;
+use rusqlite::{params, Connection, OpenFlags};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::info;
+use utoipa::ToSchema;
+
+use crate::config::Config;
+use crate::error::{ApiError, ErrorBody};
+use crate::http::{check_auth, AppState};
+use crate::nats::LogMessage;
+use crate::sink::{Rejection, Sink, SinkDefaults, SinkError};
+
+const PRUNE_INTERVAL: Duration = Duration::from_secs(3600);
+/// Queries are interrupted after this long
+const QUERY_TIMEOUT: Duration = Duration::from_secs(5);
+const DEFAULT_ROW_LIMIT: usize = 1000;
+const MAX_ROW_LIMIT: usize = 10_000;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS logs (
+        seq INTEGER NOT NULL,
+        timestamp TEXT NOT NULL,
+        received_at TEXT NOT NULL,
+        app TEXT,
+        instance TEXT,
+        region TEXT,
+        level TEXT,
+        source TEXT,
+        message TEXT,
+        raw TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS logs_timestamp ON logs (timestamp);
+    CREATE INDEX IF NOT EXISTS logs_app_level ON logs (app, level);
+";
+
+/// Fixed-width UTC timestamps, so text comparison orders them
+fn sql_time(time: chrono::DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Micros, true)
+}
+
+pub struct Archive {
+    path: String,
+    retention_days: u64,
+    conn: Arc<Mutex<Connection>>,
+    last_prune: Mutex<Option<Instant>>,
+}
+
+impl Archive {
+    /// Open the database; None without ARCHIVE_PATH
+    pub fn open(config: &Config) -> Result<Option<Arc<Self>>, String> {
+        let Some(path) = config.archive_path.clone() else {
+            return Ok(None);
+        };
+        let conn = Connection::open(&path).map_err(|e| format!("{}: {}", path, e))?;
+        conn.execute_batch("PRAGMA journal_mode = WAL; PRAGMA synchronous = NORMAL;")
+            .and_then(|_| conn.execute_batch(SCHEMA))
+            .map_err(|e| format!("{}: {}", path, e))?;
+
+        let archive = Arc::new(Self {
+            path,
+            retention_days: config.archive_retention_days,
+            conn: Arc::new(Mutex::new(conn)),
+            last_prune: Mutex::new(None),
+        });
+        info!(
+            path = %archive.path,
+            retention_days = archive.retention_days,
+            "Log archive enabled"
+        );
+        Ok(Some(archive))
+    }
+
+    /// Run one read-only statement, returning at most `limit` rows
+    pub async fn query(&self, sql: String, limit: usize) -> Result<SqlResponse, ApiError> {
+        let conn = Connection::open_with_flags(
+            &self.path,
+            OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )
+        .map_err(|e| ApiError::Internal(format!("Failed to open the archive: {}", e)))?;
+        let interrupt = conn.get_interrupt_handle();
+
+        let mut query = tokio::task::spawn_blocking(move || run_query(&conn, &sql, limit));
+        let result = match tokio::time::timeout(QUERY_TIMEOUT, &mut query).await {
+            Ok(result) => result,
+            Err(_) => {
+                interrupt.interrupt();
+                query.await
+            }
+        };
+        result.map_err(|e| ApiError::Internal(format!("Query task failed: {}", e)))?
+    }
+}
+
+#[async_trait]
+impl Sink for Archive {
+    fn kind(&self) -> &'static str {
+        "archive"
+    }
+
+    fn defaults(&self) -> SinkDefaults {
+        SinkDefaults {
+            queue_capacity: 10_000,
+            batch_size: 500,
+            flush_interval: Duration::from_secs(1),
+            max_attempts: 3,
+        }
+    }
+
+    /// Write a batch in one transaction, pruning past the retention window hourly
+    async fn send(&self, batch: &[LogMessage]) -> Result<Vec<Rejection>, SinkError> {
+        let prune = {
+            let mut last_prune = self.last_prune.lock().unwrap();
+            let due = last_prune.is_none_or(|at| at.elapsed() >= PRUNE_INTERVAL);
+            if due {
+                *last_prune = Some(Instant::now());
+            }
+            due
+        };
+        let conn = self.conn.clone();
+        let retention_days = self.retention_days;
+        let batch = batch.to_vec();
+        tokio::task::spawn_blocking(move || {
+            let mut conn = conn.lock().unwrap();
+            insert(&mut conn, &batch)?;
+            if prune {
+                let cutoff = Utc::now() - chrono::Duration::days(retention_days as i64);
+                let pruned =
+                    conn.execute("DELETE FROM logs WHERE timestamp < ?1", [sql_time(cutoff)])?;
+                if pruned > 0 {
+                    info!(pruned, "Pruned archived logs past retention");
+                }
+            }
+            Ok::<_, rusqlite::Error>(())
+        })
+        .await
+        .map_err(|e| SinkError::Unavailable(format!("writer panicked: {}", e)))?
+        .map_err(|e| SinkError::Unavailable(format!("{}: {}", self.path, e)))?;
+        Ok(Vec::new())
+    }
+}
+
+fn insert(conn: &mut Connection, batch: &[LogMessage]) -> rusqlite::Result<()> {
+    let tx = conn.transaction()?;
+    {
+        let mut stmt = tx.prepare_cached(
+            "INSERT INTO logs (seq, timestamp, received_at, app, instance, region, level, source, message, raw)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+        )?;
+        for log in batch {
+            stmt.execute(params![
+                log.seq as i64,
+                sql_time(log.timestamp),
+                sql_time(log.received_at),
+                log.app,
+                log.instance,
+                log.region,
+                log.level,
+                log.source,
+                log.message,
+                log.raw,
+            ])?;
+        }
+    }
+    tx.commit()
+}
+
+/// Whether `sql` has a `;` outside string literals, identifiers and comments
+fn has_statement_separator(sql: &str) -> bool {
+    let mut chars = sql.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            ';' => return true,
+            '\'' | '"' | '`' | '[' => {
+                let close = if c == '[' { ']' } else { c };
+                chars.find(|&next| next == close);
+            }
+            '-' if chars.peek() == Some(&'-') => {
+                chars.find(|&next| next == '\n');
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                while let Some(next) = chars.next() {
+                    if next == '*' && chars.peek() == Some(&'/') {
+                        chars.next();
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    false
+}
+
+/// Only a single SELECT (or WITH ... SELECT) gets through
+fn check_statement(sql: &str) -> Result<&str, ApiError> {
+    let sql = sql.trim().trim_end_matches(';').trim_end();
+    let keyword = sql
+        .split(|c: char| !c.is_ascii_alphabetic())
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    if keyword != "select" && keyword != "with" {
+        return Err(ApiError::InvalidRequest(
+            "Only SELECT statements are allowed".to_string(),
+        ));
+    }
+    if has_statement_separator(sql) {
+        return Err(ApiError::InvalidRequest(
+            "Send a single statement".to_string(),
+        ));
+    }
+    Ok(sql)
+}
+
+fn run_query(conn: &Connection, sql: &str, limit: usize) -> Result<SqlResponse, ApiError> {
+    let sql = check_statement(sql)?;
+    let invalid = |e: rusqlite::Error| match e {
+        rusqlite::Error::SqliteFailure(err, _)
+            if err.code == rusqlite::ErrorCode::OperationInterrupted =>
+        {
+            ApiError::InvalidRequest(format!(
+                "Query ran longer than {}s",
+                QUERY_TIMEOUT.as_secs()
+            ))
+        }
+        other => ApiError::InvalidRequest(format!("Invalid query: {}", other)),
+    };
+
+    let mut stmt = conn.prepare(sql).map_err(invalid)?;
+    if !stmt.readonly() {
+        return Err(ApiError::InvalidRequest(
+            "Only read-only statements are allowed".to_string(),
+        ));
+    }
+
+    let columns: Vec<String> = stmt.column_names().iter().map(|c| c.to_string()).collect();
+    let mut rows = stmt.query([]).map_err(invalid)?;
+    let mut out = Vec::new();
+    let mut truncated = false;
+    while let Some(row) = rows.next().map_err(invalid)? {
+        if out.len() == limit {
+            truncated = true;
+            break;
+        }
+        let values = (0..columns.len())
+            .map(|i| match row.get_ref(i) {
+                Ok(ValueRef::Null) | Err(_) => serde_json::Value::Null,
+                Ok(ValueRef::Integer(n)) => n.into(),
+                Ok(ValueRef::Real(f)) => f.into(),
+                Ok(ValueRef::Text(t)) => String::from_utf8_lossy(t).into(),
+                Ok(ValueRef::Blob(b)) => format!("<{} bytes>", b.len()).into(),
+            })
+            .collect();
+        out.push(values);
+    }
+    Ok(SqlResponse {
+        columns,
+        rows: out,
+        truncated,
+    })
+}
+
+// ==================== Handler ====================
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SqlRequest {
+    /// One SELECT against the `logs` table
+    pub sql: String,
+    /// Rows returned (default 1000, max 10000)
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SqlResponse {
+    pub columns: Vec<String>,
+    /// One array of values per row, in column order
+    #[schema(value_type = Vec<Vec<Object>>)]
+    pub rows: Vec<Vec<serde_json::Value>>,
+    /// True when more rows matched than `limit`
+    pub truncated: bool,
+}
+
+/// POST /logs/sql - query the long-term archive
+#[utoipa::path(
+    post, path = "/logs/sql", tag = "logs",
+    request_body = SqlRequest,
+    responses(
+        (status = 200, description = "Matching rows", body = SqlResponse),
+        (status = 400, description = "Not a single SELECT, invalid SQL or too slow", body = ErrorBody),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+        (status = 503, description = "ARCHIVE_PATH is not set", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+pub async fn sql_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<SqlRequest>,
+) -> Result<Json<SqlResponse>, ApiError> {
+    check_auth(&state, &headers)?;
+    let archive = state
+        .archive
+        .as_ref()
+        .ok_or_else(|| ApiError::NotConfigured("ARCHIVE_PATH is not set".to_string()))?;
+    let limit = request
+        .limit
+        .unwrap_or(DEFAULT_ROW_LIMIT)
+        .clamp(1, MAX_ROW_LIMIT);
+    Ok(Json(archive.query(request.sql, limit).await?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::log_buffer::TimestampedLog;
+
+    #[test]
+    fn test_archive_queries_are_read_only() {
+        let dir = std::env::temp_dir().join(format!("flywatch-archive-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("archive.db");
+        let mut conn = Connection::open(&path).unwrap();
+        conn.execute_batch(SCHEMA).unwrap();
+        let logs: Vec<LogMessage> = [
+            r#"{"message":"boom","log":{"level":"error"},"fly":{"app":{"name":"web"}}}"#,
+            r#"{"message":"ok","log":{"level":"info"},"fly":{"app":{"name":"web"}}}"#,
+            r#"{"message":"late","log":{"level":"error"},"fly":{"app":{"name":"api"}}}"#,
+        ]
+        .iter()
+        .enumerate()
+        .map(|(i, raw)| Arc::new(TimestampedLog::new(raw.to_string(), i as u64)))
+        .collect();
+        insert(&mut conn, &logs).unwrap();
+
+        let reader = Connection::open_with_flags(&path, OpenFlags::SQLITE_OPEN_READ_ONLY).unwrap();
+        let result = run_query(
+            &reader,
+            "SELECT app, count(*) AS errors FROM logs WHERE level = 'error' GROUP BY app ORDER BY app;",
+            10,
+        )
+        .unwrap();
+        assert_eq!(result.columns, vec!["app", "errors"]);
+        assert_eq!(
+            result.rows,
+            vec![
+                vec![serde_json::json!("api"), serde_json::json!(1)],
+                vec![serde_json::json!("web"), serde_json::json!(1)],
+            ]
+        );
+
+        let limited = run_query(&reader, "select message from logs -- ; ok\n;", 2).unwrap();
+        assert_eq!(limited.rows.len(), 2);
+        assert!(limited.truncated);
+
+        for sql in [
+            "DELETE FROM logs",
+            "SELECT 1; DELETE FROM logs",
+            "SELECT ';' AS semi; DROP TABLE logs",
+            "ATTACH DATABASE '/tmp/x.db' AS x",
+            "PRAGMA journal_mode",
+        ] {
+            assert!(run_query(&reader, sql, 10).is_err(), "{}", sql);
+        }
+        std::fs::remove_dir_all(dir).ok();
+    }
+}
@@ -0,0 +1,233 @@
+use axum::{
+    extract::{Query, State},
+    http::HeaderMap,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use stoar::Store;
+use tracing::{error, info};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::error::{ApiError, ErrorBody};
+use crate::http::{check_auth, AppState};
+use crate::text::truncate_chars;
+
+const AUDIT_COLLECTION: &str = "tool_audit";
+/// Longest tool result kept in a record
+const MAX_RESULT_CHARS: usize = 2000;
+/// Records kept in memory when there is no store
+const MEMORY_CAPACITY: usize = 1000;
+
+/// One tool call executed by the chat agent
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ToolAuditRecord {
+    pub id: String,
+    pub timestamp: DateTime<Utc>,
+    /// Client-supplied conversation id, or the request id
+    pub conversation_id: String,
+    pub request_id: Option<String>,
+    /// Client-certificate CN of the caller, under mTLS
+    #[serde(default)]
+    pub principal: Option<String>,
+    pub tool: String,
+    /// Raw JSON arguments the model passed
+    pub arguments: String,
+    /// Tool output, truncated to 2000 characters
+    pub result: String,
+    /// Length of the untruncated result in characters
+    pub result_chars: usize,
+    /// False when the tool returned an error
+    pub ok: bool,
+    pub duration_ms: u64,
+}
+
+/// A finished tool call, as handed to `ToolAudit::record`
+pub struct ToolCallEvent<'a> {
+    pub conversation_id: &'a str,
+    pub request_id: Option<&'a str>,
+    pub principal: Option<&'a str>,
+    pub tool: &'a str,
+    pub arguments: &'a str,
+    pub result: &'a Result<String, String>,
+    pub duration_ms: u64,
+}
+
+/// Audit trail of every tool call, persisted to the store when one is
+/// configured and otherwise kept in a bounded in-memory ring
+pub struct ToolAudit {
+    store: Option<Store>,
+    memory: Mutex<VecDeque<ToolAuditRecord>>,
+}
+
+impl ToolAudit {
+    pub fn new(store_path: Option<&str>) -> Self {
+        let store = store_path.and_then(|path| match Store::open(path) {
+            Ok(s) => {
+                info!(path = %path, "Tool audit persistence enabled");
+                Some(s)
+            }
+            Err(e) => {
+                error!(error = %e, path = %path, "Failed to open audit store, keeping audit in memory");
+                None
+            }
+        });
+
+        Self {
+            store,
+            memory: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    pub fn record(&self, event: ToolCallEvent<'_>) {
+        let (ok, output) = match event.result {
+            Ok(output) => (true, output),
+            Err(e) => (false, e),
+        };
+        let record = ToolAuditRecord {
+            id: uuid::Uuid::new_v4().to_string(),
+            timestamp: Utc::now(),
+            conversation_id: event.conversation_id.to_string(),
+            request_id: event.request_id.map(str::to_string),
+            principal: event.principal.map(str::to_string),
+            tool: event.tool.to_string(),
+            arguments: event.arguments.to_string(),
+            result: truncate_chars(output, MAX_RESULT_CHARS),
+            result_chars: output.chars().count(),
+            ok,
+            duration_ms: event.duration_ms,
+        };
+
+        match &self.store {
+            Some(store) => {
+                if let Err(e) = store.put(AUDIT_COLLECTION, &record.id, &record) {
+                    error!(error = %e, "Failed to persist tool audit record");
+                }
+            }
+            None => {
+                let mut memory = self.memory.lock().unwrap();
+                if memory.len() >= MEMORY_CAPACITY {
+                    memory.pop_front();
+                }
+                memory.push_back(record);
+            }
+        }
+    }
+
+    /// Matching records, newest first
+    pub fn query(&self, query: &AuditQuery) -> Vec<ToolAuditRecord> {
+        let mut records: Vec<ToolAuditRecord> = match &self.store {
+            Some(store) => match store.all(AUDIT_COLLECTION) {
+                Ok(records) => records,
+                Err(e) => {
+                    error!(error = %e, "Failed to fetch tool audit records");
+                    Vec::new()
+                }
+            },
+            None => self.memory.lock().unwrap().iter().cloned().collect(),
+        };
+
+        records.retain(|r| {
+            query
+                .conversation_id
+                .as_ref()
+                .is_none_or(|id| &r.conversation_id == id)
+                && query.tool.as_ref().is_none_or(|tool| &r.tool == tool)
+        });
+        records.sort_by_key(|r| std::cmp::Reverse(r.timestamp));
+        records.truncate(query.limit.unwrap_or(100).min(1000));
+        records
+    }
+}
+
+#[derive(Debug, Default, Deserialize, IntoParams)]
+pub struct AuditQuery {
+    /// Only calls made in this conversation
+    pub conversation_id: Option<String>,
+    /// Only calls to this tool, e.g. get_logs
+    pub tool: Option<String>,
+    /// Max records (default 100, max 1000)
+    pub limit: Option<usize>,
+}
+
+/// GET /chat/audit - tool calls made by the chat agent, newest first
+#[utoipa::path(
+    get, path = "/chat/audit", tag = "chat",
+    params(AuditQuery),
+    responses(
+        (status = 200, description = "Tool calls, newest first", body = Vec<ToolAuditRecord>),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+pub async fn audit_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<AuditQuery>,
+) -> Result<Json<Vec<ToolAuditRecord>>, ApiError> {
+    check_auth(&state, &headers)?;
+    Ok(Json(state.tool_audit.query(&query)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(
+        audit: &ToolAudit,
+        conversation_id: &str,
+        tool: &str,
+        result: Result<String, String>,
+    ) {
+        audit.record(ToolCallEvent {
+            conversation_id,
+            request_id: None,
+            principal: None,
+            tool,
+            arguments: "{}",
+            result: &result,
+            duration_ms: 3,
+        });
+    }
+
+    #[test]
+    fn test_query_filters_and_truncates() {
+        let audit = ToolAudit::new(None);
+        record(
+            &audit,
+            "c1",
+            "get_logs",
+            Ok("x".repeat(MAX_RESULT_CHARS + 10)),
+        );
+        record(
+            &audit,
+            "c1",
+            "get_metrics",
+            Err("Invalid arguments".to_string()),
+        );
+        record(&audit, "c2", "get_logs", Ok("ok".to_string()));
+
+        let c1 = audit.query(&AuditQuery {
+            conversation_id: Some("c1".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(c1.len(), 2);
+
+        let logs = audit.query(&AuditQuery {
+            conversation_id: Some("c1".to_string()),
+            tool: Some("get_logs".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(logs[0].result_chars, MAX_RESULT_CHARS + 10);
+        assert!(logs[0].result.ends_with("..."));
+
+        let failed = audit.query(&AuditQuery {
+            tool: Some("get_metrics".to_string()),
+            ..Default::default()
+        });
+        assert!(!failed[0].ok);
+        assert_eq!(failed[0].result, "Invalid arguments");
+    }
+}
@@ -0,0 +1,178 @@
+//! Named stream channels. Each rule in `CHANNELS` names a channel and the
+//! lines routed into it:
+//!
+//! ```text
+//! errors level=error|fatal
+//! payments app=payments|billing
+//! checkout-slow app=checkout match=took \d{4,}ms
+//! ```
+//!
+//! The fanout dispatcher evaluates every rule once per line and only queues
+//! the line for connections subscribed to a channel it matched, so clients
+//! streaming with `?channel=errors` don't each filter the whole firehose.
+//! `all` is every line and is what connections without a channel get.
+//! Conditions are `app=`, `level=`, `region=`, `instance=` and
+//! `field.<key>=` on a structured field (alternatives separated by `|`), and
+//! `match=`, a regex over the raw line that runs to the end of the rule.
+
+use axum::{extract::State, http::HeaderMap, Json};
+use serde::Serialize;
+use std::fmt;
+use std::str::FromStr;
+use utoipa::ToSchema;
+
+use crate::error::{ApiError, ErrorBody};
+use crate::filter::{self, Filter};
+use crate::http::{check_auth, AppState};
+use crate::ingest_filter::{alternatives, normalize_level};
+
+/// The implicit channel carrying every line
+pub const ALL: &str = "all";
+
+/// One channel, as written in `CHANNELS`
+#[derive(Debug, Clone)]
+pub struct ChannelRule {
+    text: String,
+    pub name: String,
+    pub filter: Filter,
+}
+
+impl PartialEq for ChannelRule {
+    fn eq(&self, other: &Self) -> bool {
+        self.text == other.text
+    }
+}
+
+impl fmt::Display for ChannelRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 64
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+}
+
+impl FromStr for ChannelRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let text = s.trim();
+        let (name, mut rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+        if !valid_name(name) {
+            return Err(format!(
+                "a channel starts with its name (lowercase letters, digits, '-' or '_'), found '{}'",
+                name
+            ));
+        }
+        if name == ALL {
+            return Err(format!("'{}' is reserved for every line", ALL));
+        }
+
+        let mut filter = Filter::default();
+        rest = rest.trim_start();
+        while !rest.is_empty() {
+            let (token, tail) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+            let (key, value) = token
+                .split_once('=')
+                .ok_or_else(|| format!("expected key=value, found '{}'", token))?;
+            match key {
+                "match" => {
+                    let pattern = rest["match=".len()..].trim_end();
+                    filter.pattern =
+                        Some(filter::pattern(pattern).map_err(|e| format!("match: {}", e))?);
+                    break;
+                }
+                "app" => filter.apps = alternatives(value, str::to_lowercase),
+                "level" => filter.levels = alternatives(value, normalize_level),
+                "region" => filter.regions = alternatives(value, str::to_lowercase),
+                "instance" => filter.instances = alternatives(value, str::to_string),
+                key if filter::field_key(key).is_some() => filter.fields.push((
+                    filter::field_key(key).unwrap_or_default().to_string(),
+                    alternatives(value, str::to_string),
+                )),
+                other => return Err(format!("unknown condition '{}'", other)),
+            }
+            rest = tail.trim_start();
+        }
+
+        if filter.apps.is_empty()
+            && filter.levels.is_empty()
+            && filter.regions.is_empty()
+            && filter.instances.is_empty()
+            && filter.fields.is_empty()
+            && filter.pattern.is_none()
+        {
+            return Err("a channel needs at least one condition".to_string());
+        }
+        Ok(Self {
+            text: text.to_string(),
+            name: name.to_string(),
+            filter,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ChannelStats {
+    pub name: String,
+    /// The rule from `CHANNELS`; unset for `all`
+    pub rule: Option<String>,
+    /// Lines routed into the channel since it was configured
+    pub routed: u64,
+    /// Streaming connections subscribed to it
+    pub subscribers: usize,
+}
+
+#[utoipa::path(
+    get, path = "/channels", tag = "connections",
+    responses(
+        (status = 200, description = "Configured channels with routed lines and subscribers", body = Vec<ChannelStats>),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+pub async fn channels_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<ChannelStats>>, ApiError> {
+    check_auth(&state, &headers)?;
+    Ok(Json(state.fanout.channels()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_channel_rules() {
+        let rule: ChannelRule = "checkout-slow app=Checkout match=took \\d{4,}ms"
+            .parse()
+            .unwrap();
+        assert_eq!(rule.name, "checkout-slow");
+        assert_eq!(rule.filter.apps, vec!["checkout"]);
+        assert!(rule.filter.pattern.unwrap().is_match("took 2150ms"));
+
+        let rule: ChannelRule = "errors level=ERR|fatal field.status=500|502"
+            .parse()
+            .unwrap();
+        assert_eq!(rule.filter.levels, vec!["error", "fatal"]);
+        assert_eq!(
+            rule.filter.fields,
+            vec![(
+                "status".to_string(),
+                vec!["500".to_string(), "502".to_string()]
+            )]
+        );
+
+        assert!("errors".parse::<ChannelRule>().is_err());
+        assert!("all level=error".parse::<ChannelRule>().is_err());
+        assert!("Errors level=error".parse::<ChannelRule>().is_err());
+        assert!("errors severity=high".parse::<ChannelRule>().is_err());
+        assert!("errors match=(".parse::<ChannelRule>().is_err());
+    }
+}
@@ -0,0 +1,65 @@
+//! Fault injection for resilience testing in staging. Each fault stays off
+//! until its `CHAOS_*` setting is non-zero, and all of them reload, so a
+//! fault can be switched on, watched, and switched off again:
+//!
+//! - `CHAOS_OPENROUTER_FAILURE_RATE` fails that share of OpenRouter calls
+//!   with a 503, exercising retries, model fallback and `ChatError` handling
+//! - `CHAOS_BROADCAST_LAG_MS` stalls every streaming consumer after each
+//!   message, so queues overflow and clients see `Lagged` recovery
+//! - `CHAOS_STORE_FAILURE_RATE` fails that share of log store writes and
+//!   readiness probes
+
+use std::time::Duration;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::config::Config;
+use crate::http::AppState;
+
+/// Push the chaos settings into the components that inject them
+pub fn apply(state: &AppState, config: &Config) {
+    let lag = Duration::from_millis(config.chaos_broadcast_lag_ms);
+    state.fanout.set_artificial_lag(lag);
+    state
+        .log_buffer
+        .set_write_failure_rate(config.chaos_store_failure_rate);
+    for tenant in state.tenants.iter() {
+        tenant.fanout.set_artificial_lag(lag);
+        tenant
+            .log_buffer
+            .set_write_failure_rate(config.chaos_store_failure_rate);
+    }
+
+    let enabled = enabled(config);
+    if !enabled.is_empty() {
+        warn!(faults = %enabled.join(", "), "Chaos fault injection is enabled");
+    }
+}
+
+fn enabled(config: &Config) -> Vec<String> {
+    let mut faults = Vec::new();
+    if config.chaos_openrouter_failure_rate > 0.0 {
+        faults.push(format!(
+            "openrouter_failure_rate={}",
+            config.chaos_openrouter_failure_rate
+        ));
+    }
+    if config.chaos_broadcast_lag_ms > 0 {
+        faults.push(format!(
+            "broadcast_lag_ms={}",
+            config.chaos_broadcast_lag_ms
+        ));
+    }
+    if config.chaos_store_failure_rate > 0.0 {
+        faults.push(format!(
+            "store_failure_rate={}",
+            config.chaos_store_failure_rate
+        ));
+    }
+    faults
+}
+
+/// Whether to inject a fault that fires at `rate` (0 to 1)
+pub fn roll(rate: f64) -> bool {
+    rate > 0.0 && (Uuid::new_v4().as_u128() % 1_000_000) as f64 / 1_000_000.0 < rate
+}
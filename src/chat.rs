@@ -1,33 +1,59 @@
-use axum::{
-    extract::State,
-    http::{HeaderMap, StatusCode},
-    response::{IntoResponse, Response},
-    Json,
-};
+use axum::{extract::State, http::HeaderMap, Json};
+use chrono::Utc;
+use futures::StreamExt;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
-use std::time::Instant;
-use tracing::{error, info, warn};
-
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tracing::{debug, error, info, warn};
+use utoipa::ToSchema;
+
+use crate::analysis::{self, Finding, ResponseFormat, MAX_REPAIRS, STRUCTURED_INSTRUCTIONS};
+use crate::annotations::format_annotations;
+use crate::audit::ToolCallEvent;
+use crate::chat_cache::cache_key;
+use crate::chaos;
+use crate::config::Config;
+use crate::deploys::format_comparisons;
+use crate::error::{current_request_id, ApiError, ErrorBody, REQUEST_ID_HEADER};
+use crate::facets::{parse_fields, FacetsResponse, FACET_FIELDS};
 use crate::http::AppState;
-use crate::log_buffer::LogBuffer;
-use crate::metrics::Metrics;
-use crate::pricing::{CostBreakdown, ModelPricing};
+use crate::log_buffer::LogSummary;
+use crate::pricing::{CostBreakdown, CostPolicy, PricingCatalog};
 use crate::prompt::{
     build_initial_context, build_system_prompt, format_logs_compact, format_metrics_compact,
 };
+use crate::redact::{self, Rules};
+use crate::runbooks::format_hits;
+use crate::tls;
+use crate::tokenizer::{count_prompt_tokens, count_tokens};
+use crate::trace::TraceResponse;
+use crate::transcripts::{TranscriptEvent, TranscriptMessage, TranscriptToolCall};
+use crate::usage::{UsageEvent, UsageTracker};
 
 // ==================== Request/Response Types ====================
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct ChatRequest {
     pub message: String,
     #[serde(default)]
     pub model: Option<String>,
+    /// Groups tool calls in the audit log; defaults to the request id
+    #[serde(default)]
+    pub conversation_id: Option<String>,
+    /// Completion length, up to CHAT_MAX_TOKENS_LIMIT (default CHAT_MAX_TOKENS)
+    #[serde(default)]
+    pub max_tokens: Option<u32>,
+    /// Sampling temperature from 0 to 2 (default CHAT_TEMPERATURE)
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    /// `structured` for validated JSON findings instead of prose
+    #[serde(default)]
+    pub response_format: ResponseFormat,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize, ToSchema)]
 pub struct ChatResponse {
     pub response: String,
     pub model: String,
@@ -37,13 +63,69 @@ pub struct ChatResponse {
     pub cost: Option<CostBreakdown>,
     pub tools_called: Vec<String>,
     pub processing_time_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    pub conversation_id: String,
+    /// Model the client asked for, when the cost guard swapped in the default
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub downgraded_from: Option<String>,
+    /// Answered from the response cache without calling the model
+    pub cached: bool,
+    /// The parsed answer, with `"response_format": "structured"`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub findings: Option<Vec<Finding>>,
+}
+
+/// Projected cost of a chat's first model call on one model
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ModelEstimate {
+    pub model: String,
+    /// System prompt, log context, question and tool schema, counted with
+    /// the model's tokenizer
+    pub prompt_tokens: u32,
+    pub prompt_cost_usd: f64,
+    /// The prompt plus a completion of `max_tokens`, the figure
+    /// CHAT_MAX_COST_USD is checked against
+    pub max_cost_usd: f64,
+    /// The cost guard would refuse or downgrade this model
+    pub over_limit: bool,
 }
 
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ChatEstimate {
+    pub max_tokens: u32,
+    /// CHAT_MAX_COST_USD, when set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cost_limit_usd: Option<f64>,
+    /// The requested model (or the default) first, then the default and the
+    /// fallback models
+    pub models: Vec<ModelEstimate>,
+}
+
+/// Progress of a chat in flight, streamed to `/chat/ws` clients
 #[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ChatEvent {
+    /// Next piece of the answer
+    Token { text: String },
+    /// The model asked for a tool; it runs next
+    ToolCall { name: String, arguments: String },
+    ToolResult {
+        name: String,
+        ok: bool,
+        duration_ms: u64,
+    },
+}
+
+pub type ChatEvents = mpsc::UnboundedSender<ChatEvent>;
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
 pub struct TokenUsage {
     pub prompt_tokens: u32,
     pub completion_tokens: u32,
     pub total_tokens: u32,
+    /// OpenRouter reported no usage; counts are local tokenizer estimates
+    pub estimated: bool,
 }
 
 // ==================== OpenRouter API Types ====================
@@ -56,6 +138,10 @@ struct OpenRouterRequest {
     tools: Option<Vec<Tool>>,
     max_tokens: u32,
     temperature: f32,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_options: Option<serde_json::Value>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -84,21 +170,24 @@ struct FunctionCall {
 }
 
 #[derive(Debug, Clone, Serialize)]
-struct Tool {
+pub(crate) struct Tool {
     #[serde(rename = "type")]
     tool_type: String,
-    function: FunctionDefinition,
+    pub(crate) function: FunctionDefinition,
 }
 
 #[derive(Debug, Clone, Serialize)]
-struct FunctionDefinition {
-    name: String,
-    description: String,
-    parameters: serde_json::Value,
+pub(crate) struct FunctionDefinition {
+    pub(crate) name: String,
+    pub(crate) description: String,
+    pub(crate) parameters: serde_json::Value,
 }
 
 #[derive(Debug, Deserialize)]
 struct OpenRouterResponse {
+    /// Generation id, for correlating with OpenRouter's own logs
+    #[serde(default)]
+    id: Option<String>,
     choices: Vec<Choice>,
     model: String,
     usage: Option<OpenRouterUsage>,
@@ -110,6 +199,149 @@ struct Choice {
     finish_reason: Option<String>,
 }
 
+/// One `data:` event of a streamed completion
+#[derive(Debug, Deserialize)]
+struct StreamChunk {
+    #[serde(default)]
+    id: Option<String>,
+    #[serde(default)]
+    model: Option<String>,
+    #[serde(default)]
+    choices: Vec<StreamChoice>,
+    #[serde(default)]
+    usage: Option<OpenRouterUsage>,
+    /// Set when the provider fails after the stream has started
+    #[serde(default)]
+    error: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamChoice {
+    #[serde(default)]
+    delta: StreamDelta,
+    #[serde(default)]
+    finish_reason: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct StreamDelta {
+    #[serde(default)]
+    content: Option<String>,
+    #[serde(default)]
+    tool_calls: Vec<ToolCallDelta>,
+}
+
+/// Fragment of a tool call; `arguments` arrives in pieces keyed by `index`
+#[derive(Debug, Deserialize)]
+struct ToolCallDelta {
+    index: usize,
+    #[serde(default)]
+    id: Option<String>,
+    #[serde(default)]
+    function: Option<FunctionCallDelta>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FunctionCallDelta {
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    arguments: Option<String>,
+}
+
+/// Rebuilds a complete response from streamed chunks
+struct StreamAssembler {
+    id: Option<String>,
+    model: String,
+    content: String,
+    tool_calls: Vec<ToolCall>,
+    finish_reason: Option<String>,
+    usage: Option<OpenRouterUsage>,
+}
+
+impl StreamAssembler {
+    fn new(model: &str) -> Self {
+        Self {
+            id: None,
+            model: model.to_string(),
+            content: String::new(),
+            tool_calls: Vec::new(),
+            finish_reason: None,
+            usage: None,
+        }
+    }
+
+    /// Fold in a chunk, returning any new answer text
+    fn push(&mut self, chunk: StreamChunk) -> Result<Option<String>, ChatError> {
+        if let Some(error) = chunk.error {
+            return Err(ChatError::Api(format!(
+                "OpenRouter stream error: {}",
+                error
+            )));
+        }
+        self.id = self.id.take().or(chunk.id);
+        if let Some(model) = chunk.model {
+            self.model = model;
+        }
+        if chunk.usage.is_some() {
+            self.usage = chunk.usage;
+        }
+
+        let mut text = String::new();
+        for choice in chunk.choices {
+            if choice.finish_reason.is_some() {
+                self.finish_reason = choice.finish_reason;
+            }
+            if let Some(content) = choice.delta.content {
+                text.push_str(&content);
+            }
+            for delta in choice.delta.tool_calls {
+                while self.tool_calls.len() <= delta.index {
+                    self.tool_calls.push(ToolCall {
+                        id: String::new(),
+                        call_type: "function".to_string(),
+                        function: FunctionCall {
+                            name: String::new(),
+                            arguments: String::new(),
+                        },
+                    });
+                }
+                let call = &mut self.tool_calls[delta.index];
+                if let Some(id) = delta.id {
+                    call.id = id;
+                }
+                if let Some(function) = delta.function {
+                    call.function
+                        .name
+                        .push_str(&function.name.unwrap_or_default());
+                    call.function
+                        .arguments
+                        .push_str(&function.arguments.unwrap_or_default());
+                }
+            }
+        }
+        self.content.push_str(&text);
+        Ok((!text.is_empty()).then_some(text))
+    }
+
+    fn finish(self) -> OpenRouterResponse {
+        OpenRouterResponse {
+            id: self.id,
+            choices: vec![Choice {
+                message: Message {
+                    role: "assistant".to_string(),
+                    content: (!self.content.is_empty()).then_some(self.content),
+                    tool_calls: (!self.tool_calls.is_empty()).then_some(self.tool_calls),
+                    tool_call_id: None,
+                },
+                finish_reason: self.finish_reason,
+            }],
+            model: self.model,
+            usage: self.usage,
+        }
+    }
+}
+
 #[derive(Debug, Deserialize)]
 struct OpenRouterUsage {
     prompt_tokens: u32,
@@ -119,8 +351,8 @@ struct OpenRouterUsage {
 
 // ==================== Tool Definitions ====================
 
-fn get_tools() -> Vec<Tool> {
-    vec![
+pub(crate) fn get_tools(runbooks: bool, views: &[String]) -> Vec<Tool> {
+    let mut tools = vec![
         Tool {
             tool_type: "function".to_string(),
             function: FunctionDefinition {
@@ -158,7 +390,184 @@ fn get_tools() -> Vec<Tool> {
                 }),
             },
         },
-    ]
+        Tool {
+            tool_type: "function".to_string(),
+            function: FunctionDefinition {
+                name: "get_trace".to_string(),
+                description: "Fetch every buffered line of one request across instances, by trace id or request id, in event order.".to_string(),
+                parameters: serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "trace_id": {
+                            "type": "string",
+                            "description": "Trace or request id from a log line"
+                        }
+                    },
+                    "required": ["trace_id"]
+                }),
+            },
+        },
+        Tool {
+            tool_type: "function".to_string(),
+            function: FunctionDefinition {
+                name: "get_http_analytics".to_string(),
+                description: "Summarize traffic from access logs: requests, status codes and p50/p90/p99 latency per route.".to_string(),
+                parameters: serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "minutes": {
+                            "type": "integer",
+                            "description": "Lookback in minutes (defaults to the whole rolling window)"
+                        },
+                        "limit": {
+                            "type": "integer",
+                            "description": "Routes to list, busiest first (default 20)"
+                        }
+                    }
+                }),
+            },
+        },
+        Tool {
+            tool_type: "function".to_string(),
+            function: FunctionDefinition {
+                name: "get_deploys".to_string(),
+                description: "List recent deploys with line and error counts in the 15 minutes before and after each, to check whether a release changed error rates.".to_string(),
+                parameters: serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "limit": {
+                            "type": "integer",
+                            "description": "Deploys to list, newest first (default 5)"
+                        }
+                    }
+                }),
+            },
+        },
+        Tool {
+            tool_type: "function".to_string(),
+            function: FunctionDefinition {
+                name: "get_facets".to_string(),
+                description: "Count the distinct values of log fields over a recent window, e.g. which regions, instances or levels are represented and how many lines each has.".to_string(),
+                parameters: serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "fields": {
+                            "type": "array",
+                            "items": { "type": "string", "enum": FACET_FIELDS },
+                            "description": "Fields to count (default all)"
+                        },
+                        "minutes": {
+                            "type": "integer",
+                            "description": "Lookback in minutes (default 15)"
+                        }
+                    }
+                }),
+            },
+        },
+    ];
+    if !views.is_empty() {
+        tools[0].function.parameters["properties"]["view"] = serde_json::json!({
+            "type": "string",
+            "enum": views,
+            "description": "Saved view to filter by (its levels, instances, pattern and lookback)"
+        });
+    }
+    if runbooks {
+        tools.push(Tool {
+            tool_type: "function".to_string(),
+            function: FunctionDefinition {
+                name: "search_runbooks".to_string(),
+                description: "Search the team's runbooks for remediation steps. Use the error message or symptom as the query.".to_string(),
+                parameters: serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "query": {
+                            "type": "string",
+                            "description": "Keywords, e.g. an error message or component name"
+                        },
+                        "limit": {
+                            "type": "integer",
+                            "description": "Maximum sections to return (default 3)"
+                        }
+                    },
+                    "required": ["query"]
+                }),
+            },
+        });
+    }
+    tools
+}
+
+/// One `CHAT_TOOL_SCOPES` entry: `principal=tool|tool`. The principal is who
+/// `scope_principal` says is calling: a client-certificate CN, a dashboard
+/// user or a tenant's name. `*` covers callers without an entry of their own,
+/// including the AUTH_TOKEN bearer
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolScope {
+    pub principal: String,
+    pub tools: Vec<String>,
+}
+
+impl FromStr for ToolScope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (principal, tools) = s
+            .split_once('=')
+            .filter(|(principal, _)| !principal.trim().is_empty())
+            .ok_or_else(|| format!("expected principal=tool|tool, found '{}'", s))?;
+        let known = get_tools(true, &[]);
+        let tools = tools
+            .split('|')
+            .map(str::trim)
+            .filter(|tool| !tool.is_empty())
+            .map(|tool| match known.iter().any(|t| t.function.name == tool) {
+                true => Ok(tool.to_string()),
+                false => Err(format!("unknown tool '{}' for {}", tool, principal.trim())),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            principal: principal.trim().to_string(),
+            tools,
+        })
+    }
+}
+
+/// Whether CHAT_TOOL_SCOPES lets `principal` run `tool`; callers no entry
+/// covers may run every tool
+pub(crate) fn tool_allowed(config: &Config, principal: Option<&str>, tool: &str) -> bool {
+    let scopes = &config.chat_tool_scopes;
+    principal
+        .and_then(|p| scopes.iter().find(|scope| scope.principal == p))
+        .or_else(|| scopes.iter().find(|scope| scope.principal == "*"))
+        .is_none_or(|scope| scope.tools.iter().any(|t| t == tool))
+}
+
+/// Who CHAT_TOOL_SCOPES applies to: the client-certificate CN, else the
+/// dashboard session's user, else the tenant whose token opened the route
+pub(crate) fn scope_principal(state: &AppState) -> Option<String> {
+    tls::current_principal()
+        .or_else(|| state.session_user.clone())
+        .or_else(|| state.tenant.as_ref().map(|tenant| tenant.name.clone()))
+}
+
+/// Tools reading process-wide state, which tenants don't get
+const FLEET_TOOLS: &[&str] = &["get_metrics"];
+
+/// Whether `tool` reads data the caller's routes may not see
+fn fleet_only(state: &AppState, tool: &str) -> bool {
+    state.tenant.is_some() && FLEET_TOOLS.contains(&tool)
+}
+
+/// The tools offered to the current caller: those it may run
+pub(crate) fn allowed_tools(state: &AppState) -> Vec<Tool> {
+    let config = state.config.current();
+    let principal = scope_principal(state);
+    get_tools(!state.runbooks.is_empty(), &state.views.names())
+        .into_iter()
+        .filter(|tool| tool_allowed(&config, principal.as_deref(), &tool.function.name))
+        .filter(|tool| !fleet_only(state, &tool.function.name))
+        .collect()
 }
 
 // ==================== Tool Execution ====================
@@ -167,6 +576,7 @@ fn get_tools() -> Vec<Tool> {
 struct GetLogsArgs {
     count: Option<usize>,
     minutes: Option<i64>,
+    view: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -175,36 +585,93 @@ struct GetMetricsArgs {
     metric_type: Option<String>,
 }
 
-async fn execute_tool(
+#[derive(Debug, Deserialize)]
+struct GetTraceArgs {
+    trace_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct GetHttpAnalyticsArgs {
+    minutes: Option<i64>,
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GetDeploysArgs {
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GetFacetsArgs {
+    #[serde(default)]
+    fields: Vec<String>,
+    minutes: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchRunbooksArgs {
+    query: String,
+    limit: Option<usize>,
+}
+
+pub(crate) async fn execute_tool(
     tool_name: &str,
     arguments: &str,
-    log_buffer: &Arc<LogBuffer>,
-    metrics: &Arc<Metrics>,
-    start_time: Instant,
+    state: &AppState,
 ) -> Result<String, String> {
+    let principal = scope_principal(state);
+    if !tool_allowed(&state.config.current(), principal.as_deref(), tool_name) {
+        return Err(format!(
+            "Tool '{}' is not allowed for {} by CHAT_TOOL_SCOPES",
+            tool_name,
+            principal.as_deref().unwrap_or("this caller")
+        ));
+    }
+    if fleet_only(state, tool_name) {
+        return Err(format!("Tool '{}' is not available to tenants", tool_name));
+    }
+    let log_buffer = &state.log_buffer;
     match tool_name {
         "get_logs" => {
             let args: GetLogsArgs =
                 serde_json::from_str(arguments).map_err(|e| format!("Invalid arguments: {}", e))?;
 
-            let logs = if let Some(minutes) = args.minutes {
+            let logs = if let Some(ref name) = args.view {
+                let view = state
+                    .views
+                    .get(name)
+                    .ok_or_else(|| format!("Unknown view '{}'", name))?;
+                let mut logs = match args.minutes.or(view.view.minutes) {
+                    Some(minutes) => log_buffer.get_last_minutes(minutes).await,
+                    None => log_buffer.get_last_n(usize::MAX).await,
+                };
+                logs.retain(|log| view.matches(log));
+                let count = args.count.unwrap_or(50);
+                logs.split_off(logs.len().saturating_sub(count))
+            } else if let Some(minutes) = args.minutes {
                 log_buffer.get_last_minutes(minutes).await
             } else {
                 let count = args.count.unwrap_or(50);
                 log_buffer.get_last_n(count).await
             };
 
-            Ok(format!(
+            let mut result = format!(
                 "Retrieved {} logs:\n{}",
                 logs.len(),
                 format_logs_compact(&logs)
-            ))
+            );
+            let notes = state.annotations.overlapping(&logs, state.app_scope());
+            if !notes.is_empty() {
+                result.push_str("\nAnnotations:\n");
+                result.push_str(&format_annotations(&notes));
+            }
+            Ok(result)
         }
         "get_metrics" => {
             let args: GetMetricsArgs =
                 serde_json::from_str(arguments).map_err(|e| format!("Invalid arguments: {}", e))?;
 
-            let snapshot = metrics.snapshot(start_time).await;
+            let snapshot = state.metrics.snapshot(state.start_time).await;
             let metric_type = args.metric_type.as_deref().unwrap_or("all");
 
             let result = match metric_type {
@@ -241,52 +708,235 @@ async fn execute_tool(
 
             Ok(result)
         }
+        "get_trace" => {
+            let args: GetTraceArgs =
+                serde_json::from_str(arguments).map_err(|e| format!("Invalid arguments: {}", e))?;
+
+            Ok(TraceResponse::collect(log_buffer, &args.trace_id)
+                .await
+                .summarize())
+        }
+        "get_http_analytics" => {
+            let args: GetHttpAnalyticsArgs =
+                serde_json::from_str(arguments).map_err(|e| format!("Invalid arguments: {}", e))?;
+
+            Ok(state
+                .http_analytics
+                .snapshot(args.minutes, args.limit.map(|l| l.clamp(1, 50)))
+                .summarize())
+        }
+        "get_deploys" => {
+            let args: GetDeploysArgs =
+                serde_json::from_str(arguments).map_err(|e| format!("Invalid arguments: {}", e))?;
+
+            let comparisons = state
+                .deploys
+                .compare(
+                    &state.log_buffer,
+                    args.limit.unwrap_or(5).clamp(1, 20),
+                    state.app_scope(),
+                )
+                .await;
+            Ok(format_comparisons(&comparisons))
+        }
+        "get_facets" => {
+            let args: GetFacetsArgs =
+                serde_json::from_str(arguments).map_err(|e| format!("Invalid arguments: {}", e))?;
+
+            let fields = parse_fields(Some(&args.fields.join(",")))?;
+            let minutes = args.minutes.unwrap_or(15).clamp(1, 1440);
+            Ok(FacetsResponse::collect(&state.log_buffer, &fields, minutes)
+                .await
+                .summarize())
+        }
+        "search_runbooks" => {
+            let args: SearchRunbooksArgs =
+                serde_json::from_str(arguments).map_err(|e| format!("Invalid arguments: {}", e))?;
+
+            let hits = state.runbooks.search(&args.query, args.limit.unwrap_or(3).clamp(1, 10));
+            Ok(format_hits(&args.query, &hits))
+        }
         _ => Err(format!("Unknown tool: {}", tool_name)),
     }
 }
 
 // ==================== OpenRouter Client ====================
 
+const RETRY_BACKOFF_INITIAL: Duration = Duration::from_millis(500);
+const RETRY_BACKOFF_MAX: Duration = Duration::from_secs(8);
+
 pub struct OpenRouterClient {
     client: Client,
     api_key: String,
     base_url: String,
+    /// Forwarded as X-Request-Id so OpenRouter calls correlate with ours
+    request_id: Option<String>,
+    max_retries: u32,
+    sampling: Sampling,
+    /// Share of calls failed on purpose (CHAOS_OPENROUTER_FAILURE_RATE)
+    failure_rate: f64,
 }
 
 impl OpenRouterClient {
-    pub fn new(api_key: String) -> Self {
+    pub fn new(
+        api_key: String,
+        request_id: Option<String>,
+        max_retries: u32,
+        sampling: Sampling,
+    ) -> Self {
         Self {
             client: Client::builder()
-                .timeout(std::time::Duration::from_secs(60))
+                .timeout(Duration::from_secs(60))
                 .build()
                 .expect("Failed to create HTTP client"),
             api_key,
             base_url: "https://openrouter.ai/api/v1".to_string(),
+            request_id,
+            max_retries,
+            sampling,
+            failure_rate: 0.0,
         }
     }
 
+    /// Send calls to another OpenAI-compatible API (OPENROUTER_BASE_URL)
+    fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.to_string();
+        self
+    }
+
+    /// Fail this share of calls as if OpenRouter returned 503
+    fn with_failure_rate(mut self, rate: f64) -> Self {
+        self.failure_rate = rate;
+        self
+    }
+
+    /// Call the first model in `models`, retrying transient failures with
+    /// backoff and then moving down the chain. Models that gave up are
+    /// removed so later turns of the same chat start at the one that works.
+    /// With `events`, the answer is streamed to it token by token.
     async fn chat(
         &self,
-        model: &str,
+        models: &mut Vec<String>,
         messages: Vec<Message>,
         tools: Option<Vec<Tool>>,
+        events: Option<&ChatEvents>,
     ) -> Result<OpenRouterResponse, ChatError> {
-        let request = OpenRouterRequest {
-            model: model.to_string(),
-            messages,
-            tools,
-            max_tokens: 4096,
-            temperature: 0.3,
-        };
+        loop {
+            let model = models[0].clone();
+            let mut attempt = 0;
+            let err = loop {
+                let request = OpenRouterRequest {
+                    model: model.clone(),
+                    messages: messages.clone(),
+                    tools: tools.clone(),
+                    max_tokens: self.sampling.max_tokens,
+                    temperature: self.sampling.temperature,
+                    stream: events.is_some(),
+                    stream_options: events.map(|_| serde_json::json!({ "include_usage": true })),
+                };
+                let result = match events {
+                    Some(events) => self.send_stream(&request, events).await,
+                    None => self.send(&request).await,
+                };
+                match result {
+                    Ok(response) => return Ok(response),
+                    Err(e) if e.is_transient() && attempt < self.max_retries => {
+                        let delay = retry_delay(attempt);
+                        warn!(
+                            model = %model,
+                            attempt = attempt + 1,
+                            delay_ms = delay.as_millis() as u64,
+                            error = ?e,
+                            "OpenRouter call failed, retrying"
+                        );
+                        tokio::time::sleep(delay).await;
+                        attempt += 1;
+                    }
+                    Err(e) => break e,
+                }
+            };
+
+            if !err.is_transient() || models.len() == 1 {
+                return Err(err);
+            }
+            models.remove(0);
+            warn!(
+                model = %model,
+                fallback = %models[0],
+                error = ?err,
+                "OpenRouter model unavailable, falling back"
+            );
+        }
+    }
+
+    async fn send(&self, request: &OpenRouterRequest) -> Result<OpenRouterResponse, ChatError> {
+        self.post(request)
+            .await?
+            .json()
+            .await
+            .map_err(|e| ChatError::Parse(e.to_string()))
+    }
+
+    /// Streamed variant of `send`. Failures after the first token are not
+    /// transient, since a retry would repeat text the client already has.
+    async fn send_stream(
+        &self,
+        request: &OpenRouterRequest,
+        events: &ChatEvents,
+    ) -> Result<OpenRouterResponse, ChatError> {
+        let mut body = self.post(request).await?.bytes_stream();
+        let mut assembler = StreamAssembler::new(&request.model);
+        let mut started = false;
+        let mut buffer: Vec<u8> = Vec::new();
+
+        while let Some(bytes) = body.next().await {
+            let bytes = bytes.map_err(|e| match started {
+                true => ChatError::Api(format!("OpenRouter stream interrupted: {}", e)),
+                false => ChatError::Network(e.to_string()),
+            })?;
+            buffer.extend_from_slice(&bytes);
+
+            while let Some(end) = buffer.iter().position(|b| *b == b'\n') {
+                let line: Vec<u8> = buffer.drain(..=end).collect();
+                let line = String::from_utf8_lossy(&line);
+                // Lines starting with ':' are keep-alive comments
+                let Some(data) = line.trim().strip_prefix("data:") else {
+                    continue;
+                };
+                let data = data.trim();
+                if data == "[DONE]" {
+                    return Ok(assembler.finish());
+                }
+                let chunk: StreamChunk =
+                    serde_json::from_str(data).map_err(|e| ChatError::Parse(e.to_string()))?;
+                if let Some(text) = assembler.push(chunk)? {
+                    started = true;
+                    let _ = events.send(ChatEvent::Token { text });
+                }
+            }
+        }
+        Ok(assembler.finish())
+    }
 
-        let response = self
+    async fn post(&self, request: &OpenRouterRequest) -> Result<reqwest::Response, ChatError> {
+        if chaos::roll(self.failure_rate) {
+            return Err(ChatError::Unavailable(
+                "OpenRouter API error 503 Service Unavailable: injected by chaos testing"
+                    .to_string(),
+            ));
+        }
+        let mut builder = self
             .client
             .post(format!("{}/chat/completions", self.base_url))
             .header("Authorization", format!("Bearer {}", self.api_key))
             .header("HTTP-Referer", "https://flywatch.app")
             .header("X-Title", "Flywatch Log Analyzer")
-            .header("Content-Type", "application/json")
-            .json(&request)
+            .header("Content-Type", "application/json");
+        if let Some(ref request_id) = self.request_id {
+            builder = builder.header(REQUEST_ID_HEADER, request_id);
+        }
+        let response = builder
+            .json(request)
             .send()
             .await
             .map_err(|e| ChatError::Network(e.to_string()))?;
@@ -294,107 +944,611 @@ impl OpenRouterClient {
         if !response.status().is_success() {
             let status = response.status();
             let body = response.text().await.unwrap_or_default();
-            return Err(ChatError::Api(format!(
-                "OpenRouter API error {}: {}",
-                status, body
-            )));
+            let message = format!("OpenRouter API error {}: {}", status, body);
+            if status.as_u16() == 429 || status.is_server_error() {
+                return Err(ChatError::Unavailable(message));
+            }
+            return Err(ChatError::Api(message));
         }
-
-        response
-            .json()
-            .await
-            .map_err(|e| ChatError::Parse(e.to_string()))
+        Ok(response)
     }
 }
 
+/// Exponential backoff with jitter (50-100% of the step), so concurrent
+/// chats don't retry in lockstep
+fn retry_delay(attempt: u32) -> Duration {
+    let step = std::cmp::min(
+        RETRY_BACKOFF_INITIAL * 2u32.pow(attempt.min(16)),
+        RETRY_BACKOFF_MAX,
+    );
+    let jitter = (uuid::Uuid::new_v4().as_u128() % 1000) as u32;
+    step / 2 + step / 2 * jitter / 1000
+}
+
 // ==================== Error Handling ====================
 
 #[derive(Debug)]
 pub enum ChatError {
     Network(String),
+    /// 429 or 5xx from OpenRouter
+    Unavailable(String),
     Api(String),
     Parse(String),
     Config(String),
+    InvalidRequest(String),
+    ModelNotAllowed(String),
+    CostLimit(String),
+    /// Usage records couldn't be read
+    Usage(String),
     MaxIterations,
 }
 
-impl IntoResponse for ChatError {
-    fn into_response(self) -> Response {
-        let (status, message) = match self {
-            ChatError::Network(msg) => (StatusCode::BAD_GATEWAY, msg),
-            ChatError::Api(msg) => (StatusCode::BAD_GATEWAY, msg),
-            ChatError::Parse(msg) => (StatusCode::INTERNAL_SERVER_ERROR, msg),
-            ChatError::Config(msg) => (StatusCode::INTERNAL_SERVER_ERROR, msg),
-            ChatError::MaxIterations => (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Max tool iterations exceeded".to_string(),
-            ),
-        };
+impl ChatError {
+    /// Worth retrying, possibly on another model
+    fn is_transient(&self) -> bool {
+        matches!(self, ChatError::Network(_) | ChatError::Unavailable(_))
+    }
+}
 
-        let body = serde_json::json!({
-            "error": message,
-            "status": status.as_u16()
-        });
+impl From<ChatError> for ApiError {
+    fn from(e: ChatError) -> Self {
+        match e {
+            ChatError::Network(msg) | ChatError::Unavailable(msg) | ChatError::Api(msg) => {
+                ApiError::Upstream(msg)
+            }
+            ChatError::Parse(msg) => ApiError::Upstream(format!("Unexpected response: {}", msg)),
+            ChatError::Config(msg) => ApiError::NotConfigured(msg),
+            ChatError::InvalidRequest(msg) => ApiError::InvalidRequest(msg),
+            ChatError::ModelNotAllowed(model) => {
+                ApiError::Forbidden(format!("Model '{}' is not allowed", model))
+            }
+            ChatError::CostLimit(msg) => ApiError::Forbidden(msg),
+            ChatError::Usage(msg) => ApiError::Internal(msg),
+            ChatError::MaxIterations => {
+                ApiError::Internal("Max tool iterations exceeded".to_string())
+            }
+        }
+    }
+}
+
+// ==================== Model & Cost Guard ====================
+
+/// Highest temperature OpenRouter accepts
+pub const MAX_TEMPERATURE: f32 = 2.0;
+
+/// Completion settings for one chat
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sampling {
+    pub max_tokens: u32,
+    pub temperature: f32,
+}
 
-        (status, Json(body)).into_response()
+impl Sampling {
+    /// Request overrides, falling back to and bounded by the config
+    fn resolve(config: &Config, request: &ChatRequest) -> Result<Self, ChatError> {
+        let max_tokens = request.max_tokens.unwrap_or(config.chat_max_tokens);
+        if max_tokens == 0 || max_tokens > config.chat_max_tokens_limit {
+            return Err(ChatError::InvalidRequest(format!(
+                "max_tokens must be between 1 and {}",
+                config.chat_max_tokens_limit
+            )));
+        }
+        let temperature = request.temperature.unwrap_or(config.chat_temperature);
+        if !(0.0..=MAX_TEMPERATURE).contains(&temperature) {
+            return Err(ChatError::InvalidRequest(format!(
+                "temperature must be between 0 and {}",
+                MAX_TEMPERATURE
+            )));
+        }
+        Ok(Self {
+            max_tokens,
+            temperature,
+        })
     }
 }
 
-// ==================== Chat Handler ====================
+/// `vendor/*` matches by prefix; anything else must match exactly
+fn model_matches(pattern: &str, model: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => model.starts_with(prefix),
+        None => pattern == model,
+    }
+}
 
-const MAX_TOOL_ITERATIONS: usize = 10;
+/// Check a model against CHAT_MODEL_ALLOWLIST / CHAT_MODEL_DENYLIST. The
+/// configured default model is the operator's own choice and always passes.
+fn check_model(config: &Config, model: &str) -> Result<(), ChatError> {
+    if model == config.openrouter_model {
+        return Ok(());
+    }
+    let denied = config
+        .chat_model_denylist
+        .iter()
+        .any(|p| model_matches(p, model));
+    let allowed = config.chat_model_allowlist.is_empty()
+        || config
+            .chat_model_allowlist
+            .iter()
+            .any(|p| model_matches(p, model));
+    if denied || !allowed {
+        return Err(ChatError::ModelNotAllowed(model.to_string()));
+    }
+    Ok(())
+}
 
-pub async fn chat_handler(
-    State(state): State<AppState>,
-    headers: HeaderMap,
-    Json(request): Json<ChatRequest>,
-) -> Result<Json<ChatResponse>, Response> {
-    let start = Instant::now();
+/// The models a chat may call: `model`, then each OPENROUTER_FALLBACK_MODELS
+/// entry that CHAT_MODEL_ALLOWLIST / CHAT_MODEL_DENYLIST let through
+fn model_chain(config: &Config, model: String) -> Vec<String> {
+    let mut models = vec![model];
+    for fallback in &config.openrouter_fallback_models {
+        if models.contains(fallback) {
+            continue;
+        }
+        match check_model(config, fallback) {
+            Ok(()) => models.push(fallback.clone()),
+            Err(_) => debug!(model = %fallback, "Skipping fallback model outside the allowlist"),
+        }
+    }
+    models
+}
 
-    // Auth check
-    crate::http::check_auth(&state, &headers)?;
+/// The model in `models` whose call would cost the most, and that estimate
+fn priciest(
+    config: &Config,
+    pricing: &PricingCatalog,
+    models: &[String],
+    prompt: &[&str],
+    max_tokens: u32,
+) -> (String, f64) {
+    models
+        .iter()
+        .map(|model| {
+            let tokens = count_prompt_tokens(model, prompt.iter().copied());
+            let cost = pricing
+                .for_model(config, model)
+                .estimate_max_cost(tokens, max_tokens);
+            (model.clone(), cost)
+        })
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .unwrap_or_default()
+}
 
-    // Check if OpenRouter is configured
-    let api_key = state
-        .config
-        .openrouter_api_key
-        .as_ref()
-        .ok_or_else(|| {
-            ChatError::Config("OPENROUTER_API_KEY not configured".to_string()).into_response()
-        })?;
+/// Enforce CHAT_MAX_COST_USD on the estimated cost of the first model call,
+/// priced on the most expensive model the call could fall back to. Returns
+/// the model chain to use and, after a downgrade, the model requested.
+fn guard_cost(
+    config: &Config,
+    pricing: &PricingCatalog,
+    model: String,
+    prompt: &[&str],
+    max_tokens: u32,
+) -> Result<(Vec<String>, Option<String>), ChatError> {
+    let models = model_chain(config, model);
+    let Some(ceiling) = config.chat_max_cost_usd else {
+        return Ok((models, None));
+    };
+
+    let (priciest_model, cost) = priciest(config, pricing, &models, prompt, max_tokens);
+    if cost <= ceiling {
+        return Ok((models, None));
+    }
+    let over = |model: &str, cost: f64| {
+        ChatError::CostLimit(format!(
+            "Estimated cost ${:.4} for {} exceeds the ${:.4} per-request limit",
+            cost, model, ceiling
+        ))
+    };
+    let requested = models[0].clone();
+    if config.chat_cost_policy == CostPolicy::Reject || requested == config.openrouter_model {
+        return Err(over(&priciest_model, cost));
+    }
 
-    let model = request
-        .model
-        .unwrap_or_else(|| state.config.openrouter_model.clone());
+    let fallback = model_chain(config, config.openrouter_model.clone());
+    let (fallback_model, fallback_cost) = priciest(config, pricing, &fallback, prompt, max_tokens);
+    if fallback_cost > ceiling {
+        return Err(over(&fallback_model, fallback_cost));
+    }
+    warn!(
+        requested = %requested,
+        model = %fallback[0],
+        estimated_cost_usd = cost,
+        "Chat downgraded to the default model to stay under the cost limit"
+    );
+    Ok((fallback, Some(requested)))
+}
+
+/// Enforce CHAT_MAX_COST_USD again before a later call in the tool loop:
+/// what earlier calls cost plus the estimate for this one, whose prompt has
+/// grown by every tool result, on the priciest model left in the chain
+fn check_cost(
+    config: &Config,
+    pricing: &PricingCatalog,
+    models: &[String],
+    prompt: &[&str],
+    max_tokens: u32,
+    spent: f64,
+) -> Result<(), ChatError> {
+    let Some(ceiling) = config.chat_max_cost_usd else {
+        return Ok(());
+    };
+    let (model, estimate) = priciest(config, pricing, models, prompt, max_tokens);
+    let cost = spent + estimate;
+    if cost > ceiling {
+        return Err(ChatError::CostLimit(format!(
+            "Estimated cost ${:.4} for {} after tool calls exceeds the ${:.4} per-request limit",
+            cost, model, ceiling
+        )));
+    }
+    Ok(())
+}
+
+/// Estimates for `requested` and every model the chat could fall back to
+fn model_estimates(
+    config: &Config,
+    pricing: &PricingCatalog,
+    requested: String,
+    prompt: &[&str],
+    max_tokens: u32,
+) -> Vec<ModelEstimate> {
+    let mut models = vec![requested];
+    for model in model_chain(config, config.openrouter_model.clone()) {
+        if !models.contains(&model) {
+            models.push(model);
+        }
+    }
+    models
+        .into_iter()
+        .map(|model| {
+            let prompt_tokens = count_prompt_tokens(&model, prompt.iter().copied());
+            let price = pricing.for_model(config, &model);
+            let max_cost_usd = price.estimate_max_cost(prompt_tokens, max_tokens);
+            ModelEstimate {
+                prompt_cost_usd: price.calculate_cost(prompt_tokens, 0).total_cost_usd,
+                over_limit: config
+                    .chat_max_cost_usd
+                    .is_some_and(|limit| max_cost_usd > limit),
+                model,
+                prompt_tokens,
+                max_cost_usd,
+            }
+        })
+        .collect()
+}
 
-    // Build initial context
-    let metrics_snapshot = state.metrics.snapshot(state.start_time).await;
+/// The conversation so far, as kept in a transcript
+fn transcript_messages(messages: &[Message]) -> Vec<TranscriptMessage> {
+    messages
+        .iter()
+        .map(|m| TranscriptMessage {
+            tool_calls: m
+                .tool_calls
+                .iter()
+                .flatten()
+                .map(|call| TranscriptToolCall {
+                    id: call.id.clone(),
+                    name: call.function.name.clone(),
+                    arguments: call.function.arguments.clone(),
+                })
+                .collect(),
+            tool_call_id: m.tool_call_id.clone(),
+            ..TranscriptMessage::new(&m.role, m.content.as_deref().unwrap_or_default())
+        })
+        .collect()
+}
+
+/// Enforce CHAT_MONTHLY_BUDGET_USD (or a tenant's budget) on what has been
+/// spent since the start of the calendar month, UTC
+async fn check_budget(config: &Config, usage: &UsageTracker) -> Result<(), ChatError> {
+    let Some(budget) = config.chat_monthly_budget_usd else {
+        return Ok(());
+    };
+    // Without the month's spend the budget can't be enforced, so refuse
+    let now = Utc::now();
+    let spent = usage
+        .month_to_date(now)
+        .await
+        .map_err(|e| ChatError::Usage(format!("Can't check the monthly chat budget: {}", e)))?;
+    if spent >= budget {
+        return Err(ChatError::CostLimit(format!(
+            "Monthly chat budget of ${:.2} is used up (${:.2} spent since {})",
+            budget,
+            spent,
+            now.format("%Y-%m-01")
+        )));
+    }
+    Ok(())
+}
+
+/// Text sent to the model on a call: message contents and the tool schema
+fn prompt_parts<'a>(messages: &'a [Message], tools_json: &'a str) -> Vec<&'a str> {
+    messages
+        .iter()
+        .filter_map(|m| m.content.as_deref())
+        .chain(std::iter::once(tools_json))
+        .collect()
+}
+
+/// Usage as reported by OpenRouter, or estimated locally when a provider
+/// leaves it out so usage analytics still see the call
+fn token_usage(
+    reported: Option<&OpenRouterUsage>,
+    model: &str,
+    prompt: &[&str],
+    completion: &str,
+) -> TokenUsage {
+    match reported {
+        Some(u) => TokenUsage {
+            prompt_tokens: u.prompt_tokens,
+            completion_tokens: u.completion_tokens,
+            total_tokens: u.total_tokens,
+            estimated: false,
+        },
+        None => {
+            let prompt_tokens = count_prompt_tokens(model, prompt.iter().copied());
+            let completion_tokens = count_tokens(model, completion);
+            TokenUsage {
+                prompt_tokens,
+                completion_tokens,
+                total_tokens: prompt_tokens + completion_tokens,
+                estimated: true,
+            }
+        }
+    }
+}
+
+/// Mask identifiers in text headed to OpenRouter when PII-safe mode is on
+pub(crate) fn scrub_for_provider(rules: Option<&Rules>, text: String) -> String {
+    match rules {
+        Some(rules) => rules.redact_text(&text).into_owned(),
+        None => text,
+    }
+}
+
+// ==================== Chat Handler ====================
+
+/// The metrics, log picture and annotations every chat starts from, with
+/// the summary it was built on. Tenants get no process metrics.
+async fn chat_context(state: &AppState) -> (String, LogSummary) {
+    let metrics_snapshot = match state.tenant {
+        None => Some(state.metrics.snapshot(state.start_time).await),
+        Some(_) => None,
+    };
     let log_summary = state.log_buffer.get_summary().await;
     let recent_logs = state.log_buffer.get_last_n(150).await;
-    let initial_context = build_initial_context(&metrics_snapshot, &log_summary, &recent_logs);
+    let mut context =
+        build_initial_context(metrics_snapshot.as_ref(), &log_summary, &recent_logs);
+    let notes = state.annotations.list(
+        log_summary.oldest_timestamp,
+        None,
+        None,
+        state.app_scope(),
+    );
+    if !notes.is_empty() {
+        context.push_str("\n## Annotations\n");
+        context.push_str(&format_annotations(&notes));
+        context.push('\n');
+    }
+    (context, log_summary)
+}
 
-    // Initialize messages
-    let mut messages = vec![
+/// The first call's messages: the system prompt, then the context and the
+/// question, scrubbed in PII-safe mode
+fn first_messages(
+    system_prompt: String,
+    context: &str,
+    question: &str,
+    pii_rules: Option<&Rules>,
+) -> Vec<Message> {
+    vec![
         Message {
             role: "system".to_string(),
-            content: Some(build_system_prompt().to_string()),
+            content: Some(system_prompt),
             tool_calls: None,
             tool_call_id: None,
         },
         Message {
             role: "user".to_string(),
-            content: Some(format!(
-                "{}\n\n## User Question\n{}",
-                initial_context, request.message
+            content: Some(scrub_for_provider(
+                pii_rules,
+                format!("{}\n\n## User Question\n{}", context, question),
             )),
             tool_calls: None,
             tool_call_id: None,
         },
-    ];
+    ]
+}
+
+const MAX_TOOL_ITERATIONS: usize = 10;
+
+#[utoipa::path(
+    post, path = "/chat", tag = "chat",
+    request_body = ChatRequest,
+    responses(
+        (status = 200, description = "Model answer with usage and cost", body = ChatResponse),
+        (status = 400, description = "max_tokens or temperature out of range", body = ErrorBody),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+        (status = 403, description = "Model not allowed or over the cost limit", body = ErrorBody),
+        (status = 502, description = "OpenRouter call failed", body = ErrorBody),
+        (status = 503, description = "OPENROUTER_API_KEY not configured", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+pub async fn chat_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<ChatRequest>,
+) -> Result<Json<ChatResponse>, ApiError> {
+    crate::http::check_auth(&state, &headers)?;
+    let state = state.for_caller(&headers);
+    Ok(Json(run_chat(&state, request, None).await?))
+}
+
+#[utoipa::path(
+    post, path = "/chat/estimate", tag = "chat",
+    request_body = ChatRequest,
+    responses(
+        (status = 200, description = "Projected cost of the first model call per model, without calling OpenRouter", body = ChatEstimate),
+        (status = 400, description = "max_tokens or temperature out of range", body = ErrorBody),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+        (status = 403, description = "Model not allowed", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+pub async fn estimate_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<ChatRequest>,
+) -> Result<Json<ChatEstimate>, ApiError> {
+    crate::http::check_auth(&state, &headers)?;
+    let state = state.for_caller(&headers);
+    Ok(Json(estimate(&state, &request).await?))
+}
+
+/// The system prompt, with the findings format in structured mode
+fn system_prompt(config: &Config, format: ResponseFormat) -> String {
+    let mut prompt = build_system_prompt(config);
+    if format == ResponseFormat::Structured {
+        prompt.push_str(STRUCTURED_INSTRUCTIONS);
+    }
+    prompt
+}
+
+/// Build the prompt `/chat` would send first and price it, so an expensive
+/// question can be checked before it is asked
+async fn estimate(state: &AppState, request: &ChatRequest) -> Result<ChatEstimate, ChatError> {
+    let config = state.config.current();
+    let sampling = Sampling::resolve(&config, request)?;
+    let requested = request
+        .model
+        .clone()
+        .unwrap_or_else(|| config.openrouter_model.clone());
+    check_model(&config, &requested)?;
+
+    let (context, _) = chat_context(state).await;
+    let messages = first_messages(
+        system_prompt(&config, request.response_format),
+        &context,
+        &request.message,
+        redact::chat_rules(&config).as_ref(),
+    );
+    let tools = allowed_tools(state);
+    let tools_json = serde_json::to_string(&tools).unwrap_or_default();
+    Ok(ChatEstimate {
+        max_tokens: sampling.max_tokens,
+        cost_limit_usd: config.chat_max_cost_usd,
+        models: model_estimates(
+            &config,
+            &state.pricing,
+            requested,
+            &prompt_parts(&messages, &tools_json),
+            sampling.max_tokens,
+        ),
+    })
+}
+
+/// Answer one question, running the tool loop until the model replies.
+/// With `events`, tokens and tool progress are reported as they happen.
+pub async fn run_chat(
+    state: &AppState,
+    request: ChatRequest,
+    events: Option<&ChatEvents>,
+) -> Result<ChatResponse, ChatError> {
+    let start = Instant::now();
+
+    // Check if OpenRouter is configured
+    let config = state.config.current();
+    let api_key = config
+        .openrouter_api_key
+        .as_ref()
+        .ok_or_else(|| ChatError::Config("OPENROUTER_API_KEY not configured".to_string()))?;
+
+    let sampling = Sampling::resolve(&config, &request)?;
+    let model = request
+        .model
+        .unwrap_or_else(|| config.openrouter_model.clone());
+    check_model(&config, &model)?;
+
+    let (initial_context, log_summary) = chat_context(state).await;
+    let system_prompt = system_prompt(&config, request.response_format);
+
+    let request_id = current_request_id();
+    let principal = tls::current_principal();
+    let conversation_id = request
+        .conversation_id
+        .clone()
+        .or_else(|| request_id.clone())
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+
+    let tools = allowed_tools(state);
+    let tools_json = serde_json::to_string(&tools).unwrap_or_default();
+
+    // Same question against the same error picture: reuse the last answer.
+    // Callers offered other tools could get other answers, so they're keyed too.
+    let cache_ttl = Duration::from_secs(config.chat_cache_ttl_secs);
+    let cache_key = cache_key(
+        &request.message,
+        &model,
+        sampling,
+        &system_prompt,
+        &tools_json,
+        &log_summary,
+    );
+    if !cache_ttl.is_zero() {
+        if let Some(cached) = state.chat_cache.get(cache_key, cache_ttl) {
+            info!(model = %cached.model, "Chat answered from cache");
+            if let Some(events) = events {
+                let _ = events.send(ChatEvent::Token {
+                    text: cached.response.clone(),
+                });
+            }
+            return Ok(ChatResponse {
+                usage: None,
+                cost: None,
+                processing_time_ms: start.elapsed().as_millis() as u64,
+                request_id,
+                conversation_id,
+                cached: true,
+                ..cached
+            });
+        }
+    }
+
+    // Cached answers are free; anything else counts against the budget
+    check_budget(&config, &state.usage_tracker).await?;
 
-    let client = OpenRouterClient::new(api_key.clone());
-    let tools = get_tools();
+    // In PII-safe mode the provider only ever sees scrubbed text
+    let pii_rules = redact::chat_rules(&config);
+    let scrub = |text: String| scrub_for_provider(pii_rules.as_ref(), text);
+
+    let mut messages = first_messages(
+        system_prompt,
+        &initial_context,
+        &request.message,
+        pii_rules.as_ref(),
+    );
+
+    let (mut models, downgraded_from) = guard_cost(
+        &config,
+        &state.pricing,
+        model,
+        &prompt_parts(&messages, &tools_json),
+        sampling.max_tokens,
+    )?;
+    let model = models[0].clone();
+
+    let client = OpenRouterClient::new(
+        api_key.clone(),
+        request_id.clone(),
+        config.openrouter_max_retries,
+        sampling,
+    )
+    .with_base_url(&config.openrouter_base_url)
+    .with_failure_rate(config.chaos_openrouter_failure_rate);
+    let mut upstream_ids: Vec<String> = Vec::new();
     let mut tools_called: Vec<String> = Vec::new();
+    let mut repairs = 0;
+    // What the calls so far cost, for the cost guard on the next one
+    let mut spent = 0.0;
 
     info!(
         model = %model,
@@ -404,126 +1558,508 @@ pub async fn chat_handler(
 
     // Tool loop
     for iteration in 0..MAX_TOOL_ITERATIONS {
+        if iteration > 0 {
+            check_cost(
+                &config,
+                &state.pricing,
+                &models,
+                &prompt_parts(&messages, &tools_json),
+                sampling.max_tokens,
+                spent,
+            )?;
+        }
         let response = client
-            .chat(&model, messages.clone(), Some(tools.clone()))
+            .chat(&mut models, messages.clone(), Some(tools.clone()), events)
             .await
             .map_err(|e| {
                 error!(error = ?e, "OpenRouter API call failed");
-                e.into_response()
+                e
             })?;
-
-        let choice = response.choices.first().ok_or_else(|| {
-            ChatError::Parse("No choices in response".to_string()).into_response()
-        })?;
+        upstream_ids.extend(response.id.clone());
+
+        let choice = response
+            .choices
+            .first()
+            .ok_or_else(|| ChatError::Parse("No choices in response".to_string()))?;
+        if config.chat_max_cost_usd.is_some() {
+            let usage = token_usage(
+                response.usage.as_ref(),
+                &response.model,
+                &prompt_parts(&messages, &tools_json),
+                choice.message.content.as_deref().unwrap_or_default(),
+            );
+            spent += state
+                .pricing
+                .for_model(&config, &response.model)
+                .calculate_cost(usage.prompt_tokens, usage.completion_tokens)
+                .total_cost_usd;
+        }
 
         // Check if the model wants to call tools
-        if let Some(ref tool_calls) = choice.message.tool_calls {
-            if tool_calls.is_empty() {
-                // No more tools to call, return the response
-                let response_text = choice.message.content.clone().unwrap_or_default();
-                let usage = response.usage.map(|u| TokenUsage {
-                    prompt_tokens: u.prompt_tokens,
-                    completion_tokens: u.completion_tokens,
-                    total_tokens: u.total_tokens,
-                });
-                let cost = usage.as_ref().map(|u| {
-                    ModelPricing::for_model(&response.model)
-                        .calculate_cost(u.prompt_tokens, u.completion_tokens)
-                });
-                let processing_time_ms = start.elapsed().as_millis() as u64;
-
-                // Record usage for persistence
-                if let Some(ref c) = cost {
-                    state.usage_tracker.record(&response.model, c, processing_time_ms, &tools_called).await;
-                }
-
-                return Ok(Json(ChatResponse {
-                    response: response_text,
-                    model: response.model,
-                    usage,
-                    cost,
-                    tools_called,
-                    processing_time_ms,
-                }));
-            }
-
-            // Add assistant message with tool calls
-            messages.push(Message {
-                role: "assistant".to_string(),
-                content: choice.message.content.clone(),
-                tool_calls: Some(tool_calls.clone()),
-                tool_call_id: None,
-            });
-
-            // Execute each tool call
-            for tool_call in tool_calls {
-                let tool_name = &tool_call.function.name;
-                let tool_args = &tool_call.function.arguments;
-
-                info!(
-                    tool = %tool_name,
-                    iteration = iteration,
-                    "Executing tool call"
-                );
-
-                tools_called.push(format!("{}({})", tool_name, tool_args));
-
-                let result = execute_tool(
-                    tool_name,
-                    tool_args,
-                    &state.log_buffer,
-                    &state.metrics,
-                    state.start_time,
-                )
-                .await
-                .unwrap_or_else(|e| format!("Error: {}", e));
-
-                // Add tool result message
-                messages.push(Message {
-                    role: "tool".to_string(),
-                    content: Some(result),
-                    tool_calls: None,
-                    tool_call_id: Some(tool_call.id.clone()),
-                });
-            }
-        } else {
+        let Some(tool_calls) = choice
+            .message
+            .tool_calls
+            .as_ref()
+            .filter(|calls| !calls.is_empty())
+        else {
             // No tool calls, return the final response
             let response_text = choice.message.content.clone().unwrap_or_default();
+            if choice.finish_reason.as_deref() == Some("length") {
+                warn!(model = %response.model, "Answer was cut off at max_tokens");
+            }
+            let findings = match request.response_format {
+                ResponseFormat::Text => None,
+                ResponseFormat::Structured => {
+                    match analysis::validate(&state.log_buffer, &response_text).await {
+                        Ok(findings) => Some(findings),
+                        Err(problem) if repairs < MAX_REPAIRS => {
+                            warn!(problem = %problem, "Structured answer rejected, asking again");
+                            repairs += 1;
+                            messages.push(Message {
+                                role: "assistant".to_string(),
+                                content: Some(response_text),
+                                tool_calls: None,
+                                tool_call_id: None,
+                            });
+                            messages.push(Message {
+                                role: "user".to_string(),
+                                content: Some(analysis::repair_prompt(&problem)),
+                                tool_calls: None,
+                                tool_call_id: None,
+                            });
+                            continue;
+                        }
+                        Err(problem) => {
+                            state.transcripts.record(
+                                &config,
+                                TranscriptEvent {
+                                    conversation_id: &conversation_id,
+                                    request_id: request_id.as_deref(),
+                                    model: &response.model,
+                                    messages: transcript_messages(&messages),
+                                    outcome: Err(&problem),
+                                },
+                            );
+                            return Err(ChatError::Parse(format!(
+                                "invalid structured answer: {}",
+                                problem
+                            )));
+                        }
+                    }
+                }
+            };
 
+            let usage = Some(token_usage(
+                response.usage.as_ref(),
+                &response.model,
+                &prompt_parts(&messages, &tools_json),
+                &response_text,
+            ));
+            let cost = usage.as_ref().map(|u| {
+                state
+                    .pricing
+                    .for_model(&config, &response.model)
+                    .calculate_cost(u.prompt_tokens, u.completion_tokens)
+            });
             info!(
                 model = %response.model,
                 tools_called = tools_called.len(),
+                cost = %cost.as_ref().map(|c| c.format_cost()).unwrap_or_default(),
                 processing_time_ms = start.elapsed().as_millis(),
                 "Chat request completed"
             );
-
-            let usage = response.usage.map(|u| TokenUsage {
-                prompt_tokens: u.prompt_tokens,
-                completion_tokens: u.completion_tokens,
-                total_tokens: u.total_tokens,
-            });
-            let cost = usage.as_ref().map(|u| {
-                ModelPricing::for_model(&response.model)
-                    .calculate_cost(u.prompt_tokens, u.completion_tokens)
-            });
             let processing_time_ms = start.elapsed().as_millis() as u64;
 
             // Record usage for persistence
             if let Some(ref c) = cost {
-                state.usage_tracker.record(&response.model, c, processing_time_ms, &tools_called).await;
+                state
+                    .usage_tracker
+                    .record(UsageEvent {
+                        model: &response.model,
+                        cost: c,
+                        processing_time_ms,
+                        tools_called: &tools_called,
+                        request_id: request_id.as_deref(),
+                        upstream_ids: &upstream_ids,
+                        conversation_id: Some(&conversation_id),
+                    })
+                    .await;
             }
 
-            return Ok(Json(ChatResponse {
+            state.transcripts.record(
+                &config,
+                TranscriptEvent {
+                    conversation_id: &conversation_id,
+                    request_id: request_id.as_deref(),
+                    model: &response.model,
+                    messages: transcript_messages(&messages),
+                    outcome: Ok(&response_text),
+                },
+            );
+
+            let chat_response = ChatResponse {
                 response: response_text,
                 model: response.model,
                 usage,
                 cost,
                 tools_called,
                 processing_time_ms,
-            }));
+                request_id,
+                conversation_id,
+                downgraded_from,
+                cached: false,
+                findings,
+            };
+            if !cache_ttl.is_zero() {
+                state
+                    .chat_cache
+                    .insert(cache_key, chat_response.clone(), cache_ttl);
+            }
+            return Ok(chat_response);
+        };
+
+        // Add assistant message with tool calls
+        messages.push(Message {
+            role: "assistant".to_string(),
+            content: choice.message.content.clone(),
+            tool_calls: Some(tool_calls.clone()),
+            tool_call_id: None,
+        });
+
+        // Execute each tool call
+        for tool_call in tool_calls {
+            let tool_name = &tool_call.function.name;
+            let tool_args = &tool_call.function.arguments;
+
+            info!(
+                tool = %tool_name,
+                iteration = iteration,
+                "Executing tool call"
+            );
+
+            tools_called.push(format!("{}({})", tool_name, tool_args));
+            if let Some(events) = events {
+                let _ = events.send(ChatEvent::ToolCall {
+                    name: tool_name.clone(),
+                    arguments: tool_args.clone(),
+                });
+            }
+
+            let started = Instant::now();
+            let result = execute_tool(tool_name, tool_args, state).await;
+            let duration_ms = started.elapsed().as_millis() as u64;
+            state.tool_audit.record(ToolCallEvent {
+                conversation_id: &conversation_id,
+                request_id: request_id.as_deref(),
+                principal: principal.as_deref(),
+                tool: tool_name,
+                arguments: tool_args,
+                result: &result,
+                duration_ms,
+            });
+            if let Some(events) = events {
+                let _ = events.send(ChatEvent::ToolResult {
+                    name: tool_name.clone(),
+                    ok: result.is_ok(),
+                    duration_ms,
+                });
+            }
+            let result = result.unwrap_or_else(|e| format!("Error: {}", e));
+
+            // Add tool result message
+            messages.push(Message {
+                role: "tool".to_string(),
+                content: Some(scrub(result)),
+                tool_calls: None,
+                tool_call_id: Some(tool_call.id.clone()),
+            });
         }
     }
 
     warn!("Max tool iterations exceeded");
-    Err(ChatError::MaxIterations.into_response())
+    state.transcripts.record(
+        &config,
+        TranscriptEvent {
+            conversation_id: &conversation_id,
+            request_id: request_id.as_deref(),
+            model: models.first().unwrap_or(&model),
+            messages: transcript_messages(&messages),
+            outcome: Err("Max tool iterations exceeded"),
+        },
+    );
+    Err(ChatError::MaxIterations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    const SAMPLING: Sampling = Sampling {
+        max_tokens: 4096,
+        temperature: 0.3,
+    };
+
+    #[test]
+    fn test_retry_delay_is_jittered_and_capped() {
+        for attempt in 0..3 {
+            let step = RETRY_BACKOFF_INITIAL * 2u32.pow(attempt);
+            let delay = retry_delay(attempt);
+            assert!(delay >= step / 2 && delay <= step, "{:?}", delay);
+        }
+        assert!(retry_delay(10) <= RETRY_BACKOFF_MAX);
+    }
+
+    #[tokio::test]
+    async fn test_chat_falls_back_after_retries() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        // "primary" is always overloaded; anything else answers
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let app = axum::Router::new().route(
+            "/chat/completions",
+            axum::routing::post(move |Json(body): Json<serde_json::Value>| {
+                let counter = counter.clone();
+                async move {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    let model = body["model"].as_str().unwrap_or_default().to_string();
+                    if model == "primary" {
+                        return Err(axum::http::StatusCode::SERVICE_UNAVAILABLE);
+                    }
+                    Ok(Json(serde_json::json!({
+                        "choices": [{"message": {"role": "assistant", "content": "ok"}}],
+                        "model": model,
+                    })))
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let mut client = OpenRouterClient::new("key".to_string(), None, 1, SAMPLING);
+        client.base_url = format!("http://{}", addr);
+        let mut models = vec!["primary".to_string(), "backup".to_string()];
+
+        let response = client
+            .chat(&mut models, Vec::new(), None, None)
+            .await
+            .unwrap();
+        assert_eq!(response.model, "backup");
+        // Two tries on primary, one on backup
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        assert_eq!(models, vec!["backup".to_string()]);
+
+        // Injected failures go through the same retry and fallback path
+        let mut client =
+            OpenRouterClient::new("key".to_string(), None, 1, SAMPLING).with_failure_rate(1.0);
+        client.base_url = format!("http://{}", addr);
+        let mut models = vec!["primary".to_string(), "backup".to_string()];
+        let err = client
+            .chat(&mut models, Vec::new(), None, None)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ChatError::Unavailable(_)));
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_streamed_answer_is_reassembled() {
+        let body = concat!(
+            ": OPENROUTER PROCESSING\n\n",
+            r#"data: {"id":"gen-1","model":"m","choices":[{"delta":{"content":"No "}}]}"#,
+            "\n\n",
+            r#"data: {"choices":[{"delta":{"content":"errors."}}]}"#,
+            "\n\n",
+            r#"data: {"choices":[{"delta":{"tool_calls":[{"index":0,"id":"call-1","#,
+            r#""function":{"name":"get_logs","arguments":"{\"count\""}}]}}]}"#,
+            "\n\n",
+            r#"data: {"choices":[{"delta":{"tool_calls":[{"index":0,"#,
+            r#""function":{"arguments":":5}"}}]},"finish_reason":"tool_calls"}],"#,
+            r#""usage":{"prompt_tokens":10,"completion_tokens":4,"total_tokens":14}}"#,
+            "\n\n",
+            "data: [DONE]\n\n",
+        );
+        let app = axum::Router::new().route(
+            "/chat/completions",
+            axum::routing::post(move || async move { body }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let mut client = OpenRouterClient::new("key".to_string(), None, 0, SAMPLING);
+        client.base_url = format!("http://{}", addr);
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let mut models = vec!["m".to_string()];
+        let response = client
+            .chat(&mut models, Vec::new(), None, Some(&tx))
+            .await
+            .unwrap();
+
+        let message = &response.choices[0].message;
+        assert_eq!(message.content.as_deref(), Some("No errors."));
+        let calls = message.tool_calls.as_ref().unwrap();
+        assert_eq!(calls[0].id, "call-1");
+        assert_eq!(calls[0].function.name, "get_logs");
+        assert_eq!(calls[0].function.arguments, "{\"count\":5}");
+        assert_eq!(response.id.as_deref(), Some("gen-1"));
+        assert_eq!(response.usage.unwrap().total_tokens, 14);
+
+        let mut tokens = Vec::new();
+        while let Ok(ChatEvent::Token { text }) = rx.try_recv() {
+            tokens.push(text);
+        }
+        assert_eq!(tokens, ["No ", "errors."]);
+    }
+
+    #[test]
+    fn test_sampling_bounded_by_config() {
+        let config = Config::for_tests("chat_max_tokens = 1024\nchat_max_tokens_limit = 2048");
+        let request = |max_tokens, temperature| ChatRequest {
+            message: "hi".to_string(),
+            model: None,
+            conversation_id: None,
+            max_tokens,
+            temperature,
+            response_format: ResponseFormat::Text,
+        };
+
+        let defaults = Sampling::resolve(&config, &request(None, None)).unwrap();
+        assert_eq!(defaults.max_tokens, 1024);
+        assert_eq!(defaults.temperature, 0.3);
+
+        let custom = Sampling::resolve(&config, &request(Some(2048), Some(0.0))).unwrap();
+        assert_eq!((custom.max_tokens, custom.temperature), (2048, 0.0));
+
+        assert!(Sampling::resolve(&config, &request(Some(4096), None)).is_err());
+        assert!(Sampling::resolve(&config, &request(None, Some(2.5))).is_err());
+    }
+
+    #[test]
+    fn test_model_lists() {
+        let config = Config::for_tests(
+            r#"
+chat_model_allowlist = ["openai/*", "anthropic/claude-3-haiku"]
+chat_model_denylist = ["openai/gpt-4-turbo"]
+"#,
+        );
+        assert!(check_model(&config, "moonshotai/kimi-k2").is_ok());
+        assert!(check_model(&config, "openai/gpt-4o-mini").is_ok());
+        assert!(check_model(&config, "anthropic/claude-3-haiku").is_ok());
+        assert!(check_model(&config, "anthropic/claude-3-opus").is_err());
+        assert!(check_model(&config, "openai/gpt-4-turbo").is_err());
+    }
+
+    #[test]
+    fn test_tool_scopes() {
+        let config = Config::for_tests(
+            r#"
+chat_tool_scopes = ["oncall-bot=get_logs|get_metrics", "*=get_logs"]
+"#,
+        );
+        assert!(tool_allowed(&config, Some("oncall-bot"), "get_metrics"));
+        assert!(!tool_allowed(&config, Some("oncall-bot"), "get_trace"));
+        assert!(tool_allowed(&config, None, "get_logs"));
+        assert!(!tool_allowed(&config, Some("ci"), "get_metrics"));
+        // No entries: every tool for everyone
+        assert!(tool_allowed(&Config::for_tests(""), None, "get_trace"));
+
+        assert!("ci=get_logs|restart_machine".parse::<ToolScope>().is_err());
+        assert!("=get_logs".parse::<ToolScope>().is_err());
+        assert_eq!(
+            "ci=".parse::<ToolScope>().unwrap().tools,
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn test_cost_guard_rejects_or_downgrades() {
+        let config = Config::for_tests("chat_max_cost_usd = 0.5");
+        let pricing = PricingCatalog::default();
+        let opus = "anthropic/claude-3-opus".to_string();
+
+        let short = "word ".repeat(1_000);
+        let long = "word ".repeat(20_000);
+        let (models, downgraded) = guard_cost(&config, &pricing, opus.clone(), &[&short], 4096).unwrap();
+        assert_eq!((models[0].as_str(), downgraded), (opus.as_str(), None));
+        assert!(matches!(
+            guard_cost(&config, &pricing, opus.clone(), &[&long], 4096),
+            Err(ChatError::CostLimit(_))
+        ));
+
+        let config = Config::for_tests("chat_max_cost_usd = 0.5\nchat_cost_policy = \"downgrade\"");
+        let (models, downgraded) = guard_cost(&config, &pricing, opus.clone(), &[&long], 4096).unwrap();
+        assert_eq!(models, vec!["moonshotai/kimi-k2"]);
+        assert_eq!(downgraded, Some(opus));
+    }
+
+    #[test]
+    fn test_cost_guard_counts_earlier_calls() {
+        let config = Config::for_tests("chat_max_cost_usd = 0.5");
+        let pricing = PricingCatalog::default();
+        let opus = &["anthropic/claude-3-opus".to_string()];
+
+        let prompt = "word ".repeat(5_000);
+        assert!(check_cost(&config, &pricing, opus, &[&prompt], 4096, 0.0).is_ok());
+        assert!(matches!(
+            check_cost(&config, &pricing, opus, &[&prompt], 4096, 0.4),
+            Err(ChatError::CostLimit(_))
+        ));
+        // Tool results pile up in the prompt between calls
+        let grown = prompt.repeat(4);
+        assert!(check_cost(&config, &pricing, opus, &[&grown], 4096, 0.0).is_err());
+        assert!(check_cost(&Config::for_tests(""), &pricing, opus, &[&grown], 4096, 9.0).is_ok());
+    }
+
+    #[test]
+    fn test_cost_guard_prices_the_whole_chain() {
+        let pricing = PricingCatalog::default();
+        let long = "word ".repeat(20_000);
+        let kimi = "moonshotai/kimi-k2".to_string();
+
+        // The default model is cheap, but a failure could land on opus
+        let config = Config::for_tests(
+            "chat_max_cost_usd = 0.5\nopenrouter_fallback_models = [\"anthropic/claude-3-opus\"]",
+        );
+        let Err(ChatError::CostLimit(message)) =
+            guard_cost(&config, &pricing, kimi.clone(), &[&long], 4096)
+        else {
+            panic!("expected the fallback to trip the cost limit");
+        };
+        assert!(message.contains("claude-3-opus"), "{}", message);
+
+        // Fallbacks outside the allowlist are dropped from the chain
+        let config = Config::for_tests(
+            "chat_max_cost_usd = 0.5\nopenrouter_fallback_models = [\"anthropic/claude-3-opus\", \
+             \"openai/gpt-4o-mini\"]\nchat_model_denylist = [\"anthropic/*\"]",
+        );
+        let (models, _) = guard_cost(&config, &pricing, kimi.clone(), &[&long], 4096).unwrap();
+        assert_eq!(models, vec![kimi, "openai/gpt-4o-mini".to_string()]);
+    }
+
+    #[test]
+    fn test_estimates_cover_fallbacks() {
+        let config = Config::for_tests(
+            "chat_max_cost_usd = 0.5\nopenrouter_fallback_models = [\"openai/gpt-4o-mini\"]",
+        );
+        let long = "word ".repeat(20_000);
+        let estimates = model_estimates(
+            &config,
+            &PricingCatalog::default(),
+            "anthropic/claude-3-opus".to_string(),
+            &[&long],
+            4096,
+        );
+        let models: Vec<&str> = estimates.iter().map(|e| e.model.as_str()).collect();
+        assert_eq!(
+            models,
+            vec!["anthropic/claude-3-opus", "moonshotai/kimi-k2", "openai/gpt-4o-mini"]
+        );
+        assert!(estimates[0].over_limit);
+        assert!(!estimates[1].over_limit);
+        assert!(estimates[0].prompt_tokens > 10_000);
+        assert!(estimates[0].prompt_cost_usd < estimates[0].max_cost_usd);
+    }
 }
@@ -0,0 +1,133 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::chat::{ChatResponse, Sampling};
+use crate::log_buffer::LogSummary;
+
+/// Entries kept before the oldest are evicted
+const MAX_ENTRIES: usize = 256;
+
+/// Short-lived cache of chat answers, so a dashboard asking the same
+/// question every few seconds is answered without another model call while
+/// the log buffer hasn't materially changed
+#[derive(Default)]
+pub struct ChatCache {
+    entries: Mutex<HashMap<u64, (Instant, ChatResponse)>>,
+}
+
+impl ChatCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, key: u64, ttl: Duration) -> Option<ChatResponse> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .get(&key)
+            .filter(|(stored, _)| stored.elapsed() < ttl)
+            .map(|(_, response)| response.clone())
+    }
+
+    pub fn insert(&self, key: u64, response: ChatResponse, ttl: Duration) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, (stored, _)| stored.elapsed() < ttl);
+        if entries.len() >= MAX_ENTRIES {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, (stored, _))| *stored)
+                .map(|(key, _)| *key);
+            if let Some(oldest) = oldest {
+                entries.remove(&oldest);
+            }
+        }
+        entries.insert(key, (Instant::now(), response));
+    }
+}
+
+/// Key for a question asked against the current buffer. Only the error and
+/// warning picture goes into the fingerprint: a steady trickle of info logs
+/// doesn't change the answer to "any errors right now?".
+pub fn cache_key(
+    question: &str,
+    model: &str,
+    sampling: Sampling,
+    system_prompt: &str,
+    tools: &str,
+    summary: &LogSummary,
+) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    normalize_question(question).hash(&mut hasher);
+    model.hash(&mut hasher);
+    sampling.max_tokens.hash(&mut hasher);
+    sampling.temperature.to_bits().hash(&mut hasher);
+    system_prompt.hash(&mut hasher);
+    tools.hash(&mut hasher);
+    summary.error_count.hash(&mut hasher);
+    summary.warn_count.hash(&mut hasher);
+    summary.recent_errors.hash(&mut hasher);
+    summary.active_instances.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Lowercase, collapse whitespace and drop trailing punctuation
+fn normalize_question(question: &str) -> String {
+    question
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .trim_end_matches(['?', '!', '.'])
+        .to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::log_buffer::LogBuffer;
+    use crate::source::fly_envelope;
+
+    fn key(question: &str, model: &str, temperature: f32, summary: &LogSummary) -> u64 {
+        let sampling = Sampling {
+            max_tokens: 4096,
+            temperature,
+        };
+        cache_key(question, model, sampling, "p", "t", summary)
+    }
+
+    async fn push(buffer: &LogBuffer, level: &str, instances: &[&str]) {
+        for instance in instances {
+            let raw = fly_envelope("x", Some(level), Some(instance), Some("ord"));
+            buffer.push(raw).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_key_ignores_phrasing_noise_and_info_volume() {
+        let buffer = LogBuffer::new(Default::default(), None);
+        let instances = ["abc123", "def456", "0a1b2c", "ffee99", "77aa00", "123abc"];
+        push(&buffer, "error", &instances).await;
+        let base = key(
+            "Any errors right now?",
+            "m",
+            0.3,
+            &buffer.get_summary().await,
+        );
+        // Instances come back in the same order on every summary
+        for _ in 0..10 {
+            let summary = buffer.get_summary().await;
+            assert_eq!(base, key("Any errors right now?", "m", 0.3, &summary));
+        }
+
+        push(&buffer, "info", &instances).await;
+        let summary = buffer.get_summary().await;
+        assert_eq!(base, key("  any errors   right now ", "m", 0.3, &summary));
+        assert_ne!(base, key("Any errors right now?", "other", 0.3, &summary));
+        assert_ne!(base, key("Any errors right now?", "m", 1.0, &summary));
+
+        push(&buffer, "error", &instances[..1]).await;
+        let summary = buffer.get_summary().await;
+        assert_ne!(base, key("Any errors right now?", "m", 0.3, &summary));
+    }
+}
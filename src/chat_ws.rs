@@ -0,0 +1,183 @@
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Query, State,
+    },
+    http::HeaderMap,
+    response::Response,
+};
+use futures::{SinkExt, StreamExt};
+use serde::Serialize;
+use std::time::Instant;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+
+use crate::chat::{run_chat, ChatEvent, ChatRequest, ChatResponse};
+use crate::error::{ApiError, ErrorBody};
+use crate::http::{
+    shutting_down, AppState, WsClose, WS_MAX_FRAME_SIZE, WS_PING_INTERVAL, WS_PONG_TIMEOUT,
+};
+use crate::tls;
+use crate::ws_auth::{self, TicketQuery, WsAuth};
+
+/// Frames that end a question; progress frames are `ChatEvent`s
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Outcome {
+    /// The full answer with usage and cost, same shape as POST /chat
+    Done(Box<ChatResponse>),
+    Error {
+        code: &'static str,
+        message: String,
+    },
+}
+
+/// Interactive chat over one socket. Each text frame is a ChatRequest; the
+/// server answers with `token`, `tool_call` and `tool_result` frames while it
+/// works and a final `done` (or `error`) frame. Questions on the same socket
+/// share a conversation id unless the client sends its own.
+#[utoipa::path(
+    get, path = "/chat/ws", tag = "chat",
+    params(TicketQuery),
+    responses(
+        (status = 101, description = "WebSocket upgrade; send ChatRequest frames, receive token, tool_call, tool_result and done frames"),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+pub async fn chat_ws_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(ticket): Query<TicketQuery>,
+    ws: WebSocketUpgrade,
+) -> Result<Response, ApiError> {
+    let auth = ws_auth::authorize(&state, &headers, &ticket)?;
+    let state = state.for_caller(&headers);
+    Ok(auth
+        .upgrade(ws)
+        .max_frame_size(WS_MAX_FRAME_SIZE)
+        .on_upgrade(move |socket| handle_chat_socket(socket, state, auth)))
+}
+
+fn send_frame(out: &mpsc::UnboundedSender<String>, frame: &impl Serialize) {
+    match serde_json::to_string(frame) {
+        Ok(json) => {
+            let _ = out.send(json);
+        }
+        Err(e) => error!(error = %e, "Failed to serialize chat frame"),
+    }
+}
+
+fn error_frame(out: &mpsc::UnboundedSender<String>, error: ApiError) {
+    send_frame(
+        out,
+        &Outcome::Error {
+            code: error.code(),
+            message: error.to_string(),
+        },
+    );
+}
+
+async fn handle_chat_socket(socket: WebSocket, state: AppState, auth: WsAuth) {
+    let connection_id = uuid::Uuid::new_v4();
+    info!(connection_id = %connection_id, "WebSocket client connected for chat");
+
+    let (mut sender, mut receiver) = socket.split();
+    let (out_tx, mut out_rx) = mpsc::unbounded_channel::<String>();
+    let mut in_flight: Option<JoinHandle<()>> = None;
+    let mut ping_interval = tokio::time::interval(WS_PING_INTERVAL);
+    let mut last_pong = Instant::now();
+    let mut shutdown = state.shutdown.subscribe();
+
+    let close = loop {
+        tokio::select! {
+            _ = shutting_down(&mut shutdown) => break Some(WsClose::GoingAway),
+
+            _ = ping_interval.tick() => {
+                if last_pong.elapsed() > WS_PING_INTERVAL + WS_PONG_TIMEOUT {
+                    warn!(connection_id = %connection_id, "Chat WebSocket pong timeout");
+                    break Some(WsClose::PongTimeout);
+                }
+                if !auth.still_valid(&state) {
+                    break Some(WsClose::PolicyViolation);
+                }
+                if sender.send(Message::Ping(vec![])).await.is_err() {
+                    break None;
+                }
+            }
+
+            Some(frame) = out_rx.recv() => {
+                if sender.send(Message::Text(frame)).await.is_err() {
+                    break None;
+                }
+            }
+
+            incoming = receiver.next() => match incoming {
+                Some(Ok(Message::Text(text))) => {
+                    if in_flight.as_ref().is_some_and(|task| !task.is_finished()) {
+                        error_frame(
+                            &out_tx,
+                            ApiError::InvalidRequest(
+                                "A question is already being answered".to_string(),
+                            ),
+                        );
+                        continue;
+                    }
+                    match serde_json::from_str::<ChatRequest>(&text) {
+                        Ok(mut request) => {
+                            request
+                                .conversation_id
+                                .get_or_insert_with(|| connection_id.to_string());
+                            in_flight = Some(tokio::spawn(tls::with_principal(
+                                auth.principal(),
+                                answer(state.clone(), request, out_tx.clone()),
+                            )));
+                        }
+                        Err(e) => error_frame(
+                            &out_tx,
+                            ApiError::InvalidRequest(format!("Invalid chat request: {}", e)),
+                        ),
+                    }
+                }
+                Some(Ok(Message::Pong(_))) => last_pong = Instant::now(),
+                Some(Ok(Message::Close(_))) => break Some(WsClose::Normal),
+                None => break None,
+                Some(Ok(_)) => {}
+                Some(Err(e)) => {
+                    warn!(connection_id = %connection_id, error = %e, "Chat WebSocket receive error");
+                    break None;
+                }
+            }
+        }
+    };
+
+    // Nobody is left to read the answer
+    if let Some(task) = in_flight {
+        task.abort();
+    }
+    if let Some(close) = close {
+        let _ = sender.send(close.message()).await;
+    }
+    info!(connection_id = %connection_id, "Chat WebSocket client disconnected");
+}
+
+async fn answer(state: AppState, request: ChatRequest, out: mpsc::UnboundedSender<String>) {
+    let (events_tx, mut events_rx) = mpsc::unbounded_channel::<ChatEvent>();
+    let progress_out = out.clone();
+    let progress = tokio::spawn(async move {
+        while let Some(event) = events_rx.recv().await {
+            send_frame(&progress_out, &event);
+        }
+    });
+
+    let result = run_chat(&state, request, Some(&events_tx)).await;
+    // Flush progress frames before the outcome
+    drop(events_tx);
+    let _ = progress.await;
+
+    match result {
+        Ok(response) => send_frame(&out, &Outcome::Done(Box::new(response))),
+        Err(e) => error_frame(&out, e.into()),
+    }
+}
@@ -0,0 +1,578 @@
+//! Terminal companions to the server. `flywatch tail|query|chat|usage` talk
+//! to a running instance over its HTTP API, so operators don't need curl and
+//! jq; `flywatch mcp` relays MCP over stdio and `flywatch loadgen` drives
+//! synthetic traffic. Any other invocation starts the server.
+
+use chrono::DateTime;
+use flywatch_core::loadgen::LogGenerator;
+use futures::StreamExt;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::sync::broadcast;
+
+use crate::log_buffer::{LogBuffer, LogBufferConfig, LogMessage};
+use crate::mcp;
+
+const USAGE: &str = "\
+Usage: flywatch [--config <path>]          run the server
+       flywatch <command> [options]         talk to a running server
+
+Commands:
+  tail   [--lines N] [filters]              print recent lines, then follow new ones
+  query  [--limit N] [--before RFC3339] [--cursor C] [filters]
+                                            print one page of buffered history
+  chat   [--model M] [--conversation ID] [question...]
+                                            ask about the logs (interactive without a question)
+  usage                                     print chat token usage and cost
+  mcp                                       relay MCP between stdio and the server
+  loadgen [--rate N] [--seconds S] [--instances N] [--seed N] [--webhook URL]
+                                            synthesize Fly log lines into an in-process
+                                            buffer, or into a server's webhook source
+
+Filters: --level L  --instance I  --search TEXT  --view NAME
+
+Options:
+  --url URL      server address (FLYWATCH_URL, default http://127.0.0.1:8080)
+  --token TOKEN  bearer token (AUTH_TOKEN)
+  --json         print JSON instead of text
+";
+
+/// Fields the log commands ask for
+const LOG_FIELDS: &str = "seq timestamp level instance message raw";
+
+/// Flags that don't take a value
+const SWITCHES: [&str; 2] = ["json", "help"];
+
+#[derive(Debug, Default)]
+struct Args {
+    positional: Vec<String>,
+    flags: HashMap<String, String>,
+}
+
+impl Args {
+    fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut parsed = Self::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let Some(flag) = arg.strip_prefix("--") else {
+                parsed.positional.push(arg);
+                continue;
+            };
+            let (name, value) = match flag.split_once('=') {
+                Some((name, value)) => (name.to_string(), value.to_string()),
+                None if SWITCHES.contains(&flag) => (flag.to_string(), String::new()),
+                None => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| format!("--{} needs a value", flag))?;
+                    (flag.to_string(), value)
+                }
+            };
+            parsed.flags.insert(name, value);
+        }
+        Ok(parsed)
+    }
+
+    fn get(&self, name: &str) -> Option<&str> {
+        self.flags.get(name).map(String::as_str)
+    }
+
+    fn has(&self, name: &str) -> bool {
+        self.flags.contains_key(name)
+    }
+
+    fn number(&self, name: &str, default: u64) -> Result<u64, String> {
+        self.get(name).map_or(Ok(default), |v| {
+            v.parse()
+                .map_err(|_| format!("--{} must be a number", name))
+        })
+    }
+
+    /// `logs(...)` arguments from the filter flags and `extra` names
+    fn variables(&self, extra: &[&str]) -> Map<String, Value> {
+        ["level", "instance", "search", "view"]
+            .iter()
+            .chain(extra)
+            .filter_map(|name| Some((name.to_string(), Value::from(self.get(name)?))))
+            .collect()
+    }
+}
+
+struct Client {
+    http: reqwest::Client,
+    url: String,
+    token: Option<String>,
+}
+
+impl Client {
+    fn new(args: &Args) -> Self {
+        let env = |name: &str| std::env::var(name).ok().filter(|s| !s.is_empty());
+        let url = args
+            .get("url")
+            .map(str::to_string)
+            .or_else(|| env("FLYWATCH_URL"))
+            .unwrap_or_else(|| "http://127.0.0.1:8080".to_string());
+        Self {
+            http: reqwest::Client::new(),
+            url: url.trim_end_matches('/').to_string(),
+            token: args
+                .get("token")
+                .map(str::to_string)
+                .or_else(|| env("AUTH_TOKEN")),
+        }
+    }
+
+    fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        let request = self.http.request(method, format!("{}{}", self.url, path));
+        match &self.token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+
+    /// Send and decode JSON, turning API errors into their message
+    async fn send(&self, request: reqwest::RequestBuilder) -> Result<reqwest::Response, String> {
+        let response = request
+            .send()
+            .await
+            .map_err(|e| format!("{}: {}", self.url, e))?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+        let body: Value = response.json().await.unwrap_or_default();
+        Err(match body["error"].as_str() {
+            Some(error) => format!("{} ({})", error, status),
+            None => status.to_string(),
+        })
+    }
+
+    async fn json(&self, request: reqwest::RequestBuilder) -> Result<Value, String> {
+        self.send(request)
+            .await?
+            .json()
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    async fn graphql(&self, query: &str, variables: Map<String, Value>) -> Result<Value, String> {
+        let request = self
+            .request(reqwest::Method::POST, "/graphql")
+            .json(&json!({ "query": query, "variables": variables }));
+        graphql_data(self.json(request).await?)
+    }
+}
+
+fn graphql_data(mut body: Value) -> Result<Value, String> {
+    if let Some(errors) = body["errors"].as_array() {
+        let messages: Vec<&str> = errors
+            .iter()
+            .filter_map(|e| e["message"].as_str())
+            .collect();
+        return Err(messages.join("; "));
+    }
+    Ok(body["data"].take())
+}
+
+/// `2026-01-02 15:04:05 ERROR web-1  message`
+fn format_log(log: &Value) -> String {
+    let timestamp = log["timestamp"]
+        .as_str()
+        .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
+        .map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_default();
+    let level = log["level"].as_str().unwrap_or("-").to_uppercase();
+    let instance = log["instance"].as_str().unwrap_or("-");
+    let text = log["message"]
+        .as_str()
+        .or_else(|| log["raw"].as_str())
+        .unwrap_or_default();
+    format!("{} {:<5} {:<14} {}", timestamp, level, instance, text)
+}
+
+fn print_log(log: &Value, as_json: bool) {
+    if as_json {
+        println!("{}", log);
+    } else {
+        println!("{}", format_log(log));
+    }
+}
+
+/// One page of history, oldest line first; returns the newest sequence
+async fn query(client: &Client, args: &Args, limit: u64) -> Result<u64, String> {
+    let mut variables = args.variables(&["cursor", "before"]);
+    variables.insert("limit".to_string(), Value::from(limit));
+    let query = format!(
+        "query($level: String, $instance: String, $search: String, $view: String, \
+         $limit: Int, $cursor: String, $before: String) {{ \
+         logs(level: $level, instance: $instance, search: $search, view: $view, \
+         limit: $limit, cursor: $cursor, before: $before) {{ has_more next_cursor logs {{ {} }} }} }}",
+        LOG_FIELDS
+    );
+    let data = client.graphql(&query, variables).await?;
+    let page = &data["logs"];
+    let logs = page["logs"].as_array().cloned().unwrap_or_default();
+    for log in &logs {
+        print_log(log, args.has("json"));
+    }
+    if let Some(cursor) = page["next_cursor"].as_str() {
+        if args.has("limit") || args.has("cursor") {
+            eprintln!("more: --cursor {}", cursor);
+        }
+    }
+    Ok(logs
+        .iter()
+        .filter_map(|l| l["seq"].as_u64())
+        .max()
+        .unwrap_or(0))
+}
+
+/// Recent lines, then the live stream over a GraphQL subscription
+async fn tail(client: &Client, args: &Args) -> Result<(), String> {
+    let subscription = format!(
+        "subscription($level: String, $instance: String, $search: String, $view: String) {{ \
+         logs(level: $level, instance: $instance, search: $search, view: $view) {{ {} }} }}",
+        LOG_FIELDS
+    );
+    let request = client
+        .request(reqwest::Method::POST, "/graphql")
+        .json(&json!({ "query": subscription, "variables": args.variables(&[]) }));
+    // Subscribed before the backfill, so nothing falls between the two
+    let response = client.send(request).await?;
+    let is_stream = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("text/event-stream"));
+    if !is_stream {
+        let body: Value = response.json().await.map_err(|e| e.to_string())?;
+        return graphql_data(body).map(|_| ());
+    }
+
+    let lines = args.number("lines", 10)?;
+    let mut last_seq = if lines > 0 {
+        query(client, args, lines).await?
+    } else {
+        0
+    };
+
+    let mut body = response.bytes_stream();
+    let mut buffer = String::new();
+    while let Some(chunk) = body.next().await {
+        let chunk = chunk.map_err(|e| e.to_string())?;
+        buffer.push_str(&String::from_utf8_lossy(&chunk));
+        while let Some(end) = buffer.find("\n\n") {
+            let event: String = buffer.drain(..end + 2).collect();
+            let mut name = "";
+            let mut data = String::new();
+            for line in event.lines() {
+                if let Some(value) = line.strip_prefix("event:") {
+                    name = value.trim();
+                } else if let Some(value) = line.strip_prefix("data:") {
+                    data.push_str(value.trim_start());
+                }
+            }
+            if name != "next" {
+                continue;
+            }
+            let Ok(data) = serde_json::from_str::<Value>(&data) else {
+                continue;
+            };
+            let log = graphql_data(data)?["logs"].take();
+            let seq = log["seq"].as_u64().unwrap_or(0);
+            if seq > last_seq {
+                last_seq = seq;
+                print_log(&log, args.has("json"));
+            }
+        }
+    }
+    Err("stream closed by the server".to_string())
+}
+
+async fn ask(
+    client: &Client,
+    args: &Args,
+    question: &str,
+    conversation: &mut Option<String>,
+) -> Result<(), String> {
+    let mut body = json!({ "message": question });
+    if let Some(model) = args.get("model") {
+        body["model"] = json!(model);
+    }
+    if let Some(id) = conversation.as_ref() {
+        body["conversation_id"] = json!(id);
+    }
+    let response = client
+        .json(client.request(reqwest::Method::POST, "/chat").json(&body))
+        .await?;
+    if args.has("json") {
+        println!("{}", response);
+    } else {
+        println!("{}", response["response"].as_str().unwrap_or_default());
+        let cost = response["cost"]["total_cost_usd"]
+            .as_f64()
+            .map(|c| format!(", ${:.4}", c))
+            .unwrap_or_default();
+        eprintln!(
+            "({}, {} ms{})",
+            response["model"].as_str().unwrap_or("-"),
+            response["processing_time_ms"],
+            cost
+        );
+    }
+    *conversation = response["conversation_id"].as_str().map(str::to_string);
+    Ok(())
+}
+
+/// One question from the arguments, or a prompt loop on stdin
+async fn chat(client: &Client, args: &Args) -> Result<(), String> {
+    let mut conversation = args.get("conversation").map(str::to_string);
+    let question = args.positional[1..].join(" ");
+    if !question.is_empty() {
+        return ask(client, args, &question, &mut conversation).await;
+    }
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    loop {
+        eprint!("> ");
+        let _ = std::io::stderr().flush();
+        let Ok(Some(line)) = lines.next_line().await else {
+            return Ok(());
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Err(e) = ask(client, args, line, &mut conversation).await {
+            eprintln!("error: {}", e);
+        }
+    }
+}
+
+async fn usage(client: &Client, args: &Args) -> Result<(), String> {
+    let stats = client
+        .json(client.request(reqwest::Method::GET, "/usage"))
+        .await?;
+    if args.has("json") {
+        println!("{}", stats);
+        return Ok(());
+    }
+    println!(
+        "requests     {} ({} with tools)",
+        stats["total_requests"], stats["requests_with_tools"]
+    );
+    println!(
+        "tokens       {} ({} prompt, {} completion)",
+        stats["total_tokens"], stats["total_prompt_tokens"], stats["total_completion_tokens"]
+    );
+    println!(
+        "cost         ${:.4}",
+        stats["total_cost_usd"].as_f64().unwrap_or_default()
+    );
+    println!(
+        "avg latency  {:.0} ms",
+        stats["average_processing_time_ms"]
+            .as_f64()
+            .unwrap_or_default()
+    );
+    if let (Some(start), Some(end)) = (stats["period_start"].as_str(), stats["period_end"].as_str())
+    {
+        println!("period       {} to {}", start, end);
+    }
+    Ok(())
+}
+
+/// Batches per second loadgen paces itself in
+const LOADGEN_TICKS_PER_SECOND: u64 = 10;
+
+/// Synthesize `--rate` lines per second (0 = as fast as possible) for
+/// `--seconds`. Without `--webhook` they go through a buffer and broadcast
+/// channel sized like the server's, reporting push latency and stream lag.
+async fn loadgen(client: &Client, args: &Args) -> Result<(), String> {
+    let rate = args.number("rate", 1_000)?;
+    let seconds = args.number("seconds", 10)?;
+    let seed = args.number("seed", chrono::Utc::now().timestamp_millis() as u64)?;
+    let mut generator = LogGenerator::new(seed, args.number("instances", 4)? as usize);
+    let batch = match rate {
+        0 => 1_000,
+        rate => rate.div_ceil(LOADGEN_TICKS_PER_SECOND),
+    } as usize;
+
+    let buffer = LogBuffer::new(LogBufferConfig::default(), None);
+    let (tx, mut rx) = broadcast::channel::<LogMessage>(10_000);
+    let subscriber = tokio::spawn(async move {
+        let (mut received, mut lagged) = (0u64, 0u64);
+        loop {
+            match rx.recv().await {
+                Ok(_) => received += 1,
+                Err(broadcast::error::RecvError::Lagged(n)) => lagged += n,
+                Err(broadcast::error::RecvError::Closed) => return (received, lagged),
+            }
+        }
+    });
+
+    let mut ticker =
+        tokio::time::interval(Duration::from_secs(1) / LOADGEN_TICKS_PER_SECOND as u32);
+    let mut latencies: Vec<Duration> = Vec::new();
+    let started = Instant::now();
+    let deadline = started + Duration::from_secs(seconds);
+    loop {
+        if rate > 0 {
+            ticker.tick().await;
+        }
+        if Instant::now() >= deadline {
+            break;
+        }
+        let lines: Vec<String> = (0..batch).map(|_| generator.next_line()).collect();
+        match args.get("webhook") {
+            Some(url) => {
+                let mut request = client
+                    .http
+                    .post(format!("{}/ingest", url.trim_end_matches('/')))
+                    .json(&lines);
+                if let Some(token) = &client.token {
+                    request = request.bearer_auth(token);
+                }
+                let pushed = Instant::now();
+                client.send(request).await?;
+                latencies.push(pushed.elapsed());
+            }
+            None => {
+                for line in lines {
+                    let pushed = Instant::now();
+                    let log = buffer.push(line).await;
+                    latencies.push(pushed.elapsed());
+                    let _ = tx.send(Arc::new(log));
+                }
+                // Let the subscriber keep up the way a streaming client would
+                tokio::task::yield_now().await;
+            }
+        }
+    }
+    let elapsed = started.elapsed();
+    drop(tx);
+    let (received, lagged) = subscriber.await.unwrap_or_default();
+
+    latencies.sort();
+    let percentile = |p: usize| {
+        latencies
+            .get((latencies.len() * p / 100).min(latencies.len().saturating_sub(1)))
+            .copied()
+            .unwrap_or_default()
+    };
+    let produced = generator.produced();
+    let report = json!({
+        "lines": produced,
+        "seconds": elapsed.as_secs_f64(),
+        "lines_per_second": produced as f64 / elapsed.as_secs_f64(),
+        "target": args.get("webhook").unwrap_or("in-process"),
+        "latency_p50_us": percentile(50).as_micros() as u64,
+        "latency_p99_us": percentile(99).as_micros() as u64,
+        "broadcast_received": received,
+        "broadcast_lagged": lagged,
+    });
+    if args.has("json") {
+        println!("{}", report);
+        return Ok(());
+    }
+    let per = if args.has("webhook") { "batch" } else { "push" };
+    println!(
+        "target       {}",
+        report["target"].as_str().unwrap_or_default()
+    );
+    println!(
+        "lines        {} in {:.1}s ({:.0}/s)",
+        produced,
+        elapsed.as_secs_f64(),
+        report["lines_per_second"].as_f64().unwrap_or_default()
+    );
+    println!(
+        "{:<12} p50 {:?}, p99 {:?}",
+        per,
+        percentile(50),
+        percentile(99)
+    );
+    if !args.has("webhook") {
+        println!("broadcast    {} received, {} lagged", received, lagged);
+    }
+    Ok(())
+}
+
+/// Run a subcommand; None when the arguments are for the server
+pub async fn run(args: Vec<String>) -> Option<i32> {
+    let command = args.first()?.clone();
+    if ![
+        "tail", "query", "chat", "usage", "mcp", "loadgen", "help", "--help", "-h",
+    ]
+    .contains(&command.as_str())
+    {
+        return None;
+    }
+    let args = match Args::parse(args) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            return Some(2);
+        }
+    };
+    if args.has("help") || command.starts_with('-') || command == "help" {
+        print!("{}", USAGE);
+        return Some(0);
+    }
+    let client = Client::new(&args);
+    let result = match command.as_str() {
+        "tail" => tail(&client, &args).await,
+        "query" => match args.number("limit", 100) {
+            Ok(limit) => query(&client, &args, limit).await.map(|_| ()),
+            Err(e) => Err(e),
+        },
+        "chat" => chat(&client, &args).await,
+        "usage" => usage(&client, &args).await,
+        "loadgen" => loadgen(&client, &args).await,
+        _ => {
+            mcp::run_stdio(&client.url, client.token.as_deref()).await;
+            Ok(())
+        }
+    };
+    Some(match result {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("flywatch {}: {}", command, e);
+            1
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_args_and_log_format() {
+        let args = Args::parse(
+            ["query", "--level", "error", "--json", "--limit=5", "extra"].map(String::from),
+        )
+        .unwrap();
+        assert_eq!(args.positional, vec!["query", "extra"]);
+        assert_eq!(args.get("level"), Some("error"));
+        assert!(args.has("json"));
+        assert_eq!(args.number("limit", 100).unwrap(), 5);
+        assert_eq!(args.variables(&["cursor"]).len(), 1);
+        assert!(Args::parse(["tail", "--view"].map(String::from)).is_err());
+
+        let log = json!({
+            "timestamp": "2026-01-02T15:04:05.123Z",
+            "level": "error",
+            "instance": "web-1",
+            "message": null,
+            "raw": "boom",
+        });
+        assert_eq!(
+            format_log(&log),
+            "2026-01-02 15:04:05 ERROR web-1          boom"
+        );
+    }
+}
@@ -0,0 +1,182 @@
+//! ClickHouse sink for teams pushing millions of lines a day: every
+//! buffered line is batched and inserted over ClickHouse's HTTP interface
+//! into CLICKHOUSE_TABLE, which is created on first use. flywatch stays the
+//! live tail and AI layer; ClickHouse keeps the history.
+
+use async_trait::async_trait;
+use reqwest::Client;
+use serde_json::json;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tracing::info;
+
+use crate::config::Config;
+use crate::log_buffer::TimestampedLog;
+use crate::nats::LogMessage;
+use crate::sink::{response_error, Rejection, Sink, SinkDefaults, SinkError};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// ClickHouse's DateTime64 text format
+const TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.6f";
+
+pub struct ClickHouse {
+    client: Client,
+    url: String,
+    database: String,
+    table: String,
+    user: String,
+    password: Option<String>,
+    ttl_days: u64,
+    bootstrapped: AtomicBool,
+}
+
+impl ClickHouse {
+    /// None without CLICKHOUSE_URL
+    pub fn new(config: &Config) -> Option<Self> {
+        let url = config.clickhouse_url.clone()?;
+        let client = Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .expect("Failed to build HTTP client");
+        let clickhouse = Self {
+            client,
+            url,
+            database: config.clickhouse_database.clone(),
+            table: config.clickhouse_table.clone(),
+            user: config.clickhouse_user.clone(),
+            password: config.clickhouse_password.clone(),
+            ttl_days: config.clickhouse_ttl_days,
+            bootstrapped: AtomicBool::new(false),
+        };
+        info!(
+            url = %clickhouse.url,
+            table = %format!("{}.{}", clickhouse.database, clickhouse.table),
+            "ClickHouse export enabled"
+        );
+        Some(clickhouse)
+    }
+
+    fn create_table_sql(&self) -> String {
+        let ttl = match self.ttl_days {
+            0 => String::new(),
+            days => format!("\nTTL toDateTime(timestamp) + INTERVAL {} DAY", days),
+        };
+        format!(
+            "CREATE TABLE IF NOT EXISTS {}.{} (
+    timestamp DateTime64(6, 'UTC'),
+    received_at DateTime64(6, 'UTC'),
+    seq UInt64,
+    app LowCardinality(String),
+    instance LowCardinality(String),
+    region LowCardinality(String),
+    level LowCardinality(String),
+    source LowCardinality(String),
+    message String,
+    raw String
+)
+ENGINE = MergeTree
+PARTITION BY toDate(timestamp)
+ORDER BY (app, level, timestamp){}",
+            self.database, self.table, ttl
+        )
+    }
+
+    async fn execute(&self, query: &str, body: String) -> Result<(), SinkError> {
+        let mut request = self
+            .client
+            .post(&self.url)
+            .query(&[("query", query), ("database", &self.database)])
+            .header("X-ClickHouse-User", &self.user)
+            .body(body);
+        if let Some(password) = &self.password {
+            request = request.header("X-ClickHouse-Key", password);
+        }
+        let response = request
+            .send()
+            .await
+            .map_err(|e| SinkError::Unavailable(e.to_string()))?;
+        if response.status().is_success() {
+            return Ok(());
+        }
+        Err(response_error(response).await)
+    }
+}
+
+#[async_trait]
+impl Sink for ClickHouse {
+    fn kind(&self) -> &'static str {
+        "clickhouse"
+    }
+
+    fn defaults(&self) -> SinkDefaults {
+        SinkDefaults {
+            queue_capacity: 50_000,
+            batch_size: 5_000,
+            flush_interval: Duration::from_secs(2),
+            max_attempts: 4,
+        }
+    }
+
+    /// Create the table once, then insert the batch
+    async fn send(&self, batch: &[LogMessage]) -> Result<Vec<Rejection>, SinkError> {
+        let body = batch
+            .iter()
+            .map(|log| row(log).to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let insert = format!(
+            "INSERT INTO {}.{} FORMAT JSONEachRow",
+            self.database, self.table
+        );
+        if !self.bootstrapped.load(Ordering::Relaxed) {
+            self.execute(&self.create_table_sql(), String::new())
+                .await?;
+            self.bootstrapped.store(true, Ordering::Relaxed);
+        }
+        self.execute(&insert, body).await?;
+        Ok(Vec::new())
+    }
+}
+
+/// One JSONEachRow row
+fn row(log: &TimestampedLog) -> serde_json::Value {
+    json!({
+        "timestamp": log.timestamp.format(TIME_FORMAT).to_string(),
+        "received_at": log.received_at.format(TIME_FORMAT).to_string(),
+        "seq": log.seq,
+        "app": log.app.as_deref().unwrap_or_default(),
+        "instance": log.instance.as_deref().unwrap_or_default(),
+        "region": log.region.as_deref().unwrap_or_default(),
+        "level": log.level.as_deref().unwrap_or_default(),
+        "source": log.source.as_deref().unwrap_or_default(),
+        "message": log.message.as_deref().unwrap_or_default(),
+        "raw": log.raw,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rows_and_schema() {
+        let log = TimestampedLog::new(
+            r#"{"timestamp":"2026-01-05T10:00:00.5Z","message":"boom","log":{"level":"error"},"fly":{"app":{"name":"web"}}}"#.to_string(),
+            7,
+        );
+        let row = row(&log);
+        assert_eq!(row["timestamp"], "2026-01-05 10:00:00.500000");
+        assert_eq!(row["app"], "web");
+        assert_eq!(row["instance"], "");
+        assert_eq!(row["seq"], 7);
+
+        let config = Config::for_tests(
+            "clickhouse_url = \"http://localhost:8123\"\nclickhouse_ttl_days = 90",
+        );
+        let clickhouse = ClickHouse::new(&config).unwrap();
+        let sql = clickhouse.create_table_sql();
+        assert!(sql.starts_with("CREATE TABLE IF NOT EXISTS default.flywatch_logs ("));
+        assert!(sql.ends_with("TTL toDateTime(timestamp) + INTERVAL 90 DAY"));
+    }
+}
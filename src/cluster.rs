@@ -0,0 +1,228 @@
+//! Clustering for replicas behind Fly's proxy.
+//!
+//! Every replica subscribes to the Fly log stream on its own, but lines
+//! pushed to one replica (webhook, syslog, its own logs) and history from
+//! before a restart stay on that replica. With CLUSTER_MODE each replica
+//! publishes lines from its local sources on CLUSTER_SUBJECT and ingests the
+//! others' lines, so their buffers converge. A replica that starts up first
+//! asks a peer for its buffer on `<CLUSTER_SUBJECT>.sync`.
+
+use async_nats::{Client, HeaderMap};
+use async_trait::async_trait;
+use futures::StreamExt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+use crate::config::Config;
+use crate::log_buffer::LogBuffer;
+use crate::nats;
+use crate::source::{LogSource, SourceContext, SourceError};
+
+/// Header naming the replica that published a line
+const REPLICA_HEADER: &str = "Flywatch-Replica";
+/// How long a starting replica waits for a peer's buffer
+const SYNC_TIMEOUT: Duration = Duration::from_secs(5);
+/// Room left in a sync reply for NATS framing
+const SYNC_PAYLOAD_MARGIN: usize = 1024;
+
+/// This replica's link to the others
+pub struct Cluster {
+    config: Arc<Config>,
+    log_buffer: Arc<LogBuffer>,
+    replica: String,
+    /// Set while the cluster source is connected
+    client: RwLock<Option<Client>>,
+    synced: AtomicBool,
+}
+
+impl Cluster {
+    pub fn new(config: Arc<Config>, log_buffer: Arc<LogBuffer>) -> Arc<Self> {
+        let replica = std::env::var("FLY_MACHINE_ID")
+            .unwrap_or_else(|_| uuid::Uuid::new_v4().simple().to_string());
+        Arc::new(Self {
+            config,
+            log_buffer,
+            replica,
+            client: RwLock::new(None),
+            synced: AtomicBool::new(false),
+        })
+    }
+
+    /// Share a line from a local source with the other replicas
+    pub async fn publish(&self, raw: &str) {
+        let Some(client) = self.client.read().unwrap().clone() else {
+            return;
+        };
+        let mut headers = HeaderMap::new();
+        headers.insert(REPLICA_HEADER, self.replica.as_str());
+        if let Err(e) = client
+            .publish_with_headers(
+                self.config.cluster_subject.clone(),
+                headers,
+                raw.to_string().into(),
+            )
+            .await
+        {
+            debug!(error = %e, "Failed to publish line to the cluster");
+        }
+    }
+
+    fn sync_subject(&self) -> String {
+        format!("{}.sync", self.config.cluster_subject)
+    }
+
+    /// The newest buffered lines that fit in one message, oldest first
+    async fn snapshot(&self, max_payload: usize) -> Vec<String> {
+        let budget = max_payload.saturating_sub(SYNC_PAYLOAD_MARGIN);
+        let logs = self
+            .log_buffer
+            .get_last_n(self.config.log_buffer_max_entries)
+            .await;
+        let mut size = 2;
+        let mut lines: Vec<String> = logs
+            .into_iter()
+            .rev()
+            .map(|log| log.raw)
+            .take_while(|raw| {
+                // Quoted and escaped, plus a comma
+                size += serde_json::to_string(raw).map_or(raw.len(), |s| s.len()) + 1;
+                size <= budget
+            })
+            .collect();
+        lines.reverse();
+        lines
+    }
+
+    /// Load a peer's buffer, once per process
+    async fn catch_up(&self, client: &Client, ctx: &SourceContext) {
+        if self.synced.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let reply =
+            tokio::time::timeout(SYNC_TIMEOUT, client.request(self.sync_subject(), "".into()))
+                .await;
+        let lines = match reply {
+            Ok(Ok(reply)) => match serde_json::from_slice::<Vec<String>>(&reply.payload) {
+                Ok(lines) => lines,
+                Err(e) => {
+                    warn!(error = %e, "Invalid cluster sync reply");
+                    return;
+                }
+            },
+            _ => {
+                info!("No peer replica answered; starting from the local buffer");
+                return;
+            }
+        };
+        info!(lines = lines.len(), "Caught up from a peer replica");
+        for raw in lines {
+            ctx.emit_replica(raw).await;
+        }
+    }
+}
+
+/// Ingests lines published by the other replicas and answers their sync requests
+pub struct ClusterSource {
+    cluster: Arc<Cluster>,
+}
+
+impl ClusterSource {
+    pub fn new(cluster: Arc<Cluster>) -> Self {
+        Self { cluster }
+    }
+}
+
+#[async_trait]
+impl LogSource for ClusterSource {
+    fn name(&self) -> &str {
+        "cluster"
+    }
+
+    fn kind(&self) -> &'static str {
+        "cluster"
+    }
+
+    async fn run(&self, ctx: &SourceContext) -> Result<(), SourceError> {
+        let cluster = &self.cluster;
+        let config = &cluster.config;
+        let client = nats::connect(&config.cluster_nats_url, config)
+            .await
+            .map_err(|e| SourceError::Connect(e.to_string()))?;
+
+        // Catch up before answering anyone else's sync request
+        cluster.catch_up(&client, ctx).await;
+        let subscribe_err = |e: async_nats::SubscribeError| SourceError::Connect(e.to_string());
+        let mut lines = client
+            .subscribe(config.cluster_subject.clone())
+            .await
+            .map_err(subscribe_err)?;
+        let mut syncs = client
+            .subscribe(cluster.sync_subject())
+            .await
+            .map_err(subscribe_err)?;
+        *cluster.client.write().unwrap() = Some(client.clone());
+        info!(
+            subject = %config.cluster_subject,
+            replica = %cluster.replica,
+            "Joined the cluster"
+        );
+        ctx.running().await;
+
+        loop {
+            tokio::select! {
+                Some(message) = lines.next() => {
+                    let from = message
+                        .headers
+                        .as_ref()
+                        .and_then(|h| h.get(REPLICA_HEADER))
+                        .map(|v| v.as_str());
+                    if from == Some(cluster.replica.as_str()) {
+                        continue;
+                    }
+                    ctx.emit_replica(String::from_utf8_lossy(&message.payload).into_owned())
+                        .await;
+                }
+                Some(request) = syncs.next() => {
+                    let Some(reply) = request.reply else { continue };
+                    let lines = cluster.snapshot(client.server_info().max_payload).await;
+                    let payload = serde_json::to_vec(&lines).unwrap_or_default();
+                    if let Err(e) = client.publish(reply, payload.into()).await {
+                        ctx.report_error(SourceError::Io(e.to_string())).await;
+                    }
+                }
+                else => break,
+            }
+        }
+
+        *cluster.client.write().unwrap() = None;
+        Err(SourceError::Io("Cluster subscription closed".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_sync_snapshot_keeps_the_newest_lines_that_fit() {
+        let config = Arc::new(Config::for_tests("cluster_mode = true"));
+        assert_eq!(config.cluster_subject, "flywatch.cluster.app");
+        let log_buffer = LogBuffer::new(Default::default(), None);
+        for i in 0..100 {
+            log_buffer.push(format!("line {:03}", i)).await;
+        }
+        let cluster = Cluster::new(config, log_buffer);
+
+        let everything = cluster.snapshot(1 << 20).await;
+        assert_eq!(everything.len(), 100);
+        assert_eq!(everything[0], "line 000");
+
+        // Each line is 11 bytes quoted, plus a comma
+        let newest = cluster.snapshot(SYNC_PAYLOAD_MARGIN + 2 + 12 * 10).await;
+        assert_eq!(newest.len(), 10);
+        assert_eq!(newest.first().map(String::as_str), Some("line 090"));
+        assert_eq!(newest.last().map(String::as_str), Some("line 099"));
+    }
+}
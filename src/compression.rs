@@ -0,0 +1,205 @@
+use axum::body::Body;
+use axum::extract::ws::Message;
+use bytes::Bytes;
+use flate2::write::{GzEncoder, ZlibEncoder};
+use flate2::Compression;
+use futures::StreamExt;
+use serde::Deserialize;
+use std::io::Write;
+use utoipa::ToSchema;
+
+/// Compression applied to a streaming connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum StreamCompression {
+    None,
+    Gzip,
+    /// zlib format, as in HTTP `Content-Encoding: deflate`
+    Deflate,
+}
+
+impl StreamCompression {
+    /// Pick the best encoding the client advertises in Accept-Encoding
+    pub fn negotiate(accept_encoding: Option<&str>) -> Self {
+        let accepted: Vec<&str> = accept_encoding
+            .unwrap_or("")
+            .split(',')
+            .filter_map(|part| {
+                let mut params = part.split(';').map(str::trim);
+                let coding = params.next()?;
+                // "gzip;q=0" explicitly refuses the coding
+                let refused = params.any(|p| p == "q=0" || p == "q=0.0");
+                (!refused).then_some(coding)
+            })
+            .collect();
+
+        if accepted.iter().any(|c| c.eq_ignore_ascii_case("gzip")) {
+            StreamCompression::Gzip
+        } else if accepted.iter().any(|c| c.eq_ignore_ascii_case("deflate")) {
+            StreamCompression::Deflate
+        } else {
+            StreamCompression::None
+        }
+    }
+
+    pub fn content_encoding(self) -> Option<&'static str> {
+        match self {
+            StreamCompression::None => None,
+            StreamCompression::Gzip => Some("gzip"),
+            StreamCompression::Deflate => Some("deflate"),
+        }
+    }
+
+    /// Wrap a streaming response body, flushing after every chunk so events
+    /// (and keep-alive comments) reach the client immediately
+    pub fn stream_body(self, body: Body) -> Body {
+        let mut encoder = match self {
+            StreamCompression::None => return body,
+            StreamCompression::Gzip => {
+                FlushingEncoder::Gzip(GzEncoder::new(Vec::new(), Compression::fast()))
+            }
+            StreamCompression::Deflate => {
+                FlushingEncoder::Zlib(ZlibEncoder::new(Vec::new(), Compression::fast()))
+            }
+        };
+
+        let stream = async_stream::stream! {
+            let mut data = body.into_data_stream();
+            while let Some(chunk) = data.next().await {
+                match chunk {
+                    Ok(chunk) => match encoder.compress(&chunk) {
+                        Ok(out) => yield Ok(Bytes::from(out)),
+                        Err(e) => {
+                            yield Err(axum::Error::new(e));
+                            return;
+                        }
+                    },
+                    Err(e) => {
+                        yield Err(e);
+                        return;
+                    }
+                }
+            }
+            if let Ok(trailer) = encoder.finish() {
+                yield Ok(Bytes::from(trailer));
+            }
+        };
+        Body::from_stream(stream)
+    }
+
+    /// Build a WebSocket message. This is flywatch's own `?encoding=` framing,
+    /// not permessage-deflate: compressed payloads go out as binary frames,
+    /// each a standalone gzip or zlib stream; `None` keeps text frames.
+    pub fn ws_message(self, text: String) -> Message {
+        let compressed = match self {
+            StreamCompression::None => return Message::Text(text),
+            StreamCompression::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+                encoder
+                    .write_all(text.as_bytes())
+                    .and_then(|_| encoder.finish())
+            }
+            StreamCompression::Deflate => {
+                let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+                encoder
+                    .write_all(text.as_bytes())
+                    .and_then(|_| encoder.finish())
+            }
+        };
+        match compressed {
+            Ok(bytes) => Message::Binary(bytes),
+            Err(_) => Message::Text(text),
+        }
+    }
+}
+
+enum FlushingEncoder {
+    Gzip(GzEncoder<Vec<u8>>),
+    Zlib(ZlibEncoder<Vec<u8>>),
+}
+
+impl FlushingEncoder {
+    /// Compress one chunk with a sync flush, returning the bytes produced
+    fn compress(&mut self, chunk: &[u8]) -> std::io::Result<Vec<u8>> {
+        match self {
+            FlushingEncoder::Gzip(e) => {
+                e.write_all(chunk)?;
+                e.flush()?;
+                Ok(std::mem::take(e.get_mut()))
+            }
+            FlushingEncoder::Zlib(e) => {
+                e.write_all(chunk)?;
+                e.flush()?;
+                Ok(std::mem::take(e.get_mut()))
+            }
+        }
+    }
+
+    fn finish(self) -> std::io::Result<Vec<u8>> {
+        match self {
+            FlushingEncoder::Gzip(e) => e.finish(),
+            FlushingEncoder::Zlib(e) => e.finish(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::MultiGzDecoder;
+    use std::io::Read;
+
+    #[test]
+    fn test_negotiate_prefers_gzip() {
+        assert_eq!(
+            StreamCompression::negotiate(Some("deflate, gzip;q=0.8")),
+            StreamCompression::Gzip
+        );
+        assert_eq!(
+            StreamCompression::negotiate(Some("gzip;q=0, deflate")),
+            StreamCompression::Deflate
+        );
+        assert_eq!(
+            StreamCompression::negotiate(Some("br")),
+            StreamCompression::None
+        );
+        assert_eq!(StreamCompression::negotiate(None), StreamCompression::None);
+    }
+
+    #[test]
+    fn test_ws_frames_are_standalone_streams() {
+        let text = r#"{"type":"log","message":"hello"}"#.to_string();
+        assert_eq!(
+            StreamCompression::None.ws_message(text.clone()),
+            Message::Text(text.clone())
+        );
+
+        let Message::Binary(frame) = StreamCompression::Gzip.ws_message(text.clone()) else {
+            panic!("expected a binary frame");
+        };
+        let mut inflated = String::new();
+        MultiGzDecoder::new(&frame[..])
+            .read_to_string(&mut inflated)
+            .unwrap();
+        assert_eq!(inflated, text);
+    }
+
+    #[test]
+    fn test_flushed_chunks_decode_incrementally() {
+        let mut encoder = FlushingEncoder::Gzip(GzEncoder::new(Vec::new(), Compression::fast()));
+        let mut compressed = encoder.compress(b"data: one\n\n").unwrap();
+
+        // A sync flush makes everything written so far decodable
+        let mut partial = String::new();
+        let _ = MultiGzDecoder::new(&compressed[..]).read_to_string(&mut partial);
+        assert_eq!(partial, "data: one\n\n");
+
+        compressed.extend(encoder.compress(b"data: two\n\n").unwrap());
+        compressed.extend(encoder.finish().unwrap());
+        let mut full = String::new();
+        MultiGzDecoder::new(&compressed[..])
+            .read_to_string(&mut full)
+            .unwrap();
+        assert_eq!(full, "data: one\n\ndata: two\n\n");
+    }
+}
@@ -1,18 +1,123 @@
+use axum::http::{HeaderName, Method};
+use std::cell::RefCell;
+use std::collections::HashSet;
 use std::env;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use tracing::level_filters::LevelFilter;
 
-#[derive(Debug, Clone)]
+use crate::alerts::{AlertRoute, Severity};
+use crate::channels::ChannelRule;
+use crate::chat::{ToolScope, MAX_TEMPERATURE};
+use crate::fanout::OverflowPolicy;
+use crate::heartbeat::HeartbeatDefinition;
+use crate::ingest_filter::IngestRule;
+use crate::log_metrics::{LogMetricKind, LogMetricRule};
+use crate::nats::{NatsAuth, SubjectTemplate};
+use crate::payload::BinaryFormat;
+use crate::pricing::{CostPolicy, PriceOverride};
+use crate::redact::{self, RedactKind};
+use crate::sink::SinkDefinition;
+use crate::slo::SloDefinition;
+use crate::smtp::SmtpSecurity;
+use crate::source::SOURCE_KINDS;
+use crate::tenant::{self, TenantDefinition};
+use crate::tickets::{JiraField, JiraPriority};
+use crate::tls::ClientAuth;
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Config {
     pub fly_prod_app_name: String,
     pub auth_token: Option<String>,
+    /// Accept `?access_token=` or `?ticket=` on /logs/stream, for EventSource
+    pub sse_query_auth: bool,
+    /// Dashboard logins as (username, password), from `name:password` entries
+    pub auth_users: Vec<(String, String)>,
+    pub session_ttl_hours: u64,
+    pub session_cookie_secure: bool,
     pub nats_url: String,
     pub nats_user: String,
     pub nats_password: String,
+    /// Fly credentials above unless NATS_CREDS_FILE, NATS_NKEY_SEED or NATS_TOKEN is set
+    pub nats_auth: NatsAuth,
+    /// Require TLS; implied by a CA or client certificate
+    pub nats_tls: bool,
+    /// PEM CA that signed the server's certificate
+    pub nats_ca_file: Option<String>,
+    pub nats_client_cert_file: Option<String>,
+    pub nats_client_key_file: Option<String>,
+    /// Larger NATS payloads lose their middle (0 keeps every byte)
+    pub max_line_bytes: usize,
+    /// Decoders tried on payloads that aren't text, before falling back to base64
+    pub nats_binary_formats: Vec<BinaryFormat>,
+    /// Subject patterns to consume (`{app}` expands per app), with source labels
+    pub nats_subject_templates: Vec<SubjectTemplate>,
+    /// Seconds between NATS round-trip probes; 0 turns them off
+    pub nats_ping_interval_secs: u64,
     pub host: String,
     pub port: u16,
+    /// h2c listener for the gRPC API; off when unset
+    pub grpc_bind_addr: Option<String>,
+    /// Subject prefix of the NATS request-reply API; off when unset
+    pub rpc_subject: Option<String>,
 
     // OpenRouter configuration
     pub openrouter_api_key: Option<String>,
     pub openrouter_model: String,
+    /// Retries per model on network errors, 429 and 5xx
+    pub openrouter_max_retries: u32,
+    /// Models tried in order once the requested one keeps failing
+    pub openrouter_fallback_models: Vec<String>,
+    /// OpenAI-compatible API the chat calls, OpenRouter's by default
+    pub openrouter_base_url: String,
+
+    // Models clients may request ("vendor/*" matches a prefix; empty allows any)
+    pub chat_model_allowlist: Vec<String>,
+    pub chat_model_denylist: Vec<String>,
+    /// Tools each certificate principal may run; `*` covers everyone else
+    pub chat_tool_scopes: Vec<ToolScope>,
+    /// Ceiling on a chat's estimated cost before it is sent
+    pub chat_max_cost_usd: Option<f64>,
+    /// Chat spend allowed per calendar month (UTC); needs STORE_PATH
+    pub chat_monthly_budget_usd: Option<f64>,
+    pub chat_cost_policy: CostPolicy,
+    /// Prices that win over OpenRouter's list and the built-in table
+    pub model_pricing: Vec<PriceOverride>,
+    /// Seconds between fetches of OpenRouter's prices; 0 turns them off
+    pub openrouter_pricing_refresh_secs: u64,
+    /// How long an identical question against an unchanged buffer is
+    /// answered from cache (0 disables)
+    pub chat_cache_ttl_secs: u64,
+    /// Store what each chat turn sent the model and its answer; needs STORE_PATH
+    pub chat_transcripts: bool,
+    /// Longest transcript kept, in characters across its messages
+    pub chat_transcript_max_chars: usize,
+    pub chat_transcript_retention_days: u64,
+    /// Completion length when a request doesn't set max_tokens
+    pub chat_max_tokens: u32,
+    /// Largest max_tokens a request may ask for
+    pub chat_max_tokens_limit: u32,
+    /// Sampling temperature when a request doesn't set one
+    pub chat_temperature: f32,
+    /// Mask IPs, emails and `chat_redact_patterns` in everything sent to
+    /// OpenRouter; the buffer itself keeps full fidelity
+    pub chat_pii_safe: bool,
+    pub chat_redact_patterns: Vec<String>,
+
+    // Chat system prompt customization
+    /// Service name the agent talks about (defaults to the Fly app name)
+    pub prompt_app_name: String,
+    /// Replacement for the built-in prompt, inline or from SYSTEM_PROMPT_FILE
+    pub system_prompt: Option<String>,
+    /// Free-form notes about the service appended to the prompt
+    pub prompt_context: Option<String>,
+    pub prompt_vocabulary: Vec<String>,
+    pub prompt_runbooks: Vec<String>,
+    /// Markdown runbooks indexed for the search_runbooks tool
+    pub runbook_dir: Option<String>,
+    pub runbook_urls: Vec<String>,
 
     // Log buffer configuration
     pub log_buffer_max_entries: usize,
@@ -20,74 +125,1841 @@ pub struct Config {
 
     // Persistence configuration
     pub store_path: Option<String>,
+    /// Days raw usage records are kept before compaction into daily rollups
+    pub usage_retention_days: u64,
+    /// SQLite database for the long-term archive behind /logs/sql
+    pub archive_path: Option<String>,
+    pub archive_retention_days: u64,
+
+    // ClickHouse export (HTTP interface)
+    pub clickhouse_url: Option<String>,
+    pub clickhouse_database: String,
+    pub clickhouse_table: String,
+    pub clickhouse_user: String,
+    pub clickhouse_password: Option<String>,
+    /// Rows older than this are dropped by ClickHouse (0 keeps them)
+    pub clickhouse_ttl_days: u64,
+
+    // Elasticsearch / OpenSearch export (_bulk API)
+    pub elasticsearch_url: Option<String>,
+    /// Daily indices are named `<prefix>-YYYY.MM.DD`
+    pub elasticsearch_index: String,
+    pub elasticsearch_username: Option<String>,
+    pub elasticsearch_password: Option<String>,
+    /// Sent as `Authorization: ApiKey ...`; takes precedence over basic auth
+    pub elasticsearch_api_key: Option<String>,
+
+    // Kafka / Redpanda export through a REST proxy
+    pub kafka_rest_url: Option<String>,
+    pub kafka_topic: String,
+    pub kafka_username: Option<String>,
+    pub kafka_password: Option<String>,
+    /// Enabled exporters and their queue/retry tuning
+    pub sinks: Vec<SinkDefinition>,
+
+    // Multi-tenant mode: per-tenant apps, buffers and tokens under /t/<name>/
+    pub tenants: Vec<TenantDefinition>,
+
+    // Replicas share ingested lines over NATS (see cluster)
+    pub cluster_mode: bool,
+    pub cluster_subject: String,
+    pub cluster_nats_url: String,
+
+    // Log source configuration
+    pub sources: Vec<String>,
+    pub syslog_bind_addr: String,
+    pub webhook_bind_addr: String,
+    pub file_tail_paths: Vec<String>,
+    pub file_tail_poll_ms: u64,
+    pub docker_socket: String,
+    pub docker_label_filter: Option<String>,
+    pub docker_region_label: String,
+    pub k8s_api_url: Option<String>,
+    pub k8s_namespace: Option<String>,
+    pub k8s_label_selector: Option<String>,
+    /// Pod label whose value is the lines' app; the namespace when unset or missing
+    pub k8s_app_label: Option<String>,
+
+    // Broadcast channel from sources to consumers
+    pub channel_capacity: usize,
+    /// Recent drop share (%) for any consumer class that raises a warning
+    pub drop_warning_percent: f64,
+
+    // Per-connection stream queue defaults
+    pub connection_queue_capacity: usize,
+    pub connection_overflow_policy: OverflowPolicy,
+    /// Largest `queue` a stream connection may ask for
+    pub stream_queue_max: usize,
+
+    // Fault injection for resilience testing; everything is off at 0
+    /// Share of OpenRouter calls that fail as if OpenRouter returned 503
+    pub chaos_openrouter_failure_rate: f64,
+    /// Delay after each message a streaming connection receives
+    pub chaos_broadcast_lag_ms: u64,
+    /// Share of log store writes (and readiness probes) that fail
+    pub chaos_store_failure_rate: f64,
+
+    // CORS (empty lists allow any)
+    pub cors_allowed_origins: Vec<String>,
+    pub cors_allowed_methods: Vec<String>,
+    pub cors_allowed_headers: Vec<String>,
+    pub cors_allow_credentials: bool,
+
+    // HTTPS listener (PEM files); a client CA turns on mTLS
+    pub tls_cert_file: Option<String>,
+    pub tls_key_file: Option<String>,
+    pub tls_client_ca_file: Option<String>,
+    pub tls_client_auth: ClientAuth,
+    /// Certificate CNs allowed in as principals (empty allows any the CA signed)
+    pub tls_client_cns: Vec<String>,
+
+    // Most verbose of flywatch's own logs fed into the pipeline ("off" disables)
+    pub self_log_level: LevelFilter,
+
+    // NATS ingest rules, first match wins (see ingest_filter)
+    pub ingest_rules: Vec<IngestRule>,
+
+    // Named stream channels lines are routed into (see channels)
+    pub channels: Vec<ChannelRule>,
+
+    // Counters and histograms derived from log content (see log_metrics)
+    pub log_metrics: Vec<LogMetricRule>,
+
+    // SLOs over log-based counters (see slo)
+    pub slos: Vec<SloDefinition>,
+
+    // Alerts when watched logs go quiet (see heartbeat)
+    pub heartbeats: Vec<HeartbeatDefinition>,
+
+    // Rolling window of access-log analytics (see http_analytics)
+    pub http_analytics_window_minutes: i64,
+
+    // Alert delivery
+    pub alert_webhook_url: Option<String>,
+    /// Subject prefix alerts and health transitions are published under
+    pub events_subject: Option<String>,
+    pub pagerduty_routing_key: Option<String>,
+    /// Least severe alert that pages
+    pub pagerduty_min_severity: Severity,
+    pub discord_webhook_url: Option<String>,
+    pub telegram_bot_token: Option<String>,
+    pub telegram_chat_id: Option<String>,
+    /// First match picks the notifiers; unrouted alerts go to all of them
+    pub alert_routes: Vec<AlertRoute>,
+    /// Ask the model to explain new alerts before they are delivered
+    pub alert_ai_enrichment: bool,
+
+    // Issue trackers (see tickets)
+    /// flywatch's external URL, for links back from tickets
+    pub public_url: Option<String>,
+    pub github_issues_token: Option<String>,
+    /// `owner/name` of the repository issues are filed in
+    pub github_issues_repo: Option<String>,
+    pub github_issues_labels: Vec<String>,
+    /// REST API root, for GitHub Enterprise Server
+    pub github_api_url: String,
+    pub linear_api_key: Option<String>,
+    pub linear_team_id: Option<String>,
+    /// Site root, e.g. `https://acme.atlassian.net`
+    pub jira_base_url: Option<String>,
+    pub jira_email: Option<String>,
+    pub jira_api_token: Option<String>,
+    pub jira_project_key: Option<String>,
+    pub jira_issue_type: String,
+    /// Jira priority per finding severity; unmapped ones get the project default
+    pub jira_priorities: Vec<JiraPriority>,
+    /// Extra fields set on every issue
+    pub jira_fields: Vec<JiraField>,
+    /// File an issue for each firing alert, not just confirmed findings
+    pub ticket_alerts: bool,
+
+    // Email (alerts and scheduled digests)
+    pub smtp_host: Option<String>,
+    pub smtp_port: u16,
+    pub smtp_security: SmtpSecurity,
+    pub smtp_username: Option<String>,
+    pub smtp_password: Option<String>,
+    pub smtp_from: Option<String>,
+    pub smtp_to: Vec<String>,
+    /// Email every alert transition, not just digests
+    pub smtp_alerts: bool,
+    /// Hours between digests (0 disables)
+    pub smtp_digest_hours: u64,
+    pub smtp_digest_ai_summary: bool,
+    /// HTML template from SMTP_DIGEST_TEMPLATE_FILE
+    pub smtp_digest_template: Option<String>,
+
+    // Redaction applied to every line before it is buffered or forwarded
+    pub redact_builtins: Vec<RedactKind>,
+    pub redact_patterns: Vec<String>,
+    /// Dotted JSON paths, e.g. "user.email"
+    pub redact_fields: Vec<String>,
 }
 
+/// Every problem found while loading configuration
+#[derive(Debug)]
+pub struct ConfigError {
+    pub problems: Vec<String>,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid configuration:")?;
+        for problem in &self.problems {
+            write!(f, "\n  - {}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
 impl Config {
-    pub fn from_env() -> Self {
-        let fly_prod_app_name = env::var("FLY_PROD_APP_NAME")
-            .expect("FLY_PROD_APP_NAME must be set");
+    /// Load from an optional TOML file, with environment variables taking
+    /// precedence. File keys are the lowercase env var names
+    /// (`FLY_PROD_APP_NAME` -> `fly_prod_app_name`).
+    pub fn load(path: Option<&Path>) -> Result<Self, ConfigError> {
+        let mut problems = Vec::new();
+        let file = match path {
+            Some(path) => match std::fs::read_to_string(path) {
+                Ok(contents) => match contents.parse::<toml::Table>() {
+                    Ok(table) => table,
+                    Err(e) => {
+                        problems.push(format!("{}: {}", path.display(), e));
+                        toml::Table::new()
+                    }
+                },
+                Err(e) => {
+                    problems.push(format!("{}: {}", path.display(), e));
+                    toml::Table::new()
+                }
+            },
+            None => toml::Table::new(),
+        };
+
+        let settings = Settings {
+            file,
+            env: |key| env::var(key).ok(),
+            used: RefCell::new(HashSet::new()),
+            problems: RefCell::new(problems),
+        };
+        let config = Self::from_settings(&settings);
+
+        let mut problems = settings.problems.into_inner();
+        let used = settings.used.into_inner();
+        for key in settings.file.keys() {
+            if !used.contains(key.as_str()) {
+                problems.push(format!("unknown setting '{}'", key));
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(config)
+        } else {
+            Err(ConfigError { problems })
+        }
+    }
+
+    fn from_settings(s: &Settings) -> Self {
+        let fly_prod_app_name = s.required("FLY_PROD_APP_NAME", "the Fly app to monitor");
+
+        let auth_token = s.optional("AUTH_TOKEN");
+        let sse_query_auth = s.flag("SSE_QUERY_AUTH", false);
 
-        let auth_token = env::var("AUTH_TOKEN").ok().filter(|s| !s.is_empty());
+        // Dashboard sessions are signed with AUTH_TOKEN
+        let mut auth_users = Vec::new();
+        for entry in s.list("AUTH_USERS").unwrap_or_default() {
+            match entry.split_once(':') {
+                Some((name, password)) if !name.is_empty() && !password.is_empty() => {
+                    auth_users.push((name.to_string(), password.to_string()))
+                }
+                _ => s.problem("AUTH_USERS: entries must be name:password".to_string()),
+            }
+        }
+        if !auth_users.is_empty() && auth_token.is_none() {
+            s.problem("AUTH_USERS requires AUTH_TOKEN, which signs sessions".to_string());
+        }
+        let session_ttl_hours = s.parse("SESSION_TTL_HOURS", 12u64).max(1);
+        let session_cookie_secure = s.flag("SESSION_COOKIE_SECURE", true);
 
         // Fly.io internal NATS is available at this address within 6PN
-        let nats_url = env::var("NATS_URL")
-            .unwrap_or_else(|_| "[fdaa::3]:4223".to_string());
+        let nats_url = s.string("NATS_URL", "[fdaa::3]:4223");
+
+        // Other clusters (Synadia, self-hosted) take a .creds file, an NKey
+        // seed or a token in place of the Fly credentials
+        let nats_auth = match (
+            s.optional("NATS_CREDS_FILE"),
+            s.optional("NATS_NKEY_SEED"),
+            s.optional("NATS_TOKEN"),
+        ) {
+            (None, None, None) => NatsAuth::Fly,
+            (Some(path), None, None) => NatsAuth::CredsFile(path),
+            (None, Some(seed), None) => NatsAuth::Nkey(seed),
+            (None, None, Some(token)) => NatsAuth::Token(token),
+            (creds, seed, token) => {
+                s.problem(
+                    "Set only one of NATS_CREDS_FILE, NATS_NKEY_SEED and NATS_TOKEN".to_string(),
+                );
+                creds
+                    .map(NatsAuth::CredsFile)
+                    .or(seed.map(NatsAuth::Nkey))
+                    .or(token.map(NatsAuth::Token))
+                    .unwrap_or(NatsAuth::Fly)
+            }
+        };
+        let nats_ca_file = s.optional("NATS_CA_FILE");
+        let nats_client_cert_file = s.optional("NATS_CLIENT_CERT_FILE");
+        let nats_client_key_file = s.optional("NATS_CLIENT_KEY_FILE");
+        if nats_client_cert_file.is_some() != nats_client_key_file.is_some() {
+            s.problem(
+                "NATS_CLIENT_CERT_FILE and NATS_CLIENT_KEY_FILE must be set together".to_string(),
+            );
+        }
+        let nats_tls = s.flag("NATS_TLS", false)
+            || nats_ca_file.is_some()
+            || nats_client_cert_file.is_some();
 
         // NATS authentication - org slug as user, fly token as password
-        let nats_user = env::var("ORG_SLUG")
-            .expect("ORG_SLUG must be set (your Fly organization slug)");
-        let nats_password = env::var("ACCESS_TOKEN")
-            .expect("ACCESS_TOKEN must be set (output of 'fly auth token')");
+        let (nats_user, nats_password) = if nats_auth == NatsAuth::Fly {
+            (
+                s.required("ORG_SLUG", "your Fly organization slug"),
+                s.required("ACCESS_TOKEN", "output of 'fly auth token'"),
+            )
+        } else {
+            (s.string("ORG_SLUG", ""), s.string("ACCESS_TOKEN", ""))
+        };
+        // Room for a marker and some head and tail; 0 turns the cap off
+        let max_line_bytes = match s.parse("MAX_LINE_BYTES", 256 * 1024usize) {
+            0 => 0,
+            max => max.max(1024),
+        };
+        let mut nats_binary_formats = Vec::new();
+        for format in s.list("NATS_BINARY_FORMATS").unwrap_or_default() {
+            match format.parse() {
+                Ok(parsed) => nats_binary_formats.push(parsed),
+                Err(e) => s.problem(format!("NATS_BINARY_FORMATS: {}", e)),
+            }
+        }
+        let mut nats_subject_templates = Vec::new();
+        for template in s.list("NATS_SUBJECTS").unwrap_or_default() {
+            match template.parse() {
+                Ok(parsed) => nats_subject_templates.push(parsed),
+                Err(e) => s.problem(format!("NATS_SUBJECTS: {}", e)),
+            }
+        }
+        if nats_subject_templates.is_empty() {
+            nats_subject_templates.push(SubjectTemplate::fly_logs());
+        }
+        let nats_ping_interval_secs = s.parse("NATS_PING_INTERVAL_SECS", 10u64);
 
-        let host = env::var("HOST").unwrap_or_else(|_| "0.0.0.0".to_string());
-        let port = env::var("PORT")
-            .unwrap_or_else(|_| "8080".to_string())
-            .parse()
-            .expect("PORT must be a valid number");
+        let host = s.string("HOST", "0.0.0.0");
+        let port = s.parse("PORT", 8080);
+        let grpc_bind_addr = s.optional("GRPC_BIND_ADDR");
+        let rpc_subject = s.optional("RPC_SUBJECT");
+        if let Some(subject) = rpc_subject.as_deref().filter(|s| !plain_subject(s)) {
+            s.problem(format!(
+                "RPC_SUBJECT: '{}' must be a plain subject without wildcards",
+                subject
+            ));
+        }
 
         // OpenRouter configuration
-        let openrouter_api_key = env::var("OPENROUTER_API_KEY")
-            .ok()
-            .filter(|s| !s.is_empty());
-        let openrouter_model = env::var("OPENROUTER_MODEL")
-            .unwrap_or_else(|_| "moonshotai/kimi-k2".to_string());
+        let openrouter_api_key = s.optional("OPENROUTER_API_KEY");
+        let openrouter_model = s.string("OPENROUTER_MODEL", "moonshotai/kimi-k2");
+        let openrouter_max_retries = s.parse("OPENROUTER_MAX_RETRIES", 2);
+        let openrouter_fallback_models = s.list("OPENROUTER_FALLBACK_MODELS").unwrap_or_default();
+        let openrouter_base_url = s
+            .string("OPENROUTER_BASE_URL", "https://openrouter.ai/api/v1")
+            .trim_end_matches('/')
+            .to_string();
+
+        // Guards on client-requested models and per-request spend
+        let chat_model_allowlist = s.list("CHAT_MODEL_ALLOWLIST").unwrap_or_default();
+        let chat_model_denylist = s.list("CHAT_MODEL_DENYLIST").unwrap_or_default();
+        let mut chat_tool_scopes: Vec<ToolScope> = Vec::new();
+        for entry in s.list("CHAT_TOOL_SCOPES").unwrap_or_default() {
+            match entry.parse::<ToolScope>() {
+                Ok(parsed) if chat_tool_scopes.iter().any(|t| t.principal == parsed.principal) => {
+                    s.problem(format!("CHAT_TOOL_SCOPES: '{}' is listed twice", parsed.principal))
+                }
+                Ok(parsed) => chat_tool_scopes.push(parsed),
+                Err(e) => s.problem(format!("CHAT_TOOL_SCOPES: {}", e)),
+            }
+        }
+        let chat_max_cost_usd = match s.optional("CHAT_MAX_COST_USD") {
+            Some(value) => match value.parse::<f64>() {
+                Ok(cost) if cost > 0.0 => Some(cost),
+                _ => {
+                    s.problem(format!(
+                        "CHAT_MAX_COST_USD: expected a positive number, found '{}'",
+                        value
+                    ));
+                    None
+                }
+            },
+            None => None,
+        };
+        let chat_cost_policy = s.parse("CHAT_COST_POLICY", CostPolicy::Reject);
+        let mut model_pricing: Vec<PriceOverride> = Vec::new();
+        for entry in s.list("MODEL_PRICING").unwrap_or_default() {
+            match entry.parse::<PriceOverride>() {
+                Ok(parsed) => model_pricing.push(parsed),
+                Err(e) => s.problem(format!("MODEL_PRICING: {}", e)),
+            }
+        }
+        let openrouter_pricing_refresh_secs = s.parse("OPENROUTER_PRICING_REFRESH_SECS", 86_400u64);
+        let chat_cache_ttl_secs = s.parse("CHAT_CACHE_TTL_SECS", 60);
+        let chat_transcripts = s.flag("CHAT_TRANSCRIPTS", false);
+        let chat_transcript_max_chars = s.parse("CHAT_TRANSCRIPT_MAX_CHARS", 200_000usize);
+        let chat_transcript_retention_days = s.parse("CHAT_TRANSCRIPT_RETENTION_DAYS", 7u64);
+        let chat_max_tokens = s.parse("CHAT_MAX_TOKENS", 4096);
+        let chat_max_tokens_limit = s.parse("CHAT_MAX_TOKENS_LIMIT", 8192);
+        if chat_max_tokens == 0 || chat_max_tokens > chat_max_tokens_limit {
+            s.problem(format!(
+                "CHAT_MAX_TOKENS must be between 1 and CHAT_MAX_TOKENS_LIMIT ({}), got {}",
+                chat_max_tokens_limit, chat_max_tokens
+            ));
+        }
+        let chat_temperature = s.parse("CHAT_TEMPERATURE", 0.3);
+        if !(0.0..=MAX_TEMPERATURE).contains(&chat_temperature) {
+            s.problem(format!(
+                "CHAT_TEMPERATURE must be between 0 and {}, got {}",
+                MAX_TEMPERATURE, chat_temperature
+            ));
+        }
+        let chat_pii_safe = s.flag("CHAT_PII_SAFE", false);
+        let chat_redact_patterns = s.list("CHAT_REDACT_PATTERNS").unwrap_or_default();
+        for pattern in &chat_redact_patterns {
+            if let Err(e) = redact::validate_pattern(pattern) {
+                s.problem(format!(
+                    "CHAT_REDACT_PATTERNS: invalid pattern '{}': {}",
+                    pattern, e
+                ));
+            }
+        }
+
+        // Chat system prompt: replace it outright, or extend the built-in one
+        let prompt_app_name = s
+            .optional("PROMPT_APP_NAME")
+            .unwrap_or_else(|| fly_prod_app_name.clone());
+        let system_prompt = match (
+            s.optional("SYSTEM_PROMPT"),
+            s.optional("SYSTEM_PROMPT_FILE"),
+        ) {
+            (Some(_), Some(_)) => {
+                s.problem("Set only one of SYSTEM_PROMPT and SYSTEM_PROMPT_FILE".to_string());
+                None
+            }
+            (Some(prompt), None) => Some(prompt),
+            (None, Some(path)) => match std::fs::read_to_string(&path) {
+                Ok(prompt) => Some(prompt),
+                Err(e) => {
+                    s.problem(format!("SYSTEM_PROMPT_FILE: cannot read '{}': {}", path, e));
+                    None
+                }
+            },
+            (None, None) => None,
+        };
+        let prompt_context = s.optional("PROMPT_CONTEXT");
+        let prompt_vocabulary = s.list("PROMPT_VOCABULARY").unwrap_or_default();
+        let prompt_runbooks = s.list("PROMPT_RUNBOOKS").unwrap_or_default();
+
+        // Runbook knowledge base searched by the chat agent
+        let runbook_dir = s.optional("RUNBOOK_DIR");
+        let runbook_urls = s.list("RUNBOOK_URLS").unwrap_or_default();
 
         // Log buffer configuration
-        let log_buffer_max_entries = env::var("LOG_BUFFER_MAX_ENTRIES")
-            .ok()
-            .and_then(|s| s.parse().ok())
-            .unwrap_or(10_000);
-        let log_buffer_max_age_minutes = env::var("LOG_BUFFER_MAX_AGE_MINUTES")
-            .ok()
-            .and_then(|s| s.parse().ok())
-            .unwrap_or(30);
+        let log_buffer_max_entries = s.parse("LOG_BUFFER_MAX_ENTRIES", 10_000);
+        let log_buffer_max_age_minutes = s.parse("LOG_BUFFER_MAX_AGE_MINUTES", 30);
 
         // Persistence configuration
-        let store_path = env::var("STORE_PATH")
-            .ok()
-            .filter(|s| !s.is_empty());
+        let store_path = s.optional("STORE_PATH");
+        let usage_retention_days = s.parse("USAGE_RETENTION_DAYS", 90);
+        let archive_path = s.optional("ARCHIVE_PATH");
+        let archive_retention_days = s.parse("ARCHIVE_RETENTION_DAYS", 30);
+        let clickhouse_url = s.optional("CLICKHOUSE_URL");
+        let clickhouse_database = s.string("CLICKHOUSE_DATABASE", "default");
+        let clickhouse_table = s.string("CLICKHOUSE_TABLE", "flywatch_logs");
+        for (key, name) in [
+            ("CLICKHOUSE_DATABASE", &clickhouse_database),
+            ("CLICKHOUSE_TABLE", &clickhouse_table),
+        ] {
+            if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                s.problem(format!("{}: '{}' must be letters, digits and underscores", key, name));
+            }
+        }
+        let clickhouse_user = s.string("CLICKHOUSE_USER", "default");
+        let clickhouse_password = s.optional("CLICKHOUSE_PASSWORD");
+        let clickhouse_ttl_days = s.parse("CLICKHOUSE_TTL_DAYS", 0);
+        let elasticsearch_url = s.optional("ELASTICSEARCH_URL");
+        let elasticsearch_index = s.string("ELASTICSEARCH_INDEX", "flywatch-logs");
+        if elasticsearch_index.is_empty()
+            || elasticsearch_index.starts_with(['-', '_'])
+            || !elasticsearch_index
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "-_.".contains(c))
+        {
+            s.problem(format!(
+                "ELASTICSEARCH_INDEX: '{}' must be lowercase letters, digits, '-', '_' and '.'",
+                elasticsearch_index
+            ));
+        }
+        let elasticsearch_username = s.optional("ELASTICSEARCH_USERNAME");
+        let elasticsearch_password = s.optional("ELASTICSEARCH_PASSWORD");
+        let elasticsearch_api_key = s.optional("ELASTICSEARCH_API_KEY");
+        let kafka_rest_url = s.optional("KAFKA_REST_URL");
+        let kafka_topic = s.string("KAFKA_TOPIC", "flywatch-logs");
+        if kafka_topic.is_empty()
+            || kafka_topic.len() > 249
+            || !kafka_topic
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c))
+        {
+            s.problem(format!(
+                "KAFKA_TOPIC: '{}' must be letters, digits, '-', '_' and '.'",
+                kafka_topic
+            ));
+        }
+        let kafka_username = s.optional("KAFKA_USERNAME");
+        let kafka_password = s.optional("KAFKA_PASSWORD");
+        // Each sink needs its destination; without SINKS, every configured one runs
+        let destinations = [
+            ("archive", "ARCHIVE_PATH", archive_path.is_some()),
+            ("clickhouse", "CLICKHOUSE_URL", clickhouse_url.is_some()),
+            ("elasticsearch", "ELASTICSEARCH_URL", elasticsearch_url.is_some()),
+            ("kafka", "KAFKA_REST_URL", kafka_rest_url.is_some()),
+        ];
+        let mut sinks: Vec<SinkDefinition> = Vec::new();
+        match s.list("SINKS") {
+            Some(definitions) => {
+                for definition in definitions {
+                    let parsed = match definition.parse::<SinkDefinition>() {
+                        Ok(parsed) => parsed,
+                        Err(e) => {
+                            s.problem(format!("SINKS: invalid sink '{}': {}", definition, e));
+                            continue;
+                        }
+                    };
+                    if sinks.iter().any(|sink| sink.kind == parsed.kind) {
+                        s.problem(format!("SINKS: duplicate sink '{}'", parsed.kind));
+                    }
+                    if let Some((kind, key, false)) =
+                        destinations.iter().find(|(kind, ..)| *kind == parsed.kind)
+                    {
+                        s.problem(format!("SINKS: {} needs {}", kind, key));
+                    }
+                    sinks.push(parsed);
+                }
+            }
+            None => {
+                sinks = destinations
+                    .iter()
+                    .filter(|(_, _, configured)| *configured)
+                    .map(|(kind, ..)| SinkDefinition::new(kind))
+                    .collect();
+            }
+        }
+        let chat_monthly_budget_usd = match s.optional("CHAT_MONTHLY_BUDGET_USD") {
+            Some(value) => match value.parse::<f64>() {
+                Ok(budget) if budget > 0.0 => Some(budget),
+                _ => {
+                    s.problem(format!(
+                        "CHAT_MONTHLY_BUDGET_USD: expected a positive number, found '{}'",
+                        value
+                    ));
+                    None
+                }
+            },
+            None => None,
+        };
+        if chat_monthly_budget_usd.is_some() && store_path.is_none() {
+            s.problem("CHAT_MONTHLY_BUDGET_USD needs STORE_PATH to track spend".to_string());
+        }
+        if chat_transcripts && store_path.is_none() {
+            s.problem("CHAT_TRANSCRIPTS needs STORE_PATH to keep transcripts".to_string());
+        }
+        let mut tenants: Vec<TenantDefinition> = Vec::new();
+        for definition in s.list("TENANTS").unwrap_or_default() {
+            let parsed = match definition.parse::<TenantDefinition>() {
+                Ok(parsed) => parsed,
+                Err(e) => {
+                    s.problem(format!("TENANTS: invalid tenant '{}': {}", definition, e));
+                    continue;
+                }
+            };
+            if tenants.iter().any(|t| t.name == parsed.name) {
+                s.problem(format!("TENANTS: duplicate tenant '{}'", parsed.name));
+            }
+            if auth_token.as_ref() == Some(&parsed.token)
+                || tenants.iter().any(|t| t.token == parsed.token)
+            {
+                s.problem(format!(
+                    "TENANTS: tenant '{}' must have a token of its own",
+                    parsed.name
+                ));
+            }
+            if parsed.budget_usd.is_some() && store_path.is_none() {
+                s.problem(format!(
+                    "TENANTS: tenant '{}' has a budget, which needs STORE_PATH",
+                    parsed.name
+                ));
+            }
+            tenants.push(parsed);
+        }
+        if !tenants.is_empty() && auth_token.is_none() {
+            s.problem(
+                "TENANTS requires AUTH_TOKEN, or the unprefixed routes expose every tenant"
+                    .to_string(),
+            );
+        }
+        let cluster_mode = s.flag("CLUSTER_MODE", false);
+        let cluster_subject = s.string(
+            "CLUSTER_SUBJECT",
+            &format!("flywatch.cluster.{}", fly_prod_app_name),
+        );
+        if cluster_subject.contains(['*', '>', ' ']) {
+            s.problem(format!(
+                "CLUSTER_SUBJECT: '{}' must be a plain subject without wildcards",
+                cluster_subject
+            ));
+        }
+        let cluster_nats_url = s.string("CLUSTER_NATS_URL", &nats_url);
+
+        // Log source configuration (list of source kinds)
+        let sources: Vec<String> = s
+            .list("SOURCES")
+            .unwrap_or_else(|| vec!["nats".to_string()])
+            .into_iter()
+            .map(|kind| kind.to_lowercase())
+            .collect();
+        for kind in sources.iter().filter(|k| !SOURCE_KINDS.contains(&k.as_str())) {
+            s.problem(format!(
+                "SOURCES: unknown source kind '{}' (use {})",
+                kind,
+                SOURCE_KINDS.join(", ")
+            ));
+        }
+        let syslog_bind_addr = s.string("SYSLOG_BIND_ADDR", "0.0.0.0:5514");
+        let webhook_bind_addr = s.string("WEBHOOK_BIND_ADDR", "0.0.0.0:8081");
+        let file_tail_paths = s.list("FILE_TAIL_PATHS").unwrap_or_default();
+        let file_tail_poll_ms = s.parse("FILE_TAIL_POLL_MS", 500);
+        if file_tail_poll_ms == 0 {
+            s.problem("FILE_TAIL_POLL_MS must be greater than 0".to_string());
+        }
+        let docker_socket = s.string("DOCKER_SOCKET", "/var/run/docker.sock");
+        let docker_label_filter = s.optional("DOCKER_LABEL_FILTER");
+        let docker_region_label = s.string("DOCKER_REGION_LABEL", "region");
+        // Kubernetes defaults come from the in-cluster service account
+        let k8s_api_url = s.optional("K8S_API_URL");
+        let k8s_namespace = s.optional("K8S_NAMESPACE");
+        let k8s_label_selector = s.optional("K8S_LABEL_SELECTOR");
+        let k8s_app_label = s.optional("K8S_APP_LABEL");
+
+        // Broadcast channel; consumers that fall this far behind lose messages
+        let channel_capacity = s.parse("CHANNEL_CAPACITY", 10_000);
+        if channel_capacity == 0 {
+            s.problem("CHANNEL_CAPACITY must be greater than 0".to_string());
+        }
+        let drop_warning_percent = s.parse("DROP_WARNING_PERCENT", 5.0);
+        if !(0.0..=100.0).contains(&drop_warning_percent) {
+            s.problem(format!(
+                "DROP_WARNING_PERCENT must be between 0 and 100, got {}",
+                drop_warning_percent
+            ));
+        }
+
+        // Per-connection stream queue defaults (overridable per connection)
+        let connection_queue_capacity = s.parse("CONNECTION_QUEUE_CAPACITY", 1_000);
+        let connection_overflow_policy =
+            s.parse("CONNECTION_OVERFLOW_POLICY", OverflowPolicy::DropOldest);
+        let stream_queue_max = s.parse("STREAM_QUEUE_MAX", 100_000);
+        if stream_queue_max < connection_queue_capacity {
+            s.problem(format!(
+                "STREAM_QUEUE_MAX ({}) must be at least CONNECTION_QUEUE_CAPACITY ({})",
+                stream_queue_max, connection_queue_capacity
+            ));
+        }
+
+        // Fault injection for staging; never set these in production
+        let chaos_openrouter_failure_rate = s.parse("CHAOS_OPENROUTER_FAILURE_RATE", 0.0);
+        let chaos_broadcast_lag_ms = s.parse("CHAOS_BROADCAST_LAG_MS", 0);
+        let chaos_store_failure_rate = s.parse("CHAOS_STORE_FAILURE_RATE", 0.0);
+        for (key, rate) in [
+            ("CHAOS_OPENROUTER_FAILURE_RATE", chaos_openrouter_failure_rate),
+            ("CHAOS_STORE_FAILURE_RATE", chaos_store_failure_rate),
+        ] {
+            if !(0.0..=1.0).contains(&rate) {
+                s.problem(format!("{} must be between 0 and 1, got {}", key, rate));
+            }
+        }
+
+        // CORS configuration (origins may use *.example.com; "*" means any)
+        let cors_list = |key: &str| -> Vec<String> {
+            s.list(key)
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|v| v != "*")
+                .collect()
+        };
+        let cors_allowed_origins = cors_list("CORS_ALLOWED_ORIGINS");
+        let cors_allowed_methods = cors_list("CORS_ALLOWED_METHODS");
+        let cors_allowed_headers = cors_list("CORS_ALLOWED_HEADERS");
+        let cors_allow_credentials = s.flag("CORS_ALLOW_CREDENTIALS", false);
+
+        for method in &cors_allowed_methods {
+            if Method::from_str(&method.to_uppercase()).is_err() {
+                s.problem(format!("CORS_ALLOWED_METHODS: invalid method '{}'", method));
+            }
+        }
+        for name in &cors_allowed_headers {
+            if HeaderName::from_str(name).is_err() {
+                s.problem(format!("CORS_ALLOWED_HEADERS: invalid header '{}'", name));
+            }
+        }
+        if cors_allow_credentials && cors_allowed_origins.is_empty() {
+            s.problem("CORS_ALLOW_CREDENTIALS requires CORS_ALLOWED_ORIGINS to be set".to_string());
+        }
+
+        // HTTPS, with client certificates standing in for the bearer token
+        let tls_cert_file = s.optional("TLS_CERT_FILE");
+        let tls_key_file = s.optional("TLS_KEY_FILE");
+        let tls_client_ca_file = s.optional("TLS_CLIENT_CA_FILE");
+        let tls_client_auth = s.parse("TLS_CLIENT_AUTH", ClientAuth::Required);
+        let tls_client_cns = s.list("TLS_CLIENT_CNS").unwrap_or_default();
+        if tls_cert_file.is_some() != tls_key_file.is_some() {
+            s.problem("TLS_CERT_FILE and TLS_KEY_FILE must be set together".to_string());
+        }
+        if tls_client_ca_file.is_some() && tls_cert_file.is_none() {
+            s.problem("TLS_CLIENT_CA_FILE requires TLS_CERT_FILE and TLS_KEY_FILE".to_string());
+        }
+
+        // flywatch's own logs, tagged source=self in the buffer and streams
+        let self_log_level = s.parse("SELF_LOG_LEVEL", LevelFilter::WARN);
+
+        // Drop or sample NATS messages before they are buffered or streamed
+        let mut ingest_rules = Vec::new();
+        for rule in s.list("INGEST_RULES").unwrap_or_default() {
+            match rule.parse() {
+                Ok(parsed) => ingest_rules.push(parsed),
+                Err(e) => s.problem(format!("INGEST_RULES: invalid rule '{}': {}", rule, e)),
+            }
+        }
+
+        // Channels streaming clients can subscribe to with ?channel=
+        let mut channels: Vec<ChannelRule> = Vec::new();
+        for rule in s.list("CHANNELS").unwrap_or_default() {
+            match rule.parse::<ChannelRule>() {
+                Ok(parsed) if channels.iter().any(|c| c.name == parsed.name) => {
+                    s.problem(format!("CHANNELS: duplicate channel '{}'", parsed.name))
+                }
+                Ok(parsed) => channels.push(parsed),
+                Err(e) => s.problem(format!("CHANNELS: invalid channel '{}': {}", rule, e)),
+            }
+        }
+
+        // Log-based metrics, exposed in /metrics and /metrics/prometheus
+        let mut log_metrics: Vec<LogMetricRule> = Vec::new();
+        for rule in s.list("LOG_METRICS").unwrap_or_default() {
+            match rule.parse::<LogMetricRule>() {
+                Ok(parsed) if log_metrics.iter().any(|m| m.name() == parsed.name()) => {
+                    s.problem(format!("LOG_METRICS: duplicate metric '{}'", parsed.name()))
+                }
+                Ok(parsed) => log_metrics.push(parsed),
+                Err(e) => s.problem(format!("LOG_METRICS: invalid rule '{}': {}", rule, e)),
+            }
+        }
+
+        // Scrub sensitive values before logs are stored, streamed or sent to the model
+        let mut redact_builtins = Vec::new();
+        for kind in s.list("REDACT_BUILTINS").unwrap_or_default() {
+            match kind.parse() {
+                Ok(parsed) => redact_builtins.push(parsed),
+                Err(e) => s.problem(format!("REDACT_BUILTINS: {}", e)),
+            }
+        }
+        let redact_patterns = s.list("REDACT_PATTERNS").unwrap_or_default();
+        for pattern in &redact_patterns {
+            if let Err(e) = redact::validate_pattern(pattern) {
+                s.problem(format!(
+                    "REDACT_PATTERNS: invalid pattern '{}': {}",
+                    pattern, e
+                ));
+            }
+        }
+        let redact_fields = s.list("REDACT_FIELDS").unwrap_or_default();
+
+        // SLOs must reference log-based counters
+        let mut slos: Vec<SloDefinition> = Vec::new();
+        for definition in s.list("SLOS").unwrap_or_default() {
+            let parsed = match definition.parse::<SloDefinition>() {
+                Ok(parsed) => parsed,
+                Err(e) => {
+                    s.problem(format!("SLOS: invalid SLO '{}': {}", definition, e));
+                    continue;
+                }
+            };
+            for counter in [&parsed.errors, &parsed.total] {
+                let known = log_metrics
+                    .iter()
+                    .any(|m| m.name() == counter && m.kind() == LogMetricKind::Counter);
+                if !known {
+                    s.problem(format!(
+                        "SLOS: '{}' references '{}', which is not a LOG_METRICS counter",
+                        parsed.name, counter
+                    ));
+                }
+            }
+            if slos.iter().any(|existing| existing.name == parsed.name) {
+                s.problem(format!("SLOS: duplicate SLO '{}'", parsed.name));
+            }
+            slos.push(parsed);
+        }
+        let mut heartbeats: Vec<HeartbeatDefinition> = Vec::new();
+        for definition in s.list("HEARTBEATS").unwrap_or_default() {
+            match definition.parse::<HeartbeatDefinition>() {
+                Ok(parsed) if heartbeats.iter().any(|h| h.name == parsed.name) => {
+                    s.problem(format!("HEARTBEATS: duplicate heartbeat '{}'", parsed.name));
+                }
+                Ok(parsed) => heartbeats.push(parsed),
+                Err(e) => s.problem(format!(
+                    "HEARTBEATS: invalid heartbeat '{}': {}",
+                    definition, e
+                )),
+            }
+        }
+        let http_analytics_window_minutes = s
+            .parse("HTTP_ANALYTICS_WINDOW_MINUTES", 15i64)
+            .clamp(1, 1440);
+        let alert_webhook_url = s.optional("ALERT_WEBHOOK_URL");
+        let events_subject = s.optional("EVENTS_SUBJECT");
+        if let Some(subject) = events_subject.as_deref().filter(|s| !plain_subject(s)) {
+            s.problem(format!(
+                "EVENTS_SUBJECT: '{}' must be a plain subject without wildcards",
+                subject
+            ));
+        }
+        let pagerduty_routing_key = s.optional("PAGERDUTY_ROUTING_KEY");
+        let pagerduty_min_severity = s.parse("PAGERDUTY_MIN_SEVERITY", Severity::Critical);
+        let discord_webhook_url = s.optional("DISCORD_WEBHOOK_URL");
+        let telegram_bot_token = s.optional("TELEGRAM_BOT_TOKEN");
+        let telegram_chat_id = s.optional("TELEGRAM_CHAT_ID");
+        if telegram_bot_token.is_some() != telegram_chat_id.is_some() {
+            s.problem("TELEGRAM_BOT_TOKEN and TELEGRAM_CHAT_ID must be set together".to_string());
+        }
+        let smtp_host = s.optional("SMTP_HOST");
+        let smtp_security = s.parse("SMTP_SECURITY", SmtpSecurity::StartTls);
+        let smtp_port = s.parse("SMTP_PORT", smtp_security.default_port());
+        let smtp_username = s.optional("SMTP_USERNAME");
+        let smtp_password = s.optional("SMTP_PASSWORD");
+        let smtp_from = s.optional("SMTP_FROM");
+        let smtp_to = s.list("SMTP_TO").unwrap_or_default();
+        if smtp_host.is_some() && (smtp_from.is_none() || smtp_to.is_empty()) {
+            s.problem("SMTP_HOST needs SMTP_FROM and SMTP_TO".to_string());
+        }
+        let smtp_alerts = s.flag("SMTP_ALERTS", false);
+        let smtp_digest_hours = s.parse("SMTP_DIGEST_HOURS", 0);
+        let smtp_digest_ai_summary = s.flag("SMTP_DIGEST_AI_SUMMARY", true);
+        let smtp_digest_template = match s.optional("SMTP_DIGEST_TEMPLATE_FILE") {
+            Some(path) => match std::fs::read_to_string(&path) {
+                Ok(template) => Some(template),
+                Err(e) => {
+                    s.problem(format!(
+                        "SMTP_DIGEST_TEMPLATE_FILE: cannot read '{}': {}",
+                        path, e
+                    ));
+                    None
+                }
+            },
+            None => None,
+        };
+        let alert_ai_enrichment = s.flag("ALERT_AI_ENRICHMENT", false);
+        if alert_ai_enrichment && openrouter_api_key.is_none() {
+            s.problem("ALERT_AI_ENRICHMENT needs OPENROUTER_API_KEY".to_string());
+        }
+        let public_url = s
+            .optional("PUBLIC_URL")
+            .map(|url| url.trim_end_matches('/').to_string());
+        let github_issues_token = s.optional("GITHUB_ISSUES_TOKEN");
+        let github_issues_repo = s.optional("GITHUB_ISSUES_REPO");
+        if github_issues_token.is_some() != github_issues_repo.is_some() {
+            s.problem("GITHUB_ISSUES_TOKEN and GITHUB_ISSUES_REPO must be set together".to_string());
+        }
+        if let Some(repo) = github_issues_repo
+            .as_deref()
+            .filter(|repo| repo.split('/').filter(|part| !part.is_empty()).count() != 2)
+        {
+            s.problem(format!(
+                "GITHUB_ISSUES_REPO: expected owner/name, found '{}'",
+                repo
+            ));
+        }
+        let github_issues_labels = s
+            .list("GITHUB_ISSUES_LABELS")
+            .unwrap_or_else(|| vec!["flywatch".to_string()]);
+        let github_api_url = s
+            .string("GITHUB_API_URL", "https://api.github.com")
+            .trim_end_matches('/')
+            .to_string();
+        let linear_api_key = s.optional("LINEAR_API_KEY");
+        let linear_team_id = s.optional("LINEAR_TEAM_ID");
+        if linear_api_key.is_some() != linear_team_id.is_some() {
+            s.problem("LINEAR_API_KEY and LINEAR_TEAM_ID must be set together".to_string());
+        }
+        let jira_base_url = s
+            .optional("JIRA_BASE_URL")
+            .map(|url| url.trim_end_matches('/').to_string());
+        let jira_email = s.optional("JIRA_EMAIL");
+        let jira_api_token = s.optional("JIRA_API_TOKEN");
+        let jira_project_key = s.optional("JIRA_PROJECT_KEY");
+        let jira_set = [&jira_base_url, &jira_email, &jira_api_token, &jira_project_key]
+            .iter()
+            .filter(|v| v.is_some())
+            .count();
+        if jira_set != 0 && jira_set != 4 {
+            s.problem(
+                "JIRA_BASE_URL, JIRA_EMAIL, JIRA_API_TOKEN and JIRA_PROJECT_KEY must be set together"
+                    .to_string(),
+            );
+        }
+        let jira_issue_type = s.string("JIRA_ISSUE_TYPE", "Task");
+        let mut jira_priorities: Vec<JiraPriority> = Vec::new();
+        for entry in s.list("JIRA_PRIORITIES").unwrap_or_default() {
+            match entry.parse::<JiraPriority>() {
+                Ok(parsed) if jira_priorities.iter().any(|p| p.severity == parsed.severity) => {
+                    s.problem(format!("JIRA_PRIORITIES: '{}' is mapped twice", entry))
+                }
+                Ok(parsed) => jira_priorities.push(parsed),
+                Err(e) => s.problem(format!("JIRA_PRIORITIES: {}", e)),
+            }
+        }
+        let mut jira_fields: Vec<JiraField> = Vec::new();
+        for entry in s.list("JIRA_FIELDS").unwrap_or_default() {
+            match entry.parse::<JiraField>() {
+                Ok(parsed) => jira_fields.push(parsed),
+                Err(e) => s.problem(format!("JIRA_FIELDS: {}", e)),
+            }
+        }
+        let ticket_alerts = s.flag("TICKET_ALERTS", false);
+        if ticket_alerts
+            && github_issues_token.is_none()
+            && linear_api_key.is_none()
+            && jira_api_token.is_none()
+        {
+            s.problem(
+                "TICKET_ALERTS needs GITHUB_ISSUES_TOKEN, LINEAR_API_KEY or JIRA_API_TOKEN"
+                    .to_string(),
+            );
+        }
+        let configured_notifiers = [
+            ("webhook", alert_webhook_url.is_some()),
+            ("pagerduty", pagerduty_routing_key.is_some()),
+            ("discord", discord_webhook_url.is_some()),
+            ("telegram", telegram_bot_token.is_some()),
+            ("email", smtp_alerts && smtp_host.is_some()),
+            ("nats", events_subject.is_some()),
+            ("github", ticket_alerts && github_issues_token.is_some()),
+            ("linear", ticket_alerts && linear_api_key.is_some()),
+            ("jira", ticket_alerts && jira_api_token.is_some()),
+        ];
+        let mut alert_routes: Vec<AlertRoute> = Vec::new();
+        for route in s.list("ALERT_ROUTES").unwrap_or_default() {
+            let parsed = match route.parse::<AlertRoute>() {
+                Ok(parsed) => parsed,
+                Err(e) => {
+                    s.problem(format!("ALERT_ROUTES: invalid route '{}': {}", route, e));
+                    continue;
+                }
+            };
+            for name in &parsed.notifiers {
+                if !configured_notifiers.contains(&(name.as_str(), true)) {
+                    s.problem(format!(
+                        "ALERT_ROUTES: '{}' uses the {} notifier, which is not configured",
+                        route, name
+                    ));
+                }
+            }
+            alert_routes.push(parsed);
+        }
 
         Self {
             fly_prod_app_name,
             auth_token,
+            sse_query_auth,
+            auth_users,
+            session_ttl_hours,
+            session_cookie_secure,
             nats_url,
             nats_user,
             nats_password,
+            nats_auth,
+            nats_tls,
+            nats_ca_file,
+            nats_client_cert_file,
+            nats_client_key_file,
+            max_line_bytes,
+            nats_binary_formats,
+            nats_subject_templates,
+            nats_ping_interval_secs,
             host,
             port,
+            grpc_bind_addr,
+            rpc_subject,
             openrouter_api_key,
             openrouter_model,
+            openrouter_max_retries,
+            openrouter_fallback_models,
+            openrouter_base_url,
+            chat_model_allowlist,
+            chat_model_denylist,
+            chat_tool_scopes,
+            chat_max_cost_usd,
+            chat_monthly_budget_usd,
+            chat_cost_policy,
+            model_pricing,
+            openrouter_pricing_refresh_secs,
+            chat_cache_ttl_secs,
+            chat_transcripts,
+            chat_transcript_max_chars,
+            chat_transcript_retention_days,
+            chat_max_tokens,
+            chat_max_tokens_limit,
+            chat_temperature,
+            chat_pii_safe,
+            chat_redact_patterns,
+            prompt_app_name,
+            system_prompt,
+            prompt_context,
+            prompt_vocabulary,
+            prompt_runbooks,
+            runbook_dir,
+            runbook_urls,
             log_buffer_max_entries,
             log_buffer_max_age_minutes,
             store_path,
+            usage_retention_days,
+            archive_path,
+            archive_retention_days,
+            clickhouse_url,
+            clickhouse_database,
+            clickhouse_table,
+            clickhouse_user,
+            clickhouse_password,
+            clickhouse_ttl_days,
+            elasticsearch_url,
+            elasticsearch_index,
+            elasticsearch_username,
+            elasticsearch_password,
+            elasticsearch_api_key,
+            kafka_rest_url,
+            kafka_topic,
+            kafka_username,
+            kafka_password,
+            sinks,
+            tenants,
+            cluster_mode,
+            cluster_subject,
+            cluster_nats_url,
+            sources,
+            syslog_bind_addr,
+            webhook_bind_addr,
+            file_tail_paths,
+            file_tail_poll_ms,
+            docker_socket,
+            docker_label_filter,
+            docker_region_label,
+            k8s_api_url,
+            k8s_namespace,
+            k8s_label_selector,
+            k8s_app_label,
+            channel_capacity,
+            drop_warning_percent,
+            connection_queue_capacity,
+            connection_overflow_policy,
+            stream_queue_max,
+            chaos_openrouter_failure_rate,
+            chaos_broadcast_lag_ms,
+            chaos_store_failure_rate,
+            cors_allowed_origins,
+            cors_allowed_methods,
+            cors_allowed_headers,
+            cors_allow_credentials,
+            tls_cert_file,
+            tls_key_file,
+            tls_client_ca_file,
+            tls_client_auth,
+            tls_client_cns,
+            self_log_level,
+            ingest_rules,
+            channels,
+            log_metrics,
+            slos,
+            heartbeats,
+            http_analytics_window_minutes,
+            alert_webhook_url,
+            events_subject,
+            pagerduty_routing_key,
+            pagerduty_min_severity,
+            discord_webhook_url,
+            telegram_bot_token,
+            telegram_chat_id,
+            alert_routes,
+            alert_ai_enrichment,
+            public_url,
+            github_issues_token,
+            github_issues_repo,
+            github_issues_labels,
+            github_api_url,
+            linear_api_key,
+            linear_team_id,
+            jira_base_url,
+            jira_email,
+            jira_api_token,
+            jira_project_key,
+            jira_issue_type,
+            jira_priorities,
+            jira_fields,
+            ticket_alerts,
+            smtp_host,
+            smtp_port,
+            smtp_security,
+            smtp_username,
+            smtp_password,
+            smtp_from,
+            smtp_to,
+            smtp_alerts,
+            smtp_digest_hours,
+            smtp_digest_ai_summary,
+            smtp_digest_template,
+            redact_builtins,
+            redact_patterns,
+            redact_fields,
+        }
+    }
+
+    /// Subjects to subscribe to, with the source label of each, for the
+    /// monitored app and every tenant's apps
+    pub fn nats_subjects(&self) -> Vec<(String, Option<String>)> {
+        let mut apps = vec![&self.fly_prod_app_name];
+        for app in self.tenants.iter().flat_map(|t| &t.apps) {
+            if !apps.contains(&app) {
+                apps.push(app);
+            }
+        }
+        let mut subjects: Vec<(String, Option<String>)> = Vec::new();
+        for template in &self.nats_subject_templates {
+            for subject in template.expand(&apps) {
+                if !subjects.iter().any(|(s, _)| *s == subject) {
+                    subjects.push((subject, template.source.clone()));
+                }
+            }
         }
+        subjects
     }
 
-    pub fn nats_subject(&self) -> String {
-        format!("logs.{}.>", self.fly_prod_app_name)
+    /// This config as a tenant sees it: its apps, token and budget, with its
+    /// own store file and only its own certificate CN
+    pub fn for_tenant(&self, tenant: &TenantDefinition) -> Config {
+        Config {
+            fly_prod_app_name: tenant.apps[0].clone(),
+            prompt_app_name: tenant.apps.join(", "),
+            auth_token: Some(tenant.token.clone()),
+            auth_users: Vec::new(),
+            tls_client_cns: vec![tenant.name.clone()],
+            chat_monthly_budget_usd: tenant.budget_usd.or(self.chat_monthly_budget_usd),
+            store_path: self
+                .store_path
+                .as_deref()
+                .map(|path| tenant::store_path(path, &tenant.name)),
+            tenants: Vec::new(),
+            ..self.clone()
+        }
     }
 
     pub fn bind_addr(&self) -> String {
         format!("{}:{}", self.host, self.port)
     }
 }
+
+// ==================== Reloading ====================
+
+/// The live configuration, swapped in place when the config file is reloaded
+pub struct ConfigStore {
+    current: RwLock<Arc<Config>>,
+    path: Option<PathBuf>,
+    tenant: Option<TenantView>,
+}
+
+/// A tenant's view of the shared store, re-derived when the shared config reloads
+struct TenantView {
+    shared: Arc<ConfigStore>,
+    tenant: TenantDefinition,
+    /// The shared config `current` was derived from
+    derived_from: RwLock<Arc<Config>>,
+}
+
+/// What a reload changed
+#[derive(Debug)]
+pub struct ReloadOutcome {
+    pub config: Arc<Config>,
+    /// Settings whose new values are now live
+    pub applied: Vec<&'static str>,
+    /// Settings that changed but only take effect after a restart
+    pub restart_required: Vec<&'static str>,
+}
+
+impl ConfigStore {
+    pub fn new(config: Config, path: Option<PathBuf>) -> Arc<Self> {
+        Arc::new(Self {
+            current: RwLock::new(Arc::new(config)),
+            path,
+            tenant: None,
+        })
+    }
+
+    /// The shared config with `tenant`'s overrides applied
+    pub fn for_tenant(shared: &Arc<Self>, tenant: &TenantDefinition) -> Arc<Self> {
+        let base = shared.current();
+        Arc::new(Self {
+            current: RwLock::new(Arc::new(base.for_tenant(tenant))),
+            path: None,
+            tenant: Some(TenantView {
+                shared: shared.clone(),
+                tenant: tenant.clone(),
+                derived_from: RwLock::new(base),
+            }),
+        })
+    }
+
+    pub fn current(&self) -> Arc<Config> {
+        if let Some(view) = &self.tenant {
+            let base = view.shared.current();
+            if !Arc::ptr_eq(&base, &view.derived_from.read().unwrap()) {
+                let derived = Arc::new(base.for_tenant(&view.tenant));
+                *self.current.write().unwrap() = derived.clone();
+                *view.derived_from.write().unwrap() = base;
+                return derived;
+            }
+        }
+        self.current.read().unwrap().clone()
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Re-read the file and environment. Reload-safe settings are swapped in;
+    /// the rest keep their running values. Nothing changes if loading fails.
+    pub fn reload(&self) -> Result<ReloadOutcome, ConfigError> {
+        let loaded = Config::load(self.path())?;
+        let mut current = self.current.write().unwrap();
+        let (config, applied, restart_required) = merge_reload(&current, loaded);
+        let config = Arc::new(config);
+        *current = config.clone();
+        Ok(ReloadOutcome {
+            config,
+            applied,
+            restart_required,
+        })
+    }
+}
+
+/// A subject flywatch publishes or serves on: no wildcards or empty tokens
+fn plain_subject(subject: &str) -> bool {
+    subject
+        .split('.')
+        .all(|t| !t.is_empty() && t != "*" && t != ">")
+}
+
+/// Split a freshly loaded config into the fields that can change at runtime
+/// and those that need a restart. Destructuring keeps this exhaustive, so a
+/// new setting has to be classified here before the crate compiles.
+fn merge_reload(
+    current: &Config,
+    loaded: Config,
+) -> (Config, Vec<&'static str>, Vec<&'static str>) {
+    let mut next = current.clone();
+    let mut applied = Vec::new();
+    let mut restart_required = Vec::new();
+
+    let Config {
+        fly_prod_app_name,
+        auth_token,
+        sse_query_auth,
+        auth_users,
+        session_ttl_hours,
+        session_cookie_secure,
+        nats_url,
+        nats_user,
+        nats_password,
+        nats_auth,
+        nats_tls,
+        nats_ca_file,
+        nats_client_cert_file,
+        nats_client_key_file,
+        max_line_bytes,
+        nats_binary_formats,
+        nats_subject_templates,
+        nats_ping_interval_secs,
+        host,
+        port,
+        grpc_bind_addr,
+        rpc_subject,
+        openrouter_api_key,
+        openrouter_model,
+        openrouter_max_retries,
+        openrouter_fallback_models,
+        openrouter_base_url,
+        chat_model_allowlist,
+        chat_model_denylist,
+        chat_tool_scopes,
+        chat_max_cost_usd,
+        chat_monthly_budget_usd,
+        chat_cost_policy,
+        model_pricing,
+        openrouter_pricing_refresh_secs,
+        chat_cache_ttl_secs,
+        chat_transcripts,
+        chat_transcript_max_chars,
+        chat_transcript_retention_days,
+        chat_max_tokens,
+        chat_max_tokens_limit,
+        chat_temperature,
+        chat_pii_safe,
+        chat_redact_patterns,
+        prompt_app_name,
+        system_prompt,
+        prompt_context,
+        prompt_vocabulary,
+        prompt_runbooks,
+        runbook_dir,
+        runbook_urls,
+        log_buffer_max_entries,
+        log_buffer_max_age_minutes,
+        store_path,
+        usage_retention_days,
+        archive_path,
+        archive_retention_days,
+        clickhouse_url,
+        clickhouse_database,
+        clickhouse_table,
+        clickhouse_user,
+        clickhouse_password,
+        clickhouse_ttl_days,
+        elasticsearch_url,
+        elasticsearch_index,
+        elasticsearch_username,
+        elasticsearch_password,
+        elasticsearch_api_key,
+        kafka_rest_url,
+        kafka_topic,
+        kafka_username,
+        kafka_password,
+        sinks,
+        tenants,
+        cluster_mode,
+        cluster_subject,
+        cluster_nats_url,
+        sources,
+        syslog_bind_addr,
+        webhook_bind_addr,
+        file_tail_paths,
+        file_tail_poll_ms,
+        docker_socket,
+        docker_label_filter,
+        docker_region_label,
+        k8s_api_url,
+        k8s_namespace,
+        k8s_label_selector,
+        k8s_app_label,
+        channel_capacity,
+        drop_warning_percent,
+        connection_queue_capacity,
+        connection_overflow_policy,
+        stream_queue_max,
+        chaos_openrouter_failure_rate,
+        chaos_broadcast_lag_ms,
+        chaos_store_failure_rate,
+        cors_allowed_origins,
+        cors_allowed_methods,
+        cors_allowed_headers,
+        cors_allow_credentials,
+        tls_cert_file,
+        tls_key_file,
+        tls_client_ca_file,
+        tls_client_auth,
+        tls_client_cns,
+        self_log_level,
+        ingest_rules,
+        channels,
+        log_metrics,
+        slos,
+        heartbeats,
+        http_analytics_window_minutes,
+        alert_webhook_url,
+        events_subject,
+        pagerduty_routing_key,
+        pagerduty_min_severity,
+        discord_webhook_url,
+        telegram_bot_token,
+        telegram_chat_id,
+        alert_routes,
+        alert_ai_enrichment,
+        public_url,
+        github_issues_token,
+        github_issues_repo,
+        github_issues_labels,
+        github_api_url,
+        linear_api_key,
+        linear_team_id,
+        jira_base_url,
+        jira_email,
+        jira_api_token,
+        jira_project_key,
+        jira_issue_type,
+        jira_priorities,
+        jira_fields,
+        ticket_alerts,
+        smtp_host,
+        smtp_port,
+        smtp_security,
+        smtp_username,
+        smtp_password,
+        smtp_from,
+        smtp_to,
+        smtp_alerts,
+        smtp_digest_hours,
+        smtp_digest_ai_summary,
+        smtp_digest_template,
+        redact_builtins,
+        redact_patterns,
+        redact_fields,
+    } = loaded;
+
+    macro_rules! reloadable {
+        ($($field:ident),* $(,)?) => {$(
+            if $field != current.$field {
+                next.$field = $field;
+                applied.push(stringify!($field));
+            }
+        )*};
+    }
+    macro_rules! restart_only {
+        ($($field:ident),* $(,)?) => {$(
+            if $field != current.$field {
+                restart_required.push(stringify!($field));
+            }
+        )*};
+    }
+
+    reloadable!(
+        auth_token,
+        sse_query_auth,
+        openrouter_api_key,
+        openrouter_model,
+        openrouter_max_retries,
+        openrouter_fallback_models,
+        chat_model_allowlist,
+        chat_model_denylist,
+        chat_max_cost_usd,
+        chat_cost_policy,
+        chat_cache_ttl_secs,
+        chat_max_tokens,
+        chat_max_tokens_limit,
+        chat_temperature,
+        chat_pii_safe,
+        chat_redact_patterns,
+        prompt_app_name,
+        system_prompt,
+        prompt_context,
+        prompt_vocabulary,
+        prompt_runbooks,
+        runbook_dir,
+        runbook_urls,
+        log_buffer_max_entries,
+        log_buffer_max_age_minutes,
+        drop_warning_percent,
+        connection_queue_capacity,
+        connection_overflow_policy,
+        self_log_level,
+        ingest_rules,
+        log_metrics,
+        slos,
+        heartbeats,
+        alert_webhook_url,
+        pagerduty_routing_key,
+        pagerduty_min_severity,
+        discord_webhook_url,
+        telegram_bot_token,
+        telegram_chat_id,
+        alert_routes,
+        alert_ai_enrichment,
+        smtp_host,
+        smtp_port,
+        smtp_security,
+        smtp_username,
+        smtp_password,
+        smtp_from,
+        smtp_to,
+        smtp_alerts,
+        smtp_digest_hours,
+        smtp_digest_ai_summary,
+        smtp_digest_template,
+        redact_builtins,
+        redact_patterns,
+        redact_fields,
+        tls_client_cns,
+        auth_users,
+        session_ttl_hours,
+        session_cookie_secure,
+        chat_monthly_budget_usd,
+        chaos_openrouter_failure_rate,
+        chaos_broadcast_lag_ms,
+        chaos_store_failure_rate,
+        channels,
+        model_pricing,
+        chat_transcripts,
+        chat_transcript_max_chars,
+        chat_transcript_retention_days,
+        openrouter_base_url,
+        chat_tool_scopes,
+        public_url,
+        github_issues_token,
+        github_issues_repo,
+        github_issues_labels,
+        github_api_url,
+        linear_api_key,
+        linear_team_id,
+        ticket_alerts,
+        jira_base_url,
+        jira_email,
+        jira_api_token,
+        jira_project_key,
+        jira_issue_type,
+        jira_priorities,
+        jira_fields,
+        stream_queue_max,
+    );
+    restart_only!(
+        fly_prod_app_name,
+        nats_url,
+        nats_user,
+        nats_password,
+        host,
+        port,
+        store_path,
+        sources,
+        syslog_bind_addr,
+        webhook_bind_addr,
+        file_tail_paths,
+        file_tail_poll_ms,
+        docker_socket,
+        docker_label_filter,
+        docker_region_label,
+        k8s_api_url,
+        k8s_namespace,
+        k8s_label_selector,
+        channel_capacity,
+        cors_allowed_origins,
+        cors_allowed_methods,
+        cors_allowed_headers,
+        cors_allow_credentials,
+        tls_cert_file,
+        tls_key_file,
+        tls_client_ca_file,
+        tls_client_auth,
+        tenants,
+        cluster_mode,
+        cluster_subject,
+        cluster_nats_url,
+        archive_path,
+        archive_retention_days,
+        clickhouse_url,
+        clickhouse_database,
+        clickhouse_table,
+        clickhouse_user,
+        clickhouse_password,
+        clickhouse_ttl_days,
+        elasticsearch_url,
+        elasticsearch_index,
+        elasticsearch_username,
+        elasticsearch_password,
+        elasticsearch_api_key,
+        kafka_rest_url,
+        kafka_topic,
+        kafka_username,
+        kafka_password,
+        sinks,
+        http_analytics_window_minutes,
+        grpc_bind_addr,
+        max_line_bytes,
+        nats_binary_formats,
+        nats_subject_templates,
+        events_subject,
+        rpc_subject,
+        nats_auth,
+        nats_tls,
+        nats_ca_file,
+        nats_client_cert_file,
+        nats_client_key_file,
+        nats_ping_interval_secs,
+        usage_retention_days,
+        openrouter_pricing_refresh_secs,
+        k8s_app_label,
+    );
+
+    (next, applied, restart_required)
+}
+
+#[cfg(test)]
+impl Config {
+    /// The required settings plus `extra` TOML, for tests in other modules
+    pub fn for_tests(extra: &str) -> Self {
+        let file = format!(
+            "fly_prod_app_name = \"app\"\norg_slug = \"org\"\naccess_token = \"t\"\n{}",
+            extra
+        );
+        let s = Settings {
+            file: file.parse().unwrap(),
+            env: |_| None,
+            used: RefCell::new(HashSet::new()),
+            problems: RefCell::new(Vec::new()),
+        };
+        let config = Self::from_settings(&s);
+        assert!(s.problems.borrow().is_empty(), "{:?}", s.problems.borrow());
+        config
+    }
+}
+
+// ==================== Settings Lookup ====================
+
+/// Layered lookup of one setting: env var first, then the config file.
+/// Problems are collected rather than raised so they can all be reported.
+struct Settings {
+    file: toml::Table,
+    env: fn(&str) -> Option<String>,
+    used: RefCell<HashSet<String>>,
+    problems: RefCell<Vec<String>>,
+}
+
+impl Settings {
+    fn problem(&self, problem: String) {
+        self.problems.borrow_mut().push(problem);
+    }
+
+    fn file_value(&self, key: &str) -> Option<&toml::Value> {
+        let file_key = key.to_lowercase();
+        let value = self.file.get(&file_key);
+        self.used.borrow_mut().insert(file_key);
+        value
+    }
+
+    fn raw(&self, key: &str) -> Option<String> {
+        let file_value = self.file_value(key);
+        if let Some(value) = (self.env)(key).filter(|v| !v.is_empty()) {
+            return Some(value);
+        }
+        match file_value? {
+            toml::Value::String(s) => Some(s.clone()).filter(|s| !s.is_empty()),
+            toml::Value::Integer(i) => Some(i.to_string()),
+            toml::Value::Float(f) => Some(f.to_string()),
+            toml::Value::Boolean(b) => Some(b.to_string()),
+            other => {
+                self.problem(format!(
+                    "{}: expected a single value, found {}",
+                    key.to_lowercase(),
+                    other.type_str()
+                ));
+                None
+            }
+        }
+    }
+
+    fn optional(&self, key: &str) -> Option<String> {
+        self.raw(key)
+    }
+
+    fn string(&self, key: &str, default: &str) -> String {
+        self.raw(key).unwrap_or_else(|| default.to_string())
+    }
+
+    fn required(&self, key: &str, hint: &str) -> String {
+        self.raw(key).unwrap_or_else(|| {
+            self.problem(format!("{} must be set ({})", key, hint));
+            String::new()
+        })
+    }
+
+    fn parse<T>(&self, key: &str, default: T) -> T
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        match self.raw(key) {
+            Some(value) => value.parse().unwrap_or_else(|e| {
+                self.problem(format!("{}: invalid value '{}': {}", key, value, e));
+                default
+            }),
+            None => default,
+        }
+    }
+
+    fn flag(&self, key: &str, default: bool) -> bool {
+        match self.raw(key).as_deref() {
+            Some("true" | "1" | "yes") => true,
+            Some("false" | "0" | "no") => false,
+            Some(other) => {
+                self.problem(format!("{}: expected true or false, found '{}'", key, other));
+                default
+            }
+            None => default,
+        }
+    }
+
+    /// A list: comma-separated in env, an array (or comma string) in the file
+    fn list(&self, key: &str) -> Option<Vec<String>> {
+        let split = |s: &str| -> Vec<String> {
+            s.split(',')
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty())
+                .collect()
+        };
+
+        let file_value = self.file_value(key);
+        if let Some(value) = (self.env)(key).filter(|v| !v.is_empty()) {
+            return Some(split(&value));
+        }
+        match file_value? {
+            toml::Value::String(s) => Some(split(s)),
+            toml::Value::Array(items) => {
+                let mut values = Vec::new();
+                for item in items {
+                    match item.as_str() {
+                        Some(v) => values.push(v.to_string()),
+                        None => self.problem(format!(
+                            "{}: list entries must be strings",
+                            key.to_lowercase()
+                        )),
+                    }
+                }
+                Some(values)
+            }
+            other => {
+                self.problem(format!(
+                    "{}: expected a list, found {}",
+                    key.to_lowercase(),
+                    other.type_str()
+                ));
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(file: &str, env: fn(&str) -> Option<String>) -> Settings {
+        Settings {
+            file: file.parse().unwrap(),
+            env,
+            used: RefCell::new(HashSet::new()),
+            problems: RefCell::new(Vec::new()),
+        }
+    }
+
+    #[test]
+    fn test_env_overrides_file() {
+        let s = settings(
+            "port = 9000\nsources = [\"nats\", \"syslog\"]",
+            |key| (key == "PORT").then(|| "9100".to_string()),
+        );
+        assert_eq!(s.parse::<u16>("PORT", 8080), 9100);
+        assert_eq!(
+            s.list("SOURCES"),
+            Some(vec!["nats".to_string(), "syslog".to_string()])
+        );
+        assert!(s.problems.borrow().is_empty());
+    }
+
+    #[test]
+    fn test_collects_every_problem() {
+        let s = settings(
+            "port = \"eighty\"\nconnection_overflow_policy = \"sometimes\"\n\
+             sources = [\"nats\", \"journald\"]\nfile_tail_poll_ms = 0",
+            |_| None,
+        );
+        let config = Config::from_settings(&s);
+        assert_eq!(config.port, 8080);
+
+        let problems = s.problems.into_inner();
+        // Three missing required values plus four invalid ones
+        assert_eq!(problems.len(), 7, "{:?}", problems);
+        assert!(problems.iter().any(|p| p.starts_with("PORT: invalid value")));
+        assert!(problems.iter().any(|p| p.starts_with("ORG_SLUG must be set")));
+        assert!(problems
+            .iter()
+            .any(|p| p.starts_with("SOURCES: unknown source kind 'journald'")));
+        assert!(problems.iter().any(|p| p.starts_with("FILE_TAIL_POLL_MS")));
+    }
+
+    #[test]
+    fn test_budgets_need_a_store() {
+        let s = settings(
+            "chat_monthly_budget_usd = 100\n\
+             tenants = [\"acme app=acme-web token=s3cret budget=50\"]",
+            |_| None,
+        );
+        Config::from_settings(&s);
+        let problems = s.problems.into_inner();
+        assert!(problems
+            .iter()
+            .any(|p| p.starts_with("CHAT_MONTHLY_BUDGET_USD needs STORE_PATH")));
+        assert!(problems
+            .iter()
+            .any(|p| p.starts_with("TENANTS: tenant 'acme' has a budget")));
+    }
+
+    #[test]
+    fn test_nats_auth_replaces_fly_credentials() {
+        let s = settings(
+            "fly_prod_app_name = \"app\"\nnats_creds_file = \"/secrets/flywatch.creds\"\nnats_ca_file = \"/certs/ca.pem\"",
+            |_| None,
+        );
+        let config = Config::from_settings(&s);
+        assert!(s.problems.borrow().is_empty(), "{:?}", s.problems.borrow());
+        assert_eq!(
+            config.nats_auth,
+            NatsAuth::CredsFile("/secrets/flywatch.creds".to_string())
+        );
+        assert!(config.nats_tls);
+
+        let s = settings(
+            "fly_prod_app_name = \"app\"\nnats_token = \"t\"\nnats_nkey_seed = \"SU\"\nnats_client_cert_file = \"/certs/client.pem\"",
+            |_| None,
+        );
+        Config::from_settings(&s);
+        let problems = s.problems.into_inner();
+        assert_eq!(problems.len(), 2, "{:?}", problems);
+        assert!(problems.iter().any(|p| p.starts_with("Set only one of NATS_CREDS_FILE")));
+    }
+
+    #[test]
+    fn test_reload_keeps_restart_only_settings() {
+        let current = Config::from_settings(&settings(
+            "fly_prod_app_name = \"app\"\norg_slug = \"org\"\naccess_token = \"t\"",
+            |_| None,
+        ));
+        let mut loaded = current.clone();
+        loaded.auth_token = Some("rotated".to_string());
+        loaded.log_buffer_max_entries = 500;
+        loaded.port = 9000;
+
+        let (next, applied, restart_required) = merge_reload(&current, loaded);
+        assert_eq!(next.auth_token.as_deref(), Some("rotated"));
+        assert_eq!(next.log_buffer_max_entries, 500);
+        assert_eq!(next.port, 8080);
+        assert_eq!(applied, vec!["auth_token", "log_buffer_max_entries"]);
+        assert_eq!(restart_required, vec!["port"]);
+    }
+}
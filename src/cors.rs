@@ -0,0 +1,101 @@
+use axum::http::{HeaderName, HeaderValue, Method};
+use tower_http::cors::{AllowHeaders, AllowMethods, AllowOrigin, Any, CorsLayer};
+
+use crate::config::Config;
+
+/// Build the CORS layer from config; unset lists keep the permissive defaults
+pub fn cors_layer(config: &Config) -> CorsLayer {
+    let mut layer = CorsLayer::new();
+
+    layer = if config.cors_allowed_origins.is_empty() {
+        layer.allow_origin(Any)
+    } else {
+        let patterns = config.cors_allowed_origins.clone();
+        layer.allow_origin(AllowOrigin::predicate(move |origin: &HeaderValue, _| {
+            origin
+                .to_str()
+                .is_ok_and(|origin| patterns.iter().any(|p| origin_matches(p, origin)))
+        }))
+    };
+
+    // Credentialed requests can't use "*", so mirror the request instead
+    layer = if !config.cors_allowed_methods.is_empty() {
+        let methods: Vec<Method> = config
+            .cors_allowed_methods
+            .iter()
+            .map(|m| {
+                m.to_uppercase()
+                    .parse()
+                    .unwrap_or_else(|_| panic!("Invalid CORS method: {}", m))
+            })
+            .collect();
+        layer.allow_methods(methods)
+    } else if config.cors_allow_credentials {
+        layer.allow_methods(AllowMethods::mirror_request())
+    } else {
+        layer.allow_methods(Any)
+    };
+
+    layer = if !config.cors_allowed_headers.is_empty() {
+        let headers: Vec<HeaderName> = config
+            .cors_allowed_headers
+            .iter()
+            .map(|h| {
+                h.parse()
+                    .unwrap_or_else(|_| panic!("Invalid CORS header: {}", h))
+            })
+            .collect();
+        layer.allow_headers(headers)
+    } else if config.cors_allow_credentials {
+        layer.allow_headers(AllowHeaders::mirror_request())
+    } else {
+        layer.allow_headers(Any)
+    };
+
+    layer.allow_credentials(config.cors_allow_credentials)
+}
+
+/// Match an origin against a pattern such as `https://app.example.com`
+/// or `https://*.example.com` (any subdomain, not the apex)
+fn origin_matches(pattern: &str, origin: &str) -> bool {
+    let pattern = pattern.trim_end_matches('/');
+    match pattern.split_once("*.") {
+        Some((scheme, domain)) => origin
+            .strip_prefix(scheme)
+            .and_then(|host| host.strip_suffix(domain))
+            .and_then(|sub| sub.strip_suffix('.'))
+            .is_some_and(|sub| !sub.is_empty() && !sub.contains('/')),
+        None => pattern.eq_ignore_ascii_case(origin),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exact_origin() {
+        assert!(origin_matches(
+            "https://app.example.com",
+            "https://app.example.com"
+        ));
+        assert!(origin_matches(
+            "https://app.example.com/",
+            "https://app.example.com"
+        ));
+        assert!(!origin_matches(
+            "https://app.example.com",
+            "http://app.example.com"
+        ));
+    }
+
+    #[test]
+    fn test_wildcard_subdomain() {
+        let pattern = "https://*.example.com";
+        assert!(origin_matches(pattern, "https://app.example.com"));
+        assert!(origin_matches(pattern, "https://a.b.example.com"));
+        assert!(!origin_matches(pattern, "https://example.com"));
+        assert!(!origin_matches(pattern, "https://evilexample.com"));
+        assert!(!origin_matches(pattern, "http://app.example.com"));
+    }
+}
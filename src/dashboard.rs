@@ -0,0 +1,33 @@
+use axum::extract::Path;
+use axum::http::header;
+use axum::response::{IntoResponse, Response};
+use rust_embed::RustEmbed;
+
+use crate::error::ApiError;
+
+/// Static dashboard UI, compiled into the binary
+#[derive(RustEmbed)]
+#[folder = "dashboard/"]
+struct Assets;
+
+pub async fn index_handler() -> Response {
+    serve("index.html")
+}
+
+pub async fn asset_handler(Path(path): Path<String>) -> Response {
+    serve(&path)
+}
+
+fn serve(path: &str) -> Response {
+    match Assets::get(path) {
+        Some(file) => (
+            [
+                (header::CONTENT_TYPE, file.metadata.mimetype().to_string()),
+                (header::CACHE_CONTROL, "no-cache".to_string()),
+            ],
+            file.data,
+        )
+            .into_response(),
+        None => ApiError::NotFound(format!("No dashboard asset '{}'", path)).into_response(),
+    }
+}
@@ -0,0 +1,428 @@
+//! Deploy markers. Release events posted to `/deploys` (by a CI step after
+//! `flyctl deploy`, or a relay for Fly's release webhooks) become a line in
+//! the log stream with `source: "deploy"` and an annotation tagged `deploy`,
+//! so streams, history and the chat agent can compare before and after a
+//! release. Events are persisted when a store is configured.
+
+use axum::{
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode},
+    Json,
+};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, RwLock};
+use stoar::Store;
+use tokio::sync::mpsc;
+use tracing::{error, info, warn};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::annotations::{AnnotationRequest, Annotations};
+use crate::error::{ApiError, ErrorBody};
+use crate::http::{check_auth, AppState};
+use crate::log_buffer::LogBuffer;
+use crate::source::Pipeline;
+
+const DEPLOY_COLLECTION: &str = "deploys";
+/// Deploys kept; older ones are forgotten
+const MAX_DEPLOYS: usize = 500;
+/// Marker lines waiting to be ingested
+const QUEUE_CAPACITY: usize = 64;
+/// Window on each side of a deploy when comparing error rates
+const COMPARE_MINUTES: i64 = 15;
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct DeployEvent {
+    pub id: String,
+    pub app: String,
+    /// Release version, e.g. "42"
+    pub version: Option<String>,
+    pub image: Option<String>,
+    /// started, succeeded, failed, ... (free-form)
+    pub status: String,
+    pub user: Option<String>,
+    pub description: Option<String>,
+    pub at: DateTime<Utc>,
+}
+
+impl DeployEvent {
+    fn summary(&self) -> String {
+        let mut text = format!("Deploy of {}", self.app);
+        if let Some(ref version) = self.version {
+            text.push_str(&format!(" v{}", version.trim_start_matches('v')));
+        }
+        text.push(' ');
+        text.push_str(&self.status);
+        if let Some(ref image) = self.image {
+            text.push_str(&format!(" ({})", image));
+        }
+        if let Some(ref user) = self.user {
+            text.push_str(&format!(" by {}", user));
+        }
+        if let Some(ref description) = self.description {
+            text.push_str(&format!(": {}", description));
+        }
+        text
+    }
+
+    /// Fly-shaped JSON so the buffer extracts app and level like any other line
+    fn marker_line(&self) -> String {
+        let level = if self.status.eq_ignore_ascii_case("failed") {
+            "error"
+        } else {
+            "info"
+        };
+        serde_json::json!({
+            "timestamp": self.at,
+            "message": self.summary(),
+            "source": "deploy",
+            "deploy": self,
+            "log": { "level": level },
+            "fly": { "app": { "name": self.app } },
+        })
+        .to_string()
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct DeployRequest {
+    /// Defaults to FLY_PROD_APP_NAME
+    pub app: Option<String>,
+    /// Release version, as a number or string
+    #[serde(default, alias = "release")]
+    #[schema(value_type = Option<String>)]
+    pub version: Option<Value>,
+    pub image: Option<String>,
+    /// Defaults to "started"
+    pub status: Option<String>,
+    #[serde(alias = "actor")]
+    pub user: Option<String>,
+    pub description: Option<String>,
+    /// Defaults to now
+    pub at: Option<DateTime<Utc>>,
+}
+
+/// Lines and errors in a window next to a deploy
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct WindowCounts {
+    pub lines: usize,
+    pub errors: usize,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct DeployComparison {
+    #[serde(flatten)]
+    pub deploy: DeployEvent,
+    pub window_minutes: i64,
+    /// Buffered lines in the window before the deploy
+    pub before: WindowCounts,
+    /// Buffered lines in the window after it (so far)
+    pub after: WindowCounts,
+}
+
+impl DeployComparison {
+    async fn collect(log_buffer: &LogBuffer, deploy: DeployEvent) -> Self {
+        let window = Duration::minutes(COMPARE_MINUTES);
+        let logs = log_buffer
+            .get_time_range(deploy.at - window, deploy.at + window)
+            .await;
+        let mut before = WindowCounts {
+            lines: 0,
+            errors: 0,
+        };
+        let mut after = WindowCounts {
+            lines: 0,
+            errors: 0,
+        };
+        for log in logs
+            .iter()
+            .filter(|l| l.source.as_deref() != Some("deploy"))
+        {
+            let side = if log.timestamp < deploy.at {
+                &mut before
+            } else {
+                &mut after
+            };
+            side.lines += 1;
+            side.errors += log.is_error() as usize;
+        }
+        Self {
+            deploy,
+            window_minutes: COMPARE_MINUTES,
+            before,
+            after,
+        }
+    }
+
+    /// One line for the chat agent
+    fn compact(&self) -> String {
+        format!(
+            "[{}] {} | {}m before: {} lines, {} errors | after: {} lines, {} errors",
+            self.deploy.at.format("%Y-%m-%d %H:%M:%S"),
+            self.deploy.summary(),
+            self.window_minutes,
+            self.before.lines,
+            self.before.errors,
+            self.after.lines,
+            self.after.errors
+        )
+    }
+}
+
+/// Recorded deploys, newest last
+pub struct Deploys {
+    events: RwLock<VecDeque<DeployEvent>>,
+    store: Option<Store>,
+    tx: mpsc::Sender<String>,
+    rx: Mutex<Option<mpsc::Receiver<String>>>,
+}
+
+impl Deploys {
+    pub fn new(store_path: Option<&str>) -> Arc<Self> {
+        let store = store_path.and_then(|path| match Store::open(path) {
+            Ok(s) => Some(s),
+            Err(e) => {
+                error!(error = %e, path = %path, "Failed to open deploy store, keeping deploys in memory");
+                None
+            }
+        });
+        let mut events: Vec<DeployEvent> = store
+            .as_ref()
+            .map(|store| {
+                store.all(DEPLOY_COLLECTION).unwrap_or_else(|e| {
+                    error!(error = %e, "Failed to load deploys");
+                    Vec::new()
+                })
+            })
+            .unwrap_or_default();
+        events.sort_by_key(|e| e.at);
+        let skip = events.len().saturating_sub(MAX_DEPLOYS);
+        let (tx, rx) = mpsc::channel(QUEUE_CAPACITY);
+        Arc::new(Self {
+            events: RwLock::new(events.into_iter().skip(skip).collect()),
+            store,
+            tx,
+            rx: Mutex::new(Some(rx)),
+        })
+    }
+
+    /// Start ingesting marker lines; only the first call has any effect
+    pub fn spawn_forwarder(&self, pipeline: Arc<Pipeline>) {
+        let Some(mut rx) = self.rx.lock().unwrap().take() else {
+            return;
+        };
+        tokio::spawn(async move {
+            while let Some(raw) = rx.recv().await {
+                pipeline.ingest(raw).await;
+            }
+        });
+    }
+
+    /// Record a deploy: a marker line in the stream and an annotation
+    pub fn record(
+        &self,
+        request: DeployRequest,
+        default_app: &str,
+        annotations: &Annotations,
+    ) -> Result<DeployEvent, String> {
+        let version = match request.version {
+            None | Some(Value::Null) => None,
+            Some(Value::String(s)) => Some(s).filter(|s| !s.is_empty()),
+            Some(Value::Number(n)) => Some(n.to_string()),
+            Some(_) => return Err("version must be a string or number".to_string()),
+        };
+        let event = DeployEvent {
+            id: uuid::Uuid::new_v4().to_string(),
+            app: request
+                .app
+                .filter(|a| !a.is_empty())
+                .unwrap_or_else(|| default_app.to_string()),
+            version,
+            image: request.image,
+            status: request
+                .status
+                .filter(|s| !s.is_empty())
+                .unwrap_or_else(|| "started".to_string()),
+            user: request.user,
+            description: request.description,
+            at: request.at.unwrap_or_else(Utc::now),
+        };
+
+        annotations.create(AnnotationRequest {
+            author: event.user.clone().unwrap_or_else(|| "deploy".to_string()),
+            text: event.summary(),
+            tags: vec!["deploy".to_string(), event.status.clone()],
+            at: Some(event.at),
+            until: None,
+            from_seq: None,
+            to_seq: None,
+            app: Some(event.app.clone()),
+        })?;
+        if self.tx.try_send(event.marker_line()).is_err() {
+            warn!(app = %event.app, "Deploy marker queue full, marker line dropped");
+        }
+        if let Some(ref store) = self.store {
+            if let Err(e) = store.put(DEPLOY_COLLECTION, &event.id, &event) {
+                error!(error = %e, "Failed to persist deploy");
+            }
+        }
+
+        let mut events = self.events.write().unwrap();
+        let index = events.partition_point(|e| e.at <= event.at);
+        events.insert(index, event.clone());
+        if events.len() > MAX_DEPLOYS {
+            if let Some(oldest) = events.pop_front() {
+                if let Some(ref store) = self.store {
+                    let _ = store.delete(DEPLOY_COLLECTION, &oldest.id);
+                }
+            }
+        }
+        info!(app = %event.app, version = ?event.version, status = %event.status, "Deploy recorded");
+        Ok(event)
+    }
+
+    /// The latest `limit` deploys of `apps` (any app when `None`), newest first
+    pub fn recent(&self, limit: usize, apps: Option<&[String]>) -> Vec<DeployEvent> {
+        self.events
+            .read()
+            .unwrap()
+            .iter()
+            .rev()
+            .filter(|e| apps.is_none_or(|apps| apps.contains(&e.app)))
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+
+    /// The latest `limit` deploys of `apps` with error counts either side of each
+    pub async fn compare(
+        &self,
+        log_buffer: &LogBuffer,
+        limit: usize,
+        apps: Option<&[String]>,
+    ) -> Vec<DeployComparison> {
+        let mut comparisons = Vec::new();
+        for deploy in self.recent(limit, apps) {
+            comparisons.push(DeployComparison::collect(log_buffer, deploy).await);
+        }
+        comparisons
+    }
+}
+
+/// Deploys compared before and after, for the chat agent
+pub fn format_comparisons(comparisons: &[DeployComparison]) -> String {
+    if comparisons.is_empty() {
+        return "No deploys recorded.".to_string();
+    }
+    comparisons
+        .iter()
+        .map(DeployComparison::compact)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct DeployQuery {
+    /// Deploys to list, newest first (default 20, max 500)
+    pub limit: Option<usize>,
+    /// Only deploys of this app
+    pub app: Option<String>,
+}
+
+/// GET /deploys - recent deploys with lines and errors either side
+#[utoipa::path(
+    get, path = "/deploys", tag = "logs",
+    params(DeployQuery),
+    responses(
+        (status = 200, description = "Recent deploys, newest first, with buffered lines and errors before and after each", body = Vec<DeployComparison>),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+pub async fn list_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<DeployQuery>,
+) -> Result<Json<Vec<DeployComparison>>, ApiError> {
+    check_auth(&state, &headers)?;
+    let limit = query.limit.unwrap_or(20).clamp(1, MAX_DEPLOYS);
+    let apps = query.app.map(|app| vec![app]);
+    Ok(Json(
+        state
+            .deploys
+            .compare(&state.log_buffer, limit, apps.as_deref())
+            .await,
+    ))
+}
+
+/// POST /deploys - record a deploy or release event
+#[utoipa::path(
+    post, path = "/deploys", tag = "logs",
+    request_body = DeployRequest,
+    responses(
+        (status = 201, description = "Deploy recorded", body = DeployEvent),
+        (status = 400, description = "Invalid deploy", body = ErrorBody),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+pub async fn create_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<DeployRequest>,
+) -> Result<(StatusCode, Json<DeployEvent>), ApiError> {
+    check_auth(&state, &headers)?;
+    let app = state.config.current().fly_prod_app_name.clone();
+    let event = state
+        .deploys
+        .record(request, &app, &state.annotations)
+        .map_err(ApiError::InvalidRequest)?;
+    Ok((StatusCode::CREATED, Json(event)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::log_buffer::TimestampedLog;
+
+    #[tokio::test]
+    async fn test_deploy_marks_timeline_and_compares() {
+        let deploys = Deploys::new(None);
+        let annotations = Annotations::new(None);
+        let at = Utc::now() - Duration::minutes(5);
+        let request: DeployRequest = serde_json::from_value(serde_json::json!({
+            "release": 42,
+            "status": "succeeded",
+            "actor": "alice",
+            "at": at,
+        }))
+        .unwrap();
+        let event = deploys.record(request, "web", &annotations).unwrap();
+        assert_eq!(event.summary(), "Deploy of web v42 succeeded by alice");
+        assert_eq!(annotations.list(None, None, Some("deploy"), None).len(), 1);
+
+        let marker = TimestampedLog::new(event.marker_line(), 1);
+        assert_eq!(marker.source.as_deref(), Some("deploy"));
+        assert_eq!(marker.app.as_deref(), Some("web"));
+        assert_eq!(marker.timestamp, at);
+
+        let buffer = LogBuffer::new(Default::default(), None);
+        for (offset, level) in [(-2, "info"), (1, "error"), (2, "error")] {
+            let line = serde_json::json!({
+                "timestamp": at + Duration::minutes(offset),
+                "message": "x",
+                "log": { "level": level },
+            });
+            buffer.push(line.to_string()).await;
+        }
+        buffer.push(event.marker_line()).await;
+        let comparisons = deploys.compare(&buffer, 5, None).await;
+        assert_eq!(
+            (comparisons[0].before.errors, comparisons[0].after.errors),
+            (0, 2)
+        );
+        assert!(format_comparisons(&comparisons).contains("after: 2 lines, 2 errors"));
+    }
+}
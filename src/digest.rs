@@ -0,0 +1,315 @@
+//! Scheduled email digests: alert activity, buffer statistics and, when
+//! OpenRouter is configured, an AI summary of the period's logs. The HTML
+//! comes from a template with `{{placeholder}}` slots (see DEFAULT_TEMPLATE).
+//! Maintenance windows during the period are called out so planned work
+//! doesn't read as an incident.
+
+use chrono::{DateTime, Duration, Utc};
+use std::time::Duration as StdDuration;
+use tracing::{info, warn};
+
+use crate::alerts::{Alert, AlertStatus};
+use crate::analysis::ResponseFormat;
+use crate::chat::{self, ChatRequest};
+use crate::config::Config;
+use crate::http::AppState;
+use crate::log_buffer::LogSummary;
+use crate::maintenance::MaintenanceWindow;
+use crate::smtp::{self, Email, SmtpSettings};
+
+const CHECK_INTERVAL: StdDuration = StdDuration::from_secs(60);
+
+/// Slots: app, period, maintenance, stats, alerts, summary
+pub const DEFAULT_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html>
+<body style="font-family: -apple-system, Segoe UI, sans-serif; color: #1f2933; max-width: 720px;">
+<h2>{{app}} digest</h2>
+<p style="color: #616e7c;">{{period}}</p>
+{{maintenance}}
+<h3>Logs</h3>
+{{stats}}
+<h3>Alerts</h3>
+{{alerts}}
+<h3>Summary</h3>
+{{summary}}
+</body>
+</html>
+"#;
+
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Fill `{{name}}` slots; values are inserted as given (already HTML)
+pub fn render_template(template: &str, values: &[(&str, String)]) -> String {
+    values
+        .iter()
+        .fold(template.to_string(), |html, (name, value)| {
+            html.replace(&format!("{{{{{}}}}}", name), value)
+        })
+}
+
+fn alert_line(alert: &Alert) -> String {
+    let state = match alert.status {
+        AlertStatus::Firing => format!("{:?}", alert.severity).to_uppercase(),
+        AlertStatus::Resolved => "RESOLVED".to_string(),
+    };
+    format!("[{}] {}: {}", state, alert.rule, alert.summary)
+}
+
+/// Everything a digest reports, gathered before rendering
+pub struct Digest {
+    pub app: String,
+    pub since: DateTime<Utc>,
+    pub until: DateTime<Utc>,
+    pub logs: LogSummary,
+    pub transitions: Vec<Alert>,
+    pub firing: Vec<Alert>,
+    /// Windows open at some point during the period
+    pub maintenance: Vec<MaintenanceWindow>,
+    pub summary: Option<String>,
+}
+
+impl Digest {
+    fn period(&self) -> String {
+        format!(
+            "{} to {} UTC",
+            self.since.format("%Y-%m-%d %H:%M"),
+            self.until.format("%Y-%m-%d %H:%M")
+        )
+    }
+
+    fn stats(&self) -> String {
+        format!(
+            "{} lines buffered, {} errors, {} warnings, {} instances",
+            self.logs.total_count,
+            self.logs.error_count,
+            self.logs.warn_count,
+            self.logs.active_instances.len()
+        )
+    }
+
+    /// "Maintenance in progress: ..." or None if there was none
+    fn maintenance(&self) -> Option<String> {
+        if self.maintenance.is_empty() {
+            return None;
+        }
+        let windows: Vec<String> = self
+            .maintenance
+            .iter()
+            .map(|w| match w.open_until(self.until) {
+                Some(end) => format!("{} (until {} UTC)", w.name, end.format("%H:%M")),
+                None => format!("{} (ended)", w.name),
+            })
+            .collect();
+        Some(format!("Maintenance in progress: {}", windows.join(", ")))
+    }
+
+    pub fn email(&self, template: &str) -> Email {
+        let list = |alerts: &[Alert]| -> String {
+            let items: String = alerts
+                .iter()
+                .map(|a| format!("<li>{}</li>", escape_html(&alert_line(a))))
+                .collect();
+            format!("<ul>{}</ul>", items)
+        };
+        let alerts = if self.transitions.is_empty() && self.firing.is_empty() {
+            "<p>No alerts.</p>".to_string()
+        } else {
+            format!(
+                "<p>Firing now:</p>{}<p>During the period:</p>{}",
+                list(&self.firing),
+                list(&self.transitions)
+            )
+        };
+        let summary = match self.summary {
+            Some(ref text) => format!(
+                "<pre style=\"white-space: pre-wrap; font-family: inherit;\">{}</pre>",
+                escape_html(text)
+            ),
+            None => "<p>No AI summary (OpenRouter not configured).</p>".to_string(),
+        };
+        let html = render_template(
+            template,
+            &[
+                ("app", escape_html(&self.app)),
+                ("period", escape_html(&self.period())),
+                (
+                    "maintenance",
+                    self.maintenance()
+                        .map(|note| format!("<p><strong>{}</strong></p>", escape_html(&note)))
+                        .unwrap_or_default(),
+                ),
+                ("stats", format!("<p>{}</p>", escape_html(&self.stats()))),
+                ("alerts", alerts),
+                ("summary", summary),
+            ],
+        );
+
+        let mut text = format!("{} digest\n{}\n\n", self.app, self.period());
+        if let Some(note) = self.maintenance() {
+            text.push_str(&note);
+            text.push_str("\n\n");
+        }
+        text.push_str(&self.stats());
+        text.push_str("\n\nAlerts:\n");
+        for alert in self.firing.iter().chain(&self.transitions) {
+            text.push_str(&alert_line(alert));
+            text.push('\n');
+        }
+        if let Some(ref summary) = self.summary {
+            text.push_str("\nSummary:\n");
+            text.push_str(summary);
+            text.push('\n');
+        }
+
+        Email {
+            subject: format!(
+                "{} digest: {} errors, {} alerts firing",
+                self.app,
+                self.logs.error_count,
+                self.firing.len()
+            ),
+            text,
+            html,
+        }
+    }
+}
+
+async fn ai_summary(
+    state: &AppState,
+    config: &Config,
+    hours: u64,
+    maintenance: &[MaintenanceWindow],
+) -> Option<String> {
+    if !config.smtp_digest_ai_summary || config.openrouter_api_key.is_none() {
+        return None;
+    }
+    let mut message = format!(
+        "Write a short digest of the last {} hours of logs for the on-call team: \
+         the most important errors and anomalies, which regions or instances \
+         they came from, and anything that needs follow-up. Plain text, no tables.",
+        hours
+    );
+    if !maintenance.is_empty() {
+        let names: Vec<&str> = maintenance.iter().map(|w| w.name.as_str()).collect();
+        message.push_str(&format!(
+            " Planned maintenance was in progress ({}); say so, and treat errors \
+             from it as expected rather than incidents.",
+            names.join(", ")
+        ));
+    }
+    let request = ChatRequest {
+        message,
+        model: None,
+        conversation_id: Some(format!("digest-{}", Utc::now().format("%Y%m%dT%H%M"))),
+        max_tokens: None,
+        temperature: None,
+        response_format: ResponseFormat::Text,
+    };
+    match chat::run_chat(state, request, None).await {
+        Ok(response) => Some(response.response),
+        Err(e) => {
+            warn!(error = ?e, "Digest summary failed");
+            None
+        }
+    }
+}
+
+async fn send_digest(
+    state: &AppState,
+    config: &Config,
+    settings: &SmtpSettings,
+    since: DateTime<Utc>,
+) {
+    let until = Utc::now();
+    let maintenance = state.alerts.maintenance().overlapping(since, until);
+    let digest = Digest {
+        app: config.fly_prod_app_name.clone(),
+        since,
+        until,
+        logs: state.log_buffer.get_summary().await,
+        transitions: state.alerts.history_since(since),
+        firing: state.alerts.active(),
+        summary: ai_summary(state, config, config.smtp_digest_hours, &maintenance).await,
+        maintenance,
+    };
+    let template = config
+        .smtp_digest_template
+        .as_deref()
+        .unwrap_or(DEFAULT_TEMPLATE);
+    match smtp::send(settings, &digest.email(template)).await {
+        Ok(()) => info!(recipients = settings.to.len(), "Digest sent"),
+        Err(e) => warn!(error = %e, "Digest delivery failed"),
+    }
+}
+
+/// Send a digest every SMTP_DIGEST_HOURS while SMTP is configured
+pub async fn digest_scheduler(state: AppState) {
+    let mut last = Utc::now();
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+        let config = state.config.current();
+        let settings = SmtpSettings::from_config(&config);
+        let (Some(settings), hours @ 1..) = (settings, config.smtp_digest_hours) else {
+            // Disabled: start the period over once it is turned on
+            last = Utc::now();
+            continue;
+        };
+        if Utc::now() - last < Duration::hours(hours as i64) {
+            continue;
+        }
+        let since = std::mem::replace(&mut last, Utc::now());
+        send_digest(&state, &config, &settings, since).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alerts::Severity;
+
+    #[test]
+    fn test_digest_renders_escaped_template() {
+        let alert = Alert::new(
+            "slo:api",
+            "burn_rate",
+            Severity::Critical,
+            "p99 <script> over budget".to_string(),
+        );
+        let digest = Digest {
+            app: "checkout".to_string(),
+            since: Utc::now() - Duration::hours(24),
+            until: Utc::now(),
+            logs: LogSummary {
+                total_count: 100,
+                oldest_timestamp: None,
+                newest_timestamp: None,
+                error_count: 7,
+                warn_count: 3,
+                recent_errors: Vec::new(),
+                active_instances: vec!["m1".to_string()],
+            },
+            transitions: vec![alert.clone()],
+            firing: vec![alert],
+            maintenance: Vec::new(),
+            summary: Some("All quiet & calm".to_string()),
+        };
+
+        let email = digest.email("<h1>{{app}}</h1>{{maintenance}}{{alerts}}{{summary}}");
+        assert_eq!(email.subject, "checkout digest: 7 errors, 1 alerts firing");
+        assert!(email.html.starts_with("<h1>checkout</h1>"));
+        assert!(email
+            .html
+            .contains("[CRITICAL] slo:api: p99 &lt;script&gt; over budget"));
+        assert!(email.html.contains("All quiet &amp; calm"));
+        assert!(!email.text.contains("Maintenance"));
+        assert!(email
+            .text
+            .contains("[CRITICAL] slo:api: p99 <script> over budget"));
+    }
+}
@@ -0,0 +1,371 @@
+use async_trait::async_trait;
+use bytes::Bytes;
+use http_body_util::{BodyExt, Empty};
+use hyper::body::Incoming;
+use hyper::{Request, Response};
+use hyper_util::rt::TokioIo;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+use tokio::net::UnixStream;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+use crate::source::{
+    drain_lines, fly_envelope, json_line_level, LogSource, SourceContext, SourceError,
+};
+
+const DOCKER_QUEUE_CAPACITY: usize = 10_000;
+const CONTAINER_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Streams stdout/stderr of running containers from the Docker Engine API
+pub struct DockerSource {
+    socket_path: String,
+    label_filter: Option<String>,
+    region_label: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ContainerSummary {
+    id: String,
+    names: Vec<String>,
+    #[serde(default)]
+    labels: HashMap<String, String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ContainerInspect {
+    config: ContainerConfig,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ContainerConfig {
+    #[serde(default)]
+    tty: bool,
+}
+
+impl DockerSource {
+    pub fn new(socket_path: String, label_filter: Option<String>, region_label: String) -> Self {
+        Self {
+            socket_path,
+            label_filter,
+            region_label,
+        }
+    }
+
+    async fn list_containers(&self) -> Result<Vec<ContainerSummary>, SourceError> {
+        let path = match &self.label_filter {
+            Some(label) => {
+                let filters = serde_json::json!({ "label": [label] }).to_string();
+                format!("/containers/json?filters={}", percent_encode(&filters))
+            }
+            None => "/containers/json".to_string(),
+        };
+        docker_get_json(&self.socket_path, &path).await
+    }
+}
+
+#[async_trait]
+impl LogSource for DockerSource {
+    fn name(&self) -> &str {
+        "docker"
+    }
+
+    fn kind(&self) -> &'static str {
+        "docker"
+    }
+
+    async fn run(&self, ctx: &SourceContext) -> Result<(), SourceError> {
+        let (tx, mut rx) = mpsc::channel::<String>(DOCKER_QUEUE_CAPACITY);
+        let mut streams: HashMap<String, JoinHandle<()>> = HashMap::new();
+        let mut interval = tokio::time::interval(CONTAINER_POLL_INTERVAL);
+        let mut first_poll = true;
+
+        let result = loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    let containers = match self.list_containers().await {
+                        Ok(c) => c,
+                        Err(e) => break Err(e),
+                    };
+                    if first_poll {
+                        info!(socket = %self.socket_path, containers = containers.len(), "Connected to Docker");
+                        ctx.running().await;
+                        first_poll = false;
+                    }
+
+                    // Drop handles for streams that ended (container stopped)
+                    streams.retain(|_, handle| !handle.is_finished());
+                    let live: HashSet<&str> = containers.iter().map(|c| c.id.as_str()).collect();
+                    streams.retain(|id, handle| {
+                        let keep = live.contains(id.as_str());
+                        if !keep {
+                            handle.abort();
+                        }
+                        keep
+                    });
+
+                    for container in containers {
+                        if streams.contains_key(&container.id) {
+                            continue;
+                        }
+                        let name = container
+                            .names
+                            .first()
+                            .map(|n| n.trim_start_matches('/').to_string())
+                            .unwrap_or_else(|| container.id.chars().take(12).collect());
+                        let region = container.labels.get(&self.region_label).cloned();
+
+                        info!(container = %name, "Streaming container logs");
+                        let handle = tokio::spawn(stream_container(
+                            self.socket_path.clone(),
+                            container.id.clone(),
+                            name,
+                            region,
+                            tx.clone(),
+                        ));
+                        streams.insert(container.id, handle);
+                    }
+                }
+
+                Some(raw) = rx.recv() => {
+                    ctx.emit(raw).await;
+                }
+            }
+        };
+
+        for (_, handle) in streams {
+            handle.abort();
+        }
+        result
+    }
+}
+
+/// Follow one container's log stream, forwarding each line as Fly-style JSON
+async fn stream_container(
+    socket_path: String,
+    id: String,
+    name: String,
+    region: Option<String>,
+    tx: mpsc::Sender<String>,
+) {
+    let tty = match docker_get_json::<ContainerInspect>(
+        &socket_path,
+        &format!("/containers/{}/json", id),
+    )
+    .await
+    {
+        Ok(inspect) => inspect.config.tty,
+        Err(e) => {
+            warn!(container = %name, error = %e, "Failed to inspect container");
+            return;
+        }
+    };
+
+    let since = chrono::Utc::now().timestamp();
+    let path = format!(
+        "/containers/{}/logs?follow=1&stdout=1&stderr=1&since={}",
+        id, since
+    );
+    let mut body = match docker_get(&socket_path, &path).await {
+        Ok(resp) => resp.into_body(),
+        Err(e) => {
+            warn!(container = %name, error = %e, "Failed to open container log stream");
+            return;
+        }
+    };
+
+    let mut demux = Demuxer::new(tty);
+    while let Some(frame) = body.frame().await {
+        let Ok(frame) = frame else { break };
+        let Some(data) = frame.data_ref() else {
+            continue;
+        };
+
+        for (stream, line) in demux.push(data) {
+            // Like most log collectors, treat unstructured stderr output as errors
+            let level = json_line_level(&line).or(match stream {
+                StdStream::Stderr => Some("error"),
+                StdStream::Stdout => None,
+            });
+            let raw = fly_envelope(&line, level, Some(&name), region.as_deref());
+            if tx.send(raw).await.is_err() {
+                return;
+            }
+        }
+    }
+
+    info!(container = %name, "Container log stream ended");
+}
+
+// ==================== Stream Demultiplexing ====================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StdStream {
+    Stdout,
+    Stderr,
+}
+
+/// Decoder for Docker's multiplexed log stream (8-byte frame headers),
+/// or a passthrough when the container has a TTY
+struct Demuxer {
+    tty: bool,
+    pending: Vec<u8>,
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+}
+
+impl Demuxer {
+    fn new(tty: bool) -> Self {
+        Self {
+            tty,
+            pending: Vec::new(),
+            stdout: Vec::new(),
+            stderr: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, data: &[u8]) -> Vec<(StdStream, String)> {
+        if self.tty {
+            self.stdout.extend_from_slice(data);
+            return drain_lines(&mut self.stdout)
+                .into_iter()
+                .map(|l| (StdStream::Stdout, l))
+                .collect();
+        }
+
+        self.pending.extend_from_slice(data);
+        let mut lines = Vec::new();
+
+        while self.pending.len() >= 8 {
+            let size = u32::from_be_bytes([
+                self.pending[4],
+                self.pending[5],
+                self.pending[6],
+                self.pending[7],
+            ]) as usize;
+            if self.pending.len() < 8 + size {
+                break;
+            }
+
+            let stream = if self.pending[0] == 2 {
+                StdStream::Stderr
+            } else {
+                StdStream::Stdout
+            };
+            let payload: Vec<u8> = self.pending.drain(..8 + size).skip(8).collect();
+            let buf = match stream {
+                StdStream::Stdout => &mut self.stdout,
+                StdStream::Stderr => &mut self.stderr,
+            };
+            buf.extend_from_slice(&payload);
+            lines.extend(drain_lines(buf).into_iter().map(|l| (stream, l)));
+        }
+
+        lines
+    }
+}
+
+// ==================== Docker Engine API ====================
+
+async fn docker_get(socket_path: &str, path: &str) -> Result<Response<Incoming>, SourceError> {
+    let stream = UnixStream::connect(socket_path)
+        .await
+        .map_err(|e| SourceError::Connect(format!("{}: {}", socket_path, e)))?;
+
+    let (mut sender, conn) = hyper::client::conn::http1::handshake(TokioIo::new(stream))
+        .await
+        .map_err(|e| SourceError::Connect(e.to_string()))?;
+    tokio::spawn(conn);
+
+    let request = Request::get(path)
+        .header(hyper::header::HOST, "docker")
+        .body(Empty::<Bytes>::new())
+        .map_err(|e| SourceError::Io(e.to_string()))?;
+
+    let response = sender
+        .send_request(request)
+        .await
+        .map_err(|e| SourceError::Io(e.to_string()))?;
+
+    if !response.status().is_success() {
+        return Err(SourceError::Io(format!(
+            "Docker API {} returned {}",
+            path,
+            response.status()
+        )));
+    }
+    Ok(response)
+}
+
+async fn docker_get_json<T: serde::de::DeserializeOwned>(
+    socket_path: &str,
+    path: &str,
+) -> Result<T, SourceError> {
+    let body = docker_get(socket_path, path)
+        .await?
+        .into_body()
+        .collect()
+        .await
+        .map_err(|e| SourceError::Io(e.to_string()))?
+        .to_bytes();
+    serde_json::from_slice(&body)
+        .map_err(|e| SourceError::Io(format!("invalid Docker response: {}", e)))
+}
+
+fn percent_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(stream: u8, payload: &[u8]) -> Vec<u8> {
+        let mut f = vec![stream, 0, 0, 0];
+        f.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        f.extend_from_slice(payload);
+        f
+    }
+
+    #[test]
+    fn test_demux_multiplexed_frames() {
+        let mut demux = Demuxer::new(false);
+        let mut data = frame(1, b"hello\n");
+        data.extend(frame(2, b"oops\npart"));
+
+        // Split mid-header to exercise buffering
+        let (a, b) = data.split_at(5);
+        assert!(demux.push(a).is_empty());
+        let lines = demux.push(b);
+        assert_eq!(
+            lines,
+            vec![
+                (StdStream::Stdout, "hello".to_string()),
+                (StdStream::Stderr, "oops".to_string()),
+            ]
+        );
+
+        let lines = demux.push(&frame(2, b"ial\n"));
+        assert_eq!(lines, vec![(StdStream::Stderr, "partial".to_string())]);
+    }
+
+    #[test]
+    fn test_demux_tty_passthrough() {
+        let mut demux = Demuxer::new(true);
+        let lines = demux.push(b"one\ntwo\n");
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1].1, "two");
+    }
+}
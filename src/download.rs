@@ -0,0 +1,191 @@
+//! Log exports for postmortems: a time range of the buffer (which includes
+//! entries restored from the store) as a JSON Lines or CSV attachment.
+
+use axum::{
+    body::Body,
+    extract::{Query, State},
+    http::{header, HeaderMap},
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::convert::Infallible;
+use tracing::info;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::error::{ApiError, ErrorBody};
+use crate::filter::FilterParams;
+use crate::http::{check_auth, parse_timestamp, AppState};
+use crate::log_buffer::TimestampedLog;
+
+/// Lines per body chunk
+const CHUNK_LINES: usize = 500;
+
+const CSV_HEADER: &str = "seq,timestamp,level,app,region,instance,source,message,raw\n";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum DownloadFormat {
+    /// One `TimestampedLog` JSON object per line
+    #[default]
+    Ndjson,
+    Csv,
+}
+
+impl DownloadFormat {
+    fn content_type(self) -> &'static str {
+        match self {
+            Self::Ndjson => "application/x-ndjson",
+            Self::Csv => "text/csv; charset=utf-8",
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Self::Ndjson => "ndjson",
+            Self::Csv => "csv",
+        }
+    }
+
+    fn line(self, log: &TimestampedLog) -> String {
+        match self {
+            Self::Ndjson => {
+                let mut line = serde_json::to_string(log).unwrap_or_default();
+                line.push('\n');
+                line
+            }
+            Self::Csv => {
+                let fields = [
+                    log.seq.to_string(),
+                    log.timestamp.to_rfc3339(),
+                    log.level.clone().unwrap_or_default(),
+                    log.app.clone().unwrap_or_default(),
+                    log.region.clone().unwrap_or_default(),
+                    log.instance.clone().unwrap_or_default(),
+                    log.source.clone().unwrap_or_default(),
+                    log.message.clone().unwrap_or_default(),
+                    log.raw.clone(),
+                ];
+                let mut line = fields.map(|f| csv_field(&f)).join(",");
+                line.push('\n');
+                line
+            }
+        }
+    }
+}
+
+/// Quote a CSV field (RFC 4180) when it needs it
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct DownloadQuery {
+    /// RFC3339 start (default: the oldest buffered log)
+    from: Option<String>,
+    /// RFC3339 end (default: now)
+    to: Option<String>,
+    /// ndjson (default) or csv
+    #[serde(default)]
+    #[param(inline)]
+    format: DownloadFormat,
+}
+
+/// GET /logs/download - a time range of logs as a file
+#[utoipa::path(
+    get, path = "/logs/download", tag = "logs",
+    params(DownloadQuery, FilterParams),
+    responses(
+        (status = 200, description = "Matching logs, oldest first, as an attachment", content_type = "application/x-ndjson", body = String),
+        (status = 400, description = "Invalid parameters", body = ErrorBody),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+pub async fn download_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<DownloadQuery>,
+    Query(filter): Query<FilterParams>,
+) -> Result<Response, ApiError> {
+    check_auth(&state, &headers)?;
+
+    // A view's lookback applies when `from` is unset
+    let filter = filter.compile(&state.views)?;
+    let from = match query.from {
+        Some(ts) => parse_timestamp("from", &ts)?,
+        None => filter.since(Utc::now()).unwrap_or(DateTime::<Utc>::MIN_UTC),
+    };
+    let to = match query.to {
+        Some(ts) => parse_timestamp("to", &ts)?,
+        None => Utc::now(),
+    };
+    if from > to {
+        return Err(ApiError::InvalidRequest(
+            "'from' must be before 'to'".to_string(),
+        ));
+    }
+
+    let mut logs = state.log_buffer.get_time_range(from, to).await;
+    logs.retain(|log| filter.matches(log));
+    info!(count = logs.len(), format = ?query.format, "Serving log download");
+
+    let format = query.format;
+    let filename = format!(
+        "flywatch-{}-{}-{}.{}",
+        state.config.current().fly_prod_app_name,
+        logs.first()
+            .map_or(from, |l| l.timestamp)
+            .format("%Y%m%dT%H%M%SZ"),
+        to.format("%Y%m%dT%H%M%SZ"),
+        format.extension()
+    );
+    let csv_header = (format == DownloadFormat::Csv).then(|| CSV_HEADER.to_string());
+    let len = logs.len();
+    // Format one chunk at a time as the client reads
+    let lines = (0..len).step_by(CHUNK_LINES).map(move |start| {
+        logs[start..len.min(start + CHUNK_LINES)]
+            .iter()
+            .map(|log| format.line(log))
+            .collect::<String>()
+    });
+    let body = futures::stream::iter(csv_header.into_iter().chain(lines).map(Ok::<_, Infallible>));
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, format.content_type().to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", filename),
+            ),
+        ],
+        Body::from_stream(body),
+    )
+        .into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_csv_quotes_fields_that_need_it() {
+        let mut log = TimestampedLog::new(r#"{"message":"GET /a, then \"b\""}"#.to_string(), 7);
+        log.message = Some("GET /a, then \"b\"\nretrying".to_string());
+        let line = DownloadFormat::Csv.line(&log);
+        assert!(line.starts_with("7,"));
+        assert!(line.contains(",\"GET /a, then \"\"b\"\"\nretrying\","));
+        assert_eq!(csv_field("plain"), "plain");
+
+        let ndjson = DownloadFormat::Ndjson.line(&log);
+        assert_eq!(ndjson.matches('\n').count(), 1);
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&ndjson).unwrap()["seq"],
+            7
+        );
+    }
+}
@@ -0,0 +1,247 @@
+//! Elasticsearch / OpenSearch sink for teams on the ELK stack: every
+//! buffered line is shipped through the `_bulk` API into daily indices
+//! (`<ELASTICSEARCH_INDEX>-YYYY.MM.DD`). An index template with the field
+//! mappings is installed on first use; items the cluster rejects while
+//! overloaded are sent again.
+
+use async_trait::async_trait;
+use reqwest::{Client, RequestBuilder};
+use serde::Deserialize;
+use serde_json::json;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tracing::info;
+
+use crate::config::Config;
+use crate::log_buffer::TimestampedLog;
+use crate::nats::LogMessage;
+use crate::sink::{response_error, Rejection, Sink, SinkDefaults, SinkError};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub struct Elasticsearch {
+    client: Client,
+    url: String,
+    index: String,
+    username: Option<String>,
+    password: Option<String>,
+    api_key: Option<String>,
+    template_installed: AtomicBool,
+}
+
+#[derive(Deserialize)]
+struct BulkResponse {
+    errors: bool,
+    items: Vec<serde_json::Map<String, serde_json::Value>>,
+}
+
+/// Items a bulk request didn't index
+fn failed_items(response: &BulkResponse) -> Vec<Rejection> {
+    if !response.errors {
+        return Vec::new();
+    }
+    response
+        .items
+        .iter()
+        .enumerate()
+        .filter_map(|(i, item)| {
+            let result = item.values().next()?;
+            let status = result["status"].as_u64().unwrap_or(0);
+            if (200..300).contains(&status) {
+                return None;
+            }
+            // Back-pressure and node failures; mapping errors won't improve
+            Some(Rejection {
+                index: i,
+                retryable: status == 429 || status >= 500,
+                reason: format!("{}: {}", status, result["error"]),
+            })
+        })
+        .collect()
+}
+
+impl Elasticsearch {
+    /// None without ELASTICSEARCH_URL
+    pub fn new(config: &Config) -> Option<Self> {
+        let url = config.elasticsearch_url.clone()?;
+        let client = Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .expect("Failed to build HTTP client");
+        let elasticsearch = Self {
+            client,
+            url: url.trim_end_matches('/').to_string(),
+            index: config.elasticsearch_index.clone(),
+            username: config.elasticsearch_username.clone(),
+            password: config.elasticsearch_password.clone(),
+            api_key: config.elasticsearch_api_key.clone(),
+            template_installed: AtomicBool::new(false),
+        };
+        info!(
+            url = %elasticsearch.url,
+            index = %format!("{}-*", elasticsearch.index),
+            "Elasticsearch export enabled"
+        );
+        Some(elasticsearch)
+    }
+
+    fn authorize(&self, request: RequestBuilder) -> RequestBuilder {
+        match (&self.api_key, &self.username) {
+            (Some(key), _) => request.header("Authorization", format!("ApiKey {}", key)),
+            (None, Some(user)) => request.basic_auth(user, self.password.as_deref()),
+            (None, None) => request,
+        }
+    }
+
+    fn template(&self) -> serde_json::Value {
+        let keyword = json!({ "type": "keyword" });
+        json!({
+            "index_patterns": [format!("{}-*", self.index)],
+            "template": {
+                "mappings": {
+                    "dynamic": false,
+                    "properties": {
+                        "@timestamp": { "type": "date" },
+                        "received_at": { "type": "date" },
+                        "seq": { "type": "long" },
+                        "app": keyword,
+                        "instance": keyword,
+                        "region": keyword,
+                        "level": keyword,
+                        "source": keyword,
+                        "message": { "type": "text" },
+                        "raw": { "type": "text", "index": false },
+                    }
+                }
+            }
+        })
+    }
+
+    async fn install_template(&self) -> Result<(), SinkError> {
+        let url = format!("{}/_index_template/{}", self.url, self.index);
+        let response = self
+            .authorize(self.client.put(url))
+            .json(&self.template())
+            .send()
+            .await
+            .map_err(|e| SinkError::Unavailable(e.to_string()))?;
+        if !response.status().is_success() {
+            return Err(match response_error(response).await {
+                SinkError::Unavailable(e) => {
+                    SinkError::Unavailable(format!("index template: {}", e))
+                }
+                SinkError::Rejected(e) => SinkError::Rejected(format!("index template: {}", e)),
+            });
+        }
+        Ok(())
+    }
+
+    /// `_bulk` body: an index action and the document for each line
+    fn bulk_body(&self, batch: &[LogMessage]) -> String {
+        let mut body = String::new();
+        for log in batch {
+            let index = format!("{}-{}", self.index, log.timestamp.format("%Y.%m.%d"));
+            body.push_str(&json!({ "index": { "_index": index } }).to_string());
+            body.push('\n');
+            body.push_str(&document(log).to_string());
+            body.push('\n');
+        }
+        body
+    }
+}
+
+#[async_trait]
+impl Sink for Elasticsearch {
+    fn kind(&self) -> &'static str {
+        "elasticsearch"
+    }
+
+    fn defaults(&self) -> SinkDefaults {
+        SinkDefaults {
+            queue_capacity: 50_000,
+            batch_size: 2_000,
+            flush_interval: Duration::from_secs(2),
+            max_attempts: 5,
+        }
+    }
+
+    /// Install the template once, then one `_bulk` request
+    async fn send(&self, batch: &[LogMessage]) -> Result<Vec<Rejection>, SinkError> {
+        if !self.template_installed.load(Ordering::Relaxed) {
+            self.install_template().await?;
+            self.template_installed.store(true, Ordering::Relaxed);
+        }
+        let response = self
+            .authorize(self.client.post(format!("{}/_bulk", self.url)))
+            .header("Content-Type", "application/x-ndjson")
+            .body(self.bulk_body(batch))
+            .send()
+            .await
+            .map_err(|e| SinkError::Unavailable(e.to_string()))?;
+        if !response.status().is_success() {
+            return Err(response_error(response).await);
+        }
+        let response: BulkResponse = response
+            .json()
+            .await
+            .map_err(|e| SinkError::Unavailable(e.to_string()))?;
+        Ok(failed_items(&response))
+    }
+}
+
+fn document(log: &TimestampedLog) -> serde_json::Value {
+    json!({
+        "@timestamp": log.timestamp,
+        "received_at": log.received_at,
+        "seq": log.seq,
+        "app": log.app,
+        "instance": log.instance,
+        "region": log.region,
+        "level": log.level,
+        "source": log.source,
+        "message": log.message,
+        "raw": log.raw,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bulk_body_and_item_failures() {
+        let config = Config::for_tests("elasticsearch_url = \"http://localhost:9200/\"");
+        let elasticsearch = Elasticsearch::new(&config).unwrap();
+        let log: LogMessage = std::sync::Arc::new(TimestampedLog::new(
+            r#"{"timestamp":"2026-01-05T10:00:00Z","message":"boom","fly":{"app":{"name":"web"}}}"#
+                .to_string(),
+            1,
+        ));
+        let body = elasticsearch.bulk_body(&[log]);
+        let lines: Vec<serde_json::Value> = body
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines[0]["index"]["_index"], "flywatch-logs-2026.01.05");
+        assert_eq!(lines[1]["app"], "web");
+        assert_eq!(lines[1]["@timestamp"], "2026-01-05T10:00:00Z");
+        assert_eq!(
+            elasticsearch.template()["index_patterns"][0],
+            "flywatch-logs-*"
+        );
+
+        let response: BulkResponse = serde_json::from_value(json!({
+            "errors": true,
+            "items": [
+                { "index": { "status": 201 } },
+                { "index": { "status": 429, "error": { "type": "es_rejected_execution_exception" } } },
+                { "index": { "status": 400, "error": { "type": "mapper_parsing_exception" } } },
+            ]
+        }))
+        .unwrap();
+        let failed = failed_items(&response);
+        assert_eq!(failed.len(), 2);
+        assert_eq!((failed[0].index, failed[0].retryable), (1, true));
+        assert_eq!((failed[1].index, failed[1].retryable), (2, false));
+    }
+}
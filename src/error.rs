@@ -0,0 +1,332 @@
+use axum::{
+    body::Body,
+    extract::Request,
+    http::{header, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use std::fmt;
+use utoipa::ToSchema;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Largest plain-text error body rewritten into JSON; anything bigger is
+/// not an axum rejection and passes through untouched
+const MAX_REWRITE_BODY: usize = 16 * 1024;
+
+tokio::task_local! {
+    /// Id of the request the current handler is serving
+    static REQUEST_ID: String;
+}
+
+/// The request id of the current handler, if inside one
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(Clone::clone).ok()
+}
+
+/// Error returned by every HTTP handler. Renders as
+/// `{"code", "error", "status", "request_id", "details"?}` where `code` is a
+/// stable machine-readable identifier and `error` a human-readable message.
+#[derive(Debug)]
+pub enum ApiError {
+    Unauthorized(String),
+    Forbidden(String),
+    InvalidRequest(String),
+    NotFound(String),
+    MethodNotAllowed(String),
+    InvalidConfig(Vec<String>),
+    NotReady(String),
+    NotConfigured(String),
+    Upstream(String),
+    Internal(String),
+}
+
+/// JSON body of every error response
+#[derive(Serialize, ToSchema)]
+#[schema(as = ApiError)]
+pub struct ErrorBody {
+    code: &'static str,
+    error: String,
+    status: u16,
+    request_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    details: Option<serde_json::Value>,
+}
+
+impl ApiError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ApiError::InvalidRequest(_) | ApiError::InvalidConfig(_) => StatusCode::BAD_REQUEST,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::MethodNotAllowed(_) => StatusCode::METHOD_NOT_ALLOWED,
+            ApiError::NotReady(_) | ApiError::NotConfigured(_) => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::Upstream(_) => StatusCode::BAD_GATEWAY,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::Unauthorized(_) => "unauthorized",
+            ApiError::Forbidden(_) => "forbidden",
+            ApiError::InvalidRequest(_) => "invalid_request",
+            ApiError::NotFound(_) => "not_found",
+            ApiError::MethodNotAllowed(_) => "method_not_allowed",
+            ApiError::InvalidConfig(_) => "invalid_config",
+            ApiError::NotReady(_) => "not_ready",
+            ApiError::NotConfigured(_) => "not_configured",
+            ApiError::Upstream(_) => "upstream_error",
+            ApiError::Internal(_) => "internal_error",
+        }
+    }
+
+    /// Map a bare status (e.g. from an extractor rejection) onto an error
+    fn from_status(status: StatusCode, message: String) -> Self {
+        match status {
+            StatusCode::UNAUTHORIZED => ApiError::Unauthorized(message),
+            StatusCode::FORBIDDEN => ApiError::Forbidden(message),
+            StatusCode::NOT_FOUND => ApiError::NotFound(message),
+            StatusCode::METHOD_NOT_ALLOWED => ApiError::MethodNotAllowed(message),
+            StatusCode::SERVICE_UNAVAILABLE => ApiError::NotReady(message),
+            s if s.is_client_error() => ApiError::InvalidRequest(message),
+            _ => ApiError::Internal(message),
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::InvalidConfig(problems) => {
+                write!(f, "Invalid configuration: {}", problems.join("; "))
+            }
+            ApiError::Unauthorized(msg)
+            | ApiError::Forbidden(msg)
+            | ApiError::InvalidRequest(msg)
+            | ApiError::NotFound(msg)
+            | ApiError::MethodNotAllowed(msg)
+            | ApiError::NotReady(msg)
+            | ApiError::NotConfigured(msg)
+            | ApiError::Upstream(msg)
+            | ApiError::Internal(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        let code = self.code();
+        let (error, details) = match self {
+            ApiError::InvalidConfig(problems) => (
+                "Invalid configuration".to_string(),
+                Some(serde_json::json!({ "problems": problems })),
+            ),
+            other => (other.to_string(), None),
+        };
+        let body = ErrorBody {
+            code,
+            error,
+            status: status.as_u16(),
+            request_id: current_request_id(),
+            details,
+        };
+        (status, Json(body)).into_response()
+    }
+}
+
+/// Router fallback for unknown paths
+pub async fn not_found() -> ApiError {
+    ApiError::NotFound("No such route".to_string())
+}
+
+/// Assign each request an id (honoring an incoming X-Request-Id), make it
+/// available to error bodies and the trace span, and echo it on the response.
+/// Must wrap the TraceLayer so the span can read it from the request headers.
+pub async fn request_id(mut request: Request, next: Next) -> Response {
+    let id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| !v.is_empty() && v.len() <= 128)
+        .map(str::to_string)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let header_value = HeaderValue::from_str(&id).ok();
+    if let Some(ref value) = header_value {
+        request
+            .headers_mut()
+            .insert(REQUEST_ID_HEADER, value.clone());
+    }
+
+    let mut response = REQUEST_ID.scope(id, next.run(request)).await;
+    if let Some(value) = header_value {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
+
+/// Root span for each HTTP request, tagged with its request id so every
+/// event logged while handling it can be correlated
+pub fn request_span(request: &Request) -> tracing::Span {
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("");
+    tracing::info_span!(
+        "request",
+        method = %request.method(),
+        uri = %loggable_uri(request.uri()),
+        request_id = %request_id,
+    )
+}
+
+/// Query parameters that carry credentials and never reach the logs
+const SECRET_PARAMS: &[&str] = &["access_token", "ticket"];
+
+/// The request URI with credential query values masked
+fn loggable_uri(uri: &axum::http::Uri) -> String {
+    let Some(query) = uri.query() else {
+        return uri.to_string();
+    };
+    let masked: Vec<String> = query
+        .split('&')
+        .map(|pair| match pair.split_once('=') {
+            Some((key, _)) if SECRET_PARAMS.contains(&key) => format!("{}=REDACTED", key),
+            _ => pair.to_string(),
+        })
+        .collect();
+    format!("{}?{}", uri.path(), masked.join("&"))
+}
+
+/// Rewrite plain-text error responses (axum extractor rejections, 404/405
+/// from routing) into the ApiError JSON shape. Runs inside `request_id`.
+pub async fn json_errors(request: Request, next: Next) -> Response {
+    let response = next.run(request).await;
+    let status = response.status();
+    let is_text = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_none_or(|ct| ct.starts_with("text/plain"));
+    if !(status.is_client_error() || status.is_server_error()) || !is_text {
+        return response;
+    }
+
+    let (parts, body) = response.into_parts();
+    let bytes = match axum::body::to_bytes(body, MAX_REWRITE_BODY).await {
+        Ok(bytes) => bytes,
+        Err(_) => return Response::from_parts(parts, Body::empty()),
+    };
+    let message = match String::from_utf8_lossy(&bytes).trim() {
+        "" => status
+            .canonical_reason()
+            .unwrap_or("Request failed")
+            .to_string(),
+        text => text.to_string(),
+    };
+
+    let mut rewritten = ApiError::from_status(status, message).into_response();
+    // Keep the original status and any headers like WWW-Authenticate or Allow
+    *rewritten.status_mut() = status;
+    for (name, value) in parts.headers.iter() {
+        if name != header::CONTENT_TYPE && name != header::CONTENT_LENGTH {
+            rewritten.headers_mut().insert(name.clone(), value.clone());
+        }
+    }
+    rewritten
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn body_json(response: Response) -> serde_json::Value {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_error_body_shape() {
+        let response = REQUEST_ID
+            .scope("req-1".to_string(), async {
+                ApiError::NotFound("Connection not found".into()).into_response()
+            })
+            .await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let body = body_json(response).await;
+        assert_eq!(body["code"], "not_found");
+        assert_eq!(body["error"], "Connection not found");
+        assert_eq!(body["status"], 404);
+        assert_eq!(body["request_id"], "req-1");
+        assert!(body.get("details").is_none());
+    }
+
+    #[test]
+    fn test_credentials_are_masked_in_logged_uris() {
+        let uri = "/logs/stream?format=json&access_token=s3cret&ticket=abc.def"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            loggable_uri(&uri),
+            "/logs/stream?format=json&access_token=REDACTED&ticket=REDACTED"
+        );
+        assert_eq!(loggable_uri(&"/health".parse().unwrap()), "/health");
+    }
+
+    #[tokio::test]
+    async fn test_request_id_is_honored_and_echoed() {
+        use tower::ServiceExt;
+
+        let app = axum::Router::new()
+            .route(
+                "/",
+                axum::routing::get(|| async { current_request_id().unwrap() }),
+            )
+            .fallback(not_found)
+            .layer(axum::middleware::from_fn(json_errors))
+            .layer(axum::middleware::from_fn(request_id));
+
+        let request = Request::builder()
+            .uri("/")
+            .header(REQUEST_ID_HEADER, "client-7")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.headers()[REQUEST_ID_HEADER], "client-7");
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(&bytes[..], b"client-7");
+
+        // Generated ids also reach rewritten errors
+        let request = Request::builder()
+            .uri("/missing")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        let header = response.headers()[REQUEST_ID_HEADER]
+            .to_str()
+            .unwrap()
+            .to_string();
+        let body = body_json(response).await;
+        assert_eq!(body["code"], "not_found");
+        assert_eq!(body["request_id"], header.as_str());
+    }
+
+    #[tokio::test]
+    async fn test_invalid_config_lists_problems() {
+        let response = ApiError::InvalidConfig(vec!["PORT: invalid value".into()]).into_response();
+        let body = body_json(response).await;
+        assert_eq!(body["code"], "invalid_config");
+        assert_eq!(body["details"]["problems"][0], "PORT: invalid value");
+        assert!(body["request_id"].is_null());
+    }
+}
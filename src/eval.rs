@@ -0,0 +1,289 @@
+//! Eval harness for the chat agent: `cargo test --features eval eval`.
+//!
+//! Each scenario in `evals/*.toml` is a set of log lines, a question and the
+//! findings a good answer contains. The lines are loaded into a fresh buffer,
+//! the question goes through the same `run_chat` pipeline as `/chat`, and the
+//! answer is scored on the `expect` regexes it matches (ignoring case) and
+//! the `tools` the agent called.
+//!
+//! By default the model is a mock that replays each scenario's `[[mock]]`
+//! turns, which checks the pipeline and the tool calls deterministically.
+//! With EVAL_OPENROUTER_API_KEY set, the real provider answers instead
+//! (EVAL_MODEL picks the model), to compare prompt and tool changes before a
+//! deploy. Scenarios scoring under EVAL_MIN_SCORE (default 1.0 with the mock,
+//! 0.8 with a real model) fail the run; EVAL_SCENARIO runs just one.
+
+use axum::{extract::State, routing::post, Json, Router};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use crate::chat::{run_chat, ChatRequest};
+use crate::config::Config;
+use crate::http::AppState;
+use crate::source::fly_envelope;
+
+const SCENARIO_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/evals");
+
+#[derive(Debug, Deserialize)]
+struct Scenario {
+    question: String,
+    /// `structured` to ask for validated JSON findings
+    #[serde(default)]
+    response_format: Option<String>,
+    /// Regexes a good answer matches, ignoring case
+    #[serde(default)]
+    expect: Vec<String>,
+    /// Tools a good run calls
+    #[serde(default)]
+    tools: Vec<String>,
+    #[serde(default)]
+    logs: Vec<ScenarioLog>,
+    /// The mock model's replies, one per model call
+    #[serde(default)]
+    mock: Vec<MockTurn>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ScenarioLog {
+    message: String,
+    level: Option<String>,
+    instance: Option<String>,
+    region: Option<String>,
+    /// Times the line is repeated
+    #[serde(default = "one")]
+    count: usize,
+}
+
+fn one() -> usize {
+    1
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct MockTurn {
+    /// Call this tool with `arguments`...
+    tool: Option<String>,
+    #[serde(default)]
+    arguments: Option<toml::Value>,
+    /// ...or answer
+    answer: Option<String>,
+}
+
+/// An OpenAI-compatible endpoint replaying the current scenario's turns
+async fn start_mock(turns: Arc<Mutex<Vec<MockTurn>>>) -> String {
+    async fn complete(
+        State(turns): State<Arc<Mutex<Vec<MockTurn>>>>,
+        Json(body): Json<Value>,
+    ) -> Json<Value> {
+        // One reply per model call: the assistant turns so far say which
+        let step = body["messages"]
+            .as_array()
+            .map_or(0, |m| m.iter().filter(|m| m["role"] == "assistant").count());
+        let turn = turns.lock().unwrap().get(step).cloned();
+        let message = match turn {
+            Some(MockTurn {
+                tool: Some(tool),
+                arguments,
+                ..
+            }) => json!({
+                "role": "assistant",
+                "content": null,
+                "tool_calls": [{
+                    "id": format!("call-{}", step),
+                    "type": "function",
+                    "function": {
+                        "name": tool,
+                        "arguments": serde_json::to_string(&arguments.unwrap_or(toml::Value::Table(Default::default()))).unwrap(),
+                    },
+                }],
+            }),
+            Some(MockTurn {
+                answer: Some(answer),
+                ..
+            }) => json!({"role": "assistant", "content": answer}),
+            _ => json!({"role": "assistant", "content": "The mock has no reply for this step."}),
+        };
+        Json(json!({
+            "id": format!("mock-{}", step),
+            "model": body["model"],
+            "choices": [{"message": message}],
+        }))
+    }
+
+    let app = Router::new()
+        .route("/chat/completions", post(complete))
+        .with_state(turns);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await });
+    format!("http://{}", addr)
+}
+
+#[derive(Debug)]
+struct Score {
+    name: String,
+    found: usize,
+    expected: usize,
+    missing: Vec<String>,
+}
+
+impl Score {
+    fn value(&self) -> f64 {
+        match self.expected {
+            0 => 1.0,
+            expected => self.found as f64 / expected as f64,
+        }
+    }
+}
+
+fn score(name: &str, scenario: &Scenario, answer: &str, tools_called: &[String]) -> Score {
+    let mut missing = Vec::new();
+    for finding in &scenario.expect {
+        let pattern = regex::RegexBuilder::new(finding)
+            .case_insensitive(true)
+            .build()
+            .unwrap_or_else(|e| panic!("{}: invalid finding '{}': {}", name, finding, e));
+        if !pattern.is_match(answer) {
+            missing.push(format!("finding /{}/", finding));
+        }
+    }
+    for tool in &scenario.tools {
+        let call = format!("{}(", tool);
+        if !tools_called.iter().any(|c| c.starts_with(&call)) {
+            missing.push(format!("tool {}", tool));
+        }
+    }
+    let expected = scenario.expect.len() + scenario.tools.len();
+    Score {
+        name: name.to_string(),
+        found: expected - missing.len(),
+        expected,
+        missing,
+    }
+}
+
+fn load_scenarios() -> Vec<(String, Scenario)> {
+    let only = std::env::var("EVAL_SCENARIO").ok();
+    let mut paths: Vec<_> = std::fs::read_dir(SCENARIO_DIR)
+        .expect("evals/ is readable")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|e| e == "toml"))
+        .collect();
+    paths.sort();
+    paths
+        .into_iter()
+        .filter_map(|path| {
+            let name = path.file_stem()?.to_string_lossy().into_owned();
+            if only.as_ref().is_some_and(|only| *only != name) {
+                return None;
+            }
+            let text = std::fs::read_to_string(&path).unwrap();
+            let scenario = toml::from_str(&text)
+                .unwrap_or_else(|e| panic!("{}: {}", Path::new(&path).display(), e));
+            Some((name, scenario))
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn eval() {
+    let real_key = std::env::var("EVAL_OPENROUTER_API_KEY").ok();
+    let turns = Arc::new(Mutex::new(Vec::new()));
+    let mut settings = String::from("chat_cache_ttl_secs = 0\nopenrouter_max_retries = 0\n");
+    match &real_key {
+        Some(key) => {
+            settings.push_str(&format!("openrouter_api_key = {:?}\n", key));
+            if let Ok(model) = std::env::var("EVAL_MODEL") {
+                settings.push_str(&format!("openrouter_model = {:?}\n", model));
+            }
+        }
+        None => settings.push_str(&format!(
+            "openrouter_api_key = \"mock\"\nopenrouter_base_url = {:?}\n",
+            start_mock(turns.clone()).await
+        )),
+    }
+    let min_score = std::env::var("EVAL_MIN_SCORE")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(if real_key.is_some() { 0.8 } else { 1.0 });
+
+    let scenarios = load_scenarios();
+    assert!(!scenarios.is_empty(), "no scenarios in {}", SCENARIO_DIR);
+    let mut scores = Vec::new();
+    for (name, scenario) in &scenarios {
+        // A fresh buffer per scenario, so one's lines don't leak into the next
+        let state = AppState::for_tests(Config::for_tests(&settings));
+        for log in &scenario.logs {
+            let line = fly_envelope(
+                &log.message,
+                log.level.as_deref(),
+                log.instance.as_deref(),
+                log.region.as_deref(),
+            );
+            for _ in 0..log.count {
+                state.log_buffer.push(line.clone()).await;
+            }
+        }
+        *turns.lock().unwrap() = scenario.mock.clone();
+
+        let request: ChatRequest = serde_json::from_value(json!({
+            "message": scenario.question,
+            "response_format": scenario.response_format.as_deref().unwrap_or("text"),
+        }))
+        .unwrap();
+        let score = match run_chat(&state, request, None).await {
+            Ok(response) => score(name, scenario, &response.response, &response.tools_called),
+            Err(e) => Score {
+                name: name.clone(),
+                found: 0,
+                expected: scenario.expect.len() + scenario.tools.len(),
+                missing: vec![format!("chat failed: {:?}", e)],
+            },
+        };
+        println!(
+            "{:<24} {:>5.2} ({}/{}){}",
+            score.name,
+            score.value(),
+            score.found,
+            score.expected,
+            match score.missing.is_empty() {
+                true => String::new(),
+                false => format!("  missing: {}", score.missing.join(", ")),
+            }
+        );
+        scores.push(score);
+    }
+
+    let failed: Vec<&str> = scores
+        .iter()
+        .filter(|s| s.value() < min_score)
+        .map(|s| s.name.as_str())
+        .collect();
+    assert!(
+        failed.is_empty(),
+        "scenarios under {:.2}: {}",
+        min_score,
+        failed.join(", ")
+    );
+}
+
+#[test]
+fn test_score_counts_findings_and_tools() {
+    let scenario: Scenario = toml::from_str(
+        r#"
+        question = "q"
+        expect = ["payments", "50\\d"]
+        tools = ["get_logs", "get_facets"]
+        "#,
+    )
+    .unwrap();
+    let score = score(
+        "s",
+        &scenario,
+        "PAYMENTS returns 502",
+        &["get_logs({})".to_string()],
+    );
+    assert_eq!((score.found, score.expected), (3, 4));
+    assert_eq!(score.missing, vec!["tool get_facets"]);
+}
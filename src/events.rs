@@ -0,0 +1,211 @@
+//! flywatch's own events on NATS, for internal services that would rather
+//! react than poll the HTTP API. With EVENTS_SUBJECT set (say
+//! `flywatch.events`), flywatch publishes:
+//!
+//! - `<subject>.alerts.firing` and `<subject>.alerts.resolved`: alert
+//!   transitions as the `Alert` JSON. Heartbeat and SLO burn-rate alerts are
+//!   how anomalies surface, so they arrive here too. This is the `nats`
+//!   notifier, so ALERT_ROUTES and silences apply to it like any other.
+//! - `<subject>.health`: a readiness check passing or failing, or a log
+//!   source changing status, including each one's first state at startup.
+
+use async_nats::Client;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::alerts::{Alert, AlertStatus, Notifier};
+use crate::config::Config;
+use crate::http::AppState;
+use crate::nats;
+use crate::readiness;
+
+/// How often health is compared with the last published state
+const HEALTH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Publishes events under `EVENTS_SUBJECT`
+pub struct EventPublisher {
+    subject: String,
+    app: String,
+    client: Client,
+}
+
+impl EventPublisher {
+    /// Connect when EVENTS_SUBJECT is set; the client keeps reconnecting on
+    /// its own, so a NATS outage only loses the events published during it
+    pub async fn connect(config: &Config) -> Option<Arc<Self>> {
+        let subject = config.events_subject.clone()?;
+        match nats::connect(&config.nats_url, config).await {
+            Ok(client) => {
+                info!(subject = %subject, "Publishing flywatch events to NATS");
+                Some(Arc::new(Self {
+                    subject,
+                    app: config.fly_prod_app_name.clone(),
+                    client,
+                }))
+            }
+            Err(e) => {
+                warn!(error = %e, "Failed to connect the event publisher to NATS");
+                None
+            }
+        }
+    }
+
+    pub async fn publish(&self, kind: &str, event: &impl Serialize) -> Result<(), String> {
+        let payload = serde_json::to_vec(event).map_err(|e| e.to_string())?;
+        self.client
+            .publish(format!("{}.{}", self.subject, kind), payload.into())
+            .await
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// Delivers alert transitions as `alerts.firing` and `alerts.resolved`
+pub struct NatsNotifier {
+    events: Arc<EventPublisher>,
+}
+
+impl NatsNotifier {
+    pub fn new(events: Arc<EventPublisher>) -> Self {
+        Self { events }
+    }
+}
+
+#[async_trait]
+impl Notifier for NatsNotifier {
+    fn name(&self) -> &'static str {
+        "nats"
+    }
+
+    async fn notify(&self, alert: &Alert) -> Result<(), String> {
+        let kind = match alert.status {
+            AlertStatus::Firing => "alerts.firing",
+            AlertStatus::Resolved => "alerts.resolved",
+        };
+        self.events.publish(kind, alert).await
+    }
+}
+
+/// One component changing state
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HealthEvent {
+    /// The app this flywatch monitors
+    pub app: String,
+    /// `readiness:<check>` or `source:<name>`
+    pub component: String,
+    /// `ok` or `failing` for readiness checks, the status for sources
+    pub status: String,
+    /// Unset for the first state seen after startup
+    pub previous: Option<String>,
+    pub detail: String,
+    pub at: DateTime<Utc>,
+}
+
+/// The last published state of each component
+#[derive(Default)]
+struct HealthTracker {
+    last: HashMap<String, String>,
+}
+
+impl HealthTracker {
+    /// Events for components whose status differs from the last one seen;
+    /// each entry is (component, status, detail)
+    fn changes(&mut self, app: &str, current: Vec<(String, String, String)>) -> Vec<HealthEvent> {
+        let at = Utc::now();
+        let mut events = Vec::new();
+        for (component, status, detail) in current {
+            let previous = self.last.get(&component);
+            if previous == Some(&status) {
+                continue;
+            }
+            events.push(HealthEvent {
+                app: app.to_string(),
+                previous: previous.cloned(),
+                component: component.clone(),
+                status: status.clone(),
+                detail,
+                at,
+            });
+            self.last.insert(component, status);
+        }
+        events
+    }
+}
+
+async fn health_states(state: &AppState) -> Vec<(String, String, String)> {
+    let mut states: Vec<(String, String, String)> = readiness::report(state)
+        .checks
+        .into_iter()
+        .map(|check| {
+            let status = if check.ok { "ok" } else { "failing" };
+            (
+                format!("readiness:{}", check.name),
+                status.to_string(),
+                check.detail,
+            )
+        })
+        .collect();
+    for source in state.sources.snapshot().await {
+        let status = serde_json::to_value(source.status)
+            .ok()
+            .and_then(|s| s.as_str().map(str::to_string))
+            .unwrap_or_default();
+        states.push((
+            format!("source:{}", source.name),
+            status,
+            source.last_error.unwrap_or_default(),
+        ));
+    }
+    states
+}
+
+/// Publish health transitions as they happen
+pub async fn health_publisher(state: AppState, events: Arc<EventPublisher>) {
+    let mut tracker = HealthTracker::default();
+    let mut interval = tokio::time::interval(HEALTH_INTERVAL);
+    loop {
+        interval.tick().await;
+        let current = health_states(&state).await;
+        for event in tracker.changes(&events.app, current) {
+            if let Err(e) = events.publish("health", &event).await {
+                warn!(component = %event.component, error = %e, "Failed to publish health event");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_health_tracker_reports_transitions_only() {
+        let state = |nats: &str, source: &str| {
+            vec![
+                (
+                    "readiness:nats".to_string(),
+                    nats.to_string(),
+                    String::new(),
+                ),
+                ("source:nats".to_string(), source.to_string(), String::new()),
+            ]
+        };
+        let mut tracker = HealthTracker::default();
+
+        let first = tracker.changes("app", state("ok", "running"));
+        assert_eq!(first.len(), 2);
+        assert!(first.iter().all(|e| e.previous.is_none()));
+
+        assert!(tracker.changes("app", state("ok", "running")).is_empty());
+
+        let changed = tracker.changes("app", state("failing", "running"));
+        assert_eq!(changed.len(), 1);
+        assert_eq!(changed[0].component, "readiness:nats");
+        assert_eq!(changed[0].status, "failing");
+        assert_eq!(changed[0].previous.as_deref(), Some("ok"));
+    }
+}
@@ -0,0 +1,205 @@
+//! Value counts per field over a recent window of the buffer: which levels,
+//! instances, regions, apps and sources are represented and how often.
+//! Served at `/logs/facets` for UI filter dropdowns and through the chat
+//! agent's `get_facets` tool.
+
+use axum::{
+    extract::{Query, State},
+    http::HeaderMap,
+    Json,
+};
+use chrono::{Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::error::{ApiError, ErrorBody};
+use crate::http::{check_auth, AppState};
+use crate::log_buffer::{LogBuffer, TimestampedLog};
+use crate::slo::parse_window;
+
+/// Fields facets can be counted over
+pub const FACET_FIELDS: [&str; 5] = ["level", "instance", "region", "app", "source"];
+
+const DEFAULT_WINDOW: &str = "15m";
+/// Values listed per field in the chat summary
+const SUMMARY_VALUES: usize = 15;
+
+fn field_value<'a>(log: &'a TimestampedLog, field: &str) -> Option<&'a String> {
+    match field {
+        "level" => log.level.as_ref(),
+        "instance" => log.instance.as_ref(),
+        "region" => log.region.as_ref(),
+        "app" => log.app.as_ref(),
+        "source" => log.source.as_ref(),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct FacetsResponse {
+    pub window_minutes: i64,
+    /// Lines in the window; lines without a field aren't counted under it
+    pub total: u64,
+    /// Field name to value counts
+    pub facets: BTreeMap<String, BTreeMap<String, u64>>,
+}
+
+impl FacetsResponse {
+    /// Count the values of `fields` over the last `window_minutes`
+    pub async fn collect(log_buffer: &LogBuffer, fields: &[&str], window_minutes: i64) -> Self {
+        let mut facets: BTreeMap<String, BTreeMap<String, u64>> = fields
+            .iter()
+            .map(|field| (field.to_string(), BTreeMap::new()))
+            .collect();
+        let mut total = 0;
+        let since = Utc::now() - Duration::minutes(window_minutes);
+        log_buffer
+            .for_each_since(since, |log| {
+                total += 1;
+                for (field, counts) in facets.iter_mut() {
+                    if let Some(value) = field_value(log, field) {
+                        match counts.get_mut(value) {
+                            Some(count) => *count += 1,
+                            None => {
+                                counts.insert(value.clone(), 1);
+                            }
+                        }
+                    }
+                }
+            })
+            .await;
+        Self {
+            window_minutes,
+            total,
+            facets,
+        }
+    }
+
+    /// Compact text for the chat agent, most common values first
+    pub fn summarize(&self) -> String {
+        let mut out = format!(
+            "{} lines in the last {} minutes",
+            self.total, self.window_minutes
+        );
+        for (field, counts) in &self.facets {
+            let mut values: Vec<(&String, &u64)> = counts.iter().collect();
+            values.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+            let listed: Vec<String> = values
+                .iter()
+                .take(SUMMARY_VALUES)
+                .map(|(value, count)| format!("{} {}", value, count))
+                .collect();
+            let more = values.len().saturating_sub(SUMMARY_VALUES);
+            out.push_str(&format!(
+                "\n{} ({} distinct): {}",
+                field,
+                values.len(),
+                if listed.is_empty() {
+                    "none".to_string()
+                } else {
+                    listed.join(", ")
+                }
+            ));
+            if more > 0 {
+                out.push_str(&format!(", and {} more", more));
+            }
+        }
+        out
+    }
+}
+
+/// Resolve requested field names (comma-separated; all when empty)
+pub fn parse_fields(fields: Option<&str>) -> Result<Vec<&'static str>, String> {
+    let Some(fields) = fields.filter(|f| !f.trim().is_empty()) else {
+        return Ok(FACET_FIELDS.to_vec());
+    };
+    let mut parsed = Vec::new();
+    for name in fields.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+        let field = FACET_FIELDS.iter().find(|f| **f == name).ok_or_else(|| {
+            format!(
+                "Unknown facet field: {}. Use {}.",
+                name,
+                FACET_FIELDS.join(", ")
+            )
+        })?;
+        if !parsed.contains(field) {
+            parsed.push(*field);
+        }
+    }
+    Ok(parsed)
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct FacetsQuery {
+    /// Comma-separated fields: level, instance, region, app, source (default all)
+    pub field: Option<String>,
+    /// Lookback such as 15m, 2h or 1d (default 15m)
+    pub window: Option<String>,
+}
+
+/// GET /logs/facets - value counts per field over a recent window
+#[utoipa::path(
+    get, path = "/logs/facets", tag = "logs",
+    params(FacetsQuery),
+    responses(
+        (status = 200, description = "Value counts per field", body = FacetsResponse),
+        (status = 400, description = "Unknown field or invalid window", body = ErrorBody),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+pub async fn facets_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<FacetsQuery>,
+) -> Result<Json<FacetsResponse>, ApiError> {
+    check_auth(&state, &headers)?;
+    let fields = parse_fields(query.field.as_deref()).map_err(ApiError::InvalidRequest)?;
+    let window = query.window.as_deref().unwrap_or(DEFAULT_WINDOW);
+    let minutes = parse_window(window).ok_or_else(|| {
+        ApiError::InvalidRequest(format!(
+            "Invalid window: {}. Use minutes, hours or days such as 15m, 2h or 1d.",
+            window
+        ))
+    })?;
+    Ok(Json(
+        FacetsResponse::collect(&state.log_buffer, &fields, minutes).await,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::fly_envelope;
+
+    #[tokio::test]
+    async fn test_facets_count_values_per_field() {
+        let buffer = LogBuffer::new(Default::default(), None);
+        for (level, instance, region) in [
+            ("info", "web-1", "iad"),
+            ("error", "web-1", "iad"),
+            ("info", "web-2", "ord"),
+        ] {
+            buffer
+                .push(fly_envelope("x", Some(level), Some(instance), Some(region)))
+                .await;
+        }
+        buffer.push("plain text".to_string()).await;
+
+        let fields = parse_fields(Some("region, level,region")).unwrap();
+        assert_eq!(fields, vec!["region", "level"]);
+        let facets = FacetsResponse::collect(&buffer, &fields, 15).await;
+        assert_eq!(facets.total, 4);
+        assert_eq!(facets.facets["region"]["iad"], 2);
+        assert_eq!(facets.facets["region"]["ord"], 1);
+        assert_eq!(facets.facets["level"]["info"], 2);
+        assert!(!facets.facets.contains_key("instance"));
+        assert!(facets
+            .summarize()
+            .contains("level (2 distinct): info 2, error 1"));
+
+        assert_eq!(parse_fields(None).unwrap().len(), FACET_FIELDS.len());
+        assert!(parse_fields(Some("host")).is_err());
+    }
+}
@@ -0,0 +1,701 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use tokio::sync::{broadcast, Notify};
+use tracing::{info, warn};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::channels::{self, ChannelRule, ChannelStats};
+use crate::filter::Filter;
+use crate::metrics::Metrics;
+use crate::nats::LogMessage;
+
+/// What to do when a connection's queue is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OverflowPolicy {
+    /// Evict the oldest queued message to make room
+    DropOldest,
+    /// Discard the incoming message
+    DropNewest,
+    /// Close the connection
+    Disconnect,
+}
+
+impl FromStr for OverflowPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().replace('-', "_").as_str() {
+            "drop_oldest" => Ok(Self::DropOldest),
+            "drop_newest" => Ok(Self::DropNewest),
+            "disconnect" => Ok(Self::Disconnect),
+            other => Err(format!(
+                "Invalid overflow policy: {}. Use drop_oldest, drop_newest, or disconnect.",
+                other
+            )),
+        }
+    }
+}
+
+/// Why a subscription stopped yielding messages
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecvError {
+    /// Messages were dropped by the overflow policy since the last recv
+    Lagged(u64),
+    /// Queue overflowed under the disconnect policy
+    Overflowed,
+    /// Force-disconnected through the connections API
+    Evicted,
+    /// The log channel closed
+    Closed,
+}
+
+/// Who is on the other end of a streaming connection
+#[derive(Debug, Clone)]
+pub struct ConnectionInfo {
+    pub kind: &'static str,
+    pub remote_addr: String,
+    /// Query parameters the client connected with
+    pub filters: BTreeMap<String, String>,
+}
+
+/// Bounded message queue for one streaming connection
+struct ConnectionQueue {
+    id: Uuid,
+    info: ConnectionInfo,
+    /// Only lines routed into this channel; every line when unset
+    channel: Option<String>,
+    connected_at: DateTime<Utc>,
+    capacity: usize,
+    policy: OverflowPolicy,
+    messages: Mutex<VecDeque<LogMessage>>,
+    notify: Notify,
+    /// Drops not yet reported to the consumer
+    pending_lag: AtomicU64,
+    dropped: AtomicU64,
+    delivered: AtomicU64,
+    overflowed: AtomicBool,
+    evicted: AtomicBool,
+}
+
+/// What happened to a message offered to a connection queue
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Push {
+    Queued,
+    /// Queued or discarded, but a message was lost to the overflow policy
+    Dropped,
+    /// Queue was full under the disconnect policy
+    Overflowed,
+}
+
+impl ConnectionQueue {
+    fn push(&self, msg: LogMessage) -> Push {
+        let mut messages = self.messages.lock().unwrap();
+        let mut outcome = Push::Queued;
+        if messages.len() >= self.capacity {
+            match self.policy {
+                OverflowPolicy::DropOldest => {
+                    messages.pop_front();
+                    self.record_drop();
+                    outcome = Push::Dropped;
+                }
+                OverflowPolicy::DropNewest => {
+                    self.record_drop();
+                    return Push::Dropped;
+                }
+                OverflowPolicy::Disconnect => {
+                    self.overflowed.store(true, Ordering::SeqCst);
+                    drop(messages);
+                    self.notify.notify_one();
+                    return Push::Overflowed;
+                }
+            }
+        }
+        messages.push_back(msg);
+        drop(messages);
+        self.notify.notify_one();
+        outcome
+    }
+
+    fn record_drop(&self) {
+        self.dropped.fetch_add(1, Ordering::SeqCst);
+        self.pending_lag.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+/// Queue depth and drop counters for one connection
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct QueueStats {
+    pub connection_id: Uuid,
+    pub kind: &'static str,
+    pub policy: OverflowPolicy,
+    pub capacity: usize,
+    /// Messages waiting to be sent (current lag)
+    pub queued: usize,
+    pub dropped: u64,
+    pub delivered: u64,
+}
+
+/// One active streaming connection, as listed by `GET /connections`
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ConnectionSnapshot {
+    pub id: Uuid,
+    pub kind: &'static str,
+    pub remote_addr: String,
+    pub connected_at: DateTime<Utc>,
+    pub filters: BTreeMap<String, String>,
+    /// Channel subscribed to; unset for every line
+    pub channel: Option<String>,
+    pub messages_sent: u64,
+    /// Messages queued but not yet sent
+    pub lag: usize,
+    pub dropped: u64,
+    pub policy: OverflowPolicy,
+}
+
+/// Class of the fanout dispatcher itself in consumer drop metrics
+const DISPATCHER_CONSUMER: &str = "fanout";
+
+/// A channel rule and the lines routed into it
+struct RoutedChannel {
+    rule: ChannelRule,
+    routed: AtomicU64,
+}
+
+/// Distributes broadcast log messages into per-connection bounded queues,
+/// so one slow consumer only ever affects its own queue
+pub struct Fanout {
+    metrics: Arc<Metrics>,
+    queues: RwLock<HashMap<Uuid, Arc<ConnectionQueue>>>,
+    closed: AtomicBool,
+    /// Capacity and policy for connections that don't override them
+    defaults: RwLock<(usize, OverflowPolicy)>,
+    /// Chaos testing: how long consumers stall after each message, in ms
+    lag_ms: AtomicU64,
+    /// Named channels lines are routed into (see channels)
+    channels: RwLock<Vec<RoutedChannel>>,
+    /// Lines dispatched, i.e. routed into `all`
+    dispatched: AtomicU64,
+}
+
+impl Fanout {
+    pub fn new(
+        default_capacity: usize,
+        default_policy: OverflowPolicy,
+        metrics: Arc<Metrics>,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            metrics,
+            queues: RwLock::new(HashMap::new()),
+            closed: AtomicBool::new(false),
+            defaults: RwLock::new((default_capacity, default_policy)),
+            lag_ms: AtomicU64::new(0),
+            channels: RwLock::new(Vec::new()),
+            dispatched: AtomicU64::new(0),
+        })
+    }
+
+    /// Change the defaults for new connections; existing queues keep theirs
+    pub fn set_defaults(&self, capacity: usize, policy: OverflowPolicy) {
+        *self.defaults.write().unwrap() = (capacity, policy);
+    }
+
+    /// Make every consumer stall this long after each message it receives,
+    /// so queues overflow and the lag handling runs (chaos testing)
+    pub fn set_artificial_lag(&self, lag: Duration) {
+        self.lag_ms.store(lag.as_millis() as u64, Ordering::SeqCst);
+    }
+
+    /// Replace the channel rules; unchanged rules keep their counts.
+    /// Connections on a removed channel stay open but receive nothing.
+    pub fn set_channels(&self, rules: &[ChannelRule]) {
+        let mut channels = self.channels.write().unwrap();
+        let kept: Vec<RoutedChannel> = rules
+            .iter()
+            .map(|rule| RoutedChannel {
+                rule: rule.clone(),
+                routed: AtomicU64::new(
+                    channels
+                        .iter()
+                        .find(|c| c.rule == *rule)
+                        .map_or(0, |c| c.routed.load(Ordering::Relaxed)),
+                ),
+            })
+            .collect();
+        *channels = kept;
+    }
+
+    /// The conditions of a channel, for lines replayed from the buffer;
+    /// `None` when there is no such channel
+    pub fn channel_filter(&self, name: &str) -> Option<Filter> {
+        if name == channels::ALL {
+            return Some(Filter::default());
+        }
+        self.channels
+            .read()
+            .unwrap()
+            .iter()
+            .find(|c| c.rule.name == name)
+            .map(|c| c.rule.filter.clone())
+    }
+
+    /// `all` first, then the configured channels in order
+    pub fn channels(&self) -> Vec<ChannelStats> {
+        let queues = self.queues.read().unwrap();
+        let subscribers = |name: Option<&str>| {
+            queues
+                .values()
+                .filter(|q| q.channel.as_deref() == name)
+                .count()
+        };
+        let mut stats = vec![ChannelStats {
+            name: channels::ALL.to_string(),
+            rule: None,
+            routed: self.dispatched.load(Ordering::Relaxed),
+            subscribers: subscribers(None),
+        }];
+        stats.extend(self.channels.read().unwrap().iter().map(|c| ChannelStats {
+            name: c.rule.name.clone(),
+            rule: Some(c.rule.to_string()),
+            routed: c.routed.load(Ordering::Relaxed),
+            subscribers: subscribers(Some(&c.rule.name)),
+        }));
+        stats
+    }
+
+    /// Spawn the dispatcher that copies every broadcast message into each queue
+    pub fn start(self: &Arc<Self>, mut rx: broadcast::Receiver<LogMessage>) {
+        let fanout = self.clone();
+        tokio::spawn(async move {
+            loop {
+                match rx.recv().await {
+                    Ok(msg) => {
+                        fanout.metrics.record_consumer(DISPATCHER_CONSUMER, 1, 0);
+                        fanout.dispatch(msg);
+                    }
+                    Err(broadcast::error::RecvError::Lagged(n)) => {
+                        warn!(skipped = n, "Fanout dispatcher lagged");
+                        fanout.metrics.record_consumer(DISPATCHER_CONSUMER, n, n);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+
+            fanout.closed.store(true, Ordering::SeqCst);
+            for queue in fanout.queues.read().unwrap().values() {
+                queue.notify.notify_one();
+            }
+            info!("Fanout dispatcher stopped");
+        });
+    }
+
+    fn dispatch(&self, msg: LogMessage) {
+        self.dispatched.fetch_add(1, Ordering::Relaxed);
+        // Each rule runs once per line, however many connections listen
+        let channels = self.channels.read().unwrap();
+        let routed: Vec<&str> = channels
+            .iter()
+            .filter(|c| c.rule.filter.matches(&*msg))
+            .map(|c| {
+                c.routed.fetch_add(1, Ordering::Relaxed);
+                c.rule.name.as_str()
+            })
+            .collect();
+
+        let mut overflowed = Vec::new();
+        // (kind, offered, dropped) per connection kind
+        let mut by_kind: Vec<(&'static str, u64, u64)> = Vec::new();
+        for queue in self.queues.read().unwrap().values() {
+            if queue
+                .channel
+                .as_deref()
+                .is_some_and(|channel| !routed.contains(&channel))
+            {
+                continue;
+            }
+            let outcome = queue.push(msg.clone());
+            if outcome == Push::Overflowed {
+                overflowed.push(queue.id);
+            }
+
+            let dropped = u64::from(outcome != Push::Queued);
+            match by_kind
+                .iter_mut()
+                .find(|(kind, _, _)| *kind == queue.info.kind)
+            {
+                Some(entry) => {
+                    entry.1 += 1;
+                    entry.2 += dropped;
+                }
+                None => by_kind.push((queue.info.kind, 1, dropped)),
+            }
+        }
+        for (kind, offered, dropped) in by_kind {
+            self.metrics.record_consumer(kind, offered, dropped);
+        }
+
+        if !overflowed.is_empty() {
+            let mut queues = self.queues.write().unwrap();
+            for id in overflowed {
+                warn!(connection_id = %id, "Connection queue overflowed, disconnecting");
+                queues.remove(&id);
+            }
+        }
+    }
+
+    /// Register a new connection queue; `None` uses the configured defaults
+    pub fn subscribe(
+        self: &Arc<Self>,
+        info: ConnectionInfo,
+        capacity: Option<usize>,
+        policy: Option<OverflowPolicy>,
+    ) -> Subscription {
+        self.subscribe_to(info, None, capacity, policy)
+    }
+
+    /// Register a connection queue that only receives one channel's lines;
+    /// check the name with `channel_filter` first
+    pub fn subscribe_to(
+        self: &Arc<Self>,
+        info: ConnectionInfo,
+        channel: Option<String>,
+        capacity: Option<usize>,
+        policy: Option<OverflowPolicy>,
+    ) -> Subscription {
+        let (default_capacity, default_policy) = *self.defaults.read().unwrap();
+        let queue = Arc::new(ConnectionQueue {
+            id: Uuid::new_v4(),
+            info,
+            channel: channel.filter(|c| c != channels::ALL),
+            connected_at: Utc::now(),
+            capacity: capacity.unwrap_or(default_capacity).max(1),
+            policy: policy.unwrap_or(default_policy),
+            messages: Mutex::new(VecDeque::new()),
+            notify: Notify::new(),
+            pending_lag: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            delivered: AtomicU64::new(0),
+            overflowed: AtomicBool::new(false),
+            evicted: AtomicBool::new(false),
+        });
+
+        self.queues.write().unwrap().insert(queue.id, queue.clone());
+
+        Subscription {
+            queue,
+            fanout: self.clone(),
+        }
+    }
+
+    pub fn stats(&self) -> Vec<QueueStats> {
+        self.queues
+            .read()
+            .unwrap()
+            .values()
+            .map(|q| QueueStats {
+                connection_id: q.id,
+                kind: q.info.kind,
+                policy: q.policy,
+                capacity: q.capacity,
+                queued: q.messages.lock().unwrap().len(),
+                dropped: q.dropped.load(Ordering::SeqCst),
+                delivered: q.delivered.load(Ordering::SeqCst),
+            })
+            .collect()
+    }
+
+    pub fn connections(&self) -> Vec<ConnectionSnapshot> {
+        let mut connections: Vec<ConnectionSnapshot> = self
+            .queues
+            .read()
+            .unwrap()
+            .values()
+            .map(|q| ConnectionSnapshot {
+                id: q.id,
+                kind: q.info.kind,
+                remote_addr: q.info.remote_addr.clone(),
+                connected_at: q.connected_at,
+                filters: q.info.filters.clone(),
+                channel: q.channel.clone(),
+                messages_sent: q.delivered.load(Ordering::SeqCst),
+                lag: q.messages.lock().unwrap().len(),
+                dropped: q.dropped.load(Ordering::SeqCst),
+                policy: q.policy,
+            })
+            .collect();
+        connections.sort_by_key(|c| c.connected_at);
+        connections
+    }
+
+    /// Force a connection closed; returns false if it isn't registered
+    pub fn disconnect(&self, id: Uuid) -> bool {
+        let Some(queue) = self.queues.write().unwrap().remove(&id) else {
+            return false;
+        };
+        info!(connection_id = %id, "Force-disconnecting connection");
+        queue.evicted.store(true, Ordering::SeqCst);
+        queue.notify.notify_one();
+        true
+    }
+}
+
+/// A connection's handle on its queue; unregisters when dropped
+pub struct Subscription {
+    queue: Arc<ConnectionQueue>,
+    fanout: Arc<Fanout>,
+}
+
+impl Subscription {
+    pub fn id(&self) -> Uuid {
+        self.queue.id
+    }
+
+    /// Wait for the next message, reporting drops before delivering more
+    pub async fn recv(&self) -> Result<LogMessage, RecvError> {
+        loop {
+            if self.is_ready() {
+                self.stall().await;
+            }
+            if let Some(result) = self.try_take(1) {
+                return result.map(|mut batch| batch.remove(0));
+            }
+            self.queue.notify.notified().await;
+        }
+    }
+
+    /// Injected consumer lag; taken before dequeuing so a dropped future
+    /// still loses nothing
+    async fn stall(&self) {
+        let lag = self.fanout.lag_ms.load(Ordering::SeqCst);
+        if lag > 0 {
+            tokio::time::sleep(Duration::from_millis(lag)).await;
+        }
+    }
+
+    /// Wait for a message, then hold off for `window` so everything arriving
+    /// meanwhile comes back together (at most `max` messages).
+    ///
+    /// Nothing is dequeued until the window has passed, so dropping the future
+    /// (e.g. in a `select!`) never loses messages.
+    pub async fn recv_batch(
+        &self,
+        window: Duration,
+        max: usize,
+    ) -> Result<Vec<LogMessage>, RecvError> {
+        while !self.is_ready() {
+            self.queue.notify.notified().await;
+        }
+        tokio::time::sleep(window).await;
+        self.stall().await;
+
+        loop {
+            if let Some(result) = self.try_take(max.max(1)) {
+                return result;
+            }
+            self.queue.notify.notified().await;
+        }
+    }
+
+    /// Whether `try_take` would return something
+    fn is_ready(&self) -> bool {
+        self.queue.evicted.load(Ordering::SeqCst)
+            || self.queue.pending_lag.load(Ordering::SeqCst) > 0
+            || self.queue.overflowed.load(Ordering::SeqCst)
+            || !self.queue.messages.lock().unwrap().is_empty()
+            || self.fanout.closed.load(Ordering::SeqCst)
+    }
+
+    /// Dequeue up to `max` messages without waiting, errors first
+    fn try_take(&self, max: usize) -> Option<Result<Vec<LogMessage>, RecvError>> {
+        if self.queue.evicted.load(Ordering::SeqCst) {
+            return Some(Err(RecvError::Evicted));
+        }
+        let lagged = self.queue.pending_lag.swap(0, Ordering::SeqCst);
+        if lagged > 0 {
+            return Some(Err(RecvError::Lagged(lagged)));
+        }
+        if self.queue.overflowed.load(Ordering::SeqCst) {
+            return Some(Err(RecvError::Overflowed));
+        }
+
+        let mut messages = self.queue.messages.lock().unwrap();
+        if !messages.is_empty() {
+            let n = messages.len().min(max);
+            let batch: Vec<LogMessage> = messages.drain(..n).collect();
+            self.queue
+                .delivered
+                .fetch_add(batch.len() as u64, Ordering::SeqCst);
+            return Some(Ok(batch));
+        }
+        drop(messages);
+
+        if self.fanout.closed.load(Ordering::SeqCst) {
+            return Some(Err(RecvError::Closed));
+        }
+        None
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.fanout.queues.write().unwrap().remove(&self.queue.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::log_buffer::TimestampedLog;
+
+    fn info() -> ConnectionInfo {
+        ConnectionInfo {
+            kind: "test",
+            remote_addr: "127.0.0.1:1234".to_string(),
+            filters: BTreeMap::new(),
+        }
+    }
+
+    fn msg(seq: u64) -> LogMessage {
+        Arc::new(TimestampedLog::new(format!("log {}", seq), seq))
+    }
+
+    #[tokio::test]
+    async fn test_drop_oldest_reports_lag_then_newest() {
+        let fanout = Fanout::new(2, OverflowPolicy::DropOldest, Metrics::new());
+        let sub = fanout.subscribe(info(), None, None);
+        for seq in 1..=3 {
+            fanout.dispatch(msg(seq));
+        }
+
+        assert_eq!(sub.recv().await.err(), Some(RecvError::Lagged(1)));
+        assert_eq!(sub.recv().await.unwrap().seq, 2);
+        assert_eq!(sub.recv().await.unwrap().seq, 3);
+    }
+
+    #[tokio::test]
+    async fn test_drop_newest_keeps_queued() {
+        let metrics = Metrics::new();
+        let fanout = Fanout::new(2, OverflowPolicy::DropOldest, metrics.clone());
+        let sub = fanout.subscribe(info(), None, Some(OverflowPolicy::DropNewest));
+        for seq in 1..=3 {
+            fanout.dispatch(msg(seq));
+        }
+
+        assert_eq!(sub.recv().await.err(), Some(RecvError::Lagged(1)));
+        assert_eq!(sub.recv().await.unwrap().seq, 1);
+        assert_eq!(sub.recv().await.unwrap().seq, 2);
+
+        let drops = metrics
+            .snapshot(std::time::Instant::now())
+            .await
+            .consumer_drops;
+        assert_eq!(
+            (drops[0].consumer, drops[0].messages, drops[0].dropped),
+            ("test", 3, 1)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_disconnect_policy_unregisters() {
+        let fanout = Fanout::new(1, OverflowPolicy::Disconnect, Metrics::new());
+        let sub = fanout.subscribe(info(), None, None);
+        fanout.dispatch(msg(1));
+        fanout.dispatch(msg(2));
+
+        assert!(fanout.stats().is_empty());
+        assert_eq!(sub.recv().await.err(), Some(RecvError::Overflowed));
+    }
+
+    #[tokio::test]
+    async fn test_artificial_lag_overflows_queue() {
+        let fanout = Fanout::new(2, OverflowPolicy::DropOldest, Metrics::new());
+        fanout.set_artificial_lag(Duration::from_millis(50));
+        let sub = fanout.subscribe(info(), None, None);
+        fanout.dispatch(msg(1));
+
+        let recv = sub.recv();
+        tokio::pin!(recv);
+        assert!(tokio::time::timeout(Duration::from_millis(10), &mut recv)
+            .await
+            .is_err());
+        // Arrives while the consumer is stalled
+        fanout.dispatch(msg(2));
+        fanout.dispatch(msg(3));
+
+        assert_eq!(recv.await.err(), Some(RecvError::Lagged(1)));
+        assert_eq!(sub.recv().await.unwrap().seq, 2);
+    }
+
+    #[tokio::test]
+    async fn test_recv_batch_collects_window() {
+        let fanout = Fanout::new(10, OverflowPolicy::DropOldest, Metrics::new());
+        let sub = fanout.subscribe(info(), None, None);
+        fanout.dispatch(msg(1));
+
+        let collect = sub.recv_batch(Duration::from_millis(100), 2);
+        tokio::pin!(collect);
+        // Still inside the window: nothing is dequeued yet
+        assert!(
+            tokio::time::timeout(Duration::from_millis(20), &mut collect)
+                .await
+                .is_err()
+        );
+        assert_eq!(fanout.connections()[0].lag, 1);
+        fanout.dispatch(msg(2));
+        fanout.dispatch(msg(3));
+
+        let batch = collect.await.unwrap();
+        assert_eq!(batch.iter().map(|m| m.seq).collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(sub.recv().await.unwrap().seq, 3);
+    }
+
+    #[tokio::test]
+    async fn test_disconnect_evicts_connection() {
+        let fanout = Fanout::new(10, OverflowPolicy::DropOldest, Metrics::new());
+        let sub = fanout.subscribe(info(), None, None);
+        fanout.dispatch(msg(1));
+        assert_eq!(fanout.connections()[0].lag, 1);
+
+        assert!(fanout.disconnect(sub.id()));
+        assert!(!fanout.disconnect(sub.id()));
+        assert!(fanout.connections().is_empty());
+        assert_eq!(sub.recv().await.err(), Some(RecvError::Evicted));
+    }
+
+    #[tokio::test]
+    async fn test_channel_subscribers_only_get_routed_lines() {
+        let fanout = Fanout::new(10, OverflowPolicy::DropOldest, Metrics::new());
+        fanout.set_channels(&["errors match=error".parse().unwrap()]);
+        let errors = fanout.subscribe_to(info(), Some("errors".to_string()), None, None);
+        let all = fanout.subscribe_to(info(), Some("all".to_string()), None, None);
+        fanout.dispatch(msg(1));
+        fanout.dispatch(Arc::new(TimestampedLog::new("an error".to_string(), 2)));
+
+        assert_eq!(errors.recv().await.unwrap().seq, 2);
+        assert_eq!(all.recv().await.unwrap().seq, 1);
+        assert_eq!(all.recv().await.unwrap().seq, 2);
+        assert!(fanout.channel_filter("errors").is_some());
+        assert!(fanout.channel_filter("payments").is_none());
+
+        let stats = fanout.channels();
+        assert_eq!((stats[0].routed, stats[0].subscribers), (2, 1));
+        assert_eq!((stats[1].routed, stats[1].subscribers), (1, 1));
+
+        // Reloading the same rule keeps its count
+        fanout.set_channels(&["errors match=error".parse().unwrap()]);
+        assert_eq!(fanout.channels()[1].routed, 1);
+    }
+
+    #[test]
+    fn test_policy_parsing() {
+        assert_eq!("drop-oldest".parse(), Ok(OverflowPolicy::DropOldest));
+        assert_eq!("DISCONNECT".parse(), Ok(OverflowPolicy::Disconnect));
+        assert!("sometimes".parse::<OverflowPolicy>().is_err());
+    }
+}
@@ -0,0 +1,239 @@
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::io::SeekFrom;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio::sync::Mutex;
+use tracing::info;
+
+use crate::source::{drain_lines, LogSource, SourceContext, SourceError};
+
+/// Upper bound on bytes read from one file per poll, so a huge backlog
+/// doesn't starve the other tailed files
+const MAX_READ_PER_POLL: u64 = 4 * 1024 * 1024;
+
+/// Tails local files matching one or more glob patterns (like `tail -F`)
+pub struct FileTailSource {
+    patterns: Vec<String>,
+    poll_interval: Duration,
+    /// Kept across supervisor restarts, so lines written while the source
+    /// was down are still read
+    state: Mutex<TailState>,
+}
+
+#[derive(Default)]
+struct TailState {
+    files: HashMap<PathBuf, TailedFile>,
+    /// Whether the files present at startup have been seen yet
+    scanned: bool,
+}
+
+/// Read position within one tailed file
+struct TailedFile {
+    inode: u64,
+    offset: u64,
+    partial: Vec<u8>,
+}
+
+impl FileTailSource {
+    pub fn new(patterns: Vec<String>, poll_interval: Duration) -> Self {
+        Self {
+            patterns,
+            poll_interval,
+            state: Mutex::new(TailState::default()),
+        }
+    }
+
+    fn expand(&self) -> Result<Vec<PathBuf>, SourceError> {
+        let mut paths = Vec::new();
+        for pattern in &self.patterns {
+            let entries = glob::glob(pattern)
+                .map_err(|e| SourceError::Config(format!("invalid glob {}: {}", pattern, e)))?;
+            for entry in entries.flatten() {
+                if entry.is_file() && !paths.contains(&entry) {
+                    paths.push(entry);
+                }
+            }
+        }
+        Ok(paths)
+    }
+}
+
+#[async_trait]
+impl LogSource for FileTailSource {
+    fn name(&self) -> &str {
+        "file"
+    }
+
+    fn kind(&self) -> &'static str {
+        "file"
+    }
+
+    async fn run(&self, ctx: &SourceContext) -> Result<(), SourceError> {
+        if self.patterns.is_empty() {
+            return Err(SourceError::Config("FILE_TAIL_PATHS is empty".to_string()));
+        }
+
+        let mut state = self.state.lock().await;
+        let TailState { files, scanned } = &mut *state;
+        let mut interval = tokio::time::interval(self.poll_interval);
+
+        info!(patterns = ?self.patterns, "File tail source started");
+        ctx.running().await;
+
+        loop {
+            interval.tick().await;
+            let paths = self.expand()?;
+
+            // Forget files that disappeared; rotated files reappear via inode change
+            files.retain(|path, _| paths.contains(path));
+
+            // A rotated file that still matches a pattern (e.g. `app.log*`)
+            // keeps its read position under the new name
+            let known_offsets: HashMap<u64, u64> =
+                files.values().map(|f| (f.inode, f.offset)).collect();
+
+            for path in paths {
+                let meta = match tokio::fs::metadata(&path).await {
+                    Ok(m) => m,
+                    Err(_) => continue,
+                };
+
+                let tailed = files.entry(path.clone()).or_insert_with(|| {
+                    info!(path = %path.display(), "Tailing file");
+                    TailedFile {
+                        inode: meta.ino(),
+                        // Existing files start at the end; files created later from the top
+                        offset: known_offsets
+                            .get(&meta.ino())
+                            .copied()
+                            .unwrap_or(if *scanned { 0 } else { meta.len() }),
+                        partial: Vec::new(),
+                    }
+                });
+
+                if tailed.inode != meta.ino() {
+                    info!(path = %path.display(), "File rotated, reopening");
+                    tailed.inode = meta.ino();
+                    tailed.offset = 0;
+                    tailed.partial.clear();
+                } else if meta.len() < tailed.offset {
+                    info!(path = %path.display(), "File truncated, reading from start");
+                    tailed.offset = 0;
+                    tailed.partial.clear();
+                }
+
+                if meta.len() == tailed.offset {
+                    continue;
+                }
+
+                match read_chunk(&path, tailed.offset, meta.len()).await {
+                    Ok(chunk) => {
+                        tailed.offset += chunk.len() as u64;
+                        tailed.partial.extend_from_slice(&chunk);
+                        for line in drain_lines(&mut tailed.partial) {
+                            ctx.emit(line).await;
+                        }
+                    }
+                    Err(e) => {
+                        ctx.report_error(SourceError::Io(format!(
+                            "read {}: {}",
+                            path.display(),
+                            e
+                        )))
+                        .await;
+                    }
+                }
+            }
+
+            *scanned = true;
+        }
+    }
+}
+
+async fn read_chunk(path: &Path, offset: u64, len: u64) -> std::io::Result<Vec<u8>> {
+    let mut file = tokio::fs::File::open(path).await?;
+    file.seek(SeekFrom::Start(offset)).await?;
+
+    let to_read = std::cmp::min(len - offset, MAX_READ_PER_POLL);
+    let mut buf = Vec::with_capacity(to_read as usize);
+    file.take(to_read).read_to_end(&mut buf).await?;
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::{Ingest, SourceRegistry};
+    use std::io::Write;
+    use std::sync::{Arc, Mutex as StdMutex};
+
+    struct Collect(Arc<StdMutex<Vec<String>>>);
+
+    #[async_trait]
+    impl Ingest for Collect {
+        async fn ingest_on(&self, _subject: Option<&str>, raw: String) {
+            self.0.lock().unwrap().push(raw);
+        }
+
+        async fn ingest_replica(&self, _raw: String) {}
+    }
+
+    /// Stops the tail after a while, as a failing source would
+    struct Flaky(FileTailSource);
+
+    #[async_trait]
+    impl LogSource for Flaky {
+        fn name(&self) -> &str {
+            "file"
+        }
+
+        fn kind(&self) -> &'static str {
+            "file"
+        }
+
+        async fn run(&self, ctx: &SourceContext) -> Result<(), SourceError> {
+            let _ = tokio::time::timeout(Duration::from_millis(300), self.0.run(ctx)).await;
+            Err(SourceError::Io("stopped".to_string()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_restart_keeps_read_position() {
+        let dir = std::env::temp_dir().join(format!("flywatch-tail-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("app.log");
+        std::fs::write(&path, "before startup\n").unwrap();
+        let append = |line: &str| {
+            let mut file = std::fs::OpenOptions::new()
+                .append(true)
+                .open(&path)
+                .unwrap();
+            writeln!(file, "{}", line).unwrap();
+        };
+
+        let lines = Arc::new(StdMutex::new(Vec::new()));
+        let source = FileTailSource::new(
+            vec![path.to_str().unwrap().to_string()],
+            Duration::from_millis(20),
+        );
+        SourceRegistry::new()
+            .spawn(Box::new(Flaky(source)), Arc::new(Collect(lines.clone())))
+            .await;
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        append("while running");
+        // Written after the source stopped and before its restart
+        tokio::time::sleep(Duration::from_millis(400)).await;
+        append("during the outage");
+        tokio::time::sleep(Duration::from_millis(1_200)).await;
+
+        assert_eq!(
+            *lines.lock().unwrap(),
+            vec!["while running".to_string(), "during the outage".to_string()]
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,341 @@
+//! Compiled log filters shared by the log streams, history, GraphQL and gRPC
+//! queries, saved views, log-based metrics and ingest rules, so matching
+//! behaves the same wherever a filter is written.
+//!
+//! A `Filter` holds level, instance, app and region sets, a substring, a
+//! regex over the raw line and a time range, validated once when it is
+//! built. Regexes go through a process-wide cache keyed by their text: a
+//! pattern shared by a view, a stream parameter and a rule is compiled once.
+
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock, Mutex};
+use utoipa::IntoParams;
+
+use crate::error::ApiError;
+use crate::ingest_filter::normalize_level;
+use crate::log_buffer::TimestampedLog;
+use crate::views::{LogView, Views};
+
+/// Distinct patterns kept compiled
+const PATTERN_CACHE_SIZE: usize = 256;
+
+static PATTERNS: LazyLock<Mutex<HashMap<String, Arc<Regex>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Compile `pattern`, reusing an earlier compilation of the same text
+pub fn pattern(pattern: &str) -> Result<Arc<Regex>, String> {
+    if let Some(regex) = PATTERNS.lock().unwrap().get(pattern) {
+        return Ok(regex.clone());
+    }
+    let regex = Arc::new(Regex::new(pattern).map_err(|e| e.to_string())?);
+    let mut patterns = PATTERNS.lock().unwrap();
+    if patterns.len() >= PATTERN_CACHE_SIZE {
+        // Forget patterns nothing holds any more
+        patterns.retain(|_, regex| Arc::strong_count(regex) > 1);
+    }
+    if patterns.len() < PATTERN_CACHE_SIZE {
+        patterns.insert(pattern.to_string(), regex.clone());
+    }
+    Ok(regex)
+}
+
+/// What a filter looks at on a line. Parsed logs have every field; raw
+/// lines checked at ingest extract theirs on first use.
+pub trait Line {
+    fn raw(&self) -> &str;
+    fn level(&self) -> Option<&str>;
+    fn app(&self) -> Option<&str>;
+    fn instance(&self) -> Option<&str> {
+        None
+    }
+    fn region(&self) -> Option<&str> {
+        None
+    }
+    /// Lines without a timestamp aren't bounded by the time range
+    fn timestamp(&self) -> Option<DateTime<Utc>> {
+        None
+    }
+    /// A structured field (see `TimestampedLog::fields`)
+    fn field(&self, _key: &str) -> Option<&Value> {
+        None
+    }
+}
+
+impl Line for TimestampedLog {
+    fn raw(&self) -> &str {
+        &self.raw
+    }
+
+    fn level(&self) -> Option<&str> {
+        self.level.as_deref()
+    }
+
+    fn app(&self) -> Option<&str> {
+        self.app.as_deref()
+    }
+
+    fn instance(&self) -> Option<&str> {
+        self.instance.as_deref()
+    }
+
+    fn region(&self) -> Option<&str> {
+        self.region.as_deref()
+    }
+
+    fn timestamp(&self) -> Option<DateTime<Utc>> {
+        Some(self.timestamp)
+    }
+
+    fn field(&self, key: &str) -> Option<&Value> {
+        TimestampedLog::field(self, key)
+    }
+}
+
+/// Whether a field holds `wanted`: strings compare as written, numbers by
+/// value (so `12.50` matches 12.5), booleans and null by their JSON text;
+/// objects and arrays never match
+fn field_matches(value: &Value, wanted: &str) -> bool {
+    match value {
+        Value::String(s) => s == wanted,
+        Value::Number(n) => {
+            n.to_string() == wanted || wanted.parse::<f64>().is_ok_and(|w| n.as_f64() == Some(w))
+        }
+        Value::Bool(b) => wanted.parse() == Ok(*b),
+        Value::Null => wanted == "null",
+        _ => false,
+    }
+}
+
+/// Conditions a line must all meet; empty sets and unset fields don't filter
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+    /// Normalized with `normalize_level`
+    pub levels: Vec<String>,
+    pub instances: Vec<String>,
+    /// Compared ignoring case
+    pub apps: Vec<String>,
+    /// Compared ignoring case
+    pub regions: Vec<String>,
+    /// Lowercase substring of the raw line
+    pub search: Option<String>,
+    /// Regex over the raw line
+    pub pattern: Option<Arc<Regex>>,
+    /// Structured fields and the values each may hold
+    pub fields: Vec<(String, Vec<String>)>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+}
+
+/// The field a rule's `field.<key>=` condition names
+pub fn field_key(key: &str) -> Option<&str> {
+    key.strip_prefix("field.").filter(|k| !k.is_empty())
+}
+
+/// Parse `key:value` field conditions, grouping values given for one key
+pub fn field_conditions(value: Option<&str>) -> Result<Vec<(String, Vec<String>)>, String> {
+    let mut conditions: Vec<(String, Vec<String>)> = Vec::new();
+    for condition in list(value, str::to_string) {
+        let (key, wanted) = condition
+            .split_once(':')
+            .filter(|(key, _)| !key.is_empty())
+            .ok_or_else(|| format!("expected key:value, found '{}'", condition))?;
+        match conditions.iter_mut().find(|(k, _)| k == key) {
+            Some((_, values)) => values.push(wanted.to_string()),
+            None => conditions.push((key.to_string(), vec![wanted.to_string()])),
+        }
+    }
+    Ok(conditions)
+}
+
+/// Split a comma-separated list, dropping empty entries
+pub fn list(value: Option<&str>, normalize: fn(&str) -> String) -> Vec<String> {
+    value
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(normalize)
+        .collect()
+}
+
+impl Filter {
+    /// Set the regex, compiled through the shared cache; empty means none
+    pub fn with_pattern(mut self, text: Option<&str>) -> Result<Self, String> {
+        self.pattern = match text.filter(|t| !t.is_empty()) {
+            Some(text) => Some(pattern(text)?),
+            None => None,
+        };
+        Ok(self)
+    }
+
+    pub fn matches(&self, line: &impl Line) -> bool {
+        let listed = |values: &[String], value: Option<&str>| {
+            values.is_empty() || value.is_some_and(|v| values.iter().any(|x| x == v))
+        };
+        let listed_ignoring_case = |values: &[String], value: Option<&str>| {
+            values.is_empty()
+                || value.is_some_and(|v| values.iter().any(|x| x.eq_ignore_ascii_case(v)))
+        };
+        if let Some(timestamp) = line.timestamp() {
+            if self.since.is_some_and(|since| timestamp < since)
+                || self.until.is_some_and(|until| timestamp >= until)
+            {
+                return false;
+            }
+        }
+        if !self.levels.is_empty()
+            && !line
+                .level()
+                .is_some_and(|level| self.levels.contains(&normalize_level(level)))
+        {
+            return false;
+        }
+        if !self.fields.iter().all(|(key, values)| {
+            line.field(key)
+                .is_some_and(|value| values.iter().any(|v| field_matches(value, v)))
+        }) {
+            return false;
+        }
+        listed(&self.instances, line.instance())
+            && listed_ignoring_case(&self.apps, line.app())
+            && listed_ignoring_case(&self.regions, line.region())
+            && self
+                .search
+                .as_ref()
+                .is_none_or(|search| line.raw().to_lowercase().contains(search))
+            && self.pattern.as_ref().is_none_or(|p| p.is_match(line.raw()))
+    }
+}
+
+/// Filter parameters accepted by the log streams and history
+#[derive(Debug, Default, Deserialize, IntoParams)]
+pub struct FilterParams {
+    /// Comma-separated levels
+    pub level: Option<String>,
+    /// Comma-separated instance ids
+    pub instance: Option<String>,
+    /// Case-insensitive substring of the line
+    pub search: Option<String>,
+    /// Regex over the raw line
+    pub pattern: Option<String>,
+    /// Comma-separated `key:value` conditions on structured fields, e.g.
+    /// `status:502,user.id:u1`; a key given twice matches either value
+    pub field: Option<String>,
+    /// Only lines matching this saved view (and, for history, within its lookback)
+    pub view: Option<String>,
+}
+
+impl FilterParams {
+    pub fn compile(self, views: &Views) -> Result<RequestFilter, ApiError> {
+        let view = views.resolve(self.view.as_deref().filter(|v| !v.is_empty()))?;
+        let fields = field_conditions(self.field.as_deref())
+            .map_err(|e| ApiError::InvalidRequest(format!("Invalid 'field': {}", e)))?;
+        let filter = Filter {
+            fields,
+            levels: list(self.level.as_deref(), normalize_level),
+            instances: list(self.instance.as_deref(), str::to_string),
+            search: self
+                .search
+                .filter(|s| !s.is_empty())
+                .map(|s| s.to_lowercase()),
+            ..Default::default()
+        }
+        .with_pattern(self.pattern.as_deref())
+        .map_err(|e| ApiError::InvalidRequest(format!("Invalid 'pattern': {}", e)))?;
+        Ok(RequestFilter { filter, view })
+    }
+}
+
+/// A request's own conditions plus the saved view it names
+#[derive(Debug, Default)]
+pub struct RequestFilter {
+    filter: Filter,
+    view: Option<Arc<LogView>>,
+}
+
+impl RequestFilter {
+    pub fn matches(&self, log: &TimestampedLog) -> bool {
+        self.filter.matches(log) && self.view.as_ref().is_none_or(|v| v.matches(log))
+    }
+
+    /// Oldest event time the view shows, when it has a lookback
+    pub fn since(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.view.as_ref().and_then(|v| v.since(now))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::fly_envelope;
+    use chrono::Duration;
+
+    #[test]
+    fn test_filter_conditions_and_pattern_cache() {
+        let log = |level, instance| {
+            TimestampedLog::new(
+                fly_envelope(
+                    "GET /api/orders 500",
+                    Some(level),
+                    Some(instance),
+                    Some("IAD"),
+                ),
+                1,
+            )
+        };
+        let filter = Filter {
+            levels: list(Some("ERR, warning"), normalize_level),
+            instances: list(Some("web-1,web-2"), str::to_string),
+            regions: vec!["iad".to_string()],
+            search: Some("/api/".to_string()),
+            ..Default::default()
+        }
+        .with_pattern(Some(r"\s5\d\d"))
+        .unwrap();
+
+        assert!(filter.matches(&log("error", "web-1")));
+        assert!(filter.matches(&log("WARN", "web-2")));
+        assert!(!filter.matches(&log("info", "web-1")));
+        assert!(!filter.matches(&log("error", "web-3")));
+
+        let bounded = Filter {
+            since: Some(Utc::now() + Duration::minutes(1)),
+            ..Default::default()
+        };
+        assert!(!bounded.matches(&log("error", "web-1")));
+
+        // The same text compiles once; invalid patterns are rejected up front
+        let again = Filter::default().with_pattern(Some(r"\s5\d\d")).unwrap();
+        assert!(Arc::ptr_eq(
+            filter.pattern.as_ref().unwrap(),
+            again.pattern.as_ref().unwrap()
+        ));
+        assert!(Filter::default().with_pattern(Some("(")).is_err());
+    }
+
+    #[test]
+    fn test_field_conditions() {
+        let log = TimestampedLog::new(
+            fly_envelope(
+                r#"{"status":502,"user":{"id":"u1"},"ok":false}"#,
+                None,
+                None,
+                None,
+            ),
+            1,
+        );
+        let filter = |conditions| Filter {
+            fields: field_conditions(Some(conditions)).unwrap(),
+            ..Default::default()
+        };
+        assert!(filter("status:502.0,user.id:u1,ok:false").matches(&log));
+        assert!(filter("status:500,status:502").matches(&log));
+        assert!(!filter("status:500").matches(&log));
+        assert!(!filter("region:iad").matches(&log));
+        assert!(field_conditions(Some("status")).is_err());
+    }
+}
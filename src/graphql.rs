@@ -0,0 +1,677 @@
+//! GraphQL over logs, metrics and usage at `POST /graphql`, so a frontend can
+//! fetch just the fields it needs in one round trip.
+//!
+//! This is a small executor for the part of GraphQL the schema needs:
+//! queries and subscriptions with fields, aliases, arguments and variables.
+//! Fragments, directives, mutations and introspection are not supported.
+//! Field names are the ones the REST endpoints return. Subscriptions are
+//! answered as server-sent events (GraphQL over SSE, distinct connections
+//! mode): a `next` event per matching log until the client disconnects.
+
+use axum::{
+    extract::{ConnectInfo, State},
+    http::HeaderMap,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tracing::{info, warn};
+use utoipa::ToSchema;
+
+use crate::error::{ApiError, ErrorBody};
+use crate::fanout::RecvError;
+use crate::filter::{FilterParams, RequestFilter};
+use crate::http::{check_auth, connection_info, full_snapshot, shutting_down, AppState};
+use crate::log_buffer::LogCursor;
+
+/// Deepest selection nesting accepted
+const MAX_DEPTH: usize = 32;
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct GraphqlRequest {
+    pub query: String,
+    #[schema(value_type = Option<Object>)]
+    pub variables: Option<Map<String, Value>>,
+    #[serde(rename = "operationName")]
+    pub operation_name: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum OperationKind {
+    Query,
+    Subscription,
+}
+
+#[derive(Debug)]
+struct Operation {
+    kind: OperationKind,
+    name: Option<String>,
+    selection: Vec<Field>,
+}
+
+#[derive(Debug)]
+struct Field {
+    alias: Option<String>,
+    name: String,
+    /// Arguments with variables already substituted
+    args: Map<String, Value>,
+    selection: Vec<Field>,
+}
+
+impl Field {
+    /// Name the field has in the response
+    fn key(&self) -> &str {
+        self.alias.as_deref().unwrap_or(&self.name)
+    }
+}
+
+/// Recursive-descent parser for executable documents
+struct Parser<'a> {
+    src: &'a str,
+    pos: usize,
+    depth: usize,
+    provided: &'a Map<String, Value>,
+    /// Variables of the operation being parsed, defaults included
+    vars: Map<String, Value>,
+}
+
+impl<'a> Parser<'a> {
+    fn new(src: &'a str, provided: &'a Map<String, Value>) -> Self {
+        Self {
+            src,
+            pos: 0,
+            depth: 0,
+            provided,
+            vars: Map::new(),
+        }
+    }
+
+    fn error<T>(&self, message: &str) -> Result<T, String> {
+        Err(format!("{} at offset {}", message, self.pos))
+    }
+
+    /// Skip whitespace, commas and comments
+    fn skip_ignored(&mut self) {
+        while let Some(c) = self.src[self.pos..].chars().next() {
+            if c == '#' {
+                let rest = &self.src[self.pos..];
+                self.pos += rest.find('\n').unwrap_or(rest.len());
+            } else if c.is_whitespace() || c == ',' || c == '\u{feff}' {
+                self.pos += c.len_utf8();
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_ignored();
+        self.src[self.pos..].chars().next()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            self.error(&format!("Expected '{}'", c))
+        }
+    }
+
+    fn name(&mut self) -> Result<String, String> {
+        self.skip_ignored();
+        let rest = &self.src[self.pos..];
+        let len = rest
+            .char_indices()
+            .find(|&(i, c)| !(c == '_' || c.is_ascii_alphabetic() || (i > 0 && c.is_ascii_digit())))
+            .map_or(rest.len(), |(i, _)| i);
+        if len == 0 {
+            return self.error("Expected a name");
+        }
+        self.pos += len;
+        Ok(rest[..len].to_string())
+    }
+
+    fn at_name(&mut self) -> bool {
+        self.peek()
+            .is_some_and(|c| c == '_' || c.is_ascii_alphabetic())
+    }
+
+    fn document(&mut self, operation_name: Option<&str>) -> Result<Operation, String> {
+        let mut operations = Vec::new();
+        while self.peek().is_some() {
+            operations.push(self.operation()?);
+        }
+        let mut matching: Vec<Operation> = operations
+            .into_iter()
+            .filter(|op| operation_name.is_none_or(|name| op.name.as_deref() == Some(name)))
+            .collect();
+        match (matching.len(), operation_name) {
+            (1, _) => Ok(matching.remove(0)),
+            (0, Some(name)) => Err(format!("Unknown operation '{}'", name)),
+            (0, None) => Err("The document has no operations".to_string()),
+            _ => Err(
+                "operationName is required when the document has several operations".to_string(),
+            ),
+        }
+    }
+
+    fn operation(&mut self) -> Result<Operation, String> {
+        if self.peek() == Some('{') {
+            self.vars = self.provided.clone();
+            return Ok(Operation {
+                kind: OperationKind::Query,
+                name: None,
+                selection: self.selection_set()?,
+            });
+        }
+        let kind = match self.name()?.as_str() {
+            "query" => OperationKind::Query,
+            "subscription" => OperationKind::Subscription,
+            "mutation" => return Err("Mutations are not supported".to_string()),
+            "fragment" => return Err("Fragments are not supported".to_string()),
+            other => return Err(format!("Unexpected '{}'", other)),
+        };
+        let name = if self.at_name() {
+            Some(self.name()?)
+        } else {
+            None
+        };
+        self.vars = self.provided.clone();
+        if self.eat('(') {
+            while !self.eat(')') {
+                self.expect('$')?;
+                let var = self.name()?;
+                self.expect(':')?;
+                self.type_ref()?;
+                if self.eat('=') {
+                    let default = self.value(true)?;
+                    self.vars.entry(var).or_insert(default);
+                }
+            }
+        }
+        if self.peek() == Some('@') {
+            return self.error("Directives are not supported");
+        }
+        Ok(Operation {
+            kind,
+            name,
+            selection: self.selection_set()?,
+        })
+    }
+
+    /// Variable types are only checked by how the resolvers read them
+    fn type_ref(&mut self) -> Result<(), String> {
+        if self.eat('[') {
+            self.descend()?;
+            self.type_ref()?;
+            self.expect(']')?;
+            self.depth -= 1;
+        } else {
+            self.name()?;
+        }
+        self.eat('!');
+        Ok(())
+    }
+
+    /// Enter a nested selection, list or object
+    fn descend(&mut self) -> Result<(), String> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return self.error("Document is nested too deeply");
+        }
+        Ok(())
+    }
+
+    fn selection_set(&mut self) -> Result<Vec<Field>, String> {
+        self.descend()?;
+        self.expect('{')?;
+        let mut fields = Vec::new();
+        while !self.eat('}') {
+            if self.peek() == Some('.') {
+                return self.error("Fragments are not supported");
+            }
+            fields.push(self.field()?);
+        }
+        self.depth -= 1;
+        if fields.is_empty() {
+            return self.error("Empty selection");
+        }
+        Ok(fields)
+    }
+
+    fn field(&mut self) -> Result<Field, String> {
+        let mut name = self.name()?;
+        let mut alias = None;
+        if self.eat(':') {
+            alias = Some(name);
+            name = self.name()?;
+        }
+        let mut args = Map::new();
+        if self.eat('(') {
+            while !self.eat(')') {
+                let arg = self.name()?;
+                self.expect(':')?;
+                let value = self.value(false)?;
+                args.insert(arg, value);
+            }
+        }
+        if self.peek() == Some('@') {
+            return self.error("Directives are not supported");
+        }
+        let selection = if self.peek() == Some('{') {
+            self.selection_set()?
+        } else {
+            Vec::new()
+        };
+        Ok(Field {
+            alias,
+            name,
+            args,
+            selection,
+        })
+    }
+
+    fn value(&mut self, constant: bool) -> Result<Value, String> {
+        match self.peek() {
+            Some('$') if !constant => {
+                self.pos += 1;
+                let var = self.name()?;
+                Ok(self.vars.get(&var).cloned().unwrap_or(Value::Null))
+            }
+            Some('"') => self.string().map(Value::String),
+            Some('[') => {
+                self.pos += 1;
+                self.descend()?;
+                let mut items = Vec::new();
+                while !self.eat(']') {
+                    items.push(self.value(constant)?);
+                }
+                self.depth -= 1;
+                Ok(Value::Array(items))
+            }
+            Some('{') => {
+                self.pos += 1;
+                self.descend()?;
+                let mut object = Map::new();
+                while !self.eat('}') {
+                    let key = self.name()?;
+                    self.expect(':')?;
+                    object.insert(key, self.value(constant)?);
+                }
+                self.depth -= 1;
+                Ok(Value::Object(object))
+            }
+            Some(c) if c == '-' || c.is_ascii_digit() => self.number(),
+            Some(c) if c == '_' || c.is_ascii_alphabetic() => Ok(match self.name()?.as_str() {
+                "true" => Value::Bool(true),
+                "false" => Value::Bool(false),
+                "null" => Value::Null,
+                // Enum values arrive as their names
+                other => Value::String(other.to_string()),
+            }),
+            _ => self.error("Expected a value"),
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, String> {
+        let rest = &self.src[self.pos..];
+        let len = rest
+            .find(|c: char| !(c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E')))
+            .unwrap_or(rest.len());
+        let text = &rest[..len];
+        let value = match text.parse::<i64>() {
+            Ok(n) => Value::from(n),
+            Err(_) => match text.parse::<f64>() {
+                Ok(n) => Value::from(n),
+                Err(_) => return self.error("Invalid number"),
+            },
+        };
+        self.pos += len;
+        Ok(value)
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        if self.src[self.pos..].starts_with("\"\"\"") {
+            self.pos += 3;
+            let Some(end) = self.src[self.pos..].find("\"\"\"") else {
+                return self.error("Unterminated block string");
+            };
+            let text = self.src[self.pos..self.pos + end].trim().to_string();
+            self.pos += end + 3;
+            return Ok(text);
+        }
+        self.pos += 1;
+        let mut out = String::new();
+        let mut chars = self.src[self.pos..].char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.pos += i + 1;
+                    return Ok(out);
+                }
+                '\\' => match chars.next().map(|(_, c)| c) {
+                    Some('n') => out.push('\n'),
+                    Some('t') => out.push('\t'),
+                    Some('r') => out.push('\r'),
+                    Some('b') => out.push('\u{8}'),
+                    Some('f') => out.push('\u{c}'),
+                    Some('u') => {
+                        let hex: String = chars.by_ref().take(4).map(|(_, c)| c).collect();
+                        match u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32) {
+                            Some(c) => out.push(c),
+                            None => return self.error("Invalid unicode escape"),
+                        }
+                    }
+                    Some(c) => out.push(c),
+                    None => break,
+                },
+                '\n' => break,
+                c => out.push(c),
+            }
+        }
+        self.error("Unterminated string")
+    }
+}
+
+/// Keep only the selected fields of a resolved value
+fn project(value: Value, selection: &[Field], path: &str) -> Result<Value, String> {
+    if selection.is_empty() {
+        return Ok(value);
+    }
+    match value {
+        Value::Array(items) => items
+            .into_iter()
+            .map(|item| project(item, selection, path))
+            .collect::<Result<Vec<_>, _>>()
+            .map(Value::Array),
+        Value::Object(mut object) => {
+            let mut selected = Map::new();
+            for field in selection {
+                if !field.args.is_empty() {
+                    return Err(format!("'{}.{}' takes no arguments", path, field.name));
+                }
+                let inner = object
+                    .remove(&field.name)
+                    .ok_or_else(|| format!("Cannot query field '{}' on '{}'", field.name, path))?;
+                // Aliases may select the same field twice
+                object.insert(field.name.clone(), inner.clone());
+                let value = project(inner, &field.selection, &format!("{}.{}", path, field.name))?;
+                selected.insert(field.key().to_string(), value);
+            }
+            Ok(Value::Object(selected))
+        }
+        Value::Null => Ok(Value::Null),
+        _ => Err(format!("'{}' is a scalar and has no fields", path)),
+    }
+}
+
+fn string_arg(args: &Map<String, Value>, name: &str) -> Result<Option<String>, String> {
+    match args.get(name) {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::String(s)) => Ok(Some(s.clone())),
+        Some(_) => Err(format!("Argument '{}' must be a string", name)),
+    }
+}
+
+fn int_arg(args: &Map<String, Value>, name: &str) -> Result<Option<i64>, String> {
+    match args.get(name) {
+        None | Some(Value::Null) => Ok(None),
+        Some(v) => v
+            .as_i64()
+            .map(Some)
+            .ok_or_else(|| format!("Argument '{}' must be an integer", name)),
+    }
+}
+
+/// The filter arguments of `logs`
+fn log_filter(state: &AppState, args: &Map<String, Value>) -> Result<RequestFilter, String> {
+    FilterParams {
+        level: string_arg(args, "level")?,
+        instance: string_arg(args, "instance")?,
+        search: string_arg(args, "search")?,
+        pattern: string_arg(args, "pattern")?,
+        field: string_arg(args, "field")?,
+        view: string_arg(args, "view")?,
+    }
+    .compile(&state.views)
+    .map_err(|e| e.to_string())
+}
+
+/// `logs(limit, before, cursor, level, instance, search, pattern, field, view)`: one page of
+/// history, newest page first, lines in event-time order
+async fn resolve_logs(state: &AppState, args: &Map<String, Value>) -> Result<Value, String> {
+    let filter = log_filter(state, args)?;
+    let limit = int_arg(args, "limit")?.unwrap_or(100).clamp(1, 1000) as usize;
+    let cursor = match string_arg(args, "cursor")? {
+        Some(c) => Some(LogCursor::decode(&c).ok_or("Invalid 'cursor'")?),
+        None => None,
+    };
+    let before = match string_arg(args, "before")? {
+        Some(ts) => DateTime::parse_from_rfc3339(&ts)
+            .map_err(|e| format!("Invalid 'before' timestamp: {}", e))?
+            .with_timezone(&Utc),
+        None => Utc::now(),
+    };
+    let since = filter.since(Utc::now());
+    let page = state
+        .log_buffer
+        .page_before(before, cursor.as_ref(), limit, |log| {
+            filter.matches(log) && since.is_none_or(|since| log.timestamp >= since)
+        })
+        .await;
+    Ok(json!({
+        "annotations": state.annotations.overlapping(&page.logs, state.app_scope()),
+        "logs": page.logs,
+        "total_estimate": page.total_estimate,
+        "has_more": page.next_cursor.is_some(),
+        "next_cursor": page.next_cursor.map(|c| c.encode()),
+    }))
+}
+
+async fn resolve_query(state: &AppState, field: &Field) -> Result<Value, String> {
+    let value = match field.name.as_str() {
+        "logs" => resolve_logs(state, &field.args).await?,
+        "metrics" => serde_json::to_value(full_snapshot(state).await).map_err(|e| e.to_string())?,
+        "usage" => serde_json::to_value(state.usage_tracker.get_stats().await)
+            .map_err(|e| e.to_string())?,
+        "__typename" => return Ok(Value::String("Query".to_string())),
+        other => return Err(format!("Cannot query field '{}' on 'Query'", other)),
+    };
+    project(value, &field.selection, &field.name)
+}
+
+fn errors_response(errors: Vec<String>) -> Response {
+    let errors: Vec<Value> = errors
+        .into_iter()
+        .map(|message| json!({ "message": message }))
+        .collect();
+    Json(json!({ "errors": errors })).into_response()
+}
+
+/// POST /graphql - query logs, metrics and usage, or subscribe to live logs
+#[utoipa::path(
+    post, path = "/graphql", tag = "logs",
+    request_body = GraphqlRequest,
+    responses(
+        (status = 200, description = "`{data, errors}` for queries; server-sent `next` events for subscriptions", body = Object),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+pub async fn graphql_handler(
+    State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(request): Json<GraphqlRequest>,
+) -> Result<Response, ApiError> {
+    check_auth(&state, &headers)?;
+    let variables = request.variables.unwrap_or_default();
+    let operation =
+        match Parser::new(&request.query, &variables).document(request.operation_name.as_deref()) {
+            Ok(operation) => operation,
+            Err(e) => return Ok(errors_response(vec![e])),
+        };
+
+    if operation.kind == OperationKind::Subscription {
+        return Ok(subscribe(state, peer, &headers, operation)
+            .unwrap_or_else(|e| errors_response(vec![e])));
+    }
+
+    let mut data = Map::new();
+    let mut errors = Vec::new();
+    for field in &operation.selection {
+        let value = resolve_query(&state, field).await.unwrap_or_else(|e| {
+            errors.push(json!({ "message": e, "path": [field.key()] }));
+            Value::Null
+        });
+        data.insert(field.key().to_string(), value);
+    }
+    let mut body = json!({ "data": data });
+    if !errors.is_empty() {
+        body["errors"] = Value::Array(errors);
+    }
+    Ok(Json(body).into_response())
+}
+
+/// `subscription { logs(level, instance, search, pattern, field, view) { ... } }` as SSE
+fn subscribe(
+    state: AppState,
+    peer: SocketAddr,
+    headers: &HeaderMap,
+    operation: Operation,
+) -> Result<Response, String> {
+    let [field] = <[Field; 1]>::try_from(operation.selection)
+        .map_err(|_| "A subscription selects exactly one field".to_string())?;
+    if field.name != "logs" {
+        return Err(format!(
+            "Cannot query field '{}' on 'Subscription'",
+            field.name
+        ));
+    }
+    let filter = log_filter(&state, &field.args)?;
+    let filters: BTreeMap<String, String> = field
+        .args
+        .iter()
+        .map(|(k, v)| {
+            (
+                k.clone(),
+                v.as_str().map_or_else(|| v.to_string(), str::to_string),
+            )
+        })
+        .collect();
+
+    state.metrics.increment_sse_connections();
+    let metrics = state.metrics.clone();
+    let info = connection_info("graphql", peer, headers, filters);
+    let subscription = state.fanout.subscribe(info, None, None);
+    let mut shutdown = state.shutdown.subscribe();
+
+    let stream = async_stream::stream! {
+        loop {
+            let result = tokio::select! {
+                result = subscription.recv() => result,
+                _ = shutting_down(&mut shutdown) => break,
+            };
+            match result {
+                Ok(log) => {
+                    if !filter.matches(&log) {
+                        continue;
+                    }
+                    let payload = serde_json::to_value(&*log)
+                        .map_err(|e| e.to_string())
+                        .and_then(|value| project(value, &field.selection, "logs"));
+                    let body = match payload {
+                        Ok(value) => json!({ "data": { field.key(): value } }),
+                        Err(e) => json!({ "errors": [{ "message": e }] }),
+                    };
+                    yield Ok::<_, Infallible>(Event::default().event("next").data(body.to_string()));
+                }
+                Err(RecvError::Lagged(n)) => {
+                    warn!(skipped = n, "GraphQL subscriber lagged");
+                }
+                Err(_) => break,
+            }
+        }
+        yield Ok(Event::default().event("complete").data(""));
+        metrics.decrement_active_sse_connections();
+        info!("GraphQL subscriber disconnected");
+    };
+
+    Ok(Sse::new(stream)
+        .keep_alive(KeepAlive::new().interval(Duration::from_secs(15)))
+        .into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(query: &str, variables: Value) -> Result<Operation, String> {
+        let variables = variables.as_object().cloned().unwrap_or_default();
+        Parser::new(query, &variables).document(None)
+    }
+
+    #[test]
+    fn test_parse_and_project() {
+        let operation = parse(
+            r#"
+            query Recent($level: String = "warn", $n: Int) {
+              errors: logs(level: $level, limit: $n, search: "payé") {
+                has_more
+                logs { seq message }
+              }
+              # comment
+              usage { total_requests }
+            }
+            "#,
+            json!({ "n": 5 }),
+        )
+        .unwrap();
+        assert_eq!(operation.kind, OperationKind::Query);
+        let logs = &operation.selection[0];
+        assert_eq!((logs.key(), logs.name.as_str()), ("errors", "logs"));
+        assert_eq!(logs.args["level"], "warn");
+        assert_eq!(logs.args["limit"], 5);
+        assert_eq!(logs.args["search"], "payé");
+
+        let value = json!({
+            "has_more": false,
+            "next_cursor": null,
+            "logs": [{ "seq": 1, "message": "boom", "raw": "..." }],
+        });
+        assert_eq!(
+            project(value.clone(), &logs.selection, "logs").unwrap(),
+            json!({ "has_more": false, "logs": [{ "seq": 1, "message": "boom" }] })
+        );
+
+        let bad = parse("{ logs { has_more { x } } }", json!({})).unwrap();
+        assert!(project(value.clone(), &bad.selection[0].selection, "logs").is_err());
+        let unknown = parse("{ logs { nope } }", json!({})).unwrap();
+        assert!(project(value, &unknown.selection[0].selection, "logs").is_err());
+
+        assert!(parse("subscription { logs { seq } }", json!({})).is_ok());
+        assert!(parse("{ logs { ...F } }", json!({})).is_err());
+        assert!(parse("mutation { x }", json!({})).is_err());
+        assert!(parse("query A { a } query B { b }", json!({})).is_err());
+        assert!(parse(&"{ a ".repeat(40), json!({})).is_err());
+        assert!(parse(&format!("{{ logs(x: {}) }}", "[".repeat(10_000)), json!({})).is_err());
+    }
+}
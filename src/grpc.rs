@@ -0,0 +1,628 @@
+//! gRPC API (proto/flywatch.proto) for internal services that prefer
+//! protobuf contracts and HTTP/2 multiplexing over SSE or WebSockets:
+//! StreamLogs, QueryLogs and GetMetrics, served over cleartext HTTP/2 on
+//! GRPC_BIND_ADDR. The handful of messages are encoded by hand, so there is
+//! no code generation step; unknown fields are skipped as protobuf requires.
+
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use http_body_util::{BodyExt, Limited, StreamBody};
+use hyper::body::{Frame, Incoming};
+use hyper::header::{HeaderMap, HeaderValue, CONTENT_TYPE};
+use hyper::{Request, Response};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tracing::{debug, error, info, warn};
+
+use crate::fanout::RecvError;
+use crate::filter::{FilterParams, RequestFilter};
+use crate::http::{check_auth, connection_info, full_snapshot, shutting_down, AppState};
+use crate::log_buffer::{LogCursor, TimestampedLog};
+use crate::metrics::MetricsSnapshot;
+
+const SERVICE_PREFIX: &str = "/flywatch.v1.Flywatch/";
+/// Largest request message accepted
+const MAX_MESSAGE_BYTES: usize = 4 * 1024 * 1024;
+/// Encoded messages waiting to be written to one call
+const CALL_QUEUE: usize = 64;
+
+// Status codes used here (grpc/status.proto)
+const OK: u32 = 0;
+const INVALID_ARGUMENT: u32 = 3;
+const RESOURCE_EXHAUSTED: u32 = 8;
+const UNIMPLEMENTED: u32 = 12;
+const UNAVAILABLE: u32 = 14;
+const UNAUTHENTICATED: u32 = 16;
+
+type FrameResult = Result<Frame<Bytes>, Infallible>;
+type GrpcBody = StreamBody<ReceiverStream<FrameResult>>;
+
+#[derive(Debug)]
+struct Status {
+    code: u32,
+    message: String,
+}
+
+impl Status {
+    fn new(code: u32, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+
+    fn invalid(message: impl Into<String>) -> Self {
+        Self::new(INVALID_ARGUMENT, message)
+    }
+
+    /// `grpc-status` and `grpc-message`, percent-encoded as the spec asks
+    fn headers(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("grpc-status", HeaderValue::from(self.code));
+        if !self.message.is_empty() {
+            let encoded: String = self
+                .message
+                .bytes()
+                .map(|b| match b {
+                    b' '..=b'~' if b != b'%' => (b as char).to_string(),
+                    _ => format!("%{:02X}", b),
+                })
+                .collect();
+            if let Ok(value) = HeaderValue::from_str(&encoded) {
+                headers.insert("grpc-message", value);
+            }
+        }
+        headers
+    }
+}
+
+// ==================== Protobuf ====================
+
+fn put_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn put_uint(buf: &mut Vec<u8>, field: u32, value: u64) {
+    if value != 0 {
+        put_varint(buf, (field as u64) << 3);
+        put_varint(buf, value);
+    }
+}
+
+fn put_bytes(buf: &mut Vec<u8>, field: u32, value: &[u8]) {
+    put_varint(buf, (field as u64) << 3 | 2);
+    put_varint(buf, value.len() as u64);
+    buf.extend_from_slice(value);
+}
+
+/// Empty strings are the proto3 default and aren't written
+fn put_str(buf: &mut Vec<u8>, field: u32, value: Option<&str>) {
+    if let Some(value) = value.filter(|v| !v.is_empty()) {
+        put_bytes(buf, field, value.as_bytes());
+    }
+}
+
+fn get_varint(input: &mut &[u8]) -> Result<u64, String> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = input.split_first().ok_or("truncated varint")?;
+        *input = rest;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte < 0x80 {
+            return Ok(value);
+        }
+    }
+    Err("varint too long".to_string())
+}
+
+enum Wire<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+}
+
+/// The varint and length-delimited fields of a message; fixed-width ones
+/// (not used by this API) are skipped
+fn fields(mut input: &[u8]) -> Result<Vec<(u32, Wire<'_>)>, String> {
+    let mut fields = Vec::new();
+    while !input.is_empty() {
+        let key = get_varint(&mut input)?;
+        let field = (key >> 3) as u32;
+        let skip = match key & 7 {
+            0 => {
+                fields.push((field, Wire::Varint(get_varint(&mut input)?)));
+                0
+            }
+            1 => 8,
+            2 => {
+                let len = get_varint(&mut input)? as usize;
+                if len > input.len() {
+                    return Err("truncated field".to_string());
+                }
+                let (bytes, rest) = input.split_at(len);
+                fields.push((field, Wire::Bytes(bytes)));
+                input = rest;
+                0
+            }
+            5 => 4,
+            wire => return Err(format!("unsupported wire type {}", wire)),
+        };
+        input = input.get(skip..).ok_or("truncated field")?;
+    }
+    Ok(fields)
+}
+
+fn utf8(bytes: &[u8]) -> Result<String, String> {
+    String::from_utf8(bytes.to_vec()).map_err(|_| "invalid UTF-8 in string field".to_string())
+}
+
+/// `LogFilter` message
+#[derive(Debug, Default, PartialEq)]
+struct FilterMessage {
+    level: Option<String>,
+    instance: Option<String>,
+    search: Option<String>,
+    view: Option<String>,
+}
+
+impl FilterMessage {
+    fn decode(input: &[u8]) -> Result<Self, String> {
+        let mut filter = Self::default();
+        for (field, value) in fields(input)? {
+            let Wire::Bytes(bytes) = value else { continue };
+            let slot = match field {
+                1 => &mut filter.level,
+                2 => &mut filter.instance,
+                3 => &mut filter.search,
+                4 => &mut filter.view,
+                _ => continue,
+            };
+            *slot = Some(utf8(bytes)?);
+        }
+        Ok(filter)
+    }
+
+    fn compile(self, state: &AppState) -> Result<RequestFilter, Status> {
+        FilterParams {
+            level: self.level,
+            instance: self.instance,
+            search: self.search,
+            pattern: None,
+            field: None,
+            view: self.view,
+        }
+        .compile(&state.views)
+        .map_err(|e| Status::invalid(e.to_string()))
+    }
+
+    /// Shown with the connection in `/connections`
+    fn describe(&self) -> BTreeMap<String, String> {
+        [
+            ("level", &self.level),
+            ("instance", &self.instance),
+            ("search", &self.search),
+            ("view", &self.view),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| Some((name.to_string(), value.clone()?)))
+        .collect()
+    }
+}
+
+/// `StreamLogsRequest` message
+#[derive(Debug, Default)]
+struct StreamLogsRequest {
+    filter: FilterMessage,
+    after_seq: u64,
+}
+
+impl StreamLogsRequest {
+    fn decode(input: &[u8]) -> Result<Self, String> {
+        let mut request = Self::default();
+        for (field, value) in fields(input)? {
+            match (field, value) {
+                (1, Wire::Bytes(bytes)) => request.filter = FilterMessage::decode(bytes)?,
+                (2, Wire::Varint(seq)) => request.after_seq = seq,
+                _ => {}
+            }
+        }
+        Ok(request)
+    }
+}
+
+/// `QueryLogsRequest` message
+#[derive(Debug, Default)]
+struct QueryLogsRequest {
+    filter: FilterMessage,
+    limit: u64,
+    cursor: String,
+    before_unix_ms: i64,
+}
+
+impl QueryLogsRequest {
+    fn decode(input: &[u8]) -> Result<Self, String> {
+        let mut request = Self::default();
+        for (field, value) in fields(input)? {
+            match (field, value) {
+                (1, Wire::Bytes(bytes)) => request.filter = FilterMessage::decode(bytes)?,
+                (2, Wire::Varint(limit)) => request.limit = limit,
+                (3, Wire::Bytes(bytes)) => request.cursor = utf8(bytes)?,
+                (4, Wire::Varint(ms)) => request.before_unix_ms = ms as i64,
+                _ => {}
+            }
+        }
+        Ok(request)
+    }
+}
+
+/// `LogEntry` message
+fn encode_log(log: &TimestampedLog) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(log.raw.len() + 64);
+    put_uint(&mut buf, 1, log.seq);
+    put_uint(&mut buf, 2, log.timestamp.timestamp_millis() as u64);
+    put_str(&mut buf, 3, log.level.as_deref());
+    put_str(&mut buf, 4, log.instance.as_deref());
+    put_str(&mut buf, 5, log.region.as_deref());
+    put_str(&mut buf, 6, log.app.as_deref());
+    put_str(&mut buf, 7, log.message.as_deref());
+    put_str(&mut buf, 8, Some(&log.raw));
+    put_str(&mut buf, 9, log.source.as_deref());
+    put_str(&mut buf, 10, log.trace_id.as_deref());
+    put_str(&mut buf, 11, log.request_id.as_deref());
+    buf
+}
+
+/// A `LogEntry` published to NATS, as a JSON line: its `raw` when set,
+/// otherwise a Fly envelope built from the other fields
+pub fn decode_log_entry(input: &[u8]) -> Result<String, String> {
+    let mut timestamp_ms = 0;
+    let mut text = BTreeMap::new();
+    for (field, value) in fields(input)? {
+        match (field, value) {
+            (2, Wire::Varint(ms)) => timestamp_ms = ms as i64,
+            (3..=11, Wire::Bytes(bytes)) => {
+                text.insert(field, utf8(bytes)?);
+            }
+            _ => {}
+        }
+    }
+    if let Some(raw) = text.remove(&8) {
+        return Ok(raw);
+    }
+    let message = text
+        .remove(&7)
+        .ok_or("LogEntry without a message or raw line")?;
+    let timestamp = DateTime::from_timestamp_millis(timestamp_ms)
+        .filter(|_| timestamp_ms != 0)
+        .unwrap_or_else(Utc::now);
+    Ok(serde_json::json!({
+        "timestamp": timestamp.to_rfc3339(),
+        "message": message,
+        "log": { "level": text.get(&3) },
+        "fly": {
+            "app": { "name": text.get(&6), "instance": text.get(&4) },
+            "region": text.get(&5),
+        },
+    })
+    .to_string())
+}
+
+/// `Metrics` message
+fn encode_metrics(snapshot: &MetricsSnapshot) -> Vec<u8> {
+    let mut buf = Vec::new();
+    put_uint(&mut buf, 1, snapshot.uptime_seconds);
+    put_uint(&mut buf, 2, snapshot.nats_connected as u64);
+    put_uint(&mut buf, 3, snapshot.messages_forwarded);
+    put_uint(&mut buf, 4, snapshot.messages_filtered);
+    put_uint(&mut buf, 5, snapshot.active_sse_connections);
+    put_uint(&mut buf, 6, snapshot.active_ws_connections);
+    put_uint(&mut buf, 7, snapshot.drop_warning as u64);
+    let json = serde_json::to_string(snapshot).unwrap_or_default();
+    put_str(&mut buf, 8, Some(&json));
+    buf
+}
+
+// ==================== Calls ====================
+
+/// One length-prefixed, uncompressed message
+fn data_frame(message: &[u8]) -> FrameResult {
+    let mut buf = Vec::with_capacity(message.len() + 5);
+    buf.push(0);
+    buf.extend_from_slice(&(message.len() as u32).to_be_bytes());
+    buf.extend_from_slice(message);
+    Ok(Frame::data(Bytes::from(buf)))
+}
+
+fn grpc_response(rx: mpsc::Receiver<FrameResult>, status: Option<&Status>) -> Response<GrpcBody> {
+    let mut response = Response::new(StreamBody::new(ReceiverStream::new(rx)));
+    let headers = response.headers_mut();
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/grpc"));
+    if let Some(status) = status {
+        headers.extend(status.headers());
+    }
+    response
+}
+
+/// Trailers-only response for calls that fail before sending anything
+fn failed(status: Status) -> Response<GrpcBody> {
+    let (_, rx) = mpsc::channel(1);
+    grpc_response(rx, Some(&status))
+}
+
+fn unary(result: Result<Vec<u8>, Status>) -> Response<GrpcBody> {
+    match result {
+        Ok(message) => {
+            let (tx, rx) = mpsc::channel(2);
+            let _ = tx.try_send(data_frame(&message));
+            let _ = tx.try_send(Ok(Frame::trailers(Status::new(OK, "").headers())));
+            grpc_response(rx, None)
+        }
+        Err(status) => failed(status),
+    }
+}
+
+/// The single message of a unary or server-streaming request
+async fn read_message(body: Incoming) -> Result<Bytes, Status> {
+    let body = Limited::new(body, MAX_MESSAGE_BYTES + 5)
+        .collect()
+        .await
+        .map_err(|e| Status::new(RESOURCE_EXHAUSTED, e.to_string()))?
+        .to_bytes();
+    if body.is_empty() {
+        return Ok(body);
+    }
+    if body.len() < 5 {
+        return Err(Status::invalid("truncated message frame"));
+    }
+    if body[0] != 0 {
+        return Err(Status::new(
+            UNIMPLEMENTED,
+            "compressed messages are not supported",
+        ));
+    }
+    let len = u32::from_be_bytes([body[1], body[2], body[3], body[4]]) as usize;
+    if body.len() != len + 5 {
+        return Err(Status::invalid("expected exactly one message"));
+    }
+    Ok(body.slice(5..))
+}
+
+async fn query_logs(state: &AppState, message: &[u8]) -> Result<Vec<u8>, Status> {
+    let request = QueryLogsRequest::decode(message).map_err(Status::invalid)?;
+    let filter = request.filter.compile(state)?;
+    let limit = match request.limit {
+        0 => 100,
+        n => n.min(1000) as usize,
+    };
+    let cursor = match request.cursor.as_str() {
+        "" => None,
+        c => Some(LogCursor::decode(c).ok_or_else(|| Status::invalid("invalid cursor"))?),
+    };
+    let before = match request.before_unix_ms {
+        0 => Utc::now(),
+        ms => DateTime::from_timestamp_millis(ms)
+            .ok_or_else(|| Status::invalid("invalid before_unix_ms"))?,
+    };
+    let since = filter.since(Utc::now());
+    let page = state
+        .log_buffer
+        .page_before(before, cursor.as_ref(), limit, |log| {
+            filter.matches(log) && since.is_none_or(|since| log.timestamp >= since)
+        })
+        .await;
+
+    let mut buf = Vec::new();
+    for log in &page.logs {
+        put_bytes(&mut buf, 1, &encode_log(log));
+    }
+    let cursor = page.next_cursor.map(|c| c.encode());
+    put_str(&mut buf, 2, cursor.as_deref());
+    put_uint(&mut buf, 3, page.total_estimate as u64);
+    Ok(buf)
+}
+
+/// Replays buffered lines after `after_seq`, then follows the live stream
+/// until the client cancels, the queue overflows or the server shuts down
+fn stream_logs(
+    state: AppState,
+    peer: SocketAddr,
+    headers: &HeaderMap,
+    message: &[u8],
+) -> Result<Response<GrpcBody>, Status> {
+    let request = StreamLogsRequest::decode(message).map_err(Status::invalid)?;
+    let info = connection_info("grpc", peer, headers, request.filter.describe());
+    let filter = request.filter.compile(&state)?;
+    // Subscribe before reading the backfill so nothing falls between the two
+    let subscription = state.fanout.subscribe(info, None, None);
+    let mut shutdown = state.shutdown.subscribe();
+    let (tx, rx) = mpsc::channel(CALL_QUEUE);
+
+    tokio::spawn(async move {
+        let mut last_seq = None;
+        if request.after_seq > 0 {
+            let (backfill, _) = state
+                .log_buffer
+                .get_since(request.after_seq, usize::MAX)
+                .await;
+            last_seq = backfill.last().map(|log| log.seq);
+            for log in backfill.iter().filter(|log| filter.matches(log)) {
+                if tx.send(data_frame(&encode_log(log))).await.is_err() {
+                    return;
+                }
+            }
+        }
+        let status = loop {
+            let result = tokio::select! {
+                result = subscription.recv() => result,
+                _ = shutting_down(&mut shutdown) => {
+                    break Status::new(UNAVAILABLE, "server shutting down");
+                }
+                _ = tx.closed() => return,
+            };
+            match result {
+                Ok(log) => {
+                    if last_seq.is_some_and(|seq| log.seq <= seq) || !filter.matches(&log) {
+                        continue;
+                    }
+                    if tx.send(data_frame(&encode_log(&log))).await.is_err() {
+                        return;
+                    }
+                }
+                Err(RecvError::Lagged(n)) => warn!(skipped = n, "gRPC stream lagged"),
+                Err(RecvError::Overflowed) => {
+                    break Status::new(RESOURCE_EXHAUSTED, "connection queue overflowed");
+                }
+                Err(RecvError::Evicted) => {
+                    break Status::new(UNAVAILABLE, "connection closed by an operator");
+                }
+                Err(RecvError::Closed) => break Status::new(OK, ""),
+            }
+        };
+        let _ = tx.send(Ok(Frame::trailers(status.headers()))).await;
+    });
+    Ok(grpc_response(rx, None))
+}
+
+async fn handle(
+    state: AppState,
+    peer: SocketAddr,
+    request: Request<Incoming>,
+) -> Result<Response<GrpcBody>, Infallible> {
+    let is_grpc = request
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/grpc"));
+    if !is_grpc {
+        return Ok(failed(Status::invalid(
+            "content-type must be application/grpc",
+        )));
+    }
+    if let Err(e) = check_auth(&state, request.headers()) {
+        return Ok(failed(Status::new(UNAUTHENTICATED, e.to_string())));
+    }
+    let method = request
+        .uri()
+        .path()
+        .strip_prefix(SERVICE_PREFIX)
+        .unwrap_or_default()
+        .to_string();
+    let (parts, body) = request.into_parts();
+    let message = match read_message(body).await {
+        Ok(message) => message,
+        Err(status) => return Ok(failed(status)),
+    };
+    debug!(method = %method, peer = %peer, "gRPC call");
+
+    Ok(match method.as_str() {
+        "StreamLogs" => stream_logs(state, peer, &parts.headers, &message).unwrap_or_else(failed),
+        "QueryLogs" => unary(query_logs(&state, &message).await),
+        "GetMetrics" => unary(Ok(encode_metrics(&full_snapshot(&state).await))),
+        _ => failed(Status::new(
+            UNIMPLEMENTED,
+            format!("unknown method {}", parts.uri.path()),
+        )),
+    })
+}
+
+/// Serve the gRPC API on `addr` until shutdown
+pub async fn serve(state: AppState, addr: String) {
+    let listener = match TcpListener::bind(&addr).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!(addr = %addr, error = %e, "Failed to bind gRPC listener");
+            return;
+        }
+    };
+    info!(addr = %addr, "gRPC API listening");
+    let mut shutdown = state.shutdown.subscribe();
+    loop {
+        let (stream, peer) = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    warn!(error = %e, "Failed to accept gRPC connection");
+                    continue;
+                }
+            },
+            _ = shutting_down(&mut shutdown) => break,
+        };
+        let state = state.clone();
+        tokio::spawn(async move {
+            let service =
+                hyper::service::service_fn(move |request| handle(state.clone(), peer, request));
+            if let Err(e) = hyper::server::conn::http2::Builder::new(TokioExecutor::new())
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
+                debug!(peer = %peer, error = %e, "gRPC connection error");
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_messages_round_trip() {
+        let mut filter = Vec::new();
+        put_str(&mut filter, 1, Some("error"));
+        put_str(&mut filter, 3, Some("payé"));
+        put_uint(&mut filter, 15, 7); // unknown field
+        let mut request = Vec::new();
+        put_bytes(&mut request, 1, &filter);
+        put_uint(&mut request, 2, 300);
+        let request = StreamLogsRequest::decode(&request).unwrap();
+        assert_eq!(request.after_seq, 300);
+        assert_eq!(request.filter.level.as_deref(), Some("error"));
+        assert_eq!(request.filter.search.as_deref(), Some("payé"));
+        assert_eq!(request.filter.instance, None);
+        assert!(QueryLogsRequest::decode(&[0x0a, 0x05, 0x01]).is_err());
+
+        let log = TimestampedLog::new(
+            r#"{"message":"boom","log":{"level":"error"},"fly":{"app":{"instance":"148ed193b"}}}"#
+                .to_string(),
+            42,
+        );
+        let encoded = encode_log(&log);
+        let decoded = fields(&encoded).unwrap();
+        assert!(matches!(decoded[0], (1, Wire::Varint(42))));
+        let text = |n: u32| {
+            decoded.iter().find_map(|(field, value)| match value {
+                Wire::Bytes(b) if *field == n => Some(String::from_utf8(b.to_vec()).unwrap()),
+                _ => None,
+            })
+        };
+        assert_eq!(text(3).as_deref(), Some("error"));
+        assert_eq!(text(4).as_deref(), Some("148ed193b"));
+        assert_eq!(text(5), None);
+        assert_eq!(decode_log_entry(&encoded).unwrap(), log.raw);
+
+        // Without a raw line the fields make a Fly envelope
+        let mut entry = Vec::new();
+        put_uint(&mut entry, 2, 1_700_000_000_000);
+        put_str(&mut entry, 3, Some("warn"));
+        put_str(&mut entry, 6, Some("api"));
+        put_str(&mut entry, 7, Some("slow query"));
+        let log = TimestampedLog::new(decode_log_entry(&entry).unwrap(), 1);
+        assert_eq!(log.level.as_deref(), Some("warn"));
+        assert_eq!(log.app.as_deref(), Some("api"));
+        assert_eq!(log.message.as_deref(), Some("slow query"));
+        assert_eq!(log.timestamp.timestamp(), 1_700_000_000);
+        assert!(decode_log_entry(&[0x18, 0x01]).is_err());
+
+        let status = Status::new(INVALID_ARGUMENT, "bad 100% é").headers();
+        assert_eq!(status["grpc-status"], "3");
+        assert_eq!(status["grpc-message"], "bad 100%25 %C3%A9");
+    }
+}
@@ -0,0 +1,300 @@
+//! Dead man's switches: alert when an app, instance, region or NATS subject
+//! stops sending logs. Each heartbeat names what to watch and how long it
+//! may stay quiet:
+//!
+//! ```text
+//! checkout app=checkout after=10m
+//! edge-fra region=fra after=5m severity=warning
+//! billing subject=logs.billing.> after=1h
+//! ```
+//!
+//! Silence is measured from the last matching line, or from startup (or the
+//! heartbeat being added) if none has arrived yet.
+
+use axum::{extract::State, Json};
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use utoipa::ToSchema;
+
+use crate::alerts::{Alerts, Severity};
+use crate::http::AppState;
+use crate::ingest_filter::subject_matches;
+use crate::log_buffer::TimestampedLog;
+use crate::slo::parse_window;
+
+const CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// What a heartbeat listens for
+#[derive(Debug, Clone, PartialEq)]
+pub enum HeartbeatTarget {
+    App(String),
+    Instance(String),
+    Region(String),
+    /// NATS subject pattern (`*` and `>` wildcards)
+    Subject(String),
+}
+
+impl HeartbeatTarget {
+    fn matches(&self, log: &TimestampedLog, subject: Option<&str>) -> bool {
+        match self {
+            Self::App(app) => log.app.as_deref() == Some(app),
+            Self::Instance(instance) => log.instance.as_deref() == Some(instance),
+            Self::Region(region) => log.region.as_deref() == Some(region),
+            Self::Subject(pattern) => subject.is_some_and(|s| subject_matches(pattern, s)),
+        }
+    }
+}
+
+impl fmt::Display for HeartbeatTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::App(app) => write!(f, "app={}", app),
+            Self::Instance(instance) => write!(f, "instance={}", instance),
+            Self::Region(region) => write!(f, "region={}", region),
+            Self::Subject(pattern) => write!(f, "subject={}", pattern),
+        }
+    }
+}
+
+/// One heartbeat, as written in `HEARTBEATS`
+#[derive(Debug, Clone, PartialEq)]
+pub struct HeartbeatDefinition {
+    pub name: String,
+    pub target: HeartbeatTarget,
+    pub after: String,
+    after_minutes: i64,
+    pub severity: Severity,
+}
+
+impl FromStr for HeartbeatDefinition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = s.split_whitespace();
+        let name = words.next().unwrap_or_default().to_string();
+        if name.is_empty() || name.contains('=') {
+            return Err("a heartbeat starts with its name".to_string());
+        }
+
+        let (mut target, mut after, mut severity) = (None, None, Severity::Critical);
+        for word in words {
+            let (key, value) = word
+                .split_once('=')
+                .ok_or_else(|| format!("expected key=value, found '{}'", word))?;
+            let watched = match key {
+                "app" => HeartbeatTarget::App(value.to_string()),
+                "instance" => HeartbeatTarget::Instance(value.to_string()),
+                "region" => HeartbeatTarget::Region(value.to_string()),
+                "subject" => HeartbeatTarget::Subject(value.to_string()),
+                "after" => {
+                    let minutes = parse_window(value)
+                        .ok_or_else(|| format!("invalid after '{}' (e.g. 10m, 2h)", value))?;
+                    after = Some((value.to_string(), minutes));
+                    continue;
+                }
+                "severity" => {
+                    severity = value.parse()?;
+                    continue;
+                }
+                other => return Err(format!("unknown option '{}'", other)),
+            };
+            if target.replace(watched).is_some() {
+                return Err("only one of app=, instance=, region= or subject=".to_string());
+            }
+        }
+
+        let (after, after_minutes) = after.ok_or("after= is required")?;
+        Ok(HeartbeatDefinition {
+            name,
+            target: target.ok_or("one of app=, instance=, region= or subject= is required")?,
+            after,
+            after_minutes,
+            severity,
+        })
+    }
+}
+
+struct TrackedHeartbeat {
+    definition: HeartbeatDefinition,
+    /// Last matching line, or when tracking began
+    last_seen: DateTime<Utc>,
+    seen: bool,
+}
+
+impl TrackedHeartbeat {
+    fn silent_for(&self, now: DateTime<Utc>) -> Option<Duration> {
+        let quiet = now - self.last_seen;
+        (quiet >= Duration::minutes(self.definition.after_minutes)).then_some(quiet)
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct HeartbeatStatus {
+    pub name: String,
+    /// e.g. "app=checkout"
+    pub target: String,
+    pub after: String,
+    /// None until a matching line arrives
+    pub last_seen: Option<DateTime<Utc>>,
+    /// Quiet for longer than `after`
+    pub silent: bool,
+}
+
+pub struct Heartbeats {
+    heartbeats: Mutex<Vec<TrackedHeartbeat>>,
+}
+
+impl Heartbeats {
+    pub fn new(definitions: &[HeartbeatDefinition]) -> Arc<Self> {
+        let heartbeats = Arc::new(Self {
+            heartbeats: Mutex::new(Vec::new()),
+        });
+        heartbeats.set_definitions(definitions);
+        heartbeats
+    }
+
+    /// Replace the definitions, keeping the last-seen time of unchanged ones
+    pub fn set_definitions(&self, definitions: &[HeartbeatDefinition]) {
+        let mut heartbeats = self.heartbeats.lock().unwrap();
+        let mut previous: Vec<TrackedHeartbeat> = std::mem::take(&mut *heartbeats);
+        *heartbeats = definitions
+            .iter()
+            .map(
+                |definition| match previous.iter().position(|t| t.definition == *definition) {
+                    Some(i) => previous.swap_remove(i),
+                    None => TrackedHeartbeat {
+                        definition: definition.clone(),
+                        last_seen: Utc::now(),
+                        seen: false,
+                    },
+                },
+            )
+            .collect();
+    }
+
+    /// Note an ingested line; `subject` is set for lines that came over NATS
+    pub fn observe(&self, log: &TimestampedLog, subject: Option<&str>) {
+        let mut heartbeats = self.heartbeats.lock().unwrap();
+        for heartbeat in heartbeats.iter_mut() {
+            if heartbeat.definition.target.matches(log, subject) {
+                heartbeat.last_seen = log.timestamp;
+                heartbeat.seen = true;
+            }
+        }
+    }
+
+    /// Fire an alert for each silent heartbeat, resolve the rest
+    pub fn evaluate(&self, alerts: &Alerts, now: DateTime<Utc>) {
+        let heartbeats = self.heartbeats.lock().unwrap();
+        for heartbeat in heartbeats.iter() {
+            let definition = &heartbeat.definition;
+            let rule = format!("heartbeat:{}", definition.name);
+            match heartbeat.silent_for(now) {
+                Some(quiet) => alerts.fire(
+                    &rule,
+                    "silence",
+                    definition.severity,
+                    format!(
+                        "No logs from {} for {}m (expected within {})",
+                        definition.target,
+                        quiet.num_minutes(),
+                        definition.after
+                    ),
+                ),
+                None => alerts.resolve(&rule, "silence"),
+            }
+        }
+    }
+
+    pub fn status(&self) -> Vec<HeartbeatStatus> {
+        let now = Utc::now();
+        let heartbeats = self.heartbeats.lock().unwrap();
+        heartbeats
+            .iter()
+            .map(|heartbeat| HeartbeatStatus {
+                name: heartbeat.definition.name.clone(),
+                target: heartbeat.definition.target.to_string(),
+                after: heartbeat.definition.after.clone(),
+                last_seen: heartbeat.seen.then_some(heartbeat.last_seen),
+                silent: heartbeat.silent_for(now).is_some(),
+            })
+            .collect()
+    }
+}
+
+/// Check every heartbeat for silence every 30 seconds
+pub async fn heartbeat_evaluator(state: AppState) {
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+        state.heartbeats.evaluate(&state.alerts, Utc::now());
+    }
+}
+
+/// GET /heartbeats - when each heartbeat last saw a line
+#[utoipa::path(
+    get, path = "/heartbeats", tag = "metrics",
+    responses((status = 200, description = "Every heartbeat with its last matching line", body = Vec<HeartbeatStatus>))
+)]
+pub async fn heartbeats_handler(State(state): State<AppState>) -> Json<Vec<HeartbeatStatus>> {
+    Json(state.heartbeats.status())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn test_parse_definitions() {
+        let definition: HeartbeatDefinition =
+            "edge region=fra after=5m severity=warning".parse().unwrap();
+        assert_eq!(
+            definition.target,
+            HeartbeatTarget::Region("fra".to_string())
+        );
+        assert_eq!(definition.after_minutes, 5);
+        assert_eq!(definition.severity, Severity::Warning);
+
+        assert!("edge region=fra".parse::<HeartbeatDefinition>().is_err());
+        assert!("edge app=a instance=b after=5m"
+            .parse::<HeartbeatDefinition>()
+            .is_err());
+        assert!("app=a after=5m".parse::<HeartbeatDefinition>().is_err());
+    }
+
+    #[test]
+    fn test_silence_fires_and_lines_resolve() {
+        let alerts = Alerts::new(&Config::for_tests(""));
+        let heartbeats = Heartbeats::new(&[
+            "checkout app=checkout after=10m".parse().unwrap(),
+            "billing subject=logs.billing.> after=10m".parse().unwrap(),
+        ]);
+        let start = Utc::now();
+
+        heartbeats.evaluate(&alerts, start + Duration::minutes(5));
+        assert!(alerts.active().is_empty());
+
+        let mut log = TimestampedLog::new("app[m1] fra [info] ok".to_string(), 1);
+        log.app = Some("checkout".to_string());
+        log.timestamp = start + Duration::minutes(9);
+        heartbeats.observe(&log, Some("logs.billing.fra.m1"));
+        heartbeats.evaluate(&alerts, start + Duration::minutes(12));
+        assert!(alerts.active().is_empty());
+
+        heartbeats.evaluate(&alerts, start + Duration::minutes(20));
+        let active = alerts.active();
+        assert_eq!(active.len(), 2);
+        assert!(active
+            .iter()
+            .any(|a| a.summary == "No logs from app=checkout for 11m (expected within 10m)"));
+
+        log.timestamp = start + Duration::minutes(21);
+        heartbeats.observe(&log, None);
+        heartbeats.evaluate(&alerts, start + Duration::minutes(22));
+        assert_eq!(alerts.active()[0].rule, "heartbeat:billing");
+    }
+}
@@ -1,73 +1,520 @@
 use axum::{
     extract::{
-        ws::{Message, WebSocket, WebSocketUpgrade},
-        Query, State,
+        ws::{CloseFrame, Message, WebSocket, WebSocketUpgrade},
+        ConnectInfo, Path, Query, State,
     },
     http::{header, HeaderMap, StatusCode},
     response::{
         sse::{Event, KeepAlive, Sse},
         IntoResponse, Response,
     },
-    routing::{get, post},
+    middleware,
+    routing::{delete, get, post, put},
     Json, Router,
 };
 use chrono::{DateTime, Utc};
 use futures::{SinkExt, StreamExt};
 use serde::{Deserialize, Serialize};
+use std::borrow::Borrow;
+use std::collections::BTreeMap;
 use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
-use tokio::sync::broadcast;
-use tower_http::cors::{Any, CorsLayer};
+use subtle::ConstantTimeEq;
+use tokio::sync::watch;
 use tower_http::trace::TraceLayer;
 use tracing::{debug, error, info, warn};
+use utoipa::openapi::security::{Http, HttpAuthScheme, SecurityScheme};
+use utoipa::{IntoParams, Modify, OpenApi, ToSchema};
+use utoipa_swagger_ui::SwaggerUi;
 
-use crate::chat::chat_handler;
-use crate::config::Config;
-use crate::log_buffer::{LogBuffer, LogSummary, TimestampedLog};
-use crate::metrics::{HealthStatus, Metrics, MetricsSnapshot};
+use crate::alerts::{self, Alerts};
+use crate::annotations::{self, Annotation, Annotations};
+use crate::archive::{self, Archive};
+use crate::audit::{self, ToolAudit};
+use crate::channels;
+use crate::chat::{chat_handler, estimate_handler};
+use crate::chat_cache::ChatCache;
+use crate::chat_ws::chat_ws_handler;
+use crate::compression::StreamCompression;
+use crate::config::ConfigStore;
+use crate::deploys::{self, Deploys};
+use crate::cors::cors_layer;
+use crate::dashboard;
+use crate::download;
+use crate::error::{self, ApiError, ErrorBody};
+use crate::facets;
+use crate::fanout::{
+    ConnectionInfo, ConnectionSnapshot, Fanout, OverflowPolicy, RecvError, Subscription,
+};
+use crate::filter::{Filter, FilterParams, RequestFilter};
+use crate::graphql;
+use crate::heartbeat::{self, Heartbeats};
+use crate::http_analytics::{self, HttpAnalytics};
+use crate::ingest_filter::IngestFilter;
+use crate::log_buffer::{LogBuffer, LogCursor, LogSummary, TimestampedLog};
+use crate::log_metrics::LogMetrics;
+use crate::logging::{self, LogFilter};
+use crate::maintenance;
+use crate::mcp::{self, McpSessions};
+use crate::metrics::{HealthStatus, Metrics, MetricsSnapshot, NatsDebug};
 use crate::nats::LogMessage;
-use crate::usage::{UsageStats, UsageTracker};
+use crate::pricing::{self, PricingCatalog};
+use crate::prometheus;
+use crate::readiness::{self, BroadcastMonitor};
+use crate::redact::Redactor;
+use crate::reload;
+use crate::runbooks::RunbookIndex;
+use crate::self_log::SelfLog;
+use crate::session;
+use crate::sink::{SinkHealthSnapshot, SinkRegistry};
+use crate::slo::{self, SloTracker};
+use crate::source::{SourceHealthSnapshot, SourceRegistry};
+use crate::tenant::{Tenant, TenantDefinition, Tenants};
+use crate::text::prefix_bytes;
+use crate::tickets;
+use crate::tls;
+use crate::trace;
+use crate::transcripts::{self, Transcripts};
+use crate::usage::{ConversationUsage, Feedback, FeedbackError, Rating, UsageStats, UsageTracker};
+use crate::views::{self, Views};
+use crate::ws_auth::{self, SseAuthQuery, TicketQuery, UsedTickets, WsAuth};
+
+pub const WS_PING_INTERVAL: Duration = Duration::from_secs(30);
+pub const WS_PONG_TIMEOUT: Duration = Duration::from_secs(10);
+pub const WS_MAX_FRAME_SIZE: usize = 64 * 1024;
+const MAX_BATCH_SIZE: usize = 1000;
+const MAX_BATCH_WINDOW_MS: u64 = 5000;
+/// Longest comment accepted with chat feedback
+const MAX_FEEDBACK_COMMENT_CHARS: usize = 2000;
+
+/// Why the server is closing a WebSocket, sent as the close frame's code and
+/// reason so clients can decide whether and when to reconnect
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WsClose {
+    /// 1000: the client closed, or the stream ended normally
+    Normal,
+    /// 1001: the server is shutting down; reconnect to another instance
+    GoingAway,
+    /// 1008: the token was revoked or rotated; don't reconnect with it
+    PolicyViolation,
+    /// 1013: the connection's queue overflowed; reconnect after a backoff
+    TryAgainLater,
+    /// 4000: no pong within the timeout
+    PongTimeout,
+    /// 4001: an operator closed the connection
+    Disconnected,
+}
+
+impl WsClose {
+    pub fn code(self) -> u16 {
+        match self {
+            Self::Normal => 1000,
+            Self::GoingAway => 1001,
+            Self::PolicyViolation => 1008,
+            Self::TryAgainLater => 1013,
+            Self::PongTimeout => 4000,
+            Self::Disconnected => 4001,
+        }
+    }
+
+    pub fn reason(self) -> &'static str {
+        match self {
+            Self::Normal => "NORMAL",
+            Self::GoingAway => "SHUTTING_DOWN",
+            Self::PolicyViolation => "UNAUTHORIZED",
+            Self::TryAgainLater => "SLOW_CONSUMER",
+            Self::PongTimeout => "PONG_TIMEOUT",
+            Self::Disconnected => "DISCONNECTED",
+        }
+    }
+
+    pub fn message(self) -> Message {
+        Message::Close(Some(CloseFrame {
+            code: self.code(),
+            reason: self.reason().into(),
+        }))
+    }
+}
 
-const WS_PING_INTERVAL: Duration = Duration::from_secs(30);
-const WS_PONG_TIMEOUT: Duration = Duration::from_secs(10);
-const WS_MAX_FRAME_SIZE: usize = 64 * 1024;
+/// Resolves once the server starts shutting down
+pub async fn shutting_down(shutdown: &mut watch::Receiver<bool>) {
+    let _ = shutdown.wait_for(|down| *down).await;
+}
 
 #[derive(Clone)]
 pub struct AppState {
-    pub config: Arc<Config>,
+    pub config: Arc<ConfigStore>,
     pub metrics: Arc<Metrics>,
-    pub log_tx: broadcast::Sender<LogMessage>,
     pub log_buffer: Arc<LogBuffer>,
     pub usage_tracker: Arc<UsageTracker>,
+    pub sources: Arc<SourceRegistry>,
+    pub sinks: Arc<SinkRegistry>,
+    pub fanout: Arc<Fanout>,
+    pub log_filter: Arc<LogFilter>,
+    pub self_log: Arc<SelfLog>,
+    pub broadcast: Arc<BroadcastMonitor>,
+    pub runbooks: Arc<RunbookIndex>,
+    pub pricing: Arc<PricingCatalog>,
+    pub tool_audit: Arc<ToolAudit>,
+    pub transcripts: Arc<Transcripts>,
+    pub chat_cache: Arc<ChatCache>,
+    pub ingest_filter: Arc<IngestFilter>,
+    pub redactor: Arc<Redactor>,
+    pub log_metrics: Arc<LogMetrics>,
+    pub slos: Arc<SloTracker>,
+    pub http_analytics: Arc<HttpAnalytics>,
+    pub heartbeats: Arc<Heartbeats>,
+    pub alerts: Arc<Alerts>,
+    pub tenants: Arc<Tenants>,
+    /// The tenant whose `/t/<name>/` routes this state serves
+    pub tenant: Option<Arc<TenantDefinition>>,
+    /// The dashboard user behind this request, set by `for_caller`
+    pub session_user: Option<String>,
+    pub views: Arc<Views>,
+    pub annotations: Arc<Annotations>,
+    pub deploys: Arc<Deploys>,
+    pub mcp: Arc<McpSessions>,
+    pub used_tickets: Arc<UsedTickets>,
+    pub archive: Option<Arc<Archive>>,
+    /// Flipped to true once on SIGTERM/ctrl-c so streams can close cleanly
+    pub shutdown: Arc<watch::Sender<bool>>,
     pub start_time: Instant,
 }
 
-pub fn create_router(state: AppState) -> Router {
-    let cors = CorsLayer::new()
-        .allow_origin(Any)
-        .allow_methods(Any)
-        .allow_headers(Any);
+impl AppState {
+    /// State for routes under `/t/<name>/`: the tenant's config, buffer,
+    /// streams, usage, views and HTTP analytics in place of the shared ones
+    fn for_tenant(&self, tenant: &Tenant) -> Self {
+        Self {
+            tenant: Some(Arc::new(tenant.definition.clone())),
+            views: tenant.views.clone(),
+            http_analytics: tenant.http_analytics.clone(),
+            config: tenant.config.clone(),
+            log_buffer: tenant.log_buffer.clone(),
+            usage_tracker: tenant.usage_tracker.clone(),
+            fanout: tenant.fanout.clone(),
+            tool_audit: tenant.tool_audit.clone(),
+            transcripts: tenant.transcripts.clone(),
+            chat_cache: tenant.chat_cache.clone(),
+            ..self.clone()
+        }
+    }
+
+    /// This state for a request authenticated by `headers`, remembering the
+    /// dashboard session's user when it carries no Authorization header
+    pub fn for_caller(&self, headers: &HeaderMap) -> Self {
+        let session = match headers.contains_key(header::AUTHORIZATION) {
+            true => None,
+            false => session::current(&self.config.current(), headers),
+        };
+        Self {
+            session_user: session.map(|session| session.user),
+            ..self.clone()
+        }
+    }
+
+    /// Apps a tenant's routes are limited to; None on the unprefixed routes
+    pub fn app_scope(&self) -> Option<&[String]> {
+        self.tenant.as_ref().map(|tenant| tenant.apps.as_slice())
+    }
+}
+
+#[cfg(test)]
+impl AppState {
+    /// Everything the handlers read, with nothing persisted and no sources
+    /// running
+    pub fn for_tests(config: crate::config::Config) -> Self {
+        let config_store = ConfigStore::new(config, None);
+        let config = config_store.current();
+        let metrics = Metrics::new();
+        let (tx, _) = tokio::sync::broadcast::channel(config.channel_capacity);
+        Self {
+            metrics: metrics.clone(),
+            log_buffer: LogBuffer::new(
+                crate::log_buffer::LogBufferConfig {
+                    max_entries: config.log_buffer_max_entries,
+                    max_age_minutes: config.log_buffer_max_age_minutes,
+                },
+                None,
+            ),
+            usage_tracker: Arc::new(UsageTracker::new(None, 0)),
+            sources: SourceRegistry::new(),
+            sinks: SinkRegistry::start(Vec::new()),
+            fanout: Fanout::new(
+                config.connection_queue_capacity,
+                config.connection_overflow_policy,
+                metrics.clone(),
+            ),
+            log_filter: logging::detached(),
+            self_log: SelfLog::new(),
+            broadcast: BroadcastMonitor::new(tx, config.channel_capacity),
+            runbooks: RunbookIndex::new(),
+            pricing: PricingCatalog::new(),
+            tool_audit: Arc::new(ToolAudit::new(None)),
+            transcripts: Arc::new(Transcripts::new(None)),
+            chat_cache: Arc::new(ChatCache::new()),
+            ingest_filter: IngestFilter::new(&config.ingest_rules),
+            redactor: Redactor::new(crate::redact::ingest_rules(&config)),
+            log_metrics: LogMetrics::new(&config.log_metrics),
+            slos: SloTracker::new(&config.slos),
+            http_analytics: HttpAnalytics::new(config.http_analytics_window_minutes),
+            heartbeats: Heartbeats::new(&config.heartbeats),
+            alerts: Alerts::new(&config),
+            tenants: Tenants::new(&config_store, &metrics),
+            tenant: None,
+            session_user: None,
+            views: Views::new(None),
+            annotations: Annotations::new(None),
+            deploys: Deploys::new(None),
+            mcp: McpSessions::new(),
+            used_tickets: UsedTickets::new(),
+            archive: None,
+            shutdown: Arc::new(watch::channel(false).0),
+            start_time: Instant::now(),
+            config: config_store,
+        }
+    }
+}
 
+/// Data routes each tenant gets under `/t/<name>`; operational and admin
+/// routes are only served unprefixed
+fn tenant_routes() -> Router<AppState> {
     Router::new()
+        .route("/logs/stream", get(sse_handler))
+        .route("/logs/ws", get(ws_handler))
+        .route("/logs/history", get(logs_history_handler))
+        .route("/logs/replay", get(replay_handler))
+        .route("/logs/download", get(download::download_handler))
+        .route("/logs/since/:seq", get(logs_since_handler))
+        .route("/logs/trace/:trace_id", get(trace::trace_handler))
+        .route("/logs/facets", get(facets::facets_handler))
+        .route("/logs/buffer/stats", get(logs_stats_handler))
+        .route("/http-analytics", get(http_analytics::http_analytics_handler))
+        .route("/views", get(views::list_handler).post(views::save_handler))
+        .route(
+            "/views/:name",
+            get(views::get_handler).delete(views::delete_handler),
+        )
+        .route("/chat", post(chat_handler))
+        .route("/chat/estimate", post(estimate_handler))
+        .route("/chat/ws", get(chat_ws_handler))
+        .route("/chat/audit", get(audit::audit_handler))
+        .route("/chat/transcripts/:id", get(transcripts::transcript_handler))
+        .route("/chat/:id/feedback", post(feedback_handler))
+        .route("/tickets", post(tickets::tickets_handler))
+        .route("/auth/ws-ticket", post(ws_auth::ws_ticket_handler))
+        .route("/usage", get(usage_handler))
+        .route("/pricing", get(pricing::pricing_handler))
+        .route("/usage/conversations/:id", get(conversation_usage_handler))
+}
+
+pub fn create_router(state: AppState) -> Router {
+    let cors = cors_layer(&state.config.current());
+
+    let mut router = Router::new()
+        .route("/", get(dashboard::index_handler))
+        .route("/dashboard/*path", get(dashboard::asset_handler))
         .route("/health", get(health_handler))
         .route("/healthz", get(health_handler))
-        .route("/ready", get(ready_handler))
+        .route("/ready", get(readiness::ready_handler))
         .route("/metrics", get(metrics_handler))
+        .route("/metrics/prometheus", get(prometheus_handler))
+        .route("/slo", get(slo::slo_handler))
+        .route("/http-analytics", get(http_analytics::http_analytics_handler))
+        .route("/heartbeats", get(heartbeat::heartbeats_handler))
+        .route("/alerts/active", get(alerts::active_alerts_handler))
+        .route("/alerts/:id/ack", post(alerts::ack_handler))
+        .route(
+            "/silences",
+            get(alerts::silences_handler).post(alerts::create_silence_handler),
+        )
+        .route("/silences/:id", delete(alerts::delete_silence_handler))
+        .route(
+            "/maintenance",
+            get(maintenance::list_handler).post(maintenance::create_handler),
+        )
+        .route(
+            "/maintenance/:id",
+            put(maintenance::update_handler).delete(maintenance::delete_handler),
+        )
         .route("/logs/stream", get(sse_handler))
         .route("/logs/ws", get(ws_handler))
         .route("/logs/history", get(logs_history_handler))
+        .route("/logs/replay", get(replay_handler))
+        .route("/logs/download", get(download::download_handler))
+        .route("/logs/since/:seq", get(logs_since_handler))
+        .route("/logs/trace/:trace_id", get(trace::trace_handler))
+        .route("/logs/facets", get(facets::facets_handler))
+        .route("/logs/sql", post(archive::sql_handler))
+        .route("/views", get(views::list_handler).post(views::save_handler))
+        .route(
+            "/annotations",
+            get(annotations::list_handler).post(annotations::create_handler),
+        )
+        .route("/annotations/:id", delete(annotations::delete_handler))
+        .route(
+            "/deploys",
+            get(deploys::list_handler).post(deploys::create_handler),
+        )
+        .route("/graphql", post(graphql::graphql_handler))
+        .route("/mcp", post(mcp::rpc_handler))
+        .route("/mcp/sse", get(mcp::sse_handler))
+        .route("/mcp/messages", post(mcp::message_handler))
+        .route(
+            "/views/:name",
+            get(views::get_handler).delete(views::delete_handler),
+        )
         .route("/metrics/ws", get(metrics_ws_handler))
         .route("/chat", post(chat_handler))
+        .route("/chat/estimate", post(estimate_handler))
+        .route("/chat/ws", get(chat_ws_handler))
+        .route("/chat/audit", get(audit::audit_handler))
+        .route("/chat/transcripts/:id", get(transcripts::transcript_handler))
+        .route("/chat/:id/feedback", post(feedback_handler))
+        .route("/tickets", post(tickets::tickets_handler))
+        .route("/auth/ws-ticket", post(ws_auth::ws_ticket_handler))
+        .route("/auth/login", post(session::login_handler))
+        .route("/auth/session", get(session::session_handler))
+        .route("/auth/logout", post(session::logout_handler))
         .route("/logs/buffer/stats", get(logs_stats_handler))
         .route("/usage", get(usage_handler))
+        .route("/pricing", get(pricing::pricing_handler))
+        .route("/usage/conversations/:id", get(conversation_usage_handler))
+        .route("/sources", get(sources_handler))
+        .route("/sinks", get(sinks_handler))
+        .route("/debug/nats", get(nats_debug_handler))
+        .route("/connections", get(connections_handler))
+        .route("/channels", get(channels::channels_handler))
+        .route("/connections/:id", delete(disconnect_handler))
+        .route("/admin/reload", post(reload::admin_reload_handler))
+        .route("/admin/logging", post(logging::admin_logging_handler))
+        .merge(SwaggerUi::new("/docs").url("/openapi.json", ApiDoc::openapi()))
+        .fallback(error::not_found);
+    for tenant in state.tenants.iter() {
+        router = router.nest(
+            &format!("/t/{}", tenant.definition.name),
+            tenant_routes().with_state(state.for_tenant(tenant)),
+        );
+    }
+
+    router
+        .layer(middleware::from_fn_with_state(state.clone(), session::csrf_guard))
+        .layer(middleware::from_fn(error::json_errors))
         .layer(cors)
-        .layer(TraceLayer::new_for_http())
+        .layer(middleware::from_fn(tls::principal_scope))
+        .layer(TraceLayer::new_for_http().make_span_with(error::request_span))
+        .layer(middleware::from_fn(error::request_id))
         .with_state(state)
 }
 
-pub fn check_auth(state: &AppState, headers: &HeaderMap) -> Result<(), Response> {
-    if let Some(expected_token) = &state.config.auth_token {
+// ==================== OpenAPI ====================
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "flywatch", description = "Fly.io log forwarder with live streams and AI log analysis"),
+    paths(
+        health_handler,
+        crate::readiness::ready_handler,
+        metrics_handler,
+        prometheus_handler,
+        crate::slo::slo_handler,
+        crate::http_analytics::http_analytics_handler,
+        crate::heartbeat::heartbeats_handler,
+        crate::alerts::active_alerts_handler,
+        crate::alerts::ack_handler,
+        crate::alerts::silences_handler,
+        crate::alerts::create_silence_handler,
+        crate::alerts::delete_silence_handler,
+        crate::maintenance::list_handler,
+        crate::maintenance::create_handler,
+        crate::maintenance::update_handler,
+        crate::maintenance::delete_handler,
+        sse_handler,
+        ws_handler,
+        logs_history_handler,
+        replay_handler,
+        crate::download::download_handler,
+        logs_since_handler,
+        crate::trace::trace_handler,
+        crate::facets::facets_handler,
+        crate::archive::sql_handler,
+        crate::views::list_handler,
+        crate::views::save_handler,
+        crate::views::get_handler,
+        crate::views::delete_handler,
+        crate::annotations::list_handler,
+        crate::annotations::create_handler,
+        crate::annotations::delete_handler,
+        crate::deploys::list_handler,
+        crate::deploys::create_handler,
+        crate::graphql::graphql_handler,
+        crate::mcp::rpc_handler,
+        crate::mcp::sse_handler,
+        crate::mcp::message_handler,
+        metrics_ws_handler,
+        crate::chat::chat_handler,
+        crate::chat::estimate_handler,
+        crate::chat_ws::chat_ws_handler,
+        crate::audit::audit_handler,
+        crate::transcripts::transcript_handler,
+        feedback_handler,
+        crate::tickets::tickets_handler,
+        crate::ws_auth::ws_ticket_handler,
+        crate::session::login_handler,
+        crate::session::session_handler,
+        crate::session::logout_handler,
+        logs_stats_handler,
+        usage_handler,
+        conversation_usage_handler,
+        crate::pricing::pricing_handler,
+        sources_handler,
+        sinks_handler,
+        nats_debug_handler,
+        connections_handler,
+        crate::channels::channels_handler,
+        disconnect_handler,
+        crate::reload::admin_reload_handler,
+        crate::logging::admin_logging_handler,
+    ),
+    components(schemas(ErrorBody, TimestampedLog, StreamFormat, StreamCompression, OverflowPolicy)),
+    modifiers(&BearerAuth)
+)]
+pub struct ApiDoc;
+
+/// Declares the `bearer` scheme referenced by routes that honor AUTH_TOKEN
+struct BearerAuth;
+
+impl Modify for BearerAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearer",
+            SecurityScheme::Http(Http::new(HttpAuthScheme::Bearer)),
+        );
+    }
+}
+
+/// Compare a presented secret with the expected one in constant time
+pub fn token_matches(given: &str, expected: &str) -> bool {
+    bool::from(given.as_bytes().ct_eq(expected.as_bytes()))
+}
+
+/// Accept a client-certificate principal, the bearer token or a session cookie
+pub fn check_auth(state: &AppState, headers: &HeaderMap) -> Result<(), ApiError> {
+    let config = state.config.current();
+    if let Some(principal) = tls::current_principal() {
+        return tls::check_principal(&config, &principal);
+    }
+    if let Some(expected_token) = &config.auth_token {
+        // Dashboard sessions; csrf_guard covers unsafe methods
+        if !headers.contains_key(header::AUTHORIZATION)
+            && session::current(&config, headers).is_some()
+        {
+            return Ok(());
+        }
         let auth_header = headers
             .get(header::AUTHORIZATION)
             .and_then(|v| v.to_str().ok());
@@ -75,122 +522,575 @@ pub fn check_auth(state: &AppState, headers: &HeaderMap) -> Result<(), Response>
         match auth_header {
             Some(h) if h.starts_with("Bearer ") => {
                 let token = &h[7..];
-                if token != expected_token {
-                    return Err((StatusCode::UNAUTHORIZED, "Invalid token").into_response());
+                if !token_matches(token, expected_token) {
+                    return Err(ApiError::Unauthorized("Invalid token".to_string()));
                 }
             }
             _ => {
-                return Err((
-                    StatusCode::UNAUTHORIZED,
-                    "Missing or invalid Authorization header",
-                )
-                    .into_response());
+                return Err(ApiError::Unauthorized(
+                    "Missing or invalid Authorization header".to_string(),
+                ));
             }
         }
     }
     Ok(())
 }
 
+#[utoipa::path(
+    get, path = "/health", tag = "health",
+    responses((status = 200, description = "Health summary", body = HealthStatus))
+)]
 async fn health_handler(State(state): State<AppState>) -> Json<HealthStatus> {
-    Json(state.metrics.health(state.start_time))
+    let mut health = state.metrics.health(state.start_time);
+    health.log_filter = state.log_filter.current();
+    health.sources = state.sources.snapshot().await;
+    health.sinks = state.sinks.snapshot();
+    Json(health)
 }
 
-async fn ready_handler(State(state): State<AppState>) -> Response {
-    if state.metrics.is_nats_connected() {
-        (StatusCode::OK, "ready").into_response()
-    } else {
-        (StatusCode::SERVICE_UNAVAILABLE, "not ready - NATS disconnected").into_response()
+#[utoipa::path(
+    get, path = "/metrics", tag = "metrics",
+    responses((status = 200, description = "Full metrics snapshot", body = MetricsSnapshot))
+)]
+async fn metrics_handler(State(state): State<AppState>) -> Json<MetricsSnapshot> {
+    Json(full_snapshot(&state).await)
+}
+
+#[utoipa::path(
+    get, path = "/metrics/prometheus", tag = "metrics",
+    responses((status = 200, description = "Metrics in the Prometheus text format", body = String, content_type = "text/plain"))
+)]
+async fn prometheus_handler(State(state): State<AppState>) -> impl IntoResponse {
+    let snapshot = full_snapshot(&state).await;
+    (
+        [(header::CONTENT_TYPE, prometheus::CONTENT_TYPE)],
+        prometheus::render(&snapshot),
+    )
+}
+
+/// The metrics snapshot with the parts only the HTTP layer can see
+pub(crate) async fn full_snapshot(state: &AppState) -> MetricsSnapshot {
+    let mut snapshot = state.metrics.snapshot(state.start_time).await;
+    snapshot.connection_queues = state.fanout.stats();
+    snapshot.sources = state.sources.snapshot().await;
+    snapshot.sinks = state.sinks.snapshot();
+    snapshot.log_metrics = state.log_metrics.snapshot();
+    snapshot
+}
+
+#[utoipa::path(
+    get, path = "/logs/buffer/stats", tag = "logs",
+    responses(
+        (status = 200, description = "Buffer summary", body = LogSummary),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+async fn logs_stats_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<LogSummary>, ApiError> {
+    check_auth(&state, &headers)?;
+    Ok(Json(state.log_buffer.get_summary().await))
+}
+
+#[utoipa::path(
+    get, path = "/usage", tag = "usage",
+    responses(
+        (status = 200, description = "Aggregated AI usage", body = UsageStats),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+async fn usage_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<UsageStats>, ApiError> {
+    check_auth(&state, &headers)?;
+    Ok(Json(state.usage_tracker.get_stats().await))
+}
+
+#[utoipa::path(
+    get, path = "/usage/conversations/{id}", tag = "usage",
+    params(("id" = String, Path, description = "conversation_id from a chat response")),
+    responses(
+        (status = 200, description = "Cost and tokens across the conversation's turns", body = ConversationUsage),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+        (status = 404, description = "No usage recorded for the conversation", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+async fn conversation_usage_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Json<ConversationUsage>, ApiError> {
+    check_auth(&state, &headers)?;
+    state
+        .usage_tracker
+        .conversation(&id)
+        .await
+        .map(Json)
+        .ok_or_else(|| ApiError::NotFound(format!("No usage recorded for conversation '{}'", id)))
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+struct FeedbackRequest {
+    rating: Rating,
+    /// What was wrong or right about the answer, up to 2000 characters
+    comment: Option<String>,
+}
+
+#[utoipa::path(
+    post, path = "/chat/{id}/feedback", tag = "chat",
+    params(("id" = String, Path, description = "conversation_id from a chat response")),
+    request_body = FeedbackRequest,
+    responses(
+        (status = 200, description = "Feedback recorded, replacing any earlier rating of the conversation", body = Feedback),
+        (status = 400, description = "Comment too long", body = ErrorBody),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+        (status = 404, description = "No usage recorded for the conversation", body = ErrorBody),
+        (status = 503, description = "STORE_PATH not configured", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+async fn feedback_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    Json(request): Json<FeedbackRequest>,
+) -> Result<Json<Feedback>, ApiError> {
+    check_auth(&state, &headers)?;
+    let comment = request.comment.filter(|c| !c.trim().is_empty());
+    if comment
+        .as_ref()
+        .is_some_and(|c| c.chars().count() > MAX_FEEDBACK_COMMENT_CHARS)
+    {
+        return Err(ApiError::InvalidRequest(format!(
+            "comment is longer than {} characters",
+            MAX_FEEDBACK_COMMENT_CHARS
+        )));
     }
+    state
+        .usage_tracker
+        .record_feedback(&id, request.rating, comment)
+        .await
+        .map(Json)
+        .map_err(|e| match e {
+            FeedbackError::NoStore => {
+                ApiError::NotConfigured("Feedback needs STORE_PATH".to_string())
+            }
+            FeedbackError::UnknownConversation => {
+                ApiError::NotFound(format!("No usage recorded for conversation '{}'", id))
+            }
+        })
 }
 
-async fn metrics_handler(State(state): State<AppState>) -> Json<MetricsSnapshot> {
-    Json(state.metrics.snapshot(state.start_time).await)
+#[utoipa::path(
+    get, path = "/sources", tag = "sources",
+    responses((status = 200, description = "Per-source health", body = Vec<SourceHealthSnapshot>))
+)]
+async fn sources_handler(State(state): State<AppState>) -> Json<Vec<SourceHealthSnapshot>> {
+    Json(state.sources.snapshot().await)
+}
+
+#[utoipa::path(
+    get, path = "/sinks", tag = "sinks",
+    responses((status = 200, description = "Per-sink queue, delivery and retry health", body = Vec<SinkHealthSnapshot>))
+)]
+async fn sinks_handler(State(state): State<AppState>) -> Json<Vec<SinkHealthSnapshot>> {
+    Json(state.sinks.snapshot())
+}
+
+#[utoipa::path(
+    get, path = "/debug/nats", tag = "health",
+    responses(
+        (status = 200, description = "NATS connection state and its recent errors, newest first", body = NatsDebug),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+async fn nats_debug_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<NatsDebug>, ApiError> {
+    check_auth(&state, &headers)?;
+    Ok(Json(state.metrics.nats_debug()))
+}
+
+#[utoipa::path(
+    get, path = "/connections", tag = "connections",
+    responses(
+        (status = 200, description = "Active streaming connections", body = Vec<ConnectionSnapshot>),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+async fn connections_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<ConnectionSnapshot>>, ApiError> {
+    check_auth(&state, &headers)?;
+    Ok(Json(state.fanout.connections()))
 }
 
-async fn logs_stats_handler(State(state): State<AppState>) -> Json<LogSummary> {
-    Json(state.log_buffer.get_summary().await)
+#[utoipa::path(
+    delete, path = "/connections/{id}", tag = "connections",
+    params(("id" = uuid::Uuid, Path, description = "Connection id from GET /connections")),
+    responses(
+        (status = 204, description = "Connection closed"),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+        (status = 404, description = "No such connection", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+async fn disconnect_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<uuid::Uuid>,
+) -> Result<StatusCode, ApiError> {
+    check_auth(&state, &headers)?;
+    if state.fanout.disconnect(id) {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(ApiError::NotFound("Connection not found".to_string()))
+    }
 }
 
-async fn usage_handler(State(state): State<AppState>) -> Json<UsageStats> {
-    Json(state.usage_tracker.get_stats().await)
+/// Describe a streaming client; behind the Fly proxy the peer address is the
+/// proxy itself, so prefer the client IP it forwards
+pub(crate) fn connection_info(
+    kind: &'static str,
+    peer: SocketAddr,
+    headers: &HeaderMap,
+    filters: BTreeMap<String, String>,
+) -> ConnectionInfo {
+    let remote_addr = headers
+        .get("fly-client-ip")
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+        .unwrap_or_else(|| peer.to_string());
+    ConnectionInfo {
+        kind,
+        remote_addr,
+        filters,
+    }
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, IntoParams)]
 struct HistoryQuery {
     before: Option<String>,
+    cursor: Option<String>,
     limit: Option<usize>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 struct HistoryResponse {
     logs: Vec<TimestampedLog>,
     total_count: usize,
+    total_estimate: usize,
     has_more: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next_cursor: Option<String>,
+    /// Notes that refer to lines or times on this page
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    annotations: Vec<Annotation>,
 }
 
+#[utoipa::path(
+    get, path = "/logs/history", tag = "logs",
+    params(HistoryQuery, FilterParams),
+    responses(
+        (status = 200, description = "A page of logs, newest first", body = HistoryResponse),
+        (status = 400, description = "Invalid parameters", body = ErrorBody),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
 async fn logs_history_handler(
     State(state): State<AppState>,
+    headers: HeaderMap,
     Query(query): Query<HistoryQuery>,
-) -> Result<Json<HistoryResponse>, (StatusCode, String)> {
+    Query(filter): Query<FilterParams>,
+) -> Result<Json<HistoryResponse>, ApiError> {
+    check_auth(&state, &headers)?;
     let limit = query.limit.unwrap_or(100).min(1000); // Default 100, max 1000
 
+    let cursor = match query.cursor {
+        Some(c) => Some(
+            LogCursor::decode(&c)
+                .ok_or_else(|| ApiError::InvalidRequest("Invalid 'cursor'".to_string()))?,
+        ),
+        None => None,
+    };
     let before = match query.before {
-        Some(ts) => {
-            DateTime::parse_from_rfc3339(&ts)
-                .map(|dt| dt.with_timezone(&Utc))
-                .map_err(|e| {
-                    (
-                        StatusCode::BAD_REQUEST,
-                        format!("Invalid 'before' timestamp: {}. Use RFC3339 format.", e),
-                    )
-                })?
-        }
+        Some(ts) => parse_timestamp("before", &ts)?,
         None => Utc::now(),
     };
 
-    let logs = state.log_buffer.get_before(before, limit).await;
+    let filter = filter.compile(&state.views)?;
+    let since = filter.since(Utc::now());
+    let page = state
+        .log_buffer
+        .page_before(before, cursor.as_ref(), limit, |log| {
+            filter.matches(log) && since.is_none_or(|since| log.timestamp >= since)
+        })
+        .await;
     let total_count = state.log_buffer.total_count().await;
 
-    // Check if there are more logs before the oldest returned log
-    let has_more = if let Some(oldest) = logs.first() {
-        let older_logs = state.log_buffer.get_before(oldest.timestamp, 1).await;
-        !older_logs.is_empty()
-    } else {
-        false
-    };
-
     Ok(Json(HistoryResponse {
-        logs,
+        annotations: state.annotations.overlapping(&page.logs, state.app_scope()),
+        logs: page.logs,
         total_count,
-        has_more,
+        total_estimate: page.total_estimate,
+        has_more: page.next_cursor.is_some(),
+        next_cursor: page.next_cursor.map(|c| c.encode()),
     }))
 }
 
+/// How stream events carry each log
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+enum StreamFormat {
+    /// The original log line, untouched
+    #[default]
+    Raw,
+    /// The parsed `TimestampedLog` as JSON
+    Json,
+}
+
+impl StreamFormat {
+    fn encode(self, log: &TimestampedLog) -> String {
+        match self {
+            StreamFormat::Raw => log.raw.clone(),
+            StreamFormat::Json => serde_json::to_string(log).unwrap_or_default(),
+        }
+    }
+
+    /// The log as an element of a JSON frame
+    fn value(self, log: &TimestampedLog) -> serde_json::Value {
+        match self {
+            StreamFormat::Raw => serde_json::Value::String(log.raw.clone()),
+            StreamFormat::Json => serde_json::to_value(log).unwrap_or_default(),
+        }
+    }
+}
+
+/// Per-connection options shared by the SSE and WebSocket streams
+#[derive(Debug, Default, Deserialize, IntoParams)]
+struct StreamQuery {
+    /// Queue capacity for this connection, at most `STREAM_QUEUE_MAX`
+    queue: Option<usize>,
+    /// Overflow policy: drop_oldest, drop_newest, or disconnect
+    overflow: Option<String>,
+    #[serde(default)]
+    format: StreamFormat,
+    /// SSE only: none, gzip, or deflate as the response's Content-Encoding;
+    /// negotiated from Accept-Encoding when unset. WebSockets use `encoding`.
+    compression: Option<StreamCompression>,
+    /// Coalesce logs arriving within this many milliseconds into one frame
+    batch_ms: Option<u64>,
+    /// Only lines routed into this channel (see `/channels`; default all)
+    channel: Option<String>,
+}
+
+impl StreamQuery {
+    /// The channel's conditions, for lines replayed from the buffer; live
+    /// lines arrive already routed
+    fn channel(&self, fanout: &Fanout) -> Result<Filter, ApiError> {
+        let name = self.channel.as_deref().unwrap_or(channels::ALL);
+        fanout
+            .channel_filter(name)
+            .ok_or_else(|| ApiError::InvalidRequest(format!("Unknown channel '{}'", name)))
+    }
+
+    fn policy(&self) -> Result<Option<OverflowPolicy>, ApiError> {
+        self.overflow
+            .as_deref()
+            .map(str::parse)
+            .transpose()
+            .map_err(ApiError::InvalidRequest)
+    }
+
+    /// The requested queue capacity, refusing anything above `max`
+    fn queue(&self, max: usize) -> Result<Option<usize>, ApiError> {
+        match self.queue {
+            Some(queue) if queue > max => Err(ApiError::InvalidRequest(format!(
+                "'queue' must be at most {}, got {}",
+                max, queue
+            ))),
+            queue => Ok(queue),
+        }
+    }
+
+    fn batch_window(&self) -> Option<Duration> {
+        self.batch_ms
+            .filter(|ms| *ms > 0)
+            .map(|ms| Duration::from_millis(ms.min(MAX_BATCH_WINDOW_MS)))
+    }
+}
+
+/// Wait for the next log, or the next batch when a window is set
+async fn recv_logs(
+    subscription: &Subscription,
+    batch: Option<Duration>,
+) -> Result<Vec<LogMessage>, RecvError> {
+    match batch {
+        Some(window) => subscription.recv_batch(window, MAX_BATCH_SIZE).await,
+        None => subscription.recv().await.map(|log| vec![log]),
+    }
+}
+
+/// Encode logs as SSE events: one per log, or JSON arrays when batching.
+/// Each event's id is the sequence of its last log.
+fn sse_log_events<L: Borrow<TimestampedLog>>(
+    logs: &[L],
+    format: StreamFormat,
+    batched: bool,
+) -> Vec<Event> {
+    let chunk_size = if batched { MAX_BATCH_SIZE } else { 1 };
+    logs.chunks(chunk_size)
+        .filter_map(|chunk| {
+            let last = chunk.last()?.borrow();
+            let data = if batched {
+                let items = chunk.iter().map(|l| format.value(l.borrow())).collect();
+                serde_json::Value::Array(items).to_string()
+            } else {
+                format.encode(last)
+            };
+            Some(Event::default().id(last.seq.to_string()).data(data))
+        })
+        .collect()
+}
+
+#[utoipa::path(
+    get, path = "/logs/stream", tag = "streams",
+    params(StreamQuery, FilterParams, SseAuthQuery),
+    responses(
+        (status = 200, description = "Server-sent events, one per log (or batch)", content_type = "text/event-stream", body = String),
+        (status = 400, description = "Invalid parameters", body = ErrorBody),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
 async fn sse_handler(
     State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
     headers: HeaderMap,
-) -> Result<Sse<impl futures::Stream<Item = Result<Event, Infallible>>>, Response> {
-    check_auth(&state, &headers)?;
+    Query(query): Query<StreamQuery>,
+    Query(filter): Query<FilterParams>,
+    Query(mut params): Query<BTreeMap<String, String>>,
+) -> Result<Response, ApiError> {
+    // Keep query credentials out of the connection's listed filters
+    let credentials = SseAuthQuery {
+        access_token: params.remove("access_token"),
+        ticket: params.remove("ticket"),
+    };
+    ws_auth::authorize_sse(&state, &headers, &credentials)?;
+    let policy = query.policy()?;
+    let queue = query.queue(state.config.current().stream_queue_max)?;
+    let filter = filter.compile(&state.views)?;
+    let channel = query.channel(&state.fanout)?;
+    let compression = query.compression.unwrap_or_else(|| {
+        StreamCompression::negotiate(
+            headers
+                .get(header::ACCEPT_ENCODING)
+                .and_then(|v| v.to_str().ok()),
+        )
+    });
+
+    // EventSource sends the id of the last event it saw when reconnecting
+    let resume_from = headers
+        .get("last-event-id")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<u64>().ok());
 
     state.metrics.increment_sse_connections();
     let metrics = state.metrics.clone();
-    let mut rx = state.log_tx.subscribe();
+    // Subscribe before reading the backfill so nothing falls between the two
+    let info = connection_info("sse", peer, &headers, params);
+    let subscription = state
+        .fanout
+        .subscribe_to(info, query.channel.clone(), queue, policy);
+    let log_buffer = state.log_buffer.clone();
+    let format = query.format;
+    let batch = query.batch_window();
+    let mut shutdown = state.shutdown.subscribe();
 
     let stream = async_stream::stream! {
+        let mut last_seq: Option<u64> = None;
+
+        if let Some(resume_seq) = resume_from {
+            let (mut backfill, oldest_seq) = log_buffer.get_since(resume_seq, usize::MAX).await;
+            if let Some(oldest) = oldest_seq.filter(|oldest| *oldest > resume_seq + 1) {
+                let gap_event = serde_json::json!({
+                    "type": "gap",
+                    "message": "Some logs after Last-Event-ID were already evicted from the buffer",
+                    "missed_from": resume_seq + 1,
+                    "missed_to": oldest - 1
+                });
+                yield Ok::<_, Infallible>(Event::default().event("gap").data(gap_event.to_string()));
+            }
+
+            info!(resume_from = resume_seq, backfill = backfill.len(), "SSE client resuming");
+            last_seq = backfill.last().map(|log| log.seq).or(last_seq);
+            backfill.retain(|log| channel.matches(log) && filter.matches(log));
+            for event in sse_log_events(&backfill, format, batch.is_some()) {
+                yield Ok(event);
+            }
+        }
+
         loop {
-            match rx.recv().await {
-                Ok(log_msg) => {
-                    yield Ok(Event::default().data(log_msg.raw));
+            let result = tokio::select! {
+                result = recv_logs(&subscription, batch) => result,
+                // Let graceful shutdown finish; EventSource reconnects on its own
+                _ = shutting_down(&mut shutdown) => break,
+            };
+            match result {
+                Ok(mut logs) => {
+                    // Skip live entries already delivered by the backfill
+                    logs.retain(|log| {
+                        last_seq.is_none_or(|seq| log.seq > seq) && filter.matches(log)
+                    });
+                    let Some(last) = logs.last() else {
+                        continue;
+                    };
+                    last_seq = Some(last.seq);
+                    for event in sse_log_events(&logs, format, batch.is_some()) {
+                        yield Ok(event);
+                    }
                 }
-                Err(broadcast::error::RecvError::Lagged(n)) => {
+                Err(RecvError::Lagged(n)) => {
                     warn!(skipped = n, "SSE client lagged");
                     let err_event = serde_json::json!({
                         "type": "error",
-                        "message": format!("Lagged {} messages", n)
+                        "message": format!("Lagged {} messages", n),
+                        "last_seq": last_seq
+                    });
+                    yield Ok(Event::default().event("error").data(err_event.to_string()));
+                }
+                Err(RecvError::Overflowed) => {
+                    warn!("SSE client queue overflowed, disconnecting");
+                    let err_event = serde_json::json!({
+                        "type": "error",
+                        "code": "SLOW_CONSUMER",
+                        "message": "Connection queue overflowed",
+                        "last_seq": last_seq
+                    });
+                    yield Ok(Event::default().event("error").data(err_event.to_string()));
+                    break;
+                }
+                Err(RecvError::Evicted) => {
+                    let err_event = serde_json::json!({
+                        "type": "error",
+                        "code": "DISCONNECTED",
+                        "message": "Connection closed by an operator",
+                        "last_seq": last_seq
                     });
                     yield Ok(Event::default().event("error").data(err_event.to_string()));
+                    break;
                 }
-                Err(broadcast::error::RecvError::Closed) => {
+                Err(RecvError::Closed) => {
                     break;
                 }
             }
@@ -199,6 +1099,175 @@ async fn sse_handler(
         info!("SSE client disconnected");
     };
 
+    let sse = Sse::new(stream).keep_alive(
+        KeepAlive::new()
+            .interval(Duration::from_secs(15))
+            .text("ping"),
+    );
+    Ok(compress_sse(sse.into_response(), compression))
+}
+
+/// Apply streaming compression to an SSE response
+fn compress_sse(response: Response, compression: StreamCompression) -> Response {
+    let Some(encoding) = compression.content_encoding() else {
+        return response;
+    };
+    let (mut parts, body) = response.into_parts();
+    parts
+        .headers
+        .insert(header::CONTENT_ENCODING, header::HeaderValue::from_static(encoding));
+    parts
+        .headers
+        .append(header::VARY, header::HeaderValue::from_static("accept-encoding"));
+    Response::from_parts(parts, compression.stream_body(body))
+}
+
+#[derive(Deserialize, IntoParams)]
+struct SinceQuery {
+    limit: Option<usize>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct SinceResponse {
+    logs: Vec<TimestampedLog>,
+    /// Oldest sequence still in the buffer
+    oldest_seq: Option<u64>,
+    /// True if entries after the requested sequence were already evicted
+    gap: bool,
+    has_more: bool,
+    /// Notes that refer to these lines or their times
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    annotations: Vec<Annotation>,
+}
+
+/// Backfill by sequence: everything after `seq`, oldest first
+#[utoipa::path(
+    get, path = "/logs/since/{seq}", tag = "logs",
+    params(("seq" = u64, Path, description = "Last sequence the client has seen"), SinceQuery),
+    responses(
+        (status = 200, description = "Logs after `seq`, oldest first", body = SinceResponse),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+async fn logs_since_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(seq): Path<u64>,
+    Query(query): Query<SinceQuery>,
+) -> Result<Json<SinceResponse>, ApiError> {
+    check_auth(&state, &headers)?;
+
+    let limit = query.limit.unwrap_or(1000).min(1000);
+    // Fetch one extra entry to learn whether another page exists
+    let (mut logs, oldest_seq) = state.log_buffer.get_since(seq, limit + 1).await;
+    let has_more = logs.len() > limit;
+    logs.truncate(limit);
+
+    Ok(Json(SinceResponse {
+        annotations: state.annotations.overlapping(&logs, state.app_scope()),
+        logs,
+        oldest_seq,
+        gap: oldest_seq.is_some_and(|oldest| oldest > seq + 1),
+        has_more,
+    }))
+}
+
+#[derive(Deserialize, IntoParams)]
+struct ReplayQuery {
+    from: String,
+    to: Option<String>,
+    speed: Option<String>,
+}
+
+/// Parse a replay speed like "2x", "0.5", or "max" (no pacing)
+fn parse_speed(speed: &str) -> Option<f64> {
+    if speed.eq_ignore_ascii_case("max") {
+        return Some(f64::INFINITY);
+    }
+    speed
+        .trim_end_matches(['x', 'X'])
+        .parse::<f64>()
+        .ok()
+        .filter(|s| *s > 0.0)
+}
+
+pub fn parse_timestamp(name: &str, ts: &str) -> Result<DateTime<Utc>, ApiError> {
+    DateTime::parse_from_rfc3339(ts)
+        .map(|dt| dt.with_timezone(&Utc))
+        .map_err(|e| {
+            ApiError::InvalidRequest(format!(
+                "Invalid '{}' timestamp: {}. Use RFC3339 format.",
+                name, e
+            ))
+        })
+}
+
+#[utoipa::path(
+    get, path = "/logs/replay", tag = "streams",
+    params(ReplayQuery, FilterParams),
+    responses(
+        (status = 200, description = "Buffered logs replayed as server-sent events", content_type = "text/event-stream", body = String),
+        (status = 400, description = "Invalid parameters", body = ErrorBody),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+async fn replay_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<ReplayQuery>,
+    Query(filter): Query<FilterParams>,
+) -> Result<Sse<impl futures::Stream<Item = Result<Event, Infallible>>>, ApiError> {
+    check_auth(&state, &headers)?;
+    let filter = filter.compile(&state.views)?;
+
+    let from = parse_timestamp("from", &query.from)?;
+    let to = match query.to {
+        Some(ts) => parse_timestamp("to", &ts)?,
+        None => Utc::now(),
+    };
+    if from > to {
+        return Err(ApiError::InvalidRequest(
+            "'from' must be before 'to'".to_string(),
+        ));
+    }
+    let speed = match query.speed {
+        Some(s) => parse_speed(&s).ok_or_else(|| {
+            ApiError::InvalidRequest("Invalid 'speed'. Use e.g. 1x, 2x, 0.5x, or max.".to_string())
+        })?,
+        None => 1.0,
+    };
+
+    let mut logs = state.log_buffer.get_time_range(from, to).await;
+    logs.retain(|log| filter.matches(log));
+    info!(count = logs.len(), speed = speed, "Starting log replay");
+    let mut shutdown = state.shutdown.subscribe();
+
+    let stream = async_stream::stream! {
+        let total = logs.len();
+        let mut previous: Option<DateTime<Utc>> = None;
+
+        for log in logs {
+            // Reproduce the original spacing between entries, scaled by speed
+            if let Some(prev) = previous {
+                let gap = (log.timestamp - prev).to_std().unwrap_or_default();
+                if speed.is_finite() && !gap.is_zero() {
+                    tokio::select! {
+                        _ = tokio::time::sleep(gap.div_f64(speed)) => {}
+                        // Don't hold up graceful shutdown for the rest of the replay
+                        _ = shutting_down(&mut shutdown) => return,
+                    }
+                }
+            }
+            previous = Some(log.timestamp);
+            yield Ok(Event::default().id(log.seq.to_string()).data(log.raw));
+        }
+
+        let end_event = serde_json::json!({ "type": "end", "count": total });
+        yield Ok(Event::default().event("end").data(end_event.to_string()));
+    };
+
     Ok(Sse::new(stream).keep_alive(
         KeepAlive::new()
             .interval(Duration::from_secs(15))
@@ -206,102 +1275,311 @@ async fn sse_handler(
     ))
 }
 
+#[derive(Debug, Deserialize, IntoParams)]
+struct LogWsQuery {
+    /// "reliable" enables seq-tagged frames, client acks, and retransmission
+    mode: Option<String>,
+    /// Resume after this sequence (reliable mode only)
+    since: Option<u64>,
+}
+
+#[utoipa::path(
+    get, path = "/logs/ws", tag = "streams",
+    params(LogWsQuery, StreamQuery, WsEncodingQuery, FilterParams, TicketQuery),
+    responses(
+        (status = 101, description = "WebSocket upgrade; text frames carry logs, or binary frames each holding one gzip/zlib stream with `encoding`"),
+        (status = 400, description = "Invalid parameters", body = ErrorBody),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+// Axum extractors, one per query shape
+#[allow(clippy::too_many_arguments)]
 async fn ws_handler(
     State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
     headers: HeaderMap,
+    Query(query): Query<LogWsQuery>,
+    Query(stream): Query<StreamQuery>,
+    Query(encoding): Query<WsEncodingQuery>,
+    Query(filter): Query<FilterParams>,
+    Query(mut params): Query<BTreeMap<String, String>>,
     ws: WebSocketUpgrade,
-) -> Result<Response, Response> {
-    check_auth(&state, &headers)?;
-    Ok(ws
+) -> Result<Response, ApiError> {
+    // Keep the ticket out of the connection's listed filters
+    let ticket = TicketQuery {
+        ticket: params.remove("ticket"),
+    };
+    let auth = ws_auth::authorize(&state, &headers, &ticket)?;
+    if stream.compression.is_some() {
+        return Err(ApiError::InvalidRequest(
+            "'compression' applies to /logs/stream; WebSockets take 'encoding'".to_string(),
+        ));
+    }
+    let policy = stream.policy()?;
+    let queue = stream.queue(state.config.current().stream_queue_max)?;
+    let filter = Arc::new(filter.compile(&state.views)?);
+    let channel = stream.channel(&state.fanout)?;
+
+    let reliable = match query.mode.as_deref() {
+        None | Some("raw") => false,
+        Some("reliable") => true,
+        Some(other) => {
+            return Err(ApiError::InvalidRequest(format!(
+                "Invalid 'mode': {}. Use raw or reliable.",
+                other
+            )));
+        }
+    };
+
+    let options = LogWsOptions {
+        reliable,
+        format: stream.format,
+        compression: encoding.encoding.unwrap_or(StreamCompression::None),
+        batch: stream.batch_window(),
+        since: query.since,
+        filter,
+        channel,
+    };
+
+    Ok(auth
+        .upgrade(ws)
         .max_frame_size(WS_MAX_FRAME_SIZE)
-        .on_upgrade(move |socket| handle_log_websocket(socket, state)))
+        .on_upgrade(move |socket| {
+            let info = connection_info("websocket", peer, &headers, params);
+            let subscription = state
+                .fanout
+                .subscribe_to(info, stream.channel, queue, policy);
+            handle_log_websocket(socket, state, auth, subscription, options)
+        }))
+}
+
+/// Per-connection settings for a log WebSocket
+#[derive(Debug, Clone)]
+struct LogWsOptions {
+    reliable: bool,
+    format: StreamFormat,
+    compression: StreamCompression,
+    batch: Option<Duration>,
+    since: Option<u64>,
+    /// Only lines passing this are sent
+    filter: Arc<RequestFilter>,
+    /// The subscribed channel's conditions, for replayed lines
+    channel: Filter,
+}
+
+/// Encode logs for the wire: one frame per log, or JSON arrays when batching.
+/// Reliable mode tags each log with its sequence.
+fn log_frames<L: Borrow<TimestampedLog>>(logs: &[L], options: &LogWsOptions) -> Vec<String> {
+    if options.batch.is_none() {
+        return logs
+            .iter()
+            .map(|log| log_frame(log.borrow(), options))
+            .collect();
+    }
+
+    logs.chunks(MAX_BATCH_SIZE)
+        .map(|chunk| {
+            let items: Vec<serde_json::Value> = chunk
+                .iter()
+                .map(|log| {
+                    let log = log.borrow();
+                    let data = options.format.value(log);
+                    if options.reliable {
+                        serde_json::json!({ "seq": log.seq, "data": data })
+                    } else {
+                        data
+                    }
+                })
+                .collect();
+            if options.reliable {
+                serde_json::json!({ "type": "batch", "logs": items }).to_string()
+            } else {
+                serde_json::Value::Array(items).to_string()
+            }
+        })
+        .collect()
+}
+
+fn log_frame(log: &TimestampedLog, options: &LogWsOptions) -> String {
+    if options.reliable {
+        let data = options.format.value(log);
+        return serde_json::json!({ "type": "log", "seq": log.seq, "data": data }).to_string();
+    }
+    if options.format == StreamFormat::Json {
+        return options.format.encode(log);
+    }
+    if log.raw.len() > WS_MAX_FRAME_SIZE {
+        warn!("Log message too large, truncating");
+        return prefix_bytes(&log.raw, WS_MAX_FRAME_SIZE).to_string();
+    }
+    log.raw.clone()
 }
 
-async fn handle_log_websocket(socket: WebSocket, state: AppState) {
+async fn handle_log_websocket(
+    socket: WebSocket,
+    state: AppState,
+    auth: WsAuth,
+    subscription: Subscription,
+    options: LogWsOptions,
+) {
+    let LogWsOptions {
+        reliable,
+        compression,
+        batch,
+        since,
+        ..
+    } = options;
     state.metrics.increment_ws_connections();
     let metrics = state.metrics.clone();
-    let connection_id = uuid::Uuid::new_v4();
+    let connection_id = subscription.id();
 
-    info!(connection_id = %connection_id, "WebSocket client connected for logs");
+    info!(connection_id = %connection_id, reliable, "WebSocket client connected for logs");
 
     let (mut sender, mut receiver) = socket.split();
-    let mut rx = state.log_tx.subscribe();
+    let log_buffer = state.log_buffer.clone();
+
+    // Highest sequence the client has acknowledged (reliable mode)
+    let acked = Arc::new(AtomicU64::new(since.unwrap_or(0)));
+    let acked_for_recv = acked.clone();
 
-    let (ping_tx, mut ping_rx) = tokio::sync::mpsc::channel::<()>(1);
+    // Ok(()) asks for a ping, Err closes the connection
+    let (ping_tx, mut ping_rx) = tokio::sync::mpsc::channel::<Result<(), WsClose>>(1);
     let last_pong = Arc::new(tokio::sync::Mutex::new(Instant::now()));
     let last_pong_clone = last_pong.clone();
+    let mut shutdown = state.shutdown.subscribe();
 
-    // Ping task - sends pings and checks for pong timeout
+    // Ping task - sends pings, checks for pong timeout and revoked tokens
+    let ping_state = state.clone();
     let ping_task = tokio::spawn(async move {
         let mut interval = tokio::time::interval(WS_PING_INTERVAL);
         loop {
             interval.tick().await;
 
             let last = *last_pong_clone.lock().await;
-            if last.elapsed() > WS_PING_INTERVAL + WS_PONG_TIMEOUT {
+            let tick = if last.elapsed() > WS_PING_INTERVAL + WS_PONG_TIMEOUT {
                 warn!(connection_id = %connection_id, "WebSocket pong timeout");
-                break;
-            }
+                Err(WsClose::PongTimeout)
+            } else if !auth.still_valid(&ping_state) {
+                info!(connection_id = %connection_id, "WebSocket token no longer valid");
+                Err(WsClose::PolicyViolation)
+            } else {
+                Ok(())
+            };
 
-            if ping_tx.send(()).await.is_err() {
+            let stop = tick.is_err();
+            if ping_tx.send(tick).await.is_err() || stop {
                 break;
             }
         }
     });
 
     // Send task - sends logs and pings
-    let last_pong_for_send = last_pong.clone();
     let send_task = tokio::spawn(async move {
-        loop {
+        let mut last_seq: Option<u64> = None;
+
+        // Reliable clients resuming after a known sequence get a backfill first
+        if let (true, Some(since)) = (reliable, since) {
+            match retransmit(&mut sender, &log_buffer, since, &options).await {
+                Ok(seq) => last_seq = seq.or(last_seq),
+                Err(_) => return,
+            }
+        }
+
+        let close = loop {
             tokio::select! {
                 biased;
 
-                Some(()) = ping_rx.recv() => {
-                    debug!("Sending WebSocket ping");
-                    if sender.send(Message::Ping(vec![].into())).await.is_err() {
-                        break;
+                _ = shutting_down(&mut shutdown) => break WsClose::GoingAway,
+
+                Some(tick) = ping_rx.recv() => match tick {
+                    Ok(()) => {
+                        debug!("Sending WebSocket ping");
+                        if sender.send(Message::Ping(vec![].into())).await.is_err() {
+                            return;
+                        }
                     }
-                }
+                    Err(close) => break close,
+                },
 
-                result = rx.recv() => {
+                result = recv_logs(&subscription, batch) => {
                     match result {
-                        Ok(log_msg) => {
-                            if log_msg.raw.len() > WS_MAX_FRAME_SIZE {
-                                warn!("Log message too large, truncating");
-                                let truncated = &log_msg.raw[..WS_MAX_FRAME_SIZE];
-                                if sender.send(Message::Text(truncated.to_string().into())).await.is_err() {
+                        Ok(mut logs) => {
+                            // Already delivered by a backfill or retransmission
+                            logs.retain(|log| {
+                                last_seq.is_none_or(|seq| log.seq > seq)
+                                    && options.filter.matches(log)
+                            });
+                            let Some(last) = logs.last() else {
+                                continue;
+                            };
+                            last_seq = Some(last.seq);
+                            let mut sent = true;
+                            for frame in log_frames(&logs, &options) {
+                                if sender.send(compression.ws_message(frame)).await.is_err() {
+                                    sent = false;
                                     break;
                                 }
-                            } else if sender.send(Message::Text(log_msg.raw.into())).await.is_err() {
-                                break;
+                            }
+                            if !sent {
+                                return;
+                            }
+                        }
+                        Err(RecvError::Lagged(n)) if reliable => {
+                            // Resend everything the client hasn't acknowledged
+                            let from = acked.load(Ordering::SeqCst);
+                            warn!(skipped = n, from_seq = from, "Reliable WebSocket client lagged, retransmitting");
+                            match retransmit(&mut sender, &log_buffer, from, &options).await {
+                                Ok(seq) => last_seq = seq.or(last_seq),
+                                Err(_) => return,
                             }
                         }
-                        Err(broadcast::error::RecvError::Lagged(n)) => {
+                        Err(RecvError::Lagged(n)) => {
                             warn!(skipped = n, "WebSocket client lagged");
                             let error_msg = serde_json::json!({
                                 "type": "error",
                                 "code": "LAGGED",
-                                "message": format!("Lagged {} messages", n)
+                                "message": format!("Lagged {} messages", n),
+                                "last_seq": last_seq
                             });
-                            if sender.send(Message::Text(error_msg.to_string().into())).await.is_err() {
-                                break;
+                            if sender.send(compression.ws_message(error_msg.to_string())).await.is_err() {
+                                return;
                             }
                         }
-                        Err(broadcast::error::RecvError::Closed) => {
+                        Err(RecvError::Overflowed) => {
+                            warn!(connection_id = %connection_id, "WebSocket client queue overflowed, disconnecting");
+                            let close_msg = serde_json::json!({
+                                "type": "close",
+                                "code": "SLOW_CONSUMER",
+                                "message": "Connection queue overflowed",
+                                "last_seq": last_seq
+                            });
+                            let _ = sender.send(compression.ws_message(close_msg.to_string())).await;
+                            break WsClose::TryAgainLater;
+                        }
+                        Err(RecvError::Evicted) => {
+                            let close_msg = serde_json::json!({
+                                "type": "close",
+                                "code": "DISCONNECTED",
+                                "message": "Connection closed by an operator"
+                            });
+                            let _ = sender.send(compression.ws_message(close_msg.to_string())).await;
+                            break WsClose::Disconnected;
+                        }
+                        Err(RecvError::Closed) => {
                             let close_msg = serde_json::json!({
                                 "type": "close",
                                 "code": "CHANNEL_CLOSED",
                                 "message": "Log channel closed"
                             });
-                            let _ = sender.send(Message::Text(close_msg.to_string().into())).await;
-                            break;
+                            let _ = sender.send(compression.ws_message(close_msg.to_string())).await;
+                            break WsClose::GoingAway;
                         }
                     }
                 }
             }
-        }
-        let _ = sender.send(Message::Close(None)).await;
-        let _ = last_pong_for_send;
+        };
+        let _ = sender.send(close.message()).await;
     });
 
     // Receive task - handles incoming messages
@@ -324,8 +1602,14 @@ async fn handle_log_websocket(socket: WebSocket, state: AppState) {
                 Ok(Message::Text(text)) => {
                     // Handle client commands if needed
                     if let Ok(cmd) = serde_json::from_str::<serde_json::Value>(&text) {
-                        if cmd.get("type").and_then(|t| t.as_str()) == Some("ping") {
-                            debug!("Received application-level ping");
+                        match cmd.get("type").and_then(|t| t.as_str()) {
+                            Some("ping") => debug!("Received application-level ping"),
+                            Some("ack") => {
+                                if let Some(seq) = cmd.get("seq").and_then(|s| s.as_u64()) {
+                                    acked_for_recv.fetch_max(seq, Ordering::SeqCst);
+                                }
+                            }
+                            _ => {}
                         }
                     }
                 }
@@ -349,15 +1633,68 @@ async fn handle_log_websocket(socket: WebSocket, state: AppState) {
     info!(connection_id = %connection_id, "WebSocket client disconnected");
 }
 
+/// Send buffered logs after `from_seq` as reliable frames, reporting any range
+/// already evicted. Returns the last sequence sent.
+async fn retransmit(
+    sender: &mut futures::stream::SplitSink<WebSocket, Message>,
+    log_buffer: &LogBuffer,
+    from_seq: u64,
+    options: &LogWsOptions,
+) -> Result<Option<u64>, axum::Error> {
+    let compression = options.compression;
+    let (mut missed, oldest_seq) = log_buffer.get_since(from_seq, usize::MAX).await;
+
+    if let Some(oldest) = oldest_seq.filter(|oldest| *oldest > from_seq + 1) {
+        let gap_msg = serde_json::json!({
+            "type": "gap",
+            "missed_from": from_seq + 1,
+            "missed_to": oldest - 1
+        });
+        sender
+            .send(compression.ws_message(gap_msg.to_string()))
+            .await?;
+    }
+
+    let last_seq = missed.last().map(|log| log.seq);
+    missed.retain(|log| options.channel.matches(log) && options.filter.matches(log));
+    for frame in log_frames(&missed, options) {
+        sender.send(compression.ws_message(frame)).await?;
+    }
+    Ok(last_seq)
+}
+
+/// flywatch's own opt-in frame encoding, not RFC 7692 permessage-deflate:
+/// with gzip or deflate every message goes out as a binary frame holding one
+/// complete gzip or zlib stream, to be inflated on its own. Text frames are
+/// the default.
+#[derive(Debug, Deserialize, IntoParams)]
+struct WsEncodingQuery {
+    /// none (text frames), gzip, or deflate (zlib)
+    encoding: Option<StreamCompression>,
+}
+
+#[utoipa::path(
+    get, path = "/metrics/ws", tag = "streams",
+    params(WsEncodingQuery, TicketQuery),
+    responses(
+        (status = 101, description = "WebSocket upgrade; one metrics frame per second, binary gzip/zlib with `encoding`"),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
 async fn metrics_ws_handler(
     State(state): State<AppState>,
     headers: HeaderMap,
+    Query(query): Query<WsEncodingQuery>,
+    Query(ticket): Query<TicketQuery>,
     ws: WebSocketUpgrade,
-) -> Result<Response, Response> {
-    check_auth(&state, &headers)?;
-    Ok(ws
+) -> Result<Response, ApiError> {
+    let auth = ws_auth::authorize(&state, &headers, &ticket)?;
+    let compression = query.encoding.unwrap_or(StreamCompression::None);
+    Ok(auth
+        .upgrade(ws)
         .max_frame_size(WS_MAX_FRAME_SIZE)
-        .on_upgrade(move |socket| handle_metrics_websocket(socket, state)))
+        .on_upgrade(move |socket| handle_metrics_websocket(socket, state, auth, compression)))
 }
 
 #[derive(Serialize)]
@@ -375,39 +1712,52 @@ struct ErrorEvent {
     message: String,
 }
 
-async fn handle_metrics_websocket(socket: WebSocket, state: AppState) {
+async fn handle_metrics_websocket(
+    socket: WebSocket,
+    state: AppState,
+    auth: WsAuth,
+    compression: StreamCompression,
+) {
     let connection_id = uuid::Uuid::new_v4();
     info!(connection_id = %connection_id, "WebSocket client connected for metrics");
 
     let (mut sender, mut receiver) = socket.split();
     let metrics = state.metrics.clone();
+    let fanout = state.fanout.clone();
     let start_time = state.start_time;
 
     let last_pong = Arc::new(tokio::sync::Mutex::new(Instant::now()));
     let last_pong_clone = last_pong.clone();
+    let mut shutdown = state.shutdown.subscribe();
 
     // Combined send task - metrics + pings
     let send_task = tokio::spawn(async move {
         let mut metrics_interval = tokio::time::interval(Duration::from_secs(1));
         let mut ping_interval = tokio::time::interval(WS_PING_INTERVAL);
 
-        loop {
+        let close = loop {
             tokio::select! {
                 biased;
 
+                _ = shutting_down(&mut shutdown) => break WsClose::GoingAway,
+
                 _ = ping_interval.tick() => {
                     let last = *last_pong_clone.lock().await;
                     if last.elapsed() > WS_PING_INTERVAL + WS_PONG_TIMEOUT {
                         warn!(connection_id = %connection_id, "Metrics WebSocket pong timeout");
-                        break;
+                        break WsClose::PongTimeout;
+                    }
+                    if !auth.still_valid(&state) {
+                        break WsClose::PolicyViolation;
                     }
                     if sender.send(Message::Ping(vec![].into())).await.is_err() {
-                        break;
+                        return;
                     }
                 }
 
                 _ = metrics_interval.tick() => {
-                    let snapshot = metrics.snapshot(start_time).await;
+                    let mut snapshot = metrics.snapshot(start_time).await;
+                    snapshot.connection_queues = fanout.stats();
                     let event = MetricsEvent {
                         event_type: "metrics",
                         data: snapshot,
@@ -415,8 +1765,8 @@ async fn handle_metrics_websocket(socket: WebSocket, state: AppState) {
 
                     match serde_json::to_string(&event) {
                         Ok(json) => {
-                            if sender.send(Message::Text(json.into())).await.is_err() {
-                                break;
+                            if sender.send(compression.ws_message(json)).await.is_err() {
+                                return;
                             }
                         }
                         Err(e) => {
@@ -427,14 +1777,14 @@ async fn handle_metrics_websocket(socket: WebSocket, state: AppState) {
                                 message: e.to_string(),
                             };
                             if let Ok(json) = serde_json::to_string(&err) {
-                                let _ = sender.send(Message::Text(json.into())).await;
+                                let _ = sender.send(compression.ws_message(json)).await;
                             }
                         }
                     }
                 }
             }
-        }
-        let _ = sender.send(Message::Close(None)).await;
+        };
+        let _ = sender.send(close.message()).await;
     });
 
     let last_pong_for_recv = last_pong;
@@ -461,3 +1811,590 @@ async fn handle_metrics_websocket(socket: WebSocket, state: AppState) {
 
     info!(connection_id = %connection_id, "Metrics WebSocket client disconnected");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_openapi_covers_api_routes() {
+        let spec = ApiDoc::openapi();
+        for path in [
+            "/health",
+            "/metrics",
+            "/metrics/prometheus",
+            "/slo",
+            "/http-analytics",
+            "/heartbeats",
+            "/alerts/active",
+            "/alerts/{id}/ack",
+            "/silences",
+            "/silences/{id}",
+            "/maintenance",
+            "/maintenance/{id}",
+            "/logs/stream",
+            "/logs/ws",
+            "/logs/history",
+            "/logs/since/{seq}",
+            "/logs/trace/{trace_id}",
+            "/logs/facets",
+            "/logs/download",
+            "/logs/sql",
+            "/views",
+            "/views/{name}",
+            "/annotations",
+            "/annotations/{id}",
+            "/deploys",
+            "/graphql",
+            "/mcp",
+            "/mcp/sse",
+            "/mcp/messages",
+            "/chat",
+            "/chat/estimate",
+            "/chat/ws",
+            "/chat/audit",
+            "/chat/transcripts/{id}",
+            "/chat/{id}/feedback",
+            "/tickets",
+            "/auth/ws-ticket",
+            "/auth/login",
+            "/auth/session",
+            "/usage",
+            "/usage/conversations/{id}",
+            "/pricing",
+            "/sinks",
+            "/debug/nats",
+            "/connections/{id}",
+            "/channels",
+            "/admin/reload",
+        ] {
+            assert!(spec.paths.paths.contains_key(path), "missing {}", path);
+        }
+
+        let components = spec.components.unwrap();
+        assert!(components.schemas.contains_key("ApiError"));
+        assert!(components.security_schemes.contains_key("bearer"));
+    }
+
+    #[test]
+    fn test_close_frames_carry_code_and_reason() {
+        let Message::Close(Some(frame)) = WsClose::TryAgainLater.message() else {
+            panic!("expected a close frame");
+        };
+        assert_eq!(frame.code, 1013);
+        assert_eq!(frame.reason, "SLOW_CONSUMER");
+        assert_eq!(WsClose::GoingAway.code(), 1001);
+        assert_eq!(WsClose::PolicyViolation.code(), 1008);
+    }
+
+    #[test]
+    fn test_oversized_frame_cuts_on_char_boundary() {
+        let options = LogWsOptions {
+            reliable: false,
+            format: StreamFormat::Raw,
+            compression: StreamCompression::None,
+            batch: None,
+            since: None,
+            filter: Default::default(),
+            channel: Filter::default(),
+        };
+        // 3-byte characters don't divide the frame size evenly
+        let log = TimestampedLog::new("€".repeat(WS_MAX_FRAME_SIZE), 1);
+        let frame = log_frame(&log, &options);
+        assert!(frame.len() <= WS_MAX_FRAME_SIZE);
+        assert_eq!(frame.len(), WS_MAX_FRAME_SIZE / 3 * 3);
+    }
+
+    #[tokio::test]
+    async fn test_stream_queue_is_capped() {
+        let config = crate::config::Config::for_tests("stream_queue_max = 5000");
+        let state = AppState::for_tests(config);
+        let (status, body) = get_body(&state, "/logs/stream?queue=5001").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body.contains("at most 5000"), "{}", body);
+
+        let query = StreamQuery {
+            queue: Some(5000),
+            ..Default::default()
+        };
+        assert_eq!(query.queue(5000).unwrap(), Some(5000));
+        assert_eq!(StreamQuery::default().queue(5000).unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_tenant_routes_need_the_tenant_token() {
+        let config = crate::config::Config::for_tests(
+            "auth_token = \"admin\"\ntenants = [\"acme app=acme-web token=s3cret\"]",
+        );
+        let state = AppState::for_tests(config);
+        for uri in ["/t/acme/logs/history", "/t/acme/logs/buffer/stats", "/t/acme/usage"] {
+            let (status, _) = get_body(&state, uri).await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED, "{}", uri);
+            let (status, _) = get_as(&state, uri, Some("admin")).await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED, "{}", uri);
+            let (status, _) = get_as(&state, uri, Some("s3cret")).await;
+            assert_eq!(status, StatusCode::OK, "{}", uri);
+        }
+        let (status, _) = get_body(&state, "/logs/history").await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_tenant_state_keeps_fleet_data_out() {
+        let config = crate::config::Config::for_tests(
+            "auth_token = \"admin\"\ntenants = [\"acme app=acme-web token=s3cret\"]",
+        );
+        let state = AppState::for_tests(config);
+        let acme = state.for_tenant(state.tenants.iter().next().unwrap());
+        assert!(!Arc::ptr_eq(&acme.views, &state.views));
+
+        let offered = |state: &AppState| -> Vec<String> {
+            crate::chat::allowed_tools(state)
+                .into_iter()
+                .map(|tool| tool.function.name)
+                .collect()
+        };
+        assert!(offered(&state).contains(&"get_metrics".to_string()));
+        assert!(!offered(&acme).contains(&"get_metrics".to_string()));
+        assert!(crate::chat::execute_tool("get_metrics", "{}", &acme).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_tenant_chats_only_see_their_deploys() {
+        let config = crate::config::Config::for_tests(
+            "auth_token = \"admin\"\ntenants = [\"acme app=acme-web token=s3cret\"]",
+        );
+        let state = AppState::for_tests(config);
+        for app in ["acme-web", "other-web"] {
+            let request = serde_json::from_value(serde_json::json!({ "app": app })).unwrap();
+            state.deploys.record(request, "web", &state.annotations).unwrap();
+        }
+        let acme = state.for_tenant(state.tenants.iter().next().unwrap());
+
+        let seen = crate::chat::execute_tool("get_deploys", "{}", &acme).await.unwrap();
+        assert!(seen.contains("acme-web"));
+        assert!(!seen.contains("other-web"));
+        let all = crate::chat::execute_tool("get_deploys", "{}", &state).await.unwrap();
+        assert!(all.contains("other-web"));
+
+        let (status, body) = get_as(&state, "/deploys?app=other-web", Some("admin")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains("other-web") && !body.contains("acme-web"));
+    }
+
+    #[tokio::test]
+    async fn test_token_callers_get_their_own_tool_scope() {
+        use tower::ServiceExt;
+
+        let config = crate::config::Config::for_tests(
+            "auth_token = \"admin\"\nauth_users = [\"ana:pw\"]\n\
+             tenants = [\"acme app=acme-web token=s3cret\"]\n\
+             chat_tool_scopes = [\"acme=get_logs\", \"ana=get_logs|get_trace\", \
+             \"*=get_logs|get_trace|get_metrics\"]",
+        );
+        let state = AppState::for_tests(config);
+        let offered = |state: &AppState| -> Vec<String> {
+            crate::chat::allowed_tools(state)
+                .into_iter()
+                .map(|tool| tool.function.name)
+                .collect()
+        };
+
+        // The operator's bearer token falls to `*`
+        let mut bearer = HeaderMap::new();
+        bearer.insert(header::AUTHORIZATION, "Bearer admin".parse().unwrap());
+        assert_eq!(
+            offered(&state.for_caller(&bearer)),
+            vec!["get_logs", "get_metrics", "get_trace"]
+        );
+
+        // The tenant's token only opens its routes, and gets its own tools
+        let acme = state.for_tenant(state.tenants.iter().next().unwrap());
+        assert_eq!(offered(&acme), vec!["get_logs"]);
+        assert!(crate::chat::execute_tool("get_trace", "{}", &acme).await.is_err());
+
+        // A dashboard session acts as its user
+        let login = axum::http::Request::post("/auth/login")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(axum::body::Body::from(r#"{"username":"ana","password":"pw"}"#))
+            .unwrap();
+        let response = create_router(state.clone()).oneshot(login).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let cookie = response.headers()[header::SET_COOKIE].to_str().unwrap();
+        let mut session = HeaderMap::new();
+        session.insert(header::COOKIE, cookie.split(';').next().unwrap().parse().unwrap());
+        assert_eq!(offered(&state.for_caller(&session)), vec!["get_logs", "get_trace"]);
+        // ...unless the request brings a bearer token of its own
+        session.insert(header::AUTHORIZATION, "Bearer admin".parse().unwrap());
+        assert_eq!(offered(&state.for_caller(&session)).len(), 3);
+    }
+
+    /// A state whose live streams are fed by the returned sender
+    fn live_state(config: &str) -> (AppState, tokio::sync::broadcast::Sender<LogMessage>) {
+        let state = AppState::for_tests(crate::config::Config::for_tests(config));
+        let (tx, rx) = tokio::sync::broadcast::channel(64);
+        state.fanout.start(rx);
+        (state, tx)
+    }
+
+    /// Buffer a line and send it to live streams, as ingest does
+    async fn publish(
+        state: &AppState,
+        live: &tokio::sync::broadcast::Sender<LogMessage>,
+    ) -> u64 {
+        let raw = crate::source::fly_envelope("x", Some("info"), None, None);
+        let log = state.log_buffer.push(raw).await;
+        let seq = log.seq;
+        live.send(Arc::new(log)).unwrap();
+        seq
+    }
+
+    /// Open `/logs/stream` without reading any of it yet
+    async fn open_sse(state: &AppState, last_event_id: Option<u64>) -> SseReader {
+        use tower::ServiceExt;
+
+        let mut request = axum::http::Request::builder().uri("/logs/stream");
+        if let Some(id) = last_event_id {
+            request = request.header("last-event-id", id.to_string());
+        }
+        let mut request = request.body(axum::body::Body::empty()).unwrap();
+        let peer = SocketAddr::from(([127, 0, 0, 1], 4000));
+        request.extensions_mut().insert(ConnectInfo(peer));
+        let response = create_router(state.clone()).oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        SseReader {
+            body: response.into_body(),
+            pending: String::new(),
+        }
+    }
+
+    /// Reads events off an SSE body as they arrive
+    struct SseReader {
+        body: axum::body::Body,
+        pending: String,
+    }
+
+    impl SseReader {
+        /// The next `n` events as (event, id, data), skipping keep-alives
+        async fn next(&mut self, n: usize) -> Vec<(String, Option<u64>, String)> {
+            use http_body_util::BodyExt;
+
+            let mut events = Vec::new();
+            while events.len() < n {
+                if let Some(end) = self.pending.find("\n\n") {
+                    let block: String = self.pending.drain(..end + 2).collect();
+                    let (mut event, mut id, mut data) = ("message".to_string(), None, None);
+                    for line in block.lines() {
+                        match line.split_once(':') {
+                            Some(("event", value)) => event = value.trim().to_string(),
+                            Some(("id", value)) => id = value.trim().parse().ok(),
+                            Some(("data", value)) => data = Some(value.trim().to_string()),
+                            _ => {}
+                        }
+                    }
+                    if let Some(data) = data {
+                        events.push((event, id, data));
+                    }
+                    continue;
+                }
+                let frame = tokio::time::timeout(Duration::from_secs(5), self.body.frame())
+                    .await
+                    .expect("no SSE event within 5s")
+                    .unwrap()
+                    .unwrap();
+                if let Ok(bytes) = frame.into_data() {
+                    self.pending.push_str(&String::from_utf8_lossy(&bytes));
+                }
+            }
+            events
+        }
+    }
+
+    fn ids(events: &[(String, Option<u64>, String)]) -> Vec<u64> {
+        events.iter().filter_map(|(_, id, _)| *id).collect()
+    }
+
+    #[tokio::test]
+    async fn test_sse_resumes_after_last_event_id_without_duplicates() {
+        let (state, live) = live_state("");
+        for _ in 1..=4 {
+            publish(&state, &live).await;
+        }
+        let mut stream = open_sse(&state, Some(2)).await;
+        // Buffered and queued live before the stream reads its backfill
+        assert_eq!(publish(&state, &live).await, 5);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let backfill = stream.next(3).await;
+        assert_eq!(ids(&backfill), vec![3, 4, 5]);
+        assert!(backfill.iter().all(|(event, _, _)| event == "message"));
+        // The live copy of 5 is skipped; the next line comes straight through
+        assert_eq!(publish(&state, &live).await, 6);
+        assert_eq!(ids(&stream.next(1).await), vec![6]);
+    }
+
+    #[tokio::test]
+    async fn test_sse_reports_a_gap_when_the_resume_point_was_evicted() {
+        let (state, live) = live_state("log_buffer_max_entries = 3");
+        for _ in 1..=6 {
+            publish(&state, &live).await;
+        }
+        let mut stream = open_sse(&state, Some(1)).await;
+
+        let events = stream.next(4).await;
+        let (event, id, data) = &events[0];
+        assert_eq!((event.as_str(), *id), ("gap", None));
+        let gap: serde_json::Value = serde_json::from_str(data).unwrap();
+        assert_eq!(
+            (gap["missed_from"].as_u64(), gap["missed_to"].as_u64()),
+            (Some(2), Some(3))
+        );
+        assert_eq!(ids(&events[1..]), vec![4, 5, 6]);
+    }
+
+    type WsClient = tokio_tungstenite::WebSocketStream<
+        tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
+    >;
+
+    /// Connect to `/logs/ws?<query>` on a server for `state`, once its
+    /// subscription is in place
+    async fn open_ws(state: &AppState, query: &str) -> WsClient {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app =
+            create_router(state.clone()).into_make_service_with_connect_info::<SocketAddr>();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let url = format!("ws://{}/logs/ws?{}", addr, query);
+        let (ws, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+        while state.fanout.stats().is_empty() {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        ws
+    }
+
+    /// The next `n` JSON text frames
+    async fn ws_frames(ws: &mut WsClient, n: usize) -> Vec<serde_json::Value> {
+        use tokio_tungstenite::tungstenite::Message as WsMessage;
+
+        let mut frames = Vec::new();
+        while frames.len() < n {
+            let message = tokio::time::timeout(Duration::from_secs(5), ws.next())
+                .await
+                .expect("no WebSocket frame within 5s")
+                .unwrap()
+                .unwrap();
+            if let WsMessage::Text(text) = message {
+                frames.push(serde_json::from_str(&text).unwrap());
+            }
+        }
+        frames
+    }
+
+    fn frame_seqs(frames: &[serde_json::Value]) -> Vec<u64> {
+        frames.iter().filter_map(|f| f["seq"].as_u64()).collect()
+    }
+
+    /// Receive 1 and 2 and ack 2, then lag through 3 to 8 with a queue of
+    /// two: the client gets what follows its ack, once each
+    async fn lag_after_ack(
+        state: &AppState,
+        live: &tokio::sync::broadcast::Sender<LogMessage>,
+    ) -> Vec<serde_json::Value> {
+        use tokio_tungstenite::tungstenite::Message as WsMessage;
+
+        let mut ws = open_ws(state, "mode=reliable&queue=2&overflow=drop_oldest").await;
+        publish(state, live).await;
+        publish(state, live).await;
+        assert_eq!(frame_seqs(&ws_frames(&mut ws, 2).await), vec![1, 2]);
+        let ack = serde_json::json!({ "type": "ack", "seq": 2 }).to_string();
+        ws.send(WsMessage::Text(ack)).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        // The consumer stalls on 3 while the rest overflow its queue
+        state.fanout.set_artificial_lag(Duration::from_millis(200));
+        for _ in 3..=8 {
+            publish(state, live).await;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        state.fanout.set_artificial_lag(Duration::ZERO);
+
+        let mut frames = Vec::new();
+        while frame_seqs(&frames).last() != Some(&8) {
+            frames.extend(ws_frames(&mut ws, 1).await);
+        }
+        // Nothing already sent comes again
+        publish(state, live).await;
+        frames.extend(ws_frames(&mut ws, 1).await);
+        frames
+    }
+
+    #[tokio::test]
+    async fn test_reliable_ws_retransmits_from_the_last_ack_after_lag() {
+        let (state, live) = live_state("");
+        let frames = lag_after_ack(&state, &live).await;
+        assert!(frames.iter().all(|f| f["type"] == "log"));
+        assert_eq!(frame_seqs(&frames), vec![3, 4, 5, 6, 7, 8, 9]);
+    }
+
+    #[tokio::test]
+    async fn test_reliable_ws_reports_a_gap_when_unacked_lines_were_evicted() {
+        let (state, live) = live_state("log_buffer_max_entries = 4");
+        let frames = lag_after_ack(&state, &live).await;
+        assert_eq!(frames[0]["type"], "gap");
+        let gap = &frames[0];
+        assert_eq!(
+            (gap["missed_from"].as_u64(), gap["missed_to"].as_u64()),
+            (Some(3), Some(4))
+        );
+        assert_eq!(frame_seqs(&frames), vec![5, 6, 7, 8, 9]);
+    }
+
+    #[tokio::test]
+    async fn test_reliable_ws_resumes_after_since() {
+        let (state, live) = live_state("log_buffer_max_entries = 4");
+        for _ in 1..=8 {
+            publish(&state, &live).await;
+        }
+
+        let mut ws = open_ws(&state, "mode=reliable&since=6").await;
+        assert_eq!(frame_seqs(&ws_frames(&mut ws, 2).await), vec![7, 8]);
+
+        let mut ws = open_ws(&state, "mode=reliable&since=2").await;
+        let frames = ws_frames(&mut ws, 5).await;
+        assert_eq!(frames[0]["type"], "gap");
+        let gap = &frames[0];
+        assert_eq!(
+            (gap["missed_from"].as_u64(), gap["missed_to"].as_u64()),
+            (Some(3), Some(4))
+        );
+        assert_eq!(frame_seqs(&frames[1..]), vec![5, 6, 7, 8]);
+    }
+
+    #[tokio::test]
+    async fn test_conversation_usage_is_found_by_id() {
+        let dir = std::env::temp_dir().join(format!("flywatch-http-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("store.db");
+        let state = AppState {
+            usage_tracker: Arc::new(UsageTracker::new(path.to_str(), 0)),
+            ..AppState::for_tests(crate::config::Config::for_tests(""))
+        };
+        let cost = crate::pricing::CostBreakdown {
+            input_tokens: 100,
+            output_tokens: 20,
+            total_tokens: 120,
+            input_cost_usd: 0.5,
+            output_cost_usd: 0.25,
+            total_cost_usd: 0.75,
+            model_input_price_per_million: 5.0,
+            model_output_price_per_million: 12.5,
+        };
+        state
+            .usage_tracker
+            .record(crate::usage::UsageEvent {
+                model: "m",
+                cost: &cost,
+                processing_time_ms: 300,
+                tools_called: &[],
+                request_id: None,
+                upstream_ids: &[],
+                conversation_id: Some("c1"),
+            })
+            .await;
+
+        let (status, body) = get_body(&state, "/usage/conversations/c1").await;
+        assert_eq!(status, StatusCode::OK);
+        let usage: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(usage["conversation_id"], "c1");
+        assert_eq!(usage["total_cost_usd"], 0.75);
+        assert_eq!(usage["models"], serde_json::json!(["m"]));
+
+        let (status, body) = get_body(&state, "/usage/conversations/nope").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert!(body.contains("No usage recorded for conversation 'nope'"));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_token_matches_only_the_whole_token() {
+        assert!(token_matches("s3cret", "s3cret"));
+        assert!(!token_matches("s3cre", "s3cret"));
+        assert!(!token_matches("s3cret!", "s3cret"));
+        assert!(!token_matches("", "s3cret"));
+    }
+
+    async fn get_body(state: &AppState, uri: &str) -> (StatusCode, String) {
+        get_as(state, uri, None).await
+    }
+
+    async fn get_as(state: &AppState, uri: &str, token: Option<&str>) -> (StatusCode, String) {
+        use tower::ServiceExt;
+
+        let mut request = axum::http::Request::builder().uri(uri);
+        if let Some(token) = token {
+            request = request.header(header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+        let mut request = request.body(axum::body::Body::empty()).unwrap();
+        let peer = SocketAddr::from(([127, 0, 0, 1], 4000));
+        request.extensions_mut().insert(ConnectInfo(peer));
+        let response = create_router(state.clone()).oneshot(request).await.unwrap();
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, String::from_utf8_lossy(&bytes).into_owned())
+    }
+
+    #[tokio::test]
+    async fn test_replay_applies_views_and_stops_on_shutdown() {
+        use crate::source::fly_envelope;
+
+        let state = AppState::for_tests(crate::config::Config::for_tests(""));
+        let from = (Utc::now() - chrono::Duration::minutes(1))
+            .to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+        for (message, level) in [("boom", "error"), ("fine", "info"), ("bang", "error")] {
+            let raw = fly_envelope(message, Some(level), Some("web-1"), None);
+            state.log_buffer.push(raw).await;
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        state
+            .views
+            .save(views::ViewRequest {
+                name: "errors".to_string(),
+                levels: vec!["error".to_string()],
+                instances: Vec::new(),
+                pattern: None,
+                minutes: None,
+                description: None,
+            })
+            .unwrap();
+
+        let (status, body) = get_body(
+            &state,
+            &format!("/logs/replay?from={}&speed=max&view=errors", from),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains("boom") && body.contains("bang"));
+        assert!(!body.contains("fine"));
+        assert!(body.contains(r#""count":2"#));
+        let (status, _) = get_body(&state, &format!("/logs/replay?from={}&view=nope", from)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        // At 0.001x the lines are 20s apart; shutdown ends the replay early
+        let shutdown = state.shutdown.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            shutdown.send_replace(true);
+        });
+        let (_, body) = tokio::time::timeout(
+            Duration::from_secs(5),
+            get_body(&state, &format!("/logs/replay?from={}&speed=0.001", from)),
+        )
+        .await
+        .expect("replay kept running after shutdown");
+        assert!(body.contains("boom"));
+        assert!(!body.contains("bang") && !body.contains(r#""type":"end""#));
+    }
+}
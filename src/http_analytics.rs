@@ -0,0 +1,572 @@
+//! Traffic overview from access logs. Lines shaped like HTTP requests are
+//! parsed as they arrive:
+//!
+//! ```text
+//! {"method":"GET","path":"/cart/42","status":503,"duration_ms":12.5}
+//! 10.0.0.1 - - [05/Jan/2026:10:00:00 +0000] "GET /cart/42?x=1 HTTP/1.1" 503 120
+//! GET /cart/42 503 in 12ms
+//! ```
+//!
+//! Paths are folded into routes (ids become `:id`, query strings are
+//! dropped) and per-route status counts and latency percentiles are kept
+//! over a rolling window (HTTP_ANALYTICS_WINDOW_MINUTES). Served at
+//! `/http-analytics` and through the chat agent's `get_http_analytics` tool.
+
+use axum::{
+    extract::{Query, State},
+    http::HeaderMap,
+    Json,
+};
+use chrono::{DateTime, Duration, Utc};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+use tracing::warn;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::error::{ApiError, ErrorBody};
+use crate::http::{check_auth, AppState};
+use crate::log_buffer::{find_id, TimestampedLog};
+use crate::metrics::Metrics;
+use crate::nats::LogMessage;
+
+const CONSUMER: &str = "http_analytics";
+
+/// Requests kept at most; the oldest go first under heavy traffic
+const MAX_SAMPLES: usize = 200_000;
+
+/// Routes listed when the caller doesn't ask for a number
+const DEFAULT_ROUTES: usize = 20;
+
+const METHOD_KEYS: &[&str] = &[
+    "method",
+    "http.method",
+    "http.request.method",
+    "request_method",
+    "req.method",
+];
+const PATH_KEYS: &[&str] = &[
+    "path",
+    "route",
+    "http.route",
+    "http.target",
+    "url.path",
+    "request_path",
+    "request_uri",
+    "uri",
+    "url",
+    "req.url",
+];
+const STATUS_KEYS: &[&str] = &[
+    "status",
+    "status_code",
+    "http.status_code",
+    "http.response.status_code",
+    "response_status",
+    "res.statusCode",
+];
+/// Duration fields with their unit when the value is a bare number
+const DURATION_KEYS: &[(&str, f64)] = &[
+    ("duration_ms", 1.0),
+    ("latency_ms", 1.0),
+    ("response_time_ms", 1.0),
+    ("elapsed_ms", 1.0),
+    ("responseTime", 1.0),
+    ("duration", 1.0),
+    ("latency", 1.0),
+    ("response_time", 1.0),
+    // nginx's $request_time is in seconds
+    ("request_time", 1000.0),
+];
+
+/// One request read from an access log
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccessLog {
+    pub method: String,
+    pub route: String,
+    pub status: u16,
+    pub duration_ms: Option<f64>,
+}
+
+/// Reads requests out of JSON fields or common text shapes
+struct AccessLogParser {
+    /// `GET /path HTTP/1.1" 200` and `GET /path 200 in 12ms`
+    request: Regex,
+    /// Numbers, UUIDs and long hex strings
+    id_segment: Regex,
+}
+
+impl AccessLogParser {
+    fn new() -> Self {
+        Self {
+            request: Regex::new(
+                r#"\b(GET|HEAD|POST|PUT|PATCH|DELETE|OPTIONS)\s+(/\S*?|https?://\S+?)(?:\s+HTTP/[\d.]+)?"?\s+(?:-\s+|->\s+|status=)?([1-5]\d\d)\b(?:\D{0,40}?(\d+(?:\.\d+)?)\s?(ms|s|µs|us)\b)?"#,
+            )
+            .expect("built-in access log pattern"),
+            id_segment: Regex::new(
+                r"^(?:\d+|[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12}|[0-9a-fA-F]{16,})$",
+            )
+            .expect("built-in id pattern"),
+        }
+    }
+
+    /// A JSON message, then the line's own fields, then the message (or
+    /// plain line) text
+    fn parse(&self, log: &TimestampedLog) -> Option<AccessLog> {
+        let message = log.message.as_deref().unwrap_or(&log.raw);
+        if message.starts_with('{') {
+            if let Some(request) = serde_json::from_str::<Value>(message)
+                .ok()
+                .and_then(|json| self.parse_json(&json))
+            {
+                return Some(request);
+            }
+        }
+        if let Some(request) = serde_json::from_str::<Value>(&log.raw)
+            .ok()
+            .and_then(|json| self.parse_json(&json))
+        {
+            return Some(request);
+        }
+        self.parse_text(message)
+    }
+
+    fn parse_json(&self, json: &Value) -> Option<AccessLog> {
+        let method = find_id(json, METHOD_KEYS)?.to_ascii_uppercase();
+        let path = find_id(json, PATH_KEYS)?;
+        let status = find_id(json, STATUS_KEYS)?.parse::<u16>().ok()?;
+        if !(100..600).contains(&status) {
+            return None;
+        }
+        let duration_ms = DURATION_KEYS.iter().find_map(|(key, scale)| {
+            match json
+                .get(key)
+                .or_else(|| json.pointer(&format!("/{}", key.replace('.', "/"))))?
+            {
+                Value::Number(n) => n.as_f64().map(|v| v * scale),
+                Value::String(s) => parse_duration(s, *scale),
+                _ => None,
+            }
+        });
+        Some(AccessLog {
+            method,
+            route: self.route(&path),
+            status,
+            duration_ms,
+        })
+    }
+
+    fn parse_text(&self, text: &str) -> Option<AccessLog> {
+        let caps = self.request.captures(text)?;
+        let duration_ms = caps.get(4).zip(caps.get(5)).and_then(|(value, unit)| {
+            let value: f64 = value.as_str().parse().ok()?;
+            Some(value * unit_scale(unit.as_str())?)
+        });
+        Some(AccessLog {
+            method: caps[1].to_string(),
+            route: self.route(&caps[2]),
+            status: caps[3].parse().ok()?,
+            duration_ms,
+        })
+    }
+
+    /// `/cart/42?x=1` -> `/cart/:id`
+    fn route(&self, path: &str) -> String {
+        let path = path
+            .split_once("://")
+            .map_or(path, |(_, rest)| rest.find('/').map_or("/", |i| &rest[i..]));
+        let path = path.split(['?', '#']).next().unwrap_or_default();
+        let segments: Vec<&str> = path
+            .split('/')
+            .map(|segment| {
+                if !segment.is_empty() && self.is_id(segment) {
+                    ":id"
+                } else {
+                    segment
+                }
+            })
+            .collect();
+        match segments.join("/") {
+            route if route.is_empty() => "/".to_string(),
+            route => route,
+        }
+    }
+
+    /// Ids, plus long tokens mixing letters and digits (slugs, base62 keys)
+    fn is_id(&self, segment: &str) -> bool {
+        self.id_segment.is_match(segment)
+            || (segment.len() >= 12
+                && segment.chars().any(|c| c.is_ascii_digit())
+                && segment
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'))
+    }
+}
+
+fn unit_scale(unit: &str) -> Option<f64> {
+    match unit {
+        "ms" => Some(1.0),
+        "s" => Some(1000.0),
+        "µs" | "us" => Some(0.001),
+        _ => None,
+    }
+}
+
+/// "12", "12.5ms", "0.2s" or "150µs"; bare numbers are in `scale` ms
+fn parse_duration(value: &str, scale: f64) -> Option<f64> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: f64 = number.parse().ok()?;
+    match unit.trim() {
+        "" => Some(number * scale),
+        unit => Some(number * unit_scale(unit)?),
+    }
+}
+
+/// Status counts and durations of one route
+type RouteSamples = (BTreeMap<u16, u64>, Vec<f64>);
+
+struct Sample {
+    at: DateTime<Utc>,
+    request: AccessLog,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct LatencyPercentiles {
+    pub p50_ms: f64,
+    pub p90_ms: f64,
+    pub p99_ms: f64,
+    pub max_ms: f64,
+}
+
+impl LatencyPercentiles {
+    /// Nearest-rank percentiles; None without any timed request
+    fn of(mut durations: Vec<f64>) -> Option<Self> {
+        if durations.is_empty() {
+            return None;
+        }
+        durations.sort_by(f64::total_cmp);
+        let rank = |p: f64| {
+            let index = ((p * durations.len() as f64).ceil() as usize).max(1) - 1;
+            durations[index.min(durations.len() - 1)]
+        };
+        Some(Self {
+            p50_ms: rank(0.5),
+            p90_ms: rank(0.9),
+            p99_ms: rank(0.99),
+            max_ms: durations[durations.len() - 1],
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct RouteStats {
+    pub method: String,
+    /// Path with ids folded into `:id`
+    pub route: String,
+    pub requests: u64,
+    /// Requests by status code
+    pub statuses: BTreeMap<u16, u64>,
+    /// Share of 5xx responses, 0-1
+    pub error_rate: f64,
+    pub latency: Option<LatencyPercentiles>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct HttpAnalyticsSnapshot {
+    pub window_minutes: i64,
+    pub requests: u64,
+    /// Share of 5xx responses, 0-1
+    pub error_rate: f64,
+    /// Requests by status class ("2xx", "5xx", ...)
+    pub status_classes: BTreeMap<String, u64>,
+    pub latency: Option<LatencyPercentiles>,
+    /// Busiest first
+    pub routes: Vec<RouteStats>,
+}
+
+impl HttpAnalyticsSnapshot {
+    /// Compact form for the chat agent
+    pub fn summarize(&self) -> String {
+        if self.requests == 0 {
+            return format!(
+                "No access-log lines in the last {} minutes.",
+                self.window_minutes
+            );
+        }
+        let latency = |latency: &Option<LatencyPercentiles>| {
+            latency.as_ref().map_or(String::new(), |l| {
+                format!(
+                    " p50 {:.0}ms p90 {:.0}ms p99 {:.0}ms",
+                    l.p50_ms, l.p90_ms, l.p99_ms
+                )
+            })
+        };
+        let classes: Vec<String> = self
+            .status_classes
+            .iter()
+            .map(|(class, count)| format!("{} {}", class, count))
+            .collect();
+        let mut out = format!(
+            "Last {}m: {} requests, {:.1}% 5xx ({}){}\n",
+            self.window_minutes,
+            self.requests,
+            self.error_rate * 100.0,
+            classes.join(", "),
+            latency(&self.latency)
+        );
+        for route in &self.routes {
+            let statuses: Vec<String> = route
+                .statuses
+                .iter()
+                .map(|(status, count)| format!("{}x{}", status, count))
+                .collect();
+            out.push_str(&format!(
+                "{} {}: {} req [{}]{}\n",
+                route.method,
+                route.route,
+                route.requests,
+                statuses.join(" "),
+                latency(&route.latency)
+            ));
+        }
+        out
+    }
+}
+
+/// Rolling per-route request stats fed from the log broadcast
+pub struct HttpAnalytics {
+    window_minutes: i64,
+    parser: AccessLogParser,
+    samples: Mutex<VecDeque<Sample>>,
+}
+
+impl HttpAnalytics {
+    pub fn new(window_minutes: i64) -> Arc<Self> {
+        Arc::new(Self {
+            window_minutes,
+            parser: AccessLogParser::new(),
+            samples: Mutex::new(VecDeque::new()),
+        })
+    }
+
+    pub fn start(self: &Arc<Self>, mut rx: broadcast::Receiver<LogMessage>, metrics: Arc<Metrics>) {
+        let analytics = self.clone();
+        tokio::spawn(async move {
+            loop {
+                match rx.recv().await {
+                    Ok(log) => {
+                        metrics.record_consumer(CONSUMER, 1, 0);
+                        analytics.observe(&log);
+                    }
+                    Err(broadcast::error::RecvError::Lagged(n)) => {
+                        warn!(skipped = n, "HTTP analytics lagged");
+                        metrics.record_consumer(CONSUMER, n, n);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+
+    pub fn observe(&self, log: &TimestampedLog) {
+        let Some(request) = self.parser.parse(log) else {
+            return;
+        };
+        let cutoff = Utc::now() - Duration::minutes(self.window_minutes);
+        let mut samples = self.samples.lock().unwrap();
+        while samples
+            .front()
+            .is_some_and(|s| s.at < cutoff || samples.len() >= MAX_SAMPLES)
+        {
+            samples.pop_front();
+        }
+        samples.push_back(Sample {
+            at: log.timestamp,
+            request,
+        });
+    }
+
+    /// Requests in the last `minutes` (at most the window), busiest
+    /// `limit` routes
+    pub fn snapshot(&self, minutes: Option<i64>, limit: Option<usize>) -> HttpAnalyticsSnapshot {
+        let minutes = minutes
+            .unwrap_or(self.window_minutes)
+            .clamp(1, self.window_minutes);
+        let cutoff = Utc::now() - Duration::minutes(minutes);
+
+        let mut status_classes: BTreeMap<String, u64> = BTreeMap::new();
+        let mut durations = Vec::new();
+        let mut routes: HashMap<(&str, &str), RouteSamples> = HashMap::new();
+        let samples = self.samples.lock().unwrap();
+        for sample in samples.iter().filter(|s| s.at >= cutoff) {
+            let request = &sample.request;
+            *status_classes
+                .entry(format!("{}xx", request.status / 100))
+                .or_default() += 1;
+            let route = routes.entry((&request.method, &request.route)).or_default();
+            *route.0.entry(request.status).or_default() += 1;
+            if let Some(duration) = request.duration_ms {
+                durations.push(duration);
+                route.1.push(duration);
+            }
+        }
+
+        let mut routes: Vec<RouteStats> = routes
+            .into_iter()
+            .map(|((method, route), (statuses, durations))| {
+                let requests = statuses.values().sum();
+                RouteStats {
+                    method: method.to_string(),
+                    route: route.to_string(),
+                    requests,
+                    error_rate: server_errors(&statuses) as f64 / requests as f64,
+                    statuses,
+                    latency: LatencyPercentiles::of(durations),
+                }
+            })
+            .collect();
+        routes.sort_by(|a, b| {
+            b.requests
+                .cmp(&a.requests)
+                .then_with(|| a.route.cmp(&b.route))
+                .then_with(|| a.method.cmp(&b.method))
+        });
+        routes.truncate(limit.unwrap_or(DEFAULT_ROUTES));
+
+        let requests: u64 = status_classes.values().sum();
+        let errors = status_classes.get("5xx").copied().unwrap_or_default();
+        HttpAnalyticsSnapshot {
+            window_minutes: minutes,
+            requests,
+            error_rate: if requests == 0 {
+                0.0
+            } else {
+                errors as f64 / requests as f64
+            },
+            status_classes,
+            latency: LatencyPercentiles::of(durations),
+            routes,
+        }
+    }
+}
+
+fn server_errors(statuses: &BTreeMap<u16, u64>) -> u64 {
+    statuses.range(500..600).map(|(_, count)| count).sum()
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct HttpAnalyticsQuery {
+    /// Lookback in minutes, at most HTTP_ANALYTICS_WINDOW_MINUTES
+    pub minutes: Option<i64>,
+    /// Routes to list, busiest first (default 20)
+    pub limit: Option<usize>,
+}
+
+/// GET /http-analytics - per-route status counts and latency percentiles
+#[utoipa::path(
+    get, path = "/http-analytics", tag = "metrics",
+    params(HttpAnalyticsQuery),
+    responses(
+        (status = 200, description = "Request counts, status codes and latency percentiles per route", body = HttpAnalyticsSnapshot),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+pub async fn http_analytics_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<HttpAnalyticsQuery>,
+) -> Result<Json<HttpAnalyticsSnapshot>, ApiError> {
+    check_auth(&state, &headers)?;
+    Ok(Json(
+        state.http_analytics.snapshot(query.minutes, query.limit),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::fly_envelope;
+
+    fn line(message: &str) -> TimestampedLog {
+        TimestampedLog::new(fly_envelope(message, None, None, None), 0)
+    }
+
+    #[test]
+    fn test_parse_access_log_shapes() {
+        let parser = AccessLogParser::new();
+        let parse = |message: &str| parser.parse(&line(message));
+
+        assert_eq!(
+            parse(r#"{"method":"get","path":"/cart/42?x=1","status":503,"duration_ms":12.5}"#),
+            Some(AccessLog {
+                method: "GET".to_string(),
+                route: "/cart/:id".to_string(),
+                status: 503,
+                duration_ms: Some(12.5),
+            })
+        );
+        let nginx = parse(
+            r#"{"request_method":"POST","request_uri":"/orders/3f2a9c1e-0b7d-4c1a-9e55-1d2b3c4d5e6f/pay","status":"201","request_time":"0.250"}"#,
+        )
+        .unwrap();
+        assert_eq!(nginx.route, "/orders/:id/pay");
+        assert_eq!(nginx.duration_ms, Some(250.0));
+
+        let combined = parse(
+            r#"10.0.0.1 - - [05/Jan/2026:10:00:00 +0000] "GET /users/17/avatar.png HTTP/1.1" 404 120 "-" "curl/8""#,
+        )
+        .unwrap();
+        assert_eq!(
+            (combined.route.as_str(), combined.status),
+            ("/users/:id/avatar.png", 404)
+        );
+        assert_eq!(combined.duration_ms, None);
+
+        let text = parse("GET /api/health 200 in 1.5s").unwrap();
+        assert_eq!((text.status, text.duration_ms), (200, Some(1500.0)));
+        assert_eq!(
+            parse("GET https://shop.example.com/ -> 502").unwrap().route,
+            "/"
+        );
+
+        assert_eq!(parse("user 42 logged in"), None);
+        assert_eq!(parse(r#"{"method":"GET","path":"/x","status":999}"#), None);
+    }
+
+    #[test]
+    fn test_snapshot_per_route() {
+        let analytics = HttpAnalytics::new(15);
+        for (i, status) in [200, 200, 200, 503].into_iter().enumerate() {
+            analytics.observe(&line(&format!(
+                "GET /cart/{} {} in {}ms",
+                i,
+                status,
+                (i + 1) * 10
+            )));
+        }
+        analytics.observe(&line("POST /login 200 in 5ms"));
+
+        let snapshot = analytics.snapshot(None, None);
+        assert_eq!(snapshot.requests, 5);
+        assert_eq!(snapshot.status_classes["5xx"], 1);
+        assert_eq!(snapshot.error_rate, 0.2);
+        let cart = &snapshot.routes[0];
+        assert_eq!((cart.route.as_str(), cart.requests), ("/cart/:id", 4));
+        assert_eq!(cart.statuses[&503], 1);
+        let latency = cart.latency.as_ref().unwrap();
+        assert_eq!((latency.p50_ms, latency.p99_ms), (20.0, 40.0));
+        assert!(snapshot
+            .summarize()
+            .contains("GET /cart/:id: 4 req [200x3 503x1]"));
+
+        assert_eq!(analytics.snapshot(None, Some(1)).routes.len(), 1);
+    }
+}
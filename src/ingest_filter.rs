@@ -0,0 +1,380 @@
+//! Ingest-side rules applied to NATS messages before they are buffered or
+//! broadcast, so high-volume chatter doesn't crowd out signal. A rule is an
+//! action followed by conditions that must all hold:
+//!
+//! ```text
+//! drop app=checkout level=debug|trace
+//! sample=10% subject=logs.noisy-app.>
+//! drop level=info hours=22-06
+//! drop match=GET /healthz
+//! ```
+//!
+//! The first matching rule decides; lines no rule matches are kept.
+
+use chrono::{Timelike, Utc};
+use serde::Deserialize;
+use std::cell::OnceCell;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+
+use crate::filter::{self, Filter, Line};
+
+/// What happens to a line a rule matches
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RuleAction {
+    Drop,
+    /// Keep this percentage of matching lines
+    Sample(f64),
+}
+
+/// One ingest rule, as written in `INGEST_RULES`
+#[derive(Debug, Clone)]
+pub struct IngestRule {
+    text: String,
+    action: RuleAction,
+    /// NATS subject pattern (`*` matches one token, `>` the rest)
+    subject: Option<String>,
+    /// UTC hours [start, end), wrapping past midnight
+    hours: Option<(u32, u32)>,
+    /// The `app=`, `level=` and `match=` conditions
+    filter: Filter,
+}
+
+impl PartialEq for IngestRule {
+    fn eq(&self, other: &Self) -> bool {
+        self.text == other.text
+    }
+}
+
+impl fmt::Display for IngestRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+impl FromStr for IngestRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let text = s.trim();
+        let (head, mut rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+        let action = match head {
+            "drop" => RuleAction::Drop,
+            _ => match head.strip_prefix("sample=") {
+                Some(percent) => RuleAction::Sample(parse_percent(percent)?),
+                None => return Err(format!("expected 'drop' or 'sample=N%', found '{}'", head)),
+            },
+        };
+
+        let mut rule = IngestRule {
+            text: text.to_string(),
+            action,
+            subject: None,
+            hours: None,
+            filter: Filter::default(),
+        };
+
+        rest = rest.trim_start();
+        while !rest.is_empty() {
+            let (token, tail) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+            let (key, value) = token
+                .split_once('=')
+                .ok_or_else(|| format!("expected key=value, found '{}'", token))?;
+            match key {
+                // The pattern runs to the end of the rule so it may contain spaces
+                "match" => {
+                    let pattern = rest["match=".len()..].trim_end();
+                    rule.filter.pattern =
+                        Some(filter::pattern(pattern).map_err(|e| format!("match: {}", e))?);
+                    break;
+                }
+                "app" => rule.filter.apps = alternatives(value, str::to_lowercase),
+                "level" => rule.filter.levels = alternatives(value, normalize_level),
+                "subject" => rule.subject = Some(value.to_string()),
+                "hours" => rule.hours = Some(parse_hours(value)?),
+                other => return Err(format!("unknown condition '{}'", other)),
+            }
+            rest = tail.trim_start();
+        }
+
+        if rule.filter.apps.is_empty()
+            && rule.filter.levels.is_empty()
+            && rule.subject.is_none()
+            && rule.hours.is_none()
+            && rule.filter.pattern.is_none()
+        {
+            return Err("a rule needs at least one condition".to_string());
+        }
+        Ok(rule)
+    }
+}
+
+/// Split `a|b|c` into normalized alternatives
+pub fn alternatives(value: &str, normalize: fn(&str) -> String) -> Vec<String> {
+    value
+        .split('|')
+        .filter(|v| !v.is_empty())
+        .map(normalize)
+        .collect()
+}
+
+fn parse_percent(value: &str) -> Result<f64, String> {
+    let percent: f64 = value
+        .trim_end_matches('%')
+        .parse()
+        .map_err(|_| format!("invalid sample percentage '{}'", value))?;
+    if !(0.0..=100.0).contains(&percent) {
+        return Err(format!("sample percentage must be 0-100, got {}", percent));
+    }
+    Ok(percent)
+}
+
+fn parse_hours(value: &str) -> Result<(u32, u32), String> {
+    let invalid = || format!("hours must look like 22-06 (UTC), found '{}'", value);
+    let (start, end) = value.split_once('-').ok_or_else(invalid)?;
+    let start: u32 = start.parse().map_err(|_| invalid())?;
+    let end: u32 = end.parse().map_err(|_| invalid())?;
+    if start > 23 || end > 24 || start == end {
+        return Err(invalid());
+    }
+    Ok((start, end))
+}
+
+/// Fold the spellings `TimestampedLog::is_error` / `is_warning` accept
+pub fn normalize_level(level: &str) -> String {
+    match level.to_lowercase().as_str() {
+        "err" => "error".to_string(),
+        "warning" => "warn".to_string(),
+        other => other.to_string(),
+    }
+}
+
+/// NATS subject match: `*` matches one token, a trailing `>` one or more
+pub fn subject_matches(pattern: &str, subject: &str) -> bool {
+    let mut tokens = subject.split('.');
+    for part in pattern.split('.') {
+        match (part, tokens.next()) {
+            (">", Some(_)) => return true,
+            ("*", Some(_)) => {}
+            (part, Some(token)) if part == token => {}
+            _ => return false,
+        }
+    }
+    tokens.next().is_none()
+}
+
+/// The fields of a Fly log line rules look at
+#[derive(Default)]
+struct LineFields {
+    level: Option<String>,
+    app: Option<String>,
+}
+
+impl LineFields {
+    fn parse(raw: &str) -> Self {
+        #[derive(Deserialize)]
+        struct FlyLog {
+            log: Option<LogLevel>,
+            fly: Option<FlyMeta>,
+        }
+
+        #[derive(Deserialize)]
+        struct LogLevel {
+            level: Option<String>,
+        }
+
+        #[derive(Deserialize)]
+        struct FlyMeta {
+            app: Option<AppMeta>,
+        }
+
+        #[derive(Deserialize)]
+        struct AppMeta {
+            name: Option<String>,
+        }
+
+        match serde_json::from_str::<FlyLog>(raw) {
+            Ok(parsed) => LineFields {
+                level: parsed
+                    .log
+                    .and_then(|l| l.level)
+                    .map(|l| normalize_level(&l)),
+                app: parsed
+                    .fly
+                    .and_then(|f| f.app)
+                    .and_then(|a| a.name)
+                    .map(|a| a.to_lowercase()),
+            },
+            Err(_) => LineFields::default(),
+        }
+    }
+}
+
+/// A line not parsed yet; its fields are read the first time a rule asks
+struct RawLine<'a> {
+    raw: &'a str,
+    fields: &'a OnceCell<LineFields>,
+}
+
+impl RawLine<'_> {
+    fn fields(&self) -> &LineFields {
+        self.fields.get_or_init(|| LineFields::parse(self.raw))
+    }
+}
+
+impl Line for RawLine<'_> {
+    fn raw(&self) -> &str {
+        self.raw
+    }
+
+    fn level(&self) -> Option<&str> {
+        self.fields().level.as_deref()
+    }
+
+    fn app(&self) -> Option<&str> {
+        self.fields().app.as_deref()
+    }
+}
+
+impl IngestRule {
+    fn matches(&self, subject: &str, raw: &str, fields: &OnceCell<LineFields>, hour: u32) -> bool {
+        if let Some(ref pattern) = self.subject {
+            if !subject_matches(pattern, subject) {
+                return false;
+            }
+        }
+        if let Some((start, end)) = self.hours {
+            let inside = if start < end {
+                (start..end).contains(&hour)
+            } else {
+                hour >= start || hour < end
+            };
+            if !inside {
+                return false;
+            }
+        }
+        self.filter.matches(&RawLine { raw, fields })
+    }
+}
+
+struct ActiveRule {
+    rule: IngestRule,
+    /// Lines this rule has matched, for deterministic sampling
+    matched: AtomicU64,
+}
+
+impl ActiveRule {
+    /// Keep exactly `percent` of matches, spread evenly
+    fn sample(&self, percent: f64) -> bool {
+        let n = self.matched.fetch_add(1, Ordering::Relaxed) as f64;
+        ((n + 1.0) * percent / 100.0).floor() > (n * percent / 100.0).floor()
+    }
+}
+
+/// The live rule set, swapped on config reload
+#[derive(Default)]
+pub struct IngestFilter {
+    rules: RwLock<Vec<ActiveRule>>,
+}
+
+impl IngestFilter {
+    pub fn new(rules: &[IngestRule]) -> Arc<Self> {
+        let filter = Arc::new(Self::default());
+        filter.set_rules(rules);
+        filter
+    }
+
+    /// Replace the rules; sampling restarts from zero
+    pub fn set_rules(&self, rules: &[IngestRule]) {
+        *self.rules.write().unwrap() = rules
+            .iter()
+            .map(|rule| ActiveRule {
+                rule: rule.clone(),
+                matched: AtomicU64::new(0),
+            })
+            .collect();
+    }
+
+    /// Whether a message on `subject` should be ingested
+    pub fn admit(&self, subject: &str, raw: &str) -> bool {
+        let rules = self.rules.read().unwrap();
+        if rules.is_empty() {
+            return true;
+        }
+        let fields = OnceCell::new();
+        let hour = Utc::now().hour();
+        for active in rules.iter() {
+            if active.rule.matches(subject, raw, &fields, hour) {
+                return match active.rule.action {
+                    RuleAction::Drop => false,
+                    RuleAction::Sample(percent) => active.sample(percent),
+                };
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fly_line(app: &str, level: &str, message: &str) -> String {
+        serde_json::json!({
+            "message": message,
+            "log": { "level": level },
+            "fly": { "app": { "name": app, "instance": "abc123" }, "region": "iad" },
+        })
+        .to_string()
+    }
+
+    fn rules(rules: &[&str]) -> Vec<IngestRule> {
+        rules.iter().map(|r| r.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_drop_by_app_level_and_pattern() {
+        let filter = IngestFilter::new(&rules(&[
+            "drop app=checkout level=debug|trace",
+            "drop match=GET /healthz",
+        ]));
+        let subject = "logs.checkout.iad.abc123";
+
+        assert!(!filter.admit(subject, &fly_line("checkout", "DEBUG", "cart loaded")));
+        assert!(filter.admit(subject, &fly_line("checkout", "error", "cart failed")));
+        assert!(filter.admit(subject, &fly_line("billing", "debug", "cart loaded")));
+        assert!(!filter.admit(subject, &fly_line("billing", "info", "GET /healthz 200")));
+    }
+
+    #[test]
+    fn test_sample_keeps_share_of_subject() {
+        let filter = IngestFilter::new(&rules(&["sample=10% subject=logs.noisy.>"]));
+        let line = fly_line("noisy", "info", "tick");
+
+        let kept = (0..100)
+            .filter(|_| filter.admit("logs.noisy.iad.abc123", &line))
+            .count();
+        assert_eq!(kept, 10);
+        assert!(filter.admit("logs.quiet.iad.abc123", &line));
+        assert!(!subject_matches("logs.noisy.>", "logs.noisy"));
+        assert!(subject_matches("logs.*.iad.*", "logs.noisy.iad.abc123"));
+    }
+
+    #[test]
+    fn test_quiet_hours_and_parse_errors() {
+        let rule: IngestRule = "drop level=info hours=22-06".parse().unwrap();
+        let fields = OnceCell::new();
+        let line = fly_line("app", "info", "nightly job");
+        assert!(rule.matches("logs.app", &line, &fields, 23));
+        assert!(rule.matches("logs.app", &line, &fields, 5));
+        assert!(!rule.matches("logs.app", &line, &fields, 12));
+
+        assert!("drop".parse::<IngestRule>().is_err());
+        assert!("sample=150% app=x".parse::<IngestRule>().is_err());
+        assert!("keep app=x".parse::<IngestRule>().is_err());
+        assert!("drop hours=9".parse::<IngestRule>().is_err());
+        assert!("drop match=(".parse::<IngestRule>().is_err());
+    }
+}
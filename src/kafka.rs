@@ -0,0 +1,166 @@
+//! Kafka / Redpanda sink for stream processing outside flywatch: every
+//! buffered line is produced to KAFKA_TOPIC, keyed by instance so one
+//! machine's lines stay ordered within a partition.
+//!
+//! Records go through the Kafka REST Proxy v2 API, which both Confluent's
+//! REST Proxy and Redpanda's HTTP Proxy serve, so flywatch needs no native
+//! Kafka client. Records the proxy reports as retriable are sent again.
+
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::info;
+
+use crate::config::Config;
+use crate::nats::LogMessage;
+use crate::sink::{response_error, Rejection, Sink, SinkDefaults, SinkError};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+const CONTENT_TYPE: &str = "application/vnd.kafka.json.v2+json";
+/// REST Proxy error code for records that may succeed when sent again
+const RETRIABLE: i64 = 1;
+
+pub struct Kafka {
+    client: Client,
+    url: String,
+    topic: String,
+    username: Option<String>,
+    password: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ProduceResponse {
+    offsets: Vec<RecordOffset>,
+}
+
+#[derive(Deserialize)]
+struct RecordOffset {
+    error_code: Option<i64>,
+    error: Option<String>,
+}
+
+/// Records the proxy didn't produce
+fn failed_records(response: &ProduceResponse) -> Vec<Rejection> {
+    response
+        .offsets
+        .iter()
+        .enumerate()
+        .filter_map(|(i, offset)| {
+            let code = offset.error_code?;
+            Some(Rejection {
+                index: i,
+                retryable: code == RETRIABLE,
+                reason: offset
+                    .error
+                    .clone()
+                    .unwrap_or_else(|| format!("error code {}", code)),
+            })
+        })
+        .collect()
+}
+
+impl Kafka {
+    /// None without KAFKA_REST_URL
+    pub fn new(config: &Config) -> Option<Self> {
+        let url = config.kafka_rest_url.clone()?;
+        let client = Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .expect("Failed to build HTTP client");
+        let kafka = Self {
+            client,
+            url: url.trim_end_matches('/').to_string(),
+            topic: config.kafka_topic.clone(),
+            username: config.kafka_username.clone(),
+            password: config.kafka_password.clone(),
+        };
+        info!(url = %kafka.url, topic = %kafka.topic, "Kafka export enabled");
+        Some(kafka)
+    }
+
+    /// One record per line: the parsed log as the value, its instance as the key
+    fn records(batch: &[LogMessage]) -> serde_json::Value {
+        let records: Vec<_> = batch
+            .iter()
+            .map(|log| json!({ "key": log.instance, "value": Arc::as_ref(log) }))
+            .collect();
+        json!({ "records": records })
+    }
+}
+
+#[async_trait]
+impl Sink for Kafka {
+    fn kind(&self) -> &'static str {
+        "kafka"
+    }
+
+    fn defaults(&self) -> SinkDefaults {
+        SinkDefaults {
+            queue_capacity: 50_000,
+            // Proxies cap the request size
+            batch_size: 500,
+            flush_interval: Duration::from_secs(1),
+            max_attempts: 5,
+        }
+    }
+
+    /// One produce request; returns the records that failed
+    async fn send(&self, batch: &[LogMessage]) -> Result<Vec<Rejection>, SinkError> {
+        let mut request = self
+            .client
+            .post(format!("{}/topics/{}", self.url, self.topic))
+            .header("Content-Type", CONTENT_TYPE)
+            .header("Accept", "application/vnd.kafka.v2+json")
+            .body(Self::records(batch).to_string());
+        if let Some(user) = &self.username {
+            request = request.basic_auth(user, self.password.as_deref());
+        }
+        let response = request
+            .send()
+            .await
+            .map_err(|e| SinkError::Unavailable(e.to_string()))?;
+        if !response.status().is_success() {
+            return Err(response_error(response).await);
+        }
+        let response: ProduceResponse = response
+            .json()
+            .await
+            .map_err(|e| SinkError::Unavailable(e.to_string()))?;
+        Ok(failed_records(&response))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::log_buffer::TimestampedLog;
+
+    #[test]
+    fn test_records_and_failures() {
+        let log: LogMessage = Arc::new(TimestampedLog::new(
+            r#"{"message":"boom","fly":{"app":{"instance":"148ed193b"}}}"#.to_string(),
+            3,
+        ));
+        let body = Kafka::records(&[log]);
+        assert_eq!(body["records"][0]["key"], "148ed193b");
+        assert_eq!(body["records"][0]["value"]["message"], "boom");
+        assert_eq!(body["records"][0]["value"]["seq"], 3);
+
+        let response: ProduceResponse = serde_json::from_value(json!({
+            "offsets": [
+                { "partition": 0, "offset": 10, "error_code": null, "error": null },
+                { "partition": null, "offset": null, "error_code": 1, "error": "leader not available" },
+                { "partition": null, "offset": null, "error_code": 2, "error": "record too large" },
+            ]
+        }))
+        .unwrap();
+        let failed = failed_records(&response);
+        assert_eq!(failed.len(), 2);
+        assert_eq!((failed[0].index, failed[0].retryable), (1, true));
+        assert_eq!((failed[1].index, failed[1].retryable), (2, false));
+    }
+}
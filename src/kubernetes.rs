@@ -0,0 +1,353 @@
+use async_trait::async_trait;
+use futures::StreamExt;
+use reqwest::Client;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+use crate::source::{
+    drain_lines, fly_app_envelope, json_line_level, LogSource, SourceContext, SourceError,
+};
+
+const K8S_QUEUE_CAPACITY: usize = 10_000;
+const POD_POLL_INTERVAL: Duration = Duration::from_secs(10);
+const SERVICE_ACCOUNT_DIR: &str = "/var/run/secrets/kubernetes.io/serviceaccount";
+
+/// Streams logs of labeled pods in one namespace from the Kubernetes API.
+/// Lines carry the pod (with the container, for pods with sidecars) as
+/// instance, the node as region, and the namespace, or the pod's
+/// `app_label` label, as app.
+pub struct KubernetesSource {
+    api_url: Option<String>,
+    namespace: Option<String>,
+    label_selector: Option<String>,
+    app_label: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PodList {
+    items: Vec<Pod>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Pod {
+    metadata: PodMetadata,
+    spec: PodSpec,
+    status: PodStatus,
+}
+
+#[derive(Debug, Deserialize)]
+struct PodMetadata {
+    name: String,
+    #[serde(default)]
+    labels: HashMap<String, String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PodSpec {
+    node_name: Option<String>,
+    containers: Vec<ContainerSpec>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ContainerSpec {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct PodStatus {
+    phase: Option<String>,
+}
+
+/// Resolved API endpoint and credentials
+#[derive(Clone)]
+struct ApiClient {
+    client: Client,
+    base_url: String,
+    token: Option<String>,
+    namespace: String,
+}
+
+impl ApiClient {
+    fn get(&self, path: &str) -> reqwest::RequestBuilder {
+        let request = self.client.get(format!("{}{}", self.base_url, path));
+        match &self.token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+}
+
+impl KubernetesSource {
+    pub fn new(
+        api_url: Option<String>,
+        namespace: Option<String>,
+        label_selector: Option<String>,
+        app_label: Option<String>,
+    ) -> Self {
+        Self {
+            api_url,
+            namespace,
+            label_selector,
+            app_label,
+        }
+    }
+
+    /// The app a pod's lines are filed under
+    fn app(&self, pod: &Pod, namespace: &str) -> String {
+        self.app_label
+            .as_ref()
+            .and_then(|label| pod.metadata.labels.get(label))
+            .cloned()
+            .unwrap_or_else(|| namespace.to_string())
+    }
+
+    /// Build an API client, falling back to in-cluster service account config
+    async fn api_client(&self) -> Result<ApiClient, SourceError> {
+        let base_url = match &self.api_url {
+            Some(url) => url.trim_end_matches('/').to_string(),
+            None => {
+                let host = std::env::var("KUBERNETES_SERVICE_HOST").map_err(|_| {
+                    SourceError::Config(
+                        "K8S_API_URL not set and not running in a cluster".to_string(),
+                    )
+                })?;
+                let port =
+                    std::env::var("KUBERNETES_SERVICE_PORT").unwrap_or_else(|_| "443".to_string());
+                format!("https://{}:{}", host, port)
+            }
+        };
+
+        let token = tokio::fs::read_to_string(format!("{}/token", SERVICE_ACCOUNT_DIR))
+            .await
+            .ok()
+            .map(|t| t.trim().to_string());
+
+        let namespace = match &self.namespace {
+            Some(ns) => ns.clone(),
+            None => tokio::fs::read_to_string(format!("{}/namespace", SERVICE_ACCOUNT_DIR))
+                .await
+                .map(|ns| ns.trim().to_string())
+                .unwrap_or_else(|_| "default".to_string()),
+        };
+
+        let mut builder = Client::builder().connect_timeout(Duration::from_secs(10));
+        if let Ok(ca) = tokio::fs::read(format!("{}/ca.crt", SERVICE_ACCOUNT_DIR)).await {
+            let cert = reqwest::Certificate::from_pem(&ca)
+                .map_err(|e| SourceError::Config(format!("invalid cluster CA: {}", e)))?;
+            builder = builder.add_root_certificate(cert);
+        }
+        let client = builder
+            .build()
+            .map_err(|e| SourceError::Config(e.to_string()))?;
+
+        Ok(ApiClient {
+            client,
+            base_url,
+            token,
+            namespace,
+        })
+    }
+
+    async fn list_pods(&self, api: &ApiClient) -> Result<Vec<Pod>, SourceError> {
+        let mut request = api.get(&format!("/api/v1/namespaces/{}/pods", api.namespace));
+        if let Some(selector) = &self.label_selector {
+            request = request.query(&[("labelSelector", selector)]);
+        }
+
+        let response = request
+            .send()
+            .await
+            .map_err(|e| SourceError::Connect(e.to_string()))?;
+        if !response.status().is_success() {
+            return Err(SourceError::Io(format!(
+                "Kubernetes API returned {}",
+                response.status()
+            )));
+        }
+
+        let pods: PodList = response
+            .json()
+            .await
+            .map_err(|e| SourceError::Io(format!("invalid pod list: {}", e)))?;
+        Ok(pods
+            .items
+            .into_iter()
+            .filter(|p| p.status.phase.as_deref() == Some("Running"))
+            .collect())
+    }
+}
+
+#[async_trait]
+impl LogSource for KubernetesSource {
+    fn name(&self) -> &str {
+        "kubernetes"
+    }
+
+    fn kind(&self) -> &'static str {
+        "kubernetes"
+    }
+
+    async fn run(&self, ctx: &SourceContext) -> Result<(), SourceError> {
+        let api = self.api_client().await?;
+        let (tx, mut rx) = mpsc::channel::<String>(K8S_QUEUE_CAPACITY);
+        // Keyed by "pod/container"
+        let mut streams: HashMap<String, JoinHandle<()>> = HashMap::new();
+        let mut interval = tokio::time::interval(POD_POLL_INTERVAL);
+        let mut first_poll = true;
+
+        let result = loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    let pods = match self.list_pods(&api).await {
+                        Ok(p) => p,
+                        Err(e) => break Err(e),
+                    };
+                    if first_poll {
+                        info!(
+                            api = %api.base_url,
+                            namespace = %api.namespace,
+                            pods = pods.len(),
+                            "Connected to Kubernetes"
+                        );
+                        ctx.running().await;
+                        first_poll = false;
+                    }
+
+                    let mut live = HashSet::new();
+                    for pod in &pods {
+                        for container in &pod.spec.containers {
+                            let key = format!("{}/{}", pod.metadata.name, container.name);
+                            live.insert(key.clone());
+
+                            let finished = streams.get(&key).is_some_and(|h| h.is_finished());
+                            if streams.contains_key(&key) && !finished {
+                                continue;
+                            }
+
+                            info!(pod = %pod.metadata.name, container = %container.name, "Streaming pod logs");
+                            let handle = tokio::spawn(stream_pod(
+                                api.clone(),
+                                self.app(pod, &api.namespace),
+                                pod.metadata.name.clone(),
+                                container.name.clone(),
+                                pod.spec.node_name.clone(),
+                                pod.spec.containers.len() > 1,
+                                tx.clone(),
+                            ));
+                            streams.insert(key, handle);
+                        }
+                    }
+
+                    streams.retain(|key, handle| {
+                        let keep = live.contains(key);
+                        if !keep {
+                            handle.abort();
+                        }
+                        keep
+                    });
+                }
+
+                Some(raw) = rx.recv() => {
+                    ctx.emit(raw).await;
+                }
+            }
+        };
+
+        for (_, handle) in streams {
+            handle.abort();
+        }
+        result
+    }
+}
+
+/// Follow one container's logs, forwarding each line as Fly-style JSON
+async fn stream_pod(
+    api: ApiClient,
+    app: String,
+    pod: String,
+    container: String,
+    node: Option<String>,
+    qualify_instance: bool,
+    tx: mpsc::Sender<String>,
+) {
+    let since = chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+    let response = api
+        .get(&format!(
+            "/api/v1/namespaces/{}/pods/{}/log",
+            api.namespace, pod
+        ))
+        .query(&[
+            ("follow", "true"),
+            ("container", container.as_str()),
+            ("sinceTime", since.as_str()),
+        ])
+        .send()
+        .await;
+
+    let response = match response {
+        Ok(r) if r.status().is_success() => r,
+        Ok(r) => {
+            warn!(pod = %pod, status = %r.status(), "Failed to open pod log stream");
+            return;
+        }
+        Err(e) => {
+            warn!(pod = %pod, error = %e, "Failed to open pod log stream");
+            return;
+        }
+    };
+
+    // Pods with sidecars get "pod/container" so their lines stay distinguishable
+    let instance = if qualify_instance {
+        format!("{}/{}", pod, container)
+    } else {
+        pod.clone()
+    };
+
+    let mut stream = response.bytes_stream();
+    let mut buf = Vec::new();
+    while let Some(Ok(chunk)) = stream.next().await {
+        buf.extend_from_slice(&chunk);
+        for line in drain_lines(&mut buf) {
+            let raw = fly_app_envelope(
+                &line,
+                json_line_level(&line),
+                Some(&app),
+                Some(&instance),
+                node.as_deref(),
+            );
+            if tx.send(raw).await.is_err() {
+                return;
+            }
+        }
+    }
+
+    info!(pod = %pod, container = %container, "Pod log stream ended");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_app_from_label_or_namespace() {
+        let pod: Pod = serde_json::from_value(serde_json::json!({
+            "metadata": {"name": "api-7f9", "labels": {"app.kubernetes.io/name": "payments"}},
+            "spec": {"nodeName": "node-a", "containers": [{"name": "api"}]},
+            "status": {"phase": "Running"},
+        }))
+        .unwrap();
+
+        let by_namespace = KubernetesSource::new(None, None, None, None);
+        assert_eq!(by_namespace.app(&pod, "shop"), "shop");
+        let by_label =
+            KubernetesSource::new(None, None, None, Some("app.kubernetes.io/name".to_string()));
+        assert_eq!(by_label.app(&pod, "shop"), "payments");
+        let missing = KubernetesSource::new(None, None, None, Some("team".to_string()));
+        assert_eq!(missing.app(&pod, "shop"), "shop");
+    }
+}
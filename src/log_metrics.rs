@@ -0,0 +1,395 @@
+//! Metrics derived from log content. Each rule in `LOG_METRICS` defines a
+//! counter or histogram evaluated against every ingested line:
+//!
+//! ```text
+//! counter http_5xx match=\s5\d\d\s
+//! counter checkout_errors app=checkout level=error
+//! histogram latency_ms field=duration_ms buckets=10|50|100|500|1000
+//! ```
+//!
+//! Conditions (`app=`, `level=`, `region=`, `field.<key>=` on a structured
+//! field, `match=`) must all hold; `match=` runs to the end of the rule.
+//! Histogram values come from a JSON field of the line or of its message, or
+//! from `key=value` text in the message.
+
+use regex::Regex;
+use serde::Serialize;
+use serde_json::Value;
+use std::cell::OnceCell;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, RwLock};
+use utoipa::ToSchema;
+
+use crate::filter::{self, Filter};
+use crate::ingest_filter::{alternatives, normalize_level};
+use crate::log_buffer::TimestampedLog;
+
+/// Prometheus' default latency buckets
+const DEFAULT_BUCKETS: &[f64] = &[
+    5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0, 1000.0, 2500.0, 5000.0, 10000.0,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum LogMetricKind {
+    Counter,
+    Histogram,
+}
+
+/// One log-based metric, as written in `LOG_METRICS`
+#[derive(Debug, Clone)]
+pub struct LogMetricRule {
+    text: String,
+    name: String,
+    kind: LogMetricKind,
+    /// Dotted path of the value a histogram observes
+    field: Vec<String>,
+    /// Finds `field=123` / `field: 123` in plain-text messages
+    field_text: Option<Regex>,
+    buckets: Vec<f64>,
+    /// The `app=`, `level=`, `region=` and `match=` conditions
+    filter: Filter,
+}
+
+impl PartialEq for LogMetricRule {
+    fn eq(&self, other: &Self) -> bool {
+        self.text == other.text
+    }
+}
+
+fn valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+impl FromStr for LogMetricRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let text = s.trim();
+        let mut words = text.splitn(3, char::is_whitespace);
+        let kind = match words.next() {
+            Some("counter") => LogMetricKind::Counter,
+            Some("histogram") => LogMetricKind::Histogram,
+            other => {
+                return Err(format!(
+                    "expected 'counter' or 'histogram', found '{}'",
+                    other.unwrap_or_default()
+                ))
+            }
+        };
+        let name = words.next().unwrap_or_default();
+        if !valid_name(name) {
+            return Err(format!("invalid metric name '{}'", name));
+        }
+
+        let mut rule = LogMetricRule {
+            text: text.to_string(),
+            name: name.to_string(),
+            kind,
+            field: Vec::new(),
+            field_text: None,
+            buckets: DEFAULT_BUCKETS.to_vec(),
+            filter: Filter::default(),
+        };
+
+        let mut rest = words.next().unwrap_or_default().trim_start();
+        while !rest.is_empty() {
+            let (token, tail) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+            let (key, value) = token
+                .split_once('=')
+                .ok_or_else(|| format!("expected key=value, found '{}'", token))?;
+            match key {
+                "match" => {
+                    let pattern = rest["match=".len()..].trim_end();
+                    rule.filter.pattern =
+                        Some(filter::pattern(pattern).map_err(|e| format!("match: {}", e))?);
+                    break;
+                }
+                "app" => rule.filter.apps = alternatives(value, str::to_lowercase),
+                "level" => rule.filter.levels = alternatives(value, normalize_level),
+                "region" => rule.filter.regions = alternatives(value, str::to_lowercase),
+                key if filter::field_key(key).is_some() => rule.filter.fields.push((
+                    filter::field_key(key).unwrap_or_default().to_string(),
+                    alternatives(value, str::to_string),
+                )),
+                "field" if kind == LogMetricKind::Histogram => {
+                    rule.field = value.split('.').map(str::to_string).collect();
+                    let key = regex::escape(rule.field.last().map_or("", String::as_str));
+                    rule.field_text =
+                        Regex::new(&format!(r#"\b{}"?\s*[=:]\s*"?(-?\d+(?:\.\d+)?)"#, key)).ok();
+                }
+                "buckets" if kind == LogMetricKind::Histogram => {
+                    let mut buckets = Vec::new();
+                    for bound in value.split('|') {
+                        buckets.push(
+                            bound
+                                .parse::<f64>()
+                                .map_err(|_| format!("invalid bucket '{}'", bound))?,
+                        );
+                    }
+                    if buckets.is_empty() || buckets.windows(2).any(|w| w[0] >= w[1]) {
+                        return Err("buckets must be increasing".to_string());
+                    }
+                    rule.buckets = buckets;
+                }
+                other => return Err(format!("unknown option '{}'", other)),
+            }
+            rest = tail.trim_start();
+        }
+
+        if kind == LogMetricKind::Histogram && rule.field.is_empty() {
+            return Err("a histogram needs field=".to_string());
+        }
+        Ok(rule)
+    }
+}
+
+impl LogMetricRule {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn kind(&self) -> LogMetricKind {
+        self.kind
+    }
+
+    fn matches(&self, log: &TimestampedLog) -> bool {
+        self.filter.matches(log)
+    }
+
+    /// The observed value: a JSON field of the line, then a structured
+    /// field (a JSON message or `key=value` text), then `key: value` text
+    fn value(&self, log: &TimestampedLog, raw_json: &OnceCell<Option<Value>>) -> Option<f64> {
+        let raw_json = raw_json.get_or_init(|| serde_json::from_str(&log.raw).ok());
+        if let Some(value) = raw_json.as_ref().and_then(|v| lookup(v, &self.field)) {
+            return Some(value);
+        }
+        if let Some(value) = log.field(&self.field.join(".")).and_then(number) {
+            return Some(value);
+        }
+        let caps = self
+            .field_text
+            .as_ref()?
+            .captures(log.message.as_deref()?)?;
+        caps[1].parse().ok()
+    }
+}
+
+fn lookup(value: &Value, path: &[String]) -> Option<f64> {
+    number(
+        path.iter()
+            .try_fold(value, |current, key| current.get(key))?,
+    )
+}
+
+fn number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct BucketCount {
+    /// Upper bound (inclusive)
+    pub le: f64,
+    /// Observations at or below `le`
+    pub count: u64,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct LogMetricSnapshot {
+    pub name: String,
+    pub kind: LogMetricKind,
+    /// Matching lines for a counter, observations for a histogram
+    pub count: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sum: Option<f64>,
+    /// Cumulative bucket counts (histograms only)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub buckets: Vec<BucketCount>,
+}
+
+#[derive(Default)]
+struct SeriesState {
+    count: u64,
+    sum: f64,
+    /// Per-bucket (non-cumulative) counts; the last slot is +Inf
+    buckets: Vec<u64>,
+}
+
+struct Series {
+    rule: LogMetricRule,
+    state: Mutex<SeriesState>,
+}
+
+impl Series {
+    fn new(rule: LogMetricRule) -> Arc<Self> {
+        let buckets = vec![0; rule.buckets.len() + 1];
+        Arc::new(Self {
+            rule,
+            state: Mutex::new(SeriesState {
+                buckets,
+                ..SeriesState::default()
+            }),
+        })
+    }
+
+    fn snapshot(&self) -> LogMetricSnapshot {
+        let state = self.state.lock().unwrap();
+        let histogram = self.rule.kind == LogMetricKind::Histogram;
+        let mut cumulative = 0;
+        LogMetricSnapshot {
+            name: self.rule.name.clone(),
+            kind: self.rule.kind,
+            count: state.count,
+            sum: histogram.then_some(state.sum),
+            buckets: if histogram {
+                self.rule
+                    .buckets
+                    .iter()
+                    .zip(&state.buckets)
+                    .map(|(&le, &count)| {
+                        cumulative += count;
+                        BucketCount {
+                            le,
+                            count: cumulative,
+                        }
+                    })
+                    .collect()
+            } else {
+                Vec::new()
+            },
+        }
+    }
+}
+
+/// The live set of log-based metrics
+#[derive(Default)]
+pub struct LogMetrics {
+    series: RwLock<Vec<Arc<Series>>>,
+}
+
+impl LogMetrics {
+    pub fn new(rules: &[LogMetricRule]) -> Arc<Self> {
+        let metrics = Arc::new(Self::default());
+        metrics.set_rules(rules);
+        metrics
+    }
+
+    /// Replace the rules, keeping the values of rules that didn't change
+    pub fn set_rules(&self, rules: &[LogMetricRule]) {
+        let mut series = self.series.write().unwrap();
+        let next = rules
+            .iter()
+            .map(|rule| {
+                series
+                    .iter()
+                    .find(|s| s.rule == *rule)
+                    .cloned()
+                    .unwrap_or_else(|| Series::new(rule.clone()))
+            })
+            .collect();
+        *series = next;
+    }
+
+    /// Feed one ingested line through every rule
+    pub fn observe(&self, log: &TimestampedLog) {
+        let series = self.series.read().unwrap();
+        let raw_json = OnceCell::new();
+        for s in series.iter().filter(|s| s.rule.matches(log)) {
+            match s.rule.kind {
+                LogMetricKind::Counter => s.state.lock().unwrap().count += 1,
+                LogMetricKind::Histogram => {
+                    let Some(value) = s.rule.value(log, &raw_json) else {
+                        continue;
+                    };
+                    let slot = s
+                        .rule
+                        .buckets
+                        .iter()
+                        .position(|&le| value <= le)
+                        .unwrap_or(s.rule.buckets.len());
+                    let mut state = s.state.lock().unwrap();
+                    state.count += 1;
+                    state.sum += value;
+                    state.buckets[slot] += 1;
+                }
+            }
+        }
+    }
+
+    pub fn snapshot(&self) -> Vec<LogMetricSnapshot> {
+        self.series
+            .read()
+            .unwrap()
+            .iter()
+            .map(|s| s.snapshot())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::fly_envelope;
+
+    fn log(message: &str, level: &str) -> TimestampedLog {
+        TimestampedLog::new(
+            fly_envelope(message, Some(level), Some("web-1"), Some("iad")),
+            1,
+        )
+    }
+
+    fn rules(rules: &[&str]) -> Vec<LogMetricRule> {
+        rules.iter().map(|r| r.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_counter_and_histogram() {
+        let metrics = LogMetrics::new(&rules(&[
+            r"counter http_5xx match=\s5\d\d\s",
+            "histogram latency_ms field=duration_ms buckets=10|100",
+            "counter root_requests field.path=/|/index.html",
+        ]));
+        metrics.observe(&log("GET /cart 503 in 12ms duration_ms=12", "error"));
+        metrics.observe(&log(r#"{"path":"/","duration_ms":250}"#, "info"));
+        metrics.observe(&log("GET / 200 duration_ms=4.5", "info"));
+        metrics.observe(&log("no timing here", "info"));
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot[0].count, 1);
+        assert!(snapshot[0].sum.is_none());
+
+        let histogram = &snapshot[1];
+        assert_eq!(histogram.count, 3);
+        assert_eq!(histogram.sum, Some(266.5));
+        let buckets: Vec<u64> = histogram.buckets.iter().map(|b| b.count).collect();
+        // le=10 holds 4.5; le=100 adds 12; 250 only counts toward +Inf
+        assert_eq!(buckets, vec![1, 2]);
+        assert_eq!(snapshot[2].count, 1);
+    }
+
+    #[test]
+    fn test_reload_keeps_unchanged_series() {
+        let metrics = LogMetrics::new(&rules(&["counter errors level=error"]));
+        metrics.observe(&log("boom", "ERROR"));
+        metrics.observe(&log("fine", "info"));
+
+        metrics.set_rules(&rules(&["counter errors level=error", "counter all"]));
+        metrics.observe(&log("boom", "err"));
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot[0].count, 2);
+        assert_eq!(snapshot[1].count, 1);
+
+        assert!("gauge x".parse::<LogMetricRule>().is_err());
+        assert!("counter 5xx".parse::<LogMetricRule>().is_err());
+        assert!("histogram latency".parse::<LogMetricRule>().is_err());
+        assert!("counter x field=y".parse::<LogMetricRule>().is_err());
+    }
+}
@@ -0,0 +1,143 @@
+use axum::{extract::State, http::HeaderMap, Json};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, RwLock};
+use tracing::{info, Level};
+use tracing_subscriber::{fmt, prelude::*, reload, EnvFilter, Registry};
+use utoipa::ToSchema;
+
+use crate::error::{ApiError, ErrorBody};
+use crate::http::{check_auth, AppState};
+use crate::self_log::SelfLogLayer;
+
+/// Handle for swapping the tracing filter at runtime
+pub struct LogFilter {
+    handle: reload::Handle<EnvFilter, Registry>,
+    /// Filter from RUST_LOG at startup, restored on reset
+    initial: String,
+    current: RwLock<String>,
+}
+
+impl LogFilter {
+    pub fn current(&self) -> String {
+        self.current.read().unwrap().clone()
+    }
+
+    /// Replace the active filter, e.g. "info,flywatch=debug"
+    pub fn set(&self, directives: &str) -> Result<(), String> {
+        let filter = EnvFilter::builder()
+            .with_default_directive(Level::INFO.into())
+            .parse(directives)
+            .map_err(|e| format!("invalid filter '{}': {}", directives, e))?;
+        let rendered = filter.to_string();
+        self.handle.reload(filter).map_err(|e| e.to_string())?;
+        *self.current.write().unwrap() = rendered;
+        Ok(())
+    }
+
+    pub fn reset(&self) -> Result<(), String> {
+        let initial = self.initial.clone();
+        self.set(&initial)
+    }
+}
+
+/// Install the global subscriber: JSON output plus self-log capture, both
+/// behind a reloadable EnvFilter
+pub fn init(self_log: SelfLogLayer) -> Arc<LogFilter> {
+    let filter = EnvFilter::builder()
+        .with_default_directive(Level::INFO.into())
+        .from_env_lossy();
+    let initial = filter.to_string();
+    let (filter, handle) = reload::Layer::new(filter);
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(fmt::layer().json())
+        .with(self_log)
+        .init();
+
+    Arc::new(LogFilter {
+        handle,
+        current: RwLock::new(initial.clone()),
+        initial,
+    })
+}
+
+/// A filter for tests that don't install the global subscriber; changing it
+/// has no effect
+#[cfg(test)]
+pub fn detached() -> Arc<LogFilter> {
+    let filter = EnvFilter::new(Level::INFO.to_string());
+    let initial = filter.to_string();
+    let (_, handle) = reload::Layer::<EnvFilter, Registry>::new(filter);
+    Arc::new(LogFilter {
+        handle,
+        current: RwLock::new(initial.clone()),
+        initial,
+    })
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct LoggingRequest {
+    /// New EnvFilter directives; omit to restore the startup filter
+    pub filter: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct LoggingResponse {
+    pub filter: String,
+}
+
+/// POST /admin/logging - change the tracing filter without a redeploy
+#[utoipa::path(
+    post, path = "/admin/logging", tag = "admin",
+    request_body = LoggingRequest,
+    responses(
+        (status = 200, description = "The filter now in effect", body = LoggingResponse),
+        (status = 400, description = "Invalid filter directives", body = ErrorBody),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+pub async fn admin_logging_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<LoggingRequest>,
+) -> Result<Json<LoggingResponse>, ApiError> {
+    check_auth(&state, &headers)?;
+
+    let previous = state.log_filter.current();
+    let result = match request.filter.as_deref() {
+        Some(directives) => state.log_filter.set(directives),
+        None => state.log_filter.reset(),
+    };
+
+    result.map_err(ApiError::InvalidRequest)?;
+
+    let filter = state.log_filter.current();
+    info!(previous = %previous, filter = %filter, "Log filter changed");
+    Ok(Json(LoggingResponse { filter }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_rejects_invalid_filter() {
+        let (_layer, handle) = reload::Layer::<_, Registry>::new(EnvFilter::new("info"));
+        let filter = LogFilter {
+            handle,
+            initial: "info".to_string(),
+            current: RwLock::new("info".to_string()),
+        };
+
+        assert!(filter.set("flywatch=loud").is_err());
+        assert_eq!(filter.current(), "info");
+
+        filter.set("info,flywatch=debug").unwrap();
+        assert_eq!(filter.current(), "flywatch=debug,info");
+
+        filter.reset().unwrap();
+        assert_eq!(filter.current(), "info");
+    }
+}
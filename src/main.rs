@@ -1,44 +1,168 @@
+mod alerts;
+mod analysis;
+mod annotations;
+mod archive;
+mod audit;
+mod channels;
+mod chaos;
 mod chat;
+mod chat_cache;
+mod chat_ws;
+mod cli;
+mod clickhouse;
+mod cluster;
+mod compression;
 mod config;
+mod cors;
+mod deploys;
+mod dashboard;
+mod digest;
+mod docker;
+mod elasticsearch;
+mod download;
+mod error;
+#[cfg(all(test, feature = "eval"))]
+mod eval;
+mod events;
+mod facets;
+mod filter;
+mod fanout;
+mod file_tail;
+mod graphql;
+mod grpc;
+mod heartbeat;
 mod http;
-mod log_buffer;
+mod http_analytics;
+mod ingest_filter;
+mod kafka;
+mod kubernetes;
+mod log_metrics;
+mod logging;
+mod maintenance;
+mod mcp;
 mod metrics;
 mod nats;
+mod nats_rpc;
+mod payload;
 mod pricing;
+mod prometheus;
 mod prompt;
+mod readiness;
+mod redact;
+mod reload;
+mod runbooks;
+mod self_log;
+mod session;
+mod sink;
+mod slo;
+mod smtp;
+#[cfg(all(test, feature = "soak"))]
+mod soak;
+mod source;
+mod syslog;
+mod text;
+mod tenant;
+mod tickets;
+mod tls;
+mod tokenizer;
+mod trace;
+mod transcripts;
 mod usage;
+mod views;
+mod webhook;
+mod ws_auth;
 
+// The buffer and parser are shared with embedders through flywatch-core
+use flywatch_core::log_buffer;
+
+use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::sync::Arc;
-use std::time::Instant;
-use tokio::sync::broadcast;
-use tracing::{info, Level};
-use tracing_subscriber::{fmt, prelude::*, EnvFilter};
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, watch};
+use tracing::{error, info, warn};
 
-use crate::config::Config;
-use crate::http::{create_router, AppState};
+use crate::alerts::Alerts;
+use crate::annotations::Annotations;
+use crate::archive::Archive;
+use crate::audit::ToolAudit;
+use crate::chat_cache::ChatCache;
+use crate::cluster::{Cluster, ClusterSource};
+use crate::config::{Config, ConfigStore};
+use crate::deploys::Deploys;
+use crate::events::EventPublisher;
+use crate::fanout::Fanout;
+use crate::heartbeat::Heartbeats;
+use crate::http::{create_router, shutting_down, AppState};
+use crate::http_analytics::HttpAnalytics;
+use crate::ingest_filter::IngestFilter;
 use crate::log_buffer::{LogBuffer, LogBufferConfig};
+use crate::log_metrics::LogMetrics;
+use crate::mcp::McpSessions;
 use crate::metrics::{metrics_updater, Metrics};
-use crate::nats::{LogMessage, NatsSubscriber};
+use crate::nats::LogMessage;
+use crate::pricing::PricingCatalog;
+use crate::readiness::BroadcastMonitor;
+use crate::redact::Redactor;
+use crate::runbooks::RunbookIndex;
+use crate::self_log::SelfLog;
+use crate::sink::{build_sinks, SinkRegistry};
+use crate::slo::SloTracker;
+use crate::source::{build_sources, Pipeline, SourceRegistry};
+use crate::tenant::Tenants;
+use crate::transcripts::Transcripts;
 use crate::usage::UsageTracker;
+use crate::views::Views;
+use crate::ws_auth::UsedTickets;
+
+/// How long open HTTP streams get to finish after a shutdown signal
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+/// Time for WebSocket close frames to go out before the process exits
+const WS_CLOSE_GRACE: Duration = Duration::from_millis(500);
 
-const CHANNEL_CAPACITY: usize = 10_000;
+/// Config file from `--config <path>`, falling back to FLYWATCH_CONFIG
+fn config_path() -> Option<PathBuf> {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            return args.next().map(PathBuf::from);
+        }
+        if let Some(path) = arg.strip_prefix("--config=") {
+            return Some(PathBuf::from(path));
+        }
+    }
+    std::env::var("FLYWATCH_CONFIG")
+        .ok()
+        .filter(|s| !s.is_empty())
+        .map(PathBuf::from)
+}
 
 #[tokio::main]
 async fn main() {
-    // Initialize tracing
-    tracing_subscriber::registry()
-        .with(fmt::layer().json())
-        .with(
-            EnvFilter::builder()
-                .with_default_directive(Level::INFO.into())
-                .from_env_lossy(),
-        )
-        .init();
+    // Subcommands talk to a running server and print to the terminal
+    // (stdout carries MCP for `flywatch mcp`), so they start before logging
+    if let Some(code) = cli::run(std::env::args().skip(1).collect()).await {
+        std::process::exit(code);
+    }
+
+    // Initialize tracing (filter adjustable at runtime via /admin/logging)
+    let self_log = SelfLog::new();
+    let log_filter = logging::init(self_log.layer());
 
     info!("Starting flywatch log forwarder");
 
     // Load configuration
-    let config = Arc::new(Config::from_env());
+    let config_path = config_path();
+    let config_store = match Config::load(config_path.as_deref()) {
+        Ok(config) => ConfigStore::new(config, config_path.clone()),
+        Err(e) => {
+            error!(config_path = ?config_path, "{}", e);
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let config = config_store.current();
+    self_log.set_level(config.self_log_level);
     info!(
         app = %config.fly_prod_app_name,
         nats_url = %config.nats_url,
@@ -47,6 +171,7 @@ async fn main() {
 
     // Initialize metrics
     let metrics = Metrics::new();
+    metrics.set_drop_warning_percent(config.drop_warning_percent);
 
     // Create log buffer for AI access with persistence
     let log_buffer_config = LogBufferConfig {
@@ -63,20 +188,131 @@ async fn main() {
     );
 
     // Create usage tracker for AI cost persistence
-    let usage_tracker = Arc::new(UsageTracker::new(config.store_path.as_deref()));
+    let usage_tracker = Arc::new(UsageTracker::new(
+        config.store_path.as_deref(),
+        config.usage_retention_days,
+    ));
+
+    // Long-term SQLite archive behind /logs/sql (ARCHIVE_PATH)
+    let archive = match Archive::open(&config) {
+        Ok(archive) => archive,
+        Err(e) => {
+            error!(error = %e, "Failed to open the log archive");
+            eprintln!("Failed to open the log archive: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    // Per-tenant buffers, streams and usage, served under /t/<name>/
+    let tenants = Tenants::new(&config_store, &metrics);
+    if !config.tenants.is_empty() {
+        info!(
+            tenants = ?config.tenants.iter().map(|t| &t.name).collect::<Vec<_>>(),
+            "Multi-tenant mode enabled"
+        );
+    }
+
+    // Index runbooks for the chat agent in the background
+    let runbooks = RunbookIndex::new();
+    tokio::spawn({
+        let runbooks = runbooks.clone();
+        let (dir, urls) = (config.runbook_dir.clone(), config.runbook_urls.clone());
+        async move { runbooks.reindex(dir, urls).await }
+    });
+
+    // Keep OpenRouter's model prices for the cost guard and usage records
+    let pricing = PricingCatalog::new();
+    tokio::spawn(
+        pricing
+            .clone()
+            .refresh(config_store.clone(), config.openrouter_pricing_refresh_secs),
+    );
 
     // Create broadcast channel for log distribution
-    let (log_tx, _) = broadcast::channel::<LogMessage>(CHANNEL_CAPACITY);
+    let (log_tx, _) = broadcast::channel::<LogMessage>(config.channel_capacity);
+    let broadcast_monitor = BroadcastMonitor::new(log_tx.clone(), config.channel_capacity);
+    broadcast_monitor.start();
+
+    // Fan logs out into per-connection queues for streaming clients
+    let fanout = Fanout::new(
+        config.connection_queue_capacity,
+        config.connection_overflow_policy,
+        metrics.clone(),
+    );
+    fanout.set_channels(&config.channels);
+    fanout.start(log_tx.subscribe());
+
+    // Per-route traffic stats from access-log lines
+    let http_analytics = HttpAnalytics::new(config.http_analytics_window_minutes);
+    http_analytics.start(log_tx.subscribe(), metrics.clone());
+
+    // Sensitive values are scrubbed before logs are buffered or forwarded
+    let redactor = Redactor::new(redact::ingest_rules(&config));
+    let log_metrics = LogMetrics::new(&config.log_metrics);
+    let heartbeats = Heartbeats::new(&config.heartbeats);
+
+    // Build configured log sources; NATS applies the ingest rules
+    let ingest_filter = IngestFilter::new(&config.ingest_rules);
+    let sources = build_sources(&config, &metrics, &ingest_filter);
+    let source_registry = SourceRegistry::new();
+
+    // Exporters, each behind its own queue so none can stall streaming
+    let sinks = SinkRegistry::start(build_sinks(&config, archive.clone()));
+
+    // Deploy markers are ingested like any other line
+    let deploys = Deploys::new(config.store_path.as_deref());
 
     // Create app state
     let state = AppState {
-        config: config.clone(),
+        config: config_store,
         metrics: metrics.clone(),
-        log_tx: log_tx.clone(),
         log_buffer: log_buffer.clone(),
         usage_tracker,
+        sources: source_registry.clone(),
+        sinks: sinks.clone(),
+        fanout,
+        log_filter,
+        self_log: self_log.clone(),
+        broadcast: broadcast_monitor,
+        runbooks: runbooks.clone(),
+        pricing: pricing.clone(),
+        tool_audit: Arc::new(ToolAudit::new(config.store_path.as_deref())),
+        transcripts: Arc::new(Transcripts::new(config.store_path.as_deref())),
+        chat_cache: Arc::new(ChatCache::new()),
+        ingest_filter,
+        redactor: redactor.clone(),
+        log_metrics: log_metrics.clone(),
+        slos: SloTracker::new(&config.slos),
+        http_analytics,
+        heartbeats: heartbeats.clone(),
+        alerts: Alerts::new(&config),
+        tenants: tenants.clone(),
+        tenant: None,
+        session_user: None,
+        views: Views::new(config.store_path.as_deref()),
+        annotations: Annotations::new(config.store_path.as_deref()),
+        deploys: deploys.clone(),
+        mcp: McpSessions::new(),
+        used_tickets: UsedTickets::new(),
+        archive: archive.clone(),
+        shutdown: Arc::new(watch::channel(false).0),
         start_time: Instant::now(),
     };
+    chaos::apply(&state, &config);
+    // New alerts can be explained through the chat pipeline (ALERT_AI_ENRICHMENT)
+    state
+        .alerts
+        .set_enricher(Arc::new(alerts::ChatEnricher::new(state.clone())));
+    // Firing alerts can be filed as issues quoting the logs (TICKET_ALERTS)
+    state.alerts.set_log_buffer(&config, log_buffer.clone());
+
+    // Alerts and health transitions for other services (EVENTS_SUBJECT)
+    if let Some(events) = EventPublisher::connect(&config).await {
+        state
+            .alerts
+            .set_events(Arc::new(events::NatsNotifier::new(events.clone())));
+        tokio::spawn(events::health_publisher(state.clone(), events));
+    }
 
     // Spawn metrics updater
     let metrics_clone = metrics.clone();
@@ -84,15 +320,63 @@ async fn main() {
         metrics_updater(metrics_clone).await;
     });
 
-    // Spawn NATS subscriber
-    let subscriber = NatsSubscriber::new(config.clone(), metrics.clone(), log_tx, log_buffer);
-    tokio::spawn(async move {
-        subscriber.run().await;
-    });
+    // Sample log-based counters for SLO burn rates
+    tokio::spawn(slo::slo_evaluator(state.clone()));
+
+    // Alert when a watched app, instance or subject goes quiet
+    tokio::spawn(heartbeat::heartbeat_evaluator(state.clone()));
+
+    // Email digests, when SMTP_DIGEST_HOURS is set
+    tokio::spawn(digest::digest_scheduler(state.clone()));
+
+    // Spawn supervised log sources
+    let pipeline = Pipeline::new(
+        metrics.clone(),
+        log_tx,
+        log_buffer,
+        redactor,
+        log_metrics,
+        heartbeats,
+        tenants,
+    );
+    pipeline.export_to(sinks);
+    for source in sources {
+        source_registry.spawn(source, pipeline.clone()).await;
+    }
+
+    // Replicas share what reaches only one of them (CLUSTER_MODE)
+    if config.cluster_mode {
+        let cluster = Cluster::new(config.clone(), state.log_buffer.clone());
+        pipeline.replicate_to(cluster.clone());
+        source_registry
+            .spawn(Box::new(ClusterSource::new(cluster)), pipeline.clone())
+            .await;
+    }
+    deploys.spawn_forwarder(pipeline.clone());
+    self_log.spawn_forwarder(pipeline);
+
+    if let Some(addr) = config.grpc_bind_addr.clone() {
+        tokio::spawn(grpc::serve(state.clone(), addr));
+    }
+    if let Some(subject) = config.rpc_subject.clone() {
+        tokio::spawn(nats_rpc::serve(state.clone(), subject));
+    }
+
+    // Apply reload-safe settings when the config file changes
+    tokio::spawn(reload::watch_config_file(state.clone()));
 
     // Create router and start server
+    let shutdown = state.shutdown.clone();
     let app = create_router(state);
     let bind_addr = config.bind_addr();
+    let tls_config = match tls::server_config(&config) {
+        Ok(tls_config) => tls_config,
+        Err(e) => {
+            error!(error = %e, "Invalid TLS configuration");
+            eprintln!("Invalid TLS configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
 
     info!(addr = %bind_addr, "Starting HTTP server");
 
@@ -100,9 +384,70 @@ async fn main() {
         .await
         .expect("Failed to bind to address");
 
-    info!(addr = %bind_addr, "Server listening");
+    info!(
+        addr = %bind_addr,
+        tls = tls_config.is_some(),
+        client_certs = config.tls_client_ca_file.is_some(),
+        "Server listening"
+    );
 
-    axum::serve(listener, app)
-        .await
-        .expect("Server error");
+    let server = async {
+        match tls_config {
+            Some(tls_config) => {
+                tokio::spawn(shutdown_signal(shutdown.clone()));
+                tls::serve(listener, tls_config, app, shutdown.subscribe()).await;
+                Ok(())
+            }
+            None => {
+                axum::serve(
+                    listener,
+                    app.into_make_service_with_connect_info::<SocketAddr>(),
+                )
+                .with_graceful_shutdown(shutdown_signal(shutdown.clone()))
+                .await
+            }
+        }
+    };
+    let mut stopping = shutdown.subscribe();
+    tokio::select! {
+        result = server => result.expect("Server error"),
+        _ = async {
+            shutting_down(&mut stopping).await;
+            tokio::time::sleep(SHUTDOWN_TIMEOUT).await;
+        } => warn!("Shutdown timed out with connections still open"),
+    }
+    // WebSockets are detached from the server; give them time to send close frames
+    tokio::time::sleep(WS_CLOSE_GRACE).await;
+    info!("Server stopped");
+}
+
+/// Wait for ctrl-c or SIGTERM, then tell streams to close
+async fn shutdown_signal(shutdown: Arc<watch::Sender<bool>>) {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!(error = %e, "Failed to listen for ctrl-c");
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                error!(error = %e, "Failed to listen for SIGTERM");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+    info!("Shutting down");
+    shutdown.send_replace(true);
 }
@@ -0,0 +1,459 @@
+//! Planned maintenance. While a window is open, alerts it covers are not
+//! raised and digests say maintenance was in progress. Windows can repeat
+//! daily or weekly and are persisted when a store is configured.
+
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    Json,
+};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use stoar::Store;
+use tracing::error;
+use utoipa::ToSchema;
+
+use crate::alerts::RulePattern;
+use crate::error::{ApiError, ErrorBody};
+use crate::http::{check_auth, AppState};
+
+const WINDOW_COLLECTION: &str = "maintenance_windows";
+/// Longest single window
+const MAX_WINDOW_MINUTES: i64 = 7 * 24 * 60;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Recurrence {
+    #[default]
+    Once,
+    Daily,
+    Weekly,
+}
+
+impl Recurrence {
+    fn period(self) -> Option<Duration> {
+        match self {
+            Self::Once => None,
+            Self::Daily => Some(Duration::days(1)),
+            Self::Weekly => Some(Duration::weeks(1)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct MaintenanceWindow {
+    pub id: String,
+    pub name: String,
+    /// First (or only) start
+    pub starts_at: DateTime<Utc>,
+    pub duration_minutes: i64,
+    pub recurrence: Recurrence,
+    /// Alert rules it covers, e.g. "slo:checkout" or "slo:*"; all when absent
+    pub matcher: Option<String>,
+    pub comment: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl MaintenanceWindow {
+    fn duration(&self) -> Duration {
+        Duration::minutes(self.duration_minutes)
+    }
+
+    /// Start of the latest occurrence at or before `at`
+    fn latest_start(&self, at: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        if at < self.starts_at {
+            return None;
+        }
+        let Some(period) = self.recurrence.period() else {
+            return Some(self.starts_at);
+        };
+        let occurrences = (at - self.starts_at).num_seconds() / period.num_seconds();
+        Some(self.starts_at + period * occurrences as i32)
+    }
+
+    /// End of the occurrence open at `at`, if any
+    pub fn open_until(&self, at: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.latest_start(at)
+            .map(|start| start + self.duration())
+            .filter(|end| at < *end)
+    }
+
+    /// Whether an occurrence overlapped `since..until`
+    pub fn overlaps(&self, since: DateTime<Utc>, until: DateTime<Utc>) -> bool {
+        self.latest_start(until)
+            .is_some_and(|start| start + self.duration() > since)
+    }
+
+    fn next_start(&self, at: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match (self.latest_start(at), self.recurrence.period()) {
+            (None, _) => Some(self.starts_at),
+            (Some(start), Some(period)) => Some(start + period),
+            (Some(_), None) => None,
+        }
+    }
+
+    fn covers(&self, rule: &str, key: &str) -> bool {
+        match self.matcher {
+            Some(ref matcher) => matcher
+                .parse::<RulePattern>()
+                .is_ok_and(|pattern| pattern.matches_key(rule, key)),
+            None => true,
+        }
+    }
+}
+
+/// A window with its current state, as listed by the API
+#[derive(Debug, Serialize, ToSchema)]
+pub struct MaintenanceStatus {
+    #[serde(flatten)]
+    pub window: MaintenanceWindow,
+    /// End of the open occurrence, while one is open
+    pub open_until: Option<DateTime<Utc>>,
+    pub next_start: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct MaintenanceRequest {
+    pub name: String,
+    pub starts_at: DateTime<Utc>,
+    /// Up to 7 days, and no longer than the recurrence period
+    pub duration_minutes: i64,
+    #[serde(default)]
+    pub recurrence: Recurrence,
+    pub matcher: Option<String>,
+    pub comment: Option<String>,
+}
+
+impl MaintenanceRequest {
+    fn into_window(
+        self,
+        id: String,
+        created_at: DateTime<Utc>,
+    ) -> Result<MaintenanceWindow, String> {
+        if self.name.trim().is_empty() {
+            return Err("name is required".to_string());
+        }
+        let longest = self.recurrence.period().map_or(MAX_WINDOW_MINUTES, |p| {
+            p.num_minutes().min(MAX_WINDOW_MINUTES)
+        });
+        if !(1..=longest).contains(&self.duration_minutes) {
+            return Err(format!(
+                "duration_minutes must be between 1 and {}",
+                longest
+            ));
+        }
+        if let Some(ref matcher) = self.matcher {
+            matcher.parse::<RulePattern>()?;
+        }
+        Ok(MaintenanceWindow {
+            id,
+            name: self.name,
+            starts_at: self.starts_at,
+            duration_minutes: self.duration_minutes,
+            recurrence: self.recurrence,
+            matcher: self.matcher,
+            comment: self.comment,
+            created_at,
+        })
+    }
+}
+
+/// Scheduled maintenance windows
+pub struct Maintenance {
+    windows: Mutex<Vec<MaintenanceWindow>>,
+    store: Option<Store>,
+}
+
+impl Maintenance {
+    pub fn new(store_path: Option<&str>) -> Self {
+        let store = store_path.and_then(|path| match Store::open(path) {
+            Ok(s) => Some(s),
+            Err(e) => {
+                error!(error = %e, path = %path, "Failed to open maintenance store, keeping windows in memory");
+                None
+            }
+        });
+        let windows = store
+            .as_ref()
+            .map(|store| {
+                store.all(WINDOW_COLLECTION).unwrap_or_else(|e| {
+                    error!(error = %e, "Failed to load maintenance windows");
+                    Vec::new()
+                })
+            })
+            .unwrap_or_default();
+        Self {
+            windows: Mutex::new(windows),
+            store,
+        }
+    }
+
+    fn persist(&self, window: &MaintenanceWindow) {
+        if let Some(ref store) = self.store {
+            if let Err(e) = store.put(WINDOW_COLLECTION, &window.id, window) {
+                error!(error = %e, "Failed to persist maintenance window");
+            }
+        }
+    }
+
+    /// The open window covering an alert, if any
+    pub fn covering(&self, rule: &str, key: &str, now: DateTime<Utc>) -> Option<MaintenanceWindow> {
+        self.windows
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|w| w.open_until(now).is_some() && w.covers(rule, key))
+            .cloned()
+    }
+
+    /// Windows that were open at some point in `since..until`
+    pub fn overlapping(
+        &self,
+        since: DateTime<Utc>,
+        until: DateTime<Utc>,
+    ) -> Vec<MaintenanceWindow> {
+        self.windows
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|w| w.overlaps(since, until))
+            .cloned()
+            .collect()
+    }
+
+    pub fn list(&self) -> Vec<MaintenanceStatus> {
+        let now = Utc::now();
+        let mut windows: Vec<MaintenanceStatus> = self
+            .windows
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|w| MaintenanceStatus {
+                window: w.clone(),
+                open_until: w.open_until(now),
+                next_start: w.next_start(now),
+            })
+            .collect();
+        windows.sort_by_key(|s| s.open_until.or(s.next_start).unwrap_or(s.window.starts_at));
+        windows
+    }
+
+    pub fn create(&self, request: MaintenanceRequest) -> Result<MaintenanceWindow, String> {
+        let window = request.into_window(uuid::Uuid::new_v4().to_string(), Utc::now())?;
+        self.persist(&window);
+        self.windows.lock().unwrap().push(window.clone());
+        Ok(window)
+    }
+
+    /// Replace a window; Ok(None) when there is no such window
+    pub fn update(
+        &self,
+        id: &str,
+        request: MaintenanceRequest,
+    ) -> Result<Option<MaintenanceWindow>, String> {
+        let mut windows = self.windows.lock().unwrap();
+        let Some(existing) = windows.iter_mut().find(|w| w.id == id) else {
+            return Ok(None);
+        };
+        *existing = request.into_window(existing.id.clone(), existing.created_at)?;
+        self.persist(existing);
+        Ok(Some(existing.clone()))
+    }
+
+    pub fn delete(&self, id: &str) -> bool {
+        let mut windows = self.windows.lock().unwrap();
+        let before = windows.len();
+        windows.retain(|w| w.id != id);
+        if let Some(ref store) = self.store {
+            let _ = store.delete(WINDOW_COLLECTION, id);
+        }
+        windows.len() != before
+    }
+}
+
+/// GET /maintenance - scheduled windows, open or soonest first
+#[utoipa::path(
+    get, path = "/maintenance", tag = "alerts",
+    responses(
+        (status = 200, description = "Maintenance windows", body = Vec<MaintenanceStatus>),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+pub async fn list_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<MaintenanceStatus>>, ApiError> {
+    check_auth(&state, &headers)?;
+    Ok(Json(state.alerts.maintenance().list()))
+}
+
+/// POST /maintenance - schedule a window
+#[utoipa::path(
+    post, path = "/maintenance", tag = "alerts",
+    request_body = MaintenanceRequest,
+    responses(
+        (status = 201, description = "Window scheduled", body = MaintenanceWindow),
+        (status = 400, description = "Invalid window", body = ErrorBody),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+pub async fn create_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<MaintenanceRequest>,
+) -> Result<(StatusCode, Json<MaintenanceWindow>), ApiError> {
+    check_auth(&state, &headers)?;
+    let window = state
+        .alerts
+        .maintenance()
+        .create(request)
+        .map_err(ApiError::InvalidRequest)?;
+    Ok((StatusCode::CREATED, Json(window)))
+}
+
+/// PUT /maintenance/{id} - replace a window
+#[utoipa::path(
+    put, path = "/maintenance/{id}", tag = "alerts",
+    params(("id" = String, Path, description = "Window id")),
+    request_body = MaintenanceRequest,
+    responses(
+        (status = 200, description = "Window updated", body = MaintenanceWindow),
+        (status = 400, description = "Invalid window", body = ErrorBody),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+        (status = 404, description = "No such window", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+pub async fn update_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    Json(request): Json<MaintenanceRequest>,
+) -> Result<Json<MaintenanceWindow>, ApiError> {
+    check_auth(&state, &headers)?;
+    state
+        .alerts
+        .maintenance()
+        .update(&id, request)
+        .map_err(ApiError::InvalidRequest)?
+        .map(Json)
+        .ok_or_else(|| ApiError::NotFound("Maintenance window not found".to_string()))
+}
+
+/// DELETE /maintenance/{id} - cancel a window
+#[utoipa::path(
+    delete, path = "/maintenance/{id}", tag = "alerts",
+    params(("id" = String, Path, description = "Window id")),
+    responses(
+        (status = 204, description = "Window cancelled"),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+        (status = 404, description = "No such window", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+pub async fn delete_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    check_auth(&state, &headers)?;
+    if state.alerts.maintenance().delete(&id) {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(ApiError::NotFound(
+            "Maintenance window not found".to_string(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alerts::Severity;
+
+    fn request(
+        starts_at: DateTime<Utc>,
+        minutes: i64,
+        recurrence: Recurrence,
+    ) -> MaintenanceRequest {
+        MaintenanceRequest {
+            name: "deploy".to_string(),
+            starts_at,
+            duration_minutes: minutes,
+            recurrence,
+            matcher: Some("slo:checkout".to_string()),
+            comment: None,
+        }
+    }
+
+    #[test]
+    fn test_recurring_window_opens_each_day() {
+        let maintenance = Maintenance::new(None);
+        let start = Utc::now() - Duration::days(3) - Duration::minutes(10);
+        let window = maintenance
+            .create(request(start, 30, Recurrence::Daily))
+            .unwrap();
+
+        let now = Utc::now();
+        assert_eq!(
+            window.open_until(now),
+            Some(start + Duration::days(3) + Duration::minutes(30))
+        );
+        assert!(maintenance
+            .covering("slo:checkout", "slo:checkout:burn_rate", now)
+            .is_some());
+        assert!(maintenance
+            .covering("slo:api", "slo:api:burn_rate", now)
+            .is_none());
+        assert!(window.open_until(now + Duration::hours(1)).is_none());
+        assert!(window.overlaps(now + Duration::hours(1), now + Duration::days(1)));
+
+        // A daily window can't be longer than a day
+        assert!(maintenance
+            .create(request(start, 25 * 60, Recurrence::Daily))
+            .is_err());
+        assert!(maintenance.delete(&window.id));
+        assert!(maintenance.list().is_empty());
+    }
+
+    #[test]
+    fn test_open_window_suppresses_alerts() {
+        let alerts = crate::alerts::Alerts::new(&crate::config::Config::for_tests(""));
+        let window = alerts
+            .maintenance()
+            .create(request(
+                Utc::now() - Duration::minutes(5),
+                60,
+                Recurrence::Once,
+            ))
+            .unwrap();
+
+        alerts.fire(
+            "slo:checkout",
+            "burn_rate",
+            Severity::Critical,
+            "fast burn".to_string(),
+        );
+        alerts.fire(
+            "slo:api",
+            "burn_rate",
+            Severity::Critical,
+            "fast burn".to_string(),
+        );
+        let active = alerts.active();
+        assert_eq!(active.len(), 1);
+        assert_eq!(active[0].rule, "slo:api");
+
+        alerts.maintenance().delete(&window.id);
+        alerts.fire(
+            "slo:checkout",
+            "burn_rate",
+            Severity::Critical,
+            "fast burn".to_string(),
+        );
+        assert_eq!(alerts.active().len(), 2);
+    }
+}
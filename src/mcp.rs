@@ -0,0 +1,432 @@
+//! Model Context Protocol server, so MCP clients (Claude Desktop, IDE agents)
+//! can attach flywatch as a data source independently of the built-in chat.
+//! The chat agent's tools are offered as MCP tools, and the buffer summary,
+//! recent lines and metrics as read-only resources. Text is scrubbed like
+//! chat context when CHAT_PII_SAFE is on.
+//!
+//! Transports:
+//! - HTTP+SSE: `GET /mcp/sse` announces `/mcp/messages?session_id=...`,
+//!   where the client POSTs requests; responses arrive on the stream
+//! - plain HTTP: `POST /mcp` answers each JSON-RPC request in the response
+//! - stdio: `flywatch mcp` relays stdin/stdout to a running server's
+//!   `POST /mcp` (see cli), for clients that spawn servers
+
+use axum::{
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    Json,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::sync::mpsc;
+use tracing::info;
+use utoipa::IntoParams;
+use uuid::Uuid;
+
+use crate::audit::ToolCallEvent;
+use crate::chat::{allowed_tools, execute_tool, get_tools, scrub_for_provider};
+use crate::error::{ApiError, ErrorBody};
+use crate::http::{check_auth, full_snapshot, AppState};
+use crate::redact;
+use crate::tls;
+
+/// Protocol revisions understood; the first is offered by default
+const PROTOCOL_VERSIONS: [&str; 2] = ["2024-11-05", "2025-03-26"];
+/// Responses waiting to be written to one SSE session
+const SESSION_QUEUE: usize = 32;
+/// Lines in the recent-logs resource
+const RECENT_LINES: usize = 100;
+
+// JSON-RPC error codes
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const INTERNAL_ERROR: i64 = -32603;
+
+const RESOURCES: [(&str, &str, &str); 3] = [
+    (
+        "flywatch://buffer/summary",
+        "Buffer summary",
+        "Buffered line counts by level and instance, oldest and newest timestamps",
+    ),
+    (
+        "flywatch://logs/recent",
+        "Recent logs",
+        "The last 100 buffered lines",
+    ),
+    (
+        "flywatch://metrics",
+        "Metrics",
+        "Forwarding, connection, source and sink metrics",
+    ),
+];
+
+/// Open SSE sessions by id
+#[derive(Default)]
+pub struct McpSessions {
+    sessions: Mutex<HashMap<Uuid, mpsc::Sender<Value>>>,
+}
+
+impl McpSessions {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    fn open(self: &Arc<Self>) -> (SessionGuard, mpsc::Receiver<Value>) {
+        let (tx, rx) = mpsc::channel(SESSION_QUEUE);
+        let id = Uuid::new_v4();
+        self.sessions.lock().unwrap().insert(id, tx);
+        let guard = SessionGuard {
+            id,
+            sessions: self.clone(),
+        };
+        (guard, rx)
+    }
+
+    fn sender(&self, id: &Uuid) -> Option<mpsc::Sender<Value>> {
+        self.sessions.lock().unwrap().get(id).cloned()
+    }
+}
+
+/// Forgets the session when its stream is dropped
+struct SessionGuard {
+    id: Uuid,
+    sessions: Arc<McpSessions>,
+}
+
+impl Drop for SessionGuard {
+    fn drop(&mut self) {
+        self.sessions.sessions.lock().unwrap().remove(&self.id);
+    }
+}
+
+fn rpc_error(id: Value, code: i64, message: impl Into<String>) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": code, "message": message.into() },
+    })
+}
+
+/// Answer one JSON-RPC message; notifications get no answer
+async fn dispatch(state: &AppState, session: &str, message: Value) -> Option<Value> {
+    let Some(method) = message.get("method").and_then(Value::as_str) else {
+        return Some(rpc_error(
+            Value::Null,
+            INVALID_REQUEST,
+            "Expected a JSON-RPC request",
+        ));
+    };
+    // Notifications (no id), e.g. notifications/initialized, need no reply
+    let id = message.get("id").cloned()?;
+    let params = message.get("params").cloned().unwrap_or(Value::Null);
+    let result = match method {
+        "initialize" => Ok(initialize(&params)),
+        "ping" => Ok(json!({})),
+        "tools/list" => Ok(list_tools(state)),
+        "tools/call" => call_tool(state, session, &params).await,
+        "resources/list" => Ok(list_resources()),
+        "resources/read" => read_resource(state, &params).await,
+        _ => Err((METHOD_NOT_FOUND, format!("Unknown method '{}'", method))),
+    };
+    Some(match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err((code, message)) => rpc_error(id, code, message),
+    })
+}
+
+fn initialize(params: &Value) -> Value {
+    let requested = params.get("protocolVersion").and_then(Value::as_str);
+    let version = requested
+        .filter(|v| PROTOCOL_VERSIONS.contains(v))
+        .unwrap_or(PROTOCOL_VERSIONS[0]);
+    json!({
+        "protocolVersion": version,
+        "capabilities": { "tools": {}, "resources": {} },
+        "serverInfo": { "name": "flywatch", "version": env!("CARGO_PKG_VERSION") },
+        "instructions": "Logs, metrics and traffic of a Fly.io app. Read flywatch://buffer/summary first, then fetch lines with get_logs.",
+    })
+}
+
+fn list_tools(state: &AppState) -> Value {
+    let tools: Vec<Value> = allowed_tools(state)
+        .into_iter()
+        .map(|tool| {
+            json!({
+                "name": tool.function.name,
+                "description": tool.function.description,
+                "inputSchema": tool.function.parameters,
+            })
+        })
+        .collect();
+    json!({ "tools": tools })
+}
+
+async fn call_tool(
+    state: &AppState,
+    session: &str,
+    params: &Value,
+) -> Result<Value, (i64, String)> {
+    let Some(name) = params.get("name").and_then(Value::as_str) else {
+        return Err((INVALID_PARAMS, "params.name is required".to_string()));
+    };
+    let known = get_tools(!state.runbooks.is_empty(), &state.views.names())
+        .iter()
+        .any(|tool| tool.function.name == name);
+    if !known {
+        return Err((INVALID_PARAMS, format!("Unknown tool '{}'", name)));
+    }
+    let arguments = params
+        .get("arguments")
+        .cloned()
+        .unwrap_or_else(|| json!({}))
+        .to_string();
+
+    info!(tool = %name, session = %session, "MCP tool call");
+    let started = Instant::now();
+    let result = execute_tool(name, &arguments, state).await;
+    let principal = tls::current_principal();
+    state.tool_audit.record(ToolCallEvent {
+        conversation_id: session,
+        request_id: None,
+        principal: principal.as_deref(),
+        tool: name,
+        arguments: &arguments,
+        result: &result,
+        duration_ms: started.elapsed().as_millis() as u64,
+    });
+    let (text, is_error) = match result {
+        Ok(text) => (text, false),
+        Err(e) => (format!("Error: {}", e), true),
+    };
+    Ok(json!({
+        "content": [{ "type": "text", "text": scrub(state, text) }],
+        "isError": is_error,
+    }))
+}
+
+fn list_resources() -> Value {
+    let resources: Vec<Value> = RESOURCES
+        .iter()
+        .map(|(uri, name, description)| {
+            json!({
+                "uri": uri,
+                "name": name,
+                "description": description,
+                "mimeType": "application/json",
+            })
+        })
+        .collect();
+    json!({ "resources": resources })
+}
+
+async fn read_resource(state: &AppState, params: &Value) -> Result<Value, (i64, String)> {
+    let uri = params
+        .get("uri")
+        .and_then(Value::as_str)
+        .unwrap_or_default();
+    let contents = match uri {
+        "flywatch://buffer/summary" => serde_json::to_string(&state.log_buffer.get_summary().await),
+        "flywatch://logs/recent" => {
+            serde_json::to_string(&state.log_buffer.get_last_n(RECENT_LINES).await)
+        }
+        "flywatch://metrics" => serde_json::to_string(&full_snapshot(state).await),
+        _ => return Err((INVALID_PARAMS, format!("Unknown resource '{}'", uri))),
+    }
+    .map_err(|e| (INTERNAL_ERROR, e.to_string()))?;
+    Ok(json!({
+        "contents": [{
+            "uri": uri,
+            "mimeType": "application/json",
+            "text": scrub(state, contents),
+        }],
+    }))
+}
+
+/// Mask identifiers when PII-safe mode is on, as for the chat provider
+fn scrub(state: &AppState, text: String) -> String {
+    let rules = redact::chat_rules(&state.config.current());
+    scrub_for_provider(rules.as_ref(), text)
+}
+
+/// POST /mcp - one JSON-RPC message, answered in the response
+#[utoipa::path(
+    post, path = "/mcp", tag = "chat",
+    request_body(content = Object, description = "JSON-RPC 2.0 request or notification"),
+    responses(
+        (status = 200, description = "JSON-RPC response", body = Object),
+        (status = 202, description = "Notification accepted"),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+pub async fn rpc_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: String,
+) -> Result<Response, ApiError> {
+    check_auth(&state, &headers)?;
+    let state = state.for_caller(&headers);
+    let message = match serde_json::from_str::<Value>(&body) {
+        Ok(message) => message,
+        Err(e) => {
+            return Ok(Json(rpc_error(Value::Null, PARSE_ERROR, e.to_string())).into_response())
+        }
+    };
+    Ok(match dispatch(&state, "mcp", message).await {
+        Some(response) => Json(response).into_response(),
+        None => StatusCode::ACCEPTED.into_response(),
+    })
+}
+
+/// GET /mcp/sse - open an MCP session over server-sent events
+#[utoipa::path(
+    get, path = "/mcp/sse", tag = "chat",
+    responses(
+        (status = 200, description = "An `endpoint` event naming where to POST requests, then a `message` event per response", content_type = "text/event-stream", body = String),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+pub async fn sse_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    check_auth(&state, &headers)?;
+    let (guard, mut rx) = state.mcp.open();
+    info!(session = %guard.id, "MCP session opened");
+    let stream = async_stream::stream! {
+        let endpoint = format!("/mcp/messages?session_id={}", guard.id);
+        yield Ok::<_, Infallible>(Event::default().event("endpoint").data(endpoint));
+        while let Some(message) = rx.recv().await {
+            yield Ok(Event::default().event("message").data(message.to_string()));
+        }
+        drop(guard);
+    };
+    Ok(Sse::new(stream)
+        .keep_alive(KeepAlive::new().interval(Duration::from_secs(15)))
+        .into_response())
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct SessionQuery {
+    /// From the session's `endpoint` event
+    pub session_id: Uuid,
+}
+
+/// POST /mcp/messages - a request for an open SSE session
+#[utoipa::path(
+    post, path = "/mcp/messages", tag = "chat",
+    params(SessionQuery),
+    request_body(content = Object, description = "JSON-RPC 2.0 request or notification"),
+    responses(
+        (status = 202, description = "Accepted; the response arrives on the session's stream"),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+        (status = 404, description = "No such session", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+pub async fn message_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<SessionQuery>,
+    body: String,
+) -> Result<StatusCode, ApiError> {
+    check_auth(&state, &headers)?;
+    let state = state.for_caller(&headers);
+    let sender = state
+        .mcp
+        .sender(&query.session_id)
+        .ok_or_else(|| ApiError::NotFound("MCP session not found".to_string()))?;
+    let response = match serde_json::from_str::<Value>(&body) {
+        Ok(message) => dispatch(&state, &query.session_id.to_string(), message).await,
+        Err(e) => Some(rpc_error(Value::Null, PARSE_ERROR, e.to_string())),
+    };
+    if let Some(response) = response {
+        let _ = sender.send(response).await;
+    }
+    Ok(StatusCode::ACCEPTED)
+}
+
+/// `flywatch mcp`: relay JSON-RPC lines between stdin/stdout and a running
+/// server, reporting transport failures as JSON-RPC errors
+pub async fn run_stdio(url: &str, token: Option<&str>) {
+    let endpoint = format!("{}/mcp", url);
+    let client = reqwest::Client::new();
+    eprintln!("flywatch mcp: relaying to {}", endpoint);
+
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    let mut stdout = tokio::io::stdout();
+    while let Ok(Some(line)) = lines.next_line().await {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let mut request = client
+            .post(&endpoint)
+            .header("Content-Type", "application/json")
+            .body(line.clone());
+        if let Some(token) = token {
+            request = request.bearer_auth(token);
+        }
+        let reply = match request.send().await {
+            Ok(response) if response.status() == StatusCode::ACCEPTED => continue,
+            Ok(response) if response.status().is_success() => {
+                response.text().await.map_err(|e| e.to_string())
+            }
+            Ok(response) => Err(format!("{} returned {}", endpoint, response.status())),
+            Err(e) => Err(e.to_string()),
+        };
+        let reply = match reply {
+            Ok(reply) => reply,
+            Err(e) => {
+                eprintln!("flywatch mcp: {}", e);
+                // Only requests (with an id) expect an answer
+                let id = serde_json::from_str::<Value>(&line)
+                    .ok()
+                    .and_then(|m| m.get("id").cloned());
+                let Some(id) = id else { continue };
+                rpc_error(id, INTERNAL_ERROR, e).to_string()
+            }
+        };
+        let reply = reply.replace('\n', "");
+        if stdout
+            .write_all(format!("{}\n", reply).as_bytes())
+            .await
+            .is_err()
+            || stdout.flush().await.is_err()
+        {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_initialize_negotiates_version() {
+        let result = initialize(&json!({ "protocolVersion": "2025-03-26" }));
+        assert_eq!(result["protocolVersion"], "2025-03-26");
+        let result = initialize(&json!({ "protocolVersion": "1999-01-01" }));
+        assert_eq!(result["protocolVersion"], PROTOCOL_VERSIONS[0]);
+        assert_eq!(result["serverInfo"]["name"], "flywatch");
+
+        let sessions = McpSessions::new();
+        let (guard, _rx) = sessions.open();
+        let id = guard.id;
+        assert!(sessions.sender(&id).is_some());
+        drop(guard);
+        assert!(sessions.sender(&id).is_none());
+    }
+}
@@ -1,17 +1,38 @@
 use serde::Serialize;
+use std::collections::{BTreeMap, VecDeque};
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use sysinfo::System;
 use tokio::sync::RwLock;
+use tracing::{info, warn};
+use utoipa::ToSchema;
+
+use crate::fanout::QueueStats;
+use crate::log_metrics::LogMetricSnapshot;
+use crate::sink::SinkHealthSnapshot;
+use crate::source::SourceHealthSnapshot;
+
+const DEFAULT_DROP_WARNING_PERCENT: f64 = 5.0;
+/// Distinct region/instance pairs tracked; later ones count as "other"
+const MAX_ORIGINS: usize = 500;
+const UNKNOWN_ORIGIN: &str = "unknown";
+const OTHER_INSTANCE: &str = "other";
+/// Recent NATS errors kept for `/debug/nats`
+const NATS_ERROR_HISTORY: usize = 50;
 
 #[derive(Debug, Default)]
 pub struct Metrics {
     // Connection state
     nats_connected: AtomicBool,
+    nats_ping: Mutex<NatsPing>,
+    nats_errors: Mutex<VecDeque<NatsError>>,
 
     // Counters
     subscription_errors: AtomicU64,
     messages_forwarded: AtomicU64,
+    messages_filtered: AtomicU64,
+    lines_truncated: AtomicU64,
     sse_connections_total: AtomicU64,
     ws_connections_total: AtomicU64,
 
@@ -19,11 +40,93 @@ pub struct Metrics {
     active_sse_connections: AtomicU64,
     active_ws_connections: AtomicU64,
 
+    // Broadcast delivery per consumer class ("fanout", "sse", "websocket")
+    consumers: Mutex<BTreeMap<&'static str, ConsumerCounters>>,
+    drop_warning_percent: Mutex<f64>,
+
+    // Ingested lines per (region, instance) parsed from the logs
+    origins: Mutex<BTreeMap<(String, String), OriginCounters>>,
+
     // System info (updated periodically)
     system: RwLock<Option<SystemMetrics>>,
+    system_updated_at: Mutex<Option<Instant>>,
+}
+
+/// Delivery counters for one class of broadcast consumer
+#[derive(Debug, Default)]
+struct ConsumerCounters {
+    messages: u64,
+    dropped: u64,
+    /// Totals at the previous evaluation, for the recent drop rate
+    window_messages: u64,
+    window_dropped: u64,
+    recent_drop_percent: f64,
+    warning: bool,
+}
+
+/// Results of the NATS round-trip probe
+#[derive(Debug, Default)]
+struct NatsPing {
+    rtt: Option<Duration>,
+    last_ok_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// Probes failed since the last one that succeeded
+    consecutive_failures: u64,
+    failures: u64,
+}
+
+/// One NATS failure, with what was going on when it happened
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct NatsError {
+    pub at: chrono::DateTime<chrono::Utc>,
+    /// `connect`, `subscription`, `disconnect`, `client` (an error reported
+    /// by the NATS client or server) or `probe`
+    pub kind: &'static str,
+    pub error: String,
+}
+
+/// NATS connection state with its recent errors, newest first
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct NatsDebug {
+    pub connected: bool,
+    pub rtt_ms: Option<f64>,
+    pub last_ping_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub ping_failures: u64,
+    pub subscription_errors: u64,
+    pub errors: Vec<NatsError>,
+}
+
+#[derive(Debug, Default)]
+struct OriginCounters {
+    messages: u64,
+    errors: u64,
+}
+
+/// Lines ingested from one region/instance
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct OriginStats {
+    /// Fly region, or "unknown" when the line carries none
+    pub region: String,
+    /// Machine ID, "unknown", or "other" once too many are tracked
+    pub instance: String,
+    pub messages: u64,
+    /// Lines at error level
+    pub errors: u64,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ConsumerDropStats {
+    pub consumer: &'static str,
+    /// Messages offered to consumers of this class
+    pub messages: u64,
+    /// Messages lost to lag or a full queue
+    pub dropped: u64,
+    /// Share dropped over the last metrics update interval
+    pub recent_drop_percent: f64,
+    /// Whether the recent share is above the configured threshold
+    pub warning: bool,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, ToSchema)]
 pub struct SystemMetrics {
     pub cpu_usage_percent: f32,
     pub memory_used_bytes: u64,
@@ -32,7 +135,7 @@ pub struct SystemMetrics {
     pub uptime_seconds: u64,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, ToSchema)]
 pub struct MetricsSnapshot {
     // Timestamps
     pub timestamp: chrono::DateTime<chrono::Utc>,
@@ -42,6 +145,15 @@ pub struct MetricsSnapshot {
     pub nats_connected: bool,
     pub subscription_errors: u64,
     pub messages_forwarded: u64,
+    /// NATS messages dropped or sampled out by the ingest rules
+    pub messages_filtered: u64,
+    /// NATS messages cut down to `MAX_LINE_BYTES`
+    pub lines_truncated: u64,
+    /// Round trip of the last successful NATS ping
+    pub nats_rtt_ms: Option<f64>,
+    pub nats_last_ping_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// NATS pings that timed out or failed
+    pub nats_ping_failures: u64,
 
     // Connections
     pub sse_connections_total: u64,
@@ -49,23 +161,56 @@ pub struct MetricsSnapshot {
     pub active_sse_connections: u64,
     pub active_ws_connections: u64,
 
+    // Per-connection stream queues (filled in by the HTTP layer)
+    pub connection_queues: Vec<QueueStats>,
+
+    // Broadcast drops per consumer class
+    pub consumer_drops: Vec<ConsumerDropStats>,
+    /// Any consumer class is dropping more than the threshold
+    pub drop_warning: bool,
+
+    // Ingested lines and errors per region and instance
+    pub origins: Vec<OriginStats>,
+
+    // Supervised log sources: restarts, panics and last error (filled in by the HTTP layer)
+    pub sources: Vec<SourceHealthSnapshot>,
+
+    // Exporters: queue depth, deliveries, retries and dead letters (filled in by the HTTP layer)
+    pub sinks: Vec<SinkHealthSnapshot>,
+
+    // Counters and histograms derived from log content (filled in by the HTTP layer)
+    pub log_metrics: Vec<LogMetricSnapshot>,
+
     // System
     #[serde(skip_serializing_if = "Option::is_none")]
     pub system: Option<SystemMetrics>,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, ToSchema)]
 pub struct HealthStatus {
     pub status: &'static str,
     pub nats_connected: bool,
     pub active_connections: u64,
     pub messages_forwarded: u64,
     pub uptime_seconds: u64,
+    /// Any consumer class is dropping more than the threshold
+    pub drop_warning: bool,
+    /// Active tracing filter (filled in by the HTTP layer)
+    pub log_filter: String,
+    /// Supervised log sources (filled in by the HTTP layer)
+    pub sources: Vec<SourceHealthSnapshot>,
+    /// Exporters (filled in by the HTTP layer)
+    pub sinks: Vec<SinkHealthSnapshot>,
+    /// Most recent NATS error; `/debug/nats` has the rest
+    pub last_nats_error: Option<NatsError>,
 }
 
 impl Metrics {
     pub fn new() -> Arc<Self> {
-        Arc::new(Self::default())
+        Arc::new(Self {
+            drop_warning_percent: Mutex::new(DEFAULT_DROP_WARNING_PERCENT),
+            ..Self::default()
+        })
     }
 
     // NATS connection state
@@ -77,15 +222,68 @@ impl Metrics {
         self.nats_connected.load(Ordering::SeqCst)
     }
 
+    pub fn record_nats_ping(&self, rtt: Duration) {
+        let mut ping = self.nats_ping.lock().unwrap();
+        ping.rtt = Some(rtt);
+        ping.last_ok_at = Some(chrono::Utc::now());
+        ping.consecutive_failures = 0;
+    }
+
+    pub fn record_nats_ping_failure(&self) {
+        let mut ping = self.nats_ping.lock().unwrap();
+        ping.consecutive_failures += 1;
+        ping.failures += 1;
+    }
+
+    /// Pings failed in a row; a connection that stays up while these
+    /// accumulate is stalled rather than quiet
+    pub fn nats_ping_failures_in_a_row(&self) -> u64 {
+        self.nats_ping.lock().unwrap().consecutive_failures
+    }
+
     // Error counters
-    pub fn increment_subscription_errors(&self) {
+    /// Count a connect or subscription failure and keep what it was
+    pub fn record_subscription_error(&self, kind: &'static str, error: &impl ToString) {
         self.subscription_errors.fetch_add(1, Ordering::SeqCst);
+        self.record_nats_error(kind, error);
+    }
+
+    pub fn record_nats_error(&self, kind: &'static str, error: &impl ToString) {
+        let mut errors = self.nats_errors.lock().unwrap();
+        if errors.len() >= NATS_ERROR_HISTORY {
+            errors.pop_front();
+        }
+        errors.push_back(NatsError {
+            at: chrono::Utc::now(),
+            kind,
+            error: error.to_string(),
+        });
+    }
+
+    pub fn nats_debug(&self) -> NatsDebug {
+        let ping = self.nats_ping.lock().unwrap();
+        NatsDebug {
+            connected: self.nats_connected.load(Ordering::SeqCst),
+            rtt_ms: ping.rtt.map(|rtt| rtt.as_secs_f64() * 1000.0),
+            last_ping_at: ping.last_ok_at,
+            ping_failures: ping.failures,
+            subscription_errors: self.subscription_errors.load(Ordering::SeqCst),
+            errors: self.nats_errors.lock().unwrap().iter().rev().cloned().collect(),
+        }
     }
 
     pub fn increment_messages_forwarded(&self) {
         self.messages_forwarded.fetch_add(1, Ordering::SeqCst);
     }
 
+    pub fn increment_messages_filtered(&self) {
+        self.messages_filtered.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub fn increment_lines_truncated(&self) {
+        self.lines_truncated.fetch_add(1, Ordering::SeqCst);
+    }
+
     // SSE connection tracking
     pub fn increment_sse_connections(&self) {
         self.sse_connections_total.fetch_add(1, Ordering::SeqCst);
@@ -106,6 +304,100 @@ impl Metrics {
         self.active_ws_connections.fetch_sub(1, Ordering::SeqCst);
     }
 
+    // Broadcast consumer drop accounting
+    pub fn record_consumer(&self, consumer: &'static str, messages: u64, dropped: u64) {
+        let mut consumers = self.consumers.lock().unwrap();
+        let counters = consumers.entry(consumer).or_default();
+        counters.messages += messages;
+        counters.dropped += dropped;
+    }
+
+    // Per-region/instance accounting
+    pub fn record_origin(&self, region: Option<&str>, instance: Option<&str>, error: bool) {
+        let region = region.unwrap_or(UNKNOWN_ORIGIN);
+        let instance = instance.unwrap_or(UNKNOWN_ORIGIN);
+        let mut origins = self.origins.lock().unwrap();
+        let key = (region.to_string(), instance.to_string());
+        let key = if origins.len() >= MAX_ORIGINS && !origins.contains_key(&key) {
+            (region.to_string(), OTHER_INSTANCE.to_string())
+        } else {
+            key
+        };
+        let counters = origins.entry(key).or_default();
+        counters.messages += 1;
+        if error {
+            counters.errors += 1;
+        }
+    }
+
+    fn origins(&self) -> Vec<OriginStats> {
+        self.origins
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|((region, instance), c)| OriginStats {
+                region: region.clone(),
+                instance: instance.clone(),
+                messages: c.messages,
+                errors: c.errors,
+            })
+            .collect()
+    }
+
+    pub fn set_drop_warning_percent(&self, percent: f64) {
+        *self.drop_warning_percent.lock().unwrap() = percent;
+    }
+
+    /// Recompute each class's drop share since the previous call, logging
+    /// when a class crosses the warning threshold in either direction
+    pub fn evaluate_drop_rates(&self) {
+        let threshold = *self.drop_warning_percent.lock().unwrap();
+        let mut consumers = self.consumers.lock().unwrap();
+        for (consumer, counters) in consumers.iter_mut() {
+            let messages = counters.messages - counters.window_messages;
+            let dropped = counters.dropped - counters.window_dropped;
+            counters.window_messages = counters.messages;
+            counters.window_dropped = counters.dropped;
+
+            counters.recent_drop_percent = if messages > 0 {
+                dropped as f64 * 100.0 / messages as f64
+            } else {
+                0.0
+            };
+            let warning = counters.recent_drop_percent > threshold;
+            if warning && !counters.warning {
+                warn!(
+                    consumer,
+                    drop_percent = counters.recent_drop_percent,
+                    threshold,
+                    "Consumer class is dropping log messages"
+                );
+            } else if !warning && counters.warning {
+                info!(consumer, "Consumer class drop rate back under threshold");
+            }
+            counters.warning = warning;
+        }
+    }
+
+    fn consumer_drops(&self) -> Vec<ConsumerDropStats> {
+        self.consumers
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(consumer, c)| ConsumerDropStats {
+                consumer,
+                messages: c.messages,
+                dropped: c.dropped,
+                recent_drop_percent: c.recent_drop_percent,
+                warning: c.warning,
+            })
+            .collect()
+    }
+
+    fn drop_warning(&self) -> bool {
+        self.consumers.lock().unwrap().values().any(|c| c.warning)
+    }
+
     // System metrics update
     pub async fn update_system_metrics(&self) {
         let mut sys = System::new_all();
@@ -129,20 +421,45 @@ impl Metrics {
         };
 
         *self.system.write().await = Some(metrics);
+        *self.system_updated_at.lock().unwrap() = Some(Instant::now());
+    }
+
+    /// Time since the background updater last refreshed system metrics
+    pub fn system_metrics_age(&self) -> Option<Duration> {
+        self.system_updated_at
+            .lock()
+            .unwrap()
+            .map(|at| at.elapsed())
     }
 
     // Get current snapshot
     pub async fn snapshot(&self, start_time: std::time::Instant) -> MetricsSnapshot {
+        let (nats_rtt, nats_last_ping_at, nats_ping_failures) = {
+            let ping = self.nats_ping.lock().unwrap();
+            (ping.rtt, ping.last_ok_at, ping.failures)
+        };
         MetricsSnapshot {
             timestamp: chrono::Utc::now(),
             uptime_seconds: start_time.elapsed().as_secs(),
             nats_connected: self.nats_connected.load(Ordering::SeqCst),
             subscription_errors: self.subscription_errors.load(Ordering::SeqCst),
             messages_forwarded: self.messages_forwarded.load(Ordering::SeqCst),
+            messages_filtered: self.messages_filtered.load(Ordering::SeqCst),
+            lines_truncated: self.lines_truncated.load(Ordering::SeqCst),
+            nats_rtt_ms: nats_rtt.map(|rtt| rtt.as_secs_f64() * 1000.0),
+            nats_last_ping_at,
+            nats_ping_failures,
             sse_connections_total: self.sse_connections_total.load(Ordering::SeqCst),
             ws_connections_total: self.ws_connections_total.load(Ordering::SeqCst),
             active_sse_connections: self.active_sse_connections.load(Ordering::SeqCst),
             active_ws_connections: self.active_ws_connections.load(Ordering::SeqCst),
+            connection_queues: Vec::new(),
+            consumer_drops: self.consumer_drops(),
+            drop_warning: self.drop_warning(),
+            origins: self.origins(),
+            sources: Vec::new(),
+            sinks: Vec::new(),
+            log_metrics: Vec::new(),
             system: self.system.read().await.clone(),
         }
     }
@@ -159,15 +476,106 @@ impl Metrics {
             active_connections: active_sse + active_ws,
             messages_forwarded: self.messages_forwarded.load(Ordering::SeqCst),
             uptime_seconds: start_time.elapsed().as_secs(),
+            drop_warning: self.drop_warning(),
+            log_filter: String::new(),
+            sources: Vec::new(),
+            sinks: Vec::new(),
+            last_nats_error: self.nats_errors.lock().unwrap().back().cloned(),
         }
     }
 }
 
-// Background task to periodically update system metrics
+// Background task to periodically update system metrics and drop rates
+pub const METRICS_UPDATE_INTERVAL: Duration = Duration::from_secs(5);
+
 pub async fn metrics_updater(metrics: Arc<Metrics>) {
-    let mut interval = tokio::time::interval(std::time::Duration::from_secs(5));
+    let mut interval = tokio::time::interval(METRICS_UPDATE_INTERVAL);
     loop {
         interval.tick().await;
         metrics.update_system_metrics().await;
+        metrics.evaluate_drop_rates();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_drop_warning_uses_recent_window() {
+        let metrics = Metrics::new();
+        metrics.record_consumer("sse", 100, 20);
+        metrics.record_consumer("websocket", 100, 1);
+        metrics.evaluate_drop_rates();
+
+        let drops = metrics.consumer_drops();
+        assert_eq!(drops[0].consumer, "sse");
+        assert_eq!(drops[0].recent_drop_percent, 20.0);
+        assert!(drops[0].warning);
+        assert!(!drops[1].warning);
+        assert!(metrics.drop_warning());
+
+        // A clean interval clears the warning but keeps the totals
+        metrics.record_consumer("sse", 100, 0);
+        metrics.evaluate_drop_rates();
+        let drops = metrics.consumer_drops();
+        assert_eq!(drops[0].dropped, 20);
+        assert!(!drops[0].warning);
+        assert!(!metrics.drop_warning());
+    }
+
+    #[test]
+    fn test_origins_group_by_region_and_instance() {
+        let metrics = Metrics::new();
+        metrics.record_origin(Some("iad"), Some("m1"), false);
+        metrics.record_origin(Some("iad"), Some("m1"), true);
+        metrics.record_origin(Some("ams"), Some("m2"), true);
+        metrics.record_origin(None, None, false);
+
+        let origins = metrics.origins();
+        let summary: Vec<(&str, &str, u64, u64)> = origins
+            .iter()
+            .map(|o| (o.region.as_str(), o.instance.as_str(), o.messages, o.errors))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("ams", "m2", 1, 1),
+                ("iad", "m1", 2, 1),
+                ("unknown", "unknown", 1, 0)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_nats_probes_track_round_trip_and_stalls() {
+        let metrics = Metrics::new();
+        metrics.record_nats_ping_failure();
+        metrics.record_nats_ping_failure();
+        assert_eq!(metrics.nats_ping_failures_in_a_row(), 2);
+
+        metrics.record_nats_ping(Duration::from_millis(12));
+        assert_eq!(metrics.nats_ping_failures_in_a_row(), 0);
+        let snapshot = metrics.snapshot(Instant::now()).await;
+        assert_eq!(snapshot.nats_rtt_ms, Some(12.0));
+        assert!(snapshot.nats_last_ping_at.is_some());
+        assert_eq!(snapshot.nats_ping_failures, 2);
+    }
+
+    #[test]
+    fn test_nats_errors_keep_the_latest() {
+        let metrics = Metrics::new();
+        for n in 0..NATS_ERROR_HISTORY + 5 {
+            metrics.record_nats_error("client", &format!("error {}", n));
+        }
+        metrics.record_subscription_error("connect", &"authorization violation");
+
+        let debug = metrics.nats_debug();
+        assert_eq!(debug.subscription_errors, 1);
+        assert_eq!(debug.errors.len(), NATS_ERROR_HISTORY);
+        assert_eq!(debug.errors[0].kind, "connect");
+        assert_eq!(debug.errors[1].error, format!("error {}", NATS_ERROR_HISTORY + 4));
+        let health = metrics.health(Instant::now());
+        assert_eq!(health.last_nats_error.unwrap().error, "authorization violation");
     }
 }
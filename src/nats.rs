@@ -1,112 +1,486 @@
-use async_nats::{Client, ConnectOptions, ServerAddr};
+use async_nats::client::RequestErrorKind;
+use async_nats::{Client, ConnectOptions, Event, ServerAddr};
+use async_trait::async_trait;
 use futures::StreamExt;
+use serde_json::{json, Value};
+use std::fmt;
+use std::str::FromStr;
 use std::sync::Arc;
-use tokio::sync::broadcast;
-use tracing::{error, info, warn};
+use std::time::{Duration, Instant};
+use tokio::time::MissedTickBehavior;
+use tracing::{info, warn};
 
 use crate::config::Config;
-use crate::log_buffer::LogBuffer;
+use crate::ingest_filter::IngestFilter;
 use crate::metrics::Metrics;
+use crate::payload;
+use crate::source::{LogSource, SourceContext, SourceError};
+use crate::text::elide_middle;
 
-#[derive(Debug, Clone)]
-pub struct LogMessage {
-    pub raw: String,
+pub use crate::log_buffer::LogMessage;
+
+/// How flywatch authenticates to NATS
+#[derive(Debug, Clone, PartialEq)]
+pub enum NatsAuth {
+    /// ORG_SLUG as user and ACCESS_TOKEN as password, as Fly's NATS expects
+    Fly,
+    /// A `.creds` file holding a user JWT and its NKey seed
+    CredsFile(String),
+    /// An NKey seed
+    Nkey(String),
+    Token(String),
+}
+
+impl fmt::Display for NatsAuth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            NatsAuth::Fly => "fly",
+            NatsAuth::CredsFile(_) => "creds file",
+            NatsAuth::Nkey(_) => "nkey",
+            NatsAuth::Token(_) => "token",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// `host:port` gets the `nats://` scheme; a URL with its own (`tls://`,
+/// `nats://`) is used as written
+fn server_addr(url: &str) -> String {
+    if url.contains("://") {
+        url.to_string()
+    } else {
+        format!("nats://{}", url)
+    }
+}
+
+/// Connect to `url` with the configured credentials, retrying in the background
+pub async fn connect(url: &str, config: &Config) -> Result<Client, async_nats::ConnectError> {
+    connect_with(url, config, ConnectOptions::new()).await
+}
+
+async fn connect_with(
+    url: &str,
+    config: &Config,
+    options: ConnectOptions,
+) -> Result<Client, async_nats::ConnectError> {
+    let addr: ServerAddr = server_addr(url).parse().expect("Invalid NATS URL");
+
+    let mut options = match &config.nats_auth {
+        NatsAuth::Fly => {
+            options.user_and_password(config.nats_user.clone(), config.nats_password.clone())
+        }
+        NatsAuth::CredsFile(path) => options.credentials_file(path).await?,
+        NatsAuth::Nkey(seed) => options.nkey(seed.clone()),
+        NatsAuth::Token(token) => options.token(token.clone()),
+    };
+    if config.nats_tls {
+        options = options.require_tls(true);
+    }
+    if let Some(ca) = &config.nats_ca_file {
+        options = options.add_root_certificates(ca.into());
+    }
+    if let (Some(cert), Some(key)) = (&config.nats_client_cert_file, &config.nats_client_key_file) {
+        options = options.add_client_certificate(cert.into(), key.into());
+    }
+    let options = options
+        .retry_on_initial_connect()
+        .connection_timeout(std::time::Duration::from_secs(10))
+        .reconnect_delay_callback(|attempts| {
+            std::time::Duration::from_millis(std::cmp::min((attempts * 100) as u64, 5000))
+        });
+
+    info!(
+        url = %url,
+        auth = %config.nats_auth,
+        tls = config.nats_tls,
+        "Connecting to NATS with authentication"
+    );
+    options.connect(addr).await
 }
 
-pub struct NatsSubscriber {
+/// How long a probe waits for the server before it counts as failed
+const PING_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Measure the round trip to the server on every tick while connected. The
+/// probe is a request to a fresh inbox nobody listens on, which the server
+/// answers with "no responders", so a connection that stays up while probes
+/// time out shows as stalled rather than as a quiet stream.
+async fn probe(client: &Client, metrics: &Metrics, interval_secs: u64) {
+    if interval_secs == 0 {
+        return std::future::pending().await;
+    }
+    let mut interval = tokio::time::interval(Duration::from_secs(interval_secs));
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        if !metrics.is_nats_connected() {
+            continue;
+        }
+        let started = Instant::now();
+        let reply = tokio::time::timeout(
+            PING_TIMEOUT,
+            client.request(client.new_inbox(), Default::default()),
+        )
+        .await;
+        match reply {
+            Ok(Ok(_)) => metrics.record_nats_ping(started.elapsed()),
+            Ok(Err(e)) if e.kind() == RequestErrorKind::NoResponders => {
+                metrics.record_nats_ping(started.elapsed())
+            }
+            Ok(Err(e)) => {
+                warn!(error = %e, "NATS round-trip probe failed");
+                metrics.record_nats_ping_failure();
+                metrics.record_nats_error("probe", &e);
+            }
+            Err(_) => {
+                warn!(
+                    timeout_secs = PING_TIMEOUT.as_secs(),
+                    "NATS round-trip probe timed out; the connection may be stalled"
+                );
+                metrics.record_nats_ping_failure();
+                metrics.record_nats_error(
+                    "probe",
+                    &format!("no answer within {}s", PING_TIMEOUT.as_secs()),
+                );
+            }
+        }
+    }
+}
+
+/// Placeholder in a subject template for each monitored app
+pub const APP_PLACEHOLDER: &str = "{app}";
+
+/// A subject pattern from `NATS_SUBJECTS`, e.g. `events.orders.> source=orders`.
+/// `{app}` expands to the monitored app and each tenant app; `source=`
+/// labels every line received on it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubjectTemplate {
+    pub pattern: String,
+    pub source: Option<String>,
+}
+
+impl SubjectTemplate {
+    /// Fly's log subjects for every app
+    pub fn fly_logs() -> Self {
+        Self {
+            pattern: format!("logs.{}.>", APP_PLACEHOLDER),
+            source: None,
+        }
+    }
+
+    /// The subjects this template names for `apps`
+    pub fn expand(&self, apps: &[&String]) -> Vec<String> {
+        if !self.pattern.contains(APP_PLACEHOLDER) {
+            return vec![self.pattern.clone()];
+        }
+        apps.iter()
+            .map(|app| self.pattern.replace(APP_PLACEHOLDER, app))
+            .collect()
+    }
+}
+
+impl FromStr for SubjectTemplate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split_whitespace();
+        let pattern = parts.next().ok_or("empty subject")?;
+        let tokens: Vec<&str> = pattern.split('.').collect();
+        if tokens.iter().any(|t| t.is_empty()) {
+            return Err(format!("empty token in subject '{}'", pattern));
+        }
+        if tokens[..tokens.len() - 1].contains(&">") {
+            return Err(format!("'>' must be the last token in '{}'", pattern));
+        }
+        if pattern.replace(APP_PLACEHOLDER, "").contains(['{', '}']) {
+            return Err(format!(
+                "only {} can be substituted in '{}'",
+                APP_PLACEHOLDER, pattern
+            ));
+        }
+        let mut source = None;
+        for option in parts {
+            match option.split_once('=') {
+                Some(("source", label)) if !label.is_empty() => source = Some(label.to_string()),
+                _ => return Err(format!("unknown option '{}' (expected source=)", option)),
+            }
+        }
+        Ok(Self {
+            pattern: pattern.to_string(),
+            source,
+        })
+    }
+}
+
+impl fmt::Display for SubjectTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.pattern)?;
+        if let Some(source) = &self.source {
+            write!(f, " source={}", source)?;
+        }
+        Ok(())
+    }
+}
+
+/// Label a line with the source of the subject it came on: JSON objects gain
+/// a `source` key (one they carry wins), other text becomes the message of a
+/// JSON line
+fn label(raw: String, source: &str) -> String {
+    match serde_json::from_str::<Value>(&raw) {
+        Ok(Value::Object(line)) if line.contains_key("source") => raw,
+        Ok(Value::Object(mut line)) => {
+            line.insert("source".to_string(), Value::from(source));
+            Value::Object(line).to_string()
+        }
+        _ => json!({ "message": raw, "source": source }).to_string(),
+    }
+}
+
+/// Fit a payload into `max` bytes, or None when it already fits. A JSON
+/// line keeps its envelope (app, instance, level) and loses the middle of its
+/// message; anything else, or a message too escaped to fit that way, loses
+/// the middle of the whole line.
+fn cap_payload(payload: &[u8], max: usize) -> Option<String> {
+    if max == 0 || payload.len() <= max {
+        return None;
+    }
+    if let Ok(Value::Object(mut line)) = serde_json::from_slice::<Value>(payload) {
+        if let Some(Value::String(message)) = line.remove("message") {
+            let escaped = serde_json::to_string(&message).map_or(message.len(), |m| m.len());
+            let mut budget = max.saturating_sub(payload.len() - escaped);
+            // Escaping can grow what's kept; shrink by the overshoot and retry
+            for _ in 0..3 {
+                if budget == 0 {
+                    break;
+                }
+                let kept = elide_middle(message.as_bytes(), budget);
+                line.insert("message".to_string(), Value::String(kept));
+                let capped = serde_json::to_string(&line).unwrap_or_default();
+                if capped.len() <= max {
+                    return Some(capped);
+                }
+                budget = budget.saturating_sub(capped.len() - max);
+            }
+        }
+    }
+    Some(elide_middle(payload, max))
+}
+
+/// Fly.io NATS log stream subscriber
+pub struct NatsSource {
     config: Arc<Config>,
     metrics: Arc<Metrics>,
-    tx: broadcast::Sender<LogMessage>,
-    log_buffer: Arc<LogBuffer>,
+    ingest_filter: Arc<IngestFilter>,
 }
 
-impl NatsSubscriber {
+impl NatsSource {
     pub fn new(
         config: Arc<Config>,
         metrics: Arc<Metrics>,
-        tx: broadcast::Sender<LogMessage>,
-        log_buffer: Arc<LogBuffer>,
+        ingest_filter: Arc<IngestFilter>,
     ) -> Self {
         Self {
             config,
             metrics,
-            tx,
-            log_buffer,
+            ingest_filter,
         }
     }
 
     pub async fn connect(&self) -> Result<Client, async_nats::ConnectError> {
-        let addr: ServerAddr = format!("nats://{}", self.config.nats_url)
-            .parse()
-            .expect("Invalid NATS URL");
-
-        let options = ConnectOptions::new()
-            .user_and_password(
-                self.config.nats_user.clone(),
-                self.config.nats_password.clone(),
-            )
-            .retry_on_initial_connect()
-            .connection_timeout(std::time::Duration::from_secs(10))
-            .reconnect_delay_callback(|attempts| {
-                std::time::Duration::from_millis(std::cmp::min(
-                    (attempts * 100) as u64,
-                    5000,
-                ))
-            });
-
-        info!(
-            url = %self.config.nats_url,
-            user = %self.config.nats_user,
-            "Connecting to NATS with authentication"
-        );
-        let client = options.connect(addr).await?;
+        // The client reconnects on its own; keep the health flag in step with it
+        let metrics = self.metrics.clone();
+        let options = ConnectOptions::new().event_callback(move |event| {
+            let metrics = metrics.clone();
+            async move {
+                match event {
+                    Event::Connected => {
+                        info!("NATS connection up");
+                        metrics.set_nats_connected(true);
+                    }
+                    Event::Disconnected => {
+                        warn!("Disconnected from NATS, reconnecting");
+                        metrics.set_nats_connected(false);
+                        metrics.record_nats_error("disconnect", &"disconnected, reconnecting");
+                    }
+                    event @ (Event::SlowConsumer(_)
+                    | Event::ServerError(_)
+                    | Event::ClientError(_)) => {
+                        warn!(event = %event, "NATS client event");
+                        metrics.record_nats_error("client", &event);
+                    }
+                    other => warn!(event = %other, "NATS client event"),
+                }
+            }
+        });
+        let client = connect_with(&self.config.nats_url, &self.config, options).await?;
         info!("Connected to NATS successfully");
         self.metrics.set_nats_connected(true);
 
         Ok(client)
     }
 
-    pub async fn run(&self) {
-        loop {
-            match self.connect().await {
-                Ok(client) => {
-                    if let Err(e) = self.subscribe_loop(&client).await {
-                        error!(error = %e, "Subscription loop error");
-                        self.metrics.increment_subscription_errors();
-                    }
-                    self.metrics.set_nats_connected(false);
-                }
-                Err(e) => {
-                    error!(error = %e, "Failed to connect to NATS");
-                    self.metrics.set_nats_connected(false);
-                    self.metrics.increment_subscription_errors();
+    async fn subscribe_loop(
+        &self,
+        client: &Client,
+        ctx: &SourceContext,
+    ) -> Result<(), async_nats::Error> {
+        // Every configured subject for the monitored app plus any tenant
+        // apps, merged into one stream
+        let mut subscribers = Vec::new();
+        for (subject, source) in self.config.nats_subjects() {
+            info!(subject = %subject, source = ?source, "Subscribing to NATS subject");
+            let subscriber = client.subscribe(subject.clone()).await?;
+            subscribers.push(subscriber.map(move |message| (source.clone(), message)));
+            info!(subject = %subject, "Successfully subscribed");
+        }
+        let mut subscriber = futures::stream::select_all(subscribers);
+        ctx.running().await;
+
+        while let Some((source, message)) = subscriber.next().await {
+            let line = payload::decode(&message.payload, &self.config.nats_binary_formats);
+            let line = match source {
+                Some(source) => label(line.into_owned(), &source).into(),
+                None => line,
+            };
+            // Giant payloads would otherwise sit in the buffer, every stream
+            // frame and the model's context whole
+            let raw = match cap_payload(line.as_bytes(), self.config.max_line_bytes) {
+                Some(capped) => {
+                    self.metrics.increment_lines_truncated();
+                    capped
                 }
+                None => line.into_owned(),
+            };
+            if !self.ingest_filter.admit(&message.subject, &raw) {
+                self.metrics.increment_messages_filtered();
+                continue;
             }
-
-            warn!("NATS connection lost, reconnecting in 5 seconds...");
-            tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+            ctx.emit_on(&message.subject, raw).await;
         }
+
+        Ok(())
     }
+}
 
-    async fn subscribe_loop(&self, client: &Client) -> Result<(), async_nats::Error> {
-        let subject = self.config.nats_subject();
-        info!(subject = %subject, "Subscribing to NATS subject");
+struct ConnectedGuard<'a>(&'a Metrics);
 
-        let mut subscriber = client.subscribe(subject.clone()).await?;
-        info!(subject = %subject, "Successfully subscribed");
+impl Drop for ConnectedGuard<'_> {
+    fn drop(&mut self) {
+        self.0.set_nats_connected(false);
+    }
+}
 
-        while let Some(message) = subscriber.next().await {
-            let raw = String::from_utf8_lossy(&message.payload).to_string();
+#[async_trait]
+impl LogSource for NatsSource {
+    fn name(&self) -> &str {
+        "nats"
+    }
 
-            // Push to log buffer for AI access
-            self.log_buffer.push(raw.clone()).await;
+    fn kind(&self) -> &'static str {
+        "nats"
+    }
 
-            // Broadcast to SSE/WebSocket clients
-            let log_msg = LogMessage { raw };
-            self.metrics.increment_messages_forwarded();
-            let _ = self.tx.send(log_msg);
-        }
+    async fn run(&self, ctx: &SourceContext) -> Result<(), SourceError> {
+        let client = self.connect().await.map_err(|e| {
+            self.metrics.record_subscription_error("connect", &e);
+            self.metrics.set_nats_connected(false);
+            SourceError::Connect(e.to_string())
+        })?;
 
-        Ok(())
+        // Clears the connected flag even if the loop panics
+        let _connected = ConnectedGuard(&self.metrics);
+        let interval_secs = self.config.nats_ping_interval_secs;
+        let result = tokio::select! {
+            result = self.subscribe_loop(&client, ctx) => result,
+            () = probe(&client, &self.metrics, interval_secs) => Ok(()),
+        };
+
+        result.map_err(|e| {
+            self.metrics.record_subscription_error("subscription", &e);
+            SourceError::Io(format!("Subscription loop error: {}", e))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::log_buffer::TimestampedLog;
+    use crate::source::fly_envelope;
+
+    #[test]
+    fn test_subject_templates_expand_per_app_with_labels() {
+        let config = Config::for_tests(
+            "nats_subjects = [\"logs.{app}.>\", \"events.{app}.* source=events\", \"audit.> source=audit\"]
+             tenants = [\"acme app=acme-web token=s3cret\"]
+             auth_token = \"admin\"",
+        );
+        let subjects = config.nats_subjects();
+        let labelled = |subject: &str, source: Option<&str>| {
+            (subject.to_string(), source.map(str::to_string))
+        };
+        assert_eq!(
+            subjects,
+            vec![
+                labelled("logs.app.>", None),
+                labelled("logs.acme-web.>", None),
+                labelled("events.app.*", Some("events")),
+                labelled("events.acme-web.*", Some("events")),
+                labelled("audit.>", Some("audit")),
+            ]
+        );
+        assert_eq!(
+            Config::for_tests("").nats_subjects(),
+            vec![labelled("logs.app.>", None)]
+        );
+
+        assert!("logs.>.app".parse::<SubjectTemplate>().is_err());
+        assert!("logs..app".parse::<SubjectTemplate>().is_err());
+        assert!("logs.{region}.>".parse::<SubjectTemplate>().is_err());
+        assert!("logs.> label=x".parse::<SubjectTemplate>().is_err());
+        let template: SubjectTemplate = "events.> source=orders".parse().unwrap();
+        assert_eq!(template.to_string(), "events.> source=orders");
+    }
+
+    #[test]
+    fn test_lines_are_labelled_with_their_source() {
+        let log = |raw| TimestampedLog::new(raw, 1);
+        let json = log(label(r#"{"msg":"order placed","id":7}"#.to_string(), "orders"));
+        assert_eq!(json.source.as_deref(), Some("orders"));
+        assert_eq!(json.field("id"), Some(&json!(7)));
+
+        let own = log(label(r#"{"source":"billing"}"#.to_string(), "orders"));
+        assert_eq!(own.source.as_deref(), Some("billing"));
+
+        let text = log(label("order 7 placed".to_string(), "orders"));
+        assert_eq!(text.source.as_deref(), Some("orders"));
+        assert_eq!(text.message.as_deref(), Some("order 7 placed"));
+    }
+
+    #[test]
+    fn test_giant_payloads_are_capped() {
+        let small = fly_envelope("ok", Some("info"), None, None);
+        assert_eq!(cap_payload(small.as_bytes(), 1024), None);
+        assert_eq!(cap_payload(&[b'x'; 4096], 0), None);
+
+        // The envelope survives, so the line still parses with its level
+        let dump = format!("thread panicked\n{}at main.rs:1", "  frame\n".repeat(50_000));
+        let line = fly_envelope(&dump, Some("error"), Some("web-1"), Some("iad"));
+        let capped = cap_payload(line.as_bytes(), 4096).unwrap();
+        assert!(capped.len() <= 4096);
+        let log = TimestampedLog::new(capped, 1);
+        assert_eq!(log.level.as_deref(), Some("error"));
+        assert_eq!(log.instance.as_deref(), Some("web-1"));
+        let message = log.message.unwrap();
+        assert!(message.starts_with("thread panicked"));
+        assert!(message.ends_with("at main.rs:1"));
+        assert!(message.contains("bytes]…"));
+
+        // Plain text just loses its middle
+        let blob = format!("data:{}:end", "A".repeat(100_000));
+        let capped = cap_payload(blob.as_bytes(), 2048).unwrap();
+        assert!(capped.len() <= 2048);
+        assert!(capped.starts_with("data:") && capped.ends_with(":end"));
     }
 }
@@ -0,0 +1,170 @@
+//! Queries over NATS request-reply, for sibling services on the 6PN network
+//! that would rather not go through HTTP. With RPC_SUBJECT set (say
+//! `flywatch.rpc`), requests to
+//!
+//! - `<subject>.logs`: the newest lines, oldest first. The JSON body takes
+//!   `limit` (default 100, max 1000) and the stream filters (`level`,
+//!   `instance`, `search`, `pattern`, `field`, `view`)
+//! - `<subject>.metrics`: the `/metrics` snapshot
+//! - `<subject>.summary`: the `/logs/buffer/stats` summary
+//!
+//! are answered with JSON, or with the HTTP API's error body. With
+//! AUTH_TOKEN set, requests carry `Authorization: Bearer <token>` as a NATS
+//! header. Replicas share a queue group, so each request is answered once.
+
+use async_nats::Message;
+use axum::http::{header, HeaderMap, HeaderValue};
+use chrono::Utc;
+use futures::StreamExt;
+use serde::Deserialize;
+use serde_json::json;
+use tracing::{debug, info, warn};
+
+use crate::error::ApiError;
+use crate::filter::FilterParams;
+use crate::http::{check_auth, full_snapshot, AppState};
+use crate::nats;
+
+const QUEUE_GROUP: &str = "flywatch";
+const DEFAULT_LIMIT: usize = 100;
+const MAX_LIMIT: usize = 1000;
+
+#[derive(Debug, Default, Deserialize)]
+struct LogsRequest {
+    limit: Option<usize>,
+    #[serde(flatten)]
+    filter: FilterParams,
+}
+
+/// The request's headers as the HTTP auth check reads them
+fn auth_headers(message: &Message) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    let authorization = message
+        .headers
+        .as_ref()
+        .and_then(|h| h.get(header::AUTHORIZATION.as_str()))
+        .and_then(|v| HeaderValue::from_str(v.as_str()).ok());
+    if let Some(value) = authorization {
+        headers.insert(header::AUTHORIZATION, value);
+    }
+    headers
+}
+
+async fn answer(
+    state: &AppState,
+    operation: &str,
+    headers: &HeaderMap,
+    body: &[u8],
+) -> Result<Vec<u8>, ApiError> {
+    check_auth(state, headers)?;
+    let reply = match operation {
+        "logs" => {
+            let request: LogsRequest = match body {
+                [] => LogsRequest::default(),
+                body => serde_json::from_slice(body)
+                    .map_err(|e| ApiError::InvalidRequest(format!("Invalid request: {}", e)))?,
+            };
+            let limit = request.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT);
+            let filter = request.filter.compile(&state.views)?;
+            let now = Utc::now();
+            let since = filter.since(now);
+            let page = state
+                .log_buffer
+                .page_before(now, None, limit, |log| {
+                    filter.matches(log) && since.is_none_or(|since| log.timestamp >= since)
+                })
+                .await;
+            serde_json::to_vec(&json!({
+                "logs": page.logs,
+                "total_estimate": page.total_estimate,
+            }))
+        }
+        "metrics" => serde_json::to_vec(&full_snapshot(state).await),
+        "summary" => serde_json::to_vec(&state.log_buffer.get_summary().await),
+        other => {
+            return Err(ApiError::NotFound(format!(
+                "Unknown operation '{}'; use logs, metrics or summary",
+                other
+            )))
+        }
+    };
+    reply.map_err(|e| ApiError::Internal(e.to_string()))
+}
+
+fn error_body(error: &ApiError) -> Vec<u8> {
+    json!({
+        "code": error.code(),
+        "error": error.to_string(),
+        "status": error.status().as_u16(),
+    })
+    .to_string()
+    .into_bytes()
+}
+
+/// Answer requests on `<subject>.*`; the client reconnects on its own
+pub async fn serve(state: AppState, subject: String) {
+    let config = state.config.current();
+    let client = match nats::connect(&config.nats_url, &config).await {
+        Ok(client) => client,
+        Err(e) => {
+            warn!(error = %e, "Failed to connect the request-reply API to NATS");
+            return;
+        }
+    };
+    let mut requests = match client
+        .queue_subscribe(format!("{}.*", subject), QUEUE_GROUP.to_string())
+        .await
+    {
+        Ok(requests) => requests,
+        Err(e) => {
+            warn!(error = %e, subject = %subject, "Failed to subscribe for requests");
+            return;
+        }
+    };
+    info!(subject = %subject, "Serving queries over NATS request-reply");
+
+    let prefix = format!("{}.", subject);
+    while let Some(message) = requests.next().await {
+        let Some(reply) = message.reply.clone() else {
+            continue;
+        };
+        let (state, client) = (state.clone(), client.clone());
+        let operation = message
+            .subject
+            .strip_prefix(&prefix)
+            .unwrap_or_default()
+            .to_string();
+        // Queries read the buffer; one slow one shouldn't hold up the rest
+        tokio::spawn(async move {
+            let headers = auth_headers(&message);
+            let body = answer(&state, &operation, &headers, &message.payload)
+                .await
+                .unwrap_or_else(|e| error_body(&e));
+            if let Err(e) = client.publish(reply, body.into()).await {
+                debug!(error = %e, operation = %operation, "Failed to send reply");
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_logs_request_takes_stream_filters() {
+        let request: LogsRequest =
+            serde_json::from_str(r#"{"limit": 20, "level": "error,warn", "field": "status:502"}"#)
+                .unwrap();
+        assert_eq!(request.limit, Some(20));
+        assert_eq!(request.filter.level.as_deref(), Some("error,warn"));
+        assert_eq!(request.filter.field.as_deref(), Some("status:502"));
+
+        let body: serde_json::Value = serde_json::from_slice(&error_body(&ApiError::NotFound(
+            "Unknown operation 'tail'".to_string(),
+        )))
+        .unwrap();
+        assert_eq!(body["status"], 404);
+        assert_eq!(body["code"], ApiError::NotFound(String::new()).code());
+    }
+}
@@ -0,0 +1,307 @@
+//! Binary NATS payloads. Text passes through untouched; a payload that isn't
+//! text (invalid UTF-8, or control bytes other than tabs, newlines and ANSI
+//! escapes) is decoded with the formats in `NATS_BINARY_FORMATS`, in order:
+//!
+//! - `msgpack`: a MessagePack map, turned into the equivalent JSON line
+//! - `protobuf`: a `flywatch.v1.LogEntry` from proto/flywatch.proto
+//!
+//! A payload none of them decode is kept as base64 in the `message` of a
+//! JSON line marked `"content_type": "application/octet-stream"`, rather
+//! than as text mangled by replacement characters.
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chrono::DateTime;
+use serde_json::{json, Map, Number, Value};
+use std::borrow::Cow;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::grpc::decode_log_entry;
+
+/// Content type marking a payload kept as base64
+pub const OCTET_STREAM: &str = "application/octet-stream";
+
+/// Deepest MessagePack nesting decoded
+const MAX_DEPTH: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryFormat {
+    Msgpack,
+    Protobuf,
+}
+
+impl FromStr for BinaryFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "msgpack" => Ok(BinaryFormat::Msgpack),
+            "protobuf" => Ok(BinaryFormat::Protobuf),
+            other => Err(format!(
+                "unknown format '{}' (expected msgpack or protobuf)",
+                other
+            )),
+        }
+    }
+}
+
+impl fmt::Display for BinaryFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            BinaryFormat::Msgpack => "msgpack",
+            BinaryFormat::Protobuf => "protobuf",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The payload as text, if it is text
+fn text(payload: &[u8]) -> Option<&str> {
+    let text = std::str::from_utf8(payload).ok()?;
+    text.bytes()
+        .all(|b| !b.is_ascii_control() || matches!(b, b'\t' | b'\n' | b'\r' | 0x1b))
+        .then_some(text)
+}
+
+/// The payload as a log line: text as is, binary decoded or base64-encoded
+pub fn decode<'a>(payload: &'a [u8], formats: &[BinaryFormat]) -> Cow<'a, str> {
+    if let Some(text) = text(payload) {
+        return Cow::Borrowed(text);
+    }
+    for format in formats {
+        let decoded = match format {
+            BinaryFormat::Msgpack => msgpack(payload).map(|event| event.to_string()),
+            BinaryFormat::Protobuf => decode_log_entry(payload),
+        };
+        if let Ok(line) = decoded {
+            return Cow::Owned(line);
+        }
+    }
+    Cow::Owned(
+        json!({
+            "content_type": OCTET_STREAM,
+            "encoding": "base64",
+            "size": payload.len(),
+            "message": STANDARD.encode(payload),
+        })
+        .to_string(),
+    )
+}
+
+/// A MessagePack log event: one map and nothing after it
+fn msgpack(payload: &[u8]) -> Result<Value, String> {
+    let mut reader = Reader(payload);
+    let event = reader.value(0)?;
+    if !event.is_object() {
+        return Err("expected a map".to_string());
+    }
+    if !reader.0.is_empty() {
+        return Err("trailing bytes after the event".to_string());
+    }
+    Ok(event)
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if len > self.0.len() {
+            return Err("truncated value".to_string());
+        }
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(bytes)
+    }
+
+    /// A big-endian unsigned integer of `len` bytes
+    fn uint(&mut self, len: usize) -> Result<u64, String> {
+        Ok(self
+            .take(len)?
+            .iter()
+            .fold(0, |value, &b| value << 8 | b as u64))
+    }
+
+    /// A length that can't claim more elements than bytes are left
+    fn count(&mut self, len: usize) -> Result<usize, String> {
+        let count = self.uint(len)? as usize;
+        if count > self.0.len() {
+            return Err("truncated value".to_string());
+        }
+        Ok(count)
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Value, String> {
+        if depth > MAX_DEPTH {
+            return Err("nested too deeply".to_string());
+        }
+        let byte = self.take(1)?[0];
+        Ok(match byte {
+            0x00..=0x7f => json!(byte),
+            0x80..=0x8f => self.map(byte as usize & 0x0f, depth)?,
+            0x90..=0x9f => self.array(byte as usize & 0x0f, depth)?,
+            0xa0..=0xbf => self.string(byte as usize & 0x1f)?,
+            0xc0 => Value::Null,
+            0xc1 => return Err("reserved byte 0xc1".to_string()),
+            0xc2 => Value::Bool(false),
+            0xc3 => Value::Bool(true),
+            0xc4..=0xc6 => {
+                let len = self.count(1 << (byte - 0xc4))?;
+                Value::String(STANDARD.encode(self.take(len)?))
+            }
+            0xc7..=0xc9 => {
+                let len = self.count(1 << (byte - 0xc7))?;
+                self.ext(len)?
+            }
+            0xca => float(f32::from_bits(self.uint(4)? as u32) as f64),
+            0xcb => float(f64::from_bits(self.uint(8)?)),
+            0xcc..=0xcf => json!(self.uint(1 << (byte - 0xcc))?),
+            0xd0..=0xd3 => {
+                let len = 1 << (byte - 0xd0);
+                let shift = 64 - 8 * len;
+                json!(((self.uint(len)? << shift) as i64) >> shift)
+            }
+            0xd4..=0xd8 => self.ext(1 << (byte - 0xd4))?,
+            0xd9..=0xdb => {
+                let len = self.count(1 << (byte - 0xd9))?;
+                self.string(len)?
+            }
+            0xdc | 0xdd => {
+                let len = self.count(2 << (byte - 0xdc))?;
+                self.array(len, depth)?
+            }
+            0xde | 0xdf => {
+                let len = self.count(2 << (byte - 0xde))?;
+                self.map(len, depth)?
+            }
+            0xe0..=0xff => json!(byte as i8),
+        })
+    }
+
+    fn string(&mut self, len: usize) -> Result<Value, String> {
+        let bytes = self.take(len)?;
+        Ok(Value::String(String::from_utf8_lossy(bytes).into_owned()))
+    }
+
+    fn array(&mut self, len: usize, depth: usize) -> Result<Value, String> {
+        (0..len)
+            .map(|_| self.value(depth + 1))
+            .collect::<Result<_, _>>()
+            .map(Value::Array)
+    }
+
+    /// Keys that aren't strings are written as JSON text
+    fn map(&mut self, len: usize, depth: usize) -> Result<Value, String> {
+        let mut map = Map::new();
+        for _ in 0..len {
+            let key = match self.value(depth + 1)? {
+                Value::String(key) => key,
+                other => other.to_string(),
+            };
+            map.insert(key, self.value(depth + 1)?);
+        }
+        Ok(Value::Object(map))
+    }
+
+    /// Timestamps (type -1) become RFC3339 text; other extensions, base64
+    fn ext(&mut self, len: usize) -> Result<Value, String> {
+        let kind = self.take(1)?[0] as i8;
+        let mut data = Reader(self.take(len)?);
+        if kind != -1 {
+            return Ok(Value::String(STANDARD.encode(data.0)));
+        }
+        let (secs, nanos) = match len {
+            4 => (data.uint(4)? as i64, 0),
+            8 => {
+                let packed = data.uint(8)?;
+                ((packed & 0x3_ffff_ffff) as i64, (packed >> 34) as u32)
+            }
+            12 => {
+                let nanos = data.uint(4)? as u32;
+                (data.uint(8)? as i64, nanos)
+            }
+            _ => return Err("invalid timestamp extension".to_string()),
+        };
+        DateTime::from_timestamp(secs, nanos)
+            .map(|at| Value::String(at.to_rfc3339()))
+            .ok_or_else(|| "timestamp out of range".to_string())
+    }
+}
+
+/// JSON has no NaN or infinity
+fn float(value: f64) -> Value {
+    Number::from_f64(value).map_or(Value::Null, Value::Number)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::log_buffer::TimestampedLog;
+
+    #[test]
+    fn test_text_passes_through() {
+        let line = "\x1b[31merror\x1b[0m\tdone\n";
+        assert!(matches!(
+            decode(line.as_bytes(), &[BinaryFormat::Msgpack]),
+            Cow::Borrowed(text) if text == line
+        ));
+    }
+
+    #[test]
+    fn test_msgpack_events_become_json_lines() {
+        // {"message": "boom", "log": {"level": "error"}, "status": 502,
+        //  "ok": false, "ms": -3, "at": <timestamp 1700000000>}
+        let mut event = vec![0x86, 0xa7];
+        event.extend_from_slice(b"message");
+        event.push(0xa4);
+        event.extend_from_slice(b"boom");
+        event.push(0xa3);
+        event.extend_from_slice(b"log");
+        event.extend_from_slice(&[0x81, 0xa5]);
+        event.extend_from_slice(b"level");
+        event.push(0xa5);
+        event.extend_from_slice(b"error");
+        event.push(0xa6);
+        event.extend_from_slice(b"status");
+        event.extend_from_slice(&[0xcd, 0x01, 0xf6]);
+        event.push(0xa2);
+        event.extend_from_slice(b"ok");
+        event.push(0xc2);
+        event.push(0xa2);
+        event.extend_from_slice(b"ms");
+        event.push(0xfd);
+        event.push(0xa2);
+        event.extend_from_slice(b"at");
+        event.extend_from_slice(&[0xd6, 0xff, 0x65, 0x53, 0xf1, 0x00]);
+
+        let line = decode(&event, &[BinaryFormat::Msgpack]);
+        let log = TimestampedLog::new(line.into_owned(), 1);
+        assert_eq!(log.message.as_deref(), Some("boom"));
+        assert_eq!(log.level.as_deref(), Some("error"));
+        assert_eq!(log.field("status"), Some(&json!(502)));
+        assert_eq!(log.field("ok"), Some(&json!(false)));
+        assert_eq!(log.field("ms"), Some(&json!(-3)));
+        assert_eq!(log.field("at"), Some(&json!("2023-11-14T22:13:20+00:00")));
+
+        // Truncated or not a map
+        assert!(msgpack(&event[..event.len() - 1]).is_err());
+        assert!(msgpack(&[0x92, 0x01, 0x02]).is_err());
+        assert!(msgpack(&[0xdf, 0xff, 0xff, 0xff, 0xff]).is_err());
+    }
+
+    #[test]
+    fn test_undecodable_payloads_are_kept_as_base64() {
+        let payload = b"\xff\xfe\x00\x01";
+        for formats in [&[][..], &[BinaryFormat::Msgpack, BinaryFormat::Protobuf]] {
+            let line: Value = serde_json::from_str(&decode(payload, formats)).unwrap();
+            assert_eq!(line["content_type"], OCTET_STREAM);
+            assert_eq!(line["size"], 4);
+            assert_eq!(
+                STANDARD.decode(line["message"].as_str().unwrap()).unwrap(),
+                payload
+            );
+        }
+        assert_eq!("MsgPack".parse(), Ok(BinaryFormat::Msgpack));
+        assert!("avro".parse::<BinaryFormat>().is_err());
+    }
+}
@@ -1,53 +1,101 @@
-use serde::Serialize;
+//! Model pricing for the cost guard, usage records and `GET /pricing`. A
+//! model's price comes from the first of:
+//!
+//! - `MODEL_PRICING` overrides, e.g. `openai/gpt-4o=2.5/10`
+//! - OpenRouter's model list (`<OPENROUTER_BASE_URL>/models`), fetched every
+//!   `OPENROUTER_PRICING_REFRESH_SECS` while OPENROUTER_API_KEY is set
+//! - the table built into flywatch
+//!
+//! and models none of them know are priced like Kimi K2.
+
+use axum::{extract::State, http::HeaderMap, Json};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tracing::{info, warn};
+use utoipa::ToSchema;
+
+use crate::config::{Config, ConfigStore};
+use crate::error::{ApiError, ErrorBody};
+use crate::http::{check_auth, AppState};
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// What to do with a chat whose estimated cost exceeds CHAT_MAX_COST_USD
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CostPolicy {
+    /// Refuse the request
+    Reject,
+    /// Retry the estimate with the default model and use it if it fits
+    Downgrade,
+}
+
+impl FromStr for CostPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "reject" => Ok(Self::Reject),
+            "downgrade" => Ok(Self::Downgrade),
+            other => Err(format!(
+                "Invalid cost policy: {}. Use reject or downgrade.",
+                other
+            )),
+        }
+    }
+}
 
 /// Pricing per million tokens for different models
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct ModelPricing {
     pub input_per_million: f64,
     pub output_per_million: f64,
 }
 
+/// Models priced without a catalog: (model, input, output) per million tokens
+const BUILTIN: &[(&str, f64, f64)] = &[
+    // Moonshot Kimi K2
+    ("moonshotai/kimi-k2", 0.456, 1.84),
+    // Anthropic Claude models
+    ("anthropic/claude-3.5-sonnet", 3.0, 15.0),
+    ("anthropic/claude-3-5-sonnet-20241022", 3.0, 15.0),
+    ("anthropic/claude-3-haiku", 0.25, 1.25),
+    ("anthropic/claude-3-haiku-20240307", 0.25, 1.25),
+    ("anthropic/claude-3-opus", 15.0, 75.0),
+    ("anthropic/claude-3-opus-20240229", 15.0, 75.0),
+    // OpenAI GPT-4 models
+    ("openai/gpt-4-turbo", 10.0, 30.0),
+    ("openai/gpt-4-turbo-preview", 10.0, 30.0),
+    ("openai/gpt-4o", 2.5, 10.0),
+    ("openai/gpt-4o-mini", 0.15, 0.6),
+];
+
+/// Pricing for models nothing else knows (Kimi K2 pricing as baseline)
+pub const DEFAULT_PRICING: ModelPricing = ModelPricing {
+    input_per_million: 0.456,
+    output_per_million: 1.84,
+};
+
 impl ModelPricing {
-    /// Get pricing for a model by name
-    pub fn for_model(model: &str) -> Self {
-        match model {
-            // Moonshot Kimi K2
-            "moonshotai/kimi-k2" => Self {
-                input_per_million: 0.456,
-                output_per_million: 1.84,
-            },
-            // Anthropic Claude models
-            "anthropic/claude-3.5-sonnet" | "anthropic/claude-3-5-sonnet-20241022" => Self {
-                input_per_million: 3.0,
-                output_per_million: 15.0,
-            },
-            "anthropic/claude-3-haiku" | "anthropic/claude-3-haiku-20240307" => Self {
-                input_per_million: 0.25,
-                output_per_million: 1.25,
-            },
-            "anthropic/claude-3-opus" | "anthropic/claude-3-opus-20240229" => Self {
-                input_per_million: 15.0,
-                output_per_million: 75.0,
-            },
-            // OpenAI GPT-4 models
-            "openai/gpt-4-turbo" | "openai/gpt-4-turbo-preview" => Self {
-                input_per_million: 10.0,
-                output_per_million: 30.0,
-            },
-            "openai/gpt-4o" => Self {
-                input_per_million: 2.5,
-                output_per_million: 10.0,
-            },
-            "openai/gpt-4o-mini" => Self {
-                input_per_million: 0.15,
-                output_per_million: 0.6,
-            },
-            // Default fallback (use Kimi K2 pricing as baseline)
-            _ => Self {
-                input_per_million: 0.456,
-                output_per_million: 1.84,
-            },
-        }
+    /// Get pricing for a model by name from the built-in table
+    fn builtin(model: &str) -> Option<Self> {
+        BUILTIN
+            .iter()
+            .find(|(name, _, _)| *name == model)
+            .map(|&(_, input, output)| Self {
+                input_per_million: input,
+                output_per_million: output,
+            })
+    }
+
+    /// Worst-case cost of one call: the prompt plus a full-length completion
+    pub fn estimate_max_cost(&self, prompt_tokens: u32, max_completion_tokens: u32) -> f64 {
+        self.calculate_cost(prompt_tokens, max_completion_tokens)
+            .total_cost_usd
     }
 
     /// Calculate cost for token usage
@@ -70,7 +118,7 @@ impl ModelPricing {
 }
 
 /// Detailed cost breakdown for a request
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, ToSchema)]
 pub struct CostBreakdown {
     pub input_tokens: u32,
     pub output_tokens: u32,
@@ -93,13 +141,277 @@ impl CostBreakdown {
     }
 }
 
+/// Where a model's pricing came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PricingSource {
+    /// MODEL_PRICING
+    Override,
+    /// OpenRouter's model list
+    Openrouter,
+    /// The table built into flywatch
+    Builtin,
+    /// Unknown model, priced like Kimi K2
+    Default,
+}
+
+/// One `MODEL_PRICING` entry: `model=input/output`, in USD per million tokens
+#[derive(Debug, Clone, PartialEq)]
+pub struct PriceOverride {
+    pub model: String,
+    pub pricing: ModelPricing,
+}
+
+impl FromStr for PriceOverride {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (model, prices) = s
+            .rsplit_once('=')
+            .filter(|(model, _)| !model.trim().is_empty())
+            .ok_or_else(|| format!("expected model=input/output, found '{}'", s))?;
+        let price = |value: &str| {
+            value
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|p| *p >= 0.0)
+                .ok_or_else(|| format!("invalid price '{}' for {}", value, model.trim()))
+        };
+        let (input, output) = prices
+            .split_once('/')
+            .ok_or_else(|| format!("expected input/output prices for {}", model.trim()))?;
+        Ok(Self {
+            model: model.trim().to_string(),
+            pricing: ModelPricing {
+                input_per_million: price(input)?,
+                output_per_million: price(output)?,
+            },
+        })
+    }
+}
+
+#[derive(Default)]
+struct Fetched {
+    models: HashMap<String, ModelPricing>,
+    at: Option<DateTime<Utc>>,
+    error: Option<String>,
+}
+
+/// Prices from every source, with OpenRouter's list cached between fetches
+#[derive(Default)]
+pub struct PricingCatalog {
+    fetched: RwLock<Fetched>,
+}
+
+impl PricingCatalog {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    /// Pricing for `model` from the first source that has it
+    pub fn lookup(&self, config: &Config, model: &str) -> (ModelPricing, PricingSource) {
+        resolve(&self.fetched.read().unwrap(), config, model)
+    }
+
+    pub fn for_model(&self, config: &Config, model: &str) -> ModelPricing {
+        self.lookup(config, model).0
+    }
+
+    /// Every known model, each with the price the cost guard would use
+    pub fn catalog(&self, config: &Config) -> PricingCatalogView {
+        let fetched = self.fetched.read().unwrap();
+        let names: BTreeSet<&str> = config
+            .model_pricing
+            .iter()
+            .map(|o| o.model.as_str())
+            .chain(fetched.models.keys().map(String::as_str))
+            .chain(BUILTIN.iter().map(|(name, _, _)| *name))
+            .collect();
+        let models = names
+            .into_iter()
+            .map(|model| {
+                let (pricing, source) = resolve(&fetched, config, model);
+                PriceEntry {
+                    model: model.to_string(),
+                    input_per_million: pricing.input_per_million,
+                    output_per_million: pricing.output_per_million,
+                    source,
+                }
+            })
+            .collect();
+        PricingCatalogView {
+            default: PriceEntry {
+                model: "*".to_string(),
+                input_per_million: DEFAULT_PRICING.input_per_million,
+                output_per_million: DEFAULT_PRICING.output_per_million,
+                source: PricingSource::Default,
+            },
+            fetched_at: fetched.at,
+            fetch_error: fetched.error.clone(),
+            models,
+        }
+    }
+
+    /// Fetch OpenRouter's prices now and then every `interval_secs`; a
+    /// failed fetch keeps the previous list
+    pub async fn refresh(self: Arc<Self>, config: Arc<ConfigStore>, interval_secs: u64) {
+        if interval_secs == 0 {
+            return;
+        }
+        let client = match reqwest::Client::builder().timeout(FETCH_TIMEOUT).build() {
+            Ok(client) => client,
+            Err(e) => {
+                warn!(error = %e, "Cannot create pricing HTTP client");
+                return;
+            }
+        };
+        let mut interval = tokio::time::interval(Duration::from_secs(interval_secs));
+        loop {
+            interval.tick().await;
+            // Prices only matter while chat is configured
+            let current = config.current();
+            if current.openrouter_api_key.is_none() {
+                continue;
+            }
+            let result = fetch_models(&client, &current.openrouter_base_url).await;
+            let mut fetched = self.fetched.write().unwrap();
+            match result {
+                Ok(models) => {
+                    info!(models = models.len(), "Fetched OpenRouter pricing");
+                    *fetched = Fetched {
+                        models,
+                        at: Some(Utc::now()),
+                        error: None,
+                    };
+                }
+                Err(e) => {
+                    warn!(error = %e, "Failed to fetch OpenRouter pricing");
+                    fetched.error = Some(e);
+                }
+            }
+        }
+    }
+}
+
+fn resolve(fetched: &Fetched, config: &Config, model: &str) -> (ModelPricing, PricingSource) {
+    if let Some(o) = config.model_pricing.iter().find(|o| o.model == model) {
+        return (o.pricing, PricingSource::Override);
+    }
+    if let Some(pricing) = fetched.models.get(model) {
+        return (*pricing, PricingSource::Openrouter);
+    }
+    match ModelPricing::builtin(model) {
+        Some(pricing) => (pricing, PricingSource::Builtin),
+        None => (DEFAULT_PRICING, PricingSource::Default),
+    }
+}
+
+#[derive(Deserialize)]
+struct ModelList {
+    data: Vec<ListedModel>,
+}
+
+#[derive(Deserialize)]
+struct ListedModel {
+    id: String,
+    pricing: Option<ListedPricing>,
+}
+
+/// USD per token, as decimal strings
+#[derive(Deserialize)]
+struct ListedPricing {
+    prompt: String,
+    completion: String,
+}
+
+async fn fetch_models(
+    client: &reqwest::Client,
+    base_url: &str,
+) -> Result<HashMap<String, ModelPricing>, String> {
+    let list: ModelList = client
+        .get(format!("{}/models", base_url))
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| e.to_string())?
+        .json()
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(parse_models(list))
+}
+
+/// Per-million prices for every model with a fixed price; routers such as
+/// `openrouter/auto` list -1 and are left out
+fn parse_models(list: ModelList) -> HashMap<String, ModelPricing> {
+    list.data
+        .into_iter()
+        .filter_map(|model| {
+            let pricing = model.pricing?;
+            let per_million = |value: &str| {
+                value
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|p| *p >= 0.0)
+                    .map(|p| p * 1_000_000.0)
+            };
+            Some((
+                model.id,
+                ModelPricing {
+                    input_per_million: per_million(&pricing.prompt)?,
+                    output_per_million: per_million(&pricing.completion)?,
+                },
+            ))
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct PriceEntry {
+    pub model: String,
+    /// USD per million prompt tokens
+    pub input_per_million: f64,
+    /// USD per million completion tokens
+    pub output_per_million: f64,
+    pub source: PricingSource,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct PricingCatalogView {
+    /// What models not listed here are charged
+    pub default: PriceEntry,
+    /// Last successful fetch of OpenRouter's list
+    pub fetched_at: Option<DateTime<Utc>>,
+    /// Why the last fetch failed, when it did
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fetch_error: Option<String>,
+    /// Sorted by model
+    pub models: Vec<PriceEntry>,
+}
+
+#[utoipa::path(
+    get, path = "/pricing", tag = "usage",
+    responses(
+        (status = 200, description = "Pricing for every known model and where it came from", body = PricingCatalogView),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+pub async fn pricing_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<PricingCatalogView>, ApiError> {
+    check_auth(&state, &headers)?;
+    Ok(Json(state.pricing.catalog(&state.config.current())))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
     fn test_kimi_k2_pricing() {
-        let pricing = ModelPricing::for_model("moonshotai/kimi-k2");
+        let pricing = ModelPricing::builtin("moonshotai/kimi-k2").unwrap();
         let cost = pricing.calculate_cost(1000, 500);
 
         // 1000 input tokens at $0.456/M = $0.000456
@@ -109,9 +421,48 @@ mod tests {
     }
 
     #[test]
-    fn test_unknown_model_uses_default() {
-        let pricing = ModelPricing::for_model("unknown/model");
-        assert_eq!(pricing.input_per_million, 0.456);
-        assert_eq!(pricing.output_per_million, 1.84);
+    fn test_estimate_max_cost() {
+        // 1M prompt tokens plus 4096 completion tokens on Opus
+        let cost = ModelPricing::builtin("anthropic/claude-3-opus")
+            .unwrap()
+            .estimate_max_cost(1_000_000, 4096);
+        assert!((cost - (15.0 + 4096.0 * 75.0 / 1_000_000.0)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_catalog_sources_in_order() {
+        let config = Config::for_tests("model_pricing = [\"openai/gpt-4o=2/8\"]");
+        let catalog = PricingCatalog::default();
+        let list: ModelList = serde_json::from_str(
+            r#"{"data": [
+                {"id": "openai/gpt-4o", "pricing": {"prompt": "0.0000025", "completion": "0.00001"}},
+                {"id": "google/gemini-flash", "pricing": {"prompt": "0.0000001", "completion": "0.0000004"}},
+                {"id": "openrouter/auto", "pricing": {"prompt": "-1", "completion": "-1"}}
+            ]}"#,
+        )
+        .unwrap();
+        catalog.fetched.write().unwrap().models = parse_models(list);
+
+        let (pricing, source) = catalog.lookup(&config, "openai/gpt-4o");
+        assert_eq!(source, PricingSource::Override);
+        assert_eq!(pricing.output_per_million, 8.0);
+        let (pricing, source) = catalog.lookup(&config, "google/gemini-flash");
+        assert_eq!(source, PricingSource::Openrouter);
+        assert!((pricing.input_per_million - 0.1).abs() < 1e-9);
+        assert_eq!(
+            catalog.lookup(&config, "openai/gpt-4o-mini").1,
+            PricingSource::Builtin
+        );
+        let (pricing, source) = catalog.lookup(&config, "openrouter/auto");
+        assert_eq!((pricing, source), (DEFAULT_PRICING, PricingSource::Default));
+
+        let view = catalog.catalog(&config);
+        assert!(view.models.windows(2).all(|w| w[0].model < w[1].model));
+        assert!(view.models.iter().any(|e| e.model == "moonshotai/kimi-k2"));
+        assert!(!view.models.iter().any(|e| e.model == "openrouter/auto"));
+
+        assert!("openai/gpt-4o=2".parse::<PriceOverride>().is_err());
+        assert!("=2/8".parse::<PriceOverride>().is_err());
+        assert!("openai/gpt-4o=-1/8".parse::<PriceOverride>().is_err());
     }
 }
@@ -0,0 +1,356 @@
+//! Prometheus text exposition of the metrics snapshot
+
+use std::fmt::Write;
+
+use crate::log_metrics::LogMetricKind;
+use crate::metrics::MetricsSnapshot;
+use crate::sink::SinkHealthSnapshot;
+use crate::source::SourceHealthSnapshot;
+
+type SourceCounter = fn(&SourceHealthSnapshot) -> u64;
+type SinkCounter = fn(&SinkHealthSnapshot) -> u64;
+
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+struct Exposition {
+    out: String,
+}
+
+impl Exposition {
+    fn family(&mut self, name: &str, kind: &str, help: &str) {
+        let _ = writeln!(self.out, "# HELP {} {}", name, help);
+        let _ = writeln!(self.out, "# TYPE {} {}", name, kind);
+    }
+
+    fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: impl Into<f64>) {
+        self.out.push_str(name);
+        if !labels.is_empty() {
+            let labels: Vec<String> = labels
+                .iter()
+                .map(|(key, value)| format!("{}=\"{}\"", key, escape(value)))
+                .collect();
+            let _ = write!(self.out, "{{{}}}", labels.join(","));
+        }
+        let _ = writeln!(self.out, " {}", format_value(value.into()));
+    }
+
+    fn single(&mut self, name: &str, kind: &str, help: &str, value: impl Into<f64>) {
+        self.family(name, kind, help);
+        self.sample(name, &[], value);
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn format_value(value: f64) -> String {
+    if value.is_infinite() {
+        if value > 0.0 { "+Inf" } else { "-Inf" }.to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+/// Render `snapshot` (with its HTTP-layer fields filled in) for a scraper
+pub fn render(snapshot: &MetricsSnapshot) -> String {
+    let mut e = Exposition { out: String::new() };
+
+    e.single(
+        "flywatch_uptime_seconds",
+        "gauge",
+        "Seconds since flywatch started",
+        snapshot.uptime_seconds as f64,
+    );
+    e.single(
+        "flywatch_nats_connected",
+        "gauge",
+        "1 while connected to NATS",
+        u8::from(snapshot.nats_connected),
+    );
+    if let Some(rtt_ms) = snapshot.nats_rtt_ms {
+        e.single(
+            "flywatch_nats_rtt_seconds",
+            "gauge",
+            "Round trip of the last successful NATS probe",
+            rtt_ms / 1000.0,
+        );
+    }
+    if let Some(at) = snapshot.nats_last_ping_at {
+        e.single(
+            "flywatch_nats_last_ping_timestamp_seconds",
+            "gauge",
+            "Unix time of the last successful NATS probe",
+            at.timestamp() as f64,
+        );
+    }
+    e.single(
+        "flywatch_nats_ping_failures_total",
+        "counter",
+        "NATS round-trip probes that failed or timed out",
+        snapshot.nats_ping_failures as f64,
+    );
+    e.single(
+        "flywatch_subscription_errors_total",
+        "counter",
+        "NATS connect and subscription failures",
+        snapshot.subscription_errors as f64,
+    );
+    e.single(
+        "flywatch_messages_forwarded_total",
+        "counter",
+        "Log lines buffered and broadcast",
+        snapshot.messages_forwarded as f64,
+    );
+    e.single(
+        "flywatch_messages_filtered_total",
+        "counter",
+        "NATS messages dropped or sampled out by ingest rules",
+        snapshot.messages_filtered as f64,
+    );
+    e.single(
+        "flywatch_lines_truncated_total",
+        "counter",
+        "NATS messages cut down to MAX_LINE_BYTES",
+        snapshot.lines_truncated as f64,
+    );
+    e.single(
+        "flywatch_sse_connections_total",
+        "counter",
+        "SSE connections accepted",
+        snapshot.sse_connections_total as f64,
+    );
+    e.single(
+        "flywatch_ws_connections_total",
+        "counter",
+        "WebSocket connections accepted",
+        snapshot.ws_connections_total as f64,
+    );
+    e.single(
+        "flywatch_active_sse_connections",
+        "gauge",
+        "Open SSE connections",
+        snapshot.active_sse_connections as f64,
+    );
+    e.single(
+        "flywatch_active_ws_connections",
+        "gauge",
+        "Open WebSocket connections",
+        snapshot.active_ws_connections as f64,
+    );
+
+    e.family(
+        "flywatch_consumer_messages_total",
+        "counter",
+        "Messages offered to each broadcast consumer class",
+    );
+    for c in &snapshot.consumer_drops {
+        e.sample(
+            "flywatch_consumer_messages_total",
+            &[("consumer", c.consumer)],
+            c.messages as f64,
+        );
+    }
+    e.family(
+        "flywatch_consumer_dropped_total",
+        "counter",
+        "Messages lost to lag or a full queue",
+    );
+    for c in &snapshot.consumer_drops {
+        e.sample(
+            "flywatch_consumer_dropped_total",
+            &[("consumer", c.consumer)],
+            c.dropped as f64,
+        );
+    }
+
+    e.family(
+        "flywatch_origin_messages_total",
+        "counter",
+        "Log lines ingested per region and instance",
+    );
+    for o in &snapshot.origins {
+        e.sample(
+            "flywatch_origin_messages_total",
+            &[("region", &o.region), ("instance", &o.instance)],
+            o.messages as f64,
+        );
+    }
+    e.family(
+        "flywatch_origin_errors_total",
+        "counter",
+        "Error-level log lines per region and instance",
+    );
+    for o in &snapshot.origins {
+        e.sample(
+            "flywatch_origin_errors_total",
+            &[("region", &o.region), ("instance", &o.instance)],
+            o.errors as f64,
+        );
+    }
+
+    let source_counters: [(&str, &str, SourceCounter); 4] = [
+        (
+            "flywatch_source_messages_total",
+            "Lines received per source",
+            |s| s.messages_received,
+        ),
+        (
+            "flywatch_source_errors_total",
+            "Non-fatal errors per source",
+            |s| s.errors,
+        ),
+        (
+            "flywatch_source_restarts_total",
+            "Supervisor restarts per source",
+            |s| s.restarts,
+        ),
+        ("flywatch_source_panics_total", "Panics per source", |s| {
+            s.panics
+        }),
+    ];
+    for (name, help, value) in source_counters {
+        e.family(name, "counter", help);
+        for source in &snapshot.sources {
+            e.sample(
+                name,
+                &[("source", &source.name), ("kind", source.kind)],
+                value(source) as f64,
+            );
+        }
+    }
+
+    let sink_counters: [(&str, &str, SinkCounter); 5] = [
+        (
+            "flywatch_sink_delivered_total",
+            "Lines delivered per sink",
+            |s| s.delivered,
+        ),
+        (
+            "flywatch_sink_dropped_total",
+            "Lines dropped because a sink's queue was full",
+            |s| s.dropped,
+        ),
+        (
+            "flywatch_sink_retries_total",
+            "Batch redeliveries per sink",
+            |s| s.retries,
+        ),
+        (
+            "flywatch_sink_failed_total",
+            "Lines a sink gave up on",
+            |s| s.failed,
+        ),
+        (
+            "flywatch_sink_dead_lettered_total",
+            "Failed lines written to a sink's dead-letter file",
+            |s| s.dead_lettered,
+        ),
+    ];
+    for (name, help, value) in sink_counters {
+        e.family(name, "counter", help);
+        for sink in &snapshot.sinks {
+            e.sample(name, &[("sink", sink.kind)], value(sink) as f64);
+        }
+    }
+    e.family(
+        "flywatch_sink_queue_depth",
+        "gauge",
+        "Lines waiting in each sink's queue",
+    );
+    for sink in &snapshot.sinks {
+        e.sample(
+            "flywatch_sink_queue_depth",
+            &[("sink", sink.kind)],
+            sink.queue_depth as f64,
+        );
+    }
+
+    if let Some(ref system) = snapshot.system {
+        e.single(
+            "flywatch_cpu_usage_percent",
+            "gauge",
+            "Host CPU usage",
+            system.cpu_usage_percent,
+        );
+        e.single(
+            "flywatch_memory_used_bytes",
+            "gauge",
+            "Host memory in use",
+            system.memory_used_bytes as f64,
+        );
+        e.single(
+            "flywatch_memory_total_bytes",
+            "gauge",
+            "Host memory",
+            system.memory_total_bytes as f64,
+        );
+    }
+
+    for metric in &snapshot.log_metrics {
+        let base = format!("flywatch_log_{}", metric.name);
+        match metric.kind {
+            LogMetricKind::Counter => {
+                let name = format!("{}_total", base);
+                e.single(&name, "counter", "Log-based counter", metric.count as f64);
+            }
+            LogMetricKind::Histogram => {
+                e.family(&base, "histogram", "Log-based histogram");
+                let bucket = format!("{}_bucket", base);
+                for b in &metric.buckets {
+                    e.sample(&bucket, &[("le", &format_value(b.le))], b.count as f64);
+                }
+                e.sample(&bucket, &[("le", "+Inf")], metric.count as f64);
+                e.sample(&format!("{}_sum", base), &[], metric.sum.unwrap_or(0.0));
+                e.sample(&format!("{}_count", base), &[], metric.count as f64);
+            }
+        }
+    }
+
+    e.out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::log_metrics::{BucketCount, LogMetricSnapshot};
+    use crate::metrics::Metrics;
+
+    #[tokio::test]
+    async fn test_renders_core_and_log_metrics() {
+        let metrics = Metrics::new();
+        metrics.increment_messages_forwarded();
+        metrics.record_origin(Some("iad"), Some("148e"), true);
+        let mut snapshot = metrics.snapshot(std::time::Instant::now()).await;
+        snapshot.log_metrics = vec![
+            LogMetricSnapshot {
+                name: "http_5xx".to_string(),
+                kind: LogMetricKind::Counter,
+                count: 3,
+                sum: None,
+                buckets: Vec::new(),
+            },
+            LogMetricSnapshot {
+                name: "latency_ms".to_string(),
+                kind: LogMetricKind::Histogram,
+                count: 2,
+                sum: Some(62.5),
+                buckets: vec![BucketCount { le: 50.0, count: 1 }],
+            },
+        ];
+
+        let text = render(&snapshot);
+        assert!(text.contains("# TYPE flywatch_messages_forwarded_total counter\n"));
+        assert!(text.contains("\nflywatch_messages_forwarded_total 1\n"));
+        assert!(
+            text.contains("\nflywatch_origin_errors_total{region=\"iad\",instance=\"148e\"} 1\n")
+        );
+        assert!(text.contains("\nflywatch_log_http_5xx_total 3\n"));
+        assert!(text.contains("\nflywatch_log_latency_ms_bucket{le=\"50\"} 1\n"));
+        assert!(text.contains("\nflywatch_log_latency_ms_bucket{le=\"+Inf\"} 2\n"));
+        assert!(text.contains("\nflywatch_log_latency_ms_sum 62.5\n"));
+    }
+}
@@ -1,5 +1,7 @@
+use crate::config::Config;
 use crate::log_buffer::{LogSummary, TimestampedLog};
 use crate::metrics::MetricsSnapshot;
+use crate::text::{prefix_chars, truncate_chars};
 
 /// Format a duration in human-readable form
 fn format_duration(seconds: u64) -> String {
@@ -68,30 +70,32 @@ pub fn format_log_compact(log: &TimestampedLog) -> String {
         })
         .unwrap_or("----");
 
+    // Truncate long instance IDs
     let instance = log
         .instance
-        .as_ref()
-        .map(|i| {
-            // Truncate long instance IDs
-            if i.len() > 12 {
-                &i[..12]
-            } else {
-                i.as_str()
-            }
-        })
-        .unwrap_or("unknown");
+        .as_deref()
+        .map_or("unknown", |i| prefix_chars(i, 12));
 
     let region = log.region.as_deref().unwrap_or("---");
 
     let message = log.message.as_deref().unwrap_or(&log.raw);
     // Truncate very long messages
-    let message = if message.len() > 200 {
-        format!("{}...", &message[..197])
+    let message = if message.chars().count() > 200 {
+        truncate_chars(message, 197)
     } else {
         message.to_string()
     };
 
-    format!("[{}] {} {} {}: {}", time, level, instance, region, message)
+    // Show ids the agent can pass to get_trace when the message hides them
+    let trace = match log.trace_id.as_ref().or(log.request_id.as_ref()) {
+        Some(id) if !message.contains(id.as_str()) => format!(" [trace:{}]", id),
+        _ => String::new(),
+    };
+
+    format!(
+        "#{} [{}] {} {} {}: {}{}",
+        log.seq, time, level, instance, region, message, trace
+    )
 }
 
 /// Format multiple logs in compact form
@@ -106,14 +110,17 @@ pub fn format_logs_compact(logs: &[TimestampedLog]) -> String {
         .join("\n")
 }
 
-/// Build the initial context for the AI (compressed summary)
-pub fn build_initial_context(metrics: &MetricsSnapshot, summary: &LogSummary, recent_logs: &[TimestampedLog]) -> String {
+/// Build the initial context for the AI (compressed summary). Tenants get
+/// no `metrics`, which describe the whole process.
+pub fn build_initial_context(metrics: Option<&MetricsSnapshot>, summary: &LogSummary, recent_logs: &[TimestampedLog]) -> String {
     let mut context = String::with_capacity(2000);
 
     // Current state line
     context.push_str("## Current State\n");
-    context.push_str(&format_metrics_compact(metrics));
-    context.push('\n');
+    if let Some(metrics) = metrics {
+        context.push_str(&format_metrics_compact(metrics));
+        context.push('\n');
+    }
 
     // Log buffer summary
     let time_range = match (summary.oldest_timestamp, summary.newest_timestamp) {
@@ -144,13 +151,7 @@ pub fn build_initial_context(metrics: &MetricsSnapshot, summary: &LogSummary, re
             .active_instances
             .iter()
             .take(5)
-            .map(|s| {
-                if s.len() > 12 {
-                    &s[..12]
-                } else {
-                    s.as_str()
-                }
-            })
+            .map(|s| prefix_chars(s, 12))
             .collect();
         context.push_str(&format!("\nActive instances: {}\n", instances.join(", ")));
     }
@@ -165,9 +166,8 @@ pub fn build_initial_context(metrics: &MetricsSnapshot, summary: &LogSummary, re
     context
 }
 
-/// Build the system prompt for the AI
-pub fn build_system_prompt() -> &'static str {
-    r#"You are the Synthesys Logs Agent - a production observability assistant for the Synthesys backend.
+/// Built-in prompt; `{app}` is replaced with the configured service name
+const DEFAULT_SYSTEM_PROMPT: &str = r#"You are the {app} Logs Agent - a production observability assistant for {app}.
 
 ## Tools
 
@@ -175,6 +175,7 @@ pub fn build_system_prompt() -> &'static str {
 ```json
 {"count": 100}        // last N logs
 {"minutes": 10}       // logs from last N minutes
+{"view": "payment-errors"}  // a saved view's lines (when views exist)
 ```
 
 **get_metrics** - Fetch system metrics
@@ -182,6 +183,26 @@ pub fn build_system_prompt() -> &'static str {
 {"type": "all"}       // cpu | memory | connections | all
 ```
 
+**get_trace** - Fetch every line of one request across instances
+```json
+{"trace_id": "4bf92f35"}  // trace or request id from a log line
+```
+
+**get_http_analytics** - Requests, status codes and latency per route from access logs
+```json
+{"minutes": 15, "limit": 10}
+```
+
+**get_deploys** - Recent deploys with error counts before and after each
+```json
+{"limit": 5}
+```
+
+**get_facets** - Which levels, instances, regions, apps or sources appear, with line counts
+```json
+{"fields": ["region", "instance"], "minutes": 15}
+```
+
 ## Behavior
 - Analyze provided context first; only call tools when more data is needed
 - Be concise and direct - respond in 2-4 sentences when possible
@@ -189,12 +210,50 @@ pub fn build_system_prompt() -> &'static str {
 - For patterns: note frequency and timeline
 - For metrics: highlight anomalies and thresholds
 
-Keep responses tight and actionable. The user is an engineer."#
+Keep responses tight and actionable. The user is an engineer."#;
+
+/// Build the system prompt for the AI: the configured (or built-in) base
+/// prompt, followed by any service notes, vocabulary and runbook links
+pub fn build_system_prompt(config: &Config) -> String {
+    let base = config
+        .system_prompt
+        .as_deref()
+        .unwrap_or(DEFAULT_SYSTEM_PROMPT);
+    let mut prompt = base.trim_end().replace("{app}", &config.prompt_app_name);
+
+    if config.runbook_dir.is_some() || !config.runbook_urls.is_empty() {
+        prompt.push_str(
+            "\n\n**search_runbooks** - Search our runbooks before suggesting a fix, \
+             and cite the runbook you used\n```json\n{\"query\": \"connection pool exhausted\"}\n```",
+        );
+    }
+
+    if let Some(context) = &config.prompt_context {
+        prompt.push_str(&format!(
+            "\n\n## About {}\n{}",
+            config.prompt_app_name,
+            context.trim()
+        ));
+    }
+    if !config.prompt_vocabulary.is_empty() {
+        prompt.push_str("\n\n## Domain Vocabulary\n");
+        prompt.push_str(&bullets(&config.prompt_vocabulary));
+    }
+    if !config.prompt_runbooks.is_empty() {
+        prompt.push_str(
+            "\n\n## Runbooks\nPoint the user to the relevant runbook when one applies:\n",
+        );
+        prompt.push_str(&bullets(&config.prompt_runbooks));
+    }
+    prompt
 }
 
-/// Format tool results for inclusion in the conversation
-pub fn format_tool_result(tool_name: &str, result: &str) -> String {
-    format!("## Tool Result: {}\n{}", tool_name, result)
+fn bullets(items: &[String]) -> String {
+    items
+        .iter()
+        .map(|item| format!("- {}", item))
+        .collect::<Vec<_>>()
+        .join("\n")
 }
 
 #[cfg(test)]
@@ -217,21 +276,58 @@ mod tests {
         assert_eq!(format_bytes(1_500_000_000), "1.4GB");
     }
 
+    #[test]
+    fn test_format_log_compact_cuts_multibyte_text() {
+        let mut log = TimestampedLog::new("🔥".repeat(300), 1);
+        log.instance = Some("é".repeat(20));
+        let formatted = format_log_compact(&log);
+        assert!(formatted.contains(&format!(" {} ", "é".repeat(12))));
+        assert!(formatted.ends_with(&format!("{}...", "🔥".repeat(197))));
+    }
+
     #[test]
     fn test_format_log_compact() {
         let log = TimestampedLog {
+            seq: 1,
             timestamp: Utc::now(),
+            received_at: Utc::now(),
             raw: "test".to_string(),
             level: Some("INFO".to_string()),
             instance: Some("web-abc123".to_string()),
             region: Some("iad".to_string()),
             message: Some("Request completed".to_string()),
+            app: None,
+            source: None,
+            trace_id: Some("4bf92f35".to_string()),
+            span_id: None,
+            request_id: None,
+            fields: Default::default(),
         };
 
         let formatted = format_log_compact(&log);
         assert!(formatted.contains("INFO"));
         assert!(formatted.contains("web-abc123"));
         assert!(formatted.contains("iad"));
-        assert!(formatted.contains("Request completed"));
+        assert!(formatted.ends_with("Request completed [trace:4bf92f35]"));
+    }
+
+    #[test]
+    fn test_system_prompt_customization() {
+        let prompt = build_system_prompt(&Config::for_tests(""));
+        assert!(prompt.starts_with("You are the app Logs Agent"));
+
+        let config = Config::for_tests(
+            r#"
+prompt_app_name = "Checkout"
+system_prompt = "You watch {app}."
+prompt_context = "Payments run through Stripe."
+prompt_vocabulary = ["PSP: payment service provider"]
+prompt_runbooks = ["Stripe outage: https://wiki.example.com/stripe"]
+"#,
+        );
+        let prompt = build_system_prompt(&config);
+        assert!(prompt.starts_with("You watch Checkout.\n\n## About Checkout\n"));
+        assert!(prompt.contains("## Domain Vocabulary\n- PSP: payment service provider"));
+        assert!(prompt.contains("- Stripe outage: https://wiki.example.com/stripe"));
     }
 }
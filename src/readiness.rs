@@ -0,0 +1,202 @@
+use axum::{extract::State, http::StatusCode, Json};
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+use utoipa::ToSchema;
+
+use crate::http::AppState;
+use crate::metrics::METRICS_UPDATE_INTERVAL;
+use crate::nats::LogMessage;
+
+/// Broadcast depth (as a fraction of capacity) that counts as saturated
+const SATURATION_RATIO: f64 = 0.9;
+/// How long the channel may stay saturated before readiness fails
+const SATURATION_GRACE: Duration = Duration::from_secs(30);
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+/// The metrics updater is considered stalled after missing this many ticks
+const MISSED_UPDATES: u32 = 3;
+/// NATS is considered stalled after this many round-trip probes fail in a row
+const MISSED_PINGS: u64 = 3;
+
+/// Samples the log broadcast channel so readiness can tell a momentary
+/// burst from a channel that has stopped draining
+pub struct BroadcastMonitor {
+    tx: broadcast::Sender<LogMessage>,
+    capacity: usize,
+    saturated_since: Mutex<Option<Instant>>,
+}
+
+impl BroadcastMonitor {
+    pub fn new(tx: broadcast::Sender<LogMessage>, capacity: usize) -> Arc<Self> {
+        Arc::new(Self {
+            tx,
+            capacity,
+            saturated_since: Mutex::new(None),
+        })
+    }
+
+    pub fn start(self: &Arc<Self>) {
+        let monitor = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(SAMPLE_INTERVAL);
+            loop {
+                interval.tick().await;
+                monitor.sample(monitor.tx.len());
+            }
+        });
+    }
+
+    fn sample(&self, depth: usize) {
+        let saturated = depth as f64 >= self.capacity as f64 * SATURATION_RATIO;
+        let mut since = self.saturated_since.lock().unwrap();
+        match (saturated, *since) {
+            (true, None) => *since = Some(Instant::now()),
+            (false, Some(_)) => *since = None,
+            _ => {}
+        }
+    }
+
+    fn check(&self) -> ReadinessCheck {
+        let depth = self.tx.len();
+        let detail = format!("{}/{} queued", depth, self.capacity);
+        match *self.saturated_since.lock().unwrap() {
+            Some(since) if since.elapsed() >= SATURATION_GRACE => ReadinessCheck::fail(
+                "broadcast",
+                format!("{}, saturated for {}s", detail, since.elapsed().as_secs()),
+            ),
+            _ => ReadinessCheck::pass("broadcast", detail),
+        }
+    }
+}
+
+/// Outcome of one readiness check
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ReadinessCheck {
+    pub name: &'static str,
+    pub ok: bool,
+    pub detail: String,
+}
+
+impl ReadinessCheck {
+    fn pass(name: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            ok: true,
+            detail: detail.into(),
+        }
+    }
+
+    fn fail(name: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            ok: false,
+            detail: detail.into(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ReadinessReport {
+    /// "ready" when every check passes, otherwise "not_ready"
+    pub status: &'static str,
+    pub checks: Vec<ReadinessCheck>,
+}
+
+fn nats_check(state: &AppState) -> ReadinessCheck {
+    if !state.config.current().sources.iter().any(|s| s == "nats") {
+        return ReadinessCheck::pass("nats", "not a configured source");
+    }
+    if !state.metrics.is_nats_connected() {
+        return ReadinessCheck::fail("nats", "disconnected");
+    }
+    match state.metrics.nats_ping_failures_in_a_row() {
+        failures if failures >= MISSED_PINGS => ReadinessCheck::fail(
+            "nats",
+            format!(
+                "connected but stalled: {} probes in a row unanswered",
+                failures
+            ),
+        ),
+        _ => ReadinessCheck::pass("nats", "connected"),
+    }
+}
+
+fn store_check(state: &AppState) -> ReadinessCheck {
+    match state.log_buffer.probe_store() {
+        None => ReadinessCheck::pass("store", "persistence disabled"),
+        Some(Ok(())) => ReadinessCheck::pass("store", "writable"),
+        Some(Err(e)) => ReadinessCheck::fail("store", format!("write failed: {}", e)),
+    }
+}
+
+fn metrics_updater_check(state: &AppState) -> ReadinessCheck {
+    let limit = METRICS_UPDATE_INTERVAL * MISSED_UPDATES;
+    match state.metrics.system_metrics_age() {
+        Some(age) if age <= limit => {
+            ReadinessCheck::pass("metrics_updater", format!("updated {}s ago", age.as_secs()))
+        }
+        Some(age) => ReadinessCheck::fail(
+            "metrics_updater",
+            format!("last update {}s ago", age.as_secs()),
+        ),
+        // The first update runs at startup; allow it the same grace
+        None if state.start_time.elapsed() <= limit => {
+            ReadinessCheck::pass("metrics_updater", "starting")
+        }
+        None => ReadinessCheck::fail("metrics_updater", "never ran"),
+    }
+}
+
+pub fn report(state: &AppState) -> ReadinessReport {
+    let checks = vec![
+        nats_check(state),
+        store_check(state),
+        state.broadcast.check(),
+        metrics_updater_check(state),
+    ];
+    let ready = checks.iter().all(|c| c.ok);
+    ReadinessReport {
+        status: if ready { "ready" } else { "not_ready" },
+        checks,
+    }
+}
+
+/// Readiness probe with a per-check breakdown
+#[utoipa::path(
+    get, path = "/ready", tag = "health",
+    responses(
+        (status = 200, description = "Every check passed", body = ReadinessReport),
+        (status = 503, description = "At least one check failed", body = ReadinessReport),
+    )
+)]
+pub async fn ready_handler(State(state): State<AppState>) -> (StatusCode, Json<ReadinessReport>) {
+    let report = report(&state);
+    let status = if report.status == "ready" {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_saturation_needs_to_persist() {
+        let (tx, _rx) = broadcast::channel(10);
+        let monitor = BroadcastMonitor::new(tx, 10);
+
+        monitor.sample(9);
+        assert!(monitor.check().ok, "a burst alone is not a failure");
+
+        *monitor.saturated_since.lock().unwrap() = Some(Instant::now() - SATURATION_GRACE);
+        monitor.sample(10);
+        assert!(!monitor.check().ok);
+
+        monitor.sample(2);
+        assert!(monitor.check().ok);
+    }
+}
@@ -0,0 +1,382 @@
+//! Scrubbing of sensitive values out of log lines. JSON lines have matching
+//! string values and configured field paths replaced and are re-serialized
+//! (only when something changed); other lines are scrubbed as plain text.
+
+use regex::{Captures, Regex};
+use serde_json::Value;
+use std::borrow::Cow;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use tracing::error;
+
+use crate::config::Config;
+
+/// Replacement for configured patterns and field paths
+const REDACTED: &str = "[REDACTED]";
+
+/// Built-in detectors that can be switched on by name
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RedactKind {
+    Email,
+    /// Card numbers that pass the Luhn check
+    CreditCard,
+    /// Bearer tokens, `password=`/`api_key=` values and well-known key formats
+    Token,
+    Ip,
+}
+
+impl FromStr for RedactKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "email" => Ok(RedactKind::Email),
+            "credit_card" => Ok(RedactKind::CreditCard),
+            "token" => Ok(RedactKind::Token),
+            "ip" => Ok(RedactKind::Ip),
+            other => Err(format!(
+                "unknown kind '{}' (expected email, credit_card, token or ip)",
+                other
+            )),
+        }
+    }
+}
+
+impl fmt::Display for RedactKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            RedactKind::Email => "email",
+            RedactKind::CreditCard => "credit_card",
+            RedactKind::Token => "token",
+            RedactKind::Ip => "ip",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl RedactKind {
+    /// (pattern, replacement) pairs; replacements may reference groups
+    fn patterns(self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            RedactKind::Email => &[(
+                r"[A-Za-z0-9._%+-]+@[A-Za-z0-9-]+(?:\.[A-Za-z0-9-]+)*\.[A-Za-z]{2,}",
+                "[REDACTED:email]",
+            )],
+            RedactKind::CreditCard => &[(r"\b\d(?:[ -]?\d){12,18}\b", "[REDACTED:card]")],
+            RedactKind::Token => &[
+                (
+                    r"(?i)\b(bearer)\s+[A-Za-z0-9._~+/=-]{8,}",
+                    "$1 [REDACTED:token]",
+                ),
+                (
+                    r"(?i)\b(password|passwd|secret|token|api[_-]?key|access[_-]?key)(\s*[:=]\s*)[^\s&,;'\x22]+",
+                    "$1$2[REDACTED:token]",
+                ),
+                (
+                    r"\b(?:sk|pk|rk)_(?:live|test)_[A-Za-z0-9]{10,}|\bsk-[A-Za-z0-9_-]{20,}|\bgh[pousr]_[A-Za-z0-9]{20,}|\bAKIA[0-9A-Z]{16}\b|\beyJ[A-Za-z0-9_-]+\.[A-Za-z0-9_-]+\.[A-Za-z0-9_-]+",
+                    "[REDACTED:token]",
+                ),
+            ],
+            RedactKind::Ip => &[
+                (
+                    r"\b(?:(?:25[0-5]|2[0-4]\d|1?\d?\d)\.){3}(?:25[0-5]|2[0-4]\d|1?\d?\d)\b",
+                    "[REDACTED:ip]",
+                ),
+                // Full or `::`-compressed IPv6; plain `12:30:45` times don't match
+                (
+                    r"(?i)\b(?:[0-9a-f]{1,4}:){7}[0-9a-f]{1,4}\b|\b(?:[0-9a-f]{1,4}:){1,6}:(?:[0-9a-f]{1,4}:){0,5}[0-9a-f]{1,4}\b",
+                    "[REDACTED:ip]",
+                ),
+            ],
+        }
+    }
+}
+
+struct Pattern {
+    regex: Regex,
+    replacement: &'static str,
+    /// Only replace digit runs that pass the Luhn check
+    luhn: bool,
+}
+
+/// A compiled set of redaction rules
+#[derive(Default)]
+pub struct Rules {
+    patterns: Vec<Pattern>,
+    /// Dotted JSON paths whose values are replaced outright
+    fields: Vec<Vec<String>>,
+}
+
+impl Rules {
+    /// Compile the rules; `patterns` are expected to have been validated
+    /// with [`validate_pattern`] already and any that fail are skipped
+    pub fn new(kinds: &[RedactKind], patterns: &[String], fields: &[String]) -> Self {
+        let mut compiled = Vec::new();
+        for kind in kinds {
+            for (pattern, replacement) in kind.patterns() {
+                compiled.push(Pattern {
+                    regex: Regex::new(pattern).expect("built-in redaction pattern"),
+                    replacement,
+                    luhn: *kind == RedactKind::CreditCard,
+                });
+            }
+        }
+        for pattern in patterns {
+            match Regex::new(pattern) {
+                Ok(regex) => compiled.push(Pattern {
+                    regex,
+                    replacement: REDACTED,
+                    luhn: false,
+                }),
+                Err(e) => {
+                    error!(pattern = %pattern, error = %e, "Skipping invalid redaction pattern")
+                }
+            }
+        }
+        Self {
+            patterns: compiled,
+            fields: fields
+                .iter()
+                .map(|path| path.split('.').map(str::to_string).collect())
+                .collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty() && self.fields.is_empty()
+    }
+
+    /// Scrub one log line, keeping JSON lines valid JSON
+    pub fn redact_line(&self, raw: String) -> String {
+        if self.is_empty() {
+            return raw;
+        }
+        match serde_json::from_str::<Value>(&raw) {
+            Ok(mut value @ Value::Object(_)) => {
+                let mut changed = false;
+                for path in &self.fields {
+                    changed |= redact_path(&mut value, path);
+                }
+                changed |= self.redact_strings(&mut value);
+                if changed {
+                    value.to_string()
+                } else {
+                    raw
+                }
+            }
+            _ => match self.redact_text(&raw) {
+                Cow::Borrowed(_) => raw,
+                Cow::Owned(scrubbed) => scrubbed,
+            },
+        }
+    }
+
+    /// Scrub free text with the patterns (field paths don't apply)
+    pub fn redact_text<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let mut text = Cow::Borrowed(text);
+        for pattern in &self.patterns {
+            let replaced = if pattern.luhn {
+                pattern.regex.replace_all(&text, |caps: &Captures| {
+                    if luhn_valid(&caps[0]) {
+                        pattern.replacement.to_string()
+                    } else {
+                        caps[0].to_string()
+                    }
+                })
+            } else {
+                pattern.regex.replace_all(&text, pattern.replacement)
+            };
+            if let Cow::Owned(replaced) = replaced {
+                if replaced != *text {
+                    text = Cow::Owned(replaced);
+                }
+            }
+        }
+        text
+    }
+
+    fn redact_strings(&self, value: &mut Value) -> bool {
+        match value {
+            Value::String(s) => match self.redact_text(s) {
+                Cow::Borrowed(_) => false,
+                Cow::Owned(scrubbed) => {
+                    *s = scrubbed;
+                    true
+                }
+            },
+            Value::Array(items) => items
+                .iter_mut()
+                .fold(false, |changed, item| self.redact_strings(item) | changed),
+            Value::Object(map) => map
+                .values_mut()
+                .fold(false, |changed, item| self.redact_strings(item) | changed),
+            _ => false,
+        }
+    }
+}
+
+fn redact_path(value: &mut Value, path: &[String]) -> bool {
+    let Some((last, parents)) = path.split_last() else {
+        return false;
+    };
+    let mut current = value;
+    for key in parents {
+        current = match current {
+            Value::Object(map) => match map.get_mut(key) {
+                Some(next) => next,
+                None => return false,
+            },
+            Value::Array(items) => match key.parse::<usize>().ok().and_then(|i| items.get_mut(i)) {
+                Some(next) => next,
+                None => return false,
+            },
+            _ => return false,
+        };
+    }
+    match current {
+        Value::Object(map) => match map.get_mut(last) {
+            Some(target) if !target.is_null() => {
+                *target = Value::String(REDACTED.to_string());
+                true
+            }
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+fn luhn_valid(candidate: &str) -> bool {
+    let digits: Vec<u32> = candidate.chars().filter_map(|c| c.to_digit(10)).collect();
+    if !(13..=19).contains(&digits.len()) {
+        return false;
+    }
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, &d)| match (i % 2 == 1, d * 2) {
+            (true, doubled) if doubled > 9 => doubled - 9,
+            (true, doubled) => doubled,
+            (false, _) => d,
+        })
+        .sum();
+    sum.is_multiple_of(10)
+}
+
+/// Rules applied to every line before it is buffered, streamed or persisted
+pub fn ingest_rules(config: &Config) -> Rules {
+    Rules::new(
+        &config.redact_builtins,
+        &config.redact_patterns,
+        &config.redact_fields,
+    )
+}
+
+/// Rules for text sent to the model provider when `CHAT_PII_SAFE` is on
+pub fn chat_rules(config: &Config) -> Option<Rules> {
+    config.chat_pii_safe.then(|| {
+        Rules::new(
+            &[RedactKind::Ip, RedactKind::Email],
+            &config.chat_redact_patterns,
+            &[],
+        )
+    })
+}
+
+/// Check a user-supplied pattern compiles
+pub fn validate_pattern(pattern: &str) -> Result<(), String> {
+    Regex::new(pattern).map(|_| ()).map_err(|e| e.to_string())
+}
+
+/// The live ingest redaction rules, swapped on config reload
+#[derive(Default)]
+pub struct Redactor {
+    rules: RwLock<Arc<Rules>>,
+}
+
+impl Redactor {
+    pub fn new(rules: Rules) -> Arc<Self> {
+        Arc::new(Self {
+            rules: RwLock::new(Arc::new(rules)),
+        })
+    }
+
+    pub fn set_rules(&self, rules: Rules) {
+        *self.rules.write().unwrap() = Arc::new(rules);
+    }
+
+    pub fn redact_line(&self, raw: String) -> String {
+        let rules = self.rules.read().unwrap().clone();
+        rules.redact_line(raw)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn all_kinds() -> Rules {
+        Rules::new(
+            &[
+                RedactKind::Email,
+                RedactKind::CreditCard,
+                RedactKind::Token,
+                RedactKind::Ip,
+            ],
+            &[r"cust_[0-9]{6}".to_string()],
+            &["user.id".to_string()],
+        )
+    }
+
+    #[test]
+    fn test_redacts_text_with_builtins_and_patterns() {
+        let rules = all_kinds();
+        let text = "charge by jane.doe@example.com card 4111 1111 1111 1111 order 1234567890123 \
+                    from 10.0.3.7 auth Bearer abcdef123456 password=hunter2 for cust_004211";
+        assert_eq!(
+            rules.redact_text(text),
+            "charge by [REDACTED:email] card [REDACTED:card] order 1234567890123 \
+             from [REDACTED:ip] auth Bearer [REDACTED:token] password=[REDACTED:token] for [REDACTED]"
+        );
+
+        // Nothing sensitive and no allocation
+        let clean = "GET /healthz 200 in 12:30:45";
+        assert!(matches!(rules.redact_text(clean), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_json_lines_stay_valid() {
+        let rules = all_kinds();
+        let raw = serde_json::json!({
+            "message": "login ok for jane@example.com",
+            "user": { "id": "u-991", "plan": "pro" },
+            "fly": { "region": "iad" },
+        })
+        .to_string();
+
+        let redacted: Value = serde_json::from_str(&rules.redact_line(raw)).unwrap();
+        assert_eq!(redacted["message"], "login ok for [REDACTED:email]");
+        assert_eq!(redacted["user"]["id"], REDACTED);
+        assert_eq!(redacted["user"]["plan"], "pro");
+
+        let untouched = r#"{"message":"all good","fly":{"region":"iad"}}"#;
+        assert_eq!(rules.redact_line(untouched.to_string()), untouched);
+        assert!("phone".parse::<RedactKind>().is_err());
+    }
+
+    #[test]
+    fn test_chat_rules_follow_pii_switch() {
+        assert!(chat_rules(&Config::for_tests("")).is_none());
+
+        let config =
+            Config::for_tests("chat_pii_safe = true\nchat_redact_patterns = [\"acct-[0-9]+\"]");
+        let rules = chat_rules(&config).unwrap();
+        assert_eq!(
+            rules.redact_text("acct-42 from 2001:db8::1 mailed ops@example.com at 10.1.2.3"),
+            "[REDACTED] from [REDACTED:ip] mailed [REDACTED:email] at [REDACTED:ip]"
+        );
+        // Only the provider-facing layer: card numbers are left to ingest rules
+        assert_eq!(rules.redact_text("4111111111111111"), "4111111111111111");
+    }
+}
@@ -0,0 +1,135 @@
+use axum::{extract::State, http::HeaderMap, Json};
+use serde::Serialize;
+use std::time::{Duration, SystemTime};
+use tracing::{info, warn};
+use utoipa::ToSchema;
+
+use crate::chaos;
+use crate::config::{Config, ConfigError, ReloadOutcome};
+use crate::error::{ApiError, ErrorBody};
+use crate::http::{check_auth, AppState};
+use crate::log_buffer::LogBufferConfig;
+use crate::redact;
+
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ReloadResponse {
+    pub applied: Vec<&'static str>,
+    pub restart_required: Vec<&'static str>,
+}
+
+/// Push reload-safe settings into the components that cache them.
+/// Auth and OpenRouter settings are read from the store on every request.
+/// Runbooks are re-read on every reload so edited docs are picked up.
+pub fn apply(state: &AppState, config: &Config) {
+    state.log_buffer.set_limits(LogBufferConfig {
+        max_entries: config.log_buffer_max_entries,
+        max_age_minutes: config.log_buffer_max_age_minutes,
+    });
+    state.fanout.set_defaults(
+        config.connection_queue_capacity,
+        config.connection_overflow_policy,
+    );
+    state.fanout.set_channels(&config.channels);
+    for tenant in state.tenants.iter() {
+        tenant.log_buffer.set_limits(LogBufferConfig {
+            max_entries: config.log_buffer_max_entries,
+            max_age_minutes: config.log_buffer_max_age_minutes,
+        });
+        tenant.fanout.set_defaults(
+            config.connection_queue_capacity,
+            config.connection_overflow_policy,
+        );
+        tenant.fanout.set_channels(&config.channels);
+    }
+    state.self_log.set_level(config.self_log_level);
+    state.ingest_filter.set_rules(&config.ingest_rules);
+    state.redactor.set_rules(redact::ingest_rules(config));
+    state.log_metrics.set_rules(&config.log_metrics);
+    state.slos.set_definitions(&config.slos);
+    state.heartbeats.set_definitions(&config.heartbeats);
+    state.alerts.configure(config);
+    state
+        .metrics
+        .set_drop_warning_percent(config.drop_warning_percent);
+    chaos::apply(state, config);
+
+    let runbooks = state.runbooks.clone();
+    let (dir, urls) = (config.runbook_dir.clone(), config.runbook_urls.clone());
+    tokio::spawn(async move { runbooks.reindex(dir, urls).await });
+}
+
+fn reload(state: &AppState) -> Result<ReloadOutcome, ConfigError> {
+    let outcome = state.config.reload()?;
+    apply(state, &outcome.config);
+    info!(
+        applied = ?outcome.applied,
+        restart_required = ?outcome.restart_required,
+        "Configuration reloaded"
+    );
+    if !outcome.restart_required.is_empty() {
+        warn!(
+            settings = ?outcome.restart_required,
+            "Some changed settings only take effect after a restart"
+        );
+    }
+    Ok(outcome)
+}
+
+/// POST /admin/reload - re-read the config file and environment
+#[utoipa::path(
+    post, path = "/admin/reload", tag = "admin",
+    responses(
+        (status = 200, description = "Settings applied and those needing a restart", body = ReloadResponse),
+        (status = 400, description = "Invalid configuration; the running config is kept", body = ErrorBody),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+pub async fn admin_reload_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ReloadResponse>, ApiError> {
+    check_auth(&state, &headers)?;
+
+    // The running config is kept when the new one is invalid
+    let outcome = reload(&state).map_err(|e| {
+        warn!("{}", e);
+        ApiError::InvalidConfig(e.problems)
+    })?;
+    Ok(Json(ReloadResponse {
+        applied: outcome.applied,
+        restart_required: outcome.restart_required,
+    }))
+}
+
+async fn modified(path: &std::path::Path) -> Option<SystemTime> {
+    tokio::fs::metadata(path).await.ok()?.modified().ok()
+}
+
+/// Poll the config file's mtime and reload whenever it changes. Invalid
+/// edits are logged and the running config is kept.
+pub async fn watch_config_file(state: AppState) {
+    let Some(path) = state.config.path().map(|p| p.to_path_buf()) else {
+        return;
+    };
+    info!(path = %path.display(), "Watching config file for changes");
+
+    let mut last_modified = modified(&path).await;
+    let mut interval = tokio::time::interval(WATCH_INTERVAL);
+    interval.tick().await;
+
+    loop {
+        interval.tick().await;
+        let current = modified(&path).await;
+        if current.is_none() || current == last_modified {
+            continue;
+        }
+        last_modified = current;
+
+        if let Err(e) = reload(&state) {
+            warn!(path = %path.display(), "Config reload failed: {}", e);
+        }
+    }
+}
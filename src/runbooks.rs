@@ -0,0 +1,338 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::text::truncate_chars;
+
+/// Runbook files picked up from RUNBOOK_DIR
+const RUNBOOK_EXTENSIONS: &[&str] = &["md", "markdown", "txt"];
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+/// Longest section text returned to the model per hit
+const MAX_HIT_CHARS: usize = 1500;
+
+// BM25 parameters
+const K1: f64 = 1.2;
+const B: f64 = 0.75;
+
+const STOPWORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "by", "for", "from", "how", "if", "in", "is", "it",
+    "of", "on", "or", "that", "the", "this", "to", "was", "what", "when", "with",
+];
+
+/// One heading-delimited section of a runbook
+#[derive(Debug)]
+struct Section {
+    source: String,
+    heading: String,
+    text: String,
+    terms: HashMap<String, u32>,
+    len: usize,
+}
+
+#[derive(Debug, Default)]
+struct Index {
+    sections: Vec<Section>,
+    /// Number of sections containing each term
+    document_freq: HashMap<String, usize>,
+    avg_len: f64,
+}
+
+impl Index {
+    fn build(documents: Vec<(String, String)>) -> Self {
+        let sections: Vec<Section> = documents
+            .iter()
+            .flat_map(|(source, content)| split_sections(source, content))
+            .collect();
+
+        let mut document_freq: HashMap<String, usize> = HashMap::new();
+        for section in &sections {
+            for term in section.terms.keys() {
+                *document_freq.entry(term.clone()).or_default() += 1;
+            }
+        }
+        let total: usize = sections.iter().map(|s| s.len).sum();
+        let avg_len = if sections.is_empty() {
+            0.0
+        } else {
+            total as f64 / sections.len() as f64
+        };
+
+        Self {
+            sections,
+            document_freq,
+            avg_len,
+        }
+    }
+
+    fn search(&self, query: &str, limit: usize) -> Vec<RunbookHit> {
+        let query_terms = tokenize(query);
+        let n = self.sections.len() as f64;
+
+        let mut scored: Vec<(f64, &Section)> = self
+            .sections
+            .iter()
+            .filter_map(|section| {
+                let score: f64 = query_terms
+                    .iter()
+                    .filter_map(|term| {
+                        let tf = *section.terms.get(term)? as f64;
+                        let df = self.document_freq[term] as f64;
+                        let idf = ((n - df + 0.5) / (df + 0.5) + 1.0).ln();
+                        let norm = 1.0 - B + B * section.len as f64 / self.avg_len;
+                        Some(idf * tf * (K1 + 1.0) / (tf + K1 * norm))
+                    })
+                    .sum();
+                (score > 0.0).then_some((score, section))
+            })
+            .collect();
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        scored
+            .into_iter()
+            .take(limit)
+            .map(|(_, section)| RunbookHit {
+                source: section.source.clone(),
+                heading: section.heading.clone(),
+                text: truncate_chars(&section.text, MAX_HIT_CHARS),
+            })
+            .collect()
+    }
+}
+
+/// A runbook section matching a search
+#[derive(Debug, Clone)]
+pub struct RunbookHit {
+    pub source: String,
+    pub heading: String,
+    pub text: String,
+}
+
+/// Keyword (BM25) index over markdown runbooks from a directory and/or URLs,
+/// searched by the chat agent's `search_runbooks` tool
+#[derive(Default)]
+pub struct RunbookIndex {
+    index: RwLock<Index>,
+}
+
+impl RunbookIndex {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.read().unwrap().sections.is_empty()
+    }
+
+    pub fn search(&self, query: &str, limit: usize) -> Vec<RunbookHit> {
+        self.index.read().unwrap().search(query, limit)
+    }
+
+    /// Re-read every runbook and swap in the new index. Unreadable files and
+    /// failed fetches are logged and skipped.
+    pub async fn reindex(&self, dir: Option<String>, urls: Vec<String>) {
+        if dir.is_none() && urls.is_empty() {
+            *self.index.write().unwrap() = Index::default();
+            return;
+        }
+
+        let mut documents = Vec::new();
+        if let Some(dir) = dir {
+            let root = PathBuf::from(&dir);
+            match tokio::task::spawn_blocking(move || read_dir_documents(&root)).await {
+                Ok(docs) => documents.extend(docs),
+                Err(e) => warn!(dir = %dir, error = %e, "Reading runbook directory failed"),
+            }
+        }
+        documents.extend(fetch_documents(&urls).await);
+
+        let count = documents.len();
+        let index = Index::build(documents);
+        info!(
+            documents = count,
+            sections = index.sections.len(),
+            "Runbooks indexed"
+        );
+        *self.index.write().unwrap() = index;
+    }
+}
+
+fn read_dir_documents(root: &Path) -> Vec<(String, String)> {
+    let mut documents = Vec::new();
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                warn!(dir = %dir.display(), error = %e, "Cannot read runbook directory");
+                continue;
+            }
+        };
+        for path in entries.flatten().map(|e| e.path()) {
+            if path.is_dir() {
+                pending.push(path);
+                continue;
+            }
+            let is_runbook = path
+                .extension()
+                .and_then(|e| e.to_str())
+                .is_some_and(|e| RUNBOOK_EXTENSIONS.contains(&e.to_ascii_lowercase().as_str()));
+            if !is_runbook {
+                continue;
+            }
+            match std::fs::read_to_string(&path) {
+                Ok(content) => {
+                    let name = path.strip_prefix(root).unwrap_or(&path);
+                    documents.push((name.display().to_string(), content));
+                }
+                Err(e) => warn!(path = %path.display(), error = %e, "Cannot read runbook"),
+            }
+        }
+    }
+    documents.sort_by(|a, b| a.0.cmp(&b.0));
+    documents
+}
+
+async fn fetch_documents(urls: &[String]) -> Vec<(String, String)> {
+    if urls.is_empty() {
+        return Vec::new();
+    }
+    let client = match reqwest::Client::builder().timeout(FETCH_TIMEOUT).build() {
+        Ok(client) => client,
+        Err(e) => {
+            warn!(error = %e, "Cannot create runbook HTTP client");
+            return Vec::new();
+        }
+    };
+
+    let mut documents = Vec::new();
+    for url in urls {
+        let result = async {
+            client
+                .get(url)
+                .send()
+                .await?
+                .error_for_status()?
+                .text()
+                .await
+        }
+        .await;
+        match result {
+            Ok(content) => documents.push((url.clone(), content)),
+            Err(e) => warn!(url = %url, error = %e, "Cannot fetch runbook"),
+        }
+    }
+    documents
+}
+
+/// Split markdown on headings; text before the first heading is titled
+/// after the document itself
+fn split_sections(source: &str, content: &str) -> Vec<Section> {
+    let mut sections = Vec::new();
+    let mut heading = source.to_string();
+    let mut text = String::new();
+
+    let mut flush = |heading: &str, text: &mut String| {
+        let body = text.trim();
+        if !body.is_empty() {
+            // Headings count towards matches too
+            let tokens = tokenize(&format!("{} {}", heading, body));
+            let mut terms: HashMap<String, u32> = HashMap::new();
+            for token in &tokens {
+                *terms.entry(token.clone()).or_default() += 1;
+            }
+            sections.push(Section {
+                source: source.to_string(),
+                heading: heading.to_string(),
+                text: body.to_string(),
+                terms,
+                len: tokens.len(),
+            });
+        }
+        text.clear();
+    };
+
+    // `#` lines inside code fences are shell comments, not headings
+    let mut in_fence = false;
+    for line in content.lines() {
+        if line.trim_start().starts_with("```") {
+            in_fence = !in_fence;
+        }
+        match line.strip_prefix('#') {
+            Some(title) if !in_fence => {
+                flush(&heading, &mut text);
+                heading = title.trim_start_matches('#').trim().to_string();
+            }
+            _ => {
+                text.push_str(line);
+                text.push('\n');
+            }
+        }
+    }
+    flush(&heading, &mut text);
+    sections
+}
+
+fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.len() > 1)
+        .map(str::to_lowercase)
+        .filter(|word| !STOPWORDS.contains(&word.as_str()))
+        .collect()
+}
+
+/// Format hits for the model, asking it to cite the source
+pub fn format_hits(query: &str, hits: &[RunbookHit]) -> String {
+    if hits.is_empty() {
+        return format!("No runbook sections match \"{}\".", query);
+    }
+    let mut result = format!(
+        "Found {} runbook sections for \"{}\" (cite the source when you use one):\n",
+        hits.len(),
+        query
+    );
+    for (i, hit) in hits.iter().enumerate() {
+        result.push_str(&format!(
+            "\n### [{}] {} > {}\n{}\n",
+            i + 1,
+            hit.source,
+            hit.heading,
+            hit.text
+        ));
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DB_RUNBOOK: &str = "# Database\nGeneral notes.\n\n## Connection pool exhausted\n\
+        Symptoms: `too many connections` errors.\nRestart pgbouncer, then raise pool_size.\n\n\
+        ## Disk full\nRun VACUUM and grow the volume:\n```\n# check usage first\ndf -h\n```\n";
+
+    #[test]
+    fn test_search_ranks_matching_section() {
+        let index = Index::build(vec![
+            ("database.md".to_string(), DB_RUNBOOK.to_string()),
+            (
+                "deploys.md".to_string(),
+                "# Rollback\nUse fly releases to roll back a bad deploy.\n".to_string(),
+            ),
+        ]);
+        assert_eq!(index.sections.len(), 4);
+
+        let hits = index.search("too many connections error", 2);
+        assert_eq!(hits[0].source, "database.md");
+        assert_eq!(hits[0].heading, "Connection pool exhausted");
+        assert!(hits[0].text.contains("pgbouncer"));
+
+        let hits = index.search("rollback deploy", 5);
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].source, "deploys.md");
+
+        assert!(index.search("kubernetes", 5).is_empty());
+        assert!(index.search("check usage", 1)[0].text.contains("df -h"));
+    }
+}
@@ -0,0 +1,227 @@
+use serde_json::{Map, Value};
+use std::fmt;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+use tracing::field::{Field, Visit};
+use tracing::level_filters::LevelFilter;
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+
+use crate::error::current_request_id;
+use crate::source::Pipeline;
+
+/// Self logs waiting to be ingested; beyond this they are dropped
+const QUEUE_CAPACITY: usize = 1024;
+
+tokio::task_local! {
+    /// Set while the forwarder ingests self logs, so anything logged on that
+    /// path isn't captured again
+    static FORWARDING: ();
+}
+
+/// flywatch's own tracing events, fed back through the log pipeline with
+/// `source: "self"` so streams and the AI can see what the forwarder is doing.
+///
+/// Loop protection: only flywatch's own targets are captured (so library
+/// tracing about writing a log to a client can't produce another log), events
+/// raised while forwarding are ignored, capture never blocks (a full queue
+/// drops the event), and the level ceiling is separate from the console filter
+/// so debug output doesn't flood the buffer.
+pub struct SelfLog {
+    tx: mpsc::Sender<String>,
+    rx: Mutex<Option<mpsc::Receiver<String>>>,
+    level: AtomicU8,
+}
+
+impl SelfLog {
+    pub fn new() -> Arc<Self> {
+        let (tx, rx) = mpsc::channel(QUEUE_CAPACITY);
+        Arc::new(Self {
+            tx,
+            rx: Mutex::new(Some(rx)),
+            level: AtomicU8::new(level_rank(LevelFilter::OFF)),
+        })
+    }
+
+    pub fn layer(self: &Arc<Self>) -> SelfLogLayer {
+        SelfLogLayer {
+            self_log: self.clone(),
+        }
+    }
+
+    /// Most verbose level captured; OFF disables capture
+    pub fn set_level(&self, level: LevelFilter) {
+        self.level.store(level_rank(level), Ordering::Relaxed);
+    }
+
+    fn captures(&self, level: &Level) -> bool {
+        level_rank(LevelFilter::from_level(*level)) <= self.level.load(Ordering::Relaxed)
+    }
+
+    /// Start ingesting captured events; only the first call has any effect
+    pub fn spawn_forwarder(&self, pipeline: Arc<Pipeline>) {
+        let Some(mut rx) = self.rx.lock().unwrap().take() else {
+            return;
+        };
+        tokio::spawn(FORWARDING.scope((), async move {
+            while let Some(raw) = rx.recv().await {
+                pipeline.ingest(raw).await;
+            }
+        }));
+    }
+}
+
+fn level_rank(level: LevelFilter) -> u8 {
+    match level.into_level() {
+        None => 0,
+        Some(Level::ERROR) => 1,
+        Some(Level::WARN) => 2,
+        Some(Level::INFO) => 3,
+        Some(Level::DEBUG) => 4,
+        Some(Level::TRACE) => 5,
+    }
+}
+
+fn is_own_target(target: &str) -> bool {
+    let crate_name = env!("CARGO_CRATE_NAME");
+    target
+        .strip_prefix(crate_name)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+}
+
+pub struct SelfLogLayer {
+    self_log: Arc<SelfLog>,
+}
+
+impl<S: Subscriber> Layer<S> for SelfLogLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        if !self.self_log.captures(metadata.level())
+            || !is_own_target(metadata.target())
+            || FORWARDING.try_with(|_| ()).is_ok()
+        {
+            return;
+        }
+
+        let mut visitor = FieldVisitor::default();
+        event.record(&mut visitor);
+        if let Some(request_id) = current_request_id() {
+            visitor
+                .fields
+                .insert("request_id".to_string(), Value::from(request_id));
+        }
+        let raw = envelope(
+            metadata.level(),
+            metadata.target(),
+            visitor.message,
+            visitor.fields,
+        );
+        let _ = self.self_log.tx.try_send(raw);
+    }
+}
+
+/// Fly-shaped JSON so the buffer extracts level/app/region like any other line
+fn envelope(level: &Level, target: &str, message: String, fields: Map<String, Value>) -> String {
+    let level = match *level {
+        Level::ERROR => "error",
+        Level::WARN => "warn",
+        Level::INFO => "info",
+        Level::DEBUG => "debug",
+        Level::TRACE => "trace",
+    };
+    serde_json::json!({
+        "message": message,
+        "source": "self",
+        "target": target,
+        "fields": fields,
+        "log": { "level": level },
+        "fly": {
+            "app": {
+                "name": std::env::var("FLY_APP_NAME").unwrap_or_else(|_| "flywatch".to_string()),
+                "instance": std::env::var("FLY_MACHINE_ID").ok(),
+            },
+            "region": std::env::var("FLY_REGION").ok(),
+        },
+    })
+    .to_string()
+}
+
+#[derive(Default)]
+struct FieldVisitor {
+    message: String,
+    fields: Map<String, Value>,
+}
+
+impl FieldVisitor {
+    fn insert(&mut self, field: &Field, value: Value) {
+        if field.name() == "message" {
+            self.message = match value {
+                Value::String(s) => s,
+                other => other.to_string(),
+            };
+        } else {
+            self.fields.insert(field.name().to_string(), value);
+        }
+    }
+}
+
+impl Visit for FieldVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.insert(field, Value::from(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.insert(field, Value::from(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.insert(field, Value::from(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.insert(field, Value::from(value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.insert(field, Value::from(format!("{:?}", value)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::log_buffer::TimestampedLog;
+
+    #[test]
+    fn test_level_ceiling() {
+        let self_log = SelfLog::new();
+        assert!(!self_log.captures(&Level::ERROR));
+
+        self_log.set_level(LevelFilter::WARN);
+        assert!(self_log.captures(&Level::ERROR));
+        assert!(self_log.captures(&Level::WARN));
+        assert!(!self_log.captures(&Level::INFO));
+
+        assert!(is_own_target("flywatch::source"));
+        assert!(!is_own_target("hyper::proto"));
+        assert!(!is_own_target("flywatchers"));
+    }
+
+    #[test]
+    fn test_envelope_is_tagged_self() {
+        let mut fields = Map::new();
+        fields.insert("source".to_string(), Value::from("nats"));
+        let raw = envelope(
+            &Level::WARN,
+            "flywatch::source",
+            "Source error".into(),
+            fields,
+        );
+
+        let log = TimestampedLog::new(raw, 1);
+        assert_eq!(log.source.as_deref(), Some("self"));
+        assert_eq!(log.level.as_deref(), Some("warn"));
+        assert_eq!(log.message.as_deref(), Some("Source error"));
+    }
+}
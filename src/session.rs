@@ -0,0 +1,326 @@
+//! Cookie sessions for the embedded dashboard, so the bearer token never has
+//! to live in browser JS.
+//!
+//! `POST /auth/login` takes AUTH_TOKEN or an AUTH_USERS username/password and
+//! sets an HttpOnly `flywatch_session` cookie, signed with AUTH_TOKEN like
+//! WebSocket tickets. Requests that authenticate with the cookie must send the
+//! session's CSRF token as `X-CSRF-Token` on anything but GET/HEAD/OPTIONS.
+
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap, HeaderValue, Method},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, Duration, Utc};
+use ring::hmac;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::config::Config;
+use crate::error::{ApiError, ErrorBody};
+use crate::http::{token_matches, AppState};
+
+pub const COOKIE_NAME: &str = "flywatch_session";
+pub const CSRF_HEADER: &str = "x-csrf-token";
+
+/// A verified session cookie
+#[derive(Debug, Clone, PartialEq)]
+pub struct Session {
+    pub user: String,
+    pub expires_at: DateTime<Utc>,
+    nonce: String,
+}
+
+fn key(token: &str) -> hmac::Key {
+    hmac::Key::new(hmac::HMAC_SHA256, token.as_bytes())
+}
+
+fn sign(token: &str, purpose: &str, payload: &str) -> String {
+    let tag = hmac::sign(&key(token), format!("{}.{}", purpose, payload).as_bytes());
+    URL_SAFE_NO_PAD.encode(tag)
+}
+
+impl Session {
+    /// `<expiry>.<user>.<nonce>.<signature>`, with the user base64-encoded
+    fn issue(token: &str, user: &str, ttl: Duration, now: DateTime<Utc>) -> (Self, String) {
+        // Whole seconds, as the cookie carries them
+        let expires_at = DateTime::from_timestamp((now + ttl).timestamp(), 0).unwrap_or(now + ttl);
+        let session = Self {
+            user: user.to_string(),
+            expires_at,
+            nonce: uuid::Uuid::new_v4().simple().to_string(),
+        };
+        let payload = format!(
+            "{}.{}.{}",
+            session.expires_at.timestamp(),
+            URL_SAFE_NO_PAD.encode(user),
+            session.nonce
+        );
+        let cookie = format!("{}.{}", payload, sign(token, "session", &payload));
+        (session, cookie)
+    }
+
+    fn verify(token: &str, cookie: &str, now: DateTime<Utc>) -> Option<Self> {
+        let (payload, signature) = cookie.rsplit_once('.')?;
+        let signature = URL_SAFE_NO_PAD.decode(signature).ok()?;
+        let message = format!("session.{}", payload);
+        hmac::verify(&key(token), message.as_bytes(), &signature).ok()?;
+
+        let mut parts = payload.splitn(3, '.');
+        let expiry = parts.next()?.parse::<i64>().ok()?;
+        let user = String::from_utf8(URL_SAFE_NO_PAD.decode(parts.next()?).ok()?).ok()?;
+        let nonce = parts.next()?.to_string();
+        let expires_at = DateTime::from_timestamp(expiry, 0)?;
+        (now <= expires_at).then_some(Self {
+            user,
+            expires_at,
+            nonce,
+        })
+    }
+
+    /// CSRF token bound to this session
+    pub fn csrf_token(&self, token: &str) -> String {
+        sign(token, "csrf", &self.nonce)
+    }
+}
+
+/// The raw session cookie, if the request carries one
+pub fn cookie(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(name, _)| *name == COOKIE_NAME)
+        .map(|(_, value)| value)
+}
+
+/// Verify a raw cookie value against the current config
+pub fn verify(config: &Config, cookie: &str) -> Option<Session> {
+    Session::verify(config.auth_token.as_deref()?, cookie, Utc::now())
+}
+
+/// The request's session, when it carries a valid cookie
+pub fn current(config: &Config, headers: &HeaderMap) -> Option<Session> {
+    verify(config, cookie(headers)?)
+}
+
+/// Whether a browser request comes from the page serving it. Cookies ride
+/// along on cross-site WebSocket upgrades, so session sockets check Origin.
+pub fn same_origin(headers: &HeaderMap) -> bool {
+    let Some(origin) = headers.get(header::ORIGIN).and_then(|v| v.to_str().ok()) else {
+        return true;
+    };
+    let host = headers.get(header::HOST).and_then(|v| v.to_str().ok());
+    let origin_host = origin.split_once("://").map(|(_, host)| host);
+    origin_host.is_some() && origin_host == host
+}
+
+/// Reject unsafe requests that authenticate by cookie without the session's
+/// CSRF token. Bearer-token and cookie-less requests pass through.
+pub async fn csrf_guard(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let safe = matches!(
+        *request.method(),
+        Method::GET | Method::HEAD | Method::OPTIONS
+    );
+    let headers = request.headers();
+    if safe || headers.contains_key(header::AUTHORIZATION) || request.uri().path() == "/auth/login"
+    {
+        return next.run(request).await;
+    }
+    let config = state.config.current();
+    let (Some(session), Some(token)) = (current(&config, headers), config.auth_token.as_deref())
+    else {
+        return next.run(request).await;
+    };
+    let sent = headers.get(CSRF_HEADER).and_then(|v| v.to_str().ok());
+    if sent != Some(session.csrf_token(token).as_str()) {
+        return ApiError::Forbidden("Missing or invalid X-CSRF-Token header".to_string())
+            .into_response();
+    }
+    next.run(request).await
+}
+
+fn set_cookie(config: &Config, value: &str, max_age: i64) -> HeaderValue {
+    let secure = if config.session_cookie_secure {
+        "; Secure"
+    } else {
+        ""
+    };
+    let cookie = format!(
+        "{}={}; Path=/; HttpOnly; SameSite=Strict; Max-Age={}{}",
+        COOKIE_NAME, value, max_age, secure
+    );
+    HeaderValue::from_str(&cookie).expect("session cookies are ASCII")
+}
+
+/// Either AUTH_TOKEN, or a username and password from AUTH_USERS
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct LoginRequest {
+    pub token: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SessionInfo {
+    /// False when AUTH_TOKEN is unset and the API is open
+    pub auth_required: bool,
+    /// Username, or "token" for token logins
+    pub user: Option<String>,
+    /// Send as X-CSRF-Token on POST, PUT and DELETE
+    pub csrf_token: Option<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl SessionInfo {
+    fn of(session: &Session, token: &str) -> Self {
+        Self {
+            auth_required: true,
+            user: Some(session.user.clone()),
+            csrf_token: Some(session.csrf_token(token)),
+            expires_at: Some(session.expires_at),
+        }
+    }
+}
+
+/// POST /auth/login - start a dashboard session
+#[utoipa::path(
+    post, path = "/auth/login", tag = "auth",
+    request_body = LoginRequest,
+    responses(
+        (status = 200, description = "Session started; the cookie is set", body = SessionInfo),
+        (status = 401, description = "Wrong token or username/password", body = ErrorBody),
+        (status = 503, description = "AUTH_TOKEN is not set", body = ErrorBody),
+    )
+)]
+pub async fn login_handler(
+    State(state): State<AppState>,
+    Json(login): Json<LoginRequest>,
+) -> Result<Response, ApiError> {
+    let config = state.config.current();
+    let Some(token) = config.auth_token.as_deref() else {
+        return Err(ApiError::NotConfigured(
+            "AUTH_TOKEN is not set; the dashboard needs no login".to_string(),
+        ));
+    };
+    let user = match (&login.token, &login.username, &login.password) {
+        (Some(given), _, _) if token_matches(given, token) => "token".to_string(),
+        (None, Some(username), Some(password))
+            if config
+                .auth_users
+                .iter()
+                .any(|(name, secret)| name == username && token_matches(password, secret)) =>
+        {
+            username.clone()
+        }
+        _ => {
+            return Err(ApiError::Unauthorized(
+                "Invalid token or username/password".to_string(),
+            ))
+        }
+    };
+
+    let ttl = Duration::hours(config.session_ttl_hours as i64);
+    let (session, cookie) = Session::issue(token, &user, ttl, Utc::now());
+    let mut response = Json(SessionInfo::of(&session, token)).into_response();
+    response.headers_mut().insert(
+        header::SET_COOKIE,
+        set_cookie(&config, &cookie, ttl.num_seconds()),
+    );
+    Ok(response)
+}
+
+/// GET /auth/session - the current session, for the dashboard to resume
+#[utoipa::path(
+    get, path = "/auth/session", tag = "auth",
+    responses(
+        (status = 200, description = "Active session, or auth_required=false", body = SessionInfo),
+        (status = 401, description = "No valid session cookie", body = ErrorBody),
+    )
+)]
+pub async fn session_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<SessionInfo>, ApiError> {
+    let config = state.config.current();
+    let Some(token) = config.auth_token.as_deref() else {
+        return Ok(Json(SessionInfo {
+            auth_required: false,
+            user: None,
+            csrf_token: None,
+            expires_at: None,
+        }));
+    };
+    let session = current(&config, &headers)
+        .ok_or_else(|| ApiError::Unauthorized("No active session".to_string()))?;
+    Ok(Json(SessionInfo::of(&session, token)))
+}
+
+/// POST /auth/logout - clear the session cookie
+#[utoipa::path(
+    post, path = "/auth/logout", tag = "auth",
+    responses((status = 204, description = "Cookie cleared"))
+)]
+pub async fn logout_handler(State(state): State<AppState>) -> Response {
+    let config = state.config.current();
+    (
+        axum::http::StatusCode::NO_CONTENT,
+        [(header::SET_COOKIE, set_cookie(&config, "", 0))],
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sessions_are_signed_and_expire() {
+        let now = Utc::now();
+        let (session, cookie) = Session::issue("secret", "ana.ops", Duration::hours(1), now);
+        assert_eq!(
+            Session::verify("secret", &cookie, now),
+            Some(session.clone())
+        );
+        assert_eq!(
+            Session::verify("secret", &cookie, now + Duration::hours(2)),
+            None
+        );
+        assert_eq!(Session::verify("rotated", &cookie, now), None);
+
+        let forged = cookie.replacen(
+            &URL_SAFE_NO_PAD.encode("ana.ops"),
+            &URL_SAFE_NO_PAD.encode("admin"),
+            1,
+        );
+        assert_eq!(Session::verify("secret", &forged, now), None);
+
+        // CSRF tokens are per session and per key
+        let (other, _) = Session::issue("secret", "ana.ops", Duration::hours(1), now);
+        assert_ne!(session.csrf_token("secret"), other.csrf_token("secret"));
+        assert_ne!(session.csrf_token("secret"), session.csrf_token("rotated"));
+    }
+
+    #[test]
+    fn test_cookie_and_origin_checks() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::COOKIE,
+            "theme=dark; flywatch_session=abc.def".parse().unwrap(),
+        );
+        assert_eq!(cookie(&headers), Some("abc.def"));
+
+        headers.insert(header::HOST, "flywatch.fly.dev".parse().unwrap());
+        assert!(same_origin(&headers));
+        headers.insert(header::ORIGIN, "https://flywatch.fly.dev".parse().unwrap());
+        assert!(same_origin(&headers));
+        headers.insert(header::ORIGIN, "https://evil.example".parse().unwrap());
+        assert!(!same_origin(&headers));
+    }
+}
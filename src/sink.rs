@@ -0,0 +1,592 @@
+//! Exporters behind one abstraction, the counterpart of `source`: every
+//! buffered line is offered to each sink through its own bounded queue, so
+//! a slow or failing destination drops its own lines instead of holding up
+//! live streaming. A worker per sink batches the queue, retries with
+//! backoff, and writes lines it gives up on to an optional dead-letter file.
+//!
+//! Sinks are listed in `SINKS`, each with optional tuning:
+//!
+//! ```text
+//! clickhouse batch=10000 attempts=6 dead_letter=/data/clickhouse.jsonl, kafka
+//! ```
+//!
+//! Without `SINKS`, every sink whose destination is configured is enabled
+//! with its defaults.
+
+use chrono::{DateTime, Utc};
+use futures::FutureExt;
+use serde::Serialize;
+use std::io::Write;
+use std::panic::AssertUnwindSafe;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::{error, info, warn};
+use utoipa::ToSchema;
+
+use crate::archive::Archive;
+use crate::clickhouse::ClickHouse;
+use crate::config::Config;
+use crate::elasticsearch::Elasticsearch;
+use crate::kafka::Kafka;
+use crate::nats::LogMessage;
+
+pub use flywatch_core::sink::{Rejection, Sink, SinkDefaults, SinkError};
+
+/// Sink kinds `SINKS` accepts
+pub const SINK_KINDS: [&str; 4] = ["archive", "clickhouse", "elasticsearch", "kafka"];
+
+/// Classify a failed HTTP response: throttling and server errors are retried
+pub async fn response_error(response: reqwest::Response) -> SinkError {
+    let status = response.status();
+    let detail = format!(
+        "{}: {}",
+        status,
+        response.text().await.unwrap_or_default().trim()
+    );
+    if status.as_u16() == 408 || status.as_u16() == 429 || status.is_server_error() {
+        SinkError::Unavailable(detail)
+    } else {
+        SinkError::Rejected(detail)
+    }
+}
+
+const RETRY_BACKOFF: Duration = Duration::from_secs(1);
+const RETRY_BACKOFF_MAX: Duration = Duration::from_secs(60);
+
+// ==================== Configuration ====================
+
+/// One entry of `SINKS`: a kind and the defaults it overrides
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SinkDefinition {
+    pub kind: String,
+    pub queue_capacity: Option<usize>,
+    pub batch_size: Option<usize>,
+    pub flush_ms: Option<u64>,
+    pub max_attempts: Option<u32>,
+    pub backoff_ms: Option<u64>,
+    /// JSON-lines file for lines the sink gave up on
+    pub dead_letter: Option<String>,
+}
+
+impl SinkDefinition {
+    pub fn new(kind: &str) -> Self {
+        Self {
+            kind: kind.to_string(),
+            ..Default::default()
+        }
+    }
+}
+
+fn positive<T: FromStr + PartialOrd + Default>(key: &str, value: &str) -> Result<T, String> {
+    match value.parse::<T>() {
+        Ok(n) if n > T::default() => Ok(n),
+        _ => Err(format!(
+            "{} must be a positive number, found '{}'",
+            key, value
+        )),
+    }
+}
+
+impl FromStr for SinkDefinition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = s.split_whitespace();
+        let kind = words.next().unwrap_or_default().to_lowercase();
+        if !SINK_KINDS.contains(&kind.as_str()) {
+            return Err(format!(
+                "unknown sink '{}' (expected one of: {})",
+                kind,
+                SINK_KINDS.join(", ")
+            ));
+        }
+
+        let mut definition = SinkDefinition::new(&kind);
+        for word in words {
+            let (key, value) = word
+                .split_once('=')
+                .ok_or_else(|| format!("expected key=value, found '{}'", word))?;
+            match key {
+                "queue" => definition.queue_capacity = Some(positive(key, value)?),
+                "batch" => definition.batch_size = Some(positive(key, value)?),
+                "flush_ms" => definition.flush_ms = Some(positive(key, value)?),
+                "attempts" => definition.max_attempts = Some(positive(key, value)?),
+                "backoff_ms" => definition.backoff_ms = Some(positive(key, value)?),
+                "dead_letter" if !value.is_empty() => {
+                    definition.dead_letter = Some(value.to_string())
+                }
+                other => return Err(format!("unknown option '{}'", other)),
+            }
+        }
+        Ok(definition)
+    }
+}
+
+/// A sink's defaults with its `SINKS` overrides applied
+#[derive(Debug, Clone)]
+struct SinkSettings {
+    queue_capacity: usize,
+    batch_size: usize,
+    flush_interval: Duration,
+    max_attempts: u32,
+    backoff: Duration,
+    dead_letter: Option<String>,
+}
+
+impl SinkSettings {
+    fn new(defaults: SinkDefaults, definition: &SinkDefinition) -> Self {
+        Self {
+            queue_capacity: definition.queue_capacity.unwrap_or(defaults.queue_capacity),
+            batch_size: definition.batch_size.unwrap_or(defaults.batch_size),
+            flush_interval: definition
+                .flush_ms
+                .map_or(defaults.flush_interval, Duration::from_millis),
+            max_attempts: definition.max_attempts.unwrap_or(defaults.max_attempts),
+            backoff: definition
+                .backoff_ms
+                .map_or(RETRY_BACKOFF, Duration::from_millis),
+            dead_letter: definition.dead_letter.clone(),
+        }
+    }
+
+    /// Delay after the `attempt`-th failed delivery of a batch
+    fn backoff(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(16);
+        std::cmp::min(self.backoff * 2u32.pow(exponent), RETRY_BACKOFF_MAX)
+    }
+}
+
+// ==================== Health ====================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SinkStatus {
+    /// Nothing sent yet
+    Idle,
+    /// The last batch was delivered
+    Healthy,
+    /// A batch is being retried
+    Retrying,
+    /// The last batch was given up on
+    Failing,
+}
+
+/// Per-sink health and counters
+#[derive(Debug)]
+pub struct SinkHealth {
+    kind: &'static str,
+    queue_capacity: usize,
+    delivered: AtomicU64,
+    /// Lines that didn't fit in the queue
+    dropped: AtomicU64,
+    retries: AtomicU64,
+    /// Lines given up on after retrying or being refused
+    failed: AtomicU64,
+    /// Failed lines written to the dead-letter file
+    dead_lettered: AtomicU64,
+    state: RwLock<SinkState>,
+}
+
+#[derive(Debug, Clone)]
+struct SinkState {
+    status: SinkStatus,
+    last_delivery_at: Option<DateTime<Utc>>,
+    last_error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct SinkHealthSnapshot {
+    pub kind: &'static str,
+    pub status: SinkStatus,
+    pub queue_depth: usize,
+    pub queue_capacity: usize,
+    pub delivered: u64,
+    pub dropped: u64,
+    pub retries: u64,
+    pub failed: u64,
+    pub dead_lettered: u64,
+    pub last_delivery_at: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+}
+
+impl SinkHealth {
+    fn new(kind: &'static str, queue_capacity: usize) -> Arc<Self> {
+        Arc::new(Self {
+            kind,
+            queue_capacity,
+            delivered: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            retries: AtomicU64::new(0),
+            failed: AtomicU64::new(0),
+            dead_lettered: AtomicU64::new(0),
+            state: RwLock::new(SinkState {
+                status: SinkStatus::Idle,
+                last_delivery_at: None,
+                last_error: None,
+            }),
+        })
+    }
+
+    fn set_status(&self, status: SinkStatus) {
+        self.state.write().unwrap().status = status;
+    }
+
+    fn record_delivery(&self, lines: usize) {
+        self.delivered.fetch_add(lines as u64, Ordering::Relaxed);
+        self.state.write().unwrap().last_delivery_at = Some(Utc::now());
+    }
+
+    fn record_error(&self, error: String) {
+        self.state.write().unwrap().last_error = Some(error);
+    }
+
+    fn record_drop(&self) {
+        let dropped = self.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+        // Warn at the first drop and at each doubling, not per line
+        if dropped.is_power_of_two() {
+            warn!(sink = self.kind, dropped, "Sink is behind; dropping lines");
+        }
+    }
+
+    fn snapshot(&self, queue_depth: usize) -> SinkHealthSnapshot {
+        let state = self.state.read().unwrap().clone();
+        SinkHealthSnapshot {
+            kind: self.kind,
+            status: state.status,
+            queue_depth,
+            queue_capacity: self.queue_capacity,
+            delivered: self.delivered.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            retries: self.retries.load(Ordering::Relaxed),
+            failed: self.failed.load(Ordering::Relaxed),
+            dead_lettered: self.dead_lettered.load(Ordering::Relaxed),
+            last_delivery_at: state.last_delivery_at,
+            last_error: state.last_error,
+        }
+    }
+}
+
+// ==================== Registry & Workers ====================
+
+struct SinkHandle {
+    tx: mpsc::Sender<LogMessage>,
+    health: Arc<SinkHealth>,
+}
+
+/// Running sinks, shared by the pipeline and the HTTP layer
+#[derive(Default)]
+pub struct SinkRegistry {
+    sinks: Vec<SinkHandle>,
+}
+
+impl SinkRegistry {
+    /// Start a worker for each sink
+    pub fn start(sinks: Vec<(Arc<dyn Sink>, SinkDefinition)>) -> Arc<Self> {
+        let sinks = sinks
+            .into_iter()
+            .map(|(sink, definition)| {
+                let settings = SinkSettings::new(sink.defaults(), &definition);
+                let health = SinkHealth::new(sink.kind(), settings.queue_capacity);
+                let (tx, rx) = mpsc::channel(settings.queue_capacity);
+                info!(
+                    sink = sink.kind(),
+                    queue = settings.queue_capacity,
+                    batch = settings.batch_size,
+                    attempts = settings.max_attempts,
+                    "Starting sink"
+                );
+                tokio::spawn(worker(sink, settings, health.clone(), rx));
+                SinkHandle { tx, health }
+            })
+            .collect();
+        Arc::new(Self { sinks })
+    }
+
+    /// Offer a line to every sink without waiting on any of them
+    pub fn append(&self, log: &LogMessage) {
+        for sink in &self.sinks {
+            if sink.tx.try_send(log.clone()).is_err() {
+                sink.health.record_drop();
+            }
+        }
+    }
+
+    pub fn snapshot(&self) -> Vec<SinkHealthSnapshot> {
+        self.sinks
+            .iter()
+            .map(|sink| {
+                let depth = sink.health.queue_capacity - sink.tx.capacity();
+                sink.health.snapshot(depth)
+            })
+            .collect()
+    }
+}
+
+/// Batch a sink's queue and deliver it
+async fn worker(
+    sink: Arc<dyn Sink>,
+    settings: SinkSettings,
+    health: Arc<SinkHealth>,
+    mut rx: mpsc::Receiver<LogMessage>,
+) {
+    while let Some(first) = rx.recv().await {
+        let mut batch = vec![first];
+        let deadline = tokio::time::sleep(settings.flush_interval);
+        tokio::pin!(deadline);
+        while batch.len() < settings.batch_size {
+            tokio::select! {
+                Some(log) = rx.recv() => batch.push(log),
+                _ = &mut deadline => break,
+                else => break,
+            }
+        }
+        deliver(sink.as_ref(), &settings, &health, batch).await;
+    }
+}
+
+/// Send a batch, resending what failed for a retryable reason with backoff
+async fn deliver(
+    sink: &dyn Sink,
+    settings: &SinkSettings,
+    health: &SinkHealth,
+    mut pending: Vec<LogMessage>,
+) {
+    let mut attempt = 1;
+    loop {
+        // A panic in one sink must not stop its worker
+        let result = AssertUnwindSafe(sink.send(&pending))
+            .catch_unwind()
+            .await
+            .unwrap_or_else(|_| Err(SinkError::Unavailable("send panicked".to_string())));
+        let (retry, reason) = match result {
+            Ok(rejections) => {
+                health.record_delivery(pending.len() - rejections.len());
+                let (mut retry, mut retry_reason) = (Vec::new(), String::new());
+                let (mut refused, mut refused_reason) = (Vec::new(), String::new());
+                for rejection in rejections {
+                    let log = pending[rejection.index].clone();
+                    if rejection.retryable {
+                        retry.push(log);
+                        retry_reason = rejection.reason;
+                    } else {
+                        refused.push(log);
+                        refused_reason = rejection.reason;
+                    }
+                }
+                if !refused.is_empty() {
+                    give_up(sink.kind(), settings, health, &refused, &refused_reason).await;
+                }
+                (retry, retry_reason)
+            }
+            Err(SinkError::Rejected(e)) => {
+                give_up(sink.kind(), settings, health, &pending, &e).await;
+                return;
+            }
+            Err(SinkError::Unavailable(e)) => (pending, e),
+        };
+        if retry.is_empty() {
+            health.set_status(SinkStatus::Healthy);
+            return;
+        }
+
+        health.record_error(reason.clone());
+        if attempt >= settings.max_attempts {
+            let reason = format!("gave up after {} attempts: {}", attempt, reason);
+            give_up(sink.kind(), settings, health, &retry, &reason).await;
+            return;
+        }
+        let backoff = settings.backoff(attempt);
+        warn!(
+            sink = sink.kind(),
+            lines = retry.len(),
+            attempt,
+            error = %reason,
+            backoff_ms = backoff.as_millis() as u64,
+            "Sink delivery failed; retrying"
+        );
+        health.set_status(SinkStatus::Retrying);
+        health.retries.fetch_add(1, Ordering::Relaxed);
+        tokio::time::sleep(backoff).await;
+        attempt += 1;
+        pending = retry;
+    }
+}
+
+/// Count lines as failed and write them to the dead-letter file, if any
+async fn give_up(
+    kind: &'static str,
+    settings: &SinkSettings,
+    health: &SinkHealth,
+    lines: &[LogMessage],
+    reason: &str,
+) {
+    health
+        .failed
+        .fetch_add(lines.len() as u64, Ordering::Relaxed);
+    health.record_error(reason.to_string());
+    health.set_status(SinkStatus::Failing);
+    let Some(path) = settings.dead_letter.clone() else {
+        error!(sink = kind, lines = lines.len(), error = %reason, "Sink dropped lines");
+        return;
+    };
+
+    let failed_at = Utc::now();
+    let records: String = lines
+        .iter()
+        .map(|log| {
+            let record = serde_json::json!({
+                "failed_at": failed_at,
+                "sink": kind,
+                "reason": reason,
+                "log": Arc::as_ref(log),
+            });
+            format!("{}\n", record)
+        })
+        .collect();
+    let written = tokio::task::spawn_blocking(move || {
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .and_then(|mut file| file.write_all(records.as_bytes()))
+            .map_err(|e| format!("{}: {}", path, e))
+    })
+    .await;
+    match written {
+        Ok(Ok(())) => {
+            health
+                .dead_lettered
+                .fetch_add(lines.len() as u64, Ordering::Relaxed);
+            warn!(sink = kind, lines = lines.len(), error = %reason, "Sink dead-lettered lines");
+        }
+        Ok(Err(e)) => {
+            error!(sink = kind, lines = lines.len(), error = %e, "Failed to write dead letters")
+        }
+        Err(e) => error!(sink = kind, error = %e, "Dead-letter writer panicked"),
+    }
+}
+
+/// Build the sinks listed in `Config::sinks`
+pub fn build_sinks(
+    config: &Config,
+    archive: Option<Arc<Archive>>,
+) -> Vec<(Arc<dyn Sink>, SinkDefinition)> {
+    // Config validation makes sure each listed sink has its destination
+    config
+        .sinks
+        .iter()
+        .filter_map(|definition| {
+            let sink: Arc<dyn Sink> = match definition.kind.as_str() {
+                "archive" => archive.clone()?,
+                "clickhouse" => Arc::new(ClickHouse::new(config)?),
+                "elasticsearch" => Arc::new(Elasticsearch::new(config)?),
+                "kafka" => Arc::new(Kafka::new(config)?),
+                _ => return None,
+            };
+            Some((sink, definition.clone()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::log_buffer::TimestampedLog;
+    use async_trait::async_trait;
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_parse_sinks() {
+        let sink: SinkDefinition = "ClickHouse batch=100 attempts=2 dead_letter=/tmp/ch.jsonl"
+            .parse()
+            .unwrap();
+        assert_eq!(sink.kind, "clickhouse");
+        assert_eq!(sink.batch_size, Some(100));
+        assert_eq!(sink.max_attempts, Some(2));
+        assert_eq!(sink.dead_letter.as_deref(), Some("/tmp/ch.jsonl"));
+
+        assert!("loki".parse::<SinkDefinition>().is_err());
+        assert!("kafka batch=0".parse::<SinkDefinition>().is_err());
+        assert!("kafka retries=3".parse::<SinkDefinition>().is_err());
+        assert!("kafka attempts".parse::<SinkDefinition>().is_err());
+    }
+
+    /// Refuses line 0 for good and asks for line 1 to be sent again, once
+    #[derive(Default)]
+    struct FlakySink {
+        batches: Mutex<Vec<usize>>,
+    }
+
+    #[async_trait]
+    impl Sink for FlakySink {
+        fn kind(&self) -> &'static str {
+            "test"
+        }
+
+        fn defaults(&self) -> SinkDefaults {
+            SinkDefaults {
+                queue_capacity: 10,
+                batch_size: 10,
+                flush_interval: Duration::from_millis(10),
+                max_attempts: 3,
+            }
+        }
+
+        async fn send(&self, batch: &[LogMessage]) -> Result<Vec<Rejection>, SinkError> {
+            let mut batches = self.batches.lock().unwrap();
+            batches.push(batch.len());
+            if batches.len() > 1 {
+                return Ok(Vec::new());
+            }
+            Ok(vec![
+                Rejection {
+                    index: 0,
+                    retryable: false,
+                    reason: "mapping error".to_string(),
+                },
+                Rejection {
+                    index: 1,
+                    retryable: true,
+                    reason: "busy".to_string(),
+                },
+            ])
+        }
+    }
+
+    #[tokio::test]
+    async fn test_worker_retries_and_dead_letters() {
+        let dead_letter =
+            std::env::temp_dir().join(format!("flywatch-dlq-{}.jsonl", uuid::Uuid::new_v4()));
+        let definition: SinkDefinition =
+            format!("kafka backoff_ms=5 dead_letter={}", dead_letter.display())
+                .parse()
+                .unwrap();
+        let sink = Arc::new(FlakySink::default());
+        let registry = SinkRegistry::start(vec![(sink.clone(), definition)]);
+        for i in 0..3 {
+            registry.append(&Arc::new(TimestampedLog::new(format!("line {}", i), i)));
+        }
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        // Everything in one batch, then only the retryable line
+        assert_eq!(*sink.batches.lock().unwrap(), vec![3, 1]);
+        let health = &registry.snapshot()[0];
+        assert_eq!(health.status, SinkStatus::Healthy);
+        assert_eq!(health.delivered, 2);
+        assert_eq!(
+            (health.retries, health.failed, health.dead_lettered),
+            (1, 1, 1)
+        );
+        assert_eq!(health.queue_depth, 0);
+
+        let written = std::fs::read_to_string(&dead_letter).unwrap();
+        let record: serde_json::Value = serde_json::from_str(written.trim()).unwrap();
+        assert_eq!(record["sink"], "test");
+        assert_eq!(record["reason"], "mapping error");
+        assert_eq!(record["log"]["raw"], "line 0");
+        std::fs::remove_file(dead_letter).unwrap();
+    }
+}
@@ -0,0 +1,434 @@
+//! Service level objectives over log-based counters. Each SLO names an
+//! error counter and a total counter from `LOG_METRICS`:
+//!
+//! ```text
+//! checkout target=99.9 window=30d errors=http_5xx total=http_requests
+//! ```
+//!
+//! The counters are sampled every minute and burn rates (how many times
+//! faster than sustainable the error budget is being spent) are computed
+//! over several lookback windows. Alerts follow the multi-window scheme from
+//! the SRE workbook: a long and a short window must both exceed a threshold.
+
+use axum::{extract::State, Json};
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use utoipa::ToSchema;
+
+use crate::alerts::{Alerts, Severity};
+use crate::http::AppState;
+
+const SAMPLE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Windows burn rates are reported over
+const BURN_WINDOWS: &[(&str, i64)] = &[
+    ("5m", 5),
+    ("30m", 30),
+    ("1h", 60),
+    ("2h", 120),
+    ("6h", 360),
+    ("1d", 1440),
+    ("3d", 4320),
+];
+
+/// (long window, short window, burn-rate threshold, severity)
+const BURN_ALERTS: &[(&str, &str, f64, Severity)] = &[
+    ("1h", "5m", 14.4, Severity::Critical),
+    ("6h", "30m", 6.0, Severity::Critical),
+    ("1d", "2h", 3.0, Severity::Warning),
+    ("3d", "6h", 1.0, Severity::Warning),
+];
+
+/// One SLO, as written in `SLOS`
+#[derive(Debug, Clone, PartialEq)]
+pub struct SloDefinition {
+    pub name: String,
+    /// Target availability in percent, e.g. 99.9
+    pub target: f64,
+    pub window: String,
+    window_minutes: i64,
+    /// Log-based counter of failed events
+    pub errors: String,
+    /// Log-based counter of all events
+    pub total: String,
+}
+
+/// Minutes in "90m", "12h" or "30d"
+pub fn parse_window(value: &str) -> Option<i64> {
+    let split = value.find(|c: char| !c.is_ascii_digit())?;
+    let (amount, unit) = value.split_at(split);
+    let amount: i64 = amount.parse().ok().filter(|n| *n > 0)?;
+    match unit {
+        "m" => Some(amount),
+        "h" => Some(amount * 60),
+        "d" => Some(amount * 1440),
+        _ => None,
+    }
+}
+
+impl FromStr for SloDefinition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = s.split_whitespace();
+        let name = words.next().unwrap_or_default().to_string();
+        if name.is_empty() || name.contains('=') {
+            return Err("an SLO starts with its name".to_string());
+        }
+
+        let (mut target, mut window, mut errors, mut total) = (None, None, None, None);
+        for word in words {
+            let (key, value) = word
+                .split_once('=')
+                .ok_or_else(|| format!("expected key=value, found '{}'", word))?;
+            match key {
+                "target" => {
+                    let percent: f64 = value
+                        .trim_end_matches('%')
+                        .parse()
+                        .map_err(|_| format!("invalid target '{}'", value))?;
+                    if !(0.0 < percent && percent < 100.0) {
+                        return Err(format!("target must be between 0 and 100, got {}", percent));
+                    }
+                    target = Some(percent);
+                }
+                "window" => {
+                    let minutes = parse_window(value)
+                        .ok_or_else(|| format!("invalid window '{}' (e.g. 30d, 12h)", value))?;
+                    window = Some((value.to_string(), minutes));
+                }
+                "errors" => errors = Some(value.to_string()),
+                "total" => total = Some(value.to_string()),
+                other => return Err(format!("unknown option '{}'", other)),
+            }
+        }
+
+        let (window, window_minutes) = window.unwrap_or(("30d".to_string(), 30 * 1440));
+        Ok(SloDefinition {
+            name,
+            target: target.ok_or("target= is required")?,
+            window,
+            window_minutes,
+            errors: errors.ok_or("errors= is required")?,
+            total: total.ok_or("total= is required")?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    at: DateTime<Utc>,
+    errors: u64,
+    total: u64,
+}
+
+struct TrackedSlo {
+    definition: SloDefinition,
+    samples: VecDeque<Sample>,
+}
+
+impl TrackedSlo {
+    fn retention(&self) -> Duration {
+        let longest = BURN_WINDOWS.iter().map(|(_, m)| *m).max().unwrap_or(0);
+        Duration::minutes(self.definition.window_minutes.max(longest) + 1)
+    }
+
+    /// Errors and events over the last `minutes`, from the newest sample
+    /// back to the last one at least that old (or the oldest kept)
+    fn delta(&self, minutes: i64) -> Option<(u64, u64)> {
+        let newest = self.samples.back()?;
+        let since = newest.at - Duration::minutes(minutes);
+        let start = self
+            .samples
+            .iter()
+            .rev()
+            .find(|s| s.at <= since)
+            .or_else(|| self.samples.front())?;
+        if start.at == newest.at {
+            return None;
+        }
+        Some((newest.errors - start.errors, newest.total - start.total))
+    }
+
+    fn burn_rate(&self, minutes: i64) -> Option<f64> {
+        let (errors, total) = self.delta(minutes)?;
+        let budget = 1.0 - self.definition.target / 100.0;
+        Some(if total == 0 {
+            0.0
+        } else {
+            (errors as f64 / total as f64) / budget
+        })
+    }
+
+    fn burn_rate_for(&self, window: &str) -> Option<f64> {
+        let minutes = BURN_WINDOWS.iter().find(|(name, _)| *name == window)?.1;
+        self.burn_rate(minutes)
+    }
+
+    /// The most severe burn-rate alert whose windows are both over threshold
+    fn alert(&self) -> Option<(Severity, String)> {
+        BURN_ALERTS
+            .iter()
+            .filter_map(|(long, short, threshold, severity)| {
+                let long_rate = self.burn_rate_for(long)?;
+                let short_rate = self.burn_rate_for(short)?;
+                (long_rate > *threshold && short_rate > *threshold).then(|| {
+                    let summary = format!(
+                        "{} is spending its {}% error budget {:.1}x too fast over {} ({:.1}x over {})",
+                        self.definition.name,
+                        self.definition.target,
+                        long_rate,
+                        long,
+                        short_rate,
+                        short
+                    );
+                    (*severity, summary)
+                })
+            })
+            .max_by_key(|(severity, _)| *severity)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct BurnRate {
+    pub window: &'static str,
+    /// Error-budget spend relative to sustainable; null until two samples exist
+    pub rate: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct SloStatus {
+    pub name: String,
+    pub target: f64,
+    pub window: String,
+    pub errors_metric: String,
+    pub total_metric: String,
+    /// Events counted over the SLO window (or since flywatch started)
+    pub events: u64,
+    pub error_ratio: Option<f64>,
+    /// Share of the window's error budget left; negative once overspent
+    pub error_budget_remaining: Option<f64>,
+    pub burn_rates: Vec<BurnRate>,
+    /// Severity of the burn-rate alert, while one is firing
+    pub alert: Option<Severity>,
+}
+
+/// Sampled history of every SLO's counters
+#[derive(Default)]
+pub struct SloTracker {
+    slos: Mutex<Vec<TrackedSlo>>,
+}
+
+impl SloTracker {
+    pub fn new(definitions: &[SloDefinition]) -> Arc<Self> {
+        let tracker = Arc::new(Self::default());
+        tracker.set_definitions(definitions);
+        tracker
+    }
+
+    /// Replace the definitions, keeping history for those that didn't change
+    pub fn set_definitions(&self, definitions: &[SloDefinition]) {
+        let mut slos = self.slos.lock().unwrap();
+        let mut previous: Vec<TrackedSlo> = std::mem::take(&mut *slos);
+        *slos = definitions
+            .iter()
+            .map(
+                |definition| match previous.iter().position(|t| t.definition == *definition) {
+                    Some(i) => previous.swap_remove(i),
+                    None => TrackedSlo {
+                        definition: definition.clone(),
+                        samples: VecDeque::new(),
+                    },
+                },
+            )
+            .collect();
+    }
+
+    /// Record current counter values (by log-metric name)
+    pub fn record(&self, counters: &HashMap<String, u64>, now: DateTime<Utc>) {
+        let mut slos = self.slos.lock().unwrap();
+        for slo in slos.iter_mut() {
+            let (Some(&errors), Some(&total)) = (
+                counters.get(&slo.definition.errors),
+                counters.get(&slo.definition.total),
+            ) else {
+                continue;
+            };
+            // The counters were reset (e.g. their rule changed)
+            if slo
+                .samples
+                .back()
+                .is_some_and(|last| errors < last.errors || total < last.total)
+            {
+                slo.samples.clear();
+            }
+            slo.samples.push_back(Sample {
+                at: now,
+                errors,
+                total,
+            });
+            let cutoff = now - slo.retention();
+            while slo.samples.front().is_some_and(|s| s.at < cutoff) {
+                slo.samples.pop_front();
+            }
+        }
+    }
+
+    /// Fire or resolve each SLO's burn-rate alert
+    pub fn evaluate(&self, alerts: &Alerts) {
+        let slos = self.slos.lock().unwrap();
+        for slo in slos.iter() {
+            // Too few samples (e.g. just after a restart) to say either way
+            if slo.burn_rate(BURN_WINDOWS[0].1).is_none() {
+                continue;
+            }
+            let rule = format!("slo:{}", slo.definition.name);
+            match slo.alert() {
+                Some((severity, summary)) => alerts.fire(&rule, "burn_rate", severity, summary),
+                None => alerts.resolve(&rule, "burn_rate"),
+            }
+        }
+    }
+
+    pub fn status(&self, alerts: &Alerts) -> Vec<SloStatus> {
+        let firing = alerts.active();
+        let slos = self.slos.lock().unwrap();
+        slos.iter()
+            .map(|slo| {
+                let definition = &slo.definition;
+                let window = slo.delta(definition.window_minutes);
+                let error_ratio = window
+                    .filter(|(_, total)| *total > 0)
+                    .map(|(errors, total)| errors as f64 / total as f64);
+                let budget = 1.0 - definition.target / 100.0;
+                let rule = format!("slo:{}", definition.name);
+                SloStatus {
+                    name: definition.name.clone(),
+                    target: definition.target,
+                    window: definition.window.clone(),
+                    errors_metric: definition.errors.clone(),
+                    total_metric: definition.total.clone(),
+                    events: window.map_or(0, |(_, total)| total),
+                    error_ratio,
+                    error_budget_remaining: error_ratio.map(|ratio| 1.0 - ratio / budget),
+                    burn_rates: BURN_WINDOWS
+                        .iter()
+                        .map(|(name, minutes)| BurnRate {
+                            window: name,
+                            rate: slo.burn_rate(*minutes),
+                        })
+                        .collect(),
+                    alert: firing
+                        .iter()
+                        .find(|alert| alert.rule == rule)
+                        .map(|alert| alert.severity),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Sample the log-based counters and evaluate SLO alerts every minute
+pub async fn slo_evaluator(state: AppState) {
+    let mut interval = tokio::time::interval(SAMPLE_INTERVAL);
+    loop {
+        interval.tick().await;
+        let counters = state
+            .log_metrics
+            .snapshot()
+            .into_iter()
+            .map(|m| (m.name, m.count))
+            .collect();
+        state.slos.record(&counters, Utc::now());
+        state.slos.evaluate(&state.alerts);
+    }
+}
+
+/// GET /slo - error budgets and burn rates
+#[utoipa::path(
+    get, path = "/slo", tag = "metrics",
+    responses((status = 200, description = "Every SLO with its error budget and burn rates", body = Vec<SloStatus>))
+)]
+pub async fn slo_handler(State(state): State<AppState>) -> Json<Vec<SloStatus>> {
+    Json(state.slos.status(&state.alerts))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn counters(errors: u64, total: u64) -> HashMap<String, u64> {
+        HashMap::from([
+            ("errors".to_string(), errors),
+            ("requests".to_string(), total),
+        ])
+    }
+
+    #[test]
+    fn test_fast_burn_fires_then_steps_down() {
+        let definition: SloDefinition = "api target=99 window=30d errors=errors total=requests"
+            .parse()
+            .unwrap();
+        let tracker = SloTracker::new(&[definition]);
+        let alerts = Alerts::new(&Config::for_tests(""));
+        let start = Utc::now();
+
+        // An hour of 1% errors: exactly on budget
+        for minute in 0..=60 {
+            tracker.record(
+                &counters(minute * 10, minute * 1000),
+                start + Duration::minutes(minute as i64),
+            );
+        }
+        tracker.evaluate(&alerts);
+        assert!(alerts.active().is_empty());
+        let status = &tracker.status(&alerts)[0];
+        assert!((status.burn_rates[0].rate.unwrap() - 1.0).abs() < 1e-9);
+
+        // Then 90% errors for ten minutes
+        for minute in 61..=70 {
+            let errors = 600 + (minute - 60) * 900;
+            tracker.record(
+                &counters(errors, minute * 1000),
+                start + Duration::minutes(minute as i64),
+            );
+        }
+        tracker.evaluate(&alerts);
+        let active = alerts.active();
+        assert_eq!(active.len(), 1);
+        assert_eq!(active[0].rule, "slo:api");
+        assert_eq!(active[0].severity, Severity::Critical);
+        assert_eq!(tracker.status(&alerts)[0].alert, Some(Severity::Critical));
+
+        // Once the short windows are clean only the slow-burn pairs still fire
+        for minute in 71..=110 {
+            tracker.record(
+                &counters(9600, minute * 1000),
+                start + Duration::minutes(minute as i64),
+            );
+        }
+        tracker.evaluate(&alerts);
+        let active = alerts.active();
+        assert_eq!(active.len(), 1);
+        assert_eq!(active[0].severity, Severity::Warning);
+    }
+
+    #[test]
+    fn test_parse_definitions() {
+        let slo: SloDefinition = "checkout target=99.9% window=7d errors=e total=t"
+            .parse()
+            .unwrap();
+        assert_eq!(slo.window_minutes, 7 * 1440);
+        assert!("x target=100 errors=e total=t"
+            .parse::<SloDefinition>()
+            .is_err());
+        assert!("x target=99.9 errors=e".parse::<SloDefinition>().is_err());
+        assert!("x target=99.9 window=1w errors=e total=t"
+            .parse::<SloDefinition>()
+            .is_err());
+    }
+}
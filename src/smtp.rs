@@ -0,0 +1,355 @@
+//! Minimal SMTP submission client for alert emails and digests: implicit
+//! TLS, STARTTLS or plaintext, AUTH PLAIN, and multipart text/HTML bodies.
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chrono::Utc;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio_native_tls::{native_tls, TlsConnector};
+
+use crate::config::Config;
+
+const SEND_TIMEOUT: Duration = Duration::from_secs(30);
+const CLIENT_NAME: &str = "flywatch";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmtpSecurity {
+    /// TLS from the first byte (usually port 465)
+    Tls,
+    /// Upgrade a plaintext connection (usually port 587)
+    StartTls,
+    /// No encryption, for local relays only
+    None,
+}
+
+impl SmtpSecurity {
+    pub fn default_port(self) -> u16 {
+        match self {
+            Self::Tls => 465,
+            Self::StartTls => 587,
+            Self::None => 25,
+        }
+    }
+}
+
+impl FromStr for SmtpSecurity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "tls" => Ok(Self::Tls),
+            "starttls" => Ok(Self::StartTls),
+            "none" => Ok(Self::None),
+            other => Err(format!(
+                "Invalid SMTP security: {}. Use tls, starttls or none.",
+                other
+            )),
+        }
+    }
+}
+
+/// Where and as whom mail is sent
+#[derive(Debug, Clone)]
+pub struct SmtpSettings {
+    pub host: String,
+    pub port: u16,
+    pub security: SmtpSecurity,
+    pub credentials: Option<(String, String)>,
+    pub from: String,
+    pub to: Vec<String>,
+}
+
+impl SmtpSettings {
+    /// None unless SMTP_HOST is set (config validation requires the rest)
+    pub fn from_config(config: &Config) -> Option<Self> {
+        Some(Self {
+            host: config.smtp_host.clone()?,
+            port: config.smtp_port,
+            security: config.smtp_security,
+            credentials: config
+                .smtp_username
+                .clone()
+                .map(|user| (user, config.smtp_password.clone().unwrap_or_default())),
+            from: config.smtp_from.clone()?,
+            to: config.smtp_to.clone(),
+        })
+    }
+}
+
+pub struct Email {
+    pub subject: String,
+    pub text: String,
+    pub html: String,
+}
+
+#[derive(Debug)]
+pub enum SmtpError {
+    Io(std::io::Error),
+    Tls(native_tls::Error),
+    /// The server answered with an unexpected code
+    Rejected {
+        command: &'static str,
+        reply: String,
+    },
+    Timeout,
+}
+
+impl fmt::Display for SmtpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "SMTP connection failed: {}", e),
+            Self::Tls(e) => write!(f, "SMTP TLS failed: {}", e),
+            Self::Rejected { command, reply } => write!(f, "SMTP {} rejected: {}", command, reply),
+            Self::Timeout => write!(f, "SMTP send timed out"),
+        }
+    }
+}
+
+impl From<std::io::Error> for SmtpError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<native_tls::Error> for SmtpError {
+    fn from(e: native_tls::Error) -> Self {
+        Self::Tls(e)
+    }
+}
+
+trait Connection: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Connection for T {}
+
+struct Session {
+    stream: BufReader<Box<dyn Connection>>,
+}
+
+impl Session {
+    fn new(stream: Box<dyn Connection>) -> Self {
+        Self {
+            stream: BufReader::new(stream),
+        }
+    }
+
+    /// Read a possibly multi-line reply ("250-..." continues, "250 ..." ends)
+    async fn reply(&mut self) -> Result<(u16, String), SmtpError> {
+        let mut text = String::new();
+        loop {
+            let mut line = String::new();
+            if self.stream.read_line(&mut line).await? == 0 {
+                return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+            }
+            let line = line.trim_end();
+            let code = line.get(..3).and_then(|c| c.parse().ok()).unwrap_or(0);
+            text.push_str(line);
+            if line.as_bytes().get(3) != Some(&b'-') {
+                return Ok((code, text));
+            }
+            text.push(' ');
+        }
+    }
+
+    async fn expect(&mut self, command: &'static str, codes: &[u16]) -> Result<(), SmtpError> {
+        let (code, reply) = self.reply().await?;
+        if codes.contains(&code) {
+            Ok(())
+        } else {
+            Err(SmtpError::Rejected { command, reply })
+        }
+    }
+
+    async fn command(
+        &mut self,
+        name: &'static str,
+        line: &str,
+        codes: &[u16],
+    ) -> Result<(), SmtpError> {
+        let stream = self.stream.get_mut();
+        stream.write_all(line.as_bytes()).await?;
+        stream.write_all(b"\r\n").await?;
+        stream.flush().await?;
+        self.expect(name, codes).await
+    }
+
+    fn into_inner(self) -> Box<dyn Connection> {
+        self.stream.into_inner()
+    }
+}
+
+async fn tls(host: &str, stream: Box<dyn Connection>) -> Result<Box<dyn Connection>, SmtpError> {
+    let connector = TlsConnector::from(native_tls::TlsConnector::new()?);
+    Ok(Box::new(connector.connect(host, stream).await?))
+}
+
+/// The bare address in "Name <addr@example.com>"
+fn envelope_address(mailbox: &str) -> &str {
+    match (mailbox.find('<'), mailbox.rfind('>')) {
+        (Some(start), Some(end)) if start < end => &mailbox[start + 1..end],
+        _ => mailbox.trim(),
+    }
+}
+
+/// Base64 wrapped at 76 columns, which also keeps lines from starting with "."
+fn base64_lines(data: &str) -> String {
+    let encoded = BASE64.encode(data);
+    let mut out = String::with_capacity(encoded.len() + encoded.len() / 38);
+    for chunk in encoded.as_bytes().chunks(76) {
+        out.push_str(std::str::from_utf8(chunk).unwrap_or_default());
+        out.push_str("\r\n");
+    }
+    out
+}
+
+fn encode_header(value: &str) -> String {
+    if value.is_ascii() {
+        value.replace(['\r', '\n'], " ")
+    } else {
+        format!("=?UTF-8?B?{}?=", BASE64.encode(value))
+    }
+}
+
+/// RFC 5322 message with text and HTML alternatives
+fn message(settings: &SmtpSettings, email: &Email) -> String {
+    let boundary = format!("flywatch-{}", uuid::Uuid::new_v4().simple());
+    let domain = envelope_address(&settings.from)
+        .rsplit('@')
+        .next()
+        .unwrap_or(CLIENT_NAME);
+    format!(
+        "From: {from}\r\n\
+         To: {to}\r\n\
+         Subject: {subject}\r\n\
+         Date: {date}\r\n\
+         Message-ID: <{id}@{domain}>\r\n\
+         MIME-Version: 1.0\r\n\
+         Content-Type: multipart/alternative; boundary=\"{boundary}\"\r\n\
+         \r\n\
+         --{boundary}\r\n\
+         Content-Type: text/plain; charset=utf-8\r\n\
+         Content-Transfer-Encoding: base64\r\n\
+         \r\n\
+         {text}\
+         --{boundary}\r\n\
+         Content-Type: text/html; charset=utf-8\r\n\
+         Content-Transfer-Encoding: base64\r\n\
+         \r\n\
+         {html}\
+         --{boundary}--\r\n",
+        from = settings.from,
+        to = settings.to.join(", "),
+        subject = encode_header(&email.subject),
+        date = Utc::now().to_rfc2822(),
+        id = uuid::Uuid::new_v4(),
+        text = base64_lines(&email.text),
+        html = base64_lines(&email.html),
+    )
+}
+
+async fn deliver(settings: &SmtpSettings, email: &Email) -> Result<(), SmtpError> {
+    let tcp = TcpStream::connect((settings.host.as_str(), settings.port)).await?;
+    let stream: Box<dyn Connection> = match settings.security {
+        SmtpSecurity::Tls => tls(&settings.host, Box::new(tcp)).await?,
+        SmtpSecurity::StartTls | SmtpSecurity::None => Box::new(tcp),
+    };
+    let mut session = Session::new(stream);
+    session.expect("greeting", &[220]).await?;
+    let ehlo = format!("EHLO {}", CLIENT_NAME);
+    session.command("EHLO", &ehlo, &[250]).await?;
+
+    if settings.security == SmtpSecurity::StartTls {
+        session.command("STARTTLS", "STARTTLS", &[220]).await?;
+        session = Session::new(tls(&settings.host, session.into_inner()).await?);
+        session.command("EHLO", &ehlo, &[250]).await?;
+    }
+
+    if let Some((ref user, ref password)) = settings.credentials {
+        let token = BASE64.encode(format!("\0{}\0{}", user, password));
+        session
+            .command("AUTH", &format!("AUTH PLAIN {}", token), &[235])
+            .await?;
+    }
+
+    let from = format!("MAIL FROM:<{}>", envelope_address(&settings.from));
+    session.command("MAIL FROM", &from, &[250]).await?;
+    for to in &settings.to {
+        let rcpt = format!("RCPT TO:<{}>", envelope_address(to));
+        session.command("RCPT TO", &rcpt, &[250, 251]).await?;
+    }
+    session.command("DATA", "DATA", &[354]).await?;
+    let body = message(settings, email);
+    session
+        .command("message", &format!("{}.", body), &[250])
+        .await?;
+    // The message is accepted; a failed QUIT doesn't matter
+    let _ = session.command("QUIT", "QUIT", &[221]).await;
+    Ok(())
+}
+
+/// Send one email to every configured recipient
+pub async fn send(settings: &SmtpSettings, email: &Email) -> Result<(), SmtpError> {
+    tokio::time::timeout(SEND_TIMEOUT, deliver(settings, email))
+        .await
+        .map_err(|_| SmtpError::Timeout)?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn test_sends_through_plain_relay() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut transcript = String::new();
+            socket.write_all(b"220 relay ready\r\n").await.unwrap();
+            let replies: &[&[u8]] = &[
+                b"250-relay\r\n250 AUTH PLAIN\r\n",
+                b"235 ok\r\n",
+                b"250 ok\r\n",
+                b"250 ok\r\n",
+                b"354 go ahead\r\n",
+            ];
+            let mut buf = vec![0; 64 * 1024];
+            for reply in replies {
+                let n = socket.read(&mut buf).await.unwrap();
+                transcript.push_str(&String::from_utf8_lossy(&buf[..n]));
+                socket.write_all(reply).await.unwrap();
+            }
+            while !transcript.ends_with("\r\n.\r\n") {
+                let n = socket.read(&mut buf).await.unwrap();
+                transcript.push_str(&String::from_utf8_lossy(&buf[..n]));
+            }
+            socket.write_all(b"250 queued\r\n").await.unwrap();
+            transcript
+        });
+
+        let settings = SmtpSettings {
+            host: "127.0.0.1".to_string(),
+            port,
+            security: SmtpSecurity::None,
+            credentials: Some(("bot".to_string(), "secret".to_string())),
+            from: "Flywatch <alerts@example.com>".to_string(),
+            to: vec!["oncall@example.com".to_string()],
+        };
+        let email = Email {
+            subject: "Digest ✓".to_string(),
+            text: ".starts with a dot".to_string(),
+            html: "<p>hi</p>".to_string(),
+        };
+        send(&settings, &email).await.unwrap();
+
+        let transcript = server.await.unwrap();
+        assert!(transcript.contains("AUTH PLAIN AGJvdABzZWNyZXQ=\r\n"));
+        assert!(transcript.contains("MAIL FROM:<alerts@example.com>\r\n"));
+        assert!(transcript.contains("RCPT TO:<oncall@example.com>\r\n"));
+        assert!(transcript.contains("Subject: =?UTF-8?B?"));
+        assert!(transcript.contains(&base64_lines(".starts with a dot")));
+    }
+}
@@ -0,0 +1,357 @@
+//! Soak harness for the NATS source: `cargo test --features soak soak`.
+//!
+//! A fake NATS server speaking just enough of the protocol (INFO, CONNECT,
+//! PING, SUB, MSG) drives the real `NatsSource` and ingest pipeline while
+//! the test drops connections, sends malformed payloads and bursts traffic.
+//! `SOAK_ROUNDS` sets how many disconnect cycles to run (default 3).
+//!
+//! Core NATS delivers at most once, so lines published while the client is
+//! disconnected are lost; that window is the only loss allowed. Every line
+//! published while a subscription is registered must be buffered, counted
+//! and broadcast exactly once, without the supervisor restarting the source.
+
+use flywatch_core::loadgen::LogGenerator;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+use tokio::sync::{broadcast, mpsc};
+use tokio::task::JoinHandle;
+
+use crate::config::{Config, ConfigStore};
+use crate::heartbeat::Heartbeats;
+use crate::ingest_filter::IngestFilter;
+use crate::log_buffer::LogBuffer;
+use crate::log_metrics::LogMetrics;
+use crate::metrics::Metrics;
+use crate::nats::NatsSource;
+use crate::redact::Redactor;
+use crate::source::{Pipeline, SourceRegistry, SourceStatus};
+use crate::tenant::Tenants;
+
+const BURST: usize = 5_000;
+const WAIT: Duration = Duration::from_secs(10);
+
+/// One client connection: its subscriptions and the tasks serving it
+struct Connection {
+    subscriptions: Arc<Mutex<Vec<(String, String)>>>,
+    out: mpsc::UnboundedSender<Vec<u8>>,
+    tasks: [JoinHandle<()>; 2],
+}
+
+#[derive(Default)]
+struct ServerState {
+    connections: Vec<Connection>,
+    accepted: usize,
+    /// Refuse new connections, as a server that is restarting would
+    down: bool,
+}
+
+struct FakeNats {
+    addr: SocketAddr,
+    state: Arc<Mutex<ServerState>>,
+}
+
+impl FakeNats {
+    async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let state = Arc::new(Mutex::new(ServerState::default()));
+        let accepting = state.clone();
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                let mut state = accepting.lock().unwrap();
+                if state.down {
+                    continue;
+                }
+                state.accepted += 1;
+                state.connections.push(serve(socket, addr));
+            }
+        });
+        Self { addr, state }
+    }
+
+    /// Deliver to every matching subscription; false when nobody was listening
+    fn publish(&self, subject: &str, payload: &[u8]) -> bool {
+        let state = self.state.lock().unwrap();
+        let mut delivered = false;
+        for connection in &state.connections {
+            for (pattern, sid) in connection.subscriptions.lock().unwrap().iter() {
+                if !subject_matches(pattern, subject) {
+                    continue;
+                }
+                let mut frame =
+                    format!("MSG {} {} {}\r\n", subject, sid, payload.len()).into_bytes();
+                frame.extend_from_slice(payload);
+                frame.extend_from_slice(b"\r\n");
+                delivered |= connection.out.send(frame).is_ok();
+            }
+        }
+        delivered
+    }
+
+    fn subscribed(&self) -> bool {
+        let state = self.state.lock().unwrap();
+        state
+            .connections
+            .iter()
+            .any(|c| !c.subscriptions.lock().unwrap().is_empty())
+    }
+
+    /// Drop every connection and refuse new ones until `up`
+    fn go_down(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.down = true;
+        for connection in state.connections.drain(..) {
+            for task in connection.tasks {
+                task.abort();
+            }
+        }
+    }
+
+    fn go_up(&self) {
+        self.state.lock().unwrap().down = false;
+    }
+
+    fn accepted(&self) -> usize {
+        self.state.lock().unwrap().accepted
+    }
+}
+
+fn serve(socket: tokio::net::TcpStream, addr: SocketAddr) -> Connection {
+    let (reader, mut writer) = socket.into_split();
+    let (out, mut rx) = mpsc::unbounded_channel::<Vec<u8>>();
+    let subscriptions = Arc::new(Mutex::new(Vec::new()));
+
+    let info = format!(
+        "INFO {{\"server_id\":\"soak\",\"server_name\":\"soak\",\"version\":\"2.10.0\",\
+         \"go\":\"go1.22\",\"host\":\"{}\",\"port\":{},\"headers\":true,\
+         \"max_payload\":1048576,\"proto\":1}}\r\n",
+        addr.ip(),
+        addr.port()
+    );
+    let _ = out.send(info.into_bytes());
+
+    let writing = tokio::spawn(async move {
+        while let Some(frame) = rx.recv().await {
+            if writer.write_all(&frame).await.is_err() {
+                return;
+            }
+        }
+    });
+
+    let replies = out.clone();
+    let subs = subscriptions.clone();
+    let reading = tokio::spawn(async move {
+        let mut reader = BufReader::new(reader);
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if reader.read_line(&mut line).await.unwrap_or(0) == 0 {
+                return;
+            }
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            match parts.first().map(|op| op.to_ascii_uppercase()).as_deref() {
+                Some("PING") => {
+                    let _ = replies.send(b"PONG\r\n".to_vec());
+                }
+                // SUB <subject> [queue] <sid>
+                Some("SUB") if parts.len() >= 3 => subs
+                    .lock()
+                    .unwrap()
+                    .push((parts[1].to_string(), parts[parts.len() - 1].to_string())),
+                Some("UNSUB") if parts.len() >= 2 => {
+                    subs.lock().unwrap().retain(|(_, sid)| sid != parts[1]);
+                }
+                // Skip the payload the client published
+                Some("PUB") | Some("HPUB") => {
+                    let size: usize = parts.last().and_then(|n| n.parse().ok()).unwrap_or(0);
+                    let mut payload = vec![0; size + 2];
+                    if reader.read_exact(&mut payload).await.is_err() {
+                        return;
+                    }
+                }
+                _ => {}
+            }
+        }
+    });
+
+    Connection {
+        subscriptions,
+        out,
+        tasks: [reading, writing],
+    }
+}
+
+/// NATS subject matching with `*` (one token) and `>` (the rest)
+fn subject_matches(pattern: &str, subject: &str) -> bool {
+    let mut tokens = subject.split('.');
+    for part in pattern.split('.') {
+        match (part, tokens.next()) {
+            (">", Some(_)) => return true,
+            ("*", Some(_)) => {}
+            (part, Some(token)) if part == token => {}
+            _ => return false,
+        }
+    }
+    tokens.next().is_none()
+}
+
+async fn wait_for(what: &str, condition: impl Fn() -> bool) {
+    let started = tokio::time::Instant::now();
+    while !condition() {
+        assert!(started.elapsed() < WAIT, "timed out waiting for {}", what);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+struct Harness {
+    nats: FakeNats,
+    metrics: Arc<Metrics>,
+    log_buffer: Arc<LogBuffer>,
+    registry: Arc<SourceRegistry>,
+    generator: LogGenerator,
+    /// Lines a subscriber was registered for
+    delivered: u64,
+    lost: u64,
+}
+
+impl Harness {
+    async fn start() -> (Self, broadcast::Receiver<crate::nats::LogMessage>) {
+        let nats = FakeNats::start().await;
+        let config = Arc::new(Config::for_tests(&format!("nats_url = \"{}\"", nats.addr)));
+        let metrics = Metrics::new();
+        let (tx, rx) = broadcast::channel(BURST * 8);
+        let log_buffer = LogBuffer::new(
+            crate::log_buffer::LogBufferConfig {
+                max_entries: BURST * 8,
+                max_age_minutes: 30,
+            },
+            None,
+        );
+        let pipeline = Pipeline::new(
+            metrics.clone(),
+            tx,
+            log_buffer.clone(),
+            Redactor::new(Default::default()),
+            LogMetrics::new(&[]),
+            Heartbeats::new(&[]),
+            Tenants::new(&ConfigStore::new((*config).clone(), None), &metrics),
+        );
+        let registry = SourceRegistry::new();
+        registry
+            .spawn(
+                Box::new(NatsSource::new(
+                    config,
+                    metrics.clone(),
+                    IngestFilter::new(&[]),
+                )),
+                pipeline,
+            )
+            .await;
+
+        let harness = Self {
+            nats,
+            metrics,
+            log_buffer,
+            registry,
+            generator: LogGenerator::new(1, 4),
+            delivered: 0,
+            lost: 0,
+        };
+        harness.wait_connected().await;
+        (harness, rx)
+    }
+
+    async fn wait_connected(&self) {
+        wait_for("subscription", || self.nats.subscribed()).await;
+        wait_for("connected flag", || self.metrics.is_nats_connected()).await;
+    }
+
+    fn publish(&mut self, payload: &[u8]) {
+        if self.nats.publish("logs.app.iad.web", payload) {
+            self.delivered += 1;
+        } else {
+            self.lost += 1;
+        }
+    }
+
+    fn burst(&mut self, lines: usize) {
+        for _ in 0..lines {
+            let line = self.generator.next_line();
+            self.publish(line.as_bytes());
+        }
+    }
+
+    /// Everything delivered so far was ingested exactly once
+    async fn assert_ingested(&self) {
+        let forwarded = || {
+            self.metrics
+                .health(std::time::Instant::now())
+                .messages_forwarded
+        };
+        wait_for("delivered lines to be forwarded", || {
+            forwarded() >= self.delivered
+        })
+        .await;
+        // Give any duplicates a moment to show up
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(forwarded(), self.delivered);
+        assert_eq!(self.log_buffer.total_count().await as u64, self.delivered);
+    }
+}
+
+#[tokio::test]
+async fn soak_nats_disconnects_malformed_payloads_and_bursts() {
+    let rounds: usize = std::env::var("SOAK_ROUNDS")
+        .ok()
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(3);
+    let (mut harness, mut rx) = Harness::start().await;
+
+    // Burst traffic straight after subscribing
+    harness.burst(BURST);
+    harness.assert_ingested().await;
+
+    // Malformed payloads are kept as raw lines rather than dropped or fatal
+    harness.publish(b"\xff\xfe not utf-8");
+    harness.publish(b"{\"message\": \"truncated");
+    harness.publish(b"");
+    harness.publish(&vec![b'x'; 64 * 1024]);
+    harness.assert_ingested().await;
+
+    for round in 1..=rounds {
+        harness.nats.go_down();
+        wait_for("disconnected flag", || !harness.metrics.is_nats_connected()).await;
+
+        // Published while nobody is connected: lost, by design
+        harness.burst(100);
+        assert_eq!(harness.lost, 100 * round as u64);
+
+        let accepted = harness.nats.accepted();
+        harness.nats.go_up();
+        harness.wait_connected().await;
+        assert_eq!(harness.nats.accepted(), accepted + 1);
+
+        harness.burst(BURST / 5);
+        harness.assert_ingested().await;
+    }
+
+    // The client reconnected by itself; the supervisor never restarted the source
+    let health = &harness.registry.snapshot().await[0];
+    assert_eq!(health.status, SourceStatus::Running);
+    assert_eq!(health.restarts, 0);
+    assert_eq!(health.errors, 0);
+    assert_eq!(health.messages_received, harness.delivered);
+
+    // Streaming clients saw every ingested line, in order
+    let mut previous = 0;
+    let mut streamed = 0u64;
+    while let Ok(log) = rx.try_recv() {
+        assert!(log.seq > previous);
+        previous = log.seq;
+        streamed += 1;
+    }
+    assert_eq!(streamed, harness.delivered);
+}
@@ -0,0 +1,178 @@
+//! The ingest pipeline and the sources this binary builds; the `LogSource`
+//! abstraction and its supervisor live in `flywatch_core::source`.
+
+use async_trait::async_trait;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tracing::warn;
+
+use crate::cluster::Cluster;
+use crate::config::Config;
+use crate::docker::DockerSource;
+use crate::file_tail::FileTailSource;
+use crate::heartbeat::Heartbeats;
+use crate::ingest_filter::IngestFilter;
+use crate::kubernetes::KubernetesSource;
+use crate::log_buffer::LogBuffer;
+use crate::log_metrics::LogMetrics;
+use crate::metrics::Metrics;
+use crate::nats::{LogMessage, NatsSource};
+use crate::redact::Redactor;
+use crate::sink::SinkRegistry;
+use crate::syslog::SyslogSource;
+use crate::tenant::Tenants;
+use crate::webhook::WebhookSource;
+
+pub use flywatch_core::source::*;
+
+// ==================== Pipeline ====================
+
+/// Shared fan-out used by every source: redaction, buffer, broadcast, and metrics
+pub struct Pipeline {
+    metrics: Arc<Metrics>,
+    tx: broadcast::Sender<LogMessage>,
+    log_buffer: Arc<LogBuffer>,
+    redactor: Arc<Redactor>,
+    log_metrics: Arc<LogMetrics>,
+    heartbeats: Arc<Heartbeats>,
+    tenants: Arc<Tenants>,
+    /// Exporters every buffered line is offered to
+    sinks: OnceLock<Arc<SinkRegistry>>,
+    /// Other replicas to share local lines with (CLUSTER_MODE)
+    cluster: OnceLock<Arc<Cluster>>,
+}
+
+impl Pipeline {
+    pub fn new(
+        metrics: Arc<Metrics>,
+        tx: broadcast::Sender<LogMessage>,
+        log_buffer: Arc<LogBuffer>,
+        redactor: Arc<Redactor>,
+        log_metrics: Arc<LogMetrics>,
+        heartbeats: Arc<Heartbeats>,
+        tenants: Arc<Tenants>,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            metrics,
+            tx,
+            log_buffer,
+            redactor,
+            log_metrics,
+            heartbeats,
+            tenants,
+            sinks: OnceLock::new(),
+            cluster: OnceLock::new(),
+        })
+    }
+
+    /// Also offer every buffered line to the sinks
+    pub fn export_to(&self, sinks: Arc<SinkRegistry>) {
+        let _ = self.sinks.set(sinks);
+    }
+
+    /// Publish lines from local sources to the other replicas
+    pub fn replicate_to(&self, cluster: Arc<Cluster>) {
+        let _ = self.cluster.set(cluster);
+    }
+
+    pub async fn ingest(&self, raw: String) {
+        self.ingest_on(None, raw).await
+    }
+
+    /// Ingest a line that arrived on a NATS subject
+    pub async fn ingest_on(&self, subject: Option<&str>, raw: String) {
+        // Scrub before anything is stored, streamed or shown to the model
+        let raw = self.redactor.redact_line(raw);
+
+        // Every replica subscribes to NATS itself; other lines only reach one
+        if let (None, Some(cluster)) = (subject, self.cluster.get()) {
+            cluster.publish(&raw).await;
+        }
+        self.buffer(subject, raw).await;
+    }
+
+    /// Ingest a line another replica published
+    pub async fn ingest_replica(&self, raw: String) {
+        let raw = self.redactor.redact_line(raw);
+        self.buffer(None, raw).await;
+    }
+
+    async fn buffer(&self, subject: Option<&str>, raw: String) {
+        // Push to log buffer for AI access
+        let log = self.log_buffer.push(raw).await;
+        self.log_metrics.observe(&log);
+        self.heartbeats.observe(&log, subject);
+        self.metrics.record_origin(
+            log.region.as_deref(),
+            log.instance.as_deref(),
+            log.is_error(),
+        );
+        self.tenants.route(&log).await;
+        let log = Arc::new(log);
+        if let Some(sinks) = self.sinks.get() {
+            sinks.append(&log);
+        }
+
+        // Broadcast to SSE/WebSocket clients
+        self.metrics.increment_messages_forwarded();
+        let _ = self.tx.send(log);
+    }
+}
+
+#[async_trait]
+impl Ingest for Pipeline {
+    async fn ingest_on(&self, subject: Option<&str>, raw: String) {
+        Pipeline::ingest_on(self, subject, raw).await
+    }
+
+    async fn ingest_replica(&self, raw: String) {
+        Pipeline::ingest_replica(self, raw).await
+    }
+}
+
+/// Source kinds `SOURCES` may list
+pub const SOURCE_KINDS: &[&str] = &["nats", "syslog", "webhook", "file", "docker", "kubernetes"];
+
+/// Build the sources enabled in `Config::sources`, whose kinds the config
+/// has already checked against `SOURCE_KINDS`
+pub fn build_sources(
+    config: &Arc<Config>,
+    metrics: &Arc<Metrics>,
+    ingest_filter: &Arc<IngestFilter>,
+) -> Vec<Box<dyn LogSource>> {
+    let mut sources: Vec<Box<dyn LogSource>> = Vec::new();
+
+    for kind in &config.sources {
+        match kind.as_str() {
+            "nats" => sources.push(Box::new(NatsSource::new(
+                config.clone(),
+                metrics.clone(),
+                ingest_filter.clone(),
+            ))),
+            "syslog" => sources.push(Box::new(SyslogSource::new(config.syslog_bind_addr.clone()))),
+            "webhook" => sources.push(Box::new(WebhookSource::new(
+                config.webhook_bind_addr.clone(),
+                config.auth_token.clone(),
+            ))),
+            "file" => sources.push(Box::new(FileTailSource::new(
+                config.file_tail_paths.clone(),
+                Duration::from_millis(config.file_tail_poll_ms),
+            ))),
+            "docker" => sources.push(Box::new(DockerSource::new(
+                config.docker_socket.clone(),
+                config.docker_label_filter.clone(),
+                config.docker_region_label.clone(),
+            ))),
+            "kubernetes" => sources.push(Box::new(KubernetesSource::new(
+                config.k8s_api_url.clone(),
+                config.k8s_namespace.clone(),
+                config.k8s_label_selector.clone(),
+                config.k8s_app_label.clone(),
+            ))),
+            other => warn!(kind = %other, "Skipping unknown source kind"),
+        }
+    }
+
+    sources
+}
@@ -0,0 +1,129 @@
+use async_trait::async_trait;
+use tokio::net::UdpSocket;
+use tracing::info;
+
+use crate::source::{fly_envelope, LogSource, SourceContext, SourceError};
+
+const MAX_DATAGRAM_SIZE: usize = 64 * 1024;
+
+/// Syslog (RFC 3164 / RFC 5424) receiver over UDP
+pub struct SyslogSource {
+    bind_addr: String,
+}
+
+impl SyslogSource {
+    pub fn new(bind_addr: String) -> Self {
+        Self { bind_addr }
+    }
+}
+
+#[async_trait]
+impl LogSource for SyslogSource {
+    fn name(&self) -> &str {
+        "syslog"
+    }
+
+    fn kind(&self) -> &'static str {
+        "syslog"
+    }
+
+    async fn run(&self, ctx: &SourceContext) -> Result<(), SourceError> {
+        let socket = UdpSocket::bind(&self.bind_addr)
+            .await
+            .map_err(|e| SourceError::Io(format!("bind {}: {}", self.bind_addr, e)))?;
+        info!(addr = %self.bind_addr, "Syslog source listening");
+        ctx.running().await;
+
+        let mut buf = vec![0u8; MAX_DATAGRAM_SIZE];
+        loop {
+            let (len, _peer) = socket
+                .recv_from(&mut buf)
+                .await
+                .map_err(|e| SourceError::Io(e.to_string()))?;
+
+            let datagram = String::from_utf8_lossy(&buf[..len]);
+            for line in datagram.lines().filter(|l| !l.trim().is_empty()) {
+                ctx.emit(to_fly_json(line)).await;
+            }
+        }
+    }
+}
+
+/// Map a syslog severity (0-7) onto the level names used in Fly logs
+fn severity_level(severity: u8) -> &'static str {
+    match severity {
+        0..=3 => "error",
+        4 => "warn",
+        5 | 6 => "info",
+        _ => "debug",
+    }
+}
+
+/// Parse a syslog line into (level, hostname, message)
+fn parse_syslog(line: &str) -> (Option<&'static str>, Option<&str>, &str) {
+    let Some(rest) = line.strip_prefix('<') else {
+        return (None, None, line);
+    };
+    let Some((pri, rest)) = rest.split_once('>') else {
+        return (None, None, line);
+    };
+    let Ok(pri) = pri.parse::<u16>() else {
+        return (None, None, line);
+    };
+    let level = Some(severity_level((pri % 8) as u8));
+
+    if let Some(rest) = rest.strip_prefix("1 ") {
+        // RFC 5424: TIMESTAMP HOSTNAME APP-NAME PROCID MSGID SD MSG
+        let mut parts = rest.splitn(6, ' ');
+        let _timestamp = parts.next();
+        let hostname = parts.next().filter(|h| *h != "-");
+        let message = parts.nth(3).unwrap_or("");
+        // Skip a nil structured-data marker
+        let message = message.strip_prefix("- ").unwrap_or(message);
+        (level, hostname, message)
+    } else {
+        // RFC 3164: "Mmm dd hh:mm:ss HOSTNAME TAG: MSG"
+        let mut parts = rest.splitn(5, ' ').filter(|p| !p.is_empty());
+        let (_mon, _day, _time) = (parts.next(), parts.next(), parts.next());
+        match (parts.next(), parts.next()) {
+            (Some(host), Some(message)) => (level, Some(host), message),
+            _ => (level, None, rest),
+        }
+    }
+}
+
+/// Re-shape a syslog line as Fly-style JSON so the buffer parses its metadata
+fn to_fly_json(line: &str) -> String {
+    let (level, hostname, message) = parse_syslog(line);
+    fly_envelope(message, level, hostname, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rfc3164() {
+        let (level, host, msg) = parse_syslog("<11>Oct 14 10:00:00 web-1 app: disk full");
+        assert_eq!(level, Some("error"));
+        assert_eq!(host, Some("web-1"));
+        assert_eq!(msg, "app: disk full");
+    }
+
+    #[test]
+    fn test_parse_rfc5424() {
+        let (level, host, msg) =
+            parse_syslog("<165>1 2026-10-14T10:00:00Z web-2 app 123 ID47 - started");
+        assert_eq!(level, Some("info"));
+        assert_eq!(host, Some("web-2"));
+        assert_eq!(msg, "started");
+    }
+
+    #[test]
+    fn test_parse_plain_line() {
+        let (level, host, msg) = parse_syslog("no priority here");
+        assert_eq!(level, None);
+        assert_eq!(host, None);
+        assert_eq!(msg, "no priority here");
+    }
+}
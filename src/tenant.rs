@@ -0,0 +1,300 @@
+//! Multi-tenant mode: each entry in `TENANTS` gets its own Fly apps, log
+//! buffer, stream fan-out, chat usage and token, served under `/t/<name>/`:
+//!
+//! ```text
+//! acme app=acme-web app=acme-api token=s3cret budget=50
+//! globex app=globex token=hunter2
+//! ```
+//!
+//! Lines from a tenant's apps are copied into its buffer once the shared
+//! pipeline has redacted them, keeping their sequence numbers. A tenant's
+//! token only opens its own prefix; the unprefixed routes keep serving every
+//! app to AUTH_TOKEN holders.
+
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::sync::broadcast;
+
+use crate::audit::ToolAudit;
+use crate::chat_cache::ChatCache;
+use crate::config::{Config, ConfigStore};
+use crate::fanout::Fanout;
+use crate::http_analytics::HttpAnalytics;
+use crate::log_buffer::{LogBuffer, LogBufferConfig, TimestampedLog};
+use crate::metrics::Metrics;
+use crate::nats::LogMessage;
+use crate::transcripts::Transcripts;
+use crate::usage::UsageTracker;
+use crate::views::Views;
+
+/// One tenant, as written in `TENANTS`
+#[derive(Debug, Clone, PartialEq)]
+pub struct TenantDefinition {
+    /// Path segment under `/t/`
+    pub name: String,
+    /// Fly apps whose logs the tenant sees
+    pub apps: Vec<String>,
+    pub token: String,
+    /// Monthly chat budget, overriding CHAT_MONTHLY_BUDGET_USD
+    pub budget_usd: Option<f64>,
+}
+
+/// Lowercase letters, digits and dashes, so names are safe in URLs and paths
+fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+}
+
+impl FromStr for TenantDefinition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = s.split_whitespace();
+        let name = words.next().unwrap_or_default().to_string();
+        if name.contains('=') {
+            return Err("a tenant starts with its name".to_string());
+        }
+        if !valid_name(&name) {
+            return Err(format!(
+                "invalid name '{}' (use lowercase letters, digits and dashes)",
+                name
+            ));
+        }
+
+        let (mut apps, mut token, mut budget_usd) = (Vec::new(), None, None);
+        for word in words {
+            let (key, value) = word
+                .split_once('=')
+                .ok_or_else(|| format!("expected key=value, found '{}'", word))?;
+            match key {
+                "app" => apps.push(value.to_string()),
+                "token" => token = Some(value.to_string()).filter(|t| !t.is_empty()),
+                "budget" => match value.parse::<f64>() {
+                    Ok(budget) if budget > 0.0 => budget_usd = Some(budget),
+                    _ => return Err(format!("invalid budget '{}' (USD, e.g. 50)", value)),
+                },
+                other => return Err(format!("unknown option '{}'", other)),
+            }
+        }
+
+        if apps.is_empty() {
+            return Err("at least one app= is required".to_string());
+        }
+        Ok(TenantDefinition {
+            name,
+            apps,
+            token: token.ok_or("token= is required")?,
+            budget_usd,
+        })
+    }
+}
+
+/// Where a tenant's buffer, usage and audit records live:
+/// `/data/flywatch.db` becomes `/data/flywatch-acme.db`
+pub fn store_path(store_path: &str, tenant: &str) -> String {
+    let path = Path::new(store_path);
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy())
+        .unwrap_or_default();
+    let file = match path.extension() {
+        Some(ext) => format!("{}-{}.{}", stem, tenant, ext.to_string_lossy()),
+        None => format!("{}-{}", stem, tenant),
+    };
+    path.with_file_name(file).to_string_lossy().into_owned()
+}
+
+/// A tenant's isolated runtime state
+pub struct Tenant {
+    pub definition: TenantDefinition,
+    /// The shared config with this tenant's overrides
+    pub config: Arc<ConfigStore>,
+    pub log_buffer: Arc<LogBuffer>,
+    pub fanout: Arc<Fanout>,
+    pub usage_tracker: Arc<UsageTracker>,
+    pub tool_audit: Arc<ToolAudit>,
+    pub transcripts: Arc<Transcripts>,
+    pub chat_cache: Arc<ChatCache>,
+    pub views: Arc<Views>,
+    pub http_analytics: Arc<HttpAnalytics>,
+    tx: broadcast::Sender<LogMessage>,
+}
+
+impl Tenant {
+    fn new(
+        store: &Arc<ConfigStore>,
+        definition: &TenantDefinition,
+        metrics: &Arc<Metrics>,
+    ) -> Self {
+        let config_store = ConfigStore::for_tenant(store, definition);
+        let config = config_store.current();
+        let store_path = config.store_path.as_deref();
+
+        let log_buffer = LogBuffer::new(
+            LogBufferConfig {
+                max_entries: config.log_buffer_max_entries,
+                max_age_minutes: config.log_buffer_max_age_minutes,
+            },
+            store_path,
+        );
+        let (tx, _) = broadcast::channel(config.channel_capacity);
+        let fanout = Fanout::new(
+            config.connection_queue_capacity,
+            config.connection_overflow_policy,
+            metrics.clone(),
+        );
+        fanout.set_channels(&config.channels);
+        fanout.start(tx.subscribe());
+        let http_analytics = HttpAnalytics::new(config.http_analytics_window_minutes);
+        http_analytics.start(tx.subscribe(), metrics.clone());
+
+        Self {
+            definition: definition.clone(),
+            config: config_store,
+            log_buffer,
+            fanout,
+            usage_tracker: Arc::new(UsageTracker::new(store_path, config.usage_retention_days)),
+            tool_audit: Arc::new(ToolAudit::new(store_path)),
+            transcripts: Arc::new(Transcripts::new(store_path)),
+            chat_cache: Arc::new(ChatCache::new()),
+            views: Views::new(store_path),
+            http_analytics,
+            tx,
+        }
+    }
+
+    fn owns(&self, log: &TimestampedLog) -> bool {
+        log.app
+            .as_deref()
+            .is_some_and(|app| self.definition.apps.iter().any(|a| a == app))
+    }
+}
+
+/// Every configured tenant; empty outside multi-tenant mode
+pub struct Tenants {
+    tenants: Vec<Tenant>,
+}
+
+impl Tenants {
+    pub fn new(store: &Arc<ConfigStore>, metrics: &Arc<Metrics>) -> Arc<Self> {
+        let config: Arc<Config> = store.current();
+        Arc::new(Self {
+            tenants: config
+                .tenants
+                .iter()
+                .map(|definition| Tenant::new(store, definition, metrics))
+                .collect(),
+        })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Tenant> {
+        self.tenants.iter()
+    }
+
+    /// Copy a buffered line into the buffers and streams of tenants that own
+    /// its app. The copy keeps the shared seq, so annotations, Last-Event-ID
+    /// and `/logs/since` agree between the shared and tenant routes.
+    pub async fn route(&self, log: &TimestampedLog) {
+        for tenant in self.tenants.iter().filter(|t| t.owns(log)) {
+            let copy = tenant.log_buffer.push_sequenced(log.clone()).await;
+            let _ = tenant.tx.send(Arc::new(copy));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_tenants() {
+        let acme: TenantDefinition = "acme app=acme-web app=acme-api token=s3cret budget=50"
+            .parse()
+            .unwrap();
+        assert_eq!(acme.apps, vec!["acme-web", "acme-api"]);
+        assert_eq!(acme.token, "s3cret");
+        assert_eq!(acme.budget_usd, Some(50.0));
+
+        assert!("Acme app=a token=t".parse::<TenantDefinition>().is_err());
+        assert!("acme token=t".parse::<TenantDefinition>().is_err());
+        assert!("acme app=a".parse::<TenantDefinition>().is_err());
+        assert!("acme app=a token=t budget=-1"
+            .parse::<TenantDefinition>()
+            .is_err());
+        assert!("app=a token=t".parse::<TenantDefinition>().is_err());
+
+        assert_eq!(
+            store_path("/data/flywatch.db", "acme"),
+            "/data/flywatch-acme.db"
+        );
+        assert_eq!(store_path("logs", "acme"), "logs-acme");
+    }
+
+    #[tokio::test]
+    async fn test_routed_lines_keep_the_shared_seq() {
+        let config = Config::for_tests(
+            "auth_token = \"admin\"\ntenants = [\"acme app=acme-web token=s3cret\"]",
+        );
+        let tenants = Tenants::new(&ConfigStore::new(config, None), &Metrics::new());
+        let line = r#"{"message":"hi","fly":{"app":{"name":"acme-web"}}}"#;
+        tenants
+            .route(&TimestampedLog::new(line.to_string(), 42))
+            .await;
+        tenants
+            .route(&TimestampedLog::new(
+                r#"{"message":"other"}"#.to_string(),
+                43,
+            ))
+            .await;
+
+        let acme = tenants.iter().next().unwrap();
+        let (logs, _) = acme.log_buffer.get_since(0, 10).await;
+        let seqs: Vec<u64> = logs.iter().map(|l| l.seq).collect();
+        assert_eq!(seqs, vec![42]);
+    }
+
+    #[tokio::test]
+    async fn test_tenant_http_analytics_see_only_its_apps() {
+        use crate::source::fly_app_envelope;
+
+        let config = Config::for_tests(
+            "auth_token = \"admin\"\ntenants = [\"acme app=acme-web token=s3cret\"]",
+        );
+        let tenants = Tenants::new(&ConfigStore::new(config, None), &Metrics::new());
+        for (seq, app) in [(1, "acme-web"), (2, "globex")] {
+            let raw = fly_app_envelope("GET /cart/1 200 in 5ms", None, Some(app), None, None);
+            tenants.route(&TimestampedLog::new(raw, seq)).await;
+        }
+
+        let acme = tenants.iter().next().unwrap();
+        let deadline = tokio::time::Instant::now() + std::time::Duration::from_secs(2);
+        while acme.http_analytics.snapshot(None, None).requests == 0
+            && tokio::time::Instant::now() < deadline
+        {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert_eq!(acme.http_analytics.snapshot(None, None).requests, 1);
+    }
+
+    #[test]
+    fn test_tenant_view_follows_the_shared_config() {
+        let config = Config::for_tests(
+            "auth_token = \"admin\"\nstore_path = \"/data/flywatch.db\"\n\
+             tenants = [\"acme app=acme-web app=acme-api token=s3cret\"]",
+        );
+        let definition = config.tenants[0].clone();
+        let store = ConfigStore::new(config, None);
+        let view = ConfigStore::for_tenant(&store, &definition);
+
+        let scoped = view.current();
+        assert_eq!(scoped.auth_token.as_deref(), Some("s3cret"));
+        assert_eq!(scoped.fly_prod_app_name, "acme-web");
+        assert_eq!(scoped.tls_client_cns, vec!["acme"]);
+        assert_eq!(scoped.store_path.as_deref(), Some("/data/flywatch-acme.db"));
+        assert!(scoped.tenants.is_empty());
+        assert!(Arc::ptr_eq(&scoped, &view.current()));
+    }
+}
@@ -0,0 +1,95 @@
+//! Truncation that never splits a UTF-8 sequence, for log text cut down to
+//! fit a prompt, an audit record, a WebSocket frame or the line size cap.
+
+/// The first `max` characters of `text`
+pub fn prefix_chars(text: &str, max: usize) -> &str {
+    match text.char_indices().nth(max) {
+        Some((end, _)) => &text[..end],
+        None => text,
+    }
+}
+
+/// Cut `text` to at most `max` characters, marking the cut with "..."
+pub fn truncate_chars(text: &str, max: usize) -> String {
+    match text.char_indices().nth(max) {
+        Some((end, _)) => format!("{}...", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+/// The longest prefix of `text` that fits in `max` bytes
+pub fn prefix_bytes(text: &str, max: usize) -> &str {
+    if text.len() <= max {
+        return text;
+    }
+    let mut end = max;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+/// Bytes set aside for the marker `elide_middle` puts in the gap
+const MARKER_BYTES: usize = 40;
+
+/// Cut `bytes` to at most `max` bytes by dropping the middle, so both the
+/// start of a stack dump and its innermost frames survive. The gap is marked
+/// with how many bytes were dropped; invalid UTF-8 is replaced as usual.
+pub fn elide_middle(bytes: &[u8], max: usize) -> String {
+    if bytes.len() <= max {
+        return String::from_utf8_lossy(bytes).into_owned();
+    }
+    let keep = max.saturating_sub(MARKER_BYTES);
+    let head = &bytes[..keep / 2];
+    // Back off a sequence cut in half at either end
+    let head = match std::str::from_utf8(head) {
+        Err(e) if e.error_len().is_none() => &head[..e.valid_up_to()],
+        _ => head,
+    };
+    let mut tail = &bytes[bytes.len() - (keep - keep / 2)..];
+    while tail.first().is_some_and(|b| b & 0xc0 == 0x80) {
+        tail = &tail[1..];
+    }
+    format!(
+        "{}…[truncated {} bytes]…{}",
+        String::from_utf8_lossy(head),
+        bytes.len() - head.len() - tail.len(),
+        String::from_utf8_lossy(tail)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_truncation_respects_char_boundaries() {
+        assert_eq!(truncate_chars("héllo", 2), "hé...");
+        assert_eq!(truncate_chars("hi", 5), "hi");
+        assert_eq!(prefix_chars("🔥🔥🔥 deploy", 2), "🔥🔥");
+        assert_eq!(prefix_chars("short", 12), "short");
+
+        // Each flame is 4 bytes; a cut at 6 backs off to the first one
+        assert_eq!(prefix_bytes("🔥🔥", 6), "🔥");
+        assert_eq!(prefix_bytes("🔥🔥", 8), "🔥🔥");
+        assert_eq!(prefix_bytes("🔥", 3), "");
+    }
+
+    #[test]
+    fn test_elide_middle_keeps_head_and_tail() {
+        assert_eq!(elide_middle(b"short", 100), "short");
+
+        let line = format!("panic: boom{}innermost frame", "x".repeat(10_000));
+        let cut = elide_middle(line.as_bytes(), 100);
+        assert!(cut.len() <= 100);
+        assert!(cut.starts_with("panic: boom"));
+        assert!(cut.ends_with("innermost frame"));
+        assert!(cut.contains("…[truncated 9966 bytes]…"));
+
+        // Cuts inside a multi-byte character move to its edge
+        let flames = "🔥".repeat(100);
+        let cut = elide_middle(flames.as_bytes(), 90);
+        assert!(!cut.contains('\u{fffd}'));
+        assert!(cut.starts_with("🔥") && cut.ends_with("🔥"));
+    }
+}
@@ -0,0 +1,962 @@
+//! Issue trackers for incident follow-ups. With GITHUB_ISSUES_TOKEN and
+//! GITHUB_ISSUES_REPO, LINEAR_API_KEY and LINEAR_TEAM_ID, or the JIRA_*
+//! settings, flywatch files issues:
+//!
+//! - for findings someone has confirmed, with `POST /tickets`: the
+//!   `findings` of a `"response_format": "structured"` chat answer
+//! - for firing alerts, with TICKET_ALERTS: the `github`, `linear` and
+//!   `jira` notifiers, which ALERT_ROUTES picks like any other
+//!
+//! Each issue carries the summary (for alerts, the AI analysis when there is
+//! one), the log lines it rests on and, with PUBLIC_URL, a link to download
+//! the time window.
+
+use async_trait::async_trait;
+use axum::{extract::State, http::HeaderMap, Json};
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use tracing::info;
+use utoipa::ToSchema;
+
+use crate::alerts::{self, Alert, AlertStatus, Notifier};
+use crate::analysis::{self, Finding, Severity};
+use crate::config::Config;
+use crate::error::{ApiError, ErrorBody};
+use crate::http::{check_auth, AppState};
+use crate::log_buffer::{LogBuffer, TimestampedLog};
+use crate::prompt::format_logs_compact;
+use crate::text::truncate_chars;
+
+const LINEAR_API_URL: &str = "https://api.linear.app/graphql";
+/// Most log lines quoted in one issue
+const MAX_EXCERPT_LINES: usize = 20;
+const MAX_TITLE_CHARS: usize = 120;
+/// Logs linked around a finding's evidence
+const EVIDENCE_MARGIN: Duration = Duration::minutes(5);
+/// Logs linked before an alert started, and quoted when they are errors
+const ALERT_LOOKBACK: Duration = Duration::minutes(15);
+/// Findings filed by one request
+const MAX_FINDINGS: usize = 20;
+
+/// An issue ready to file
+#[derive(Debug, Clone, PartialEq)]
+pub struct TicketDraft {
+    pub title: String,
+    /// Markdown
+    pub body: String,
+    pub severity: Severity,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct FiledTicket {
+    /// `github`, `linear` or `jira`
+    pub tracker: String,
+    /// Issue number or key, e.g. `412` or `OPS-12`
+    pub id: String,
+    pub url: String,
+    pub title: String,
+}
+
+/// Somewhere issues can be filed
+#[async_trait]
+pub trait Tracker: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    async fn file(&self, draft: &TicketDraft) -> Result<FiledTicket, String>;
+}
+
+/// Opens issues in one repository through the REST API
+pub struct GitHubTracker {
+    api_url: String,
+    token: String,
+    repo: String,
+    labels: Vec<String>,
+    client: Client,
+}
+
+impl GitHubTracker {
+    pub fn new(api_url: String, token: String, repo: String, labels: Vec<String>) -> Self {
+        Self {
+            api_url,
+            token,
+            repo,
+            labels,
+            client: alerts::notify_client(),
+        }
+    }
+}
+
+#[async_trait]
+impl Tracker for GitHubTracker {
+    fn name(&self) -> &'static str {
+        "github"
+    }
+
+    async fn file(&self, draft: &TicketDraft) -> Result<FiledTicket, String> {
+        let response = self
+            .client
+            .post(format!("{}/repos/{}/issues", self.api_url, self.repo))
+            .bearer_auth(&self.token)
+            .header("Accept", "application/vnd.github+json")
+            .header("User-Agent", "flywatch")
+            .json(&json!({
+                "title": draft.title,
+                "body": draft.body,
+                "labels": self.labels,
+            }))
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(format!("GitHub returned {}: {}", status, body));
+        }
+        let issue: Value = response.json().await.map_err(|e| e.to_string())?;
+        Ok(FiledTicket {
+            tracker: self.name().to_string(),
+            id: issue["number"].to_string(),
+            url: issue["html_url"].as_str().unwrap_or_default().to_string(),
+            title: draft.title.clone(),
+        })
+    }
+}
+
+/// Creates issues for one team through the GraphQL API
+pub struct LinearTracker {
+    api_url: String,
+    api_key: String,
+    team_id: String,
+    client: Client,
+}
+
+impl LinearTracker {
+    pub fn new(api_key: String, team_id: String) -> Self {
+        Self {
+            api_url: LINEAR_API_URL.to_string(),
+            api_key,
+            team_id,
+            client: alerts::notify_client(),
+        }
+    }
+
+    /// Linear's priorities: 1 urgent to 4 low, 0 for none
+    fn priority(severity: Severity) -> u8 {
+        match severity {
+            Severity::Critical => 1,
+            Severity::High => 2,
+            Severity::Medium => 3,
+            Severity::Low => 4,
+            Severity::Info => 0,
+        }
+    }
+}
+
+#[async_trait]
+impl Tracker for LinearTracker {
+    fn name(&self) -> &'static str {
+        "linear"
+    }
+
+    async fn file(&self, draft: &TicketDraft) -> Result<FiledTicket, String> {
+        let query = "mutation IssueCreate($input: IssueCreateInput!) { \
+                     issueCreate(input: $input) { success issue { identifier url } } }";
+        let response = self
+            .client
+            .post(&self.api_url)
+            .header("Authorization", &self.api_key)
+            .json(&json!({
+                "query": query,
+                "variables": { "input": {
+                    "teamId": self.team_id,
+                    "title": draft.title,
+                    "description": draft.body,
+                    "priority": Self::priority(draft.severity),
+                }},
+            }))
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        let status = response.status();
+        let body: Value = response.json().await.map_err(|e| e.to_string())?;
+        // GraphQL errors can come with a 200
+        if let Some(error) = body["errors"][0]["message"].as_str() {
+            return Err(format!("Linear returned {}: {}", status, error));
+        }
+        let issue = &body["data"]["issueCreate"]["issue"];
+        match issue["url"].as_str() {
+            Some(url) if status.is_success() => Ok(FiledTicket {
+                tracker: self.name().to_string(),
+                id: issue["identifier"].as_str().unwrap_or_default().to_string(),
+                url: url.to_string(),
+                title: draft.title.clone(),
+            }),
+            _ => Err(format!("Linear returned {} without an issue", status)),
+        }
+    }
+}
+
+/// One `JIRA_PRIORITIES` entry: `severity=priority`, e.g. `critical=Highest`
+#[derive(Debug, Clone, PartialEq)]
+pub struct JiraPriority {
+    pub severity: Severity,
+    /// The priority's name in Jira
+    pub priority: String,
+}
+
+impl FromStr for JiraPriority {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (severity, priority) = s
+            .split_once('=')
+            .filter(|(_, priority)| !priority.trim().is_empty())
+            .ok_or_else(|| format!("expected severity=priority, found '{}'", s))?;
+        let severity = serde_json::from_value(Value::String(severity.trim().to_lowercase()))
+            .map_err(|_| {
+                format!(
+                    "unknown severity '{}' (use critical, high, medium, low or info)",
+                    severity.trim()
+                )
+            })?;
+        Ok(Self {
+            severity,
+            priority: priority.trim().to_string(),
+        })
+    }
+}
+
+/// One `JIRA_FIELDS` entry: `field=value`, set on every issue. The value is
+/// taken as JSON when it parses as JSON, e.g. `labels=["flywatch"]` or
+/// `customfield_10020={"value":"Checkout"}`, and as a string otherwise
+#[derive(Debug, Clone, PartialEq)]
+pub struct JiraField {
+    pub field: String,
+    pub value: Value,
+}
+
+impl FromStr for JiraField {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (field, value) = s
+            .split_once('=')
+            .filter(|(field, value)| !field.trim().is_empty() && !value.trim().is_empty())
+            .ok_or_else(|| format!("expected field=value, found '{}'", s))?;
+        let field = field.trim();
+        if matches!(
+            field,
+            "project" | "issuetype" | "summary" | "description" | "priority"
+        ) {
+            return Err(format!(
+                "'{}' is set by flywatch (see JIRA_PROJECT_KEY, JIRA_ISSUE_TYPE and JIRA_PRIORITIES)",
+                field
+            ));
+        }
+        let value = value.trim();
+        Ok(Self {
+            field: field.to_string(),
+            value: serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.to_string())),
+        })
+    }
+}
+
+/// Creates issues in one Jira Cloud project through the REST API (v3)
+pub struct JiraTracker {
+    base_url: String,
+    email: String,
+    api_token: String,
+    project_key: String,
+    issue_type: String,
+    priorities: Vec<JiraPriority>,
+    fields: Vec<JiraField>,
+    client: Client,
+}
+
+impl JiraTracker {
+    pub fn new(config: &Config) -> Option<Self> {
+        Some(Self {
+            base_url: config.jira_base_url.clone()?,
+            email: config.jira_email.clone()?,
+            api_token: config.jira_api_token.clone()?,
+            project_key: config.jira_project_key.clone()?,
+            issue_type: config.jira_issue_type.clone(),
+            priorities: config.jira_priorities.clone(),
+            fields: config.jira_fields.clone(),
+            client: alerts::notify_client(),
+        })
+    }
+
+    fn fields(&self, draft: &TicketDraft) -> Value {
+        let mut fields = json!({
+            "project": { "key": self.project_key },
+            "issuetype": { "name": self.issue_type },
+            "summary": draft.title,
+            "description": adf(&draft.body),
+        });
+        if let Some(mapped) = self
+            .priorities
+            .iter()
+            .find(|p| p.severity == draft.severity)
+        {
+            fields["priority"] = json!({ "name": mapped.priority });
+        }
+        for extra in &self.fields {
+            fields[extra.field.as_str()] = extra.value.clone();
+        }
+        fields
+    }
+}
+
+#[async_trait]
+impl Tracker for JiraTracker {
+    fn name(&self) -> &'static str {
+        "jira"
+    }
+
+    async fn file(&self, draft: &TicketDraft) -> Result<FiledTicket, String> {
+        let response = self
+            .client
+            .post(format!("{}/rest/api/3/issue", self.base_url))
+            .basic_auth(&self.email, Some(&self.api_token))
+            .header("Accept", "application/json")
+            .json(&json!({ "fields": self.fields(draft) }))
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if !response.status().is_success() {
+            // Field problems come back as {"errors": {"field": "why"}}
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(format!("Jira returned {}: {}", status, body));
+        }
+        let issue: Value = response.json().await.map_err(|e| e.to_string())?;
+        let key = issue["key"].as_str().unwrap_or_default();
+        Ok(FiledTicket {
+            tracker: self.name().to_string(),
+            id: key.to_string(),
+            url: format!("{}/browse/{}", self.base_url, key),
+            title: draft.title.clone(),
+        })
+    }
+}
+
+/// A text node, with `**bold**`, `` `code` `` and `[text](url)` turned into
+/// marks; other markdown is left as written
+fn adf_inline(text: &str) -> Vec<Value> {
+    let mut nodes = Vec::new();
+    let mut plain = String::new();
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        let marked = match c {
+            '*' if rest.starts_with("**") => rest[2..]
+                .find("**")
+                .map(|end| (&rest[2..2 + end], json!({"type": "strong"}), 4 + end)),
+            '`' => rest[1..]
+                .find('`')
+                .map(|end| (&rest[1..1 + end], json!({"type": "code"}), 2 + end)),
+            '[' => rest.find("](").and_then(|mid| {
+                rest[mid + 2..].find(')').map(|end| {
+                    let url = &rest[mid + 2..mid + 2 + end];
+                    (
+                        &rest[1..mid],
+                        json!({"type": "link", "attrs": {"href": url}}),
+                        mid + 3 + end,
+                    )
+                })
+            }),
+            _ => None,
+        };
+        match marked {
+            Some((inner, mark, len)) if !inner.is_empty() => {
+                if !plain.is_empty() {
+                    nodes.push(json!({"type": "text", "text": std::mem::take(&mut plain)}));
+                }
+                nodes.push(json!({"type": "text", "text": inner, "marks": [mark]}));
+                rest = &rest[len..];
+            }
+            _ => {
+                plain.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+    if !plain.is_empty() {
+        nodes.push(json!({"type": "text", "text": plain}));
+    }
+    nodes
+}
+
+/// An issue body in the Atlassian Document Format Jira's v3 API takes:
+/// paragraphs, `###` headings and fenced code blocks
+fn adf(markdown: &str) -> Value {
+    let mut content: Vec<Value> = Vec::new();
+    let mut paragraph: Vec<&str> = Vec::new();
+    let mut code: Option<Vec<&str>> = None;
+    let flush = |paragraph: &mut Vec<&str>, content: &mut Vec<Value>| {
+        if !paragraph.is_empty() {
+            let text = paragraph.join(" ");
+            content.push(json!({"type": "paragraph", "content": adf_inline(&text)}));
+            paragraph.clear();
+        }
+    };
+    for line in markdown.lines() {
+        if let Some(lines) = code.as_mut() {
+            if line.trim_start().starts_with("```") {
+                let text = lines.join("\n");
+                let block = match text.is_empty() {
+                    true => json!({"type": "codeBlock"}),
+                    false => {
+                        json!({"type": "codeBlock", "content": [{"type": "text", "text": text}]})
+                    }
+                };
+                content.push(block);
+                code = None;
+            } else {
+                lines.push(line);
+            }
+        } else if line.trim_start().starts_with("```") {
+            flush(&mut paragraph, &mut content);
+            code = Some(Vec::new());
+        } else if let Some(heading) = line.strip_prefix("### ") {
+            flush(&mut paragraph, &mut content);
+            content.push(json!({
+                "type": "heading",
+                "attrs": {"level": 3},
+                "content": adf_inline(heading.trim()),
+            }));
+        } else if line.trim().is_empty() {
+            flush(&mut paragraph, &mut content);
+        } else {
+            paragraph.push(line.trim());
+        }
+    }
+    // An unclosed fence keeps its lines as code
+    if let Some(lines) = code.filter(|lines| !lines.is_empty()) {
+        content.push(
+            json!({"type": "codeBlock", "content": [{"type": "text", "text": lines.join("\n")}]}),
+        );
+    }
+    flush(&mut paragraph, &mut content);
+    json!({"type": "doc", "version": 1, "content": content})
+}
+
+/// The configured trackers: GitHub, then Linear, then Jira
+pub fn trackers(config: &Config) -> Vec<Arc<dyn Tracker>> {
+    let mut trackers: Vec<Arc<dyn Tracker>> = Vec::new();
+    if let (Some(token), Some(repo)) = (&config.github_issues_token, &config.github_issues_repo) {
+        trackers.push(Arc::new(GitHubTracker::new(
+            config.github_api_url.clone(),
+            token.clone(),
+            repo.clone(),
+            config.github_issues_labels.clone(),
+        )));
+    }
+    if let (Some(key), Some(team)) = (&config.linear_api_key, &config.linear_team_id) {
+        trackers.push(Arc::new(LinearTracker::new(key.clone(), team.clone())));
+    }
+    if let Some(jira) = JiraTracker::new(config) {
+        trackers.push(Arc::new(jira));
+    }
+    trackers
+}
+
+fn title(severity: Severity, summary: &str) -> String {
+    let severity = serde_json::to_value(severity).unwrap_or_default();
+    let first_line = summary.lines().next().unwrap_or_default().trim();
+    truncate_chars(
+        &format!("[{}] {}", severity.as_str().unwrap_or_default(), first_line),
+        MAX_TITLE_CHARS,
+    )
+}
+
+/// The issue body: the text, the log lines quoted, and where to find the rest
+fn body(
+    config: &Config,
+    text: &str,
+    action: Option<&str>,
+    logs: &[TimestampedLog],
+    window: (DateTime<Utc>, DateTime<Utc>),
+    origin: &str,
+) -> String {
+    let mut body = text.trim().to_string();
+    if let Some(action) = action.filter(|a| !a.trim().is_empty()) {
+        body.push_str(&format!("\n\n**Suggested action:** {}", action.trim()));
+    }
+    if !logs.is_empty() {
+        let quoted = &logs[logs.len().saturating_sub(MAX_EXCERPT_LINES)..];
+        body.push_str(&format!(
+            "\n\n### Log excerpts\n\n```\n{}\n```",
+            format_logs_compact(quoted)
+        ));
+    }
+    let (from, to) = (
+        window.0.to_rfc3339_opts(SecondsFormat::Secs, true),
+        window.1.to_rfc3339_opts(SecondsFormat::Secs, true),
+    );
+    match &config.public_url {
+        Some(url) => body.push_str(&format!(
+            "\n\n[Logs from {} to {}]({}/logs/download?from={}&to={})",
+            from, to, url, from, to
+        )),
+        None => body.push_str(&format!("\n\nLogs from {} to {}", from, to)),
+    }
+    body.push_str(&format!(
+        "\n\n_Filed by flywatch for {} ({})_",
+        config.fly_prod_app_name, origin
+    ));
+    body
+}
+
+/// An issue for a confirmed finding, quoting its evidence
+pub async fn finding_draft(
+    config: &Config,
+    buffer: &LogBuffer,
+    finding: &Finding,
+    conversation_id: Option<&str>,
+) -> TicketDraft {
+    let logs = analysis::evidence(buffer, &finding.evidence_log_seqs).await;
+    let now = Utc::now();
+    let window = match (
+        logs.iter().map(|l| l.timestamp).min(),
+        logs.iter().map(|l| l.timestamp).max(),
+    ) {
+        (Some(first), Some(last)) => (first - EVIDENCE_MARGIN, last + EVIDENCE_MARGIN),
+        _ => (now - ALERT_LOOKBACK, now),
+    };
+    let origin = match conversation_id {
+        Some(id) => format!("chat conversation `{}`", id),
+        None => "a chat finding".to_string(),
+    };
+    TicketDraft {
+        title: title(finding.severity, &finding.summary),
+        body: body(
+            config,
+            &finding.summary,
+            finding.suggested_action.as_deref(),
+            &logs,
+            window,
+            &origin,
+        ),
+        severity: finding.severity,
+    }
+}
+
+/// An issue for a firing alert, quoting the errors and warnings leading up to it
+pub async fn alert_draft(
+    config: &Config,
+    buffer: Option<&LogBuffer>,
+    alert: &Alert,
+) -> TicketDraft {
+    let severity = match alert.severity {
+        alerts::Severity::Critical => Severity::Critical,
+        alerts::Severity::Warning => Severity::Medium,
+    };
+    let window = (alert.started_at - ALERT_LOOKBACK, Utc::now());
+    let logs = match buffer {
+        Some(buffer) => {
+            let mut logs = buffer.get_time_range(window.0, window.1).await;
+            logs.retain(|log| log.is_error() || log.is_warning());
+            logs
+        }
+        None => Vec::new(),
+    };
+    let mut text = format!("**{}**: {}", alert.rule, alert.summary);
+    if let Some(ref analysis) = alert.analysis {
+        text.push_str("\n\n");
+        text.push_str(analysis);
+    }
+    TicketDraft {
+        title: title(severity, &alert.summary),
+        body: body(
+            config,
+            &text,
+            None,
+            &logs,
+            window,
+            &format!("alert `{}`", alert.key()),
+        ),
+        severity,
+    }
+}
+
+/// Firings already filed, by tracker. Owned by the alerts rather than the
+/// notifiers, which are rebuilt on every config reload.
+#[derive(Default)]
+pub struct FiledTickets {
+    filed: Mutex<HashSet<(&'static str, String)>>,
+}
+
+impl FiledTickets {
+    /// Whether `tracker` has yet to file this firing; marks it filed
+    fn claim(&self, tracker: &'static str, alert: &str) -> bool {
+        self.filed
+            .lock()
+            .unwrap()
+            .insert((tracker, alert.to_string()))
+    }
+
+    fn release(&self, tracker: &'static str, alert: &str) {
+        self.filed
+            .lock()
+            .unwrap()
+            .remove(&(tracker, alert.to_string()));
+    }
+
+    /// Drop a firing once it resolves
+    pub fn forget(&self, alert: &str) {
+        self.filed.lock().unwrap().retain(|(_, id)| id != alert);
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.filed.lock().unwrap().len()
+    }
+}
+
+/// Files an issue the first time each alert fires (TICKET_ALERTS). Built
+/// from the config current at the last reload.
+pub struct TicketNotifier {
+    tracker: Arc<dyn Tracker>,
+    config: Config,
+    buffer: Option<Arc<LogBuffer>>,
+    /// A severity change or an ack is not a new issue
+    filed: Arc<FiledTickets>,
+}
+
+impl TicketNotifier {
+    pub fn new(
+        tracker: Arc<dyn Tracker>,
+        config: &Config,
+        buffer: Option<Arc<LogBuffer>>,
+        filed: Arc<FiledTickets>,
+    ) -> Self {
+        Self {
+            tracker,
+            config: config.clone(),
+            buffer,
+            filed,
+        }
+    }
+}
+
+#[async_trait]
+impl Notifier for TicketNotifier {
+    fn name(&self) -> &'static str {
+        self.tracker.name()
+    }
+
+    async fn notify(&self, alert: &Alert) -> Result<(), String> {
+        if alert.status == AlertStatus::Resolved {
+            self.filed.forget(&alert.id);
+            return Ok(());
+        }
+        if !self.filed.claim(self.name(), &alert.id) {
+            return Ok(());
+        }
+        let draft = alert_draft(&self.config, self.buffer.as_deref(), alert).await;
+        match self.tracker.file(&draft).await {
+            Ok(ticket) => {
+                info!(alert = %alert.key(), url = %ticket.url, "Filed an issue for the alert");
+                Ok(())
+            }
+            Err(e) => {
+                // Let the next notification for this firing try again
+                self.filed.release(self.name(), &alert.id);
+                Err(e)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct TicketRequest {
+    /// Findings from a structured `/chat` answer, as returned
+    pub findings: Vec<Finding>,
+    /// `github`, `linear` or `jira`; defaults to the first configured
+    #[serde(default)]
+    pub tracker: Option<String>,
+    /// The conversation the findings came from, noted in each issue
+    #[serde(default)]
+    pub conversation_id: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TicketsResponse {
+    pub tickets: Vec<FiledTicket>,
+}
+
+/// POST /tickets - file confirmed findings as issues
+#[utoipa::path(
+    post, path = "/tickets", tag = "chat",
+    request_body = TicketRequest,
+    responses(
+        (status = 200, description = "One issue per finding, in order", body = TicketsResponse),
+        (status = 400, description = "No findings, too many, or an unknown tracker", body = ErrorBody),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+        (status = 502, description = "The tracker refused an issue; the message lists any filed before it", body = ErrorBody),
+        (status = 503, description = "No tracker configured", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+pub async fn tickets_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<TicketRequest>,
+) -> Result<Json<TicketsResponse>, ApiError> {
+    check_auth(&state, &headers)?;
+    let config = state.config.current();
+    let trackers = trackers(&config);
+    if trackers.is_empty() {
+        return Err(ApiError::NotConfigured(
+            "No issue tracker configured; set GITHUB_ISSUES_TOKEN, LINEAR_API_KEY or JIRA_API_TOKEN".to_string(),
+        ));
+    }
+    if request.findings.is_empty() || request.findings.len() > MAX_FINDINGS {
+        return Err(ApiError::InvalidRequest(format!(
+            "Send between 1 and {} findings",
+            MAX_FINDINGS
+        )));
+    }
+    if request.findings.iter().any(|f| f.summary.trim().is_empty()) {
+        return Err(ApiError::InvalidRequest(
+            "Every finding needs a summary".to_string(),
+        ));
+    }
+    let tracker = match request.tracker.as_deref() {
+        None => trackers[0].clone(),
+        Some(name) => trackers
+            .iter()
+            .find(|t| t.name() == name)
+            .cloned()
+            .ok_or_else(|| {
+                let names: Vec<&str> = trackers.iter().map(|t| t.name()).collect();
+                ApiError::InvalidRequest(format!(
+                    "Tracker '{}' is not configured (use {})",
+                    name,
+                    names.join(" or ")
+                ))
+            })?,
+    };
+
+    let mut tickets = Vec::new();
+    for finding in &request.findings {
+        let draft = finding_draft(
+            &config,
+            &state.log_buffer,
+            finding,
+            request.conversation_id.as_deref(),
+        )
+        .await;
+        match tracker.file(&draft).await {
+            Ok(ticket) => tickets.push(ticket),
+            Err(e) => {
+                let filed: Vec<&str> = tickets
+                    .iter()
+                    .map(|t: &FiledTicket| t.url.as_str())
+                    .collect();
+                return Err(ApiError::Upstream(match filed.is_empty() {
+                    true => e,
+                    false => format!("{}; filed before the failure: {}", e, filed.join(", ")),
+                }));
+            }
+        }
+    }
+    info!(
+        tracker = tracker.name(),
+        tickets = tickets.len(),
+        "Filed issues for findings"
+    );
+    Ok(Json(TicketsResponse { tickets }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::log_buffer::LogBufferConfig;
+
+    #[tokio::test]
+    async fn test_findings_and_alerts_become_issues() {
+        // A GitHub API that records what it is sent
+        let received = Arc::new(Mutex::new(Vec::<Value>::new()));
+        let sink = received.clone();
+        let app = axum::Router::new().route(
+            "/repos/acme/web/issues",
+            axum::routing::post(move |Json(body): Json<Value>| {
+                let sink = sink.clone();
+                async move {
+                    let mut sink = sink.lock().unwrap();
+                    sink.push(body);
+                    Json(json!({
+                        "number": 400 + sink.len(),
+                        "html_url": format!("https://github.com/acme/web/issues/{}", 400 + sink.len()),
+                    }))
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let config = Config::for_tests(&format!(
+            "public_url = \"https://flywatch.example.com/\"\n\
+             github_issues_token = \"ghp_x\"\ngithub_issues_repo = \"acme/web\"\n\
+             github_api_url = \"http://{}\"",
+            addr
+        ));
+        let tracker = trackers(&config).remove(0);
+        let buffer = LogBuffer::new(
+            LogBufferConfig {
+                max_entries: 10,
+                max_age_minutes: 60,
+            },
+            None,
+        );
+        let line = buffer
+            .push(r#"{"message":"payments-api returned 502","log":{"level":"error"}}"#.to_string())
+            .await;
+
+        let finding = Finding {
+            severity: Severity::High,
+            summary: "Checkouts get 502s from payments-api".to_string(),
+            evidence_log_seqs: vec![line.seq],
+            suggested_action: Some("Check payments-api health".to_string()),
+        };
+        let draft = finding_draft(&config, &buffer, &finding, Some("c1")).await;
+        let ticket = tracker.file(&draft).await.unwrap();
+        assert_eq!(ticket.id, "401");
+        assert_eq!(ticket.url, "https://github.com/acme/web/issues/401");
+
+        let issue = received.lock().unwrap()[0].clone();
+        assert_eq!(
+            issue["title"],
+            "[high] Checkouts get 502s from payments-api"
+        );
+        assert_eq!(issue["labels"], json!(["flywatch"]));
+        let body = issue["body"].as_str().unwrap();
+        assert!(body.contains("**Suggested action:** Check payments-api health"));
+        assert!(body.contains(&format!("#{} ", line.seq)));
+        assert!(body.contains("](https://flywatch.example.com/logs/download?from="));
+        assert!(body.contains("chat conversation `c1`"));
+
+        // An alert is filed once per firing, however often it is notified
+        // and however often a reload rebuilds the notifier
+        let filed = Arc::new(FiledTickets::default());
+        let notifier = |filed: &Arc<FiledTickets>| {
+            TicketNotifier::new(
+                tracker.clone(),
+                &config,
+                Some(buffer.clone()),
+                filed.clone(),
+            )
+        };
+        let (notifier, rebuilt) = (notifier(&filed), notifier(&filed));
+        let mut alert = Alert::new(
+            "slo:checkout",
+            "fast-burn",
+            alerts::Severity::Critical,
+            "checkout burning its error budget at 14x".to_string(),
+        );
+        alert.analysis = Some("payments-api is returning 502s.".to_string());
+        notifier.notify(&alert).await.unwrap();
+        alert.acknowledged_at = Some(Utc::now());
+        rebuilt.notify(&alert).await.unwrap();
+        assert_eq!(filed.len(), 1);
+        alert.status = AlertStatus::Resolved;
+        notifier.notify(&alert).await.unwrap();
+        assert_eq!(filed.len(), 0);
+
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 2);
+        assert_eq!(
+            received[1]["title"],
+            "[critical] checkout burning its error budget at 14x"
+        );
+        let body = received[1]["body"].as_str().unwrap();
+        assert!(body.contains("payments-api is returning 502s."));
+        assert!(body.contains("payments-api returned 502"));
+    }
+
+    #[tokio::test]
+    async fn test_jira_maps_project_priority_and_fields() {
+        // A Jira site that records each request's auth header and body
+        let received = Arc::new(Mutex::new(Vec::<(String, Value)>::new()));
+        let sink = received.clone();
+        let app = axum::Router::new().route(
+            "/rest/api/3/issue",
+            axum::routing::post(move |headers: HeaderMap, Json(body): Json<Value>| {
+                let sink = sink.clone();
+                async move {
+                    let auth = headers["authorization"].to_str().unwrap().to_string();
+                    sink.lock().unwrap().push((auth, body));
+                    Json(json!({"id": "10042", "key": "OPS-42", "self": "ignored"}))
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let config = Config::for_tests(&format!(
+            "jira_base_url = \"http://{}/\"\n\
+             jira_email = \"ops@example.com\"\njira_api_token = \"secret\"\n\
+             jira_project_key = \"OPS\"\njira_issue_type = \"Incident\"\n\
+             jira_priorities = [\"critical=Highest\", \"high=High\"]\n\
+             jira_fields = ['labels=[\"flywatch\",\"checkout\"]', 'customfield_10020={{\"value\":\"Payments\"}}', 'environment=production']",
+            addr
+        ));
+        let tracker = trackers(&config).remove(0);
+        assert_eq!(tracker.name(), "jira");
+
+        let draft = TicketDraft {
+            title: "[high] Checkouts get 502s from payments-api".to_string(),
+            body: "Checkouts get 502s\n\n**Suggested action:** Check payments-api\n\n\
+                   ### Log excerpts\n\n```\n#7 payments-api returned 502\n```\n\n\
+                   [Logs](https://flywatch.example.com/logs/download)"
+                .to_string(),
+            severity: Severity::High,
+        };
+        let ticket = tracker.file(&draft).await.unwrap();
+        assert_eq!(ticket.id, "OPS-42");
+        assert_eq!(ticket.url, format!("http://{}/browse/OPS-42", addr));
+
+        let (auth, issue) = received.lock().unwrap()[0].clone();
+        // ops@example.com:secret
+        assert_eq!(auth, "Basic b3BzQGV4YW1wbGUuY29tOnNlY3JldA==");
+        let fields = &issue["fields"];
+        assert_eq!(fields["project"]["key"], "OPS");
+        assert_eq!(fields["issuetype"]["name"], "Incident");
+        assert_eq!(fields["summary"], draft.title);
+        assert_eq!(fields["priority"]["name"], "High");
+        assert_eq!(fields["labels"], json!(["flywatch", "checkout"]));
+        assert_eq!(fields["customfield_10020"]["value"], "Payments");
+        assert_eq!(fields["environment"], "production");
+
+        let doc = &fields["description"]["content"];
+        assert_eq!(doc[1]["content"][0]["text"], "Suggested action:");
+        assert_eq!(doc[1]["content"][0]["marks"][0]["type"], "strong");
+        assert_eq!(doc[2]["type"], "heading");
+        assert_eq!(doc[3]["type"], "codeBlock");
+        assert_eq!(doc[3]["content"][0]["text"], "#7 payments-api returned 502");
+        assert_eq!(
+            doc[4]["content"][0]["marks"][0]["attrs"]["href"],
+            "https://flywatch.example.com/logs/download"
+        );
+
+        // Severities without a mapping leave the project's default priority
+        let low = TicketDraft {
+            severity: Severity::Low,
+            ..draft
+        };
+        tracker.file(&low).await.unwrap();
+        assert!(received.lock().unwrap()[1].1["fields"]["priority"].is_null());
+
+        assert!("urgent=Highest".parse::<JiraPriority>().is_err());
+        assert!("summary=x".parse::<JiraField>().is_err());
+    }
+}
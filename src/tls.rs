@@ -0,0 +1,332 @@
+//! HTTPS listener with optional client-certificate (mTLS) authentication.
+//!
+//! With TLS_CLIENT_CA_FILE set, clients present a certificate signed by that
+//! CA. The certificate's CN becomes the request's principal: it stands in for
+//! the bearer token (restricted by TLS_CLIENT_CNS when set) and is recorded
+//! in audit logs. A CN naming a tenant only opens that tenant's `/t/<name>/`
+//! routes, unless TLS_CLIENT_CNS lists it.
+
+use axum::{
+    extract::{ConnectInfo, Request},
+    middleware::Next,
+    response::Response,
+    Router,
+};
+use hyper::body::Incoming;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto;
+use std::fs::File;
+use std::future::Future;
+use std::io::BufReader;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::watch;
+use tokio_rustls::rustls::pki_types::CertificateDer;
+use tokio_rustls::rustls::server::WebPkiClientVerifier;
+use tokio_rustls::rustls::{self, RootCertStore, ServerConfig};
+use tokio_rustls::TlsAcceptor;
+use tower::ServiceExt;
+use tracing::{debug, warn};
+
+use crate::config::Config;
+use crate::error::ApiError;
+use crate::http::shutting_down;
+
+/// Time a client gets to finish the TLS handshake
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Whether clients must present a certificate once TLS_CLIENT_CA_FILE is set
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientAuth {
+    /// Handshakes without a valid certificate fail
+    Required,
+    /// Clients without a certificate fall back to the bearer token
+    Optional,
+}
+
+impl FromStr for ClientAuth {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "required" => Ok(Self::Required),
+            "optional" => Ok(Self::Optional),
+            other => Err(format!(
+                "Invalid client auth: {}. Use required or optional.",
+                other
+            )),
+        }
+    }
+}
+
+/// CN of the verified client certificate, attached to each request
+#[derive(Debug, Clone)]
+pub struct ClientPrincipal(pub String);
+
+tokio::task_local! {
+    /// Principal of the client the current handler is serving
+    static PRINCIPAL: Option<String>;
+}
+
+/// The certificate principal of the current handler's client, if any
+pub fn current_principal() -> Option<String> {
+    PRINCIPAL.try_with(Clone::clone).ok().flatten()
+}
+
+/// Run `future` as `principal`, for work spawned off a request
+pub async fn with_principal<F: Future>(principal: Option<String>, future: F) -> F::Output {
+    PRINCIPAL.scope(principal, future).await
+}
+
+/// Expose the connection's principal to handlers via `current_principal`
+pub async fn principal_scope(request: Request, next: Next) -> Response {
+    let principal = request
+        .extensions()
+        .get::<ClientPrincipal>()
+        .map(|p| p.0.clone());
+    PRINCIPAL.scope(principal, next.run(request)).await
+}
+
+/// Whether a certificate principal may use the API. An empty allowlist
+/// admits any CN the CA signed, except tenants' own.
+pub fn principal_allowed(config: &Config, principal: &str) -> bool {
+    if config.tls_client_cns.iter().any(|cn| cn == principal) {
+        return true;
+    }
+    config.tls_client_cns.is_empty() && !config.tenants.iter().any(|t| t.name == principal)
+}
+
+pub fn check_principal(config: &Config, principal: &str) -> Result<(), ApiError> {
+    if principal_allowed(config, principal) {
+        Ok(())
+    } else {
+        Err(ApiError::Forbidden(format!(
+            "Client certificate '{}' is not in TLS_CLIENT_CNS",
+            principal
+        )))
+    }
+}
+
+fn load_certs(path: &str) -> Result<Vec<CertificateDer<'static>>, String> {
+    let file = File::open(path).map_err(|e| format!("{}: {}", path, e))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("{}: {}", path, e))?;
+    if certs.is_empty() {
+        return Err(format!("{}: no certificates found", path));
+    }
+    Ok(certs)
+}
+
+/// rustls settings for the listener, or None when TLS isn't configured
+pub fn server_config(config: &Config) -> Result<Option<Arc<ServerConfig>>, String> {
+    let (Some(cert_file), Some(key_file)) = (&config.tls_cert_file, &config.tls_key_file) else {
+        return Ok(None);
+    };
+    let certs = load_certs(cert_file)?;
+    let key_reader = File::open(key_file).map_err(|e| format!("{}: {}", key_file, e))?;
+    let key = rustls_pemfile::private_key(&mut BufReader::new(key_reader))
+        .map_err(|e| format!("{}: {}", key_file, e))?
+        .ok_or_else(|| format!("{}: no private key found", key_file))?;
+
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .map_err(|e| e.to_string())?;
+    let builder = match &config.tls_client_ca_file {
+        Some(ca_file) => {
+            let mut roots = RootCertStore::empty();
+            for cert in load_certs(ca_file)? {
+                roots.add(cert).map_err(|e| format!("{}: {}", ca_file, e))?;
+            }
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider);
+            let verifier = match config.tls_client_auth {
+                ClientAuth::Required => verifier,
+                ClientAuth::Optional => verifier.allow_unauthenticated(),
+            };
+            builder.with_client_cert_verifier(verifier.build().map_err(|e| e.to_string())?)
+        }
+        None => builder.with_no_client_auth(),
+    };
+    let mut server = builder
+        .with_single_cert(certs, key)
+        .map_err(|e| format!("{}: {}", cert_file, e))?;
+    server.alpn_protocols = vec![b"http/1.1".to_vec()];
+    Ok(Some(Arc::new(server)))
+}
+
+/// Serve `app` over TLS until shutdown. Requests carry `ConnectInfo` like
+/// `axum::serve`, plus a `ClientPrincipal` when the client sent a certificate.
+pub async fn serve(
+    listener: TcpListener,
+    server_config: Arc<ServerConfig>,
+    app: Router,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let acceptor = TlsAcceptor::from(server_config);
+    loop {
+        let (stream, peer) = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    warn!(error = %e, "Failed to accept connection");
+                    continue;
+                }
+            },
+            _ = shutting_down(&mut shutdown) => break,
+        };
+        let acceptor = acceptor.clone();
+        let app = app.clone();
+        tokio::spawn(async move {
+            let stream =
+                match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                    Ok(Ok(stream)) => stream,
+                    Ok(Err(e)) => {
+                        debug!(peer = %peer, error = %e, "TLS handshake failed");
+                        return;
+                    }
+                    Err(_) => {
+                        debug!(peer = %peer, "TLS handshake timed out");
+                        return;
+                    }
+                };
+            let principal = stream
+                .get_ref()
+                .1
+                .peer_certificates()
+                .and_then(|certs| certs.first())
+                .and_then(|cert| common_name(cert));
+
+            let service =
+                hyper::service::service_fn(move |mut request: hyper::Request<Incoming>| {
+                    request.extensions_mut().insert(ConnectInfo(peer));
+                    if let Some(principal) = &principal {
+                        request
+                            .extensions_mut()
+                            .insert(ClientPrincipal(principal.clone()));
+                    }
+                    app.clone().oneshot(request)
+                });
+            if let Err(e) = auto::Builder::new(TokioExecutor::new())
+                .serve_connection_with_upgrades(TokioIo::new(stream), service)
+                .await
+            {
+                debug!(peer = %peer, error = %e, "HTTPS connection error");
+            }
+        });
+    }
+}
+
+// ==================== Certificate Parsing ====================
+
+/// One DER element: (tag, contents, rest of input)
+fn der_element(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, input) = input.split_first()?;
+    let (&first, mut input) = input.split_first()?;
+    let len = if first < 0x80 {
+        first as usize
+    } else {
+        let octets = (first & 0x7f) as usize;
+        if octets == 0 || octets > 4 || input.len() < octets {
+            return None;
+        }
+        let (len, rest) = input.split_at(octets);
+        input = rest;
+        len.iter().fold(0usize, |acc, b| (acc << 8) | *b as usize)
+    };
+    if input.len() < len {
+        return None;
+    }
+    let (contents, rest) = input.split_at(len);
+    Some((tag, contents, rest))
+}
+
+/// Subject CN of a DER certificate
+pub fn common_name(cert: &[u8]) -> Option<String> {
+    const SEQUENCE: u8 = 0x30;
+    const SET: u8 = 0x31;
+    const OID: u8 = 0x06;
+    const VERSION: u8 = 0xa0;
+    const CN_OID: &[u8] = &[0x55, 0x04, 0x03];
+
+    let (_, certificate, _) = der_element(cert).filter(|(tag, ..)| *tag == SEQUENCE)?;
+    let (_, tbs, _) = der_element(certificate).filter(|(tag, ..)| *tag == SEQUENCE)?;
+    // version?, serialNumber, signature, issuer, validity, subject
+    let mut rest = tbs;
+    if rest.first() == Some(&VERSION) {
+        rest = der_element(rest)?.2;
+    }
+    for _ in 0..4 {
+        rest = der_element(rest)?.2;
+    }
+    let (_, mut subject, _) = der_element(rest).filter(|(tag, ..)| *tag == SEQUENCE)?;
+
+    while let Some((tag, rdn, next)) = der_element(subject) {
+        subject = next;
+        if tag != SET {
+            continue;
+        }
+        let Some((_, attribute, _)) = der_element(rdn) else {
+            continue;
+        };
+        let Some((OID, oid, value)) = der_element(attribute) else {
+            continue;
+        };
+        if oid == CN_OID {
+            let (_, value, _) = der_element(value)?;
+            return String::from_utf8(value.to_vec()).ok();
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Leaf signed by "Fleet CA", subject O=Fleet, CN=billing-worker
+    const LEAF_PEM: &str = "-----BEGIN CERTIFICATE-----
+MIIBgzCCASigAwIBAgIUF1XAlvRmiDXpqqIYlJmEfq7Kuu4wCgYIKoZIzj0EAwIw
+EzERMA8GA1UEAwwIRmxlZXQgQ0EwIBcNMjYxMDE1MDExMTA2WhgPMjEyNjA5MjEw
+MTExMDZaMCkxDjAMBgNVBAoMBUZsZWV0MRcwFQYDVQQDDA5iaWxsaW5nLXdvcmtl
+cjBZMBMGByqGSM49AgEGCCqGSM49AwEHA0IABPO+WJZdguISRD3U8w0y9vPe2KeV
+KjvK4lX9S4r1pO6Pnip9Rnaf3da36Bw7Y5HQiG+IH3LmnjUJIHecVpHzoCOjQjBA
+MB0GA1UdDgQWBBR1+k7v9uWDj2bqZb0wK98ruw5nvDAfBgNVHSMEGDAWgBRzFq84
+axHCD/WUu8T8t4C1/eFLYzAKBggqhkjOPQQDAgNJADBGAiEA/y5H79UfR5R5N3UK
+xGBahLsSStE1hpGk5BkqPxGbxosCIQC5j6dRQztIMp5NW/KoYsoxqJ/ZycswLE+Y
+KUES4CvxnQ==
+-----END CERTIFICATE-----
+";
+
+    #[test]
+    fn test_principal_is_the_subject_cn() {
+        let cert = rustls_pemfile::certs(&mut LEAF_PEM.as_bytes())
+            .next()
+            .unwrap()
+            .unwrap();
+        // Not the issuer's CN, which comes first
+        assert_eq!(common_name(&cert).as_deref(), Some("billing-worker"));
+        assert_eq!(common_name(&cert[..40]), None);
+
+        let mut config = Config::for_tests("tls_client_cns = [\"billing-worker\"]");
+        assert!(check_principal(&config, "billing-worker").is_ok());
+        assert!(check_principal(&config, "intruder").is_err());
+        config.tls_client_cns.clear();
+        assert!(principal_allowed(&config, "intruder"));
+    }
+
+    #[test]
+    fn test_tenant_cns_only_open_their_prefix() {
+        let config = Config::for_tests(
+            "auth_token = \"admin\"\ntenants = [\"acme app=acme-web token=s3cret\"]",
+        );
+        assert!(!principal_allowed(&config, "acme"));
+        assert!(principal_allowed(&config, "billing-worker"));
+
+        let tenant = config.for_tenant(&config.tenants[0]);
+        assert!(principal_allowed(&tenant, "acme"));
+        assert!(!principal_allowed(&tenant, "billing-worker"));
+    }
+}
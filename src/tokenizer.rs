@@ -0,0 +1,195 @@
+//! Offline token counting. Text is split the way tiktoken's pre-tokenizer
+//! splits it (words with their leading space, digit groups of three,
+//! punctuation runs, newlines), then each piece is costed with per-encoding
+//! rules instead of a vocabulary lookup. That tracks the real encoders far
+//! better than a flat characters-per-token ratio on logs and JSON, without
+//! shipping vocabulary files.
+
+/// Tokens added per chat message for role and separators
+const MESSAGE_OVERHEAD: u32 = 3;
+/// Tokens that prime the assistant's reply
+const REPLY_OVERHEAD: u32 = 3;
+
+/// Tokenizer family a model's counts are estimated with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// GPT-4 / GPT-3.5, and the default for open models with similar vocabularies
+    Cl100k,
+    /// GPT-4o and the o-series
+    O200k,
+    /// Anthropic Claude
+    Claude,
+}
+
+struct Params {
+    /// Longest ASCII word that is still a single token
+    word_chars: usize,
+    /// Characters per token once a word is split into sub-words
+    chars_per_token: f64,
+    /// Characters per token for accented and other non-ASCII letters
+    non_ascii_chars_per_token: f64,
+    /// Tokens per CJK character
+    cjk_tokens_per_char: f64,
+}
+
+impl Encoding {
+    pub fn for_model(model: &str) -> Self {
+        let model = model.to_lowercase();
+        let name = model.rsplit('/').next().unwrap_or(&model);
+        if model.starts_with("anthropic/") || name.starts_with("claude") {
+            Encoding::Claude
+        } else if ["gpt-4o", "gpt-4.1", "gpt-5", "o1", "o3", "o4"]
+            .iter()
+            .any(|prefix| name.starts_with(prefix))
+        {
+            Encoding::O200k
+        } else {
+            Encoding::Cl100k
+        }
+    }
+
+    fn params(self) -> Params {
+        match self {
+            Encoding::Cl100k => Params {
+                word_chars: 10,
+                chars_per_token: 5.0,
+                non_ascii_chars_per_token: 2.0,
+                cjk_tokens_per_char: 1.3,
+            },
+            Encoding::O200k => Params {
+                word_chars: 12,
+                chars_per_token: 5.5,
+                non_ascii_chars_per_token: 3.0,
+                cjk_tokens_per_char: 1.0,
+            },
+            Encoding::Claude => Params {
+                word_chars: 9,
+                chars_per_token: 4.5,
+                non_ascii_chars_per_token: 2.0,
+                cjk_tokens_per_char: 1.5,
+            },
+        }
+    }
+
+    pub fn count(self, text: &str) -> u32 {
+        let params = self.params();
+        let chars: Vec<char> = text.chars().collect();
+        let mut tokens = 0;
+        let mut i = 0;
+
+        while i < chars.len() {
+            let start = i;
+            let c = chars[i];
+            if c.is_alphabetic() {
+                while i < chars.len() && chars[i].is_alphabetic() {
+                    i += 1;
+                }
+                tokens += word_tokens(&chars[start..i], &params);
+            } else if c.is_numeric() {
+                while i < chars.len() && chars[i].is_numeric() {
+                    i += 1;
+                }
+                tokens += (i - start).div_ceil(3) as u32;
+            } else if c.is_whitespace() {
+                while i < chars.len() && chars[i].is_whitespace() {
+                    i += 1;
+                }
+                let run = &chars[start..i];
+                // A single trailing space rides along with the next word or
+                // punctuation run; numbers don't take a leading space
+                let absorbed =
+                    run.last() == Some(&' ') && chars.get(i).is_some_and(|next| !next.is_numeric());
+                let newline = run.iter().rposition(|c| *c == '\n' || *c == '\r');
+                let tail = match newline {
+                    Some(pos) => run.len() - pos - 1,
+                    None => run.len(),
+                };
+                tokens += newline.is_some() as u32 + (tail > absorbed as usize) as u32;
+            } else {
+                while i < chars.len() && is_punctuation(chars[i]) {
+                    i += 1;
+                }
+                tokens += (i - start).div_ceil(2) as u32;
+            }
+        }
+        tokens
+    }
+}
+
+fn is_punctuation(c: char) -> bool {
+    !c.is_alphanumeric() && !c.is_whitespace()
+}
+
+fn is_cjk(c: char) -> bool {
+    matches!(c as u32,
+        0x3040..=0x30FF    // Hiragana, Katakana
+        | 0x3400..=0x4DBF  // CJK Extension A
+        | 0x4E00..=0x9FFF  // CJK Unified Ideographs
+        | 0xAC00..=0xD7AF  // Hangul
+        | 0xF900..=0xFAFF) // CJK Compatibility
+}
+
+fn word_tokens(word: &[char], params: &Params) -> u32 {
+    if word.iter().all(char::is_ascii) {
+        return if word.len() <= params.word_chars {
+            1
+        } else {
+            (word.len() as f64 / params.chars_per_token).ceil() as u32
+        };
+    }
+    let cjk = word.iter().filter(|c| is_cjk(**c)).count();
+    let other = word.len() - cjk;
+    (cjk as f64 * params.cjk_tokens_per_char + other as f64 / params.non_ascii_chars_per_token)
+        .ceil() as u32
+}
+
+/// Estimated tokens in `text` for `model`
+pub fn count_tokens(model: &str, text: &str) -> u32 {
+    Encoding::for_model(model).count(text)
+}
+
+/// Estimated prompt tokens for a chat request made of `parts` (message
+/// contents and the serialized tool schema), including per-message framing
+pub fn count_prompt_tokens<'a>(model: &str, parts: impl IntoIterator<Item = &'a str>) -> u32 {
+    let encoding = Encoding::for_model(model);
+    parts
+        .into_iter()
+        .map(|part| encoding.count(part) + MESSAGE_OVERHEAD)
+        .sum::<u32>()
+        + REPLY_OVERHEAD
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches_reference_counts() {
+        // Same counts as tiktoken's cl100k_base
+        let cl100k = Encoding::Cl100k;
+        assert_eq!(cl100k.count("Hello world"), 2);
+        assert_eq!(cl100k.count("1234567"), 3);
+        assert_eq!(cl100k.count("error: connection refused\n"), 5);
+        assert_eq!(cl100k.count(""), 0);
+
+        // Long words are split into sub-words, sooner for Claude
+        assert_eq!(cl100k.count("internationalization"), 4);
+        assert_eq!(Encoding::O200k.count("credentials"), 1);
+        assert_eq!(Encoding::Claude.count("credentials"), 3);
+    }
+
+    #[test]
+    fn test_encoding_for_model() {
+        assert_eq!(Encoding::for_model("openai/gpt-4o-mini"), Encoding::O200k);
+        assert_eq!(Encoding::for_model("openai/gpt-4-turbo"), Encoding::Cl100k);
+        assert_eq!(
+            Encoding::for_model("anthropic/claude-3-haiku"),
+            Encoding::Claude
+        );
+        assert_eq!(Encoding::for_model("moonshotai/kimi-k2"), Encoding::Cl100k);
+        assert_eq!(
+            count_prompt_tokens("openai/gpt-4o", ["Hello world"]),
+            2 + 3 + 3
+        );
+    }
+}
@@ -0,0 +1,147 @@
+//! Request correlation: every buffered line that shares a trace or request
+//! id, across instances and regions, in event order. Served at
+//! `/logs/trace/:trace_id` and through the chat agent's `get_trace` tool.
+
+use axum::{
+    extract::{Path, State},
+    http::HeaderMap,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::BTreeSet;
+use utoipa::ToSchema;
+
+use crate::error::{ApiError, ErrorBody};
+use crate::http::{check_auth, AppState};
+use crate::log_buffer::{LogBuffer, TimestampedLog};
+use crate::prompt::format_logs_compact;
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TraceResponse {
+    pub trace_id: String,
+    /// Instances that logged for the request
+    pub instances: Vec<String>,
+    pub regions: Vec<String>,
+    /// Distinct span ids seen
+    pub spans: usize,
+    pub errors: usize,
+    pub started_at: Option<DateTime<Utc>>,
+    pub ended_at: Option<DateTime<Utc>>,
+    /// Time between the first and last line
+    pub duration_ms: Option<i64>,
+    /// Oldest first
+    pub logs: Vec<TimestampedLog>,
+}
+
+impl TraceResponse {
+    pub async fn collect(log_buffer: &LogBuffer, trace_id: &str) -> Self {
+        let logs = log_buffer.get_trace(trace_id).await;
+        let distinct = |field: fn(&TimestampedLog) -> Option<&String>| {
+            logs.iter()
+                .filter_map(field)
+                .cloned()
+                .collect::<BTreeSet<_>>()
+        };
+        let started_at = logs.first().map(|log| log.timestamp);
+        let ended_at = logs.last().map(|log| log.timestamp);
+        Self {
+            trace_id: trace_id.to_string(),
+            instances: distinct(|log| log.instance.as_ref()).into_iter().collect(),
+            regions: distinct(|log| log.region.as_ref()).into_iter().collect(),
+            spans: distinct(|log| log.span_id.as_ref()).len(),
+            errors: logs.iter().filter(|log| log.is_error()).count(),
+            started_at,
+            ended_at,
+            duration_ms: started_at
+                .zip(ended_at)
+                .map(|(start, end)| (end - start).num_milliseconds()),
+            logs,
+        }
+    }
+
+    /// Compact form for the chat agent
+    pub fn summarize(&self) -> String {
+        if self.logs.is_empty() {
+            return format!("No buffered lines for trace or request {}.", self.trace_id);
+        }
+        format!(
+            "Trace {}: {} lines, {} errors, {}ms across instances [{}] in [{}]\n{}",
+            self.trace_id,
+            self.logs.len(),
+            self.errors,
+            self.duration_ms.unwrap_or_default(),
+            self.instances.join(", "),
+            self.regions.join(", "),
+            format_logs_compact(&self.logs)
+        )
+    }
+}
+
+#[utoipa::path(
+    get, path = "/logs/trace/{trace_id}", tag = "logs",
+    params(("trace_id" = String, Path, description = "Trace or request id")),
+    responses(
+        (status = 200, description = "Every buffered line of the trace or request, oldest first", body = TraceResponse),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+        (status = 404, description = "No buffered line has this trace or request id", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+pub async fn trace_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(trace_id): Path<String>,
+) -> Result<Json<TraceResponse>, ApiError> {
+    check_auth(&state, &headers)?;
+
+    let trace = TraceResponse::collect(&state.log_buffer, &trace_id).await;
+    if trace.logs.is_empty() {
+        return Err(ApiError::NotFound(format!(
+            "No buffered lines for trace or request {}",
+            trace_id
+        )));
+    }
+    Ok(Json(trace))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::fly_envelope;
+
+    #[tokio::test]
+    async fn test_trace_spans_instances() {
+        let buffer = LogBuffer::new(Default::default(), None);
+        for (message, instance, level) in [
+            (
+                r#"{"msg":"checkout","trace_id":"t1","span_id":"a"}"#,
+                "web-1",
+                "info",
+            ),
+            (r#"{"msg":"unrelated","trace_id":"t2"}"#, "web-1", "info"),
+            (
+                r#"{"msg":"charge failed","trace_id":"t1","span_id":"b"}"#,
+                "pay-1",
+                "error",
+            ),
+        ] {
+            buffer
+                .push(fly_envelope(
+                    message,
+                    Some(level),
+                    Some(instance),
+                    Some("iad"),
+                ))
+                .await;
+        }
+
+        let trace = TraceResponse::collect(&buffer, "t1").await;
+        assert_eq!(trace.logs.len(), 2);
+        assert_eq!(trace.instances, vec!["pay-1", "web-1"]);
+        assert_eq!((trace.spans, trace.errors), (2, 1));
+        assert!(trace.summarize().starts_with("Trace t1: 2 lines, 1 errors"));
+
+        assert!(TraceResponse::collect(&buffer, "t3").await.logs.is_empty());
+    }
+}
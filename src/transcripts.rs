@@ -0,0 +1,311 @@
+//! Full chat transcripts, for working out why the model gave a bad answer.
+//! With CHAT_TRANSCRIPTS on, each `/chat` turn stores everything sent to the
+//! model (system prompt, log context, question, tool calls and their results)
+//! and its answer, as sent: after PII-safe masking. A record is capped at
+//! CHAT_TRANSCRIPT_MAX_CHARS by trimming its longest messages first, so the
+//! question and answer survive a huge log context, and records are deleted
+//! after CHAT_TRANSCRIPT_RETENTION_DAYS.
+
+use axum::{
+    extract::{Path, State},
+    http::HeaderMap,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use stoar::Store;
+use tracing::{error, info};
+use utoipa::ToSchema;
+
+use crate::config::Config;
+use crate::error::{ApiError, ErrorBody};
+use crate::http::{check_auth, AppState};
+use crate::text::truncate_chars;
+
+const TRANSCRIPT_COLLECTION: &str = "chat_transcripts";
+/// How often expired transcripts are looked for
+const PRUNE_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// A tool call the model asked for
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TranscriptToolCall {
+    pub id: String,
+    pub name: String,
+    /// Raw JSON arguments
+    pub arguments: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TranscriptMessage {
+    /// `system`, `user`, `assistant` or `tool`
+    pub role: String,
+    pub content: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<TranscriptToolCall>,
+    /// The call a `tool` message answers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+    /// Length of `content` before trimming, in characters
+    pub chars: usize,
+    pub truncated: bool,
+}
+
+impl TranscriptMessage {
+    pub fn new(role: &str, content: &str) -> Self {
+        Self {
+            role: role.to_string(),
+            content: content.to_string(),
+            tool_calls: Vec::new(),
+            tool_call_id: None,
+            chars: content.chars().count(),
+            truncated: false,
+        }
+    }
+}
+
+/// One chat turn, as the model saw it
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Transcript {
+    pub id: String,
+    pub timestamp: DateTime<Utc>,
+    pub conversation_id: String,
+    pub request_id: Option<String>,
+    /// The model that answered, or the one last tried
+    pub model: String,
+    /// Why the turn ended without an answer
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub messages: Vec<TranscriptMessage>,
+}
+
+/// A finished turn, as handed to `Transcripts::record`
+pub struct TranscriptEvent<'a> {
+    pub conversation_id: &'a str,
+    pub request_id: Option<&'a str>,
+    pub model: &'a str,
+    pub messages: Vec<TranscriptMessage>,
+    /// The answer, or why there is none
+    pub outcome: Result<&'a str, &'a str>,
+}
+
+/// The longest content a message may keep so the record fits in `budget`;
+/// messages shorter than that are kept whole
+fn message_cap(lengths: &[usize], budget: usize) -> usize {
+    let mut sorted = lengths.to_vec();
+    sorted.sort_unstable();
+    let mut remaining = budget;
+    for (i, &len) in sorted.iter().enumerate() {
+        let share = remaining / (sorted.len() - i);
+        if len > share {
+            return share;
+        }
+        remaining -= len;
+    }
+    usize::MAX
+}
+
+/// Chat transcripts in the store; without STORE_PATH nothing is kept
+pub struct Transcripts {
+    store: Option<Store>,
+    last_prune: Mutex<Option<Instant>>,
+}
+
+impl Transcripts {
+    pub fn new(store_path: Option<&str>) -> Self {
+        let store = store_path.and_then(|path| match Store::open(path) {
+            Ok(s) => Some(s),
+            Err(e) => {
+                error!(error = %e, path = %path, "Failed to open transcript store");
+                None
+            }
+        });
+        Self {
+            store,
+            last_prune: Mutex::new(None),
+        }
+    }
+
+    pub fn record(&self, config: &Config, event: TranscriptEvent<'_>) {
+        let Some(store) = self.store.as_ref().filter(|_| config.chat_transcripts) else {
+            return;
+        };
+        self.prune_if_due(store, config.chat_transcript_retention_days);
+
+        let mut messages = event.messages;
+        if let Ok(answer) = event.outcome {
+            messages.push(TranscriptMessage::new("assistant", answer));
+        }
+        let lengths: Vec<usize> = messages.iter().map(|m| m.chars).collect();
+        let cap = message_cap(&lengths, config.chat_transcript_max_chars);
+        for message in &mut messages {
+            if message.chars > cap {
+                message.content = truncate_chars(&message.content, cap);
+                message.truncated = true;
+            }
+        }
+
+        let transcript = Transcript {
+            id: uuid::Uuid::new_v4().to_string(),
+            timestamp: Utc::now(),
+            conversation_id: event.conversation_id.to_string(),
+            request_id: event.request_id.map(str::to_string),
+            model: event.model.to_string(),
+            error: event.outcome.err().map(str::to_string),
+            messages,
+        };
+        if let Err(e) = store.put(TRANSCRIPT_COLLECTION, &transcript.id, &transcript) {
+            error!(error = %e, "Failed to persist chat transcript");
+        }
+    }
+
+    /// Delete transcripts past retention, at most hourly
+    fn prune_if_due(&self, store: &Store, retention_days: u64) {
+        {
+            let mut last_prune = self.last_prune.lock().unwrap();
+            if last_prune.is_some_and(|at| at.elapsed() < PRUNE_INTERVAL) {
+                return;
+            }
+            *last_prune = Some(Instant::now());
+        }
+        let cutoff = Utc::now() - chrono::Duration::days(retention_days as i64);
+        let mut expired = Vec::new();
+        let pruned = store
+            .each(TRANSCRIPT_COLLECTION, |t: Transcript| {
+                if t.timestamp < cutoff {
+                    expired.push(t.id);
+                }
+            })
+            .and_then(|()| {
+                store.tx(|tx| {
+                    for id in &expired {
+                        tx.delete(TRANSCRIPT_COLLECTION, id)?;
+                    }
+                    Ok(expired.len())
+                })
+            });
+        match pruned {
+            Ok(0) => {}
+            Ok(pruned) => info!(pruned, "Deleted chat transcripts past retention"),
+            Err(e) => error!(error = %e, "Failed to prune chat transcripts"),
+        }
+    }
+
+    /// Every stored turn of a conversation, oldest first
+    pub fn conversation(&self, conversation_id: &str) -> Vec<Transcript> {
+        let Some(store) = &self.store else {
+            return Vec::new();
+        };
+        let mut transcripts = Vec::new();
+        let result = store.each(TRANSCRIPT_COLLECTION, |t: Transcript| {
+            if t.conversation_id == conversation_id {
+                transcripts.push(t);
+            }
+        });
+        if let Err(e) = result {
+            error!(error = %e, "Failed to read chat transcripts");
+        }
+        transcripts.sort_by_key(|t| t.timestamp);
+        transcripts
+    }
+}
+
+/// GET /chat/transcripts/:id - what the model saw in each turn of a conversation
+#[utoipa::path(
+    get, path = "/chat/transcripts/{id}", tag = "chat",
+    params(("id" = String, Path, description = "The conversation_id from /chat")),
+    responses(
+        (status = 200, description = "The conversation's turns, oldest first", body = Vec<Transcript>),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+        (status = 404, description = "No transcripts stored for the conversation", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+pub async fn transcript_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Json<Vec<Transcript>>, ApiError> {
+    check_auth(&state, &headers)?;
+    let transcripts = state.transcripts.conversation(&id);
+    if transcripts.is_empty() {
+        return Err(ApiError::NotFound(format!(
+            "No transcripts stored for conversation '{}'",
+            id
+        )));
+    }
+    Ok(Json(transcripts))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transcripts_trim_longest_messages_first() {
+        let dir =
+            std::env::temp_dir().join(format!("flywatch-transcripts-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("store.db");
+        let transcripts = Transcripts::new(path.to_str());
+        let config = Config::for_tests(&format!(
+            "store_path = {:?}\nchat_transcripts = true\nchat_transcript_max_chars = 1000",
+            path.to_str().unwrap()
+        ));
+
+        let context = "x".repeat(5_000);
+        transcripts.record(
+            &config,
+            TranscriptEvent {
+                conversation_id: "c1",
+                request_id: None,
+                model: "moonshotai/kimi-k2",
+                messages: vec![
+                    TranscriptMessage::new("system", &context),
+                    TranscriptMessage::new("user", "why are checkouts failing?"),
+                ],
+                outcome: Ok("The payments API is returning 502s."),
+            },
+        );
+        transcripts.record(
+            &config,
+            TranscriptEvent {
+                conversation_id: "c1",
+                request_id: None,
+                model: "moonshotai/kimi-k2",
+                messages: vec![TranscriptMessage::new("user", "and now?")],
+                outcome: Err("Maximum tool iterations exceeded"),
+            },
+        );
+
+        let stored = transcripts.conversation("c1");
+        assert_eq!(stored.len(), 2);
+        let first = &stored[0].messages;
+        assert_eq!(first.len(), 3);
+        assert!(first[0].truncated);
+        assert_eq!(first[0].chars, 5_000);
+        assert!(first[0].content.chars().count() < 1_000);
+        assert!(!first[1].truncated && !first[2].truncated);
+        assert_eq!(first[2].content, "The payments API is returning 502s.");
+        assert_eq!(
+            stored[1].error.as_deref(),
+            Some("Maximum tool iterations exceeded")
+        );
+        assert!(transcripts.conversation("c2").is_empty());
+
+        // Nothing is kept with transcripts off
+        transcripts.record(
+            &Config::for_tests(""),
+            TranscriptEvent {
+                conversation_id: "c2",
+                request_id: None,
+                model: "moonshotai/kimi-k2",
+                messages: Vec::new(),
+                outcome: Ok("ok"),
+            },
+        );
+        assert!(transcripts.conversation("c2").is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
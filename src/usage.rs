@@ -1,13 +1,21 @@
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use stoar::Store;
 use tokio::sync::RwLock;
 use tracing::{error, info};
+use utoipa::ToSchema;
 
 use crate::pricing::CostBreakdown;
 
 const USAGE_COLLECTION: &str = "ai_usage";
+/// Daily totals of records compacted past the retention window
+const ROLLUP_COLLECTION: &str = "ai_usage_daily";
+const COMPACT_INTERVAL: Duration = Duration::from_secs(3600);
+/// Ratings on chat answers, keyed by conversation
+const FEEDBACK_COLLECTION: &str = "ai_feedback";
 
 /// A single AI chat usage record
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -21,10 +29,128 @@ pub struct UsageRecord {
     pub cost_usd: f64,
     pub processing_time_ms: u64,
     pub tools_called: Vec<String>,
+    /// X-Request-Id of the chat call that incurred this usage
+    #[serde(default)]
+    pub request_id: Option<String>,
+    /// OpenRouter generation ids for each model call in the chat
+    #[serde(default)]
+    pub upstream_ids: Vec<String>,
+    /// The chat conversation this turn belongs to
+    #[serde(default)]
+    pub conversation_id: Option<String>,
+}
+
+/// Running totals over any number of usage records
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UsageTotals {
+    pub requests: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub total_tokens: u64,
+    pub cost_usd: f64,
+    pub processing_time_ms: u64,
+    pub requests_with_tools: u64,
+    pub first: Option<DateTime<Utc>>,
+    pub last: Option<DateTime<Utc>>,
+}
+
+impl UsageTotals {
+    pub fn add(&mut self, record: &UsageRecord) {
+        self.merge(&UsageTotals {
+            requests: 1,
+            prompt_tokens: record.prompt_tokens as u64,
+            completion_tokens: record.completion_tokens as u64,
+            total_tokens: record.total_tokens as u64,
+            cost_usd: record.cost_usd,
+            processing_time_ms: record.processing_time_ms,
+            requests_with_tools: u64::from(!record.tools_called.is_empty()),
+            first: Some(record.timestamp),
+            last: Some(record.timestamp),
+        });
+    }
+
+    pub fn merge(&mut self, other: &UsageTotals) {
+        self.requests += other.requests;
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+        self.total_tokens += other.total_tokens;
+        self.cost_usd += other.cost_usd;
+        self.processing_time_ms += other.processing_time_ms;
+        self.requests_with_tools += other.requests_with_tools;
+        self.first = self.first.into_iter().chain(other.first).min();
+        self.last = self.last.into_iter().chain(other.last).max();
+    }
+}
+
+/// One UTC day of records compacted out of the raw collection
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageRollup {
+    pub day: NaiveDate,
+    #[serde(flatten)]
+    pub totals: UsageTotals,
+}
+
+/// Thumbs up or down on a chat's answers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Rating {
+    Up,
+    Down,
+}
+
+/// Feedback on a conversation; rating it again replaces the earlier feedback
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Feedback {
+    pub conversation_id: String,
+    pub timestamp: DateTime<Utc>,
+    pub rating: Rating,
+    pub comment: Option<String>,
+    /// Models that answered in the conversation, so ratings can be compared
+    #[serde(default)]
+    pub models: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, ToSchema)]
+pub struct FeedbackStats {
+    pub up: u64,
+    pub down: u64,
+    /// Share of ratings that are thumbs up; unset until there are any
+    pub positive_rate: Option<f64>,
+}
+
+impl FeedbackStats {
+    fn count(&mut self, rating: Rating, delta: i64) {
+        let count = match rating {
+            Rating::Up => &mut self.up,
+            Rating::Down => &mut self.down,
+        };
+        *count = count.saturating_add_signed(delta);
+        let rated = self.up + self.down;
+        self.positive_rate = (rated > 0).then(|| self.up as f64 / rated as f64);
+    }
+}
+
+/// Why feedback couldn't be recorded
+#[derive(Debug, PartialEq)]
+pub enum FeedbackError {
+    /// Feedback is only kept in the store
+    NoStore,
+    UnknownConversation,
+}
+
+/// One completed chat, as handed to `UsageTracker::record`
+pub struct UsageEvent<'a> {
+    pub model: &'a str,
+    pub cost: &'a CostBreakdown,
+    pub processing_time_ms: u64,
+    pub tools_called: &'a [String],
+    pub request_id: Option<&'a str>,
+    pub upstream_ids: &'a [String],
+    pub conversation_id: Option<&'a str>,
 }
 
 /// Aggregated usage statistics
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, ToSchema)]
 pub struct UsageStats {
     pub total_requests: u64,
     pub total_tokens: u64,
@@ -35,32 +161,114 @@ pub struct UsageStats {
     pub requests_with_tools: u64,
     pub period_start: Option<DateTime<Utc>>,
     pub period_end: Option<DateTime<Utc>>,
+    /// Ratings from `POST /chat/{id}/feedback`
+    pub feedback: FeedbackStats,
+}
+
+/// Usage of one conversation across all its turns
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ConversationUsage {
+    pub conversation_id: String,
+    #[serde(flatten)]
+    pub usage: UsageStats,
+    /// Models that answered, in order of first use
+    pub models: Vec<String>,
 }
 
 impl Default for UsageStats {
     fn default() -> Self {
+        Self::from(&UsageTotals::default())
+    }
+}
+
+impl From<&UsageTotals> for UsageStats {
+    fn from(totals: &UsageTotals) -> Self {
         Self {
-            total_requests: 0,
-            total_tokens: 0,
-            total_prompt_tokens: 0,
-            total_completion_tokens: 0,
-            total_cost_usd: 0.0,
-            average_processing_time_ms: 0.0,
-            requests_with_tools: 0,
-            period_start: None,
-            period_end: None,
+            total_requests: totals.requests,
+            total_tokens: totals.total_tokens,
+            total_prompt_tokens: totals.prompt_tokens,
+            total_completion_tokens: totals.completion_tokens,
+            total_cost_usd: totals.cost_usd,
+            average_processing_time_ms: match totals.requests {
+                0 => 0.0,
+                requests => totals.processing_time_ms as f64 / requests as f64,
+            },
+            requests_with_tools: totals.requests_with_tools,
+            period_start: totals.first,
+            period_end: totals.last,
+            feedback: FeedbackStats::default(),
         }
     }
 }
 
-/// Usage tracker with persistent storage
+/// Fold raw records older than `cutoff` into their day's rollup and delete
+/// them; returns how many were compacted
+fn compact(store: &Store, cutoff: DateTime<Utc>) -> stoar::Result<usize> {
+    let mut days: BTreeMap<NaiveDate, UsageTotals> = BTreeMap::new();
+    let mut expired = Vec::new();
+    store.each(USAGE_COLLECTION, |record: UsageRecord| {
+        if record.timestamp < cutoff {
+            days.entry(record.timestamp.date_naive())
+                .or_default()
+                .add(&record);
+            expired.push(record.id);
+        }
+    })?;
+    if expired.is_empty() {
+        return Ok(0);
+    }
+    store.tx(|tx| {
+        for (day, totals) in &days {
+            let key = day.to_string();
+            let mut rollup =
+                tx.get::<UsageRollup>(ROLLUP_COLLECTION, &key)?
+                    .unwrap_or(UsageRollup {
+                        day: *day,
+                        totals: UsageTotals::default(),
+                    });
+            rollup.totals.merge(totals);
+            tx.put(ROLLUP_COLLECTION, &key, &rollup)?;
+        }
+        for id in &expired {
+            tx.delete(USAGE_COLLECTION, id)?;
+        }
+        Ok(expired.len())
+    })
+}
+
+/// Everything in the store: the daily rollups plus the raw records,
+/// streamed rather than loaded at once
+fn load_totals(store: &Store) -> stoar::Result<UsageTotals> {
+    let mut totals = UsageTotals::default();
+    store.each(ROLLUP_COLLECTION, |rollup: UsageRollup| {
+        totals.merge(&rollup.totals)
+    })?;
+    store.each(USAGE_COLLECTION, |record: UsageRecord| totals.add(&record))?;
+    Ok(totals)
+}
+
+/// Usage tracker with persistent storage. Totals are kept in memory,
+/// loaded from the store at startup and updated on every record, so
+/// `/usage` never rescans the store.
 pub struct UsageTracker {
     store: Arc<RwLock<Option<Store>>>,
+    totals: Mutex<UsageTotals>,
+    feedback: Mutex<FeedbackStats>,
+    /// Days raw records are kept before compaction; 0 keeps them forever
+    retention_days: u64,
+    last_compact: Mutex<Option<Instant>>,
+    /// Spend this month, for CHAT_MONTHLY_BUDGET_USD; None until first asked
+    month: Mutex<Option<MonthToDate>>,
+}
+
+struct MonthToDate {
+    start: DateTime<Utc>,
+    cost_usd: f64,
 }
 
 impl UsageTracker {
     /// Create a new usage tracker with optional persistence
-    pub fn new(store_path: Option<&str>) -> Self {
+    pub fn new(store_path: Option<&str>, retention_days: u64) -> Self {
         let store = store_path.and_then(|path| {
             match Store::open(path) {
                 Ok(s) => {
@@ -74,102 +282,511 @@ impl UsageTracker {
             }
         });
 
+        Self::with_store(store, retention_days)
+    }
+
+    fn with_store(store: Option<Store>, retention_days: u64) -> Self {
+        let totals = match store.as_ref().map(load_totals) {
+            Some(Ok(totals)) => {
+                info!(requests = totals.requests, "Loaded usage totals");
+                totals
+            }
+            Some(Err(e)) => {
+                error!(error = %e, "Failed to load usage totals");
+                UsageTotals::default()
+            }
+            None => UsageTotals::default(),
+        };
+        let mut feedback = FeedbackStats::default();
+        if let Some(store) = &store {
+            let loaded = store.each(FEEDBACK_COLLECTION, |f: Feedback| {
+                feedback.count(f.rating, 1)
+            });
+            if let Err(e) = loaded {
+                error!(error = %e, "Failed to load chat feedback");
+            }
+        }
         Self {
             store: Arc::new(RwLock::new(store)),
+            totals: Mutex::new(totals),
+            feedback: Mutex::new(feedback),
+            retention_days,
+            last_compact: Mutex::new(None),
+            month: Mutex::new(None),
+        }
+    }
+
+    /// Compact records past retention, at most hourly
+    async fn compact_if_due(&self) {
+        if self.retention_days == 0 {
+            return;
+        }
+        {
+            let mut last_compact = self.last_compact.lock().unwrap();
+            if last_compact.is_some_and(|at| at.elapsed() < COMPACT_INTERVAL) {
+                return;
+            }
+            *last_compact = Some(Instant::now());
+        }
+        let store_guard = self.store.read().await;
+        let Some(store) = store_guard.as_ref() else {
+            return;
+        };
+        let cutoff = Utc::now() - chrono::Duration::days(self.retention_days as i64);
+        match compact(store, cutoff) {
+            Ok(0) => {}
+            Ok(compacted) => info!(
+                compacted,
+                "Compacted usage records past retention into daily rollups"
+            ),
+            Err(e) => error!(error = %e, "Failed to compact usage records"),
         }
     }
 
     /// Record a new AI chat usage
-    pub async fn record(
-        &self,
-        model: &str,
-        cost: &CostBreakdown,
-        processing_time_ms: u64,
-        tools_called: &[String],
-    ) {
+    pub async fn record(&self, event: UsageEvent<'_>) {
         let record = UsageRecord {
             id: uuid::Uuid::new_v4().to_string(),
             timestamp: Utc::now(),
-            model: model.to_string(),
-            prompt_tokens: cost.input_tokens,
-            completion_tokens: cost.output_tokens,
-            total_tokens: cost.total_tokens,
-            cost_usd: cost.total_cost_usd,
-            processing_time_ms,
-            tools_called: tools_called.to_vec(),
+            model: event.model.to_string(),
+            prompt_tokens: event.cost.input_tokens,
+            completion_tokens: event.cost.output_tokens,
+            total_tokens: event.cost.total_tokens,
+            cost_usd: event.cost.total_cost_usd,
+            processing_time_ms: event.processing_time_ms,
+            tools_called: event.tools_called.to_vec(),
+            request_id: event.request_id.map(str::to_string),
+            upstream_ids: event.upstream_ids.to_vec(),
+            conversation_id: event.conversation_id.map(str::to_string),
         };
+        self.totals.lock().unwrap().add(&record);
 
         let store_guard = self.store.read().await;
-        if let Some(store) = store_guard.as_ref() {
-            if let Err(e) = store.put(USAGE_COLLECTION, &record.id, &record) {
-                error!(error = %e, "Failed to persist usage record");
+        {
+            // The same lock month_to_date scans under
+            let mut month = self.month.lock().unwrap();
+            if let Some(store) = store_guard.as_ref() {
+                if let Err(e) = store.put(USAGE_COLLECTION, &record.id, &record) {
+                    error!(error = %e, "Failed to persist usage record");
+                }
+            }
+            if let Some(month) = month.as_mut().filter(|m| record.timestamp >= m.start) {
+                month.cost_usd += record.cost_usd;
             }
         }
+        drop(store_guard);
+        self.compact_if_due().await;
     }
 
-    /// Get aggregated usage statistics
+    /// Get aggregated usage statistics, from the running totals
     pub async fn get_stats(&self) -> UsageStats {
-        let store_guard = self.store.read().await;
+        self.compact_if_due().await;
+        UsageStats {
+            feedback: *self.feedback.lock().unwrap(),
+            ..UsageStats::from(&*self.totals.lock().unwrap())
+        }
+    }
 
-        let Some(store) = store_guard.as_ref() else {
-            return UsageStats::default();
+    /// Rate a conversation that has usage on record, replacing any earlier
+    /// rating of it
+    pub async fn record_feedback(
+        &self,
+        conversation_id: &str,
+        rating: Rating,
+        comment: Option<String>,
+    ) -> Result<Feedback, FeedbackError> {
+        if self.store.read().await.is_none() {
+            return Err(FeedbackError::NoStore);
+        }
+        let conversation = self
+            .conversation(conversation_id)
+            .await
+            .ok_or(FeedbackError::UnknownConversation)?;
+        let feedback = Feedback {
+            conversation_id: conversation_id.to_string(),
+            timestamp: Utc::now(),
+            rating,
+            comment,
+            models: conversation.models,
         };
 
-        let records: Vec<UsageRecord> = match store.all(USAGE_COLLECTION) {
-            Ok(r) => r,
-            Err(e) => {
-                error!(error = %e, "Failed to fetch usage records");
-                return UsageStats::default();
+        let store_guard = self.store.read().await;
+        let store = store_guard.as_ref().ok_or(FeedbackError::NoStore)?;
+        let previous = store
+            .get::<Feedback>(FEEDBACK_COLLECTION, conversation_id)
+            .unwrap_or_else(|e| {
+                error!(error = %e, "Failed to read chat feedback");
+                None
+            });
+        if let Err(e) = store.put(FEEDBACK_COLLECTION, conversation_id, &feedback) {
+            error!(error = %e, "Failed to persist chat feedback");
+            return Ok(feedback);
+        }
+        let mut stats = self.feedback.lock().unwrap();
+        if let Some(previous) = previous {
+            stats.count(previous.rating, -1);
+        }
+        stats.count(rating, 1);
+        Ok(feedback)
+    }
+
+    /// Totals for one conversation's turns still within retention; `None`
+    /// when none are stored
+    pub async fn conversation(&self, conversation_id: &str) -> Option<ConversationUsage> {
+        let store_guard = self.store.read().await;
+        let store = store_guard.as_ref()?;
+
+        let mut totals = UsageTotals::default();
+        let mut models: Vec<String> = Vec::new();
+        let scanned = store.each(USAGE_COLLECTION, |record: UsageRecord| {
+            if record.conversation_id.as_deref() == Some(conversation_id) {
+                totals.add(&record);
+                if !models.contains(&record.model) {
+                    models.push(record.model);
+                }
             }
-        };
+        });
+        if let Err(e) = scanned {
+            error!(error = %e, "Failed to fetch usage records");
+            return None;
+        }
+
+        if totals.requests == 0 {
+            return None;
+        }
+        let mut feedback = FeedbackStats::default();
+        match store.get::<Feedback>(FEEDBACK_COLLECTION, conversation_id) {
+            Ok(rated) => rated.into_iter().for_each(|f| feedback.count(f.rating, 1)),
+            Err(e) => error!(error = %e, "Failed to read chat feedback"),
+        }
+        Some(ConversationUsage {
+            conversation_id: conversation_id.to_string(),
+            usage: UsageStats {
+                feedback,
+                ..UsageStats::from(&totals)
+            },
+            models,
+        })
+    }
 
-        if records.is_empty() {
-            return UsageStats::default();
+    /// Spend since the start of `now`'s month. Read from the store on the
+    /// first call each month, then kept current as usage is recorded.
+    pub async fn month_to_date(&self, now: DateTime<Utc>) -> Result<f64, String> {
+        let start = month_start(now);
+        let store_guard = self.store.read().await;
+        let store = store_guard
+            .as_ref()
+            .ok_or_else(|| "usage is not persisted".to_string())?;
+        // Held while scanning so a record can't land between the scan and
+        // the total
+        let mut month = self.month.lock().unwrap();
+        if let Some(month) = month.as_ref().filter(|month| month.start == start) {
+            return Ok(month.cost_usd);
         }
+        let cost_usd = cost_since(store, start).map_err(|e| e.to_string())?;
+        *month = Some(MonthToDate { start, cost_usd });
+        Ok(cost_usd)
+    }
+}
+
+/// Midnight UTC on the first of `now`'s month
+fn month_start(now: DateTime<Utc>) -> DateTime<Utc> {
+    now.date_naive()
+        .with_day(1)
+        .and_then(|day| day.and_hms_opt(0, 0, 0))
+        .map_or(now, |start| start.and_utc())
+}
 
-        let total_requests = records.len() as u64;
-        let total_tokens: u64 = records.iter().map(|r| r.total_tokens as u64).sum();
-        let total_prompt_tokens: u64 = records.iter().map(|r| r.prompt_tokens as u64).sum();
-        let total_completion_tokens: u64 = records.iter().map(|r| r.completion_tokens as u64).sum();
-        let total_cost_usd: f64 = records.iter().map(|r| r.cost_usd).sum();
-        let total_processing_time: u64 = records.iter().map(|r| r.processing_time_ms).sum();
-        let requests_with_tools = records.iter().filter(|r| !r.tools_called.is_empty()).count() as u64;
+/// Total cost of usage recorded at or after `since`; rolled-up days count
+/// whole when they start at or after it
+fn cost_since(store: &Store, since: DateTime<Utc>) -> stoar::Result<f64> {
+    let mut cost = 0.0;
+    let since_day = since.date_naive();
+    let first_whole_day = if since.time() == chrono::NaiveTime::MIN {
+        Some(since_day)
+    } else {
+        since_day.succ_opt()
+    };
+    store.each(ROLLUP_COLLECTION, |rollup: UsageRollup| {
+        if first_whole_day.is_some_and(|day| rollup.day >= day) {
+            cost += rollup.totals.cost_usd;
+        }
+    })?;
+    store.each(USAGE_COLLECTION, |record: UsageRecord| {
+        if record.timestamp >= since {
+            cost += record.cost_usd;
+        }
+    })?;
+    Ok(cost)
+}
 
-        let period_start = records.iter().map(|r| r.timestamp).min();
-        let period_end = records.iter().map(|r| r.timestamp).max();
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        UsageStats {
-            total_requests,
-            total_tokens,
-            total_prompt_tokens,
-            total_completion_tokens,
-            total_cost_usd,
-            average_processing_time_ms: total_processing_time as f64 / total_requests as f64,
-            requests_with_tools,
-            period_start,
-            period_end,
+    fn record(id: &str, timestamp: DateTime<Utc>, cost_usd: f64) -> UsageRecord {
+        UsageRecord {
+            id: id.to_string(),
+            timestamp,
+            model: "m".to_string(),
+            prompt_tokens: 100,
+            completion_tokens: 20,
+            total_tokens: 120,
+            cost_usd,
+            processing_time_ms: 400,
+            tools_called: vec!["search_logs".to_string()],
+            request_id: None,
+            upstream_ids: Vec::new(),
+            conversation_id: None,
         }
     }
 
-    /// Get recent usage records (last N)
-    pub async fn get_recent(&self, limit: usize) -> Vec<UsageRecord> {
-        let store_guard = self.store.read().await;
+    /// A model call costing `cost_usd`, 100 tokens in and 20 out
+    fn cost(cost_usd: f64) -> CostBreakdown {
+        CostBreakdown {
+            input_tokens: 100,
+            output_tokens: 20,
+            total_tokens: 120,
+            input_cost_usd: cost_usd / 2.0,
+            output_cost_usd: cost_usd / 2.0,
+            total_cost_usd: cost_usd,
+            model_input_price_per_million: 1.0,
+            model_output_price_per_million: 1.0,
+        }
+    }
 
-        let Some(store) = store_guard.as_ref() else {
-            return Vec::new();
+    async fn record_turn(
+        tracker: &UsageTracker,
+        model: &str,
+        conversation_id: Option<&str>,
+        cost_usd: f64,
+    ) {
+        tracker
+            .record(UsageEvent {
+                model,
+                cost: &cost(cost_usd),
+                processing_time_ms: 200,
+                tools_called: &[],
+                request_id: None,
+                upstream_ids: &[],
+                conversation_id,
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_conversation_totals_cover_only_its_turns() {
+        let tracker = UsageTracker::with_store(Some(Store::memory().unwrap()), 0);
+        record_turn(&tracker, "fast", Some("c1"), 0.25).await;
+        record_turn(&tracker, "deep", Some("c1"), 1.5).await;
+        record_turn(&tracker, "fast", Some("c1"), 0.25).await;
+        record_turn(&tracker, "deep", Some("c2"), 4.0).await;
+        record_turn(&tracker, "deep", None, 8.0).await;
+
+        let c1 = tracker.conversation("c1").await.unwrap();
+        assert_eq!(c1.conversation_id, "c1");
+        assert_eq!(c1.usage.total_requests, 3);
+        assert_eq!(c1.usage.total_tokens, 360);
+        assert_eq!(c1.usage.total_cost_usd, 2.0);
+        // In order of first use, each once
+        assert_eq!(c1.models, vec!["fast", "deep"]);
+        assert_eq!(tracker.conversation("c2").await.unwrap().usage.total_cost_usd, 4.0);
+
+        assert!(tracker.conversation("c3").await.is_none());
+        assert!(UsageTracker::new(None, 0).conversation("c1").await.is_none());
+    }
+
+    #[test]
+    fn test_feedback_rate_is_the_share_rated_up() {
+        let mut stats = FeedbackStats::default();
+        assert_eq!(stats.positive_rate, None);
+        for rating in [Rating::Up, Rating::Up, Rating::Up, Rating::Down] {
+            stats.count(rating, 1);
+        }
+        assert_eq!((stats.up, stats.down, stats.positive_rate), (3, 1, Some(0.75)));
+        stats.count(Rating::Up, -1);
+        stats.count(Rating::Up, -1);
+        assert_eq!(stats.positive_rate, Some(0.5));
+        stats.count(Rating::Up, -1);
+        assert_eq!(stats.positive_rate, Some(0.0));
+        stats.count(Rating::Down, -1);
+        assert_eq!(stats, FeedbackStats::default());
+    }
+
+    #[tokio::test]
+    async fn test_rating_again_replaces_the_earlier_rating() {
+        let tracker = UsageTracker::with_store(Some(Store::memory().unwrap()), 0);
+        for conversation in ["c1", "c2", "c3"] {
+            record_turn(&tracker, "m", Some(conversation), 0.5).await;
+        }
+        let rate = |conversation, rating| tracker.record_feedback(conversation, rating, None);
+        rate("c1", Rating::Down).await.unwrap();
+        rate("c2", Rating::Down).await.unwrap();
+        rate("c3", Rating::Up).await.unwrap();
+        assert_eq!(tracker.get_stats().await.feedback.positive_rate, Some(1.0 / 3.0));
+
+        // A second rating moves the conversation rather than adding to it
+        rate("c1", Rating::Up).await.unwrap();
+        rate("c1", Rating::Up).await.unwrap();
+        let feedback = tracker.get_stats().await.feedback;
+        assert_eq!((feedback.up, feedback.down), (2, 1));
+        assert_eq!(feedback.positive_rate, Some(2.0 / 3.0));
+        let c1 = tracker.conversation("c1").await.unwrap().usage.feedback;
+        assert_eq!((c1.up, c1.down, c1.positive_rate), (1, 0, Some(1.0)));
+    }
+
+    #[tokio::test]
+    async fn test_compaction_keeps_totals() {
+        let store = Store::memory().unwrap();
+        let now = Utc::now();
+        let old = now - chrono::Duration::days(120);
+        for (id, at, cost) in [("a", old, 1.0), ("b", old, 2.0), ("c", now, 4.0)] {
+            store
+                .put(USAGE_COLLECTION, id, &record(id, at, cost))
+                .unwrap();
+        }
+        assert_eq!(
+            compact(&store, now - chrono::Duration::days(90)).unwrap(),
+            2
+        );
+        assert_eq!(store.count(USAGE_COLLECTION).unwrap(), 1);
+        assert_eq!(store.count(ROLLUP_COLLECTION).unwrap(), 1);
+
+        let old_day = old.date_naive().and_hms_opt(0, 0, 0).unwrap().and_utc();
+        assert_eq!(cost_since(&store, old_day).unwrap(), 7.0);
+        assert_eq!(
+            cost_since(&store, old + chrono::Duration::hours(1)).unwrap(),
+            4.0
+        );
+
+        let tracker = UsageTracker::with_store(Some(store), 90);
+        let stats = tracker.get_stats().await;
+        assert_eq!(stats.total_requests, 3);
+        assert_eq!(stats.total_tokens, 360);
+        assert_eq!(stats.total_cost_usd, 7.0);
+        assert_eq!(stats.requests_with_tools, 3);
+        assert_eq!(stats.period_start, Some(old));
+        assert_eq!(stats.period_end, Some(now));
+    }
+
+    #[tokio::test]
+    async fn test_month_to_date_is_seeded_once_then_kept_current() {
+        let store = Store::memory().unwrap();
+        let now = Utc::now();
+        let last_month = month_start(now) - chrono::Duration::hours(1);
+        for (id, at, cost) in [("a", last_month, 5.0), ("b", month_start(now), 2.0)] {
+            store
+                .put(USAGE_COLLECTION, id, &record(id, at, cost))
+                .unwrap();
+        }
+        let tracker = UsageTracker::with_store(Some(store), 0);
+        assert_eq!(tracker.month_to_date(now).await, Ok(2.0));
+
+        let cost = CostBreakdown {
+            input_tokens: 10,
+            output_tokens: 10,
+            total_tokens: 20,
+            input_cost_usd: 0.25,
+            output_cost_usd: 0.25,
+            total_cost_usd: 0.5,
+            model_input_price_per_million: 1.0,
+            model_output_price_per_million: 1.0,
         };
+        tracker
+            .record(UsageEvent {
+                model: "m",
+                cost: &cost,
+                processing_time_ms: 1,
+                tools_called: &[],
+                request_id: None,
+                upstream_ids: &[],
+                conversation_id: None,
+            })
+            .await;
+        // Counted without another scan: a record slipped into the store
+        // behind the tracker's back isn't seen
+        let store_guard = tracker.store.read().await;
+        let store = store_guard.as_ref().unwrap();
+        store
+            .put(USAGE_COLLECTION, "c", &record("c", now, 9.0))
+            .unwrap();
+        drop(store_guard);
+        assert_eq!(tracker.month_to_date(now).await, Ok(2.5));
 
-        let mut records: Vec<UsageRecord> = match store.all(USAGE_COLLECTION) {
-            Ok(r) => r,
-            Err(e) => {
-                error!(error = %e, "Failed to fetch usage records");
-                return Vec::new();
-            }
+        // Next month starts from the store again
+        let next = month_start(now) + chrono::Duration::days(40);
+        assert_eq!(tracker.month_to_date(next).await, Ok(0.0));
+
+        assert!(UsageTracker::new(None, 0).month_to_date(now).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_totals_update_on_record_and_reload_from_store() {
+        let dir = std::env::temp_dir().join(format!("flywatch-usage-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("store.db");
+        let path = path.to_str().unwrap();
+        let cost = CostBreakdown {
+            input_tokens: 100,
+            output_tokens: 20,
+            total_tokens: 120,
+            input_cost_usd: 0.5,
+            output_cost_usd: 0.25,
+            total_cost_usd: 0.75,
+            model_input_price_per_million: 5.0,
+            model_output_price_per_million: 12.5,
+        };
+        let event = || UsageEvent {
+            model: "m",
+            cost: &cost,
+            processing_time_ms: 300,
+            tools_called: &[],
+            request_id: None,
+            upstream_ids: &[],
+            conversation_id: Some("c1"),
         };
 
-        // Sort by timestamp descending and take limit
-        records.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
-        records.truncate(limit);
-        records
+        let tracker = UsageTracker::new(Some(path), 90);
+        tracker.record(event()).await;
+        tracker.record(event()).await;
+        let stats = tracker.get_stats().await;
+        assert_eq!(stats.total_requests, 2);
+        assert_eq!(stats.total_cost_usd, 1.5);
+        assert_eq!(stats.average_processing_time_ms, 300.0);
+        let conversation = tracker.conversation("c1").await.unwrap();
+        assert_eq!(conversation.usage.total_requests, 2);
+        assert_eq!(conversation.models, vec!["m"]);
+        assert!(tracker.conversation("c2").await.is_none());
+
+        // Rating again replaces the earlier rating
+        tracker
+            .record_feedback("c1", Rating::Down, None)
+            .await
+            .unwrap();
+        let feedback = tracker
+            .record_feedback("c1", Rating::Up, Some("found the 502s".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(feedback.models, vec!["m"]);
+        assert_eq!(
+            tracker.record_feedback("c2", Rating::Up, None).await.unwrap_err(),
+            FeedbackError::UnknownConversation
+        );
+        let rated = tracker.conversation("c1").await.unwrap().usage.feedback;
+        assert_eq!((rated.up, rated.down), (1, 0));
+        drop(tracker);
+
+        let reopened = UsageTracker::new(Some(path), 90);
+        let stats = reopened.get_stats().await;
+        assert_eq!(stats.total_tokens, 240);
+        assert_eq!(stats.feedback.positive_rate, Some(1.0));
+        assert_eq!(
+            UsageTracker::new(None, 90)
+                .record_feedback("c1", Rating::Up, None)
+                .await
+                .unwrap_err(),
+            FeedbackError::NoStore
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 }
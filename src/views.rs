@@ -0,0 +1,350 @@
+//! Saved searches: named filter sets (levels, instances, a regex over the
+//! line and a lookback window) created with `POST /views` and applied by
+//! name with `?view=` on the log streams, `/logs/history` and
+//! `/logs/download`, and through the chat agent's `get_logs` tool. Views are
+//! persisted when a store is configured.
+
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    Json,
+};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+use stoar::Store;
+use tracing::error;
+use utoipa::ToSchema;
+
+use crate::error::{ApiError, ErrorBody};
+use crate::filter::Filter;
+use crate::http::{check_auth, AppState};
+use crate::ingest_filter::normalize_level;
+use crate::log_buffer::TimestampedLog;
+
+const VIEW_COLLECTION: &str = "views";
+/// Longest lookback a view can carry
+const MAX_VIEW_MINUTES: i64 = 7 * 24 * 60;
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SavedView {
+    pub name: String,
+    /// Levels shown; all when empty
+    pub levels: Vec<String>,
+    /// Instances shown; all when empty
+    pub instances: Vec<String>,
+    /// Regex the raw line must match
+    pub pattern: Option<String>,
+    /// Lookback for history, downloads and the chat agent
+    pub minutes: Option<i64>,
+    pub description: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ViewRequest {
+    /// Lowercase letters, digits, `-` and `_`
+    pub name: String,
+    #[serde(default)]
+    pub levels: Vec<String>,
+    #[serde(default)]
+    pub instances: Vec<String>,
+    pub pattern: Option<String>,
+    /// Up to 7 days
+    pub minutes: Option<i64>,
+    pub description: Option<String>,
+}
+
+fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 64
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+}
+
+/// A saved view compiled to a filter
+#[derive(Debug)]
+pub struct LogView {
+    pub view: SavedView,
+    filter: Filter,
+}
+
+impl LogView {
+    fn compile(view: SavedView) -> Result<Self, String> {
+        let filter = Filter {
+            levels: view.levels.iter().map(|l| normalize_level(l)).collect(),
+            instances: view.instances.clone(),
+            ..Default::default()
+        }
+        .with_pattern(view.pattern.as_deref())
+        .map_err(|e| format!("pattern: {}", e))?;
+        Ok(Self { view, filter })
+    }
+
+    pub fn matches(&self, log: &TimestampedLog) -> bool {
+        self.filter.matches(log)
+    }
+
+    /// Oldest event time the view shows, when it has a lookback
+    pub fn since(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.view
+            .minutes
+            .map(|minutes| now - Duration::minutes(minutes))
+    }
+}
+
+/// Saved views by name
+pub struct Views {
+    views: RwLock<BTreeMap<String, Arc<LogView>>>,
+    store: Option<Store>,
+}
+
+impl Views {
+    pub fn new(store_path: Option<&str>) -> Arc<Self> {
+        let store = store_path.and_then(|path| match Store::open(path) {
+            Ok(s) => Some(s),
+            Err(e) => {
+                error!(error = %e, path = %path, "Failed to open view store, keeping views in memory");
+                None
+            }
+        });
+        let saved: Vec<SavedView> = store
+            .as_ref()
+            .map(|store| {
+                store.all(VIEW_COLLECTION).unwrap_or_else(|e| {
+                    error!(error = %e, "Failed to load saved views");
+                    Vec::new()
+                })
+            })
+            .unwrap_or_default();
+        let views = saved
+            .into_iter()
+            .filter_map(|view| match LogView::compile(view) {
+                Ok(view) => Some((view.view.name.clone(), Arc::new(view))),
+                Err(e) => {
+                    error!(error = %e, "Skipping invalid saved view");
+                    None
+                }
+            })
+            .collect();
+        Arc::new(Self {
+            views: RwLock::new(views),
+            store,
+        })
+    }
+
+    pub fn list(&self) -> Vec<SavedView> {
+        self.views
+            .read()
+            .unwrap()
+            .values()
+            .map(|v| v.view.clone())
+            .collect()
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.views.read().unwrap().keys().cloned().collect()
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<LogView>> {
+        self.views.read().unwrap().get(name).cloned()
+    }
+
+    /// The view named by a `view` parameter; unknown names are invalid
+    pub fn resolve(&self, name: Option<&str>) -> Result<Option<Arc<LogView>>, ApiError> {
+        match name {
+            Some(name) => self
+                .get(name)
+                .map(Some)
+                .ok_or_else(|| ApiError::InvalidRequest(format!("Unknown view '{}'", name))),
+            None => Ok(None),
+        }
+    }
+
+    /// Create a view, or replace the one with the same name
+    pub fn save(&self, request: ViewRequest) -> Result<SavedView, String> {
+        if !valid_name(&request.name) {
+            return Err("name must be 1-64 lowercase letters, digits, '-' or '_'".to_string());
+        }
+        if let Some(minutes) = request.minutes {
+            if !(1..=MAX_VIEW_MINUTES).contains(&minutes) {
+                return Err(format!(
+                    "minutes must be between 1 and {}",
+                    MAX_VIEW_MINUTES
+                ));
+            }
+        }
+        let now = Utc::now();
+        let created_at = self.get(&request.name).map_or(now, |v| v.view.created_at);
+        let view = LogView::compile(SavedView {
+            name: request.name,
+            levels: request.levels.iter().map(|l| normalize_level(l)).collect(),
+            instances: request.instances,
+            pattern: request.pattern.filter(|p| !p.is_empty()),
+            minutes: request.minutes,
+            description: request.description,
+            created_at,
+            updated_at: now,
+        })?;
+        if let Some(ref store) = self.store {
+            if let Err(e) = store.put(VIEW_COLLECTION, &view.view.name, &view.view) {
+                error!(error = %e, "Failed to persist saved view");
+            }
+        }
+        let saved = view.view.clone();
+        self.views
+            .write()
+            .unwrap()
+            .insert(saved.name.clone(), Arc::new(view));
+        Ok(saved)
+    }
+
+    pub fn delete(&self, name: &str) -> bool {
+        let removed = self.views.write().unwrap().remove(name).is_some();
+        if let Some(ref store) = self.store {
+            let _ = store.delete(VIEW_COLLECTION, name);
+        }
+        removed
+    }
+}
+
+/// GET /views - saved views by name
+#[utoipa::path(
+    get, path = "/views", tag = "logs",
+    responses(
+        (status = 200, description = "Saved views", body = Vec<SavedView>),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+pub async fn list_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<SavedView>>, ApiError> {
+    check_auth(&state, &headers)?;
+    Ok(Json(state.views.list()))
+}
+
+/// POST /views - save a view, replacing one with the same name
+#[utoipa::path(
+    post, path = "/views", tag = "logs",
+    request_body = ViewRequest,
+    responses(
+        (status = 201, description = "View saved", body = SavedView),
+        (status = 400, description = "Invalid view", body = ErrorBody),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+pub async fn save_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<ViewRequest>,
+) -> Result<(StatusCode, Json<SavedView>), ApiError> {
+    check_auth(&state, &headers)?;
+    let view = state
+        .views
+        .save(request)
+        .map_err(ApiError::InvalidRequest)?;
+    Ok((StatusCode::CREATED, Json(view)))
+}
+
+/// GET /views/{name} - one saved view
+#[utoipa::path(
+    get, path = "/views/{name}", tag = "logs",
+    params(("name" = String, Path, description = "View name")),
+    responses(
+        (status = 200, description = "The view", body = SavedView),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+        (status = 404, description = "No such view", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+pub async fn get_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(name): Path<String>,
+) -> Result<Json<SavedView>, ApiError> {
+    check_auth(&state, &headers)?;
+    state
+        .views
+        .get(&name)
+        .map(|v| Json(v.view.clone()))
+        .ok_or_else(|| ApiError::NotFound("View not found".to_string()))
+}
+
+/// DELETE /views/{name} - remove a saved view
+#[utoipa::path(
+    delete, path = "/views/{name}", tag = "logs",
+    params(("name" = String, Path, description = "View name")),
+    responses(
+        (status = 204, description = "View removed"),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+        (status = 404, description = "No such view", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+pub async fn delete_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(name): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    check_auth(&state, &headers)?;
+    if state.views.delete(&name) {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(ApiError::NotFound("View not found".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::fly_envelope;
+
+    fn request(name: &str, pattern: Option<&str>) -> ViewRequest {
+        ViewRequest {
+            name: name.to_string(),
+            levels: vec!["ERR".to_string()],
+            instances: Vec::new(),
+            pattern: pattern.map(str::to_string),
+            minutes: Some(60),
+            description: None,
+        }
+    }
+
+    #[test]
+    fn test_saved_view_filters_and_persists() {
+        let dir = std::env::temp_dir().join(format!("flywatch-views-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("store.db");
+        let path = path.to_str();
+
+        let views = Views::new(path);
+        let saved = views
+            .save(request("payment-errors", Some("payment|charge")))
+            .unwrap();
+        assert_eq!(saved.levels, vec!["error"]);
+        assert!(views.save(request("Payment Errors", None)).is_err());
+        assert!(views.save(request("bad-regex", Some("("))).is_err());
+
+        let line = |message: &str, level: &str| {
+            TimestampedLog::new(fly_envelope(message, Some(level), Some("web-1"), None), 1)
+        };
+        let view = Views::new(path).get("payment-errors").unwrap();
+        assert!(view.matches(&line("charge declined", "error")));
+        assert!(!view.matches(&line("charge declined", "info")));
+        assert!(!view.matches(&line("cart updated", "error")));
+        assert!(matches!(
+            views.resolve(Some("missing")),
+            Err(ApiError::InvalidRequest(_))
+        ));
+
+        assert!(views.delete("payment-errors"));
+        assert!(Views::new(path).list().is_empty());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
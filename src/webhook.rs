@@ -0,0 +1,129 @@
+use async_trait::async_trait;
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::{header, HeaderMap, StatusCode},
+    middleware,
+    routing::post,
+    Router,
+};
+use subtle::ConstantTimeEq;
+use tokio::sync::mpsc;
+use tracing::info;
+
+use crate::error::{self, ApiError};
+use crate::source::{LogSource, SourceContext, SourceError};
+
+const WEBHOOK_QUEUE_CAPACITY: usize = 10_000;
+
+/// HTTP push source: accepts log lines via `POST /ingest` on its own listener
+pub struct WebhookSource {
+    bind_addr: String,
+    auth_token: Option<String>,
+}
+
+impl WebhookSource {
+    pub fn new(bind_addr: String, auth_token: Option<String>) -> Self {
+        Self {
+            bind_addr,
+            auth_token,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct WebhookState {
+    tx: mpsc::Sender<String>,
+    auth_token: Option<String>,
+}
+
+#[async_trait]
+impl LogSource for WebhookSource {
+    fn name(&self) -> &str {
+        "webhook"
+    }
+
+    fn kind(&self) -> &'static str {
+        "webhook"
+    }
+
+    async fn run(&self, ctx: &SourceContext) -> Result<(), SourceError> {
+        let (tx, mut rx) = mpsc::channel::<String>(WEBHOOK_QUEUE_CAPACITY);
+        let state = WebhookState {
+            tx,
+            auth_token: self.auth_token.clone(),
+        };
+
+        let app = Router::new()
+            .route("/ingest", post(ingest_handler))
+            .fallback(error::not_found)
+            .layer(middleware::from_fn(error::json_errors))
+            .layer(middleware::from_fn(error::request_id))
+            .with_state(state);
+
+        let listener = tokio::net::TcpListener::bind(&self.bind_addr)
+            .await
+            .map_err(|e| SourceError::Io(format!("bind {}: {}", self.bind_addr, e)))?;
+        info!(addr = %self.bind_addr, "Webhook source listening");
+        ctx.running().await;
+
+        let server = tokio::spawn(async move { axum::serve(listener, app).await });
+
+        while let Some(raw) = rx.recv().await {
+            ctx.emit(raw).await;
+        }
+
+        server.abort();
+        Err(SourceError::Io("webhook server stopped".to_string()))
+    }
+}
+
+async fn ingest_handler(
+    State(state): State<WebhookState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<StatusCode, ApiError> {
+    if let Some(expected) = &state.auth_token {
+        let token = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|h| h.strip_prefix("Bearer "));
+        // Compared in constant time so response timing doesn't leak the token
+        let valid = token.is_some_and(|t| bool::from(t.as_bytes().ct_eq(expected.as_bytes())));
+        if !valid {
+            return Err(ApiError::Unauthorized(
+                "Missing or invalid bearer token".to_string(),
+            ));
+        }
+    }
+
+    let body = String::from_utf8_lossy(&body);
+    for line in split_payload(&body) {
+        if state.tx.send(line).await.is_err() {
+            return Err(ApiError::NotReady(
+                "Webhook source is restarting".to_string(),
+            ));
+        }
+    }
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+/// Split a webhook body into log lines: a JSON array yields one line per
+/// element, anything else is treated as newline-delimited text.
+fn split_payload(body: &str) -> Vec<String> {
+    if let Ok(serde_json::Value::Array(items)) = serde_json::from_str(body) {
+        return items
+            .into_iter()
+            .map(|v| match v {
+                serde_json::Value::String(s) => s,
+                other => other.to_string(),
+            })
+            .collect();
+    }
+
+    body.lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(str::to_string)
+        .collect()
+}
@@ -0,0 +1,361 @@
+//! Authentication for browser WebSocket clients, which can't set an
+//! Authorization header on the upgrade. Besides the header, a socket may
+//! authenticate with:
+//!
+//! - a subprotocol: `flywatch.bearer.<AUTH_TOKEN>` or `flywatch.ticket.<ticket>`,
+//!   offered next to plain `flywatch`, which the server selects
+//! - `?ticket=<ticket>` on the upgrade URL
+//! - the dashboard's session cookie, from the same origin only
+//!
+//! Tickets come from `POST /auth/ws-ticket`, expire after 30 seconds and are
+//! signed with AUTH_TOKEN, so every instance sharing the token accepts them
+//! and rotating the token invalidates them. Each instance accepts a ticket
+//! once; a replica that hasn't seen it would still take it until it expires.
+//!
+//! The expiry only limits the upgrade: a socket opened with a ticket stays
+//! open past it, and is closed only once the ticket's signature stops matching
+//! a rotated AUTH_TOKEN.
+//!
+//! EventSource can't send headers either. With SSE_QUERY_AUTH set,
+//! `/logs/stream` also takes `?access_token=<AUTH_TOKEN>` or `?ticket=`; both
+//! are masked in request logs.
+
+use axum::{
+    extract::{ws::WebSocketUpgrade, State},
+    http::{header, HeaderMap},
+    Json,
+};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, Duration, Utc};
+use ring::hmac;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::error::{ApiError, ErrorBody};
+use crate::http::{check_auth, token_matches, AppState};
+use crate::session;
+use crate::tls;
+
+/// Subprotocol the server selects for clients that authenticate with one
+pub const SUBPROTOCOL: &str = "flywatch";
+const BEARER_PREFIX: &str = "flywatch.bearer.";
+const TICKET_PREFIX: &str = "flywatch.ticket.";
+
+const TICKET_TTL_SECS: i64 = 30;
+
+/// `?ticket=` on a WebSocket upgrade
+#[derive(Debug, Default, Deserialize, IntoParams)]
+pub struct TicketQuery {
+    /// Ticket from POST /auth/ws-ticket
+    pub ticket: Option<String>,
+}
+
+fn ticket_key(token: &str) -> hmac::Key {
+    hmac::Key::new(hmac::HMAC_SHA256, token.as_bytes())
+}
+
+/// `<expiry>.<nonce>.<signature>`, signed with the auth token
+pub fn issue_ticket(token: &str, now: DateTime<Utc>) -> (String, DateTime<Utc>) {
+    let expires_at = now + Duration::seconds(TICKET_TTL_SECS);
+    let payload = format!(
+        "{}.{}",
+        expires_at.timestamp(),
+        uuid::Uuid::new_v4().simple()
+    );
+    let signature = hmac::sign(&ticket_key(token), payload.as_bytes());
+    let ticket = format!("{}.{}", payload, URL_SAFE_NO_PAD.encode(signature));
+    (ticket, expires_at)
+}
+
+/// Whether `ticket` was signed with `token`, and (given `now`) hasn't expired
+fn verify_ticket(token: &str, ticket: &str, now: Option<DateTime<Utc>>) -> bool {
+    let Some((payload, signature)) = ticket.rsplit_once('.') else {
+        return false;
+    };
+    let Ok(signature) = URL_SAFE_NO_PAD.decode(signature) else {
+        return false;
+    };
+    if hmac::verify(&ticket_key(token), payload.as_bytes(), &signature).is_err() {
+        return false;
+    }
+    let expires_at = payload
+        .split_once('.')
+        .and_then(|(expiry, _)| expiry.parse::<i64>().ok());
+    match (now, expires_at) {
+        (_, None) => false,
+        (None, Some(_)) => true,
+        (Some(now), Some(expiry)) => now.timestamp() <= expiry,
+    }
+}
+
+/// Tickets already used on this instance, each kept until it has expired
+#[derive(Default)]
+pub struct UsedTickets {
+    used: Mutex<HashMap<String, DateTime<Utc>>>,
+}
+
+impl UsedTickets {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    /// Accept `ticket` if it's valid and hasn't been used here before
+    fn redeem(&self, token: &str, ticket: &str, now: DateTime<Utc>) -> bool {
+        let mut used = self.used.lock().unwrap();
+        used.retain(|_, forget_at| *forget_at > now);
+        if !verify_ticket(token, ticket, Some(now)) {
+            return false;
+        }
+        // Issued no earlier than now, so expired by then
+        let forget_at = now + Duration::seconds(TICKET_TTL_SECS + 1);
+        used.insert(ticket.to_string(), forget_at).is_none()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Credential {
+    /// No AUTH_TOKEN was set at upgrade time
+    Open,
+    /// Authorization header or `flywatch.bearer.` subprotocol
+    Bearer(String),
+    Ticket(String),
+    /// Client-certificate CN, checked against TLS_CLIENT_CNS
+    Certificate(String),
+    /// Dashboard session cookie
+    Session(String),
+}
+
+/// How an upgraded socket authenticated, so it can be re-checked later
+#[derive(Debug, Clone)]
+pub struct WsAuth {
+    credential: Credential,
+    /// Whether the client offered an auth subprotocol, which must be answered
+    pub subprotocol: bool,
+}
+
+impl WsAuth {
+    /// Whether the credential is still accepted by the current config. A
+    /// ticket's expiry only limits the upgrade; its signature must still
+    /// match the (possibly rotated) token.
+    pub fn still_valid(&self, state: &AppState) -> bool {
+        let config = state.config.current();
+        match (&self.credential, &config.auth_token) {
+            (Credential::Certificate(principal), _) => tls::principal_allowed(&config, principal),
+            (_, None) => true,
+            (Credential::Open, Some(_)) => false,
+            (Credential::Bearer(token), Some(expected)) => token_matches(token, expected),
+            (Credential::Ticket(ticket), Some(expected)) => verify_ticket(expected, ticket, None),
+            (Credential::Session(cookie), Some(_)) => session::verify(&config, cookie).is_some(),
+        }
+    }
+
+    /// Select the `flywatch` subprotocol when the client authenticated with one
+    pub fn upgrade(&self, ws: WebSocketUpgrade) -> WebSocketUpgrade {
+        if self.subprotocol {
+            ws.protocols([SUBPROTOCOL])
+        } else {
+            ws
+        }
+    }
+
+    /// Certificate principal the socket authenticated as
+    pub fn principal(&self) -> Option<String> {
+        match &self.credential {
+            Credential::Certificate(principal) => Some(principal.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// Authenticate a WebSocket upgrade by header, subprotocol or ticket
+pub fn authorize(
+    state: &AppState,
+    headers: &HeaderMap,
+    query: &TicketQuery,
+) -> Result<WsAuth, ApiError> {
+    let offered: Vec<&str> = headers
+        .get_all(header::SEC_WEBSOCKET_PROTOCOL)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .collect();
+    let subprotocol = offered.iter().find_map(|p| {
+        p.strip_prefix(BEARER_PREFIX)
+            .map(|t| Credential::Bearer(t.to_string()))
+            .or_else(|| {
+                p.strip_prefix(TICKET_PREFIX)
+                    .map(|t| Credential::Ticket(t.to_string()))
+            })
+    });
+
+    if let Some(principal) = tls::current_principal() {
+        tls::check_principal(&state.config.current(), &principal)?;
+        return Ok(WsAuth {
+            credential: Credential::Certificate(principal),
+            subprotocol: subprotocol.is_some(),
+        });
+    }
+    let Some(expected) = state.config.current().auth_token.clone() else {
+        return Ok(WsAuth {
+            credential: Credential::Open,
+            subprotocol: subprotocol.is_some(),
+        });
+    };
+
+    // Browsers send the dashboard's cookie on upgrades, but no Authorization
+    let session_cookie = session::cookie(headers)
+        .filter(|_| !headers.contains_key(header::AUTHORIZATION))
+        .filter(|cookie| session::verify(&state.config.current(), cookie).is_some());
+    let credential = match (subprotocol.clone(), &query.ticket) {
+        (Some(credential), _) => credential,
+        (None, Some(ticket)) => Credential::Ticket(ticket.clone()),
+        (None, None) => match session_cookie {
+            Some(_) if !session::same_origin(headers) => {
+                return Err(ApiError::Forbidden(
+                    "Session WebSockets must come from the dashboard's origin".to_string(),
+                ));
+            }
+            Some(cookie) => Credential::Session(cookie.to_string()),
+            None => {
+                check_auth(state, headers)?;
+                let token = headers
+                    .get(header::AUTHORIZATION)
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|v| v.strip_prefix("Bearer "))
+                    .unwrap_or_default();
+                Credential::Bearer(token.to_string())
+            }
+        },
+    };
+    let valid = match &credential {
+        Credential::Bearer(token) => token_matches(token, &expected),
+        Credential::Ticket(ticket) => state.used_tickets.redeem(&expected, ticket, Utc::now()),
+        Credential::Session(_) => true,
+        Credential::Open | Credential::Certificate(_) => false,
+    };
+    if !valid {
+        return Err(ApiError::Unauthorized(
+            "Invalid or expired WebSocket credential".to_string(),
+        ));
+    }
+    Ok(WsAuth {
+        credential,
+        subprotocol: subprotocol.is_some(),
+    })
+}
+
+/// Query credentials for `/logs/stream`, honored with SSE_QUERY_AUTH
+#[derive(Debug, Default, Deserialize, IntoParams)]
+pub struct SseAuthQuery {
+    /// AUTH_TOKEN itself (requires SSE_QUERY_AUTH)
+    pub access_token: Option<String>,
+    /// Ticket from POST /auth/ws-ticket (requires SSE_QUERY_AUTH)
+    pub ticket: Option<String>,
+}
+
+/// Authenticate an SSE request by header or, when SSE_QUERY_AUTH is set, by
+/// query credential
+pub fn authorize_sse(
+    state: &AppState,
+    headers: &HeaderMap,
+    query: &SseAuthQuery,
+) -> Result<(), ApiError> {
+    let config = state.config.current();
+    if tls::current_principal().is_some() {
+        return check_auth(state, headers);
+    }
+    let Some(expected) = config.auth_token.as_deref() else {
+        return Ok(());
+    };
+    if !config.sse_query_auth || headers.contains_key(header::AUTHORIZATION) {
+        return check_auth(state, headers);
+    }
+    let valid = match (&query.access_token, &query.ticket) {
+        (Some(token), _) => token_matches(token, expected),
+        (None, Some(ticket)) => state.used_tickets.redeem(expected, ticket, Utc::now()),
+        (None, None) => return check_auth(state, headers),
+    };
+    if !valid {
+        return Err(ApiError::Unauthorized(
+            "Invalid or expired query credential".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct WsTicket {
+    /// Pass as `?ticket=` or the `flywatch.ticket.<ticket>` subprotocol
+    pub ticket: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// POST /auth/ws-ticket - a short-lived credential for a browser WebSocket
+#[utoipa::path(
+    post, path = "/auth/ws-ticket", tag = "streams",
+    responses(
+        (status = 200, description = "Ticket valid for one upgrade within 30 seconds; open sockets outlive it", body = WsTicket),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+        (status = 503, description = "AUTH_TOKEN is not set", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+pub async fn ws_ticket_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<WsTicket>, ApiError> {
+    check_auth(&state, &headers)?;
+    let Some(token) = state.config.current().auth_token.clone() else {
+        return Err(ApiError::NotConfigured(
+            "AUTH_TOKEN is not set; WebSockets need no ticket".to_string(),
+        ));
+    };
+    let (ticket, expires_at) = issue_ticket(&token, Utc::now());
+    Ok(Json(WsTicket { ticket, expires_at }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tickets_expire_and_follow_the_token() {
+        let now = Utc::now();
+        let (ticket, expires_at) = issue_ticket("secret", now);
+        assert_eq!(expires_at, now + Duration::seconds(TICKET_TTL_SECS));
+
+        assert!(verify_ticket("secret", &ticket, Some(now)));
+        assert!(!verify_ticket(
+            "secret",
+            &ticket,
+            Some(now + Duration::minutes(1))
+        ));
+        assert!(!verify_ticket("rotated", &ticket, Some(now)));
+        // Still valid on a socket that upgraded in time
+        assert!(verify_ticket("secret", &ticket, None));
+
+        let forged = ticket.replacen(&expires_at.timestamp().to_string(), "9999999999", 1);
+        assert!(!verify_ticket("secret", &forged, None));
+        assert!(!verify_ticket("secret", "garbage", None));
+    }
+
+    #[test]
+    fn test_tickets_are_single_use() {
+        let used = UsedTickets::default();
+        let now = Utc::now();
+        let (ticket, _) = issue_ticket("secret", now);
+        assert!(!used.redeem("rotated", &ticket, now));
+        assert!(used.redeem("secret", &ticket, now));
+        assert!(!used.redeem("secret", &ticket, now + Duration::seconds(5)));
+
+        let (other, _) = issue_ticket("secret", now);
+        assert!(used.redeem("secret", &other, now + Duration::seconds(5)));
+        // Expired entries are forgotten; the tickets are refused by expiry
+        assert!(!used.redeem("secret", &ticket, now + Duration::minutes(1)));
+        assert!(used.used.lock().unwrap().is_empty());
+    }
+}
@@ -18,10 +18,10 @@ impl Store {
         let conn = Connection::open(path)?;
 
         // Performance tuning for low latency
-        let _ = conn.execute_batch("PRAGMA journal_mode = WAL")?;
-        let _ = conn.execute_batch("PRAGMA synchronous = NORMAL")?;
-        let _ = conn.execute_batch("PRAGMA cache_size = 10000")?;
-        let _ = conn.execute_batch("PRAGMA foreign_keys = ON")?;
+        conn.execute_batch("PRAGMA journal_mode = WAL")?;
+        conn.execute_batch("PRAGMA synchronous = NORMAL")?;
+        conn.execute_batch("PRAGMA cache_size = 10000")?;
+        conn.execute_batch("PRAGMA foreign_keys = ON")?;
 
         let instance_id = Uuid::new_v4().to_string();
 
@@ -37,10 +37,10 @@ impl Store {
     pub fn memory() -> Result<Self> {
         let conn = Connection::open_in_memory()?;
 
-        let _ = conn.execute_batch("PRAGMA journal_mode = WAL")?;
-        let _ = conn.execute_batch("PRAGMA synchronous = NORMAL")?;
-        let _ = conn.execute_batch("PRAGMA cache_size = 10000")?;
-        let _ = conn.execute_batch("PRAGMA foreign_keys = ON")?;
+        conn.execute_batch("PRAGMA journal_mode = WAL")?;
+        conn.execute_batch("PRAGMA synchronous = NORMAL")?;
+        conn.execute_batch("PRAGMA cache_size = 10000")?;
+        conn.execute_batch("PRAGMA foreign_keys = ON")?;
 
         let instance_id = Uuid::new_v4().to_string();
         let store = Store {
@@ -59,9 +59,9 @@ impl Store {
         })?;
 
         // Create tables individually - use single line SQL to avoid execute_batch issues
-        let _ = conn.execute_batch("CREATE TABLE IF NOT EXISTS __meta (key TEXT PRIMARY KEY, value TEXT NOT NULL)")?;
+        conn.execute_batch("CREATE TABLE IF NOT EXISTS __meta (key TEXT PRIMARY KEY, value TEXT NOT NULL)")?;
 
-        let _ = conn.execute_batch("CREATE TABLE IF NOT EXISTS __objects (key TEXT PRIMARY KEY, data BLOB NOT NULL, mime_type TEXT, size INTEGER, hash TEXT, created_at INTEGER, updated_at INTEGER)")?;
+        conn.execute_batch("CREATE TABLE IF NOT EXISTS __objects (key TEXT PRIMARY KEY, data BLOB NOT NULL, mime_type TEXT, size INTEGER, hash TEXT, created_at INTEGER, updated_at INTEGER)")?;
 
         // Initialize meta table with instance_id and schema version
         let _ = conn.execute(
@@ -174,6 +174,28 @@ impl Store {
         self.query::<T>(&sql, &[])
     }
 
+    /// Visit every value in a collection in insertion order, one row at a
+    /// time rather than collecting them. The store is locked meanwhile, so
+    /// `f` must not call back into it.
+    pub fn each<T, F>(&self, collection: &str, mut f: F) -> Result<()>
+    where
+        T: serde::de::DeserializeOwned,
+        F: FnMut(T),
+    {
+        let conn = self
+            .conn
+            .lock()
+            .map_err(|e| StoreError::InvalidConfig(format!("Failed to acquire lock: {}", e)))?;
+        self.ensure_collection_inner(&conn, collection)?;
+        let mut stmt = conn.prepare(&format!("SELECT data FROM [{}] ORDER BY rowid", collection))?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            let json_str: String = row.get(0)?;
+            f(serde_json::from_str(&json_str)?);
+        }
+        Ok(())
+    }
+
     /// Count items in a collection
     #[inline]
     pub fn count(&self, collection: &str) -> Result<usize> {